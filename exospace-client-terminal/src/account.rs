@@ -0,0 +1,187 @@
+//! Logging in to a player account before the game starts.
+//!
+//! This runs before notcurses takes over the terminal, so it can prompt on
+//! stdin. A saved token is tried first; otherwise the password comes from the
+//! config or a prompt, and the fresh token is kept in the config for next time.
+//! Connecting from the title menu logs in again without the prompt.
+
+use exospace_core::{AccountInfo, AuthToken, Credentials, ErrorCode};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::apierror::ServerError;
+
+/// Password prompts before giving up and playing as a guest
+const MAX_PROMPTS: usize = 3;
+
+/// The `account` section of the config
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct AccountConfig {
+    pub name: String,
+    /// Prompted for when missing
+    pub password: Option<String>,
+    /// Token from the last login, reused until the server stops accepting it
+    pub token: Option<String>,
+}
+
+/// Value of the `Authorization` header for a token
+pub fn bearer(token: &str) -> String {
+    format!("Bearer {}", token)
+}
+
+/// Log in (or register) and store the token in `account`; returns the account name
+pub fn authenticate(server_url: &str, account: &mut AccountConfig, register: bool) -> Result<String, String> {
+    let client = http_client()?;
+    if let Some(name) = saved_login(&client, server_url, account, register)? {
+        return Ok(name);
+    }
+
+    let endpoint = if register { "register" } else { "login" };
+    let mut last_error = "No password given".to_string();
+    for _ in 0..MAX_PROMPTS {
+        let Some(password) = prompt_password(&account.name, register) else {
+            break;
+        };
+        match request_token(&client, server_url, endpoint, &account.name, password) {
+            Ok(token) => {
+                account.token = Some(token.token);
+                return Ok(token.name);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Log in with the saved token or the config's password only, for once the terminal is taken (or headless)
+pub fn authenticate_quietly(server_url: &str, account: &mut AccountConfig, register: bool) -> Result<String, String> {
+    saved_login(&http_client()?, server_url, account, register)?.ok_or_else(|| "No saved token or password for this server".to_string())
+}
+
+fn http_client() -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+/// The saved token, else the config's password; `Ok(None)` when there's neither to use
+fn saved_login(client: &reqwest::blocking::Client, server_url: &str, account: &mut AccountConfig, register: bool) -> Result<Option<String>, String> {
+    if !register && let Some(token) = &account.token {
+        match check_token(client, server_url, token)? {
+            Some(name) => return Ok(Some(name)),
+            None => account.token = None,
+        }
+    }
+    let endpoint = if register { "register" } else { "login" };
+    if let Some(password) = account.password.clone() {
+        let token = request_token(client, server_url, endpoint, &account.name, password)?;
+        account.token = Some(token.token);
+        return Ok(Some(token.name));
+    }
+    Ok(None)
+}
+
+/// Ask the server who a token belongs to; `Ok(None)` means it was refused
+fn check_token(client: &reqwest::blocking::Client, server_url: &str, token: &str) -> Result<Option<String>, String> {
+    let response = client
+        .get(format!("{}/me", server_url))
+        .header(reqwest::header::AUTHORIZATION, bearer(token))
+        .send()
+        .map_err(|e| format!("Failed to connect to server: {}", e))?;
+    if !response.status().is_success() {
+        let error = ServerError::from_response(response);
+        if error.code == ErrorCode::BadToken || error.status == 401 {
+            return Ok(None);
+        }
+        return Err(error.to_string());
+    }
+    let account: AccountInfo = response.json().map_err(|e| format!("Failed to parse account: {}", e))?;
+    Ok(Some(account.name))
+}
+
+/// POST credentials to `/register` or `/login`; errors carry the server's reason
+fn request_token(
+    client: &reqwest::blocking::Client,
+    server_url: &str,
+    endpoint: &str,
+    name: &str,
+    password: String,
+) -> Result<AuthToken, String> {
+    let credentials = Credentials { name: name.to_string(), password };
+    let response = client
+        .post(format!("{}/{}", server_url, endpoint))
+        .json(&credentials)
+        .send()
+        .map_err(|e| format!("Failed to connect to server: {}", e))?;
+    if !response.status().is_success() {
+        return Err(ServerError::from_response(response).to_string());
+    }
+    response.json().map_err(|e| format!("Failed to parse token: {}", e))
+}
+
+/// Read a password from the terminal without echoing it; None if left empty
+fn prompt_password(name: &str, register: bool) -> Option<String> {
+    let action = if register { "Choose a password" } else { "Password" };
+    print!("{} for {} (empty to play as a guest): ", action, name);
+    let _ = io::stdout().flush();
+
+    set_echo(false);
+    let mut line = String::new();
+    let read = io::stdin().lock().read_line(&mut line);
+    set_echo(true);
+    println!();
+
+    let password = line.trim_end_matches(['\r', '\n']).to_string();
+    (read.is_ok() && !password.is_empty()).then_some(password)
+}
+
+/// Switch terminal echo; a no-op where `stty` isn't available, so the password shows
+fn set_echo(on: bool) {
+    let _ = Command::new("stty").arg(if on { "echo" } else { "-echo" }).stdin(Stdio::inherit()).status();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_config_defaults() {
+        let account: AccountConfig = serde_json::from_str(r#"{"name":"Ace"}"#).unwrap();
+        assert_eq!(account.name, "Ace");
+        assert!(account.password.is_none() && account.token.is_none());
+    }
+
+    #[test]
+    fn test_bearer_header() {
+        assert_eq!(bearer("abc"), "Bearer abc");
+    }
+
+    #[test]
+    fn test_authenticate_keeps_token_when_server_is_down() {
+        let mut account = AccountConfig { name: "Ace".to_string(), password: None, token: Some("abc".to_string()) };
+        // Port 9 (discard) on localhost is essentially never an ExoSpace server
+        assert!(authenticate("http://127.0.0.1:9", &mut account, false).is_err());
+        assert_eq!(account.token.as_deref(), Some("abc"), "An unreachable server doesn't invalidate the token");
+    }
+
+    #[test]
+    fn test_authenticate_with_config_password_fails_offline() {
+        let mut account = AccountConfig { name: "Ace".to_string(), password: Some("hunter22".to_string()), token: None };
+        let error = authenticate("http://127.0.0.1:9", &mut account, false).unwrap_err();
+        assert!(error.contains("Failed to connect"), "Got: {}", error);
+        assert!(account.token.is_none());
+    }
+
+    #[test]
+    fn test_authenticate_quietly_never_prompts() {
+        let mut account = AccountConfig { name: "Ace".to_string(), password: None, token: None };
+        assert_eq!(authenticate_quietly("http://127.0.0.1:9", &mut account, false), Err("No saved token or password for this server".to_string()));
+    }
+}
@@ -0,0 +1,106 @@
+//! Failed requests to the server's HTTP endpoints.
+//!
+//! Servers answer a failed request with an `ErrorBody`: an `ErrorCode` to act
+//! on and a message meant for the player. `ServerError` reads it off a
+//! response; older servers sent the reason as plain text, or nothing, and
+//! then the text or the status is all there is to show. A request over the
+//! server's budget also says, in `Retry-After`, how long to hold off.
+
+use exospace_core::{ErrorBody, ErrorCode};
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerError {
+    pub status: u16,
+    pub code: ErrorCode,
+    /// The server's reason, if it gave one
+    pub message: Option<String>,
+    /// How long the server asked us to wait before trying again
+    pub retry_after: Option<Duration>,
+}
+
+impl ServerError {
+    pub fn parse(status: u16, body: &[u8]) -> Self {
+        if let Ok(body) = serde_json::from_slice::<ErrorBody>(body) {
+            return ServerError { status, code: body.code, message: Some(body.error), retry_after: None };
+        }
+        let text = String::from_utf8_lossy(body).trim().to_string();
+        ServerError { status, code: ErrorCode::Unknown, message: (!text.is_empty()).then_some(text), retry_after: None }
+    }
+
+    /// Reads the body, so only for responses that weren't a success
+    pub fn from_response(response: reqwest::blocking::Response) -> Self {
+        let status = response.status().as_u16();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = response.bytes().unwrap_or_default();
+        ServerError { retry_after, ..Self::parse(status, &body) }
+    }
+
+    /// Over the server's budget: back off for `retry_after`, or `RATE_LIMIT_BACKOFF` if it didn't say
+    pub fn is_rate_limited(&self) -> bool {
+        self.status == 429 || self.code == ErrorCode::RateLimited
+    }
+
+    /// Whether the server broke, rather than turning down what was asked
+    pub fn is_server_fault(&self) -> bool {
+        self.status >= 500 || self.code == ErrorCode::Storage
+    }
+}
+
+/// Wait when a rate-limited response doesn't say how long
+pub const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(10);
+
+/// A `Retry-After` in seconds; the HTTP-date form isn't used by our server and reads as nothing
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.message {
+            Some(message) => f.write_str(message),
+            None => write!(f, "Server returned error: {}", self.status),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Parsing Tests ====================
+
+    #[test]
+    fn test_error_bodies_are_parsed() {
+        let error = ServerError::parse(409, br#"{"code":"short_stock","error":"Only 3 in stock"}"#);
+        assert_eq!(error.code, ErrorCode::ShortStock);
+        assert_eq!(error.to_string(), "Only 3 in stock", "The player sees the server's reason, not JSON");
+        assert!(!error.is_server_fault());
+
+        let error = ServerError::parse(500, br#"{"code":"storage","error":"disk full"}"#);
+        assert!(error.is_server_fault());
+    }
+
+    #[test]
+    fn test_older_servers_errors() {
+        let error = ServerError::parse(400, b"Map size must be between 3x3 and 2000x1000\n");
+        assert_eq!(error.code, ErrorCode::Unknown);
+        assert_eq!(error.to_string(), "Map size must be between 3x3 and 2000x1000", "Plain text reasons are shown as they are");
+        assert_eq!(ServerError::parse(502, b"").to_string(), "Server returned error: 502");
+    }
+
+    #[test]
+    fn test_rate_limits() {
+        let error = ServerError::parse(429, br#"{"code":"rate_limited","error":"Too many requests; try again in 3s"}"#);
+        assert!(error.is_rate_limited());
+        assert!(!error.is_server_fault());
+        assert!(ServerError::parse(429, b"").is_rate_limited(), "Older servers' 429s count too");
+        assert_eq!(parse_retry_after(" 30 "), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+    }
+}
@@ -0,0 +1,244 @@
+//! Speech bubbles over ships, for local chat and emotes.
+//!
+//! `/say TEXT` and `/emote TEXT` (`/me`) go to the pilots within
+//! `SAY_RANGE` of the ship, who see the line in a bubble over it, and a
+//! bubble shows over our own ship whether we're online or not. A bubble
+//! wraps to `BUBBLE_WIDTH`, stays up for `BUBBLE_LIFETIME` and dims over its
+//! last `FADE_TIME`; someone saying something new replaces their bubble.
+//! `place()` puts a bubble above the ship's name tag, or below or beside the
+//! ship when that would cover a ship, run off the view or overlap a bubble
+//! already drawn.
+
+use std::time::{Duration, Instant};
+
+/// Widest line of text in a bubble, in characters
+pub const BUBBLE_WIDTH: usize = 24;
+
+/// Lines a bubble holds before the rest is cut off
+const MAX_LINES: usize = 3;
+
+/// How long a bubble stays up
+const BUBBLE_LIFETIME: Duration = Duration::from_secs(6);
+
+/// The part of that it spends fading out
+const FADE_TIME: Duration = Duration::from_secs(2);
+
+/// Text and background colors of a bubble, and of an emote's
+pub const SAY_STYLE: (u32, u32) = (0x101010, 0xE0E0D0);
+pub const EMOTE_STYLE: (u32, u32) = (0xFFE0A0, 0x403060);
+
+/// Whose ship a bubble is over
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Speaker {
+    Me,
+    Pilot(u64),
+}
+
+/// A line said over a ship, wrapped to fit
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bubble {
+    pub speaker: Speaker,
+    pub lines: Vec<String>,
+    pub emote: bool,
+    said: Instant,
+}
+
+impl Bubble {
+    /// Columns and rows it takes up on screen, with a space either side of the text
+    pub fn size(&self) -> (i32, i32) {
+        let width = self.lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        (width as i32 + 2, self.lines.len() as i32)
+    }
+
+    /// How bright it is at `now`, as a percentage: full until it starts fading, then down to 0
+    pub fn brightness(&self, now: Instant) -> u32 {
+        let left = (self.said + BUBBLE_LIFETIME).saturating_duration_since(now);
+        if left >= FADE_TIME {
+            100
+        } else {
+            (left.as_millis() * 100 / FADE_TIME.as_millis()) as u32
+        }
+    }
+}
+
+/// The bubbles up at the moment, oldest first
+#[derive(Default)]
+pub struct Bubbles {
+    bubbles: Vec<Bubble>,
+}
+
+impl Bubbles {
+    /// Show a line over a speaker's ship, in place of whatever they said before
+    pub fn say(&mut self, speaker: Speaker, text: &str, emote: bool, now: Instant) {
+        self.bubbles.retain(|bubble| bubble.speaker != speaker);
+        let text = if emote { format!("*{}*", text.trim()) } else { text.trim().to_string() };
+        self.bubbles.push(Bubble { speaker, lines: wrap(&text, BUBBLE_WIDTH), emote, said: now });
+    }
+
+    /// Take down the bubbles that have faded out
+    pub fn expire(&mut self, now: Instant) {
+        self.bubbles.retain(|bubble| now < bubble.said + BUBBLE_LIFETIME);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Bubble> {
+        self.bubbles.iter()
+    }
+}
+
+/// Word-wrap text to lines of at most `width` characters, splitting words longer than that;
+/// past `MAX_LINES` the last line ends in "…"
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        let used = line.chars().count();
+        if used > 0 && used + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        while word.len() > width {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            lines.push(word.drain(..width).collect());
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    if lines.len() > MAX_LINES {
+        lines.truncate(MAX_LINES);
+        let last = &mut lines[MAX_LINES - 1];
+        let kept: String = last.chars().take(width - 1).collect();
+        *last = format!("{}…", kept);
+    }
+    lines
+}
+
+/// A spot on screen: column, row, width and height
+pub type Rect = (i32, i32, i32, i32);
+
+fn overlaps(a: Rect, b: Rect) -> bool {
+    a.0 < b.0 + b.2 && b.0 < a.0 + a.2 && a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+}
+
+/// Where a bubble of `size` goes for a ship centered on screen cell `ship`, whose name tag is
+/// `rise` rows above it: above the tag, then below the ship, then to its right and left. A spot
+/// must fit in `screen` (columns, rows), stay clear of `covers_ship` cells and, if it can, of
+/// the bubbles already `taken`. None when nowhere keeps the ship in view
+pub fn place(ship: (i32, i32), size: (i32, i32), rise: i32, screen: (i32, i32), covers_ship: impl Fn(i32, i32) -> bool, taken: &[Rect]) -> Option<Rect> {
+    let (width, height) = size;
+    // Half the ship's width, beyond its center cell: a sprite at 1x, a glyph zoomed out
+    let reach = rise - 1;
+    let centered = ship.0 - width / 2;
+    let candidates = [
+        (centered, ship.1 - rise - height),
+        (centered, ship.1 + rise),
+        (ship.0 + reach + 2, ship.1 - height / 2),
+        (ship.0 - reach - 2 - width, ship.1 - height / 2),
+    ];
+    let fits = |&(x, y): &(i32, i32)| {
+        x >= 0
+            && y >= 0
+            && x + width <= screen.0
+            && y + height <= screen.1
+            && !(y..y + height).any(|row| (x..x + width).any(|col| covers_ship(col, row)))
+    };
+    // Slid along the row to stay on screen, so bubbles over ships near the edge still show
+    let spots: Vec<(i32, i32)> = candidates.iter().map(|&(x, y)| (x.clamp(0, (screen.0 - width).max(0)), y)).filter(fits).collect();
+    let rect = |(x, y): (i32, i32)| (x, y, width, height);
+    spots
+        .iter()
+        .map(|&spot| rect(spot))
+        .find(|&spot| !taken.iter().any(|&other| overlaps(spot, other)))
+        .or_else(|| spots.first().map(|&spot| rect(spot)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Wrapping Tests ====================
+
+    #[test]
+    fn test_wrap_breaks_between_words() {
+        assert_eq!(wrap("hello there pilot", 11), vec!["hello there", "pilot"]);
+        assert_eq!(wrap("  spaced   out  ", 24), vec!["spaced out"], "Runs of spaces collapse");
+        assert!(wrap("", 24).is_empty());
+    }
+
+    #[test]
+    fn test_wrap_splits_long_words_and_cuts_long_lines() {
+        assert_eq!(wrap("abcdefghij", 4), vec!["abcd", "efgh", "ij"], "Words longer than a line are split");
+        assert_eq!(wrap("hi abcdefgh", 4), vec!["hi", "abcd", "efgh"]);
+
+        let lines = wrap("one two three four five six seven eight", 9);
+        assert_eq!(lines.len(), MAX_LINES);
+        assert_eq!(lines[MAX_LINES - 1], "four fiv…", "What doesn't fit is cut off");
+        assert!(lines.iter().all(|line| line.chars().count() <= 9));
+    }
+
+    // ==================== Bubble Tests ====================
+
+    #[test]
+    fn test_bubbles_replace_fade_and_expire() {
+        let start = Instant::now();
+        let mut bubbles = Bubbles::default();
+        bubbles.say(Speaker::Pilot(1), "hi", false, start);
+        bubbles.say(Speaker::Me, "waves", true, start);
+        bubbles.say(Speaker::Pilot(1), "hello again", false, start + Duration::from_secs(1));
+        let lines: Vec<_> = bubbles.iter().map(|b| (b.speaker, b.lines.join(" "))).collect();
+        assert_eq!(
+            lines,
+            vec![(Speaker::Me, "*waves*".to_string()), (Speaker::Pilot(1), "hello again".to_string())],
+            "Emotes are starred, and a new line replaces the speaker's bubble"
+        );
+
+        let mine = bubbles.iter().next().unwrap();
+        assert_eq!(mine.size(), (9, 1), "A space either side of the text");
+        assert_eq!(mine.brightness(start), 100);
+        assert_eq!(mine.brightness(start + BUBBLE_LIFETIME - FADE_TIME / 2), 50, "Halfway through fading");
+
+        bubbles.expire(start + BUBBLE_LIFETIME);
+        assert_eq!(bubbles.iter().count(), 1, "Mine is gone");
+        bubbles.expire(start + BUBBLE_LIFETIME + Duration::from_secs(1));
+        assert!(bubbles.iter().next().is_none());
+    }
+
+    // ==================== Placement Tests ====================
+
+    #[test]
+    fn test_place_above_the_name_tag() {
+        let no_ships = |_, _| false;
+        assert_eq!(place((40, 12), (8, 2), 2, (80, 24), no_ships, &[]), Some((36, 8, 8, 2)));
+        // Near the edge it slides along to stay on screen
+        assert_eq!(place((1, 12), (8, 1), 2, (80, 24), no_ships, &[]), Some((0, 9, 8, 1)));
+    }
+
+    #[test]
+    fn test_place_avoids_ships_the_edge_and_other_bubbles() {
+        let ship_at = |cx: i32, cy: i32| move |x: i32, y: i32| (x - cx).abs() <= 1 && (y - cy).abs() <= 1;
+
+        // A ship above ours: the bubble goes below
+        assert_eq!(place((40, 12), (8, 2), 2, (80, 24), ship_at(40, 8), &[]), Some((36, 14, 8, 2)));
+
+        // No room above at the top of the view either
+        assert_eq!(place((40, 2), (8, 2), 2, (80, 24), |_, _| false, &[]), Some((36, 4, 8, 2)));
+
+        // Above is taken by another bubble: below instead, unless there's nowhere else
+        let taken = [(30, 8, 20, 2)];
+        assert_eq!(place((40, 12), (8, 2), 2, (80, 24), |_, _| false, &taken), Some((36, 14, 8, 2)));
+        let everywhere = [(0, 0, 80, 24)];
+        assert_eq!(place((40, 12), (8, 2), 2, (80, 24), |_, _| false, &everywhere), Some((36, 8, 8, 2)), "Overlapping beats hiding it");
+
+        // Never over a ship
+        let bubble = place((40, 12), (8, 3), 2, (80, 24), ship_at(40, 12), &[]).unwrap();
+        assert!(!overlaps(bubble, (39, 11, 3, 3)));
+        assert_eq!(place((40, 12), (8, 2), 2, (80, 24), |_, _| true, &[]), None, "Nowhere clear of ships");
+    }
+}
@@ -0,0 +1,218 @@
+//! The camera: where the view is centered, following the ship or free.
+//!
+//! Following, the camera eases toward the ship instead of jumping a cell at
+//! a time. It keeps its spot to a fraction of a tile and closes most of the
+//! gap within the configured lag (`CameraFeel::smoothing`), however fast
+//! frames come. A dead zone lets the ship wander a few cells from the middle
+//! before the view scrolls, and look-ahead leads the view a few cells the
+//! way the ship faces. A jump further than `SNAP_DISTANCE` cells (teleports,
+//! new maps) snaps straight there. All three are in the settings screen.
+//!
+//! Spectator mode: F5 (`/spectate`) unhooks the view from the ship. The
+//! movement keys then pan the camera instead of thrusting, `PAN_CELLS`
+//! screen cells a step at any zoom, and Home (`snap_camera`) brings it back
+//! over the ship. The ship keeps drifting meanwhile, and the autopilot keeps
+//! flying it. F5 again, or `/spectate off`, goes back to following the ship.
+//! The camera shows what the ship has explored and what its sensors pick up,
+//! nothing more; map chunks stream in around wherever it looks.
+
+use std::time::{Duration, Instant};
+
+/// Screen cells the camera pans each movement step
+pub const PAN_CELLS: i32 = 2;
+
+/// Camera lag by default, in milliseconds
+pub const DEFAULT_SMOOTHING_MS: u64 = 80;
+
+/// Camera lags the settings accept, in milliseconds; 0 locks the view to the ship
+pub const SMOOTHING_LIMITS: (u64, u64) = (0, 1000);
+
+/// Dead zone and look-ahead sizes the settings accept, in cells
+pub const DEADZONE_LIMITS: (u32, u32) = (0, 10);
+pub const LOOKAHEAD_LIMITS: (u32, u32) = (0, 10);
+
+/// Cells behind beyond which the camera stops easing and jumps
+const SNAP_DISTANCE: f32 = 40.0;
+
+/// How the camera follows the ship, from the config
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CameraFeel {
+    /// Time to close about two thirds of the gap to the ship; zero keeps it locked on
+    pub smoothing: Duration,
+    /// Cells the ship can stray from the middle before the view scrolls
+    pub deadzone: u32,
+    /// Cells the view leads the ship the way it faces
+    pub lookahead: u32,
+}
+
+impl Default for CameraFeel {
+    fn default() -> Self {
+        CameraFeel { smoothing: Duration::from_millis(DEFAULT_SMOOTHING_MS), deadzone: 0, lookahead: 0 }
+    }
+}
+
+/// Where the view is centered: a free camera's spot in tiles, or where the following camera has got to
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Camera {
+    free: Option<(i32, i32)>,
+    /// The following camera, in tiles to a fraction; none until it first sees the ship
+    focus: Option<(f32, f32)>,
+    last_update: Option<Instant>,
+}
+
+impl Camera {
+    pub fn is_free(&self) -> bool {
+        self.free.is_some()
+    }
+
+    /// Unhook the camera over the ship, or go back to following it; true if it's now free
+    pub fn toggle(&mut self, ship: (i32, i32)) -> bool {
+        match self.free {
+            Some(_) => self.follow(),
+            None => self.free = Some(ship),
+        }
+        self.is_free()
+    }
+
+    /// Follow the ship again, picking it up where it is
+    pub fn follow(&mut self) {
+        self.free = None;
+        self.focus = None;
+    }
+
+    /// Bring a free camera back over the ship, still free
+    pub fn snap_to(&mut self, ship: (i32, i32)) {
+        if self.free.is_some() {
+            self.free = Some(ship);
+        }
+    }
+
+    /// Move a free camera by a movement key's (dx, dy), `zoom` tiles per cell
+    pub fn pan(&mut self, (dx, dy): (i32, i32), zoom: i32) {
+        if let Some((x, y)) = &mut self.free {
+            *x += dx * PAN_CELLS * zoom;
+            *y += dy * PAN_CELLS * zoom;
+        }
+    }
+
+    /// Ease the following camera toward the ship at `ship`, facing `facing` (dx, dy), with
+    /// `zoom` tiles per cell; once a frame
+    pub fn update(&mut self, ship: (i32, i32), facing: (i32, i32), zoom: i32, feel: &CameraFeel, now: Instant) {
+        let elapsed = self.last_update.map_or(Duration::ZERO, |last| now.saturating_duration_since(last));
+        self.last_update = Some(now);
+        let zoom = zoom as f32;
+        let lead = feel.lookahead as f32 * zoom;
+        let target = (ship.0 as f32 + facing.0 as f32 * lead, ship.1 as f32 + facing.1 as f32 * lead);
+        let Some((x, y)) = self.focus else {
+            self.focus = Some(target);
+            return;
+        };
+
+        // Inside the dead zone the view holds still; past it, it only moves to bring the ship back to the edge
+        let slack = feel.deadzone as f32 * zoom;
+        let settle = |at: f32, to: f32| if (to - at).abs() <= slack { at } else { to - slack * (to - at).signum() };
+        let goal = (settle(x, target.0), settle(y, target.1));
+        let behind = (goal.0 - x).abs().max((goal.1 - y).abs()) / zoom;
+        let keep = if feel.smoothing.is_zero() || behind > SNAP_DISTANCE {
+            0.0
+        } else {
+            (-elapsed.as_secs_f32() / feel.smoothing.as_secs_f32()).exp()
+        };
+        self.focus = Some((goal.0 + (x - goal.0) * keep, goal.1 + (y - goal.1) * keep));
+    }
+
+    /// The tile the view centers on: the free camera's, the following camera's nearest, or the ship before it's moved
+    pub fn center(&self, ship: (i32, i32)) -> (i32, i32) {
+        match (self.free, self.focus) {
+            (Some(free), _) => free,
+            (None, Some((x, y))) => (x.round() as i32, y.round() as i32),
+            (None, None) => ship,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Camera Tests ====================
+
+    #[test]
+    fn test_camera_follows_the_ship_until_freed() {
+        let mut camera = Camera::default();
+        assert_eq!(camera.center((5, 5)), (5, 5));
+        camera.pan((1, 0), 1);
+        assert_eq!(camera.center((5, 5)), (5, 5), "A following camera doesn't pan");
+
+        assert!(camera.toggle((5, 5)));
+        assert_eq!(camera.center((9, 9)), (5, 5), "A free camera stays put as the ship moves");
+        assert!(!camera.toggle((9, 9)));
+        assert_eq!(camera.center((9, 9)), (9, 9));
+    }
+
+    #[test]
+    fn test_camera_pans_and_snaps_back() {
+        let mut camera = Camera::default();
+        camera.snap_to((3, 3));
+        assert!(!camera.is_free(), "Snapping doesn't free the camera");
+
+        camera.toggle((0, 0));
+        camera.pan((1, -1), 1);
+        assert_eq!(camera.center((0, 0)), (PAN_CELLS, -PAN_CELLS));
+        camera.pan((-1, 0), 4);
+        assert_eq!(camera.center((0, 0)), (PAN_CELLS - 4 * PAN_CELLS, -PAN_CELLS), "Zoomed out it covers more tiles");
+
+        camera.snap_to((10, 20));
+        assert_eq!(camera.center((0, 0)), (10, 20));
+        assert!(camera.is_free(), "Still free after snapping back");
+        camera.follow();
+        assert_eq!(camera.center((0, 0)), (0, 0));
+    }
+
+    #[test]
+    fn test_camera_eases_toward_the_ship() {
+        let start = Instant::now();
+        let feel = CameraFeel { smoothing: Duration::from_millis(100), ..CameraFeel::default() };
+        let mut camera = Camera::default();
+        camera.update((0, 0), (1, 0), 1, &feel, start);
+        assert_eq!(camera.center((0, 0)), (0, 0), "It starts on the ship");
+
+        camera.update((10, 0), (1, 0), 1, &feel, start);
+        assert_eq!(camera.center((10, 0)), (0, 0), "No time has passed");
+        camera.update((10, 0), (1, 0), 1, &feel, start + Duration::from_millis(100));
+        assert_eq!(camera.center((10, 0)), (6, 0), "About two thirds of the way after the lag");
+        camera.update((10, 0), (1, 0), 1, &feel, start + Duration::from_millis(600));
+        assert_eq!(camera.center((10, 0)), (10, 0), "Caught up");
+
+        let locked = CameraFeel { smoothing: Duration::ZERO, ..feel };
+        camera.update((12, 3), (1, 0), 1, &locked, start + Duration::from_millis(610));
+        assert_eq!(camera.center((12, 3)), (12, 3), "No lag, no easing");
+
+        camera.update((500, 3), (1, 0), 1, &feel, start + Duration::from_millis(620));
+        assert_eq!(camera.center((500, 3)), (500, 3), "Long jumps snap");
+    }
+
+    #[test]
+    fn test_camera_dead_zone_and_look_ahead() {
+        let start = Instant::now();
+        let locked = CameraFeel { smoothing: Duration::ZERO, deadzone: 3, lookahead: 0 };
+        let mut camera = Camera::default();
+        camera.update((0, 0), (1, 0), 1, &locked, start);
+        camera.update((3, -2), (1, 0), 1, &locked, start);
+        assert_eq!(camera.center((3, -2)), (0, 0), "Small moves don't scroll");
+        camera.update((5, 0), (1, 0), 1, &locked, start);
+        assert_eq!(camera.center((5, 0)), (2, 0), "Past the dead zone the view keeps the ship at its edge");
+        camera.update((5, 0), (1, 0), 2, &locked, start);
+        assert_eq!(camera.center((5, 0)), (2, 0), "Zoomed out the zone covers more tiles");
+
+        let ahead = CameraFeel { smoothing: Duration::ZERO, deadzone: 0, lookahead: 4 };
+        camera.update((5, 0), (0, 1), 1, &ahead, start);
+        assert_eq!(camera.center((5, 0)), (5, 4), "The view leads the way the ship faces");
+        camera.update((5, 0), (-1, -1), 2, &ahead, start);
+        assert_eq!(camera.center((5, 0)), (-3, -8));
+
+        camera.toggle((5, 0));
+        camera.update((9, 9), (0, 1), 1, &ahead, start);
+        assert_eq!(camera.center((9, 9)), (5, 0), "A free camera doesn't follow");
+    }
+}
@@ -0,0 +1,96 @@
+//! Glyph sets: Unicode, or plain ASCII for terminals and fonts that can't
+//! show arrows, block shading and box drawing.
+//!
+//! Everything is drawn with Unicode glyphs and passed through
+//! `Charset::glyph()` (or `text()` for strings) on its way to the screen, so
+//! the ASCII set is a single table here. `Config::charset` picks one;
+//! left out, notcurses' UTF-8 check decides at startup.
+
+use exospace_core::Direction;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Charset {
+    #[default]
+    Unicode,
+    Ascii,
+}
+
+/// ASCII stand-ins for every non-ASCII glyph the client draws, apart from ship arrows
+const ASCII_GLYPHS: &[(char, char)] = &[
+    // Walls, fog and nebulae
+    ('█', '#'),
+    ('▓', '#'),
+    ('▒', '%'),
+    ('░', ':'),
+    // Crystals, route dots and points of interest
+    ('◆', '*'),
+    ('·', '.'),
+    ('⌂', 'H'),
+    ('Ħ', 'H'),
+    ('¤', '%'),
+    ('✦', '+'),
+    ('◎', 'O'),
+    ('◊', '?'),
+    // Radar blips and waypoints
+    ('•', 'o'),
+    ('◎', 'O'),
+    // Panel borders
+    ('┌', '+'),
+    ('┐', '+'),
+    ('└', '+'),
+    ('┘', '+'),
+    ('─', '-'),
+    ('│', '|'),
+];
+
+impl Charset {
+    /// ASCII unless the terminal reports UTF-8
+    pub fn detect(utf8: bool) -> Charset {
+        if utf8 { Charset::Unicode } else { Charset::Ascii }
+    }
+
+    pub fn glyph(self, ch: char) -> char {
+        if self == Charset::Unicode || ch.is_ascii() {
+            return ch;
+        }
+        if let Some(&(_, ascii)) = ASCII_GLYPHS.iter().find(|&&(unicode, _)| unicode == ch) {
+            return ascii;
+        }
+        Direction::ALL.into_iter().find(|d| d.to_char() == ch).map_or('?', Direction::to_ascii)
+    }
+
+    /// `glyph()` for each character of a string
+    pub fn text(self, text: &str) -> Cow<'_, str> {
+        if self == Charset::Unicode || text.is_ascii() {
+            Cow::Borrowed(text)
+        } else {
+            Cow::Owned(text.chars().map(|ch| self.glyph(ch)).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Glyph Tests ====================
+
+    #[test]
+    fn test_ascii_glyphs() {
+        assert_eq!(Charset::Ascii.glyph('█'), '#');
+        assert_eq!(Charset::Ascii.glyph('↗'), '/', "Ship arrows come from Direction::to_ascii()");
+        assert_eq!(Charset::Ascii.glyph('@'), '@');
+        assert_eq!(Charset::Ascii.glyph('☃'), '?', "Anything unknown is still ASCII");
+        assert_eq!(Charset::Unicode.glyph('█'), '█');
+        assert!(ASCII_GLYPHS.iter().all(|&(unicode, ascii)| !unicode.is_ascii() && ascii.is_ascii()));
+
+        assert_eq!(Charset::Ascii.text("┌─ MAP ─┐"), "+- MAP -+");
+        assert!(matches!(Charset::Ascii.text("plain"), Cow::Borrowed(_)));
+        assert_eq!(Charset::Unicode.text("│"), "│");
+        assert_eq!(Charset::detect(false), Charset::Ascii);
+        assert_eq!(Charset::detect(true), Charset::Unicode);
+    }
+}
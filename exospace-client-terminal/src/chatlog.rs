@@ -0,0 +1,192 @@
+//! The chat log: every kept chat message, scrolled through over the whole
+//! view (PageUp or `/log`) on a plane of its own.
+//!
+//! Messages are wrapped to the log's width under their time (UTC, as there's
+//! no time zone database to hand) and keep the colors the chat pane gives
+//! them; muted channels are listed too, so the log is where they can still
+//! be read. `ChatLog` is just whether it's open and how far it's scrolled;
+//! the lines are built each frame from `ChatWindow::messages`.
+
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use libnotcurses_sys::NcKey;
+
+use crate::ChatWindow;
+
+/// Width of "HH:MM:SS " in front of each message
+pub const TIME_WIDTH: usize = 9;
+
+/// One line of the log as drawn
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogLine {
+    /// Set on a message's first line only
+    pub time: Option<String>,
+    pub text: String,
+    pub color: u32,
+}
+
+#[derive(Debug)]
+pub struct ChatLog {
+    open: bool,
+    /// Lines scrolled back from the newest
+    scroll: usize,
+    /// Lines shown at the last `view()`, the PageUp/PageDown step
+    page: usize,
+}
+
+impl Default for ChatLog {
+    fn default() -> Self {
+        ChatLog { open: false, scroll: 0, page: 10 }
+    }
+}
+
+impl ChatLog {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Open at the newest messages
+    pub fn open(&mut self) {
+        self.open = true;
+        self.scroll = 0;
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+    }
+
+    /// Scroll for a key, returning whether it was used: PageUp opens the
+    /// log, PageDown at the bottom closes it, and arrows and Home/End scroll
+    /// while it's open
+    pub fn scroll_key(&mut self, key: NcKey) -> bool {
+        if !self.open {
+            if key == NcKey::PgUp {
+                self.open();
+                return true;
+            }
+            return false;
+        }
+        let step = self.page.saturating_sub(1).max(1);
+        match key {
+            NcKey::PgUp => self.scroll += step,
+            NcKey::PgDown if self.scroll == 0 => self.close(),
+            NcKey::PgDown => self.scroll = self.scroll.saturating_sub(step),
+            NcKey::Up => self.scroll += 1,
+            NcKey::Down => self.scroll = self.scroll.saturating_sub(1),
+            NcKey::Home => self.scroll = usize::MAX,
+            NcKey::End => self.scroll = 0,
+            _ => return false,
+        }
+        true
+    }
+
+    /// The lines of `total` that fit in `rows`, newest at the bottom; keeps the scroll in range
+    pub fn view(&mut self, total: usize, rows: usize) -> Range<usize> {
+        self.page = rows;
+        self.scroll = self.scroll.min(total.saturating_sub(rows));
+        let end = total - self.scroll;
+        end.saturating_sub(rows)..end
+    }
+}
+
+/// Every message, wrapped to `width` columns with its time in front
+pub fn lines(chat: &ChatWindow, width: usize) -> Vec<LogLine> {
+    let text_width = width.saturating_sub(TIME_WIDTH).max(1);
+    let mut lines = Vec::new();
+    for message in &chat.messages {
+        let color = chat.color_of(message);
+        for (i, text) in wrap(&message.text, text_width).into_iter().enumerate() {
+            let time = (i == 0).then(|| clock_time(message.time));
+            lines.push(LogLine { time, text, color });
+        }
+    }
+    lines
+}
+
+/// Break text into lines of at most `width` characters, at spaces where it can
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let mut word: Vec<char> = word.chars().collect();
+        let line_len = line.chars().count();
+        if line_len > 0 && line_len + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        } else if line_len > 0 {
+            line.push(' ');
+        }
+        // Words longer than a line are cut
+        while line.chars().count() + word.len() > width {
+            let room = width - line.chars().count();
+            line.extend(word.drain(..room));
+            lines.push(std::mem::take(&mut line));
+        }
+        line.extend(word);
+    }
+    lines.push(line);
+    lines
+}
+
+/// "HH:MM:SS" of the day, UTC
+pub fn clock_time(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs()) % 86_400;
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatChannel, ChatMessage};
+    use std::time::Duration;
+
+    // ==================== Wrapping Tests ====================
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("the quick brown fox", 10), ["the quick", "brown fox"]);
+        assert_eq!(wrap("abcdefghijkl xy", 5), ["abcde", "fghij", "kl xy"], "Long words are cut");
+        assert_eq!(wrap("", 5), [""], "An empty message is still a line");
+        assert_eq!(wrap("a  b", 10), ["a  b"], "Spacing is kept within a line");
+        assert_eq!(clock_time(UNIX_EPOCH + Duration::from_secs(86_400 * 3 + 3600 * 13 + 60 * 7 + 9)), "13:07:09");
+    }
+
+    #[test]
+    fn test_lines_keep_time_and_color() {
+        let mut chat = ChatWindow::default();
+        chat.add_message(ChatMessage::system("short"));
+        chat.add_message(ChatMessage::new(ChatChannel::Player, "word ".repeat(6).trim_end().to_string(), 0x123456));
+        chat.muted = vec![ChatChannel::Player];
+
+        let lines = lines(&chat, TIME_WIDTH + 12);
+        assert_eq!(lines.len(), 4, "The long message wraps over three lines");
+        assert!(lines[0].time.is_some() && lines[1].time.is_some());
+        assert_eq!(lines[2].time, None, "Only a message's first line has its time");
+        assert_eq!(lines[1].text, "word word");
+        assert_eq!(lines[3].color, 0x123456, "Wrapped lines keep the message's color; muted ones are listed");
+    }
+
+    // ==================== Scrolling Tests ====================
+
+    #[test]
+    fn test_scrolling() {
+        let mut log = ChatLog::default();
+        assert!(!log.scroll_key(NcKey::PgDown), "Only PageUp opens the log");
+        assert!(log.scroll_key(NcKey::PgUp));
+        assert!(log.is_open());
+        assert_eq!(log.view(50, 10), 40..50, "Opens at the newest lines");
+
+        log.scroll_key(NcKey::PgUp);
+        assert_eq!(log.view(50, 10), 31..41, "A page up keeps one line of the last page");
+        log.scroll_key(NcKey::Home);
+        assert_eq!(log.view(50, 10), 0..10);
+        log.scroll_key(NcKey::Down);
+        assert_eq!(log.view(50, 10), 1..11);
+        assert_eq!(log.view(4, 10), 0..4, "Fewer lines than rows show them all");
+
+        log.scroll_key(NcKey::End);
+        log.scroll_key(NcKey::PgDown);
+        assert!(!log.is_open(), "PageDown at the bottom closes the log");
+    }
+}
@@ -0,0 +1,167 @@
+//! Streamed chunks kept on disk, so coming back to a server costs a
+//! revalidation instead of a download.
+//!
+//! A chunk the server tagged (an ETag, see the server's `etag.rs`) is written
+//! to `~/.config/exospace/chunks/<server>/<seed>/<cx>_<cy>.bin`: the tag on
+//! the first line, then the chunk in the binary encoding. Fetching it again
+//! sends the tag as `If-None-Match`, and a 304 means the copy on disk is still
+//! the server's chunk. Chunks from servers that don't tag them aren't kept.
+//! The `chunk_cache` setting turns the store off; its files can be deleted
+//! at any time. Past `MAX_KEPT_CHUNKS` files, across every server and seed,
+//! the oldest written are deleted.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Most chunk files kept on disk in all; a full-size chunk is about 4 KiB
+pub const MAX_KEPT_CHUNKS: usize = 8192;
+
+/// Where one server's chunks for one seed are kept; none keeps nothing
+#[derive(Clone, Debug, Default)]
+pub struct ChunkStore {
+    dir: Option<PathBuf>,
+    /// The directory every server's and seed's stores are under, which the limit covers
+    root: Option<PathBuf>,
+}
+
+impl ChunkStore {
+    /// The store for a server and seed, under the config directory
+    pub fn for_server(server_url: &str, seed: u64) -> Self {
+        let root = dirs::config_dir().map(|mut p| {
+            p.push("exospace");
+            p.push("chunks");
+            p
+        });
+        let dir = root.as_ref().map(|root| root.join(server_dir(server_url)).join(seed.to_string()));
+        ChunkStore { dir, root }
+    }
+
+    /// A store in a directory of its own
+    #[cfg(test)]
+    pub fn at(dir: PathBuf) -> Self {
+        ChunkStore { dir: Some(dir.clone()), root: Some(dir) }
+    }
+
+    fn path(&self, cx: i32, cy: i32) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(format!("{}_{}.bin", cx, cy)))
+    }
+
+    /// The kept copy of a chunk, as (tag, binary chunk)
+    pub fn load(&self, cx: i32, cy: i32) -> Option<(String, Vec<u8>)> {
+        let mut bytes = fs::read(self.path(cx, cy)?).ok()?;
+        let newline = bytes.iter().position(|&b| b == b'\n')?;
+        let tag = String::from_utf8(bytes[..newline].to_vec()).ok()?;
+        bytes.drain(..=newline);
+        Some((tag, bytes))
+    }
+
+    /// Keep a chunk the server tagged; failures are logged, and the chunk is just downloaded next time
+    pub fn save(&self, cx: i32, cy: i32, tag: &str, chunk: &[u8]) {
+        let Some(path) = self.path(cx, cy) else {
+            return;
+        };
+        let mut bytes = Vec::with_capacity(tag.len() + 1 + chunk.len());
+        bytes.extend_from_slice(tag.as_bytes());
+        bytes.push(b'\n');
+        bytes.extend_from_slice(chunk);
+        let written = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|()| fs::write(&path, bytes));
+        if let Err(e) = written {
+            tracing::warn!("Failed to keep chunk ({}, {}): {}", cx, cy, e);
+        }
+        self.trim(MAX_KEPT_CHUNKS);
+    }
+
+    /// Once more than `limit` chunks are kept in all, delete the oldest written down to three quarters of it,
+    /// so the next few saves don't each have to
+    fn trim(&self, limit: usize) {
+        let Some(root) = &self.root else {
+            return;
+        };
+        let mut kept = chunk_files(root);
+        if kept.len() <= limit {
+            return;
+        }
+        kept.sort_by_key(|(written, _)| *written);
+        let excess = kept.len() - limit * 3 / 4;
+        for (_, path) in kept.into_iter().take(excess) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Forget a kept chunk that turned out to be unreadable
+    pub fn remove(&self, cx: i32, cy: i32) {
+        if let Some(path) = self.path(cx, cy) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Every chunk file under `dir`, however deep, with when it was written
+fn chunk_files(dir: &Path) -> Vec<(SystemTime, PathBuf)> {
+    let mut files = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return files;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.metadata() {
+            Ok(meta) if meta.is_dir() => files.extend(chunk_files(&path)),
+            Ok(meta) if path.extension().is_some_and(|ext| ext == "bin") => files.push((meta.modified().unwrap_or(UNIX_EPOCH), path)),
+            _ => {}
+        }
+    }
+    files
+}
+
+/// A directory name for a server: its URL with anything but letters and digits as '_'
+fn server_dir(server_url: &str) -> String {
+    let url = server_url.split_once("://").map_or(server_url, |(_, rest)| rest);
+    url.trim_end_matches('/').chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Store Tests ====================
+
+    #[test]
+    fn test_store_keeps_tagged_chunks() {
+        let dir = std::env::temp_dir().join(format!("exospace-chunks-test-{}", std::process::id()));
+        let store = ChunkStore::at(dir.clone());
+        assert_eq!(store.load(0, -1), None);
+
+        store.save(0, -1, "W/\"abc\"", &[1, 10, 2]);
+        assert_eq!(store.load(0, -1), Some(("W/\"abc\"".to_string(), vec![1, 10, 2])), "Newlines in the chunk survive");
+        store.save(0, -1, "W/\"def\"", &[3]);
+        assert_eq!(store.load(0, -1), Some(("W/\"def\"".to_string(), vec![3])), "A fresh copy replaces the old");
+        store.remove(0, -1);
+        assert_eq!(store.load(0, -1), None);
+
+        ChunkStore::default().save(0, 0, "W/\"abc\"", &[1]);
+        assert_eq!(ChunkStore::default().load(0, 0), None, "No directory, nothing kept");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_store_trims_the_oldest_chunks() {
+        let dir = std::env::temp_dir().join(format!("exospace-chunks-trim-test-{}", std::process::id()));
+        let store = ChunkStore::at(dir.clone());
+        for cx in 0..5 {
+            store.save(cx, 0, "W/\"abc\"", &[1]);
+        }
+        store.trim(8);
+        assert_eq!(chunk_files(&dir).len(), 5, "Under the limit, everything stays");
+        store.trim(4);
+        assert_eq!(chunk_files(&dir).len(), 3, "Over it, down to three quarters");
+        ChunkStore::default().trim(0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_server_dir() {
+        assert_eq!(server_dir("http://localhost:3000/"), "localhost_3000");
+        assert_eq!(server_dir("https://exo.example.com/game"), "exo_example_com_game");
+    }
+}
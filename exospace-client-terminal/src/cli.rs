@@ -0,0 +1,164 @@
+//! Command-line options.
+//!
+//! Every option overrides the matching `config.json` setting for this run
+//! only; nothing given on the command line is written back to the config.
+
+use clap::{CommandFactory, FromArgMatches, Parser};
+use std::path::PathBuf;
+
+use crate::keys::KeyMap;
+use crate::{LOCAL_MAP_SIZE, WORLD_SEED};
+
+/// Smallest and largest locally generated map sides; smaller maps can't fit the generator's rooms and nebulae
+pub const MIN_LOCAL_SIZE: u32 = 64;
+pub const MAX_LOCAL_SIZE: u32 = 2000;
+
+#[derive(Parser, Debug, Default, PartialEq)]
+#[command(name = "exospace-client-terminal", version, about = "Terminal client for ExoSpace")]
+pub struct Cli {
+    /// Server to connect to at startup, e.g. http://localhost:3000; without it the game starts solo
+    /// and the title menu's Connect joins one
+    #[arg(long, value_name = "URL")]
+    pub server: Option<String>,
+    /// World seed, for the streamed world and local generation
+    #[arg(long, value_name = "N")]
+    pub seed: Option<u64>,
+    /// Play today's seed, the same for everyone (UTC day, from the server when online)
+    #[arg(long, conflicts_with = "seed")]
+    pub daily: bool,
+    /// Width of a locally generated map
+    #[arg(long, value_name = "TILES", value_parser = clap::value_parser!(u32).range(MIN_LOCAL_SIZE as i64..=MAX_LOCAL_SIZE as i64))]
+    pub width: Option<u32>,
+    /// Height of a locally generated map
+    #[arg(long, value_name = "TILES", value_parser = clap::value_parser!(u32).range(MIN_LOCAL_SIZE as i64..=MAX_LOCAL_SIZE as i64))]
+    pub height: Option<u32>,
+    /// Turn background effects on
+    #[arg(long, conflicts_with = "no_effects")]
+    pub effects: bool,
+    /// Turn background effects off
+    #[arg(long)]
+    pub no_effects: bool,
+    /// Config file to use instead of ~/.config/exospace/config.json
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Play solo without contacting the server, resuming the latest saved map
+    /// unless a seed or size is given
+    #[arg(long)]
+    pub offline: bool,
+    /// Create the config's account on the server before logging in
+    #[arg(long)]
+    pub register: bool,
+    /// Run without the terminal UI, driven by a script, writing chat to stdout (for bots and CI)
+    #[arg(long)]
+    pub headless: bool,
+    /// Script for --headless; without it the script is read from stdin
+    #[arg(long, value_name = "PATH", requires = "headless")]
+    pub script: Option<PathBuf>,
+}
+
+impl Cli {
+    /// Parse the process arguments; `--help` and bad arguments exit here
+    pub fn load() -> Self {
+        let matches = Self::command().after_help(keys_help()).get_matches();
+        Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed.unwrap_or(WORLD_SEED)
+    }
+
+    /// Size of a locally generated map as (width, height)
+    pub fn local_size(&self) -> (usize, usize) {
+        (
+            self.width.map_or(LOCAL_MAP_SIZE.0, |w| w as usize),
+            self.height.map_or(LOCAL_MAP_SIZE.1, |h| h as usize),
+        )
+    }
+
+    /// Whether a particular local map was asked for, rather than whatever was played last
+    pub fn wants_new_map(&self) -> bool {
+        self.seed.is_some() || self.daily || self.width.is_some() || self.height.is_some()
+    }
+
+    /// Whether to load the server's world and dial in before the title menu, rather than start solo
+    pub fn connects_at_start(&self) -> bool {
+        !self.offline && self.server.is_some()
+    }
+
+    /// `--effects` or `--no-effects`, if either was given
+    pub fn effects(&self) -> Option<bool> {
+        match (self.effects, self.no_effects) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// Default keys for `--help`
+fn keys_help() -> String {
+    let mut help = String::from("Keys (change them with /bind, or \"keybindings\" and \"input_profile\" in the config):\n");
+    for line in KeyMap::default().describe() {
+        help.push_str(&format!("  {}\n", line));
+    }
+    help.push_str("\nIn chat, /help lists the slash commands.");
+    help
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("exospace-client-terminal").chain(line.split_whitespace()))
+    }
+
+    // ==================== Argument Tests ====================
+
+    #[test]
+    fn test_no_arguments_keeps_defaults() {
+        let cli = parse("").unwrap();
+        assert_eq!(cli, Cli::default());
+        assert_eq!(cli.seed(), WORLD_SEED);
+        assert_eq!(cli.local_size(), LOCAL_MAP_SIZE);
+        assert_eq!(cli.effects(), None, "The config decides");
+        assert!(!cli.wants_new_map());
+        assert!(!cli.connects_at_start(), "Solo until the title menu connects");
+    }
+
+    #[test]
+    fn test_arguments_parse() {
+        let cli = parse("--server http://game:4000 --seed 7 --width 300 --no-effects --config /tmp/c.json --offline").unwrap();
+        assert_eq!(cli.server.as_deref(), Some("http://game:4000"));
+        assert_eq!(cli.seed(), 7);
+        assert_eq!(cli.local_size(), (300, LOCAL_MAP_SIZE.1));
+        assert_eq!(cli.effects(), Some(false));
+        assert_eq!(cli.config, Some(PathBuf::from("/tmp/c.json")));
+        assert!(cli.offline && cli.wants_new_map());
+        assert!(!cli.connects_at_start(), "--offline wins over --server");
+        assert!(parse("--server http://game:4000").unwrap().connects_at_start());
+        assert_eq!(parse("--effects").unwrap().effects(), Some(true));
+        assert!(parse("--daily").unwrap().wants_new_map(), "A daily map isn't the last save");
+        let cli = parse("--headless --script bot.txt --server http://game:4000").unwrap();
+        assert!(cli.headless && cli.connects_at_start());
+        assert_eq!(cli.script, Some(PathBuf::from("bot.txt")));
+    }
+
+    #[test]
+    fn test_bad_arguments_are_refused() {
+        assert!(parse("--seed many").is_err());
+        assert!(parse("--width 10").is_err(), "Too small to generate");
+        assert!(parse("--height 5000").is_err(), "Too big");
+        assert!(parse("--effects --no-effects").is_err());
+        assert!(parse("--bogus").is_err());
+        assert!(parse("--daily --seed 3").is_err(), "Today's seed or a chosen one, not both");
+        assert!(parse("--script bot.txt").is_err(), "Scripts are for --headless");
+    }
+
+    #[test]
+    fn test_help_lists_keys() {
+        let help = Cli::command().after_help(keys_help()).render_help().to_string();
+        assert!(help.contains("--offline"));
+        assert!(help.contains("toggle_minimap = "), "{}", help);
+    }
+}
@@ -0,0 +1,145 @@
+//! Game time: where the main loop gets "now" from, and fixed timesteps.
+//!
+//! The loop reads the time once per frame from a `GameClock` and hands that
+//! `Instant` to everything it drives. Movement, ship ticks and animation run on
+//! `FixedStep`s, which bank the time between frames and pay it out in whole
+//! steps, so a slow frame is made up on the next one instead of slowing the
+//! game down. Tests use `MockClock` to step time by hand.
+
+use std::time::{Duration, Instant};
+
+/// Length of one ship tick: hull, mining and missions, whatever the ship class flies at
+pub const SHIP_TICK: Duration = Duration::from_millis(33);
+
+/// Length of one animation frame (`Renderer::tick()`)
+pub const ANIMATION_STEP: Duration = Duration::from_millis(16);
+
+/// Pause between loading screen frames; the game itself is paced by `perf::FramePacer`
+pub const FRAME_SLEEP: Duration = Duration::from_millis(16);
+
+/// Most steps paid out at once; time beyond that (a stalled terminal, a suspended process) is dropped
+pub const MAX_CATCH_UP_STEPS: u32 = 5;
+
+pub trait GameClock {
+    fn now(&self) -> Instant;
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// The wall clock
+pub struct RealClock;
+
+impl GameClock for RealClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves when told to; sleeping moves it
+#[cfg(test)]
+pub struct MockClock {
+    now: Instant,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new() -> Self {
+        MockClock { now: Instant::now() }
+    }
+
+    pub fn advance(&mut self, by: Duration) {
+        self.now += by;
+    }
+}
+
+#[cfg(test)]
+impl GameClock for MockClock {
+    fn now(&self) -> Instant {
+        self.now
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// A fixed timestep fed by an accumulator
+pub struct FixedStep {
+    step: Duration,
+    banked: Duration,
+    last: Instant,
+}
+
+impl FixedStep {
+    pub fn new(step: Duration, now: Instant) -> Self {
+        FixedStep { step: step.max(Duration::from_millis(1)), banked: Duration::ZERO, last: now }
+    }
+
+    /// Change the step length; banked time carries over
+    pub fn set_step(&mut self, step: Duration) {
+        self.step = step.max(Duration::from_millis(1));
+    }
+
+    /// Bank the time since the last call and return how many whole steps it buys
+    pub fn advance(&mut self, now: Instant) -> u32 {
+        self.banked += now.saturating_duration_since(self.last);
+        self.last = now;
+        let due = (self.banked.as_nanos() / self.step.as_nanos()) as u32;
+        if due > MAX_CATCH_UP_STEPS {
+            self.banked = Duration::ZERO;
+            return MAX_CATCH_UP_STEPS;
+        }
+        self.banked -= self.step * due;
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(clock: &mut MockClock, steps: &mut FixedStep, frames: &[u64]) -> Vec<u32> {
+        frames
+            .iter()
+            .map(|&ms| {
+                clock.sleep(Duration::from_millis(ms));
+                steps.advance(clock.now())
+            })
+            .collect()
+    }
+
+    // ==================== Cadence Tests ====================
+
+    #[test]
+    fn test_steps_keep_cadence_across_uneven_frames() {
+        let mut clock = MockClock::new();
+        let mut moves = FixedStep::new(Duration::from_millis(33), clock.now());
+        let due = run(&mut clock, &mut moves, &[16, 16, 16, 50, 10, 20, 33]);
+        assert_eq!(due, [0, 0, 1, 1, 1, 0, 1], "Leftover time carries into later frames");
+        assert_eq!(due.iter().sum::<u32>(), 161 / 33, "One step per 33ms overall");
+
+        let due = run(&mut clock, &mut moves, &[16; 60]);
+        assert_eq!(due.iter().sum::<u32>(), 29, "960ms of 16ms frames and the 29ms banked before buy 29 moves");
+    }
+
+    #[test]
+    fn test_stalls_are_capped() {
+        let mut clock = MockClock::new();
+        let mut anim = FixedStep::new(ANIMATION_STEP, clock.now());
+        assert_eq!(run(&mut clock, &mut anim, &[5000, 16]), [MAX_CATCH_UP_STEPS, 1], "A stall doesn't replay every missed step");
+    }
+
+    #[test]
+    fn test_step_changes_keep_banked_time() {
+        let mut clock = MockClock::new();
+        let mut moves = FixedStep::new(Duration::from_millis(40), clock.now());
+        assert_eq!(run(&mut clock, &mut moves, &[30]), [0]);
+        moves.set_step(Duration::from_millis(20));
+        assert_eq!(run(&mut clock, &mut moves, &[10]), [2], "A faster ship spends the banked 30ms at the new rate");
+        moves.set_step(Duration::ZERO);
+        assert_eq!(run(&mut clock, &mut moves, &[3]), [3], "Zero-length steps are a millisecond long");
+    }
+}
@@ -0,0 +1,147 @@
+//! Firing projectiles and flying them for the game view.
+//!
+//! The fire key (space) shoots a projectile the way the ship faces, at most
+//! once per `FIRE_COOLDOWN`. Flying solo the shots are ours: they cut plain
+//! asteroids away and stop at anything else solid. In multiplayer the server
+//! decides what a shot hits, so we send `Fire`, mirror each `Shot` it
+//! announces under the server's id and fly it on the same step so it moves
+//! smoothly until its `ShotEnded`. Either way a shot is drawn with a fading
+//! trail over the tiles it just crossed.
+
+use exospace_core::Direction;
+use exospace_core::ecs::{Entities, Entity, FIRE_COOLDOWN, Impact, PROJECTILE_RANGE, PROJECTILE_STEP, Position};
+use std::time::Instant;
+
+use crate::clock::FixedStep;
+
+/// How a shot's trail is drawn, from the tile right behind it back
+pub const TRAIL_STYLES: [(char, u32); 3] = [('·', 0xC09838), ('·', 0x806428), ('·', 0x4A3A1C)];
+
+/// Our guns and every shot in flight near us
+pub struct Guns {
+    pub shots: Entities,
+    steps: FixedStep,
+    last_fired: Option<Instant>,
+}
+
+impl Guns {
+    pub fn new(now: Instant) -> Self {
+        Guns { shots: Entities::default(), steps: FixedStep::new(PROJECTILE_STEP, now), last_fired: None }
+    }
+
+    /// Pull the trigger: true if the guns were ready, which starts their cooldown
+    pub fn trigger(&mut self, now: Instant) -> bool {
+        if self.last_fired.is_some_and(|at| now.saturating_duration_since(at) < FIRE_COOLDOWN) {
+            return false;
+        }
+        self.last_fired = Some(now);
+        true
+    }
+
+    /// A shot of our own, flying solo
+    pub fn fire(&mut self, (x, y): (i32, i32), direction: Direction) {
+        self.shots.fire(0, Position { x, y }, direction);
+    }
+
+    /// A shot the server announced
+    pub fn mirror(&mut self, id: u64, owner: u64, (x, y): (i32, i32), direction: Direction) {
+        self.shots.put_projectile(Entity(id), owner, Position { x, y }, direction);
+    }
+
+    /// The server says a shot stopped; our copy may have already
+    pub fn end(&mut self, id: u64) {
+        self.shots.despawn(Entity(id));
+    }
+
+    /// Drop every shot, e.g. when the link comes or goes and ids change hands
+    pub fn clear(&mut self) {
+        self.shots.clear();
+    }
+
+    /// Fly shots a tile for every step due by `now`; why each shot that stopped did
+    pub fn tick(
+        &mut self,
+        now: Instant,
+        mut solid: impl FnMut(Position) -> bool,
+        mut target: impl FnMut(Position) -> Option<u64>,
+    ) -> Vec<Impact> {
+        let mut impacts = Vec::new();
+        for _ in 0..self.steps.advance(now) {
+            let stopped = self.shots.step_projectiles(&mut solid, &mut target);
+            impacts.extend(stopped.into_iter().map(|(_, _, impact)| impact));
+        }
+        impacts
+    }
+
+    /// Tiles behind each shot and how far back they are (0 is right behind); never further back than it has flown
+    pub fn trails(&self) -> Vec<(Position, usize)> {
+        let mut trails = Vec::new();
+        for (entity, shot) in self.shots.projectiles.iter() {
+            let (Some(position), Some(velocity)) = (self.shots.positions.get(entity), self.shots.velocities.get(entity)) else {
+                continue;
+            };
+            let flown = (PROJECTILE_RANGE - shot.range) as usize;
+            for back in 0..flown.min(TRAIL_STYLES.len()) {
+                let tiles = back as i32 + 1;
+                trails.push((Position { x: position.x - velocity.dx * tiles, y: position.y - velocity.dy * tiles }, back));
+            }
+        }
+        trails
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{GameClock, MockClock};
+    use std::time::Duration;
+
+    // ==================== Firing Tests ====================
+
+    #[test]
+    fn test_trigger_cools_down() {
+        let mut clock = MockClock::new();
+        let mut guns = Guns::new(clock.now());
+        assert!(guns.trigger(clock.now()));
+        clock.advance(FIRE_COOLDOWN / 2);
+        assert!(!guns.trigger(clock.now()), "Still cooling down");
+        clock.advance(FIRE_COOLDOWN / 2);
+        assert!(guns.trigger(clock.now()));
+    }
+
+    // ==================== Flight Tests ====================
+
+    #[test]
+    fn test_shots_fly_on_the_step_and_leave_trails() {
+        let mut clock = MockClock::new();
+        let mut guns = Guns::new(clock.now());
+        guns.fire((0, 0), Direction::Right);
+        assert!(guns.trails().is_empty(), "No trail before it has moved");
+
+        clock.advance(PROJECTILE_STEP * 2);
+        assert!(guns.tick(clock.now(), |_| false, |_| None).is_empty());
+        let trails: Vec<(i32, usize)> = guns.trails().iter().map(|&(position, back)| (position.x, back)).collect();
+        assert_eq!(trails, [(1, 0), (0, 1)], "Two steps flown, two tiles of trail");
+
+        clock.advance(PROJECTILE_STEP * 3);
+        let impacts = guns.tick(clock.now(), |p| p.x == 4, |_| None);
+        assert_eq!(impacts, [Impact::Tile(Position { x: 4, y: 0 })]);
+        assert!(guns.shots.is_empty());
+    }
+
+    #[test]
+    fn test_mirrored_shots_end_when_told() {
+        let mut clock = MockClock::new();
+        let mut guns = Guns::new(clock.now());
+        guns.mirror(40, 2, (5, 5), Direction::Up);
+        guns.mirror(41, 3, (5, 5), Direction::Down);
+        clock.advance(Duration::from_millis(60));
+        guns.tick(clock.now(), |_| false, |_| None);
+        assert_eq!(guns.shots.positions.get(Entity(40)), Some(&Position { x: 5, y: 4 }));
+        guns.end(40);
+        guns.end(40);
+        assert_eq!(guns.shots.len(), 1, "Ending twice is harmless");
+        guns.clear();
+        assert!(guns.shots.is_empty());
+    }
+}
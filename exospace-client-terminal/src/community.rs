@@ -0,0 +1,163 @@
+//! Community maps: maps pilots have uploaded to the server.
+//!
+//! `/maps` fetches the server's list and opens it as a screen (see
+//! `screens.rs`); Enter plays the chosen map as a solo map in place of a
+//! generated one, and Esc closes the list. `/upload NAME` sends the solo map
+//! being flown, from the ship's position, under the pilot's account. A
+//! community map has no seed of its own, so it's flown on a seed hashed from
+//! its contents: the same map keeps its exploration and difficulty however
+//! it was fetched.
+
+use std::time::Duration;
+
+use crate::account;
+use crate::apierror::ServerError;
+use crate::menu::{Menu, MenuEvent, MenuItem};
+use crate::screens::Key;
+use exospace_core::{BINARY_CONTENT_TYPE, MapData, MapListing, fnv1a};
+
+/// Rows of the list shown at once; the list scrolls to keep the cursor in view
+const VISIBLE_ROWS: usize = 12;
+
+/// What a key on the list asks for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapPick {
+    Play(u64),
+    Close,
+}
+
+/// The server's community maps, as listed
+pub struct CommunityScreen {
+    listings: Vec<MapListing>,
+    menu: Menu,
+}
+
+impl CommunityScreen {
+    pub fn new(listings: Vec<MapListing>) -> Self {
+        let items = listings
+            .iter()
+            .map(|listing| MenuItem::new(&listing.name, format!("{:<16}{:>4}x{}", listing.author, listing.width, listing.height)))
+            .collect();
+        CommunityScreen { listings, menu: Menu::new(items) }
+    }
+
+    pub fn input(&mut self, key: Key) -> Option<MapPick> {
+        match self.menu.input(key) {
+            MenuEvent::Chosen(index) => self.listings.get(index).map(|listing| MapPick::Play(listing.id)),
+            MenuEvent::Closed => Some(MapPick::Close),
+            _ => None,
+        }
+    }
+
+    /// Panel text: a window of the list around the cursor, then the keys
+    pub fn lines(&self) -> Vec<String> {
+        if self.listings.is_empty() {
+            return vec!["No community maps yet".to_string(), "/upload NAME shares yours".to_string(), "Esc closes".to_string()];
+        }
+        let first = self.menu.selected().saturating_sub(VISIBLE_ROWS - 1);
+        let mut lines = vec![format!("{} maps, newest first", self.listings.len())];
+        lines.extend(self.menu.lines().into_iter().skip(first).take(VISIBLE_ROWS));
+        lines.push("Up/Down and Enter plays, Esc closes".to_string());
+        lines
+    }
+}
+
+/// The local seed a community map is flown on: an FNV-1a hash of its binary encoding
+pub fn map_seed(map: &MapData) -> u64 {
+    fnv1a(&map.to_bytes())
+}
+
+fn client(timeout: Duration) -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+/// The server's community maps, newest first
+pub fn fetch_listings(server_url: &str) -> Result<Vec<MapListing>, String> {
+    let url = format!("{}/maps", server_url.trim_end_matches('/'));
+    let response = client(Duration::from_secs(2))?.get(url).send().map_err(|e| format!("Failed to fetch the community maps: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Server refused the community maps: {}", ServerError::from_response(response)));
+    }
+    response.json().map_err(|e| format!("Bad community map list: {}", e))
+}
+
+/// Download one community map in the binary encoding
+pub fn fetch_map(server_url: &str, id: u64) -> Result<MapData, String> {
+    let url = format!("{}/maps/{}?format=bin", server_url.trim_end_matches('/'), id);
+    let response = client(Duration::from_secs(10))?.get(url).send().map_err(|e| format!("Failed to fetch community map {}: {}", id, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Server refused community map {}: {}", id, ServerError::from_response(response)));
+    }
+    let bytes = response.bytes().map_err(|e| format!("Failed to read community map {}: {}", id, e))?;
+    MapData::from_bytes(&bytes).map_err(|e| format!("Bad community map {}: {}", id, e))
+}
+
+/// Share a map under the account `token` belongs to; the server answers with its listing
+pub fn upload(server_url: &str, token: &str, name: &str, map: &MapData) -> Result<MapListing, String> {
+    let url = format!("{}/maps/upload", server_url.trim_end_matches('/'));
+    let response = client(Duration::from_secs(10))?
+        .post(url)
+        .query(&[("name", name)])
+        .header(reqwest::header::AUTHORIZATION, account::bearer(token))
+        .header(reqwest::header::CONTENT_TYPE, BINARY_CONTENT_TYPE)
+        .body(map.to_bytes())
+        .send()
+        .map_err(|e| format!("Failed to upload the map: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Server refused the map: {}", ServerError::from_response(response)));
+    }
+    response.json().map_err(|e| format!("Bad upload reply: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::Tile;
+
+    fn listing(id: u64, name: &str) -> MapListing {
+        MapListing { id, name: name.to_string(), author: "Ace".to_string(), width: 40, height: 20, uploaded: 0 }
+    }
+
+    // ==================== List Tests ====================
+
+    #[test]
+    fn test_list_picks_a_map() {
+        let mut screen = CommunityScreen::new(vec![listing(7, "Belt"), listing(3, "The Maze")]);
+        let lines = screen.lines();
+        assert_eq!(lines[0], "2 maps, newest first");
+        assert_eq!(lines[1], "> Belt      Ace               40x20");
+        assert_eq!(screen.input(Key::Down), None);
+        assert_eq!(screen.input(Key::Enter), Some(MapPick::Play(3)), "Enter plays the map under the cursor by id");
+        assert_eq!(screen.input(Key::Esc), Some(MapPick::Close));
+
+        let mut empty = CommunityScreen::new(Vec::new());
+        assert_eq!(empty.input(Key::Enter), None, "Nothing to play");
+        assert_eq!(empty.lines()[0], "No community maps yet");
+    }
+
+    #[test]
+    fn test_long_list_scrolls() {
+        let listings = (0..30).map(|i| listing(i, &format!("Map {}", i))).collect();
+        let mut screen = CommunityScreen::new(listings);
+        assert_eq!(screen.lines().len(), VISIBLE_ROWS + 2);
+        for _ in 0..20 {
+            screen.input(Key::Down);
+        }
+        let lines = screen.lines();
+        assert_eq!(lines.len(), VISIBLE_ROWS + 2);
+        assert!(lines[VISIBLE_ROWS].starts_with("> Map 20"), "The cursor stays on the last row shown");
+    }
+
+    // ==================== Seed Tests ====================
+
+    #[test]
+    fn test_map_seed_follows_the_contents() {
+        let map = MapData::filled(10, 5, Tile::Floor);
+        assert_eq!(map_seed(&map), map_seed(&map.clone()), "The same map, the same seed");
+        let other = MapData { start_x: 2, ..map.clone() };
+        assert_ne!(map_seed(&map), map_seed(&other));
+    }
+}
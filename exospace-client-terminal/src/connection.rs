@@ -0,0 +1,397 @@
+//! Keeping the multiplayer connection up.
+//!
+//! `Connection` owns the `NetClient` and watches it: while online it pings
+//! the server's `/health` every `HEALTH_INTERVAL` on a throwaway thread, and
+//! a closed socket or `MAX_MISSED_PINGS` unanswered pings in a row drop it to
+//! reconnecting. Reconnects are tried on a thread too (health first, then the
+//! websocket), `FIRST_RETRY` after the drop and twice as long after each
+//! failure, up to `MAX_RETRY`. Answered pings' round trips are kept as the
+//! link's `latency()`. The game loop calls `update()` every frame
+//! and resyncs on `LinkEvent::Restored`; `--offline` never dials at all.
+//! Every dial shows the server our ship's livery, which `repaint()` keeps
+//! current.
+
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use exospace_core::{ClientMessage, ShipLivery};
+
+use crate::Config;
+use crate::net::NetClient;
+
+/// Time between health pings while online
+pub const HEALTH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a health ping may take before it counts as missed
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Missed pings in a row that mean the server is gone
+pub const MAX_MISSED_PINGS: u32 = 2;
+
+/// Wait before the first reconnect
+pub const FIRST_RETRY: Duration = Duration::from_secs(1);
+
+/// Longest wait between reconnects
+pub const MAX_RETRY: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkState {
+    Online,
+    Offline,
+    Reconnecting,
+}
+
+impl LinkState {
+    pub fn label(self) -> &'static str {
+        match self {
+            LinkState::Online => "ONLINE",
+            LinkState::Offline => "OFFLINE",
+            LinkState::Reconnecting => "RECONNECTING",
+        }
+    }
+}
+
+/// A change worth telling the player about
+#[derive(Debug, PartialEq)]
+pub enum LinkEvent {
+    /// The connection dropped, and why
+    Lost(String),
+    /// Connected again; everything the server sent before is stale
+    Restored,
+}
+
+pub struct Connection {
+    server_url: String,
+    name: String,
+    token: Option<String>,
+    /// Our ship's name, callsign and paint, sent on every connect
+    ship: ShipLivery,
+    /// The socket while online
+    pub client: Option<NetClient>,
+    state: LinkState,
+    /// Failed reconnects since the connection dropped
+    attempts: u32,
+    next_attempt: Instant,
+    next_ping: Instant,
+    missed_pings: u32,
+    /// A health ping under way; it sends its round trip if answered
+    ping: Option<Receiver<Option<Duration>>>,
+    /// Round trip of the last answered ping
+    latency: Option<Duration>,
+    /// A reconnect under way
+    dialing: Option<Receiver<Result<NetClient, String>>>,
+    /// Times the connection has come back
+    reconnects: u32,
+}
+
+impl Connection {
+    /// A connection that never dials (`--offline`, or solo until the title menu connects)
+    pub fn offline(now: Instant) -> Self {
+        Connection {
+            server_url: String::new(),
+            name: String::new(),
+            token: None,
+            ship: ShipLivery::default(),
+            client: None,
+            state: LinkState::Offline,
+            attempts: 0,
+            next_attempt: now,
+            next_ping: now,
+            missed_pings: 0,
+            ping: None,
+            latency: None,
+            dialing: None,
+            reconnects: 0,
+        }
+    }
+
+    /// Connect straight away; on failure keep retrying in the background
+    pub fn start(config: &Config, now: Instant) -> (Self, Result<(), String>) {
+        let mut connection = Connection {
+            server_url: config.server_url().to_string(),
+            name: config.player_name().to_string(),
+            token: config.auth_token().map(str::to_string),
+            ship: config.livery().0,
+            ..Self::offline(now)
+        };
+        let result = NetClient::connect(&connection.server_url, &connection.name, connection.token.as_deref(), &connection.ship);
+        match result {
+            Ok(client) => {
+                connection.online(client, now);
+                (connection, Ok(()))
+            }
+            Err(e) => {
+                connection.drop_link(now);
+                (connection, Err(e))
+            }
+        }
+    }
+
+    pub fn state(&self) -> LinkState {
+        self.state
+    }
+
+    /// Times the connection has come back since startup
+    pub fn reconnects(&self) -> u32 {
+        self.reconnects
+    }
+
+    /// Time left before the next reconnect, while waiting for one
+    pub fn retry_in(&self, now: Instant) -> Option<Duration> {
+        (self.state == LinkState::Reconnecting && self.dialing.is_none()).then(|| self.next_attempt.saturating_duration_since(now))
+    }
+
+    /// Round trip of the last answered health ping while online
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    /// Show a new livery now if online, and on every reconnect
+    pub fn repaint(&mut self, ship: ShipLivery) {
+        if let Some(client) = &self.client {
+            client.send(ClientMessage::Livery { ship: ship.clone() });
+        }
+        self.ship = ship;
+    }
+
+    /// Drop the socket for good: offline, with no reconnects
+    pub fn hang_up(&mut self) {
+        self.client = None;
+        self.state = LinkState::Offline;
+        self.ping = None;
+        self.latency = None;
+        self.dialing = None;
+    }
+
+    /// Watch the socket, ping and reconnect as due; call once per frame after polling the client
+    pub fn update(&mut self, now: Instant) -> Option<LinkEvent> {
+        match self.state {
+            LinkState::Offline => None,
+            LinkState::Online => self.watch(now),
+            LinkState::Reconnecting => self.redial(now),
+        }
+    }
+
+    fn watch(&mut self, now: Instant) -> Option<LinkEvent> {
+        if self.client.as_ref().is_some_and(|client| !client.connected) {
+            self.drop_link(now);
+            return Some(LinkEvent::Lost("Lost connection to multiplayer server".to_string()));
+        }
+        if let Some(answer) = self.ping.as_ref().and_then(finished) {
+            self.ping = None;
+            match answer.flatten() {
+                Some(round_trip) => {
+                    self.missed_pings = 0;
+                    self.latency = Some(round_trip);
+                }
+                None => self.missed_pings += 1,
+            }
+            if self.missed_pings >= MAX_MISSED_PINGS {
+                self.drop_link(now);
+                return Some(LinkEvent::Lost("The multiplayer server stopped answering".to_string()));
+            }
+        }
+        if self.ping.is_none() && now >= self.next_ping {
+            self.next_ping = now + HEALTH_INTERVAL;
+            let (sender, receiver) = mpsc::channel();
+            let url = self.server_url.clone();
+            thread::spawn(move || {
+                let sent = Instant::now();
+                let _ = sender.send(check_health(&url).ok().map(|()| sent.elapsed()));
+            });
+            self.ping = Some(receiver);
+        }
+        None
+    }
+
+    fn redial(&mut self, now: Instant) -> Option<LinkEvent> {
+        if let Some(dialing) = &self.dialing {
+            match finished(dialing)? {
+                Some(Ok(client)) => {
+                    self.online(client, now);
+                    self.reconnects += 1;
+                    return Some(LinkEvent::Restored);
+                }
+                failed => {
+                    if let Some(Err(e)) = failed {
+                        tracing::debug!(attempt = self.attempts + 1, "Reconnect failed: {}", e);
+                    }
+                    self.dialing = None;
+                    self.attempts += 1;
+                    self.next_attempt = now + backoff(self.attempts);
+                }
+            }
+        } else if now >= self.next_attempt {
+            let (sender, receiver) = mpsc::channel();
+            let (url, name, token, ship) = (self.server_url.clone(), self.name.clone(), self.token.clone(), self.ship.clone());
+            thread::spawn(move || {
+                let result = check_health(&url).and_then(|()| NetClient::connect(&url, &name, token.as_deref(), &ship));
+                let _ = sender.send(result);
+            });
+            self.dialing = Some(receiver);
+        }
+        None
+    }
+
+    fn online(&mut self, client: NetClient, now: Instant) {
+        self.client = Some(client);
+        self.state = LinkState::Online;
+        self.attempts = 0;
+        self.missed_pings = 0;
+        self.next_ping = now + HEALTH_INTERVAL;
+        self.dialing = None;
+    }
+
+    fn drop_link(&mut self, now: Instant) {
+        self.client = None;
+        self.state = LinkState::Reconnecting;
+        self.attempts = 0;
+        self.next_attempt = now + backoff(0);
+        self.ping = None;
+        self.latency = None;
+    }
+}
+
+/// Wait before reconnect attempt `attempts + 1`: doubling from `FIRST_RETRY`, capped at `MAX_RETRY`
+pub fn backoff(attempts: u32) -> Duration {
+    FIRST_RETRY.saturating_mul(1 << attempts.saturating_sub(1).min(16)).min(MAX_RETRY)
+}
+
+/// What a worker thread sent, once it's done; a thread that died counts as done with nothing
+fn finished<T>(receiver: &Receiver<T>) -> Option<Option<T>> {
+    match receiver.try_recv() {
+        Ok(value) => Some(Some(value)),
+        Err(TryRecvError::Empty) => None,
+        Err(TryRecvError::Disconnected) => Some(None),
+    }
+}
+
+/// GET `/health`, which answers 200 while the server is up
+fn check_health(server_url: &str) -> Result<(), String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(PING_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let url = format!("{}/health", server_url.trim_end_matches('/'));
+    let response = client.get(url).send().map_err(|e| format!("Health check failed: {}", e))?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("Health check failed: {}", response.status()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::ServerMessage;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// A server for `sessions` websocket sessions, answering any health checks between them;
+    /// each session is welcomed and then hung up on
+    fn serve(sessions: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let mut left = sessions;
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut start = [0; 11];
+                if stream.peek(&mut start).is_ok() && &start == b"GET /health" {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        let n = stream.read(&mut buf).unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok").unwrap();
+                    continue;
+                }
+                let mut socket = tungstenite::accept(stream).unwrap();
+                let _hello = socket.read().unwrap();
+                let welcome = ServerMessage::Welcome { id: left as u64, players: Vec::new(), seq: 0, unix_secs: 0 };
+                socket.send(tungstenite::Message::text(serde_json::to_string(&welcome).unwrap())).unwrap();
+                let _ = socket.close(None);
+                let _ = socket.flush();
+                left -= 1;
+                if left == 0 {
+                    return;
+                }
+            }
+        });
+        url
+    }
+
+    /// Update until an event comes (polling the client like the game loop does)
+    fn next_event(connection: &mut Connection, now: Instant) -> Option<LinkEvent> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if let Some(client) = connection.client.as_mut() {
+                client.poll();
+            }
+            if let Some(event) = connection.update(now) {
+                return Some(event);
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        None
+    }
+
+    // ==================== Backoff Tests ====================
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let waits: Vec<u64> = (0..8).map(|attempts| backoff(attempts).as_secs()).collect();
+        assert_eq!(waits, [1, 1, 2, 4, 8, 16, 30, 30]);
+        assert_eq!(backoff(u32::MAX), MAX_RETRY, "No overflow however long it's been down");
+        assert_eq!(LinkState::Reconnecting.label(), "RECONNECTING");
+    }
+
+    // ==================== Reconnect Tests ====================
+
+    #[test]
+    fn test_unreachable_server_backs_off() {
+        let config = Config { server_url: Some("http://127.0.0.1:9".to_string()), ..Default::default() };
+        let now = Instant::now();
+        let (mut connection, result) = Connection::start(&config, now);
+        assert!(result.is_err());
+        assert_eq!(connection.state(), LinkState::Reconnecting);
+        assert_eq!(connection.update(now), None);
+        assert_eq!(connection.retry_in(now), Some(FIRST_RETRY), "Nothing is tried before the first wait");
+
+        let later = now + FIRST_RETRY;
+        connection.update(later);
+        assert_eq!(connection.retry_in(later), None, "Dialing");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while connection.retry_in(later).is_none() && Instant::now() < deadline {
+            connection.update(later);
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(connection.retry_in(later), Some(backoff(1)), "The wait grows after a failure");
+        assert!(connection.client.is_none());
+        assert_eq!(Connection::offline(now).update(later), None, "Offline never dials");
+    }
+
+    #[test]
+    fn test_dropped_connection_is_restored() {
+        let config = Config { server_url: Some(serve(2)), ..Default::default() };
+        let now = Instant::now();
+        let (mut connection, result) = Connection::start(&config, now);
+        assert_eq!(result, Ok(()));
+        assert_eq!(connection.state(), LinkState::Online);
+
+        assert_eq!(next_event(&mut connection, now), Some(LinkEvent::Lost("Lost connection to multiplayer server".to_string())));
+        assert_eq!(connection.state(), LinkState::Reconnecting);
+        assert_eq!(next_event(&mut connection, now + FIRST_RETRY), Some(LinkEvent::Restored), "Health, then the websocket");
+        assert_eq!(connection.state(), LinkState::Online);
+        assert_eq!(connection.reconnects(), 1);
+        let messages = connection.client.as_mut().map(|client| client.poll()).unwrap_or_default();
+        assert!(messages.iter().all(|m| matches!(m, ServerMessage::Welcome { .. })), "The new session starts with a Welcome");
+
+        connection.hang_up();
+        assert_eq!((connection.state(), connection.client.is_none()), (LinkState::Offline, true));
+        assert_eq!(connection.update(now + MAX_RETRY), None, "Hung up connections don't redial");
+    }
+}
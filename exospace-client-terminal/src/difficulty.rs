@@ -0,0 +1,83 @@
+//! Difficulty for solo worlds.
+//!
+//! Solo play, on the locally generated map used when the server can't be
+//! reached, has nobody else setting the rules, so each solo world has its own
+//! difficulty, saved in the config under the world's key like its explored
+//! tiles. It scales mining: how much a tile yields and how long it takes to
+//! extract. NPC ships and events only run on servers, so there is nothing
+//! else to scale or adapt to offline.
+
+use serde::{Deserialize, Serialize};
+
+use crate::mining::{MINE_TICKS, Resource};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        Self::ALL.into_iter().find(|d| d.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Units extracted from one tile; never less than one
+    pub fn mining_yield(self, resource: Resource) -> u32 {
+        let base = resource.yield_amount();
+        match self {
+            Difficulty::Easy => base * 2,
+            Difficulty::Normal => base,
+            Difficulty::Hard => base.div_ceil(2),
+        }
+    }
+
+    /// Movement ticks to extract one tile
+    pub fn mine_ticks(self) -> u32 {
+        match self {
+            Difficulty::Easy => MINE_TICKS * 2 / 3,
+            Difficulty::Normal => MINE_TICKS,
+            Difficulty::Hard => MINE_TICKS * 3 / 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Difficulty Tests ====================
+
+    #[test]
+    fn test_names_round_trip() {
+        for difficulty in Difficulty::ALL {
+            assert_eq!(Difficulty::from_name(difficulty.name()), Some(difficulty));
+        }
+        assert_eq!(Difficulty::from_name("HARD"), Some(Difficulty::Hard));
+        assert_eq!(Difficulty::from_name("nightmare"), None);
+        assert_eq!(serde_json::to_string(&Difficulty::Easy).unwrap(), r#""easy""#);
+    }
+
+    #[test]
+    fn test_mining_scales_with_difficulty() {
+        assert_eq!(Difficulty::Normal.mining_yield(Resource::Ore), Resource::Ore.yield_amount());
+        assert_eq!(Difficulty::Easy.mining_yield(Resource::Ore), 6);
+        assert_eq!(Difficulty::Hard.mining_yield(Resource::Ore), 2);
+        assert_eq!(Difficulty::Hard.mining_yield(Resource::Crystal), 1, "Hard never yields nothing");
+        assert!(Difficulty::Easy.mine_ticks() < MINE_TICKS);
+        assert!(Difficulty::Hard.mine_ticks() > MINE_TICKS);
+    }
+}
@@ -0,0 +1,476 @@
+//! Docking at stations and the docked screen.
+//!
+//! Pressing the dock key ('d') within `DOCKING_RANGE` tiles of a station
+//! stops the ship and switches the game loop to the docked screen, drawn on
+//! its own plane over the world. Its menu lists the station's services:
+//! repairs restore hull and shields for `REPAIR_PRICE` credits a hull point
+//! (free on local maps, which have no economy), refuelling is a placeholder
+//! until ships burn fuel, and Trade opens the station's market. Esc, the
+//! dock key or Undock leaves. The server measures docking the same way, so `/sethome`
+//! works wherever this screen opens.
+//!
+//! The market lists the server's prices for each good (`GET
+//! /station/{id}/market`); 'b' buys one of the highlighted good, 's' sells
+//! one and 'a' sells the lot, each as an order to the server, which answers
+//! with what it cost and the prices since. Credits and hold space are
+//! checked here first. A logged-in pilot's credits are kept by the server,
+//! so its receipts carry the balance to take; guests count their own. The
+//! screen never talks to the server itself: it leaves a `MarketCall` for the
+//! game loop and takes the reply in `answer`.
+
+use exospace_core::{MarketGood, MarketInfo, Poi, PoiKind, RepairOrder, RepairReceipt, TradeOrder, TradeReceipt, TradeSide};
+
+use crate::hull::ShipStatus;
+use crate::mining::{Miner, Resource};
+
+/// How close, in tiles along either axis, a ship must be to dock; the server's `DOCKING_RANGE`
+pub const DOCKING_RANGE: i32 = 3;
+
+/// What a station offers, in menu order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Service {
+    Repair,
+    Refuel,
+    Trade,
+    Undock,
+}
+
+impl Service {
+    pub const ALL: [Service; 4] = [Service::Repair, Service::Refuel, Service::Trade, Service::Undock];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Service::Repair => "Repair",
+            Service::Refuel => "Refuel",
+            Service::Trade => "Trade",
+            Service::Undock => "Undock",
+        }
+    }
+}
+
+/// Keys the docked screen understands
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DockInput {
+    Up,
+    Down,
+    /// Use the highlighted service
+    Select,
+    /// A service's number on the menu, from 1
+    Number(u32),
+    /// Esc or the dock key; leaves the market first
+    Leave,
+    /// Market keys, for the highlighted good
+    Buy,
+    Sell,
+    SellAll,
+}
+
+/// A request for the station's market or repair yard
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MarketCall {
+    Open,
+    Trade(TradeOrder),
+    Repair(RepairOrder),
+}
+
+/// The server's answer to a `MarketCall`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MarketReply {
+    Opened(MarketInfo),
+    Traded(TradeReceipt),
+    Repaired(RepairReceipt),
+}
+
+/// The open market: goods we can carry, and which one is highlighted
+#[derive(Clone, Debug, PartialEq)]
+struct MarketView {
+    goods: Vec<(Resource, MarketGood)>,
+    selected: usize,
+}
+
+impl MarketView {
+    fn new(info: MarketInfo) -> Self {
+        let goods = info.goods.into_iter().filter_map(|good| Some((Resource::from_item(&good.item)?, good))).collect();
+        MarketView { goods, selected: 0 }
+    }
+}
+
+/// Pay for a repair the station has quoted and fix the ship; a guest who can't cover it is turned away
+fn settle_repair(receipt: &RepairReceipt, ship: &mut ShipStatus, miner: &mut Miner) -> String {
+    let inventory = &mut miner.inventory;
+    let credits = match receipt.balance {
+        Some(balance) => balance,
+        None if inventory.credits < receipt.cost => return format!("{} credits short", receipt.cost - inventory.credits),
+        None => inventory.credits - receipt.cost,
+    };
+    inventory.credits = credits;
+    ship.repair();
+    match receipt.cost {
+        0 => "Hull and shields restored".to_string(),
+        cost => format!("Hull and shields restored for {} cr", cost),
+    }
+}
+
+/// The closest station a ship at `ship` can dock at
+pub fn station_in_range<'a>(ship: (i32, i32), pois: impl Iterator<Item = &'a Poi>) -> Option<&'a Poi> {
+    pois.filter(|poi| poi.kind == PoiKind::Station)
+        .map(|poi| ((poi.x - ship.0).abs().max((poi.y - ship.1).abs()), poi))
+        .filter(|(distance, _)| *distance <= DOCKING_RANGE)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, poi)| poi)
+}
+
+/// The menu shown while docked
+#[derive(Clone, Debug, PartialEq)]
+pub struct DockedScreen {
+    pub station: String,
+    selected: usize,
+    /// What the last service did
+    notice: Option<String>,
+    market: Option<MarketView>,
+    /// Waiting for the game loop to send
+    call: Option<MarketCall>,
+}
+
+impl DockedScreen {
+    pub fn new(station: &str) -> Self {
+        DockedScreen { station: station.to_string(), selected: 0, notice: None, market: None, call: None }
+    }
+
+    /// Handle a key; false once the ship undocks
+    pub fn input(&mut self, input: DockInput, ship: &mut ShipStatus, miner: &Miner) -> bool {
+        if self.market.is_some() {
+            self.market_input(input, miner);
+            return true;
+        }
+        let count = Service::ALL.len();
+        match input {
+            DockInput::Up => self.selected = (self.selected + count - 1) % count,
+            DockInput::Down => self.selected = (self.selected + 1) % count,
+            DockInput::Select => return self.use_service(Service::ALL[self.selected], ship),
+            DockInput::Number(n) => {
+                if let Some(&service) = (n as usize).checked_sub(1).and_then(|i| Service::ALL.get(i)) {
+                    self.selected = n as usize - 1;
+                    return self.use_service(service, ship);
+                }
+            }
+            DockInput::Leave => return false,
+            DockInput::Buy | DockInput::Sell | DockInput::SellAll => {}
+        }
+        true
+    }
+
+    fn market_input(&mut self, input: DockInput, miner: &Miner) {
+        let Some(market) = &mut self.market else {
+            return;
+        };
+        let count = market.goods.len().max(1);
+        let (side, everything) = match input {
+            DockInput::Up => {
+                market.selected = (market.selected + count - 1) % count;
+                return;
+            }
+            DockInput::Down => {
+                market.selected = (market.selected + 1) % count;
+                return;
+            }
+            DockInput::Leave => {
+                self.market = None;
+                self.notice = None;
+                return;
+            }
+            DockInput::Buy => (TradeSide::Buy, false),
+            DockInput::Sell => (TradeSide::Sell, false),
+            DockInput::SellAll => (TradeSide::Sell, true),
+            DockInput::Select | DockInput::Number(_) => return,
+        };
+        let Some((resource, good)) = market.goods.get(market.selected) else {
+            return;
+        };
+        let inventory = &miner.inventory;
+        let held = inventory.count(*resource);
+        let refusal = match side {
+            TradeSide::Buy if good.stock == 0 => Some(format!("{} is sold out", resource.name())),
+            TradeSide::Buy if inventory.credits < good.buy_price => Some(format!("{} credits short", good.buy_price - inventory.credits)),
+            TradeSide::Buy if inventory.total() >= miner.capacity => Some(format!("Hold full ({} units)", miner.capacity)),
+            TradeSide::Sell if held == 0 => Some(format!("No {} to sell", resource.name())),
+            _ => None,
+        };
+        if let Some(refusal) = refusal {
+            self.notice = Some(refusal);
+            return;
+        }
+        let quantity = if everything { held } else { 1 };
+        self.call = Some(MarketCall::Trade(TradeOrder { item: good.item.clone(), quantity, side }));
+    }
+
+    /// The market request waiting to be sent, if any
+    pub fn take_call(&mut self) -> Option<MarketCall> {
+        self.call.take()
+    }
+
+    /// Take the server's reply to the last `MarketCall`, settling a trade with the hold or paying for repairs
+    pub fn answer(&mut self, reply: Result<MarketReply, String>, ship: &mut ShipStatus, miner: &mut Miner) {
+        let receipt = match reply {
+            Ok(MarketReply::Opened(info)) => {
+                self.market = Some(MarketView::new(info));
+                self.notice = None;
+                return;
+            }
+            Ok(MarketReply::Repaired(receipt)) => {
+                self.notice = Some(settle_repair(&receipt, ship, miner));
+                return;
+            }
+            Ok(MarketReply::Traded(receipt)) => receipt,
+            Err(e) => {
+                self.notice = Some(e);
+                return;
+            }
+        };
+        let selected = self.market.as_ref().map_or(0, |market| market.selected);
+        self.market = Some(MarketView { selected, ..MarketView::new(receipt.market) });
+        let Some(resource) = Resource::from_item(&receipt.order.item) else {
+            return;
+        };
+        let inventory = &mut miner.inventory;
+        let quantity = receipt.order.quantity;
+        self.notice = Some(match receipt.order.side {
+            TradeSide::Buy => {
+                inventory.add(resource, quantity);
+                inventory.credits = receipt.balance.unwrap_or(inventory.credits.saturating_sub(receipt.total));
+                format!("Bought {} {} for {} cr", quantity, resource.name(), receipt.total)
+            }
+            TradeSide::Sell => {
+                inventory.remove(resource, quantity.min(inventory.count(resource)));
+                inventory.credits = receipt.balance.unwrap_or(inventory.credits.saturating_add(receipt.total));
+                format!("Sold {} {} for {} cr", quantity, resource.name(), receipt.total)
+            }
+        });
+    }
+
+    fn use_service(&mut self, service: Service, ship: &mut ShipStatus) -> bool {
+        let notice = match service {
+            Service::Repair if ship.damaged() => {
+                self.call = Some(MarketCall::Repair(RepairOrder { hull: ship.hull_damage() }));
+                return true;
+            }
+            Service::Repair => "Nothing to repair".to_string(),
+            Service::Refuel => "Tanks full: ships don't burn fuel yet".to_string(),
+            Service::Trade => {
+                self.call = Some(MarketCall::Open);
+                return true;
+            }
+            Service::Undock => return false,
+        };
+        self.notice = Some(notice);
+        true
+    }
+
+    /// Panel text for the docked plane
+    pub fn lines(&self, ship: &ShipStatus, miner: &Miner) -> Vec<String> {
+        if let Some(market) = &self.market {
+            return self.market_lines(market, miner);
+        }
+        let mut lines = vec![format!("Welcome to {}", self.station), String::new()];
+        for (i, service) in Service::ALL.into_iter().enumerate() {
+            let marker = if i == self.selected { '>' } else { ' ' };
+            let detail = match service {
+                Service::Repair => ship.summary(),
+                _ => String::new(),
+            };
+            lines.push(format!("{} {}  {:<8}{}", marker, i + 1, service.label(), detail).trim_end().to_string());
+        }
+        lines.push(String::new());
+        lines.push(self.notice.clone().unwrap_or_default());
+        lines.push("Up/Down and Enter, or 1-4; Esc undocks".to_string());
+        lines
+    }
+
+    fn market_lines(&self, market: &MarketView, miner: &Miner) -> Vec<String> {
+        let inventory = &miner.inventory;
+        let mut lines = vec![
+            format!("{} market, {} cr to spend", self.station, inventory.credits),
+            String::new(),
+            "  Good       Buy  Sell  Stock  Hold".to_string(),
+        ];
+        for (i, (resource, good)) in market.goods.iter().enumerate() {
+            let marker = if i == market.selected { '>' } else { ' ' };
+            lines.push(format!(
+                "{} {:<9}{:>5}{:>6}{:>7}{:>6}",
+                marker,
+                resource.name(),
+                good.buy_price,
+                good.sell_price,
+                good.stock,
+                inventory.count(*resource)
+            ));
+        }
+        if market.goods.is_empty() {
+            lines.push("  Nothing for sale".to_string());
+        }
+        lines.push(String::new());
+        lines.push(self.notice.clone().unwrap_or_default());
+        lines.push(format!("Hold {}/{}; B buys one, S sells one, A sells all; Esc goes back", inventory.total(), miner.capacity));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn station(name: &str, x: i32, y: i32) -> Poi {
+        Poi { kind: PoiKind::Station, name: name.to_string(), x, y }
+    }
+
+    // ==================== Docking Tests ====================
+
+    #[test]
+    fn test_closest_station_in_range() {
+        let pois = [
+            station("Haven Station", 0, 0),
+            station("Frontier Relay", 4, 0),
+            Poi { kind: PoiKind::Beacon, name: "Beacon Tau-1".to_string(), x: 3, y: 0 },
+        ];
+        assert_eq!(station_in_range((3, 1), pois.iter()).unwrap().name, "Frontier Relay", "The closer one wins");
+        assert_eq!(station_in_range((-3, -3), pois.iter()).unwrap().name, "Haven Station");
+        assert!(station_in_range((-4, 0), pois.iter()).is_none(), "Too far out");
+        assert!(station_in_range((8, 0), pois.iter()).is_none(), "Beacons aren't stations");
+    }
+
+    // ==================== Menu Tests ====================
+
+    #[test]
+    fn test_menu_wraps_and_repairs() {
+        let mut screen = DockedScreen::new("Haven Station");
+        let mut ship = ShipStatus::default();
+        let mut miner = Miner::default();
+        ship.damage(80.0);
+        assert!(screen.input(DockInput::Up, &mut ship, &miner));
+        assert!(screen.lines(&ship, &miner)[5].starts_with("> 4  Undock"), "Up from the top wraps to the bottom");
+
+        assert!(screen.input(DockInput::Down, &mut ship, &miner));
+        assert!(screen.lines(&ship, &miner)[2].starts_with("> 1  Repair  H[#####-] S[------]"));
+        assert!(screen.input(DockInput::Select, &mut ship, &miner));
+        assert_eq!(screen.take_call(), Some(MarketCall::Repair(RepairOrder { hull: 30 })), "Repairs are quoted by the station");
+        screen.answer(Ok(MarketReply::Repaired(RepairReceipt { hull: 30, cost: 0, balance: None })), &mut ship, &mut miner);
+        assert_eq!(ship, ShipStatus::default(), "Repairs restore hull and shields");
+        assert!(screen.lines(&ship, &miner).contains(&"Hull and shields restored".to_string()));
+        assert!(screen.input(DockInput::Number(1), &mut ship, &miner));
+        assert!(screen.lines(&ship, &miner).contains(&"Nothing to repair".to_string()));
+    }
+
+    #[test]
+    fn test_trade_opens_market_and_leaving() {
+        let mut screen = DockedScreen::new("Haven Station");
+        let mut ship = ShipStatus::default();
+        let miner = Miner::default();
+        assert!(screen.input(DockInput::Number(3), &mut ship, &miner));
+        assert_eq!(screen.take_call(), Some(MarketCall::Open), "Trade asks the server for the market");
+        assert_eq!(screen.take_call(), None);
+        assert!(screen.input(DockInput::Number(9), &mut ship, &miner), "Numbers off the menu do nothing");
+        assert!(!screen.input(DockInput::Number(4), &mut ship, &miner));
+        assert!(!screen.input(DockInput::Leave, &mut ship, &miner));
+    }
+
+    // ==================== Market Tests ====================
+
+    fn market(ore: (u32, u32, u32)) -> MarketInfo {
+        let good = |item: &str, (buy_price, sell_price, stock)| MarketGood { item: item.to_string(), buy_price, sell_price, stock };
+        MarketInfo {
+            station: "Haven Station".to_string(),
+            goods: vec![good("ore", ore), good("gas", (5, 4, 10)), good("crystal", (44, 36, 100))],
+        }
+    }
+
+    fn open_market(miner: &mut Miner) -> DockedScreen {
+        let mut screen = DockedScreen::new("Haven Station");
+        screen.input(DockInput::Number(3), &mut ShipStatus::default(), miner);
+        screen.take_call();
+        screen.answer(Ok(MarketReply::Opened(market((11, 9, 100)))), &mut ShipStatus::default(), miner);
+        screen
+    }
+
+    #[test]
+    fn test_market_checks_before_ordering() {
+        let mut ship = ShipStatus::default();
+        let mut miner = Miner::default();
+        let mut screen = open_market(&mut miner);
+        let lines = screen.lines(&ship, &miner);
+        assert_eq!(lines[0], "Haven Station market, 0 cr to spend");
+        assert_eq!(lines[3], "> ore         11     9    100     0");
+        assert!(lines[4].starts_with("  crystal"), "Goods we can't carry aren't listed");
+
+        screen.input(DockInput::Buy, &mut ship, &miner);
+        assert_eq!(screen.take_call(), None);
+        assert!(screen.lines(&ship, &miner).contains(&"11 credits short".to_string()));
+        screen.input(DockInput::Sell, &mut ship, &miner);
+        assert!(screen.lines(&ship, &miner).contains(&"No ore to sell".to_string()));
+
+        miner.inventory.credits = 50;
+        miner.capacity = 0;
+        screen.input(DockInput::Buy, &mut ship, &miner);
+        assert!(screen.lines(&ship, &miner).contains(&"Hold full (0 units)".to_string()));
+
+        miner.capacity = 10;
+        miner.inventory.crystal = 4;
+        screen.input(DockInput::Down, &mut ship, &miner);
+        screen.input(DockInput::SellAll, &mut ship, &miner);
+        let order = TradeOrder { item: "crystal".to_string(), quantity: 4, side: TradeSide::Sell };
+        assert_eq!(screen.take_call(), Some(MarketCall::Trade(order)));
+
+        assert!(screen.input(DockInput::Leave, &mut ship, &miner), "Esc leaves the market, not the station");
+        assert!(screen.lines(&ship, &miner)[0].starts_with("Welcome"));
+    }
+
+    #[test]
+    fn test_receipts_settle_with_the_hold() {
+        let ship = ShipStatus::default();
+        let mut miner = Miner::default();
+        miner.inventory.credits = 30;
+        let mut screen = open_market(&mut miner);
+        let order = TradeOrder { item: "ore".to_string(), quantity: 2, side: TradeSide::Buy };
+        let receipt = TradeReceipt { order, total: 23, market: market((12, 10, 98)), balance: None };
+        screen.answer(Ok(MarketReply::Traded(receipt)), &mut ShipStatus::default(), &mut miner);
+        assert_eq!((miner.inventory.ore, miner.inventory.credits), (2, 7));
+        let lines = screen.lines(&ship, &miner);
+        assert_eq!(lines[3], "> ore         12    10     98     2", "Prices come from the receipt");
+        assert!(lines.contains(&"Bought 2 ore for 23 cr".to_string()));
+
+        let order = TradeOrder { item: "ore".to_string(), quantity: 2, side: TradeSide::Sell };
+        let receipt = TradeReceipt { order, total: 19, market: market((11, 9, 100)), balance: None };
+        screen.answer(Ok(MarketReply::Traded(receipt)), &mut ShipStatus::default(), &mut miner);
+        assert_eq!((miner.inventory.ore, miner.inventory.credits), (0, 26));
+
+        let order = TradeOrder { item: "ore".to_string(), quantity: 1, side: TradeSide::Sell };
+        miner.inventory.ore = 1;
+        let receipt = TradeReceipt { order, total: 9, market: market((11, 9, 101)), balance: Some(509) };
+        screen.answer(Ok(MarketReply::Traded(receipt)), &mut ShipStatus::default(), &mut miner);
+        assert_eq!(miner.inventory.credits, 509, "An account's balance comes from the server");
+
+        screen.answer(Err("Only 3 in stock".to_string()), &mut ShipStatus::default(), &mut miner);
+        assert!(screen.lines(&ship, &miner).contains(&"Only 3 in stock".to_string()), "Refusals are shown as they come");
+    }
+
+    #[test]
+    fn test_repairs_are_paid_for() {
+        let mut screen = DockedScreen::new("Haven Station");
+        let mut ship = ShipStatus::default();
+        let mut miner = Miner::default();
+        ship.damage(60.0);
+        let quote = RepairReceipt { hull: 10, cost: 20, balance: None };
+        miner.inventory.credits = 15;
+        screen.answer(Ok(MarketReply::Repaired(quote)), &mut ship, &mut miner);
+        assert!(ship.damaged(), "A guest who can't pay stays damaged");
+        assert!(screen.lines(&ship, &miner).contains(&"5 credits short".to_string()));
+
+        miner.inventory.credits = 25;
+        screen.answer(Ok(MarketReply::Repaired(quote)), &mut ship, &mut miner);
+        assert_eq!((ship.damaged(), miner.inventory.credits), (false, 5), "Guests pay from their own credits");
+        assert!(screen.lines(&ship, &miner).contains(&"Hull and shields restored for 20 cr".to_string()));
+
+        ship.damage(60.0);
+        screen.answer(Ok(MarketReply::Repaired(RepairReceipt { balance: Some(80), ..quote })), &mut ship, &mut miner);
+        assert_eq!((ship.damaged(), miner.inventory.credits), (false, 80), "Accounts take the server's balance");
+    }
+}
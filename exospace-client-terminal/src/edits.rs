@@ -0,0 +1,130 @@
+//! Tiles the player changed in a streamed world.
+//!
+//! Mining and the laser change tiles in the client's copy of the world, but a
+//! streamed chunk is replaced whole whenever it's fetched again. `TileEdits`
+//! keeps those changes as a diff layer that's laid over every chunk as it
+//! arrives. Logged-in pilots also report each edit to the server with
+//! `POST /tile/update`. Once the server accepts one, its chunks include it
+//! and the edit is dropped from the layer. Edits the server couldn't be
+//! reached for stay and are retried every `EDIT_RETRY_DELAY`, so they merge
+//! into the world when it's back. Ones it refuses (someone else got to the
+//! tile first) are dropped so its version of the tile shows. Guests' edits
+//! are never sent and last for the session.
+
+use exospace_core::{ChunkData, Tile, chunk_coords, chunk_local};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// How long to wait before sending edits again after the server couldn't be reached
+pub const EDIT_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// What the server made of an edit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EditOutcome {
+    Accepted,
+    /// The server's tile can't become this one; forget the edit
+    Refused,
+    /// The server couldn't be reached; try again later
+    Failed,
+}
+
+#[derive(Default)]
+pub struct TileEdits {
+    /// Edits not yet in the server's world, in position order so sending is predictable
+    tiles: BTreeMap<(i32, i32), Tile>,
+    /// No sends before this, after a failure
+    retry_at: Option<Instant>,
+}
+
+impl TileEdits {
+    pub fn record(&mut self, x: i32, y: i32, tile: Tile) {
+        self.tiles.insert((x, y), tile);
+    }
+
+    /// Whether a tile has an edit the server hasn't taken yet
+    pub fn pending(&self, x: i32, y: i32) -> bool {
+        self.tiles.contains_key(&(x, y))
+    }
+
+    /// Lay the edits that fall inside a freshly fetched chunk over it
+    pub fn apply(&self, chunk: &mut ChunkData) {
+        for (&(x, y), &tile) in &self.tiles {
+            if chunk_coords(x, y) != (chunk.cx, chunk.cy) {
+                continue;
+            }
+            let (local_x, local_y) = chunk_local(x, y);
+            if let Some(slot) = chunk.get_mut(local_x, local_y) {
+                *slot = tile;
+            }
+        }
+    }
+
+    /// The next edit to send, unless the server failed too recently
+    pub fn next_unsent(&self, now: Instant) -> Option<(i32, i32, Tile)> {
+        if self.retry_at.is_some_and(|at| now < at) {
+            return None;
+        }
+        self.tiles.iter().next().map(|(&(x, y), &tile)| (x, y, tile))
+    }
+
+    pub fn sent(&mut self, x: i32, y: i32, outcome: EditOutcome, now: Instant) {
+        match outcome {
+            EditOutcome::Accepted | EditOutcome::Refused => {
+                self.tiles.remove(&(x, y));
+                self.retry_at = None;
+            }
+            EditOutcome::Failed => self.retry_at = Some(now + EDIT_RETRY_DELAY),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::CHUNK_SIZE;
+
+    fn open_chunk(cx: i32, cy: i32) -> ChunkData {
+        ChunkData { cx, cy, tiles: vec![Tile::Asteroid; (CHUNK_SIZE * CHUNK_SIZE) as usize], pois: Vec::new(), regions: Vec::new() }
+    }
+
+    // ==================== Diff Layer Tests ====================
+
+    #[test]
+    fn test_apply_only_touches_its_chunk() {
+        let mut edits = TileEdits::default();
+        edits.record(3, 4, Tile::Floor);
+        edits.record(CHUNK_SIZE + 1, 4, Tile::Floor);
+        edits.record(-1, -1, Tile::Floor);
+
+        let mut chunk = open_chunk(0, 0);
+        edits.apply(&mut chunk);
+        assert_eq!(chunk.get(3, 4), Some(Tile::Floor));
+        assert_eq!(chunk.tiles.iter().filter(|&&t| t == Tile::Floor).count(), 1, "Other chunks' edits stay out");
+
+        let mut west = open_chunk(-1, -1);
+        edits.apply(&mut west);
+        assert_eq!(west.get(CHUNK_SIZE - 1, CHUNK_SIZE - 1), Some(Tile::Floor), "Negative positions map to the far corner");
+        assert!(edits.pending(-1, -1));
+        assert!(!edits.pending(1, -1));
+    }
+
+    // ==================== Sync Tests ====================
+
+    #[test]
+    fn test_sending_retries_after_failure() {
+        let now = Instant::now();
+        let mut edits = TileEdits::default();
+        edits.record(5, 5, Tile::Floor);
+        edits.record(1, 1, Tile::Nebula);
+        assert_eq!(edits.next_unsent(now), Some((1, 1, Tile::Nebula)));
+
+        edits.sent(1, 1, EditOutcome::Failed, now);
+        assert_eq!(edits.next_unsent(now + Duration::from_secs(1)), None, "Waits after a failure");
+        let later = now + EDIT_RETRY_DELAY;
+        assert_eq!(edits.next_unsent(later), Some((1, 1, Tile::Nebula)), "The failed edit is kept");
+
+        edits.sent(1, 1, EditOutcome::Accepted, later);
+        edits.sent(5, 5, EditOutcome::Refused, later);
+        assert_eq!(edits.next_unsent(later), None, "Accepted edits are the server's now; refused ones are forgotten");
+    }
+}
@@ -0,0 +1,149 @@
+//! Meteor showers and ion storms: the server's regional events.
+//!
+//! The server sends `EventStarted` for every event it begins, and for those
+//! already under way when we connect, then `EventEnded` once one is over;
+//! its announcements arrive as ambient chatter. `ActiveEvents` lives on the
+//! `Map`, so a jump or a new map leaves them behind, and it lets an event go
+//! by itself when its time is up in case the end never arrives. Events only
+//! happen in the server's home system. A shower's falling rock
+//! (`WorldEvent::blocks()`) is impassable, so ships stop short of it and
+//! autopilot routes round it, and a storm wears a ship down like the ion
+//! storms in nebulae. Both are drawn over the view: tumbling rock, falling
+//! meteors and crackling storm with effects on, still rock and static
+//! otherwise.
+
+use std::time::{Duration, Instant};
+
+use exospace_core::{EventKind, Tile, WorldEvent, hash_position};
+
+/// Falling rock under a meteor shower
+const DEBRIS_STYLE: (char, u32) = ('*', 0xD08040);
+
+/// Debris glyphs, cycled as the rock tumbles
+const DEBRIS_CHARS: [char; 4] = ['*', '+', 'x', '+'];
+
+/// A meteor falling through open space
+const METEOR_STYLE: (char, u32) = ('\'', 0xFFB060);
+
+/// Storm crackle, bright and faint
+const STORM_STYLES: [(char, u32); 2] = [('~', 0x70B0FF), ('·', 0x4060C0)];
+
+/// The events under way, each with when it ends
+#[derive(Default)]
+pub struct ActiveEvents {
+    events: Vec<(WorldEvent, Instant)>,
+}
+
+impl ActiveEvents {
+    /// Track an event, ending `secs_left` from `now`; a repeat of one already tracked replaces it
+    pub fn start(&mut self, event: WorldEvent, now: Instant) {
+        self.end(event.id);
+        let ends = now + Duration::from_secs(event.secs_left as u64);
+        self.events.push((event, ends));
+    }
+
+    pub fn end(&mut self, id: u64) {
+        self.events.retain(|(event, _)| event.id != id);
+    }
+
+    /// Forget the events whose time is up
+    pub fn expire(&mut self, now: Instant) {
+        self.events.retain(|&(_, ends)| ends > now);
+    }
+
+    /// The latest event covering (x, y)
+    pub fn at(&self, x: i32, y: i32) -> Option<&WorldEvent> {
+        self.events.iter().rev().map(|(event, _)| event).find(|event| event.covers(x, y))
+    }
+
+    /// Whether falling rock blocks (x, y)
+    pub fn blocks(&self, x: i32, y: i32) -> bool {
+        self.events.iter().any(|(event, _)| event.blocks(x, y))
+    }
+
+    /// Whether a ship at (x, y) is caught in a storm
+    pub fn damages(&self, x: i32, y: i32) -> bool {
+        self.events.iter().any(|(event, _)| event.damages(x, y))
+    }
+}
+
+/// How the tile at (x, y) looks inside an event, `frame` into the animation (None with effects off);
+/// None leaves the tile to the map. Only open space changes, apart from the shower's rock
+pub fn style(event: &WorldEvent, x: i32, y: i32, tile: Option<Tile>, frame: Option<u64>) -> Option<(char, u32)> {
+    if event.blocks(x, y) {
+        let spin = frame.map_or(0, |frame| (frame / 10 + hash_position(x, y, 91) as u64) % 4);
+        return Some((DEBRIS_CHARS[spin as usize], DEBRIS_STYLE.1));
+    }
+    if !tile.is_some_and(|tile| tile.is_passable()) {
+        return None;
+    }
+    match (event.kind, frame) {
+        // Meteors fall down and to the left, a cell every few frames
+        (EventKind::MeteorShower, Some(frame)) => {
+            let fall = (frame / 3) as i32;
+            hash_position(x + fall, y - fall, event.id as u32).is_multiple_of(40).then_some(METEOR_STYLE)
+        }
+        (EventKind::IonStorm, frame) => {
+            let phase = frame.map_or(0, |frame| (frame / 4) as u32);
+            match hash_position(x, y, 0x5707_u32.wrapping_add(phase)) % 10 {
+                0 => Some(STORM_STYLES[0]),
+                1 if frame.is_some() => Some(STORM_STYLES[1]),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: u64, kind: EventKind, secs_left: u32) -> WorldEvent {
+        WorldEvent { id, kind, x: 0, y: 0, radius: 10, secs_left }
+    }
+
+    // ==================== Tracking Tests ====================
+
+    #[test]
+    fn test_events_end_and_expire() {
+        let now = Instant::now();
+        let mut events = ActiveEvents::default();
+        events.start(event(1, EventKind::IonStorm, 30), now);
+        events.start(event(2, EventKind::MeteorShower, 90), now);
+        events.start(event(1, EventKind::IonStorm, 60), now);
+        assert_eq!(events.at(3, 3).map(|event| event.id), Some(1), "Replaced, so now the latest");
+        assert!(events.damages(3, 3) && !events.damages(30, 0));
+
+        events.expire(now + Duration::from_secs(60));
+        assert!(!events.damages(3, 3), "The storm's time is up");
+        assert_eq!(events.at(3, 3).map(|event| event.id), Some(2));
+        let (x, y) = (-7..=7).flat_map(|x| (-7..=7).map(move |y| (x, y))).find(|&(x, y)| events.blocks(x, y)).expect("Some rock in the shower");
+        assert!(!events.blocks(x + 40, y), "Only inside it");
+
+        events.end(2);
+        assert!(events.at(3, 3).is_none());
+        assert!(!events.blocks(x, y));
+    }
+
+    // ==================== Style Tests ====================
+
+    #[test]
+    fn test_styles() {
+        let shower = event(2, EventKind::MeteorShower, 90);
+        let rock = (-10..=10).find(|&x| shower.blocks(x, 0)).unwrap();
+        assert_eq!(style(&shower, rock, 0, Some(Tile::Floor), None), Some(DEBRIS_STYLE), "Rock holds still with effects off");
+        assert!(style(&shower, rock, 0, Some(Tile::Wall), Some(25)).is_some_and(|(ch, _)| DEBRIS_CHARS.contains(&ch)), "Rock shows over anything");
+        let open = (-10..=10).find(|&x| !shower.blocks(x, 0)).unwrap();
+        assert_eq!(style(&shower, open, 0, Some(Tile::Floor), None), None, "No meteors without effects");
+
+        let storm = event(1, EventKind::IonStorm, 90);
+        let cells: Vec<_> = (-7..=7).flat_map(|x| (-7..=7).map(move |y| (x, y))).collect();
+        let still = cells.iter().filter(|&&(x, y)| style(&storm, x, y, Some(Tile::Floor), None).is_some()).count();
+        assert!(still > 0 && still < cells.len() / 4, "Sparse static with effects off, not {}", still);
+        assert!(cells.iter().all(|&(x, y)| style(&storm, x, y, Some(Tile::Asteroid), Some(8)).is_none()), "Rocks keep their looks");
+        let crackle = |frame| cells.iter().map(|&(x, y)| style(&storm, x, y, Some(Tile::Nebula), Some(frame))).collect::<Vec<_>>();
+        assert_ne!(crackle(0), crackle(4), "The storm crackles");
+        assert_eq!(style(&event(3, EventKind::Unknown, 9), 0, 0, Some(Tile::Floor), Some(0)), None);
+    }
+}
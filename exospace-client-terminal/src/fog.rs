@@ -0,0 +1,297 @@
+//! Fog of war: which tiles the player has already seen.
+//!
+//! Exploration is kept as one bitset per 64x64 chunk, so the bounded local
+//! map and the unbounded streamed world are tracked the same way. The set is
+//! saved per world so exploration survives restarts.
+//!
+//! Inside a nebula the ship's sensors are jammed: it sees less of the map
+//! and other ships only within `NEBULA_SENSOR_RANGE`.
+
+use exospace_core::{CHUNK_SIZE, NEBULA_SENSOR_RANGE, Tile, chunk_coords, chunk_local, within_sensor_range};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How far the ship can see, in rows; columns reach twice as far since cells are tall
+pub const VISIBILITY_RADIUS: i32 = 10;
+
+/// 64-bit words in one chunk's bitset
+const WORDS_PER_CHUNK: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize / 64;
+
+/// What the ship's sensors reach from where it is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sensors {
+    /// Inside a nebula
+    pub jammed: bool,
+    origin: (i32, i32),
+}
+
+impl Sensors {
+    /// Sensors of a ship at (x, y) on `tile`
+    pub fn at(tile: Option<Tile>, x: i32, y: i32) -> Self {
+        Sensors { jammed: tile == Some(Tile::Nebula), origin: (x, y) }
+    }
+
+    /// How far the ship can see, in rows
+    pub fn radius(self) -> i32 {
+        if self.jammed { NEBULA_SENSOR_RANGE } else { VISIBILITY_RADIUS }
+    }
+
+    /// Whether another ship at (x, y) shows up
+    pub fn sees(self, x: i32, y: i32) -> bool {
+        !self.jammed || within_sensor_range(self.origin, (x, y))
+    }
+}
+
+const FOG_MAGIC: &[u8; 4] = b"EXOF";
+const FOG_VERSION: u8 = 1;
+
+/// Tiles the player has explored, one bit per tile
+#[derive(Clone, Default)]
+pub struct ExploredTiles {
+    chunks: HashMap<(i32, i32), [u64; WORDS_PER_CHUNK]>,
+    /// Something new was seen since the last save
+    pub dirty: bool,
+}
+
+impl ExploredTiles {
+    fn bit(x: i32, y: i32) -> ((i32, i32), usize, u64) {
+        let (local_x, local_y) = chunk_local(x, y);
+        let index = (local_y * CHUNK_SIZE + local_x) as usize;
+        (chunk_coords(x, y), index / 64, 1 << (index % 64))
+    }
+
+    /// Mark a tile as seen
+    pub fn mark(&mut self, x: i32, y: i32) {
+        let (key, word, mask) = Self::bit(x, y);
+        let words = self.chunks.entry(key).or_insert([0; WORDS_PER_CHUNK]);
+        if words[word] & mask == 0 {
+            words[word] |= mask;
+            self.dirty = true;
+        }
+    }
+
+    pub fn is_explored(&self, x: i32, y: i32) -> bool {
+        let (key, word, mask) = Self::bit(x, y);
+        self.chunks.get(&key).is_some_and(|words| words[word] & mask != 0)
+    }
+
+    /// Mark everything within `radius` of (x, y), stretched horizontally to look round on screen
+    pub fn reveal_around(&mut self, x: i32, y: i32, radius: i32) {
+        for dy in -radius..=radius {
+            for dx in -2 * radius..=2 * radius {
+                if dx * dx + 4 * dy * dy <= 4 * radius * radius {
+                    self.mark(x + dx, y + dy);
+                }
+            }
+        }
+    }
+
+    /// Number of explored tiles
+    pub fn count(&self) -> usize {
+        self.chunks.values().flatten().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Bounding box of explored chunks as (min_x, min_y, max_x, max_y), max exclusive
+    pub fn bounds(&self) -> Option<(i32, i32, i32, i32)> {
+        let mut keys = self.chunks.iter().filter(|(_, words)| words.iter().any(|&w| w != 0)).map(|(&key, _)| key);
+        let first = keys.next()?;
+        let (min_cx, min_cy, max_cx, max_cy) = keys.fold((first.0, first.1, first.0, first.1), |(a, b, c, d), (cx, cy)| {
+            (a.min(cx), b.min(cy), c.max(cx), d.max(cy))
+        });
+        Some((min_cx * CHUNK_SIZE, min_cy * CHUNK_SIZE, (max_cx + 1) * CHUNK_SIZE, (max_cy + 1) * CHUNK_SIZE))
+    }
+
+    /// Encode as magic, version, chunk count, then per chunk cx, cy and its bitset (all little-endian)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(9 + self.chunks.len() * (8 + WORDS_PER_CHUNK * 8));
+        out.extend_from_slice(FOG_MAGIC);
+        out.push(FOG_VERSION);
+        out.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+        for (&(cx, cy), words) in &self.chunks {
+            out.extend_from_slice(&cx.to_le_bytes());
+            out.extend_from_slice(&cy.to_le_bytes());
+            for word in words {
+                out.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let rest = bytes.strip_prefix(FOG_MAGIC).ok_or("Not an exploration file")?;
+        let (&version, rest) = rest.split_first().ok_or("Exploration file is truncated")?;
+        if version != FOG_VERSION {
+            return Err(format!("Unsupported exploration file version {}", version));
+        }
+        let (count, mut rest) = rest.split_at_checked(4).ok_or("Exploration file is truncated")?;
+        let count = u32::from_le_bytes(count.try_into().unwrap());
+
+        let mut explored = ExploredTiles::default();
+        for _ in 0..count {
+            let (entry, tail) = rest.split_at_checked(8 + WORDS_PER_CHUNK * 8).ok_or("Exploration file is truncated")?;
+            let cx = i32::from_le_bytes(entry[0..4].try_into().unwrap());
+            let cy = i32::from_le_bytes(entry[4..8].try_into().unwrap());
+            let mut words = [0; WORDS_PER_CHUNK];
+            for (word, bytes) in words.iter_mut().zip(entry[8..].chunks_exact(8)) {
+                *word = u64::from_le_bytes(bytes.try_into().unwrap());
+            }
+            explored.chunks.insert((cx, cy), words);
+            rest = tail;
+        }
+        Ok(explored)
+    }
+
+    /// Where exploration for a world is saved
+    pub fn path(world: &str) -> Option<PathBuf> {
+        dirs::config_dir().map(|mut p| {
+            p.push("exospace");
+            p.push("explored");
+            p.push(format!("{}.bin", world));
+            p
+        })
+    }
+
+    /// Load saved exploration for a world, starting fresh if there is none
+    pub fn load(world: &str) -> Self {
+        Self::path(world).map(|path| Self::load_from(&path)).unwrap_or_default()
+    }
+
+    pub fn load_from(path: &Path) -> Self {
+        match fs::read(path) {
+            Ok(bytes) => Self::from_bytes(&bytes).unwrap_or_else(|e| {
+                tracing::warn!("Failed to load exploration: {}", e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Save exploration for a world if anything changed
+    pub fn save(&mut self, world: &str) -> Result<(), String> {
+        let path = Self::path(world).ok_or_else(|| "Could not determine config directory".to_string())?;
+        self.save_to(&path)
+    }
+
+    pub fn save_to(&mut self, path: &Path) -> Result<(), String> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create exploration directory: {}", e))?;
+        }
+        fs::write(path, self.to_bytes()).map_err(|e| format!("Failed to write exploration file: {}", e))?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark_and_query() {
+        let mut explored = ExploredTiles::default();
+        assert!(!explored.is_explored(5, 5));
+        explored.mark(5, 5);
+        explored.mark(-1, -70);
+        assert!(explored.is_explored(5, 5));
+        assert!(explored.is_explored(-1, -70), "Negative coords work");
+        assert!(!explored.is_explored(6, 5));
+        assert!(!explored.is_explored(5 + CHUNK_SIZE, 5), "Same local position in another chunk is separate");
+        assert_eq!(explored.count(), 2);
+    }
+
+    #[test]
+    fn test_mark_sets_dirty_only_when_new() {
+        let mut explored = ExploredTiles::default();
+        explored.mark(1, 1);
+        assert!(explored.dirty);
+        explored.dirty = false;
+        explored.mark(1, 1);
+        assert!(!explored.dirty, "Re-marking a seen tile isn't a change");
+    }
+
+    #[test]
+    fn test_reveal_around_shape() {
+        let mut explored = ExploredTiles::default();
+        explored.reveal_around(0, 0, 4);
+        assert!(explored.is_explored(0, 0));
+        assert!(explored.is_explored(0, 4) && explored.is_explored(0, -4));
+        assert!(explored.is_explored(8, 0) && explored.is_explored(-8, 0), "Columns reach twice as far");
+        assert!(!explored.is_explored(0, 5));
+        assert!(!explored.is_explored(9, 0));
+        assert!(!explored.is_explored(8, 4), "Corners are outside the circle");
+    }
+
+    #[test]
+    fn test_bounds_cover_explored_chunks() {
+        let mut explored = ExploredTiles::default();
+        assert_eq!(explored.bounds(), None);
+        explored.mark(5, 5);
+        assert_eq!(explored.bounds(), Some((0, 0, CHUNK_SIZE, CHUNK_SIZE)));
+        explored.mark(-1, 2 * CHUNK_SIZE);
+        assert_eq!(explored.bounds(), Some((-CHUNK_SIZE, 0, CHUNK_SIZE, 3 * CHUNK_SIZE)));
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut explored = ExploredTiles::default();
+        explored.reveal_around(60, -3, VISIBILITY_RADIUS);
+        let parsed = ExploredTiles::from_bytes(&explored.to_bytes()).unwrap();
+        assert_eq!(parsed.count(), explored.count());
+        assert!(parsed.is_explored(60, -3));
+        assert!(!parsed.dirty, "Freshly loaded exploration has nothing to save");
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_bad_data() {
+        let mut explored = ExploredTiles::default();
+        explored.mark(0, 0);
+        let bytes = explored.to_bytes();
+        assert!(ExploredTiles::from_bytes(b"NOPE").is_err());
+        assert!(ExploredTiles::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut wrong_version = bytes;
+        wrong_version[4] = 9;
+        assert!(ExploredTiles::from_bytes(&wrong_version).is_err());
+    }
+
+    #[test]
+    fn test_save_and_load_file() {
+        let path = std::env::temp_dir().join(format!("exospace-fog-test-{}", std::process::id())).join("world.bin");
+        let mut explored = ExploredTiles::default();
+        explored.mark(3, 4);
+        explored.save_to(&path).unwrap();
+        assert!(!explored.dirty);
+
+        let loaded = ExploredTiles::load_from(&path);
+        assert!(loaded.is_explored(3, 4));
+        assert_eq!(ExploredTiles::load_from(&path.with_file_name("missing.bin")).count(), 0);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_path_is_per_world() {
+        if let (Some(a), Some(b)) = (ExploredTiles::path("stream-1"), ExploredTiles::path("stream-2")) {
+            assert_ne!(a, b);
+            assert!(a.to_string_lossy().contains("exospace"));
+        }
+    }
+
+    #[test]
+    fn test_nebulae_jam_sensors() {
+        let clear = Sensors::at(Some(Tile::Floor), 0, 0);
+        assert!(!clear.jammed);
+        assert_eq!(clear.radius(), VISIBILITY_RADIUS);
+        assert!(clear.sees(100, 100), "Open space hides nothing");
+
+        let jammed = Sensors::at(Some(Tile::Nebula), 0, 0);
+        assert!(jammed.jammed);
+        assert!(jammed.radius() < VISIBILITY_RADIUS, "A nebula shrinks what we see of the map");
+        assert!(jammed.sees(NEBULA_SENSOR_RANGE, 0));
+        assert!(!jammed.sees(NEBULA_SENSOR_RANGE + 1, 0), "Ships further out vanish");
+        assert!(!Sensors::at(None, 0, 0).jammed, "Unloaded tiles don't jam anything");
+    }
+}
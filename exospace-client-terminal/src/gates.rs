@@ -0,0 +1,186 @@
+//! Jump gates: hopping between the server's star systems.
+//!
+//! The server's universe is a few star systems (`GET /systems`), each
+//! streamed like any world from its own seed and linked by gates, which come
+//! in their chunks as points of interest. Parked on a gate, the jump key
+//! ('g') or `/jump` opens the warp: stars streak past over the whole view
+//! (a plain black cover with effects off), keys wait, and once the streaks
+//! hide it the map is swapped for the far system's seed, arriving on the
+//! gate back. The warp ends when that spot has streamed in. Local maps have
+//! no gates. The warp is a screen of its own (see `screens.rs`).
+
+use std::time::{Duration, Instant};
+
+use exospace_core::{SPAWN_POSITION, SystemInfo, hash_position};
+
+/// The shortest warp
+pub const WARP_DURATION: Duration = Duration::from_millis(1500);
+
+/// How long a warp waits for the far side's chunks before giving up on them
+pub const WARP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// When the map is swapped, once the streaks are up to speed
+const SWAP_AT: Duration = Duration::from_millis(300);
+
+/// Columns a second the stars rush past, per second of warp
+const WARP_ACCELERATION: f32 = 60.0;
+
+/// Columns a second of speed per column of streak
+const STREAK_STRETCH: f32 = 4.0;
+
+/// One star per this many cells
+const STREAK_DENSITY: u32 = 30;
+
+/// Streak colors, far to near; nearer stars are faster and longer
+const STREAK_COLORS: [u32; 3] = [0x503080, 0x9070E0, 0xE0D0FF];
+
+/// Width of the warp's progress bar
+const BAR_WIDTH: usize = 20;
+
+/// Where a jump from (x, y) on the world with `seed` goes: the far system and the spot to arrive at
+pub fn jump_target(systems: &[SystemInfo], seed: u64, (x, y): (i32, i32)) -> Result<(&SystemInfo, (i32, i32)), String> {
+    let here = systems
+        .iter()
+        .find(|system| system.seed == seed)
+        .ok_or_else(|| "No jump gates here: they link the server's star systems".to_string())?;
+    let gate = here.gate_at(x, y).ok_or_else(|| "Fly onto a jump gate first; /poi lists the ones you've found".to_string())?;
+    let there = systems.iter().find(|system| system.id == gate.to).ok_or_else(|| format!("The gate to {} leads nowhere", gate.to))?;
+    let arrival = there.gate_to(&here.id).map_or(SPAWN_POSITION, |gate| (gate.x, gate.y));
+    Ok((there, arrival))
+}
+
+/// A jump under way, from the gate to the far side's chunks streaming in
+pub struct WarpScreen {
+    started: Instant,
+    now: Instant,
+    /// The system's name
+    to: String,
+    seed: u64,
+    arrival: (i32, i32),
+    /// Whether the map swap has been handed out
+    swapped: bool,
+}
+
+impl WarpScreen {
+    pub fn new(now: Instant, system: &SystemInfo, arrival: (i32, i32)) -> Self {
+        WarpScreen { started: now, now, to: system.name.clone(), seed: system.seed, arrival, swapped: false }
+    }
+
+    /// Move on to `now`; the far system's seed and arrival spot, once, when it's time to swap maps
+    pub fn tick(&mut self, now: Instant) -> Option<(u64, (i32, i32))> {
+        self.now = now;
+        if self.swapped || self.elapsed() < SWAP_AT {
+            return None;
+        }
+        self.swapped = true;
+        Some((self.seed, self.arrival))
+    }
+
+    /// Whether the warp is over: the map swapped, its time up and `arrived` (the far side's
+    /// chunk under the ship is in), or waiting on it too long
+    pub fn is_over(&self, arrived: bool) -> bool {
+        self.swapped && self.elapsed() >= WARP_DURATION && (arrived || self.elapsed() >= WARP_TIMEOUT)
+    }
+
+    /// The far system's seed and where the ship comes out
+    pub fn destination(&self) -> (u64, (i32, i32)) {
+        (self.seed, self.arrival)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.now.duration_since(self.started)
+    }
+
+    /// Panel text: where to, and how far along; the bar waits full for the chunks
+    pub fn lines(&self) -> Vec<String> {
+        let done = (self.elapsed().as_secs_f32() / WARP_DURATION.as_secs_f32()).min(1.0);
+        let filled = (done * BAR_WIDTH as f32) as usize;
+        vec![format!("Jumping to {}", self.to), format!("[{:<width$}]", "=".repeat(filled), width = BAR_WIDTH)]
+    }
+}
+
+/// The streaks to draw over a `cols` x `rows` area, `elapsed` into a warp, as (x, y, length, color):
+/// stars rushing left, stretching out as the ship speeds up; nearer ones come later, drawn on top
+pub fn streaks(cols: u32, rows: u32, elapsed: Duration) -> Vec<(u32, u32, u32, u32)> {
+    if cols == 0 || rows == 0 {
+        return Vec::new();
+    }
+    let t = elapsed.as_secs_f32();
+    let (speed, travelled) = (WARP_ACCELERATION * t, WARP_ACCELERATION * t * t / 2.0);
+    let mut streaks: Vec<_> = (0..(cols * rows / STREAK_DENSITY).max(1) as i32)
+        .map(|i| {
+            let depth = hash_position(i, 0, 0x5742) % 3;
+            let x = hash_position(i, 1, 0x5742) % cols;
+            let y = hash_position(i, 2, 0x5742) % rows;
+            let moved = (travelled * (depth + 1) as f32) as u32;
+            let length = ((speed * (depth + 1) as f32 / STREAK_STRETCH) as u32).clamp(1, cols);
+            ((x + cols - moved % cols) % cols, y, length, depth)
+        })
+        .collect();
+    streaks.sort_by_key(|&(.., depth)| depth);
+    streaks.into_iter().map(|(x, y, length, depth)| (x, y, length, STREAK_COLORS[depth as usize])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::GateInfo;
+
+    fn system(id: &str, seed: u64, gates: &[(i32, i32, &str)]) -> SystemInfo {
+        let gates = gates.iter().map(|&(x, y, to)| GateInfo { x, y, to: to.to_string() }).collect();
+        SystemInfo { id: id.to_string(), name: id.to_uppercase(), seed, gates }
+    }
+
+    // ==================== Jump Tests ====================
+
+    #[test]
+    fn test_jump_target() {
+        let systems = [
+            system("haven", 1, &[(10, 5, "vega"), (-4, 9, "lost")]),
+            system("vega", 2, &[(-30, 2, "haven"), (7, 7, "tarsus")]),
+            system("tarsus", 3, &[]),
+        ];
+        let (there, arrival) = jump_target(&systems, 1, (10, 5)).unwrap();
+        assert_eq!((there.id.as_str(), arrival), ("vega", (-30, 2)), "Arriving on the gate back");
+        let (there, arrival) = jump_target(&systems, 2, (7, 7)).unwrap();
+        assert_eq!((there.id.as_str(), arrival), ("tarsus", SPAWN_POSITION), "No gate back: the spawn point");
+        assert_eq!(jump_target(&systems, 1, (10, 6)).unwrap_err(), "Fly onto a jump gate first; /poi lists the ones you've found");
+        assert_eq!(jump_target(&systems, 1, (-4, 9)).unwrap_err(), "The gate to lost leads nowhere");
+        assert!(jump_target(&systems, 99, (10, 5)).is_err(), "A world that isn't a system");
+    }
+
+    // ==================== Warp Tests ====================
+
+    #[test]
+    fn test_warp_swaps_once_then_waits_for_the_far_side() {
+        let start = Instant::now();
+        let vega = system("vega", 2, &[]);
+        let mut warp = WarpScreen::new(start, &vega, (-30, 2));
+        assert_eq!(warp.lines(), vec!["Jumping to VEGA".to_string(), format!("[{}]", " ".repeat(BAR_WIDTH))]);
+        assert_eq!(warp.tick(start), None, "Not until the streaks cover the view");
+        assert_eq!(warp.tick(start + SWAP_AT), Some((2, (-30, 2))));
+        assert_eq!(warp.tick(start + WARP_DURATION / 2), None, "Swapped once");
+        assert_eq!(warp.lines()[1], format!("[{:<20}]", "=".repeat(10)));
+        assert!(!warp.is_over(true), "The shortest warp still runs its course");
+        warp.tick(start + WARP_DURATION);
+        assert_eq!(warp.lines()[1], format!("[{}]", "=".repeat(BAR_WIDTH)));
+        assert!(!warp.is_over(false), "Waiting for the chunk under the ship");
+        assert!(warp.is_over(true));
+        warp.tick(start + WARP_TIMEOUT);
+        assert!(warp.is_over(false), "Giving up on a silent server");
+    }
+
+    #[test]
+    fn test_streaks_stretch_and_rush_left() {
+        let still = streaks(80, 20, Duration::ZERO);
+        assert_eq!(still.len(), 1600 / 30);
+        assert!(still.iter().all(|&(x, y, length, _)| x < 80 && y < 20 && length == 1), "Stars are points before the ship moves");
+        assert_eq!((still[0].3, still[still.len() - 1].3), (STREAK_COLORS[0], STREAK_COLORS[2]), "Far ones first");
+        let later = streaks(80, 20, Duration::from_secs(1));
+        let (near_then, near_now) = (still.last().unwrap(), later.last().unwrap());
+        assert_eq!(near_now.0, (near_then.0 + 70) % 80, "Near stars travel 90 columns, three times the far ones");
+        assert_eq!(near_now.2, 45, "Near streaks stretch with speed");
+        assert!(streaks(80, 20, Duration::from_secs(9)).iter().all(|&(.., length, _)| length <= 80), "No longer than the view");
+        assert!(streaks(80, 0, Duration::ZERO).is_empty());
+    }
+}
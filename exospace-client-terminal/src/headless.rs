@@ -0,0 +1,298 @@
+//! Headless mode: the game loop without a terminal, for bots and CI.
+//!
+//! With `--headless` notcurses is never started. The loop runs as usual
+//! (networking, movement, missions, the command queue) but takes its input
+//! from a `Script` read line by line from `--script` or stdin, skips drawing,
+//! and writes every chat message to stdout as `transcript_line()`s. Lines
+//! reach the script as they're written, so a bot can hold stdin open and
+//! answer what it reads; the game quits when the script ends. Script lines:
+//!
+//! ```text
+//! # a comment
+//! /nav 10 -4        a slash command, as if typed in chat
+//! say hello         a chat line
+//! key x             press a key (names as in /bind)
+//! hold right 800    hold a key down for 800 ms
+//! wait 500          let 500 ms of the game go by
+//! expect Autopilot  wait for a chat message containing the text
+//! ```
+//!
+//! A bad line, or an `expect` not met within `EXPECT_TIMEOUT`, ends the run
+//! with an error, so a CI job fails.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant, SystemTime};
+
+use libnotcurses_sys::{NcInput, NcInputType, NcReceived};
+
+use crate::chatlog;
+use crate::keys::KeySpec;
+
+/// Terminal size the layout assumes headless, as (rows, columns)
+pub const HEADLESS_SIZE: (u32, u32) = (24, 80);
+
+/// Longest an `expect` waits for its message
+pub const EXPECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the script does next, in place of the player
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Key(NcReceived),
+    /// A line typed into chat and sent
+    Line(String),
+}
+
+/// One line of a script
+#[derive(Debug, PartialEq)]
+enum Step {
+    Line(String),
+    Key(KeySpec),
+    Hold(KeySpec, Duration),
+    Wait(Duration),
+    Expect(String),
+}
+
+/// What the script is waiting on before its next line
+enum Blocked {
+    Until(Instant),
+    Holding(KeySpec, Instant),
+    /// The text, where the wait gives up and the script line asking for it
+    Expecting(String, Instant, usize),
+}
+
+fn parse_key(text: &str) -> Result<KeySpec, String> {
+    KeySpec::parse(text).ok_or_else(|| format!("unknown key \"{}\"", text))
+}
+
+fn parse_millis(text: &str) -> Result<Duration, String> {
+    text.parse().map(Duration::from_millis).map_err(|_| format!("expected milliseconds, not \"{}\"", text))
+}
+
+/// Parse a script line; None for blanks and comments
+fn parse(line: &str) -> Result<Option<Step>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    if line.starts_with('/') {
+        return Ok(Some(Step::Line(line.to_string())));
+    }
+    let (word, rest) = line.split_once(' ').map_or((line, ""), |(word, rest)| (word, rest.trim()));
+    let step = match (word, rest.split_whitespace().collect::<Vec<_>>().as_slice()) {
+        ("say", _) if !rest.is_empty() => Step::Line(rest.to_string()),
+        ("key", [key]) => Step::Key(parse_key(key)?),
+        ("hold", [key, millis]) => Step::Hold(parse_key(key)?, parse_millis(millis)?),
+        ("wait", [millis]) => Step::Wait(parse_millis(millis)?),
+        ("expect", _) if !rest.is_empty() => Step::Expect(rest.to_string()),
+        ("say" | "key" | "hold" | "wait" | "expect", _) => return Err(format!("usage: {}", usage(word))),
+        _ => return Err(format!("unknown step \"{}\" (commands start with /)", word)),
+    };
+    Ok(Some(step))
+}
+
+fn usage(word: &str) -> &'static str {
+    match word {
+        "say" => "say TEXT",
+        "key" => "key KEY",
+        "hold" => "hold KEY MILLISECONDS",
+        "wait" => "wait MILLISECONDS",
+        _ => "expect TEXT",
+    }
+}
+
+/// Input read from a script as the game runs
+pub struct Script {
+    lines: Receiver<String>,
+    /// Lines read so far, for errors
+    line_number: usize,
+    blocked: Option<Blocked>,
+    /// This frame's input, with how each key is pressed
+    queue: VecDeque<(Input, NcInputType)>,
+    /// Whether every line has been read
+    ended: bool,
+}
+
+impl Script {
+    /// Read the script at `path`, or stdin without one, on a thread of its own
+    pub fn open(path: Option<&Path>) -> Result<Script, String> {
+        let reader: Box<dyn BufRead + Send> = match path {
+            Some(path) => Box::new(BufReader::new(
+                File::open(path).map_err(|e| format!("Failed to open script {}: {}", path.display(), e))?,
+            )),
+            None => Box::new(BufReader::new(io::stdin())),
+        };
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in reader.lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Script::new(lines))
+    }
+
+    fn new(lines: Receiver<String>) -> Self {
+        Script { lines, line_number: 0, blocked: None, queue: VecDeque::new(), ended: false }
+    }
+
+    /// Queue this frame's input: the lines up to the next wait, or another push of a held key.
+    /// A bad line or an `expect` that timed out is an error
+    pub fn advance(&mut self, now: Instant) -> Result<(), String> {
+        match &self.blocked {
+            Some(Blocked::Until(until)) if now < *until => return Ok(()),
+            Some(Blocked::Holding(key, until)) if now < *until => {
+                self.queue.push_back((Input::Key(key.received()), NcInputType::Repeat));
+                return Ok(());
+            }
+            Some(Blocked::Expecting(text, deadline, line)) => {
+                if now < *deadline {
+                    return Ok(());
+                }
+                return Err(format!("Script line {}: no \"{}\" within {}s", line, text, EXPECT_TIMEOUT.as_secs()));
+            }
+            _ => self.blocked = None,
+        }
+        while self.blocked.is_none() {
+            let line = match self.lines.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.ended = true;
+                    break;
+                }
+            };
+            self.line_number += 1;
+            let step = parse(&line).map_err(|e| format!("Script line {}: {}", self.line_number, e))?;
+            match step {
+                None => {}
+                Some(Step::Line(text)) => self.queue.push_back((Input::Line(text), NcInputType::Press)),
+                Some(Step::Key(key)) => self.queue.push_back((Input::Key(key.received()), NcInputType::Press)),
+                Some(Step::Hold(key, time)) => {
+                    self.queue.push_back((Input::Key(key.received()), NcInputType::Press));
+                    self.blocked = Some(Blocked::Holding(key, now + time));
+                }
+                Some(Step::Wait(time)) => self.blocked = Some(Blocked::Until(now + time)),
+                Some(Step::Expect(text)) => self.blocked = Some(Blocked::Expecting(text, now + EXPECT_TIMEOUT, self.line_number)),
+            }
+        }
+        Ok(())
+    }
+
+    /// The next input this frame, with `input` set to how a key is pressed
+    pub fn next(&mut self, input: &mut NcInput) -> Option<Input> {
+        let (next, evtype) = self.queue.pop_front()?;
+        input.evtype = evtype.into();
+        Some(next)
+    }
+
+    /// Look at a chat message, which may be the one an `expect` waits for
+    pub fn saw(&mut self, text: &str) {
+        if matches!(&self.blocked, Some(Blocked::Expecting(expected, ..)) if text.contains(expected.as_str())) {
+            self.blocked = None;
+        }
+    }
+
+    /// Whether the script has run to its end
+    pub fn finished(&self) -> bool {
+        self.ended && self.blocked.is_none() && self.queue.is_empty()
+    }
+}
+
+/// A chat message as written to stdout: time (UTC), channel and text, split by tabs
+pub fn transcript_line(time: SystemTime, channel: &str, text: &str) -> String {
+    format!("{}\t{}\t{}", chatlog::clock_time(time), channel, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libnotcurses_sys::NcKey;
+    use std::time::UNIX_EPOCH;
+
+    fn from_lines(text: &str) -> Script {
+        let (sender, lines) = mpsc::channel();
+        for line in text.lines() {
+            sender.send(line.to_string()).unwrap();
+        }
+        Script::new(lines)
+    }
+
+    fn drain(script: &mut Script) -> Vec<(Input, NcInputType)> {
+        let mut input = NcInput::new_empty();
+        std::iter::from_fn(|| script.next(&mut input).map(|next| (next, NcInputType::from(input.evtype)))).collect()
+    }
+
+    // ==================== Parse Tests ====================
+
+    #[test]
+    fn test_parse_steps() {
+        assert_eq!(parse("  # comment"), Ok(None));
+        assert_eq!(parse(""), Ok(None));
+        assert_eq!(parse("/nav 10 -4"), Ok(Some(Step::Line("/nav 10 -4".to_string()))));
+        assert_eq!(parse("say hi there"), Ok(Some(Step::Line("hi there".to_string()))));
+        assert_eq!(parse("key f12"), Ok(Some(Step::Key(KeySpec::Key(NcKey::F12)))));
+        assert_eq!(parse("hold right 800"), Ok(Some(Step::Hold(KeySpec::Key(NcKey::Right), Duration::from_millis(800)))));
+        assert_eq!(parse("wait 250"), Ok(Some(Step::Wait(Duration::from_millis(250)))));
+        assert_eq!(parse("expect Autopilot: arrived"), Ok(Some(Step::Expect("Autopilot: arrived".to_string()))));
+        assert!(parse("wait soon").unwrap_err().contains("milliseconds"));
+        assert!(parse("key bogus").unwrap_err().contains("unknown key"));
+        assert_eq!(parse("hold x").unwrap_err(), "usage: hold KEY MILLISECONDS");
+        assert!(parse("nav 1 2").unwrap_err().contains("commands start with /"), "A forgotten slash is caught");
+    }
+
+    // ==================== Script Tests ====================
+
+    #[test]
+    fn test_script_stops_at_waits_and_holds() {
+        let start = Instant::now();
+        let mut script = from_lines("/pos\nkey x\nwait 100\nhold d 50\nsay done");
+        script.advance(start).unwrap();
+        assert_eq!(drain(&mut script), [
+            (Input::Line("/pos".to_string()), NcInputType::Press),
+            (Input::Key(NcReceived::Char('x')), NcInputType::Press),
+        ]);
+        script.advance(start + Duration::from_millis(50)).unwrap();
+        assert!(drain(&mut script).is_empty(), "Still waiting");
+
+        script.advance(start + Duration::from_millis(100)).unwrap();
+        assert_eq!(drain(&mut script), [(Input::Key(NcReceived::Char('d')), NcInputType::Press)]);
+        script.advance(start + Duration::from_millis(120)).unwrap();
+        assert_eq!(drain(&mut script), [(Input::Key(NcReceived::Char('d')), NcInputType::Repeat)], "Held down");
+
+        assert!(!script.finished());
+        script.advance(start + Duration::from_millis(150)).unwrap();
+        assert_eq!(drain(&mut script), [(Input::Line("done".to_string()), NcInputType::Press)]);
+        assert!(script.finished(), "Every line read and run");
+    }
+
+    #[test]
+    fn test_expect_waits_for_its_message() {
+        let start = Instant::now();
+        let mut script = from_lines("/pos\nexpect Position:\n/quit");
+        script.advance(start).unwrap();
+        drain(&mut script);
+        script.saw("Welcome to Exospace!");
+        script.advance(start + Duration::from_secs(1)).unwrap();
+        assert!(drain(&mut script).is_empty(), "Not seen yet");
+        script.saw("Position: (3, 4) - 20 tiles explored");
+        script.advance(start + Duration::from_secs(1)).unwrap();
+        assert_eq!(drain(&mut script), [(Input::Line("/quit".to_string()), NcInputType::Press)]);
+
+        let mut script = from_lines("expect Docked");
+        script.advance(start).unwrap();
+        let error = script.advance(start + EXPECT_TIMEOUT).unwrap_err();
+        assert_eq!(error, "Script line 1: no \"Docked\" within 10s");
+        assert!(from_lines("wait x").advance(start).unwrap_err().starts_with("Script line 1:"));
+    }
+
+    #[test]
+    fn test_transcript_line() {
+        let time = UNIX_EPOCH + Duration::from_secs(3600 + 65);
+        assert_eq!(transcript_line(time, "system", "Docked at Vesta"), "01:01:05\tsystem\tDocked at Vesta");
+    }
+}
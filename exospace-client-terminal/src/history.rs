@@ -0,0 +1,152 @@
+//! Chat input history: lines sent from the chat box, recalled with Up/Down.
+//!
+//! Kept one line per entry in `~/.config/exospace/chat_history.txt` and
+//! rewritten after every line sent, so it carries over between sessions.
+//! Browsing starts from the newest entry; the line being typed is kept as
+//! the draft and comes back when Down walks past the newest.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Most lines kept, oldest dropped first
+pub const MAX_HISTORY: usize = 200;
+
+#[derive(Debug, Default)]
+pub struct InputHistory {
+    /// Oldest first
+    entries: Vec<String>,
+    /// Index of the entry shown while browsing
+    browsing: Option<usize>,
+    /// What was typed before browsing started
+    draft: String,
+    /// File written after each push; tests leave it unset
+    path: Option<PathBuf>,
+}
+
+impl InputHistory {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|mut p| {
+            p.push("exospace");
+            p.push("chat_history.txt");
+            p
+        })
+    }
+
+    /// Load saved history, starting empty if there is none
+    pub fn load() -> Self {
+        Self::path().map(Self::load_from).unwrap_or_default()
+    }
+
+    pub fn load_from(path: PathBuf) -> Self {
+        let entries: Vec<String> = fs::read_to_string(&path)
+            .map(|text| text.lines().filter(|line| !line.trim().is_empty()).map(str::to_string).collect())
+            .unwrap_or_default();
+        let skip = entries.len().saturating_sub(MAX_HISTORY);
+        InputHistory { entries: entries[skip..].to_vec(), path: Some(path), ..Default::default() }
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Remember a sent line (skipping blanks and repeats of the last one), stop browsing and save
+    pub fn push(&mut self, line: &str) {
+        self.reset();
+        if line.trim().is_empty() || self.entries.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.remove(0);
+        }
+        if let Some(path) = &self.path {
+            let _ = save_to(path, &self.entries);
+        }
+    }
+
+    /// Step back to the previous entry; `current` is kept as the draft when browsing starts
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let index = match self.browsing {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len() - 1
+            }
+            Some(0) => return None,
+            Some(i) => i - 1,
+        };
+        self.browsing = Some(index);
+        Some(&self.entries[index])
+    }
+
+    /// Step forward to the next entry, or back to the draft past the newest
+    pub fn newer(&mut self) -> Option<&str> {
+        let index = self.browsing?;
+        if index + 1 < self.entries.len() {
+            self.browsing = Some(index + 1);
+            Some(&self.entries[index + 1])
+        } else {
+            self.browsing = None;
+            Some(&self.draft)
+        }
+    }
+
+    /// Stop browsing, e.g. when the chat box closes
+    pub fn reset(&mut self) {
+        self.browsing = None;
+        self.draft.clear();
+    }
+}
+
+fn save_to(path: &Path, entries: &[String]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    }
+    let mut text = entries.join("\n");
+    text.push('\n');
+    fs::write(path, text).map_err(|e| format!("Failed to write chat history: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Browsing Tests ====================
+
+    #[test]
+    fn test_browsing_keeps_the_draft() {
+        let mut history = InputHistory::default();
+        assert_eq!(history.older("typed"), None, "Nothing to recall yet");
+        history.push("/pos");
+        history.push("hello");
+        history.push("hello");
+        history.push("  ");
+        assert_eq!(history.len(), 2, "Blanks and repeats aren't kept");
+
+        assert_eq!(history.older("half a li"), Some("hello"));
+        assert_eq!(history.older("hello"), Some("/pos"));
+        assert_eq!(history.older("/pos"), None, "Stops at the oldest");
+        assert_eq!(history.newer(), Some("hello"));
+        assert_eq!(history.newer(), Some("half a li"), "Past the newest is the draft again");
+        assert_eq!(history.newer(), None);
+    }
+
+    // ==================== Persistence Tests ====================
+
+    #[test]
+    fn test_history_is_saved_and_capped() {
+        let path = std::env::temp_dir().join(format!("exospace-history-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut history = InputHistory::load_from(path.clone());
+        for i in 0..MAX_HISTORY + 5 {
+            history.push(&format!("/goto {} 0", i));
+        }
+        assert_eq!(history.len(), MAX_HISTORY);
+
+        let mut loaded = InputHistory::load_from(path.clone());
+        assert_eq!(loaded.len(), MAX_HISTORY, "Written after every line");
+        assert_eq!(loaded.older(""), Some(format!("/goto {} 0", MAX_HISTORY + 4).as_str()));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+//! HUD widgets, each drawn on its own notcurses plane.
+//!
+//! The game view is drawn on the standard plane; the status bar, chat pane,
+//! minimap, picture-in-picture, frame timings, net diagnostics, the top
+//! screen's dialog, a script's panel, the region banner and the chat log each get a child plane. `Hud::place()` makes a widget's plane,
+//! or moves and resizes it, so widgets are laid out independently, and keeps
+//! the planes stacked in `Widget` order. A widget placed with the same content
+//! key as last frame, in the same spot, isn't drawn again; widgets drawn from
+//! the map (minimap, picture-in-picture) pass no key and redraw every frame.
+
+use libnotcurses_sys::{NcPlane, NcResult};
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// HUD elements, bottom of the stack first
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Widget {
+    Pip,
+    Minimap,
+    Radar,
+    Missions,
+    /// Frame timings (F3)
+    Perf,
+    NetStats,
+    /// Text a running script shows (`/run`)
+    Script,
+    /// The region just entered
+    Region,
+    /// The top screen's panel, when it asks for its own plane
+    Dialog,
+    /// Covers the game view, so over everything drawn there
+    ChatLog,
+    Chat,
+    Status,
+}
+
+/// Where a widget sits on the terminal; empty by default
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub y: u32,
+    pub x: u32,
+    pub rows: u32,
+    pub cols: u32,
+}
+
+impl Rect {
+    pub fn new(y: u32, x: u32, rows: u32, cols: u32) -> Self {
+        Rect { y, x, rows, cols }
+    }
+
+    /// A panel `rows` x `cols` centered in an area, if it fits
+    pub fn centered(rows: u32, cols: u32, area_rows: u32, area_cols: u32) -> Option<Self> {
+        (area_cols >= cols && area_rows >= rows).then(|| Rect::new((area_rows - rows) / 2, (area_cols - cols) / 2, rows, cols))
+    }
+
+    /// The top-left corner as (x, y)
+    pub fn origin(self) -> (u32, u32) {
+        (self.x, self.y)
+    }
+}
+
+/// The game view and the bottom rows: chat messages, the input line, then the status bar
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub game_height: u32,
+    /// Messages and the input line
+    pub chat: Rect,
+    pub status: Rect,
+}
+
+impl Layout {
+    /// `pane_height` counts the chat messages, input line and status bar (`ChatWindow::height()`)
+    pub fn new(term_height: u32, term_width: u32, pane_height: u32) -> Self {
+        let game_height = term_height.saturating_sub(pane_height);
+        let status_y = term_height.saturating_sub(1);
+        Layout {
+            game_height,
+            chat: Rect::new(game_height, 0, status_y - game_height, term_width),
+            status: Rect::new(status_y, 0, term_height.min(1), term_width),
+        }
+    }
+}
+
+/// Content key for `Hud::place()`: equal keys mean the widget would be drawn the same
+pub fn content_key(content: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+struct Slot<'a> {
+    plane: &'a mut NcPlane,
+    rect: Rect,
+    /// Key of what's on the plane; None when it has to be drawn
+    drawn: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct Hud<'a> {
+    slots: BTreeMap<Widget, Slot<'a>>,
+}
+
+impl<'a> Hud<'a> {
+    /// The widget's plane at `rect`, erased and ready to draw on; None when it already shows `key` there.
+    /// Empty rects hide the widget
+    pub fn place(&mut self, parent: &mut NcPlane, widget: Widget, rect: Rect, key: Option<u64>) -> NcResult<Option<&mut NcPlane>> {
+        if rect.rows == 0 || rect.cols == 0 {
+            self.hide(widget)?;
+            return Ok(None);
+        }
+        let restack = !self.slots.contains_key(&widget);
+        let slot = match self.slots.get_mut(&widget) {
+            Some(slot) => {
+                if slot.rect != rect {
+                    slot.plane.move_yx(rect.y as i32, rect.x as i32)?;
+                    if (slot.rect.rows, slot.rect.cols) != (rect.rows, rect.cols) {
+                        slot.plane.resize_simple(rect.rows, rect.cols)?;
+                    }
+                    slot.rect = rect;
+                    slot.drawn = None;
+                }
+                slot
+            }
+            None => {
+                let plane = NcPlane::new_child_sized(parent, rect.y as i32, rect.x as i32, rect.rows, rect.cols)?;
+                self.slots.entry(widget).or_insert(Slot { plane, rect, drawn: None })
+            }
+        };
+        if key.is_some() && slot.drawn == key {
+            return Ok(None);
+        }
+        slot.drawn = key;
+        if restack {
+            self.restack();
+        }
+        let plane = &mut *self.slots.get_mut(&widget).expect("just placed").plane;
+        plane.erase();
+        Ok(Some(plane))
+    }
+
+    /// Have every widget drawn again when next placed, whatever its key
+    pub fn redraw_all(&mut self) {
+        for slot in self.slots.values_mut() {
+            slot.drawn = None;
+        }
+    }
+
+    /// Take a widget off the screen; it's made again when next placed
+    pub fn hide(&mut self, widget: Widget) -> NcResult<()> {
+        match self.slots.remove(&widget) {
+            Some(slot) => slot.plane.destroy(),
+            None => Ok(()),
+        }
+    }
+
+    /// A new plane starts on top; put every plane back in `Widget` order
+    fn restack(&mut self) {
+        for slot in self.slots.values_mut() {
+            slot.plane.move_top();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Layout Tests ====================
+
+    #[test]
+    fn test_layout_bottom_rows() {
+        let layout = Layout::new(40, 100, 8);
+        assert_eq!(layout.game_height, 32);
+        assert_eq!(layout.chat, Rect::new(32, 0, 7, 100), "Messages and the input line");
+        assert_eq!(layout.status, Rect::new(39, 0, 1, 100), "The status bar is the last row");
+    }
+
+    #[test]
+    fn test_layout_tiny_terminals() {
+        let layout = Layout::new(1, 20, 2);
+        assert_eq!(layout.game_height, 0);
+        assert_eq!(layout.chat.rows, 0, "No room for chat");
+        assert_eq!(layout.status, Rect::new(0, 0, 1, 20));
+        assert_eq!(Layout::new(0, 20, 2).status.rows, 0, "Nothing fits in no rows");
+    }
+
+    #[test]
+    fn test_centered_rects() {
+        assert_eq!(Rect::centered(4, 10, 20, 40), Some(Rect::new(8, 15, 4, 10)));
+        assert_eq!(Rect::centered(4, 10, 4, 10), Some(Rect::new(0, 0, 4, 10)), "An exact fit");
+        assert_eq!(Rect::centered(4, 41, 20, 40), None);
+    }
+
+    // ==================== Stacking Tests ====================
+
+    #[test]
+    fn test_widget_order_and_content_keys() {
+        assert!(Widget::Pip < Widget::Dialog && Widget::Dialog < Widget::ChatLog, "The chat log covers screens' dialogs");
+        assert!(Widget::ChatLog < Widget::Status);
+        assert_eq!(content_key(&("status", 3)), content_key(&("status", 3)));
+        assert_ne!(content_key(&("status", 3)), content_key(&("status", 4)));
+    }
+}
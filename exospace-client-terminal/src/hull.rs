@@ -0,0 +1,204 @@
+//! Hull, shields and the hazards that wear them down.
+//!
+//! Ramming an asteroid (plain, ore or crystal) faster than
+//! `SAFE_IMPACT_SPEED` costs `IMPACT_DAMAGE` per tile/tick of speed, and
+//! every ship tick (`SHIP_TICK`) spent inside an ion storm (the orange nebula regions)
+//! costs `STORM_DAMAGE`. Damage comes off the shields first and the hull
+//! takes the rest. Shields recharge by `SHIELD_REGEN` a tick once
+//! `REGEN_DELAY` ticks have passed without damage; the hull doesn't. At zero
+//! hull the ship is destroyed: the game-over panel stays up until a key is
+//! pressed, then the ship respawns at the start position as good as new.
+//! How much hull there is depends on the ship class (`ships.rs`).
+
+use exospace_core::{Tile, hash_position};
+
+/// A scout's hull
+pub const MAX_HULL: f32 = 100.0;
+pub const MAX_SHIELDS: f32 = 50.0;
+
+/// Impacts slower than this (tiles per tick) just bump
+pub const SAFE_IMPACT_SPEED: f32 = 0.35;
+
+/// Damage per tile/tick of impact speed; a full-speed crash costs 40
+pub const IMPACT_DAMAGE: f32 = 40.0;
+
+/// Damage per ship tick inside an ion storm, about 4.5 a second
+pub const STORM_DAMAGE: f32 = 0.15;
+
+/// Shield recharged per ship tick, about 6 a second
+pub const SHIELD_REGEN: f32 = 0.2;
+
+/// Movement ticks without damage before shields recharge, about 3 seconds
+pub const REGEN_DELAY: u32 = 90;
+
+/// Nebula regions (20x20 tiles) come in six hues; this one is an ion storm
+const STORM_REGION: u32 = 2;
+
+/// Which of the six nebula hues covers a position
+pub fn nebula_region(x: i32, y: i32) -> u32 {
+    hash_position(x / 20, y / 20, 123) % 6
+}
+
+/// Whether a nebula tile at this position is an ion storm
+pub fn is_storm(x: i32, y: i32) -> bool {
+    nebula_region(x, y) == STORM_REGION
+}
+
+/// Tiles that damage a ship flying into them
+pub fn is_rock(tile: Option<Tile>) -> bool {
+    matches!(tile, Some(Tile::Asteroid | Tile::OreAsteroid | Tile::CrystalField))
+}
+
+/// The ship's hull and shields
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShipStatus {
+    pub hull: f32,
+    pub max_hull: f32,
+    pub shields: f32,
+    /// Movement ticks since the last damage
+    calm_ticks: u32,
+}
+
+impl Default for ShipStatus {
+    fn default() -> Self {
+        ShipStatus::new(MAX_HULL)
+    }
+}
+
+impl ShipStatus {
+    /// A ship in perfect shape
+    pub fn new(max_hull: f32) -> Self {
+        ShipStatus { hull: max_hull, max_hull, shields: MAX_SHIELDS, calm_ticks: 0 }
+    }
+
+    /// Move to a ship with a different hull, keeping the same share of it intact
+    pub fn refit(&mut self, max_hull: f32) {
+        self.hull = self.hull / self.max_hull * max_hull;
+        self.max_hull = max_hull;
+    }
+
+    /// Whether hull or shields are down at all
+    pub fn damaged(&self) -> bool {
+        self.hull < self.max_hull || self.shields < MAX_SHIELDS
+    }
+
+    /// Whole hull points missing, as repairs are charged
+    pub fn hull_damage(&self) -> u32 {
+        (self.max_hull - self.hull).max(0.0).ceil() as u32
+    }
+
+    /// Restore hull and shields; false if there was nothing to fix
+    pub fn repair(&mut self) -> bool {
+        if !self.damaged() {
+            return false;
+        }
+        *self = ShipStatus::new(self.max_hull);
+        true
+    }
+
+    pub fn destroyed(&self) -> bool {
+        self.hull <= 0.0
+    }
+
+    /// Take damage, shields first
+    pub fn damage(&mut self, amount: f32) {
+        if amount <= 0.0 {
+            return;
+        }
+        let absorbed = amount.min(self.shields);
+        self.shields -= absorbed;
+        self.hull = (self.hull - (amount - absorbed)).max(0.0);
+        self.calm_ticks = 0;
+    }
+
+    /// One ship tick: `impact` is the speed of any crash into rock, `in_storm` whether the ship sits in an ion storm
+    pub fn tick(&mut self, impact: Option<f32>, in_storm: bool) {
+        if self.destroyed() {
+            return;
+        }
+        let crash = impact.filter(|&speed| speed > SAFE_IMPACT_SPEED).map_or(0.0, |speed| speed * IMPACT_DAMAGE);
+        let storm = if in_storm { STORM_DAMAGE } else { 0.0 };
+        if crash + storm > 0.0 {
+            self.damage(crash + storm);
+            return;
+        }
+        self.calm_ticks = self.calm_ticks.saturating_add(1);
+        if self.calm_ticks >= REGEN_DELAY {
+            self.shields = (self.shields + SHIELD_REGEN).min(MAX_SHIELDS);
+        }
+    }
+
+    /// A `width`-cell gauge like `[####----]`
+    pub fn bar(value: f32, max: f32, width: usize) -> String {
+        let filled = ((value / max).clamp(0.0, 1.0) * width as f32).ceil() as usize;
+        format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+    }
+
+    /// Status bar text, e.g. `H[#####-] S[###---]`
+    pub fn summary(&self) -> String {
+        format!("H{} S{}", Self::bar(self.hull, self.max_hull, 6), Self::bar(self.shields, MAX_SHIELDS, 6))
+    }
+}
+
+/// Game-over panel text
+pub fn game_over_lines() -> Vec<String> {
+    vec![
+        "Your ship broke apart.".to_string(),
+        String::new(),
+        "Any key respawns at the start".to_string(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Damage Tests ====================
+
+    #[test]
+    fn test_damage_drains_shields_first() {
+        let mut ship = ShipStatus::default();
+        ship.damage(30.0);
+        assert_eq!((ship.hull, ship.shields), (MAX_HULL, 20.0));
+        ship.damage(50.0);
+        assert_eq!((ship.hull, ship.shields), (70.0, 0.0), "The hull takes what the shields can't");
+        ship.refit(200.0);
+        assert_eq!((ship.hull, ship.max_hull), (140.0, 200.0), "Refits keep the share of hull left");
+        ship.damage(500.0);
+        assert_eq!(ship.hull, 0.0);
+        assert!(ship.destroyed());
+    }
+
+    #[test]
+    fn test_hazards_and_regen() {
+        let mut ship = ShipStatus::default();
+        ship.tick(Some(SAFE_IMPACT_SPEED), false);
+        assert_eq!(ship, ShipStatus { calm_ticks: 1, ..ShipStatus::default() }, "Gentle bumps are free");
+        ship.tick(Some(1.0), false);
+        assert_eq!(ship.shields, MAX_SHIELDS - IMPACT_DAMAGE);
+        ship.tick(None, true);
+        assert!(ship.shields < MAX_SHIELDS - IMPACT_DAMAGE, "Storms keep draining");
+
+        let drained = ship.shields;
+        for _ in 0..REGEN_DELAY - 1 {
+            ship.tick(None, false);
+        }
+        assert_eq!(ship.shields, drained, "No regen until things calm down");
+        ship.tick(None, false);
+        assert_eq!(ship.shields, drained + SHIELD_REGEN);
+        for _ in 0..1000 {
+            ship.tick(None, false);
+        }
+        assert_eq!((ship.hull, ship.shields), (MAX_HULL, MAX_SHIELDS), "Shields cap out; the hull was never hit");
+    }
+
+    #[test]
+    fn test_bars_and_rocks() {
+        assert_eq!(ShipStatus::bar(50.0, 100.0, 6), "[###---]");
+        assert_eq!(ShipStatus::bar(1.0, 100.0, 6), "[#-----]", "Any hull left shows");
+        assert_eq!(ShipStatus::bar(0.0, 100.0, 6), "[------]");
+        assert_eq!(ShipStatus::default().summary(), "H[######] S[######]");
+        assert!(is_rock(Some(Tile::OreAsteroid)) && !is_rock(Some(Tile::Wall)) && !is_rock(None));
+        assert!((0..400).any(|x| is_storm(x, 0)) && (0..400).any(|x| !is_storm(x, 0)), "Some nebulae are storms");
+    }
+}
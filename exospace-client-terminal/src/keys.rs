@@ -0,0 +1,625 @@
+//! Game-mode keybindings: which key triggers which action.
+//!
+//! Config stores only the bindings that differ from the defaults, as action
+//! name → key spec, e.g. `"move_up": "w"` or `"quit": "none"`. Letters match
+//! either case, so `"q"` also catches Shift+Q.
+//!
+//! An input profile (config `input_profile`) adds a second set of movement
+//! keys next to the arrows: WASD, or vi's hjkl with yubn for the diagonals.
+//! Letters a profile takes move their actions elsewhere (see
+//! `InputProfile::moved_key`), and `/bind` can still take the keys back.
+
+use libnotcurses_sys::{NcKey, NcReceived};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Something a key can do outside of chat
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveUpLeft,
+    MoveUpRight,
+    MoveDownLeft,
+    MoveDownRight,
+    /// Open the chat input
+    Chat,
+    /// Open the chat input with `/` typed
+    Command,
+    ToggleEffects,
+    TogglePip,
+    ToggleMinimap,
+    /// Show the radar scope
+    ToggleRadar,
+    /// Show the mission log, fetching the board
+    ToggleMissions,
+    /// Show the frame timing overlay
+    TogglePerf,
+    /// Show the network diagnostics overlay
+    ToggleNetStats,
+    /// Mine the resource tile next to the ship
+    Mine,
+    /// Cut away the asteroid next to the ship
+    FireLaser,
+    /// Fire a projectile the way the ship faces
+    Fire,
+    /// Dock at the station next to the ship, or undock
+    Dock,
+    /// Jump through the gate the ship is on
+    Jump,
+    /// Open or close the settings screen
+    Settings,
+    /// Save the screen to text and ANSI files
+    Screenshot,
+    /// Cycle the chat pane between normal, expanded and collapsed
+    ChatPane,
+    /// Open the full-screen chat log
+    ChatLog,
+    /// Show fewer, closer tiles
+    ZoomIn,
+    /// Show more of the map, several tiles per cell
+    ZoomOut,
+    /// Unhook the view from the ship, so the movement keys pan it; again to follow the ship
+    Spectate,
+    /// Bring the free camera back over the ship
+    SnapCamera,
+    Quit,
+}
+
+impl Action {
+    /// Every action, in `/bind` listing order
+    pub const ALL: [Action; 31] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUpLeft,
+        Action::MoveUpRight,
+        Action::MoveDownLeft,
+        Action::MoveDownRight,
+        Action::Chat,
+        Action::Command,
+        Action::ToggleEffects,
+        Action::TogglePip,
+        Action::ToggleMinimap,
+        Action::ToggleRadar,
+        Action::ToggleMissions,
+        Action::TogglePerf,
+        Action::ToggleNetStats,
+        Action::Mine,
+        Action::FireLaser,
+        Action::Fire,
+        Action::Dock,
+        Action::Jump,
+        Action::Settings,
+        Action::Screenshot,
+        Action::ChatPane,
+        Action::ChatLog,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::Spectate,
+        Action::SnapCamera,
+        Action::Quit,
+    ];
+
+    /// Name used in config and `/bind`
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::MoveUpLeft => "move_up_left",
+            Action::MoveUpRight => "move_up_right",
+            Action::MoveDownLeft => "move_down_left",
+            Action::MoveDownRight => "move_down_right",
+            Action::Chat => "chat",
+            Action::Command => "command",
+            Action::ToggleEffects => "toggle_effects",
+            Action::TogglePip => "toggle_pip",
+            Action::ToggleMinimap => "toggle_minimap",
+            Action::ToggleRadar => "toggle_radar",
+            Action::ToggleMissions => "toggle_missions",
+            Action::TogglePerf => "toggle_perf",
+            Action::ToggleNetStats => "toggle_net_stats",
+            Action::Mine => "mine",
+            Action::FireLaser => "fire_laser",
+            Action::Fire => "fire",
+            Action::Dock => "dock",
+            Action::Jump => "jump",
+            Action::Settings => "settings",
+            Action::Screenshot => "screenshot",
+            Action::ChatPane => "chat_pane",
+            Action::ChatLog => "chat_log",
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::Spectate => "spectate",
+            Action::SnapCamera => "snap_camera",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Movement keys are held; everything else fires once per press
+    pub fn is_movement(self) -> bool {
+        self.direction() != (0, 0)
+    }
+
+    /// Thrust a held movement key adds, as (dx, dy); (0, 0) for other actions
+    pub fn direction(self) -> (i32, i32) {
+        match self {
+            Action::MoveUp => (0, -1),
+            Action::MoveDown => (0, 1),
+            Action::MoveLeft => (-1, 0),
+            Action::MoveRight => (1, 0),
+            Action::MoveUpLeft => (-1, -1),
+            Action::MoveUpRight => (1, -1),
+            Action::MoveDownLeft => (-1, 1),
+            Action::MoveDownRight => (1, 1),
+            _ => (0, 0),
+        }
+    }
+
+    /// Key under a profile; diagonals have none of their own, only the profile's extras
+    fn default_key(self, profile: InputProfile) -> Option<KeySpec> {
+        if let Some(ch) = profile.moved_key(self) {
+            return Some(KeySpec::Char(ch));
+        }
+        let key = match self {
+            Action::MoveUp => KeySpec::Key(NcKey::Up),
+            Action::MoveDown => KeySpec::Key(NcKey::Down),
+            Action::MoveLeft => KeySpec::Key(NcKey::Left),
+            Action::MoveRight => KeySpec::Key(NcKey::Right),
+            Action::MoveUpLeft | Action::MoveUpRight | Action::MoveDownLeft | Action::MoveDownRight => return None,
+            Action::Chat => KeySpec::Key(NcKey::Enter),
+            Action::Command => KeySpec::Char('/'),
+            Action::ToggleEffects => KeySpec::Char('b'),
+            Action::TogglePip => KeySpec::Char('p'),
+            Action::ToggleMinimap => KeySpec::Char('m'),
+            Action::ToggleRadar => KeySpec::Char('r'),
+            Action::ToggleMissions => KeySpec::Char('j'),
+            Action::TogglePerf => KeySpec::Key(NcKey::F03),
+            Action::ToggleNetStats => KeySpec::Key(NcKey::F04),
+            Action::Mine => KeySpec::Char('x'),
+            Action::FireLaser => KeySpec::Char('l'),
+            Action::Fire => KeySpec::Char(' '),
+            Action::Dock => KeySpec::Char('d'),
+            Action::Jump => KeySpec::Char('g'),
+            Action::Settings => KeySpec::Key(NcKey::F02),
+            Action::Screenshot => KeySpec::Key(NcKey::F12),
+            Action::ChatPane => KeySpec::Char('c'),
+            Action::ChatLog => KeySpec::Key(NcKey::PgUp),
+            Action::ZoomIn => KeySpec::Char('+'),
+            Action::ZoomOut => KeySpec::Char('-'),
+            Action::Spectate => KeySpec::Key(NcKey::F05),
+            Action::SnapCamera => KeySpec::Key(NcKey::Home),
+            Action::Quit => KeySpec::Char('q'),
+        };
+        Some(key)
+    }
+}
+
+/// Movement layout added alongside the arrows
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InputProfile {
+    /// Arrow keys only
+    #[default]
+    Arrows,
+    /// W/A/S/D; dock moves to E
+    Wasd,
+    /// h/j/k/l, with y/u/b/n for the diagonals; the mission log, laser and effects move to O, F and E
+    Vi,
+}
+
+impl InputProfile {
+    /// Keys the profile adds, each next to the action's own key
+    fn movement_keys(self) -> &'static [(Action, char)] {
+        match self {
+            InputProfile::Arrows => &[],
+            InputProfile::Wasd => &[
+                (Action::MoveUp, 'w'),
+                (Action::MoveLeft, 'a'),
+                (Action::MoveDown, 's'),
+                (Action::MoveRight, 'd'),
+            ],
+            InputProfile::Vi => &[
+                (Action::MoveLeft, 'h'),
+                (Action::MoveDown, 'j'),
+                (Action::MoveUp, 'k'),
+                (Action::MoveRight, 'l'),
+                (Action::MoveUpLeft, 'y'),
+                (Action::MoveUpRight, 'u'),
+                (Action::MoveDownLeft, 'b'),
+                (Action::MoveDownRight, 'n'),
+            ],
+        }
+    }
+
+    /// New key for an action whose default letter the profile takes
+    fn moved_key(self, action: Action) -> Option<char> {
+        match (self, action) {
+            (InputProfile::Wasd, Action::Dock) => Some('e'),
+            (InputProfile::Vi, Action::ToggleMissions) => Some('o'),
+            (InputProfile::Vi, Action::FireLaser) => Some('f'),
+            (InputProfile::Vi, Action::ToggleEffects) => Some('e'),
+            _ => None,
+        }
+    }
+}
+
+/// A single key, as written in config
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeySpec {
+    /// A printable character; letters are stored lowercase
+    Char(char),
+    Key(NcKey),
+}
+
+/// Spellings for non-printable keys
+const NAMED_KEYS: &[(&str, NcKey)] = &[
+    ("up", NcKey::Up),
+    ("down", NcKey::Down),
+    ("left", NcKey::Left),
+    ("right", NcKey::Right),
+    ("enter", NcKey::Enter),
+    ("tab", NcKey::Tab),
+    ("esc", NcKey::Esc),
+    ("backspace", NcKey::Backspace),
+    ("del", NcKey::Del),
+    ("ins", NcKey::Ins),
+    ("home", NcKey::Home),
+    ("end", NcKey::End),
+    ("pgup", NcKey::PgUp),
+    ("pgdown", NcKey::PgDown),
+];
+
+/// Written in config for an action with no key
+const UNBOUND: &str = "none";
+
+impl KeySpec {
+    /// Parse "q", "space", "up", "f5" and the like
+    pub fn parse(text: &str) -> Option<KeySpec> {
+        let mut chars = text.chars();
+        if let (Some(ch), None) = (chars.next(), chars.next()) {
+            return ch.is_ascii_graphic().then(|| KeySpec::Char(ch.to_ascii_lowercase()));
+        }
+
+        let lower = text.to_ascii_lowercase();
+        if lower == "space" {
+            return Some(KeySpec::Char(' '));
+        }
+        if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u32>().ok()) {
+            return (1..=12).contains(&n).then(|| KeySpec::Key(NcKey(NcKey::F00.0 + n)));
+        }
+        NAMED_KEYS.iter().find(|(name, _)| *name == lower).map(|&(_, key)| KeySpec::Key(key))
+    }
+
+    /// Spelling that `parse` reads back
+    pub fn name(self) -> String {
+        match self {
+            KeySpec::Char(' ') => "space".to_string(),
+            KeySpec::Char(ch) => ch.to_string(),
+            KeySpec::Key(key) => match NAMED_KEYS.iter().find(|(_, k)| *k == key) {
+                Some((name, _)) => name.to_string(),
+                None => format!("f{}", key.0 - NcKey::F00.0),
+            },
+        }
+    }
+
+    /// The key as notcurses reports it, for scripted input
+    pub fn received(self) -> NcReceived {
+        match self {
+            KeySpec::Char(ch) => NcReceived::Char(ch),
+            KeySpec::Key(key) => NcReceived::Key(key),
+        }
+    }
+
+    fn from_received(received: NcReceived) -> Option<KeySpec> {
+        match received {
+            NcReceived::Char(ch) => Some(KeySpec::Char(ch.to_ascii_lowercase())),
+            NcReceived::Key(key) => Some(KeySpec::Key(key)),
+            NcReceived::NoInput => None,
+        }
+    }
+}
+
+/// Current key for each action; an action without an entry is unbound
+pub struct KeyMap {
+    keys: HashMap<Action, KeySpec>,
+    /// The input profile's movement keys, working alongside `keys`
+    extra: HashMap<Action, KeySpec>,
+    profile: InputProfile,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap::new(InputProfile::default())
+    }
+}
+
+impl KeyMap {
+    /// Default keys under a profile
+    pub fn new(profile: InputProfile) -> Self {
+        let keys = Action::ALL.into_iter().filter_map(|action| Some((action, action.default_key(profile)?))).collect();
+        let extra = profile.movement_keys().iter().map(|&(action, ch)| (action, KeySpec::Char(ch))).collect();
+        KeyMap { keys, extra, profile }
+    }
+
+    /// Profile defaults with the config's bindings applied; bad entries are skipped and reported
+    pub fn from_config(bindings: &HashMap<Action, String>, profile: InputProfile) -> (Self, Vec<String>) {
+        let mut keymap = KeyMap::new(profile);
+        let mut sorted: Vec<_> = bindings.iter().collect();
+        sorted.sort();
+        let errors = sorted
+            .into_iter()
+            .filter_map(|(&action, spec)| keymap.bind_spec(action, spec).err().map(|e| format!("{}: {}", action.name(), e)))
+            .collect();
+        (keymap, errors)
+    }
+
+    /// Action a key triggers in game mode
+    pub fn action_for(&self, received: NcReceived) -> Option<Action> {
+        let spec = KeySpec::from_received(received)?;
+        let owner = |keys: &HashMap<Action, KeySpec>| Action::ALL.into_iter().find(|action| keys.get(action) == Some(&spec));
+        owner(&self.keys).or_else(|| owner(&self.extra))
+    }
+
+    pub fn key_for(&self, action: Action) -> Option<KeySpec> {
+        self.keys.get(&action).copied()
+    }
+
+    /// Bind an action to a key spec ("none" unbinds), returning the action that lost the key
+    pub fn bind_spec(&mut self, action: Action, spec: &str) -> Result<Option<Action>, String> {
+        let key = if spec.eq_ignore_ascii_case(UNBOUND) {
+            None
+        } else {
+            Some(KeySpec::parse(spec).ok_or_else(|| format!("unknown key '{}'", spec))?)
+        };
+
+        // A key does one thing, so whoever had it before loses it, profile keys included
+        let mut keys = self.keys.clone();
+        let mut extra = self.extra.clone();
+        let owner = |keys: &HashMap<Action, KeySpec>| {
+            key.and_then(|key| Action::ALL.into_iter().find(|&other| other != action && keys.get(&other) == Some(&key)))
+        };
+        let previous = if let Some(other) = owner(&keys) {
+            keys.remove(&other);
+            Some(other)
+        } else if let Some(other) = owner(&extra) {
+            extra.remove(&other);
+            Some(other)
+        } else {
+            None
+        };
+        match key {
+            Some(key) => keys.insert(action, key),
+            None => keys.remove(&action),
+        };
+
+        if !keys.contains_key(&Action::Chat) && !keys.contains_key(&Action::Command) {
+            return Err("keep a key for chat or command, or /bind can't be reached".to_string());
+        }
+        self.keys = keys;
+        self.extra = extra;
+        Ok(previous)
+    }
+
+    /// Bindings that differ from the profile's defaults, as saved in config
+    pub fn to_config(&self) -> HashMap<Action, String> {
+        Action::ALL
+            .into_iter()
+            .filter(|&action| self.key_for(action) != action.default_key(self.profile))
+            .map(|action| (action, self.key_for(action).map_or(UNBOUND.to_string(), KeySpec::name)))
+            .collect()
+    }
+
+    /// One "action = key" line per action, for `/bind` with no arguments
+    pub fn describe(&self) -> Vec<String> {
+        Action::ALL
+            .into_iter()
+            .map(|action| {
+                let keys: Vec<String> = self.key_for(action).into_iter().chain(self.extra.get(&action).copied()).map(KeySpec::name).collect();
+                let keys = if keys.is_empty() { UNBOUND.to_string() } else { keys.join(", ") };
+                format!("{} = {}", action.name(), keys)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== KeySpec Tests ====================
+
+    #[test]
+    fn test_key_spec_parse() {
+        assert_eq!(KeySpec::parse("q"), Some(KeySpec::Char('q')));
+        assert_eq!(KeySpec::parse("Q"), Some(KeySpec::Char('q')), "Letters are case-insensitive");
+        assert_eq!(KeySpec::parse("/"), Some(KeySpec::Char('/')));
+        assert_eq!(KeySpec::parse("space"), Some(KeySpec::Char(' ')));
+        assert_eq!(KeySpec::parse("Up"), Some(KeySpec::Key(NcKey::Up)));
+        assert_eq!(KeySpec::parse("f5"), Some(KeySpec::Key(NcKey::F05)));
+        assert_eq!(KeySpec::parse("f13"), None);
+        assert_eq!(KeySpec::parse("hyper"), None);
+        assert_eq!(KeySpec::parse(""), None);
+    }
+
+    #[test]
+    fn test_key_spec_name_round_trips() {
+        for text in ["w", "space", "left", "enter", "pgdown", "f12"] {
+            let spec = KeySpec::parse(text).unwrap();
+            assert_eq!(spec.name(), text);
+            assert_eq!(KeySpec::parse(&spec.name()), Some(spec));
+        }
+    }
+
+    // ==================== KeyMap Tests ====================
+
+    #[test]
+    fn test_default_keymap_matches_original_keys() {
+        let keymap = KeyMap::default();
+        assert_eq!(keymap.action_for(NcReceived::Char('q')), Some(Action::Quit));
+        assert_eq!(keymap.action_for(NcReceived::Char('Q')), Some(Action::Quit), "Shifted letters still work");
+        assert_eq!(keymap.action_for(NcReceived::Char('m')), Some(Action::ToggleMinimap));
+        assert_eq!(keymap.action_for(NcReceived::Char('r')), Some(Action::ToggleRadar));
+        assert_eq!(keymap.action_for(NcReceived::Char('j')), Some(Action::ToggleMissions));
+        assert_eq!(keymap.action_for(NcReceived::Char('g')), Some(Action::Jump));
+        assert_eq!(keymap.action_for(NcReceived::Char('/')), Some(Action::Command));
+        assert_eq!(keymap.action_for(NcReceived::Key(NcKey::Up)), Some(Action::MoveUp));
+        assert_eq!(keymap.action_for(NcReceived::Key(NcKey::Enter)), Some(Action::Chat));
+        assert_eq!(keymap.action_for(NcReceived::Key(NcKey::F04)), Some(Action::ToggleNetStats));
+        assert_eq!(keymap.action_for(NcReceived::Key(NcKey::F03)), Some(Action::TogglePerf));
+        assert_eq!(keymap.action_for(NcReceived::Char('x')), Some(Action::Mine));
+        assert_eq!(keymap.action_for(NcReceived::Char(' ')), Some(Action::Fire));
+        assert_eq!(keymap.action_for(NcReceived::Char('l')), Some(Action::FireLaser));
+        assert_eq!(keymap.action_for(NcReceived::Char('D')), Some(Action::Dock));
+        assert_eq!(keymap.action_for(NcReceived::Key(NcKey::F02)), Some(Action::Settings));
+        assert_eq!(keymap.action_for(NcReceived::Key(NcKey::F12)), Some(Action::Screenshot));
+        assert_eq!(keymap.action_for(NcReceived::Key(NcKey::F05)), Some(Action::Spectate));
+        assert_eq!(keymap.action_for(NcReceived::Key(NcKey::Home)), Some(Action::SnapCamera));
+        assert_eq!(keymap.action_for(NcReceived::Char('z')), None);
+        assert_eq!(keymap.action_for(NcReceived::NoInput), None);
+        assert!(keymap.to_config().is_empty(), "Defaults aren't written to config");
+    }
+
+    #[test]
+    fn test_bind_moves_key_and_steals_from_previous_owner() {
+        let mut keymap = KeyMap::default();
+        assert_eq!(keymap.bind_spec(Action::MoveUp, "w"), Ok(None));
+        assert_eq!(keymap.action_for(NcReceived::Char('w')), Some(Action::MoveUp));
+        assert_eq!(keymap.action_for(NcReceived::Key(NcKey::Up)), None, "Old key is released");
+
+        assert_eq!(keymap.bind_spec(Action::Quit, "m"), Ok(Some(Action::ToggleMinimap)));
+        assert_eq!(keymap.key_for(Action::ToggleMinimap), None);
+        assert_eq!(keymap.action_for(NcReceived::Char('m')), Some(Action::Quit));
+    }
+
+    #[test]
+    fn test_bind_rejects_bad_keys_and_locking_out_chat() {
+        let mut keymap = KeyMap::default();
+        assert!(keymap.bind_spec(Action::Quit, "hyper").is_err());
+        assert_eq!(keymap.bind_spec(Action::Chat, "none"), Ok(None), "Command still opens chat");
+        assert!(keymap.bind_spec(Action::Command, "none").is_err());
+        assert!(keymap.bind_spec(Action::Quit, "/").is_err(), "Stealing the last chat key is refused too");
+        assert_eq!(keymap.key_for(Action::Command), Some(KeySpec::Char('/')), "Refused binds change nothing");
+    }
+
+    #[test]
+    fn test_keymap_config_round_trip() {
+        let mut keymap = KeyMap::default();
+        keymap.bind_spec(Action::MoveLeft, "a").unwrap();
+        keymap.bind_spec(Action::Quit, "none").unwrap();
+
+        let saved = keymap.to_config();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[&Action::Quit], "none");
+
+        let (loaded, errors) = KeyMap::from_config(&saved, InputProfile::Arrows);
+        assert!(errors.is_empty());
+        assert_eq!(loaded.key_for(Action::MoveLeft), Some(KeySpec::Char('a')));
+        assert_eq!(loaded.key_for(Action::Quit), None);
+        assert_eq!(loaded.describe().len(), Action::ALL.len());
+    }
+
+    #[test]
+    fn test_keymap_from_config_reports_errors() {
+        let bindings = HashMap::from([(Action::Quit, "nope".to_string()), (Action::MoveUp, "w".to_string())]);
+        let (keymap, errors) = KeyMap::from_config(&bindings, InputProfile::Arrows);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("quit:"), "Got {:?}", errors);
+        assert_eq!(keymap.key_for(Action::Quit), Some(KeySpec::Char('q')), "Bad entries keep the default");
+        assert_eq!(keymap.key_for(Action::MoveUp), Some(KeySpec::Char('w')));
+    }
+
+    // ==================== Input Profile Tests ====================
+
+    #[test]
+    fn test_wasd_profile_moves_alongside_arrows() {
+        let keymap = KeyMap::new(InputProfile::Wasd);
+        assert_eq!(keymap.action_for(NcReceived::Char('w')), Some(Action::MoveUp));
+        assert_eq!(keymap.action_for(NcReceived::Char('A')), Some(Action::MoveLeft), "Shifted letters still work");
+        assert_eq!(keymap.action_for(NcReceived::Char('s')), Some(Action::MoveDown));
+        assert_eq!(keymap.action_for(NcReceived::Char('d')), Some(Action::MoveRight), "D moves instead of docking");
+        assert_eq!(keymap.action_for(NcReceived::Key(NcKey::Up)), Some(Action::MoveUp), "Arrows keep working");
+        assert_eq!(keymap.action_for(NcReceived::Char('e')), Some(Action::Dock));
+        assert!(keymap.to_config().is_empty(), "Profile defaults aren't written to config");
+        assert!(keymap.describe().contains(&"move_up = up, w".to_string()), "Got {:?}", keymap.describe());
+    }
+
+    #[test]
+    fn test_vi_profile_has_diagonals_and_moves_taken_keys() {
+        let keymap = KeyMap::new(InputProfile::Vi);
+        for (ch, action) in [
+            ('h', Action::MoveLeft),
+            ('j', Action::MoveDown),
+            ('k', Action::MoveUp),
+            ('l', Action::MoveRight),
+            ('y', Action::MoveUpLeft),
+            ('u', Action::MoveUpRight),
+            ('b', Action::MoveDownLeft),
+            ('n', Action::MoveDownRight),
+            ('o', Action::ToggleMissions),
+            ('g', Action::Jump),
+            ('f', Action::FireLaser),
+            ('e', Action::ToggleEffects),
+        ] {
+            assert_eq!(keymap.action_for(NcReceived::Char(ch)), Some(action), "Key {}", ch);
+        }
+        assert_eq!(keymap.action_for(NcReceived::Key(NcKey::Left)), Some(Action::MoveLeft));
+        assert!(keymap.to_config().is_empty());
+
+        let arrows = KeyMap::default();
+        assert_eq!(arrows.key_for(Action::MoveUpLeft), None, "Diagonals are unbound without a profile");
+        assert_eq!(arrows.action_for(NcReceived::Char('y')), None);
+    }
+
+    #[test]
+    fn test_bind_takes_profile_keys() {
+        let mut keymap = KeyMap::new(InputProfile::Wasd);
+        assert_eq!(keymap.bind_spec(Action::Dock, "d"), Ok(Some(Action::MoveRight)), "The profile's key is taken back");
+        assert_eq!(keymap.action_for(NcReceived::Char('d')), Some(Action::Dock));
+        assert_eq!(keymap.key_for(Action::MoveRight), Some(KeySpec::Key(NcKey::Right)), "The arrow stays");
+
+        let saved = keymap.to_config();
+        assert_eq!(saved, HashMap::from([(Action::Dock, "d".to_string())]));
+        let (loaded, errors) = KeyMap::from_config(&saved, InputProfile::Wasd);
+        assert!(errors.is_empty());
+        assert_eq!(loaded.action_for(NcReceived::Char('d')), Some(Action::Dock), "Reloading takes it again");
+        assert_eq!(loaded.action_for(NcReceived::Char('e')), None);
+
+        let mut vi = KeyMap::new(InputProfile::Vi);
+        assert_eq!(vi.bind_spec(Action::MoveUpLeft, "7"), Ok(None));
+        assert!(vi.describe().contains(&"move_up_left = 7, y".to_string()), "Got {:?}", vi.describe());
+    }
+
+    #[test]
+    fn test_movement_directions() {
+        let moves: Vec<_> = Action::ALL.into_iter().filter(|action| action.is_movement()).collect();
+        assert_eq!(moves.len(), 8);
+        for action in moves {
+            let (dx, dy) = action.direction();
+            assert!(dx.abs() <= 1 && dy.abs() <= 1, "{:?} moves one step", action);
+        }
+        assert_eq!(Action::MoveDownLeft.direction(), (-1, 1));
+        assert_eq!(Action::Dock.direction(), (0, 0));
+    }
+
+    #[test]
+    fn test_action_names() {
+        for action in Action::ALL {
+            assert_eq!(Action::from_name(action.name()), Some(action));
+            let json = serde_json::to_string(&action).unwrap();
+            assert_eq!(json, format!("\"{}\"", action.name()), "Config and /bind use the same names");
+        }
+        assert_eq!(Action::from_name("QUIT"), Some(Action::Quit));
+        assert_eq!(Action::from_name("teleport"), None);
+    }
+}
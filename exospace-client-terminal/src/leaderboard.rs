@@ -0,0 +1,87 @@
+//! The leaderboard panel.
+//!
+//! The server ranks logged-in pilots by distance flown, ore mined or credits
+//! earned; `/top` (or L on the title menu) fetches a page and opens it here.
+//! Up and Down turn the page and the digits switch boards, each by asking
+//! the game loop for a fresh page. The panel is a screen of its own (see
+//! `screens.rs`).
+
+use exospace_core::{Leaderboard, LeaderboardMetric};
+
+/// A leaderboard page, as shown
+pub struct LeaderboardScreen {
+    board: Leaderboard,
+    /// Ours, to pick out our row
+    pilot: Option<String>,
+}
+
+impl LeaderboardScreen {
+    pub fn new(board: Leaderboard, pilot: Option<String>) -> Self {
+        LeaderboardScreen { board, pilot }
+    }
+
+    /// The board and page a key asks for, if it asks for a different one
+    pub fn turn(&self, page_delta: i32, metric: Option<LeaderboardMetric>) -> Option<(LeaderboardMetric, u32)> {
+        if let Some(metric) = metric {
+            return (metric != self.board.metric).then_some((metric, 1));
+        }
+        let page = self.board.page as i32 + page_delta;
+        (page != self.board.page as i32 && (1..=self.board.pages as i32).contains(&page)).then_some((self.board.metric, page as u32))
+    }
+
+    /// Panel text: the board, one line per pilot, then the keys
+    pub fn lines(&self) -> Vec<String> {
+        let board = &self.board;
+        let mut lines = vec![format!("{:<22}page {}/{}", board.metric.title(), board.page, board.pages)];
+        if board.entries.is_empty() {
+            lines.push("No pilots ranked yet".to_string());
+        }
+        for entry in &board.entries {
+            let ours = self.pilot.as_deref().is_some_and(|pilot| pilot.eq_ignore_ascii_case(&entry.name));
+            lines.push(format!("{}{:>3}. {:<16}{:>10}", if ours { '>' } else { ' ' }, entry.rank, entry.name, entry.value));
+        }
+        let boards: Vec<String> = LeaderboardMetric::ALL.iter().enumerate().map(|(i, metric)| format!("{} {}", i + 1, metric.name())).collect();
+        lines.push(boards.join("  "));
+        lines.push("Up/Down pages, Esc closes".to_string());
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::LeaderboardEntry;
+
+    fn board(page: u32, pages: u32) -> Leaderboard {
+        let entries = vec![
+            LeaderboardEntry { rank: 11, name: "Bee".to_string(), value: 900 },
+            LeaderboardEntry { rank: 12, name: "Ace".to_string(), value: 45 },
+        ];
+        Leaderboard { metric: LeaderboardMetric::Distance, page, pages, entries }
+    }
+
+    // ==================== Panel Tests ====================
+
+    #[test]
+    fn test_lines_rank_pilots() {
+        let screen = LeaderboardScreen::new(board(2, 3), Some("ace".to_string()));
+        let lines = screen.lines();
+        assert_eq!(lines[0], "Distance traveled     page 2/3");
+        assert_eq!(lines[1], "  11. Bee                    900");
+        assert_eq!(lines[2], "> 12. Ace                     45", "Our own row is marked");
+        assert_eq!(lines[3], "1 distance  2 ore  3 credits");
+        let empty = Leaderboard { entries: Vec::new(), ..board(1, 1) };
+        assert_eq!(LeaderboardScreen::new(empty, None).lines()[1], "No pilots ranked yet");
+    }
+
+    #[test]
+    fn test_turn_stays_on_the_board() {
+        let screen = LeaderboardScreen::new(board(2, 3), None);
+        assert_eq!(screen.turn(1, None), Some((LeaderboardMetric::Distance, 3)));
+        assert_eq!(screen.turn(-1, None), Some((LeaderboardMetric::Distance, 1)));
+        assert_eq!(LeaderboardScreen::new(board(3, 3), None).turn(1, None), None, "No page past the last");
+        assert_eq!(LeaderboardScreen::new(board(1, 3), None).turn(-1, None), None, "Or before the first");
+        assert_eq!(screen.turn(0, Some(LeaderboardMetric::Credits)), Some((LeaderboardMetric::Credits, 1)), "New boards start at the top");
+        assert_eq!(screen.turn(0, Some(LeaderboardMetric::Distance)), None, "Already showing it");
+    }
+}
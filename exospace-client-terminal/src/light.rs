@@ -0,0 +1,122 @@
+//! Ambient light: a slow day/night cycle that dims the map.
+//!
+//! The cycle runs on the server's clock when online (it comes with
+//! `Welcome`, see `RemotePlayers::server_time()`), so pilots on a server
+//! share their dusks, and on our own clock otherwise. `brightness()` falls
+//! from full at midday to `NIGHT_BRIGHTNESS` at midnight along a cosine, and
+//! the Renderer scales tile colors by it with `dim()`; ships, stations and
+//! the HUD stay lit. `/time` says where in the day we are, and
+//! `light_level` in the config holds the brightness still for players who
+//! find the change hard to see by.
+
+use std::f64::consts::TAU;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Length of one day/night cycle, in seconds
+pub const DAY_LENGTH: u64 = 1200;
+
+/// Brightness at midnight, in percent
+pub const NIGHT_BRIGHTNESS: u32 = 40;
+
+/// Brightness the config can lock the map at, in percent
+pub const LEVEL_LIMITS: (u32, u32) = (20, 100);
+
+/// Parts of the day, in the order they come
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Night,
+    Dawn,
+    Day,
+    Dusk,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Night => "Night",
+            Phase::Dawn => "Dawn",
+            Phase::Day => "Day",
+            Phase::Dusk => "Dusk",
+        }
+    }
+}
+
+/// Where each part of the day begins, in seconds after midnight; night runs on past midnight to dawn
+const PHASES: [(u64, Phase); 4] = [(240, Phase::Dawn), (360, Phase::Day), (840, Phase::Dusk), (960, Phase::Night)];
+
+/// Seconds into the cycle: the server's clock when we have it, else ours
+pub fn cycle_time(server_time: Option<u64>) -> u64 {
+    server_time.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()))
+}
+
+/// Brightness in percent at a Unix time
+pub fn brightness(unix_secs: u64) -> u32 {
+    let daylight = (1.0 - ((unix_secs % DAY_LENGTH) as f64 / DAY_LENGTH as f64 * TAU).cos()) / 2.0;
+    NIGHT_BRIGHTNESS + ((100 - NIGHT_BRIGHTNESS) as f64 * daylight).round() as u32
+}
+
+/// The part of the day at a Unix time, the next one and the seconds until it
+pub fn phase(unix_secs: u64) -> (Phase, Phase, u64) {
+    let secs = unix_secs % DAY_LENGTH;
+    let index = PHASES.iter().rposition(|&(start, _)| start <= secs).unwrap_or(PHASES.len() - 1);
+    let (starts, next) = PHASES[(index + 1) % PHASES.len()];
+    (PHASES[index].1, next, (starts + DAY_LENGTH - secs) % DAY_LENGTH)
+}
+
+/// Scale a color's channels to `percent` of their brightness
+pub fn dim(color: u32, percent: u32) -> u32 {
+    if percent >= 100 {
+        return color;
+    }
+    [16, 8, 0].iter().fold(0, |dimmed, &shift| dimmed | ((color >> shift & 0xFF) * percent / 100) << shift)
+}
+
+/// `/time`'s line: the part of the day, the light and what comes next
+pub fn describe(unix_secs: u64, locked: Option<u32>) -> String {
+    let (now, next, secs) = phase(unix_secs);
+    match locked {
+        Some(level) => format!("{}: light locked at {}%", now.name(), level),
+        None => format!(
+            "{}: light at {}%, {} in {} min",
+            now.name(),
+            brightness(unix_secs),
+            next.name().to_lowercase(),
+            secs.div_ceil(60)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Cycle Tests ====================
+
+    #[test]
+    fn test_brightness_follows_the_day() {
+        assert_eq!(brightness(0), NIGHT_BRIGHTNESS, "Darkest at midnight");
+        assert_eq!(brightness(DAY_LENGTH / 2), 100, "Full at midday");
+        assert_eq!(brightness(DAY_LENGTH * 5 + DAY_LENGTH / 2), 100, "Every day alike");
+        let levels: Vec<u32> = (0..=DAY_LENGTH / 2).step_by(60).map(brightness).collect();
+        assert!(levels.windows(2).all(|pair| pair[0] <= pair[1]), "Brightens through the morning: {:?}", levels);
+        assert_eq!(brightness(300), brightness(DAY_LENGTH - 300), "Evening mirrors morning");
+    }
+
+    #[test]
+    fn test_phases() {
+        assert_eq!(phase(0), (Phase::Night, Phase::Dawn, 240));
+        assert_eq!(phase(300), (Phase::Dawn, Phase::Day, 60));
+        assert_eq!(phase(DAY_LENGTH + 600), (Phase::Day, Phase::Dusk, 240));
+        assert_eq!(phase(900), (Phase::Dusk, Phase::Night, 60));
+        assert_eq!(phase(1000), (Phase::Night, Phase::Dawn, DAY_LENGTH + 240 - 1000), "The night runs on past midnight");
+    }
+
+    #[test]
+    fn test_dim_and_describe() {
+        assert_eq!(dim(0x80FF40, 100), 0x80FF40);
+        assert_eq!(dim(0x80FF40, 50), 0x407F20, "Each channel halves");
+        assert_eq!(dim(0xFFFFFF, 0), 0);
+        assert_eq!(describe(600, None), "Day: light at 100%, dusk in 4 min");
+        assert_eq!(describe(0, Some(80)), "Night: light locked at 80%");
+    }
+}
@@ -0,0 +1,170 @@
+//! Loading the streamed world without freezing the terminal.
+//!
+//! `MapLoader` downloads the sectors around the spawn point on a background
+//! thread and reports each one over a channel, so `main` can keep drawing a
+//! loading panel with a spinner and a sector count. Esc gives up on the
+//! server and plays a local map instead; a cancelled loader's thread stops
+//! at its next report (a request under way still runs to its timeout). A
+//! sector the server turns down as over our budget is tried once more after
+//! the wait it asks for.
+
+use exospace_core::{SPAWN_POSITION, chunk_coords};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::apierror::RATE_LIMIT_BACKOFF;
+use crate::missions::CallFailure;
+use crate::{ChunkCache, Config, Map};
+
+/// Spinner frames, a quarter turn each
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Time per spinner frame
+const SPINNER_STEP: Duration = Duration::from_millis(120);
+
+/// Longest the loader waits out a rate limit before giving up on a sector
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(30);
+
+/// What the loader thread says
+enum LoadEvent {
+    /// About to download sector `done + 1` of `total`
+    Progress { done: usize, total: usize },
+    Done(Result<Box<Map>, String>),
+}
+
+pub struct MapLoader {
+    events: Receiver<LoadEvent>,
+    server_url: String,
+    started: Instant,
+    done: usize,
+    total: usize,
+}
+
+impl MapLoader {
+    /// Start downloading the spawn sector and the eight around it
+    pub fn start(config: &Config, seed: u64, now: Instant) -> Self {
+        let (sender, events) = mpsc::channel();
+        let server_url = config.server_url().to_string();
+        let mut cache = ChunkCache::for_config(config, seed);
+        thread::spawn(move || {
+            let sectors = spawn_sectors();
+            for (i, &(cx, cy)) in sectors.iter().enumerate() {
+                if sender.send(LoadEvent::Progress { done: i, total: sectors.len() }).is_err() {
+                    return;
+                }
+                let mut fetched = cache.fetch(cx, cy);
+                // Over the server's budget: wait as long as it asks (within reason), then try once more
+                if let Err(CallFailure::Refused(error)) = &fetched
+                    && error.is_rate_limited()
+                {
+                    thread::sleep(error.retry_after.unwrap_or(RATE_LIMIT_BACKOFF).min(MAX_RATE_LIMIT_WAIT));
+                    fetched = cache.fetch(cx, cy);
+                }
+                match fetched {
+                    Ok(chunk) => cache.insert(chunk),
+                    // Without the spawn sector there's nothing to fly in; the others load again in flight
+                    Err(e) if i == 0 => {
+                        let _ = sender.send(LoadEvent::Done(Err(e.to_string())));
+                        return;
+                    }
+                    Err(_) => {}
+                }
+            }
+            let _ = sender.send(LoadEvent::Done(Ok(Box::new(Map::streaming(cache, seed)))));
+        });
+        MapLoader { events, server_url, started: now, done: 0, total: spawn_sectors().len() }
+    }
+
+    /// The loaded map (or why it failed) once the thread is finished
+    pub fn poll(&mut self) -> Option<Result<Map, String>> {
+        loop {
+            match self.events.try_recv() {
+                Ok(LoadEvent::Progress { done, total }) => (self.done, self.total) = (done, total),
+                Ok(LoadEvent::Done(result)) => return Some(result.map(|map| *map)),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => return Some(Err("The loader stopped".to_string())),
+            }
+        }
+    }
+
+    /// The loading panel's text
+    pub fn lines(&self, now: Instant) -> Vec<String> {
+        let elapsed = now.saturating_duration_since(self.started);
+        vec![
+            format!("Connecting to {}", self.server_url),
+            String::new(),
+            format!("{} Downloading sector {} of {} ({}s)", spinner(elapsed), self.done + 1, self.total, elapsed.as_secs()),
+            String::new(),
+            "Esc to cancel and play a local map".to_string(),
+        ]
+    }
+}
+
+/// The spawn sector first, then its neighbours
+fn spawn_sectors() -> Vec<(i32, i32)> {
+    let (cx, cy) = chunk_coords(SPAWN_POSITION.0, SPAWN_POSITION.1);
+    let mut sectors = vec![(cx, cy)];
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            if (dx, dy) != (0, 0) {
+                sectors.push((cx + dx, cy + dy));
+            }
+        }
+    }
+    sectors
+}
+
+fn spinner(elapsed: Duration) -> char {
+    SPINNER[(elapsed.as_millis() / SPINNER_STEP.as_millis()) as usize % SPINNER.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait(loader: &mut MapLoader) -> Result<Map, String> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if let Some(result) = loader.poll() {
+                return result;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("The loader never finished");
+    }
+
+    // ==================== Loader Tests ====================
+
+    #[test]
+    fn test_loader_reports_unreachable_server() {
+        let config = Config { server_url: Some("http://127.0.0.1:9".to_string()), ..Default::default() };
+        let now = Instant::now();
+        let mut loader = MapLoader::start(&config, 7, now);
+        assert!(loader.lines(now)[0].ends_with("127.0.0.1:9"));
+        assert!(loader.lines(now)[2].starts_with("| Downloading sector 1 of 9 (0s)"));
+        assert!(wait(&mut loader).is_err(), "No spawn sector, no map");
+    }
+
+    #[test]
+    fn test_loader_fills_the_cache_around_spawn() {
+        let (url, server) = crate::serve_chunks(9);
+        // Tagged chunks would otherwise be kept in the real config directory
+        let config = Config { server_url: Some(url), chunk_cache: Some(false), ..Default::default() };
+        let mut loader = MapLoader::start(&config, 7, Instant::now());
+        let map = wait(&mut loader).expect("Every sector is served");
+        assert_eq!(map.chunks.as_ref().map(|cache| cache.chunks.len()), Some(9));
+        assert_eq!(map.start_position, Some(SPAWN_POSITION));
+        let requests = server.join().unwrap();
+        assert!(requests[0].contains(&format!("cx={}&cy={}", spawn_sectors()[0].0, spawn_sectors()[0].1)), "Spawn first");
+    }
+
+    #[test]
+    fn test_spawn_sectors_and_spinner() {
+        let sectors = spawn_sectors();
+        assert_eq!(sectors.len(), 9);
+        assert_eq!(sectors[0], chunk_coords(SPAWN_POSITION.0, SPAWN_POSITION.1), "The spawn sector comes first");
+        assert_eq!(spinner(Duration::ZERO), '|');
+        assert_eq!(spinner(SPINNER_STEP * 5), '/', "The spinner wraps around");
+    }
+}
@@ -0,0 +1,107 @@
+//! The client's log file.
+//!
+//! Notcurses owns the terminal (and headless, stdout is the transcript), so
+//! the client logs through `tracing` to a file a day in
+//! `~/.config/exospace/logs/`, like `exospace.2026-10-17.log`, keeping the
+//! last `KEPT_LOGS`. `EXOSPACE_LOG` filters it as on the server: `info` by
+//! default, `debug` for more. `/debug log` shows the newest lines in chat.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable holding the log filter
+pub const LOG_ENV: &str = "EXOSPACE_LOG";
+
+const DEFAULT_FILTER: &str = "info";
+const LOG_PREFIX: &str = "exospace";
+const LOG_SUFFIX: &str = "log";
+
+/// Days of logs kept
+const KEPT_LOGS: usize = 7;
+
+/// Lines `/debug log` shows without a count, and the most it shows
+pub const TAIL_LINES: usize = 10;
+pub const MAX_TAIL_LINES: usize = 50;
+
+/// Directory holding the log files
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|mut p| {
+        p.push("exospace");
+        p.push("logs");
+        p
+    })
+}
+
+/// Start logging to today's file; on an error the game runs without a log
+pub fn init() -> Result<(), String> {
+    let dir = dir().ok_or_else(|| "Could not determine config directory".to_string())?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create log directory: {}", e))?;
+    let file = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_PREFIX)
+        .filename_suffix(LOG_SUFFIX)
+        .max_log_files(KEPT_LOGS)
+        .build(&dir)
+        .map_err(|e| format!("Failed to open log file: {}", e))?;
+    let (filter, problem) = match std::env::var(LOG_ENV) {
+        Ok(value) => match EnvFilter::try_new(value.trim()) {
+            Ok(filter) => (filter, None),
+            Err(e) => (EnvFilter::new(DEFAULT_FILTER), Some(format!("Ignoring {}: '{}' is not a log filter ({})", LOG_ENV, value, e))),
+        },
+        Err(_) => (EnvFilter::new(DEFAULT_FILTER), None),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(file)
+        .with_ansi(false)
+        .try_init()
+        .map_err(|e| format!("Failed to start logging: {}", e))?;
+    if let Some(problem) = problem {
+        tracing::warn!("{}", problem);
+    }
+    Ok(())
+}
+
+/// The last `lines` lines of the newest log
+pub fn tail(lines: usize) -> Result<Vec<String>, String> {
+    let dir = dir().ok_or_else(|| "Could not determine config directory".to_string())?;
+    tail_in(&dir, lines)
+}
+
+pub fn tail_in(dir: &Path, lines: usize) -> Result<Vec<String>, String> {
+    // Dated names sort oldest to newest
+    let newest = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with(LOG_PREFIX) && name.ends_with(LOG_SUFFIX)))
+        .max()
+        .ok_or_else(|| "Nothing logged yet".to_string())?;
+    let text = fs::read_to_string(&newest).map_err(|e| format!("Failed to read {}: {}", newest.display(), e))?;
+    let all: Vec<&str> = text.lines().filter(|line| !line.is_empty()).collect();
+    Ok(all[all.len().saturating_sub(lines)..].iter().map(|line| line.to_string()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Log Tail Tests ====================
+
+    #[test]
+    fn test_tail_reads_the_newest_log() {
+        let dir = std::env::temp_dir().join(format!("exospace-logs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(tail_in(&dir, 5), Err("Nothing logged yet".to_string()));
+
+        fs::write(dir.join("exospace.2026-10-16.log"), "old\n").unwrap();
+        fs::write(dir.join("exospace.2026-10-17.log"), "one\ntwo\nthree\n").unwrap();
+        fs::write(dir.join("notes.txt"), "not a log\n").unwrap();
+        assert_eq!(tail_in(&dir, 2).unwrap(), vec!["two", "three"], "Only the newest file's last lines");
+        assert_eq!(tail_in(&dir, 10).unwrap().len(), 3, "A short log comes back whole");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,26 +1,272 @@
+mod account;
+mod apierror;
+mod bubbles;
+mod camera;
+mod charset;
+mod chatlog;
+mod chunkstore;
+mod cli;
+mod clock;
+mod combat;
+mod community;
+mod connection;
+mod difficulty;
+mod docking;
+mod edits;
+mod events;
+mod fog;
+mod gates;
+mod headless;
+mod history;
+mod hud;
+mod hull;
+mod keys;
+mod leaderboard;
+mod light;
+mod loading;
+mod logging;
+mod menu;
+mod mining;
+mod missions;
+mod nav;
+mod net;
+mod netstats;
+mod perf;
+mod poi;
+mod radar;
+mod regions;
+mod saves;
+mod scripting;
+mod screens;
+mod screenshot;
+mod session;
+mod settings;
+mod ships;
+mod stats;
+mod theme;
+mod title;
+mod waypoints;
+
+use exospace_core::ecs::{Entity, Impact, Position, Sprite, SpriteKind};
+use exospace_core::regions::Biome;
+use exospace_core::{
+    chunk_coords, chunk_local, hash_position, station_id, ChunkData, CHUNK_SIZE, ClientMessage, DailySeed, Direction, DistanceTarget, NpcBehavior,
+    Balance, MapData, MissionBoard, MissionReward, PlayerInfo, Poi, PoiKind, RepairReceipt, ShipLivery, ShipPart, Tile, TileChange,
+    TransactionHistory, Leaderboard, LeaderboardMetric, SystemInfo, EventKind,
+    BINARY_CONTENT_TYPE, MAX_DICE, MAX_DIE_SIDES, SPAWN_POSITION, MapDiff, WORLD_VERSION_HEADER,
+};
+use account::AccountConfig;
+use apierror::{RATE_LIMIT_BACKOFF, ServerError};
+use bubbles::{Bubbles, Speaker, EMOTE_STYLE, SAY_STYLE};
+use camera::{Camera, CameraFeel};
+use chunkstore::ChunkStore;
+use charset::Charset;
+use chatlog::{ChatLog, TIME_WIDTH};
+use clock::{FixedStep, GameClock, RealClock, ANIMATION_STEP, FRAME_SLEEP, SHIP_TICK};
+use connection::{Connection, LinkEvent, LinkState};
+use cli::Cli;
+use combat::{Guns, TRAIL_STYLES};
+use community::CommunityScreen;
+use difficulty::Difficulty;
+use docking::{DockedScreen, MarketCall, MarketReply, DOCKING_RANGE};
+use edits::{EditOutcome, TileEdits};
+use fog::{ExploredTiles, Sensors};
+use events::ActiveEvents;
+use gates::WarpScreen;
+use headless::{Input, Script, HEADLESS_SIZE};
+use history::InputHistory;
+use hud::{content_key, Hud, Layout, Rect, Widget};
+use keys::{Action, InputProfile, KeyMap, KeySpec};
+use leaderboard::LeaderboardScreen;
+use loading::MapLoader;
+use mining::{MineEvent, Miner, Resource};
+use missions::{CallFailure, MissionLog};
+use nav::{Autopilot, NavStep, MAX_NAV_DISTANCE, SEARCH_MARGIN};
 use libnotcurses_sys::*;
+use net::{NetClient, NetEvent, RemotePlayers, UtilityKind, describe_content};
+use netstats::NetDiagnostics;
+use perf::{FramePacer, PerfStats};
+use poi::KnownPois;
+use radar::{Radar, BLIP};
+use regions::{KnownRegions, RegionBanner};
+use screenshot::{Canvas, Surface, Tee};
+use scripting::Scripts;
+use screens::{Backdrop, GameOverScreen, Key, KeyPress, Screen, ScreenContext, ScreenKind, ScreenManager, ShipMenuScreen};
 use serde::{Deserialize, Serialize};
+use session::Session;
+use settings::SettingsScreen;
+use theme::Theme;
+use title::TitleScreen;
+use waypoints::{Bookmarks, Waypoint, ARRIVAL_DISTANCE, WAYPOINT_STYLE};
+use hull::ShipStatus;
+use ships::ShipClass;
+use stats::StatsScreen;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
+use std::ops::Range;
 use std::path::PathBuf;
-use std::time::{Duration, Instant};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Server URL for map fetching
 const SERVER_URL: &str = "http://localhost:3000";
 
+/// World seed; matches the server's default so `/map` and `/map/chunk` agree
+const WORLD_SEED: u64 = 12345;
+
+/// Size of the locally generated map as (width, height) unless `--width`/`--height` say otherwise
+const LOCAL_MAP_SIZE: (usize, usize) = (500, 200);
+
+/// How often new exploration is written to disk while playing
+const EXPLORED_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Name used in multiplayer when none is configured
+const DEFAULT_PLAYER_NAME: &str = "Pilot";
+
+/// Chat pane message lines when none are configured, and the most allowed
+const DEFAULT_CHAT_LINES: usize = 3;
+
+/// Messages kept for the chat log, oldest dropped first
+const MAX_CHAT_MESSAGES: usize = 500;
+const MAX_CHAT_LINES: usize = 20;
+
+/// Held-key timeout when none is configured, in milliseconds
+const DEFAULT_KEY_TIMEOUT_MS: u64 = 300;
+
+/// Game rows kept on screen however big the chat pane gets
+const MIN_GAME_ROWS: u32 = 8;
+
+/// Transactions `/wallet` lists, newest first
+const WALLET_HISTORY: usize = 5;
+
 /// User configuration
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
 struct Config {
     /// Enable background visual effects (stars, nebula animations, etc.)
+    /// Off by default for performance on slow terminals
     effects_enabled: bool,
     /// Server URL override
     server_url: Option<String>,
+    /// Name shown to other players
+    player_name: Option<String>,
+    /// Slash commands run at startup, validated like typed commands
+    autoexec: Vec<String>,
+    /// Chat colors per channel as "#RRGGBB", replacing the built-in ones
+    chat_colors: HashMap<ChatChannel, String>,
+    /// Channels hidden from the chat pane
+    muted_channels: Vec<ChatChannel>,
+    /// Game keys that differ from the defaults, as action name → key spec (see `keys.rs`)
+    keybindings: HashMap<Action, String>,
+    /// Movement keys added alongside the arrows: WASD or vi's hjkl/yubn
+    input_profile: InputProfile,
+    /// Message lines in the normal-size chat pane
+    chat_lines: Option<usize>,
+    /// How the ship accelerates and drifts
+    physics: ShipPhysics,
+    /// Player account to log in with; guests leave it out
+    account: Option<AccountConfig>,
+    /// Difficulty of each solo world, by `Map::world_key()`
+    solo_difficulty: HashMap<String, Difficulty>,
+    /// Bookmarked positions in each world, by `Map::world_key()`
+    bookmarks: HashMap<String, Bookmarks>,
+    /// Ship flown; the ship menu opens at startup until one is picked
+    ship_class: Option<ShipClass>,
+    /// The ship's own name, shown to other pilots
+    ship_name: Option<String>,
+    /// Short tag shown before our name above the ship, e.g. "ACE-1"
+    callsign: Option<String>,
+    /// Paint per ship part as "#RRGGBB", over the theme's ship colors
+    ship_colors: HashMap<ShipPart, String>,
+    /// Movement tick length in ms, overriding the ship class's
+    move_delay_ms: Option<u64>,
+    /// How long a held movement key lasts without a repeat, in ms (terminals without release events)
+    key_timeout_ms: Option<u64>,
+    /// Frames drawn per second at most
+    target_fps: Option<u32>,
+    /// Color theme: a preset or a file in the themes directory (see `theme.rs`)
+    theme: Option<String>,
+    /// Unicode or ASCII glyphs; left out, picked from what the terminal supports
+    charset: Option<Charset>,
+    /// Ask the server to gzip or zstd map downloads (default: on)
+    compression: Option<bool>,
+    /// Keep streamed chunks on disk and only revalidate them next time (default: on; see `chunkstore.rs`)
+    chunk_cache: Option<bool>,
+    /// Map brightness in percent, held there instead of following the day/night cycle (see `light.rs`)
+    light_level: Option<u32>,
+    /// How long the view takes to catch up with the ship, in ms; 0 locks it on (see `camera.rs`)
+    camera_smoothing_ms: Option<u64>,
+    /// Cells the ship can move from the middle of the view before it scrolls
+    camera_deadzone: Option<u32>,
+    /// Cells the view leads the ship the way it faces
+    camera_lookahead: Option<u32>,
+    /// File this config was loaded from and saves to (`--config`, else the default path)
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// `--server`, used instead of `server_url` for this run but never saved
+    #[serde(skip)]
+    server_override: Option<String>,
+}
+
+/// Momentum movement tuning; speeds are in tiles per movement tick (33ms)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+struct ShipPhysics {
+    /// Velocity gained per tick while a movement key is held
+    thrust: f32,
+    /// Fraction of velocity lost per tick in open space
+    drag: f32,
+    /// Extra drag while inside a nebula
+    nebula_drag: f32,
+    /// Top speed
+    max_speed: f32,
+    /// Whether diagonal moves are as fast as straight ones
+    diagonals: Diagonals,
+}
+
+/// How fast the ship and the autopilot fly diagonally
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Diagonals {
+    /// The same speed in every direction
+    #[default]
+    Uniform,
+    /// Top speed along both axes at once, about 1.41x as fast as a straight line
+    Classic,
 }
 
-impl Default for Config {
+impl Default for ShipPhysics {
     fn default() -> Self {
-        Config {
-            effects_enabled: false,  // Off by default
-            server_url: None,
+        ShipPhysics {
+            thrust: 0.2,
+            drag: 0.12,
+            nebula_drag: 0.2,
+            max_speed: 1.0,
+            diagonals: Diagonals::Uniform,
+        }
+    }
+}
+
+/// Thrust the config may set, per tick; with none the ship couldn't move
+const THRUST_LIMITS: (f32, f32) = (0.01, 1.0);
+
+/// Drag the config may set, as a fraction of speed lost per tick
+const DRAG_LIMITS: (f32, f32) = (0.0, 1.0);
+
+/// Top speeds the config may set, per tick; past a few tiles a tick the server pulls the ship back
+const MAX_SPEED_LIMITS: (f32, f32) = (0.05, 3.0);
+
+impl ShipPhysics {
+    /// Each constant within its limits, so a hand-edited config can't stall the ship or fling it off
+    fn clamped(self) -> Self {
+        let limit = |value: f32, (low, high): (f32, f32)| value.clamp(low, high);
+        ShipPhysics {
+            thrust: limit(self.thrust, THRUST_LIMITS),
+            drag: limit(self.drag, DRAG_LIMITS),
+            nebula_drag: limit(self.nebula_drag, DRAG_LIMITS),
+            max_speed: limit(self.max_speed, MAX_SPEED_LIMITS),
+            diagonals: self.diagonals,
         }
     }
 }
@@ -35,13 +281,13 @@ impl Config {
         })
     }
 
-    /// Load config from file, or return default if not found
-    fn load() -> Self {
-        let Some(path) = Self::config_path() else {
+    /// Load config from the given file or the default one, or return default if not found
+    fn load(path: Option<PathBuf>) -> Self {
+        let Some(path) = path.or_else(Self::config_path) else {
             return Self::default();
         };
 
-        match fs::read_to_string(&path) {
+        let mut config: Config = match fs::read_to_string(&path) {
             Ok(contents) => {
                 serde_json::from_str(&contents).unwrap_or_else(|e| {
                     eprintln!("Warning: Failed to parse config: {}", e);
@@ -49,12 +295,18 @@ impl Config {
                 })
             }
             Err(_) => Self::default(),
-        }
+        };
+        // A hand-edited file can't make the ship stand still or skip tiles
+        let (low, high) = settings::MOVE_DELAY_LIMITS;
+        config.move_delay_ms = config.move_delay_ms.map(|ms| ms.clamp(low, high));
+        config.physics = config.physics.clamped();
+        config.path = Some(path);
+        config
     }
 
-    /// Save config to file
+    /// Save config to the file it came from
     fn save(&self) -> Result<(), String> {
-        let path = Self::config_path()
+        let path = self.path.clone().or_else(Self::config_path)
             .ok_or_else(|| "Could not determine config directory".to_string())?;
 
         // Create parent directories if needed
@@ -72,141 +324,548 @@ impl Config {
         Ok(())
     }
 
-    /// Get the server URL (config override or default)
+    /// Get the server URL (`--server`, then config override, then default)
     fn server_url(&self) -> &str {
-        self.server_url.as_deref().unwrap_or(SERVER_URL)
+        self.server_override.as_deref().or(self.server_url.as_deref()).unwrap_or(SERVER_URL)
+    }
+
+    /// Get the multiplayer name (config override or default)
+    fn player_name(&self) -> &str {
+        self.player_name.as_deref().unwrap_or(DEFAULT_PLAYER_NAME)
+    }
+
+    /// Token from the last successful login, sent with every server request
+    fn auth_token(&self) -> Option<&str> {
+        self.account.as_ref().and_then(|a| a.token.as_deref())
+    }
+
+    /// Difficulty of a solo world; normal until one is picked
+    fn difficulty_for(&self, world_key: &str) -> Difficulty {
+        self.solo_difficulty.get(world_key).copied().unwrap_or_default()
+    }
+
+    /// Get the chat pane height in message lines (config override or default, within limits)
+    fn chat_lines(&self) -> usize {
+        self.chat_lines.unwrap_or(DEFAULT_CHAT_LINES).clamp(1, MAX_CHAT_LINES)
+    }
+
+    /// Movement tick length (config override, else the ship's own); `load()` keeps the override within `MOVE_DELAY_LIMITS`
+    fn move_delay(&self, ship: Duration) -> Duration {
+        self.move_delay_ms.map_or(ship, Duration::from_millis)
+    }
+
+    /// Name of the color theme (config override or default)
+    fn theme_name(&self) -> &str {
+        self.theme.as_deref().unwrap_or(theme::DEFAULT_THEME)
+    }
+
+    /// Whether map downloads may come compressed
+    fn compression(&self) -> bool {
+        self.compression.unwrap_or(true)
+    }
+
+    /// Whether streamed chunks are kept on disk
+    fn chunk_cache(&self) -> bool {
+        self.chunk_cache.unwrap_or(true)
+    }
+
+    /// Frame rate to pace the main loop at (config override or default, within limits)
+    fn target_fps(&self) -> u32 {
+        self.target_fps.unwrap_or(perf::DEFAULT_TARGET_FPS).clamp(perf::FPS_LIMITS.0, perf::FPS_LIMITS.1)
+    }
+
+    /// Held-key timeout (config override or default)
+    fn key_timeout(&self) -> Duration {
+        Duration::from_millis(self.key_timeout_ms.unwrap_or(DEFAULT_KEY_TIMEOUT_MS))
+    }
+
+    /// Locked map brightness, within limits; None follows the day/night cycle
+    fn light_level(&self) -> Option<u32> {
+        self.light_level.map(|level| level.clamp(light::LEVEL_LIMITS.0, light::LEVEL_LIMITS.1))
+    }
+
+    /// How the view follows the ship, within limits
+    fn camera_feel(&self) -> CameraFeel {
+        let smoothing = self.camera_smoothing_ms.map_or(camera::DEFAULT_SMOOTHING_MS, |ms| ms.min(camera::SMOOTHING_LIMITS.1));
+        CameraFeel {
+            smoothing: Duration::from_millis(smoothing),
+            deadzone: self.camera_deadzone.unwrap_or(0).min(camera::DEADZONE_LIMITS.1),
+            lookahead: self.camera_lookahead.unwrap_or(0).min(camera::LOOKAHEAD_LIMITS.1),
+        }
+    }
+
+    /// Ship name, callsign and paint as shown to everyone; bad entries are left out and reported
+    fn livery(&self) -> (ShipLivery, Vec<String>) {
+        let mut errors = Vec::new();
+        let mut check = |what: &str, result: Result<String, String>| {
+            result.map_err(|e| errors.push(format!("Bad {}: {}", what, e))).ok()
+        };
+        let name = self.ship_name.as_deref().and_then(|name| check("ship_name", ShipLivery::check_name(name)));
+        let callsign = self.callsign.as_deref().and_then(|callsign| check("callsign", ShipLivery::check_callsign(callsign)));
+        let mut colors: Vec<_> = self.ship_colors.iter().collect();
+        colors.sort();
+        let colors = colors
+            .into_iter()
+            .filter_map(|(&part, text)| match parse_hex_color(text) {
+                Some(color) => Some((part, color)),
+                None => {
+                    errors.push(format!("Bad ship color for {}: {} (use #RRGGBB)", part.name(), text));
+                    None
+                }
+            })
+            .collect();
+        (ShipLivery { name, callsign, colors }, errors)
     }
 }
 
-/// Tile types in the map
-#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
-enum Tile {
-    Wall,
-    Floor,
-    Asteroid,
-    Nebula,
+/// A server for `count` chunk requests, answering each with a plain binary chunk of floor at world
+/// version 4 tagged `W/"floor"` (as a server without compression would), or a 304 when the request
+/// has that tag; the thread returns the requests, lowercased
+#[cfg(test)]
+fn serve_chunks(count: usize) -> (String, std::thread::JoinHandle<Vec<String>>) {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for stream in listener.incoming().take(count) {
+            let mut stream = stream.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let request = String::from_utf8_lossy(&request).to_lowercase();
+            let (cx, cy) = ["cx=", "cy="].map(|key| {
+                let value = request.split(key).nth(1).and_then(|rest| rest.split('&').next()).unwrap_or("0");
+                value.parse().unwrap_or(0)
+            }).into();
+            let size = exospace_core::CHUNK_SIZE;
+            let mut body = ChunkData { cx, cy, tiles: vec![Tile::Floor; (size * size) as usize], pois: Vec::new(), regions: Vec::new() }.to_bytes();
+            let status = if request.contains("if-none-match: w/\"floor\"") {
+                body.clear();
+                "304 Not Modified"
+            } else {
+                "200 OK"
+            };
+            let head = format!(
+                "HTTP/1.1 {}\r\nContent-Type: {}\r\nETag: W/\"floor\"\r\n{}: 4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status, BINARY_CONTENT_TYPE, WORLD_VERSION_HEADER, body.len()
+            );
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+            requests.push(request);
+        }
+        requests
+    });
+    (url, server)
 }
 
-/// Map data received from server
-#[derive(Deserialize)]
-struct MapData {
-    tiles: Vec<Vec<Tile>>,
-    width: usize,
-    height: usize,
-    start_x: i32,
-    start_y: i32,
+/// A server answering one request with each of `replies` as JSON, in order; the thread returns
+/// the requests' first lines, lowercased
+#[cfg(test)]
+fn serve_json(replies: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for (reply, stream) in replies.into_iter().zip(listener.incoming()) {
+            let mut stream = stream.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let head = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", reply.len());
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(reply.as_bytes()).unwrap();
+            requests.push(String::from_utf8_lossy(&request).lines().next().unwrap_or_default().to_lowercase());
+        }
+        requests
+    });
+    (url, server)
 }
 
-impl Tile {
-    fn is_passable(&self) -> bool {
-        matches!(self, Tile::Floor | Tile::Nebula)
-    }
+/// Most chunks kept in memory before the farthest ones are dropped
+const MAX_CACHED_CHUNKS: usize = 64;
+
+/// How long to wait before retrying a chunk that failed to download
+const CHUNK_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Pages of `/map/diff` read catching up before fetching the chunks again is quicker
+const MAX_DIFF_PAGES: usize = 8;
+
+/// Chunks of the server's unbounded world, fetched on demand
+struct ChunkCache {
+    server_url: String,
+    seed: u64,
+    client: reqwest::blocking::Client,
+    chunks: HashMap<(i32, i32), ChunkData>,
+    /// Chunks whose last download failed, and when
+    failed: HashMap<(i32, i32), Instant>,
+    /// No chunks are asked for before this, after the server said we were over our budget
+    paused_until: Option<Instant>,
+    /// Our tile changes, laid over chunks as they arrive
+    edits: TileEdits,
+    /// Points of interest from every chunk fetched, kept when the chunk is evicted
+    pois: KnownPois,
+    /// Regions named by every chunk fetched, kept the same way
+    regions: KnownRegions,
+    /// The world version every loaded chunk is at least as current as; none until the server says
+    /// (servers that predate `/map/diff`, and other systems' seeds, which never change)
+    version: Option<u64>,
+    /// Chunks kept on disk from earlier runs, revalidated instead of downloaded
+    store: ChunkStore,
 }
 
-/// 8-directional orientation
-#[derive(Clone, Copy, PartialEq, Debug, Default)]
-enum Direction {
-    #[default]
-    Up,
-    UpRight,
-    Right,
-    DownRight,
-    Down,
-    DownLeft,
-    Left,
-    UpLeft,
-}
-
-impl Direction {
-    fn from_delta(dx: i32, dy: i32) -> Option<Direction> {
-        match (dx, dy) {
-            (0, -1) => Some(Direction::Up),
-            (1, -1) => Some(Direction::UpRight),
-            (1, 0) => Some(Direction::Right),
-            (1, 1) => Some(Direction::DownRight),
-            (0, 1) => Some(Direction::Down),
-            (-1, 1) => Some(Direction::DownLeft),
-            (-1, 0) => Some(Direction::Left),
-            (-1, -1) => Some(Direction::UpLeft),
-            _ => None,
-        }
-    }
-
-    fn to_char(self) -> char {
-        match self {
-            Direction::Up => '↑',
-            Direction::UpRight => '↗',
-            Direction::Right => '→',
-            Direction::DownRight => '↘',
-            Direction::Down => '↓',
-            Direction::DownLeft => '↙',
-            Direction::Left => '←',
-            Direction::UpLeft => '↖',
+impl ChunkCache {
+    /// With `compression`, chunks may arrive gzip- or zstd-encoded and are unpacked on the way in;
+    /// servers without compression just send them plain
+    fn new(server_url: &str, token: Option<&str>, seed: u64, compression: bool) -> Self {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(value) = token.and_then(|t| reqwest::header::HeaderValue::from_str(&account::bearer(t)).ok()) {
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(2))
+            .default_headers(headers)
+            .gzip(compression)
+            .zstd(compression)
+            .build()
+            .unwrap_or_default();
+        ChunkCache {
+            server_url: server_url.to_string(),
+            seed,
+            client,
+            chunks: HashMap::new(),
+            failed: HashMap::new(),
+            paused_until: None,
+            edits: TileEdits::default(),
+            pois: KnownPois::default(),
+            regions: KnownRegions::default(),
+            version: None,
+            store: ChunkStore::default(),
         }
     }
 
-    fn name(self) -> &'static str {
-        match self {
-            Direction::Up => "N",
-            Direction::UpRight => "NE",
-            Direction::Right => "E",
-            Direction::DownRight => "SE",
-            Direction::Down => "S",
-            Direction::DownLeft => "SW",
-            Direction::Left => "W",
-            Direction::UpLeft => "NW",
+    /// The cache for the configured server and account, keeping chunks on disk unless that's turned off
+    fn for_config(config: &Config, seed: u64) -> Self {
+        let mut cache = Self::new(config.server_url(), config.auth_token(), seed, config.compression());
+        if config.chunk_cache() {
+            cache.store = ChunkStore::for_server(config.server_url(), seed);
         }
+        cache
+    }
+
+    /// Download a single chunk, preferring the compact binary encoding, and note the world version it's current as of.
+    /// A chunk kept on disk is only downloaded again if the server's copy has changed
+    fn fetch(&mut self, cx: i32, cy: i32) -> Result<ChunkData, CallFailure> {
+        let url = format!("{}/map/chunk?cx={}&cy={}&seed={}&format=bin", self.server_url, cx, cy, self.seed);
+
+        let kept = self.store.load(cx, cy);
+        let mut request = self.client.get(&url);
+        if let Some((tag, _)) = &kept {
+            request = request.header(reqwest::header::IF_NONE_MATCH, tag.as_str());
+        }
+        let response = request.send()
+            .map_err(|e| CallFailure::Unreachable(format!("Failed to connect to server: {}", e)))?;
+
+        let unchanged = response.status() == reqwest::StatusCode::NOT_MODIFIED;
+        if !response.status().is_success() && !unchanged {
+            return Err(CallFailure::Refused(ServerError::from_response(response)));
+        }
+
+        // Servers that predate the binary format ignore the parameter and send JSON
+        let is_binary = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.starts_with(BINARY_CONTENT_TYPE));
+        let version = response
+            .headers()
+            .get(WORLD_VERSION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        let tag = response.headers().get(reqwest::header::ETAG).and_then(|v| v.to_str().ok()).map(str::to_string);
+
+        let chunk = match kept {
+            Some((_, bytes)) if unchanged => ChunkData::from_bytes(&bytes).map_err(|e| {
+                self.store.remove(cx, cy);
+                CallFailure::Unreachable(format!("Failed to decode kept chunk: {}", e))
+            })?,
+            _ if is_binary => {
+                let bytes = response.bytes()
+                    .map_err(|e| CallFailure::Unreachable(format!("Failed to read chunk data: {}", e)))?;
+                let chunk = ChunkData::from_bytes(&bytes)
+                    .map_err(|e| CallFailure::Unreachable(format!("Failed to decode chunk data: {}", e)))?;
+                if let Some(tag) = &tag {
+                    self.store.save(cx, cy, tag, &bytes);
+                }
+                chunk
+            }
+            _ => response
+                .json()
+                .map_err(|e| CallFailure::Unreachable(format!("Failed to parse chunk data: {}", e)))?,
+        };
+        // Chunks fetched since the last catch-up are each at least as current as the one before, so the oldest counts
+        if let Some(version) = version {
+            self.version = Some(self.version.map_or(version, |known| known.min(version)));
+        }
+        Ok(chunk)
+    }
+
+    /// The world's tile changes since `since`, a page at a time
+    fn fetch_diff(&self, since: u64) -> Result<MapDiff, CallFailure> {
+        let url = format!("{}/map/diff?since={}", self.server_url, since);
+        let response = self.client.get(&url).send().map_err(|e| CallFailure::Unreachable(format!("Failed to connect to server: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(CallFailure::Refused(ServerError::from_response(response)));
+        }
+        response.json().map_err(|e| CallFailure::Unreachable(format!("Failed to parse map diff: {}", e)))
+    }
+
+    /// Bring the loaded chunks up to date with the server's tile changes since they were fetched; false when
+    /// that can't be done (no version known, a server without `/map/diff`, or one whose world started over)
+    /// and the chunks should be fetched again instead. Tiles with edits of ours waiting to be sent keep them
+    fn catch_up(&mut self) -> bool {
+        let Some(mut since) = self.version else {
+            return false;
+        };
+        for _ in 0..MAX_DIFF_PAGES {
+            let Ok(diff) = self.fetch_diff(since) else {
+                return false;
+            };
+            if diff.version < since {
+                return false;
+            }
+            for change in diff.tiles {
+                if !self.edits.pending(change.x, change.y)
+                    && let Some(slot) = self.get_mut(change.x, change.y)
+                {
+                    *slot = change.tile;
+                }
+            }
+            since = diff.version;
+            self.version = Some(since);
+            if !diff.more {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Report a tile change; only breaking a tile the way `Tile::broken()` allows is accepted.
+    /// A refusal comes with the server's reason
+    fn send_edit(&self, x: i32, y: i32, tile: Tile) -> (EditOutcome, Option<ServerError>) {
+        let url = format!("{}/tile/update", self.server_url);
+        match self.client.post(&url).json(&TileChange { x, y, tile }).send() {
+            Ok(response) if response.status().is_success() => (EditOutcome::Accepted, None),
+            Ok(response) if response.status().is_client_error() => (EditOutcome::Refused, Some(ServerError::from_response(response))),
+            Ok(_) | Err(_) => (EditOutcome::Failed, None),
+        }
+    }
+
+    /// Fetch a station's market, place an order there or pay for repairs; errors carry the server's reason
+    fn market_call(&self, station: &str, call: &MarketCall) -> Result<MarketReply, String> {
+        let url = format!("{}/station/{}", self.server_url, station_id(station));
+        let request = match call {
+            MarketCall::Open => self.client.get(format!("{}/market", url)),
+            MarketCall::Trade(order) => self.client.post(format!("{}/market", url)).json(order),
+            MarketCall::Repair(order) => self.client.post(format!("{}/repair", url)).json(order),
+        };
+        let response = request.send().map_err(|e| format!("Failed to connect to server: {}", e))?;
+        if !response.status().is_success() {
+            return Err(ServerError::from_response(response).to_string());
+        }
+        match call {
+            MarketCall::Open => response.json().map(MarketReply::Opened),
+            MarketCall::Trade(_) => response.json().map(MarketReply::Traded),
+            MarketCall::Repair(_) => response.json().map(MarketReply::Repaired),
+        }
+        .map_err(|e| format!("Failed to parse market: {}", e))
+    }
+
+    /// Fetch the server's mission board
+    fn fetch_missions(&self) -> Result<MissionBoard, CallFailure> {
+        let url = format!("{}/missions", self.server_url);
+        let response = self.client.get(&url).send().map_err(|e| CallFailure::Unreachable(format!("Failed to connect to server: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(CallFailure::Refused(ServerError::from_response(response)));
+        }
+        response.json().map_err(|e| CallFailure::Unreachable(format!("Failed to parse missions: {}", e)))
+    }
+
+    /// Sign the account up for a mission on the board
+    fn accept_mission(&self, id: &str) -> Result<(), CallFailure> {
+        let url = format!("{}/missions/{}/accept", self.server_url, id);
+        let response = self.client.post(&url).send().map_err(|e| CallFailure::Unreachable(format!("Failed to connect to server: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(CallFailure::Refused(ServerError::from_response(response)));
+        }
+        Ok(())
+    }
+
+    /// Hand in a finished mission; the server checks its own count and answers with the reward
+    fn complete_mission(&self, id: &str) -> Result<MissionReward, CallFailure> {
+        let url = format!("{}/missions/{}/complete", self.server_url, id);
+        let response = self.client.post(&url).send()
+            .map_err(|e| CallFailure::Unreachable(format!("Failed to connect to server: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(CallFailure::Refused(ServerError::from_response(response)));
+        }
+        response.json().map_err(|e| CallFailure::Unreachable(format!("Failed to parse reward: {}", e)))
+    }
+
+    /// The account's credits, as the server keeps them
+    fn fetch_balance(&self) -> Result<Balance, CallFailure> {
+        let url = format!("{}/player/balance", self.server_url);
+        let response = self.client.get(&url).send().map_err(|e| CallFailure::Unreachable(format!("Failed to connect to server: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(CallFailure::Refused(ServerError::from_response(response)));
+        }
+        response.json().map_err(|e| CallFailure::Unreachable(format!("Failed to parse balance: {}", e)))
+    }
+
+    /// The account's latest transactions, newest first
+    fn fetch_transactions(&self) -> Result<TransactionHistory, CallFailure> {
+        let url = format!("{}/player/transactions", self.server_url);
+        let response = self.client.get(&url).send().map_err(|e| CallFailure::Unreachable(format!("Failed to connect to server: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(CallFailure::Refused(ServerError::from_response(response)));
+        }
+        response.json().map_err(|e| CallFailure::Unreachable(format!("Failed to parse transactions: {}", e)))
+    }
+
+    fn insert(&mut self, mut chunk: ChunkData) {
+        self.edits.apply(&mut chunk);
+        self.pois.add(&chunk.pois);
+        self.regions.add(&chunk.regions);
+        self.failed.remove(&(chunk.cx, chunk.cy));
+        self.chunks.insert((chunk.cx, chunk.cy), chunk);
+    }
+
+    /// Tile at a world position, if its chunk is loaded
+    fn get(&self, x: i32, y: i32) -> Option<Tile> {
+        let chunk = self.chunks.get(&chunk_coords(x, y))?;
+        let (local_x, local_y) = chunk_local(x, y);
+        chunk.get(local_x, local_y)
     }
-}
 
-/// Simple deterministic hash for consistent random-looking values
-fn hash_position(x: i32, y: i32, seed: u32) -> u32 {
-    let mut h = (x as u32).wrapping_mul(374761393);
-    h = h.wrapping_add((y as u32).wrapping_mul(668265263));
-    h = h.wrapping_add(seed.wrapping_mul(1013904223));
-    h ^= h >> 13;
-    h = h.wrapping_mul(1274126177);
-    h ^= h >> 16;
-    h
+    fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut Tile> {
+        let chunk = self.chunks.get_mut(&chunk_coords(x, y))?;
+        let (local_x, local_y) = chunk_local(x, y);
+        chunk.get_mut(local_x, local_y)
+    }
+
+    /// Fetch any missing chunks overlapping the rectangle centered on (x, y)
+    fn load_area(&mut self, x: i32, y: i32, radius_x: i32, radius_y: i32) {
+        if self.paused_until.is_some_and(|until| Instant::now() < until) {
+            return;
+        }
+        let (min_cx, min_cy) = chunk_coords(x - radius_x, y - radius_y);
+        let (max_cx, max_cy) = chunk_coords(x + radius_x, y + radius_y);
+
+        for cy in min_cy..=max_cy {
+            for cx in min_cx..=max_cx {
+                if self.chunks.contains_key(&(cx, cy)) {
+                    continue;
+                }
+                if let Some(failed_at) = self.failed.get(&(cx, cy))
+                    && failed_at.elapsed() < CHUNK_RETRY_DELAY
+                {
+                    continue;
+                }
+                match self.fetch(cx, cy) {
+                    Ok(chunk) => self.insert(chunk),
+                    // Over the server's budget: stop asking until it says there's room
+                    Err(CallFailure::Refused(error)) if error.is_rate_limited() => {
+                        self.paused_until = Some(Instant::now() + error.retry_after.unwrap_or(RATE_LIMIT_BACKOFF));
+                        return;
+                    }
+                    Err(_) => {
+                        self.failed.insert((cx, cy), Instant::now());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drop the chunks farthest from (x, y) once the cache is over its limit
+    fn evict_distant(&mut self, x: i32, y: i32) {
+        if self.chunks.len() <= MAX_CACHED_CHUNKS {
+            return;
+        }
+        let (pcx, pcy) = chunk_coords(x, y);
+        let mut by_distance: Vec<(i32, (i32, i32))> = self
+            .chunks
+            .keys()
+            .map(|&(cx, cy)| ((cx - pcx).abs().max((cy - pcy).abs()), (cx, cy)))
+            .collect();
+        by_distance.sort_unstable();
+        for (_, key) in by_distance.drain(MAX_CACHED_CHUNKS..) {
+            self.chunks.remove(&key);
+        }
+    }
 }
 
 /// The game map
 struct Map {
-    tiles: Vec<Vec<Tile>>,
+    /// Row-major, `width * height` tiles
+    tiles: Vec<Tile>,
     width: usize,
     height: usize,
     start_position: Option<(i32, i32)>,
+    /// Streamed world from the server; when set, `tiles` is unused
+    chunks: Option<ChunkCache>,
+    seed: u64,
+    /// Fog of war: tiles the player has seen
+    explored: ExploredTiles,
+    /// A local map's points of interest; streamed ones are in the chunk cache
+    pois: KnownPois,
+    /// A local map's regions, likewise
+    regions: KnownRegions,
+    /// The server's meteor showers and ion storms under way; none on local maps
+    events: ActiveEvents,
 }
 
 impl Map {
-    /// Fetch map from the server
-    fn fetch_from_server(config: &Config) -> Result<Self, String> {
-        let url = format!("{}/map", config.server_url());
-
-        let response = reqwest::blocking::get(&url)
-            .map_err(|e| format!("Failed to connect to server: {}", e))?;
-
-        if !response.status().is_success() {
-            return Err(format!("Server returned error: {}", response.status()));
+    /// Stream the world from the server through a cache `loading::MapLoader` has filled around the spawn point
+    fn streaming(cache: ChunkCache, seed: u64) -> Self {
+        Map {
+            tiles: Vec::new(),
+            width: 0,
+            height: 0,
+            start_position: Some(SPAWN_POSITION),
+            chunks: Some(cache),
+            seed,
+            explored: ExploredTiles::default(),
+            pois: KnownPois::default(),
+            regions: KnownRegions::default(),
+            events: ActiveEvents::default(),
         }
-
-        let map_data: MapData = response
-            .json()
-            .map_err(|e| format!("Failed to parse map data: {}", e))?;
-
-        Ok(Map {
-            tiles: map_data.tiles,
-            width: map_data.width,
-            height: map_data.height,
-            start_position: Some((map_data.start_x, map_data.start_y)),
-        })
     }
 
     /// Generate map locally (fallback)
-    fn generate_local(width: usize, height: usize) -> Self {
-        let mut tiles = vec![vec![Tile::Wall; width]; height];
+    fn generate_local(width: usize, height: usize, seed: u64) -> Self {
+        let mut map = Map {
+            tiles: vec![Tile::Wall; width * height],
+            width,
+            height,
+            start_position: None,
+            chunks: None,
+            seed,
+            explored: ExploredTiles::default(),
+            pois: KnownPois::default(),
+            regions: KnownRegions::default(),
+            events: ActiveEvents::default(),
+        };
 
-        let mut rng_state: u64 = 12345;
+        let mut rng_state: u64 = map.seed;
 
         let mut rand = || -> u64 {
             rng_state = rng_state.wrapping_mul(1103515245).wrapping_add(12345);
@@ -219,10 +878,8 @@ impl Map {
             let corridor_height = (rand() % 15 + 3) as usize;
             let wall_height = (rand() % 4 + 1) as usize;
 
-            for cy in y..(y + corridor_height).min(height - 1) {
-                for x in 1..width - 1 {
-                    tiles[cy][x] = Tile::Floor;
-                }
+            for row in map.rect_mut(1..width - 1, y..(y + corridor_height).min(height - 1)) {
+                row.fill(Tile::Floor);
             }
 
             y += corridor_height + wall_height;
@@ -234,10 +891,8 @@ impl Map {
             let corridor_width = (rand() % 18 + 2) as usize;
             let wall_width = (rand() % 6 + 2) as usize;
 
-            for cx in x..(x + corridor_width).min(width - 1) {
-                for y in 1..height - 1 {
-                    tiles[y][cx] = Tile::Floor;
-                }
+            for row in map.rect_mut(x..(x + corridor_width).min(width - 1), 1..height - 1) {
+                row.fill(Tile::Floor);
             }
 
             x += corridor_width + wall_width;
@@ -251,10 +906,8 @@ impl Map {
             let room_x = (rand() as usize % (width.saturating_sub(room_w + 2))).max(1);
             let room_y = (rand() as usize % (height.saturating_sub(room_h + 2))).max(1);
 
-            for ry in room_y..(room_y + room_h).min(height - 1) {
-                for rx in room_x..(room_x + room_w).min(width - 1) {
-                    tiles[ry][rx] = Tile::Floor;
-                }
+            for row in map.rect_mut(room_x..(room_x + room_w).min(width - 1), room_y..(room_y + room_h).min(height - 1)) {
+                row.fill(Tile::Floor);
             }
         }
 
@@ -266,10 +919,10 @@ impl Map {
             let neb_x = (rand() as usize % width.saturating_sub(neb_w + 2)).max(1);
             let neb_y = (rand() as usize % height.saturating_sub(neb_h + 2)).max(1);
 
-            for ny in neb_y..(neb_y + neb_h).min(height - 1) {
-                for nx in neb_x..(neb_x + neb_w).min(width - 1) {
-                    if tiles[ny][nx] == Tile::Floor {
-                        tiles[ny][nx] = Tile::Nebula;
+            for row in map.rect_mut(neb_x..(neb_x + neb_w).min(width - 1), neb_y..(neb_y + neb_h).min(height - 1)) {
+                for tile in row {
+                    if *tile == Tile::Floor {
+                        *tile = Tile::Nebula;
                     }
                 }
             }
@@ -283,24 +936,16 @@ impl Map {
             let pillar_x = (rand() as usize % width.saturating_sub(pillar_w + 4)) + 2;
             let pillar_y = (rand() as usize % height.saturating_sub(pillar_h + 4)) + 2;
 
-            let mut can_place = true;
-            for py in pillar_y.saturating_sub(1)..(pillar_y + pillar_h + 1).min(height) {
-                for px in pillar_x.saturating_sub(1)..(pillar_x + pillar_w + 1).min(width) {
-                    if tiles[py][px] == Tile::Wall {
-                        can_place = false;
-                        break;
-                    }
-                }
-                if !can_place {
-                    break;
-                }
-            }
+            let can_place = !map
+                .rect(
+                    pillar_x.saturating_sub(1)..(pillar_x + pillar_w + 1).min(width),
+                    pillar_y.saturating_sub(1)..(pillar_y + pillar_h + 1).min(height),
+                )
+                .any(|row| row.contains(&Tile::Wall));
 
             if can_place {
-                for py in pillar_y..(pillar_y + pillar_h).min(height - 1) {
-                    for px in pillar_x..(pillar_x + pillar_w).min(width - 1) {
-                        tiles[py][px] = Tile::Wall;
-                    }
+                for row in map.rect_mut(pillar_x..(pillar_x + pillar_w).min(width - 1), pillar_y..(pillar_y + pillar_h).min(height - 1)) {
+                    row.fill(Tile::Wall);
                 }
             }
         }
@@ -313,55 +958,351 @@ impl Map {
             let field_x = (rand() as usize % width.saturating_sub(field_w + 2)).max(1);
             let field_y = (rand() as usize % height.saturating_sub(field_h + 2)).max(1);
 
-            for fy in field_y..(field_y + field_h).min(height - 1) {
-                for fx in field_x..(field_x + field_w).min(width - 1) {
+            for row in map.rect_mut(field_x..(field_x + field_w).min(width - 1), field_y..(field_y + field_h).min(height - 1)) {
+                for tile in row {
                     // Sparse asteroids
-                    if rand() % 3 == 0 && tiles[fy][fx] == Tile::Floor {
-                        tiles[fy][fx] = Tile::Asteroid;
+                    if rand().is_multiple_of(3) && *tile == Tile::Floor {
+                        *tile = Tile::Asteroid;
                     }
                 }
             }
         }
 
         // Ensure borders are walls
-        for x in 0..width {
-            tiles[0][x] = Tile::Wall;
-            tiles[height - 1][x] = Tile::Wall;
+        for (y, row) in map.rect_mut(0..width, 0..height).enumerate() {
+            if y == 0 || y == height - 1 {
+                row.fill(Tile::Wall);
+            } else {
+                row[0] = Tile::Wall;
+                row[width - 1] = Tile::Wall;
+            }
         }
-        for y in 0..height {
-            tiles[y][0] = Tile::Wall;
-            tiles[y][width - 1] = Tile::Wall;
+
+        let regions = exospace_core::regions::survey_map(seed, width, height, |_, _| None, |x, y| map.get(x, y));
+        map.regions.add(&regions);
+        map
+    }
+
+    /// The map to play: what the loader fetched (None when cancelled, or solo), else a local one,
+    /// with this world's saved exploration, and a line for the chat saying which it is.
+    /// Solo the most recent saved map is resumed if there is one, unless the command line asked
+    /// for a particular seed or size
+    fn new(loaded: Option<Result<Map, String>>, cli: &Cli) -> (Self, ChatMessage) {
+        let (width, height) = cli.local_size();
+        let (mut map, notice) = match loaded {
+            Some(Ok(map)) => (map, ChatMessage::system("Connected to server, streaming world")),
+            Some(Err(e)) => {
+                let notice = ChatMessage::error(&format!("Server unavailable ({}), generating local map", e));
+                (Self::generate_local(width, height, cli.seed()), notice)
+            }
+            None if !cli.connects_at_start() => {
+                let solo = if cli.offline { "Offline" } else { "Flying solo" };
+                let mut notice = ChatMessage::system(&format!("{}, generating local map", solo));
+                if !cli.wants_new_map() && let Some(name) = saves::latest() {
+                    match saves::load(&name) {
+                        Ok(saved) => return (Self::from_saved(saved), ChatMessage::system(&format!("{}, resuming saved map '{}'", solo, name))),
+                        Err(e) => notice = ChatMessage::error(&format!("{}; generating local map", e)),
+                    }
+                }
+                (Self::generate_local(width, height, cli.seed()), notice)
+            }
+            None => (Self::generate_local(width, height, cli.seed()), ChatMessage::system("Loading cancelled, generating local map")),
+        };
+        map.explored = ExploredTiles::load(&map.world_key());
+        (map, notice)
+    }
+
+    /// A saved local map, with exploration and edits as they are now
+    fn from_saved(saved: saves::SavedMap) -> Self {
+        let mut pois = KnownPois::default();
+        pois.add(&saved.map.pois);
+        let mut regions = KnownRegions::default();
+        regions.add(&saved.map.regions);
+        Map {
+            tiles: saved.map.tiles,
+            width: saved.map.width,
+            height: saved.map.height,
+            start_position: Some((saved.map.start_x, saved.map.start_y)),
+            chunks: None,
+            seed: saved.seed,
+            explored: saved.explored,
+            pois,
+            regions,
+            events: ActiveEvents::default(),
         }
+    }
 
-        Map { tiles, width, height, start_position: None }
+    /// This local map as a save that starts wherever the ship is now
+    fn to_saved(&self, ship: (i32, i32)) -> saves::SavedMap {
+        saves::SavedMap {
+            seed: self.seed,
+            map: MapData {
+                tiles: self.tiles.clone(),
+                width: self.width,
+                height: self.height,
+                start_x: ship.0,
+                start_y: ship.1,
+                pois: self.pois.iter().cloned().collect(),
+                regions: self.regions.iter().cloned().collect(),
+            },
+            explored: self.explored.clone(),
+        }
     }
 
-    /// Get map from server, falling back to local generation
-    fn new(config: &Config) -> Self {
-        match Self::fetch_from_server(config) {
-            Ok(map) => {
-                eprintln!("Connected to server, map loaded");
-                map
-            }
-            Err(e) => {
-                eprintln!("Server unavailable ({}), generating local map", e);
-                Self::generate_local(500, 200)
-            }
+    /// Names the world for saved exploration; local and streamed worlds differ even with the same seed
+    fn world_key(&self) -> String {
+        let source = if self.chunks.is_some() { "stream" } else { "local" };
+        format!("{}-{}", source, self.seed)
+    }
+
+    /// Area worth showing on the minimap as (min_x, min_y, max_x, max_y), max exclusive:
+    /// the whole local map, or the explored part of a streamed world
+    fn extent(&self) -> Option<(i32, i32, i32, i32)> {
+        if self.chunks.is_some() {
+            self.explored.bounds()
+        } else {
+            Some((0, 0, self.width as i32, self.height as i32))
         }
     }
 
-    fn get(&self, x: i32, y: i32) -> Option<Tile> {
-        if x < 0 || y < 0 {
+    /// Write exploration to disk if anything new was seen
+    fn save_explored(&mut self) -> Result<(), String> {
+        let world = self.world_key();
+        self.explored.save(&world)
+    }
+
+    /// Position of (x, y) in `tiles` for local maps
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
             return None;
         }
+        Some(y as usize * self.width + x as usize)
+    }
+
+    fn get(&self, x: i32, y: i32) -> Option<Tile> {
+        if let Some(cache) = &self.chunks {
+            return cache.get(x, y);
+        }
+        self.index(x, y).map(|i| self.tiles[i])
+    }
+
+    /// Change a tile in our copy of the map; a streamed chunk reverts if it's fetched again
+    fn set(&mut self, x: i32, y: i32, tile: Tile) {
+        let slot = match &mut self.chunks {
+            Some(cache) => cache.get_mut(x, y),
+            None => self.index(x, y).map(|i| &mut self.tiles[i]),
+        };
+        if let Some(slot) = slot {
+            *slot = tile;
+        }
+    }
+
+    /// Change a tile because the player broke it; streamed worlds keep the change over refetched chunks
+    fn edit(&mut self, x: i32, y: i32, tile: Tile) {
+        if let Some(cache) = &mut self.chunks {
+            cache.edits.record(x, y, tile);
+        }
+        self.set(x, y, tile);
+    }
+
+    /// Send the next unsent edit to the server; a refused one's chunk is dropped so the server's tile comes back,
+    /// and the server's reason is returned for the chat
+    fn sync_edits(&mut self, now: Instant) -> Option<ChatMessage> {
+        let cache = self.chunks.as_mut()?;
+        let (x, y, tile) = cache.edits.next_unsent(now)?;
+        let (outcome, refusal) = cache.send_edit(x, y, tile);
+        cache.edits.sent(x, y, outcome, now);
+        if outcome == EditOutcome::Refused {
+            cache.chunks.remove(&chunk_coords(x, y));
+        }
+        refusal.map(|error| ChatMessage::server_error(&format!("Server kept its tile at ({}, {})", x, y), &error))
+    }
+
+    /// Drop streamed chunks so they're fetched again as they come into view
+    fn refetch_chunks(&mut self) {
+        if let Some(cache) = &mut self.chunks {
+            cache.chunks.clear();
+            cache.failed.clear();
+            cache.version = None;
+        }
+    }
+
+    /// Catch streamed chunks up with the tiles the server changed while we weren't listening, from its diff
+    /// if it can give one, otherwise by fetching them again
+    fn catch_up_chunks(&mut self) {
+        if let Some(cache) = &mut self.chunks
+            && !cache.catch_up()
+        {
+            self.refetch_chunks();
+        }
+    }
+
+    /// Row slices covering a rectangle of a local map, clipped to its bounds
+    fn rect(&self, xs: Range<usize>, ys: Range<usize>) -> impl Iterator<Item = &[Tile]> {
+        let (xs, ys) = self.clip(xs, ys);
+        ys.map(move |y| &self.tiles[y * self.width + xs.start..y * self.width + xs.end])
+    }
+
+    /// Mutable row slices covering a rectangle of a local map, clipped to its bounds
+    fn rect_mut(&mut self, xs: Range<usize>, ys: Range<usize>) -> impl Iterator<Item = &mut [Tile]> {
+        let (xs, ys) = self.clip(xs, ys);
         self.tiles
-            .get(y as usize)
-            .and_then(|row| row.get(x as usize))
-            .copied()
+            .chunks_exact_mut(self.width.max(1))
+            .skip(ys.start)
+            .take(ys.len())
+            .map(move |row| &mut row[xs.clone()])
+    }
+
+    fn clip(&self, xs: Range<usize>, ys: Range<usize>) -> (Range<usize>, Range<usize>) {
+        let xs = xs.start.min(self.width)..xs.end.min(self.width);
+        let ys = ys.start.min(self.height)..ys.end.min(self.height);
+        (xs.start..xs.end.max(xs.start), ys.start..ys.end.max(ys.start))
+    }
+
+    /// Tiles along row `y` for the columns in `xs`, reading each contiguous run of storage as a slice
+    fn row_span(&self, y: i32, xs: Range<i32>) -> impl Iterator<Item = Option<Tile>> + '_ {
+        let mut x = xs.start;
+        std::iter::from_fn(move || {
+            (x < xs.end).then(|| {
+                let (len, run) = self.row_run(x, y, (xs.end - x) as usize);
+                x += len as i32;
+                (len, run)
+            })
+        })
+        .flat_map(|(len, run)| {
+            let missing = if run.is_some() { 0 } else { len };
+            run.unwrap_or_default()
+                .iter()
+                .map(|&tile| Some(tile))
+                .chain(std::iter::repeat_n(None, missing))
+        })
+    }
+
+    /// The `zoom` x `zoom` block of tiles with its top-left corner at (x, y), as one zoomed-out cell:
+    /// whether any of it is explored, and the most common explored tile
+    fn block_summary(&self, x: i32, y: i32, zoom: i32) -> (bool, Option<Tile>) {
+        let mut counts: Vec<(Tile, u32)> = Vec::new();
+        let mut explored = false;
+        for ty in y..y + zoom {
+            for tx in x..x + zoom {
+                if !self.explored.is_explored(tx, ty) {
+                    continue;
+                }
+                explored = true;
+                let Some(tile) = self.get(tx, ty) else {
+                    continue;
+                };
+                match counts.iter_mut().find(|(seen, _)| *seen == tile) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((tile, 1)),
+                }
+            }
+        }
+        // Ties go to impassable tiles, so zooming out never hides half a wall
+        let majority = counts.into_iter().max_by_key(|&(tile, count)| (count, !tile.is_passable())).map(|(tile, _)| tile);
+        (explored, majority)
+    }
+
+    /// Longest contiguous run of stored tiles starting at (x, y), at most `max_len` long.
+    /// Returns the run's length and its tiles, or `None` where nothing is loaded.
+    fn row_run(&self, x: i32, y: i32, max_len: usize) -> (usize, Option<&[Tile]>) {
+        if let Some(cache) = &self.chunks {
+            // Runs stop at chunk edges
+            let (local_x, local_y) = chunk_local(x, y);
+            let len = ((CHUNK_SIZE - local_x) as usize).min(max_len);
+            let start = (local_y * CHUNK_SIZE + local_x) as usize;
+            let run = cache.chunks.get(&chunk_coords(x, y)).and_then(|chunk| chunk.tiles.get(start..start + len));
+            return (len, run);
+        }
+        match self.index(x, y) {
+            Some(start) => {
+                let len = (self.width - x as usize).min(max_len);
+                (len, Some(&self.tiles[start..start + len]))
+            }
+            // Left of the map the gap ends at column 0; anywhere else the rest of the row is empty
+            None if x < 0 && y >= 0 && (y as usize) < self.height => (((-x) as usize).min(max_len), None),
+            None => (max_len, None),
+        }
     }
 
+    /// Whether a ship can fly through (x, y): an open tile with no meteor shower's rock on it
     fn is_passable(&self, x: i32, y: i32) -> bool {
-        self.get(x, y).map(|t| t.is_passable()).unwrap_or(false)
+        self.get(x, y).map(|t| t.is_passable()).unwrap_or(false) && !self.events.blocks(x, y)
+    }
+
+    /// Points of interest found so far: the local map's, or those in every chunk fetched
+    fn pois(&self) -> &KnownPois {
+        self.chunks.as_ref().map_or(&self.pois, |cache| &cache.pois)
+    }
+
+    /// Regions known so far: the local map's, or those named by every chunk fetched
+    fn regions(&self) -> &KnownRegions {
+        self.chunks.as_ref().map_or(&self.regions, |cache| &cache.regions)
+    }
+
+    /// The biome at a position; deep space where no region is known
+    fn biome_at(&self, x: i32, y: i32) -> Biome {
+        self.regions().at(x, y).map_or(Biome::DeepSpace, |region| region.biome)
+    }
+
+    /// Send a docked screen's request; only the streamed world's stations trade, and local repairs are free
+    fn market_call(&self, station: &str, call: &MarketCall) -> Result<MarketReply, String> {
+        match (&self.chunks, call) {
+            (Some(cache), _) => cache.market_call(station, call),
+            (None, MarketCall::Repair(order)) => Ok(MarketReply::Repaired(RepairReceipt { hull: order.hull, cost: 0, balance: None })),
+            (None, _) => Err("The market is closed: stations only trade on the streamed world".to_string()),
+        }
+    }
+
+    /// The server's mission board; only the streamed world has one
+    fn fetch_missions(&self) -> Result<MissionBoard, CallFailure> {
+        match &self.chunks {
+            Some(cache) => cache.fetch_missions(),
+            None => Err(CallFailure::Unreachable("Missions come from the server: fly the streamed world to take them".to_string())),
+        }
+    }
+
+    fn accept_mission(&self, id: &str) -> Result<(), CallFailure> {
+        match &self.chunks {
+            Some(cache) => cache.accept_mission(id),
+            None => Err(CallFailure::Unreachable("Missions are taken on the streamed world".to_string())),
+        }
+    }
+
+    fn complete_mission(&self, id: &str) -> Result<MissionReward, CallFailure> {
+        match &self.chunks {
+            Some(cache) => cache.complete_mission(id),
+            None => Err(CallFailure::Unreachable("Missions are handed in on the streamed world".to_string())),
+        }
+    }
+
+    /// The logged-in account's banked credits; local maps have no bank
+    fn fetch_balance(&self) -> Result<Balance, CallFailure> {
+        match &self.chunks {
+            Some(cache) => cache.fetch_balance(),
+            None => Err(CallFailure::Unreachable("Credits are banked on the streamed world".to_string())),
+        }
+    }
+
+    fn fetch_transactions(&self) -> Result<TransactionHistory, CallFailure> {
+        match &self.chunks {
+            Some(cache) => cache.fetch_transactions(),
+            None => Err(CallFailure::Unreachable("Credits are banked on the streamed world".to_string())),
+        }
+    }
+
+    /// Make sure the area around (x, y) is loaded when streaming; no-op for local maps
+    fn load_around(&mut self, x: i32, y: i32, radius_x: i32, radius_y: i32) {
+        if let Some(cache) = &mut self.chunks {
+            cache.load_area(x, y, radius_x, radius_y);
+        }
+    }
+
+    /// Keep the chunk cache bounded, preferring chunks near (x, y)
+    fn evict_distant_chunks(&mut self, x: i32, y: i32) {
+        if let Some(cache) = &mut self.chunks {
+            cache.evict_distant(x, y);
+        }
     }
 
     fn find_start_position(&self) -> (i32, i32) {
@@ -402,88 +1343,282 @@ impl ShipCell {
         ShipCell { ch, fg, bg: None }
     }
 
-    fn with_bg(ch: char, fg: u32, bg: u32) -> Self {
-        ShipCell { ch, fg, bg: Some(bg) }
-    }
-
     fn empty() -> Self {
         ShipCell { ch: ' ', fg: 0x000000, bg: None }
     }
 }
 
-/// Ship sprite data - 3x3 grid for each direction
-/// Grid is [row][col] where (0,0) is top-left
-struct ShipSprite {
-    cells: [[ShipCell; 3]; 3],
+/// Colors used to paint a ship sprite
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ShipPalette {
+    hull: u32,
+    cockpit: u32,
+    wing: u32,
+    accent: u32,
 }
 
-impl ShipSprite {
-    /// Get ship sprite for a direction
-    fn for_direction(direction: Direction) -> Self {
-        let hull = 0x40C080;      // Cyan-green hull
-        let cockpit = 0x80FFFF;   // Bright cyan cockpit
-        let wing = 0x3090A0;      // Darker wing color
-        let accent = 0x60A0C0;    // Accent color
+impl ShipPalette {
+    /// The local player's ship
+    fn player() -> Self {
+        ShipPalette {
+            hull: 0x40C080,     // Cyan-green hull
+            cockpit: 0x80FFFF,  // Bright cyan cockpit
+            wing: 0x3090A0,     // Darker wing color
+            accent: 0x60A0C0,   // Accent color
+        }
+    }
 
-        let e = ShipCell::empty();
+    /// Other players' ships - warm tones so they never look like our own
+    fn remote() -> Self {
+        ShipPalette {
+            hull: 0xD08040,     // Orange hull
+            cockpit: 0xFFD080,  // Pale gold cockpit
+            wing: 0xA05030,     // Rust wings
+            accent: 0xC09060,   // Tan accent
+        }
+    }
 
-        match direction {
-            Direction::Up => ShipSprite {
+    /// Server-driven ships, tinted by what they're up to
+    fn npc(behavior: NpcBehavior) -> Self {
+        match behavior {
+            NpcBehavior::Patrol => ShipPalette {
+                hull: 0x5080D0,     // Steel blue hull
+                cockpit: 0xA0C8FF,  // Ice cockpit
+                wing: 0x3050A0,     // Navy wings
+                accent: 0x7090C0,   // Slate accent
+            },
+            NpcBehavior::Wander => ShipPalette {
+                hull: 0x90A070,     // Olive hull
+                cockpit: 0xD0E0A0,  // Pale lime cockpit
+                wing: 0x607048,     // Moss wings
+                accent: 0xA0B080,   // Sage accent
+            },
+            NpcBehavior::Flee => ShipPalette {
+                hull: 0xB070C0,     // Violet hull
+                cockpit: 0xE0B0F0,  // Lilac cockpit
+                wing: 0x804890,     // Plum wings
+                accent: 0xA080B0,   // Mauve accent
+            },
+            NpcBehavior::Unknown => ShipPalette {
+                hull: 0x909090,     // Grey hull
+                cockpit: 0xC0C0C0,  // Light grey cockpit
+                wing: 0x606060,     // Dark grey wings
+                accent: 0x808080,   // Mid grey accent
+            },
+        }
+    }
+
+    /// This palette with a pilot's own paint over it
+    fn painted(self, ship: &ShipLivery) -> Self {
+        let color = |part, default| ship.colors.get(&part).copied().unwrap_or(default);
+        ShipPalette {
+            hull: color(ShipPart::Hull, self.hull),
+            cockpit: color(ShipPart::Cockpit, self.cockpit),
+            wing: color(ShipPart::Wing, self.wing),
+            accent: self.accent,
+        }
+    }
+
+    /// Another pilot's ship: the remote palette under their paint
+    fn pilot(player: &PlayerInfo) -> Self {
+        ShipPalette::remote().painted(&player.ship)
+    }
+
+    /// Palette of a ship sprite; None for things that aren't ships
+    fn for_sprite(kind: SpriteKind) -> Option<Self> {
+        match kind {
+            SpriteKind::Pilot => Some(ShipPalette::remote()),
+            SpriteKind::Npc(behavior) => Some(ShipPalette::npc(behavior)),
+            SpriteKind::Projectile | SpriteKind::Pickup => None,
+        }
+    }
+}
+
+/// Ship sprite data - 3x3 grid for each direction
+/// Grid is [row][col] where (0,0) is top-left
+struct ShipSprite {
+    cells: [[ShipCell; 3]; 3],
+}
+
+impl ShipSprite {
+    /// Get a class's ship sprite for a direction painted with the given palette
+    fn for_class_and_direction(class: ShipClass, direction: Direction, palette: &ShipPalette) -> Self {
+        let hull = palette.hull;
+        let cockpit = palette.cockpit;
+        let wing = palette.wing;
+        let accent = palette.accent;
+
+        let e = ShipCell::empty();
+
+        match (class, direction) {
+            (ShipClass::Scout, Direction::Up) => ShipSprite {
                 cells: [
                     [e,                              ShipCell::new('^', cockpit), e],
                     [ShipCell::new('/', wing),       ShipCell::new('|', hull),    ShipCell::new('\\', wing)],
                     [ShipCell::new('<', accent),     ShipCell::new('=', hull),    ShipCell::new('>', accent)],
                 ],
             },
-            Direction::Down => ShipSprite {
+            (ShipClass::Scout, Direction::Down) => ShipSprite {
                 cells: [
                     [ShipCell::new('>', accent),     ShipCell::new('=', hull),    ShipCell::new('<', accent)],
                     [ShipCell::new('\\', wing),      ShipCell::new('|', hull),    ShipCell::new('/', wing)],
                     [e,                              ShipCell::new('v', cockpit), e],
                 ],
             },
-            Direction::Left => ShipSprite {
+            (ShipClass::Scout, Direction::Left) => ShipSprite {
                 cells: [
                     [e,                              ShipCell::new('/', wing),    ShipCell::new('^', accent)],
                     [ShipCell::new('<', cockpit),    ShipCell::new('-', hull),    ShipCell::new('=', hull)],
                     [e,                              ShipCell::new('\\', wing),   ShipCell::new('v', accent)],
                 ],
             },
-            Direction::Right => ShipSprite {
+            (ShipClass::Scout, Direction::Right) => ShipSprite {
                 cells: [
                     [ShipCell::new('v', accent),     ShipCell::new('\\', wing),   e],
                     [ShipCell::new('=', hull),       ShipCell::new('-', hull),    ShipCell::new('>', cockpit)],
                     [ShipCell::new('^', accent),     ShipCell::new('/', wing),    e],
                 ],
             },
-            Direction::UpRight => ShipSprite {
+            (ShipClass::Scout, Direction::UpRight) => ShipSprite {
                 cells: [
                     [e,                              ShipCell::new('/', wing),    ShipCell::new('>', cockpit)],
                     [ShipCell::new('/', wing),       ShipCell::new('/', hull),    ShipCell::new('/', wing)],
                     [ShipCell::new('<', accent),     ShipCell::new('/', wing),    e],
                 ],
             },
-            Direction::UpLeft => ShipSprite {
+            (ShipClass::Scout, Direction::UpLeft) => ShipSprite {
                 cells: [
                     [ShipCell::new('<', cockpit),    ShipCell::new('\\', wing),   e],
                     [ShipCell::new('\\', wing),      ShipCell::new('\\', hull),   ShipCell::new('\\', wing)],
                     [e,                              ShipCell::new('\\', wing),   ShipCell::new('>', accent)],
                 ],
             },
-            Direction::DownRight => ShipSprite {
+            (ShipClass::Scout, Direction::DownRight) => ShipSprite {
                 cells: [
                     [ShipCell::new('^', accent),     ShipCell::new('\\', wing),   e],
                     [ShipCell::new('\\', wing),      ShipCell::new('\\', hull),   ShipCell::new('\\', wing)],
                     [e,                              ShipCell::new('\\', wing),   ShipCell::new('v', cockpit)],
                 ],
             },
-            Direction::DownLeft => ShipSprite {
+            (ShipClass::Scout, Direction::DownLeft) => ShipSprite {
                 cells: [
                     [e,                              ShipCell::new('/', wing),    ShipCell::new('^', accent)],
                     [ShipCell::new('/', wing),       ShipCell::new('/', hull),    ShipCell::new('/', wing)],
                     [ShipCell::new('v', cockpit),    ShipCell::new('/', wing),    e],
                 ],
             },
+            (ShipClass::Freighter, Direction::Up) => ShipSprite {
+                cells: [
+                    [ShipCell::new('/', hull),       ShipCell::new('^', cockpit), ShipCell::new('\\', hull)],
+                    [ShipCell::new('#', wing),       ShipCell::new('#', hull),    ShipCell::new('#', wing)],
+                    [ShipCell::new('=', accent),     ShipCell::new('=', hull),    ShipCell::new('=', accent)],
+                ],
+            },
+            (ShipClass::Freighter, Direction::Down) => ShipSprite {
+                cells: [
+                    [ShipCell::new('=', accent),     ShipCell::new('=', hull),    ShipCell::new('=', accent)],
+                    [ShipCell::new('#', wing),       ShipCell::new('#', hull),    ShipCell::new('#', wing)],
+                    [ShipCell::new('\\', hull),      ShipCell::new('v', cockpit), ShipCell::new('/', hull)],
+                ],
+            },
+            (ShipClass::Freighter, Direction::Left) => ShipSprite {
+                cells: [
+                    [ShipCell::new('/', hull),       ShipCell::new('#', wing),    ShipCell::new('=', accent)],
+                    [ShipCell::new('<', cockpit),    ShipCell::new('#', hull),    ShipCell::new('=', hull)],
+                    [ShipCell::new('\\', hull),      ShipCell::new('#', wing),    ShipCell::new('=', accent)],
+                ],
+            },
+            (ShipClass::Freighter, Direction::Right) => ShipSprite {
+                cells: [
+                    [ShipCell::new('=', accent),     ShipCell::new('#', wing),    ShipCell::new('\\', hull)],
+                    [ShipCell::new('=', hull),       ShipCell::new('#', hull),    ShipCell::new('>', cockpit)],
+                    [ShipCell::new('=', accent),     ShipCell::new('#', wing),    ShipCell::new('/', hull)],
+                ],
+            },
+            (ShipClass::Freighter, Direction::UpRight) => ShipSprite {
+                cells: [
+                    [ShipCell::new('/', wing),       ShipCell::new('#', hull),    ShipCell::new('>', cockpit)],
+                    [ShipCell::new('#', wing),       ShipCell::new('#', hull),    ShipCell::new('#', hull)],
+                    [ShipCell::new('<', accent),     ShipCell::new('#', wing),    ShipCell::new('/', wing)],
+                ],
+            },
+            (ShipClass::Freighter, Direction::UpLeft) => ShipSprite {
+                cells: [
+                    [ShipCell::new('<', cockpit),    ShipCell::new('#', hull),    ShipCell::new('\\', wing)],
+                    [ShipCell::new('#', hull),       ShipCell::new('#', hull),    ShipCell::new('#', wing)],
+                    [ShipCell::new('\\', wing),      ShipCell::new('#', wing),    ShipCell::new('>', accent)],
+                ],
+            },
+            (ShipClass::Freighter, Direction::DownRight) => ShipSprite {
+                cells: [
+                    [ShipCell::new('^', accent),     ShipCell::new('#', wing),    ShipCell::new('\\', wing)],
+                    [ShipCell::new('#', wing),       ShipCell::new('#', hull),    ShipCell::new('#', hull)],
+                    [ShipCell::new('\\', wing),      ShipCell::new('#', hull),    ShipCell::new('v', cockpit)],
+                ],
+            },
+            (ShipClass::Freighter, Direction::DownLeft) => ShipSprite {
+                cells: [
+                    [ShipCell::new('/', wing),       ShipCell::new('#', wing),    ShipCell::new('^', accent)],
+                    [ShipCell::new('#', hull),       ShipCell::new('#', hull),    ShipCell::new('#', wing)],
+                    [ShipCell::new('v', cockpit),    ShipCell::new('#', hull),    ShipCell::new('/', wing)],
+                ],
+            },
+            (ShipClass::Interceptor, Direction::Up) => ShipSprite {
+                cells: [
+                    [e,                              ShipCell::new('^', cockpit), e],
+                    [ShipCell::new('<', wing),       ShipCell::new('A', hull),    ShipCell::new('>', wing)],
+                    [ShipCell::new('\'', accent),    ShipCell::new('|', hull),    ShipCell::new('\'', accent)],
+                ],
+            },
+            (ShipClass::Interceptor, Direction::Down) => ShipSprite {
+                cells: [
+                    [ShipCell::new('.', accent),     ShipCell::new('|', hull),    ShipCell::new('.', accent)],
+                    [ShipCell::new('<', wing),       ShipCell::new('V', hull),    ShipCell::new('>', wing)],
+                    [e,                              ShipCell::new('v', cockpit), e],
+                ],
+            },
+            (ShipClass::Interceptor, Direction::Left) => ShipSprite {
+                cells: [
+                    [e,                              ShipCell::new('^', wing),    ShipCell::new('.', accent)],
+                    [ShipCell::new('<', cockpit),    ShipCell::new('<', hull),    ShipCell::new('-', hull)],
+                    [e,                              ShipCell::new('v', wing),    ShipCell::new('\'', accent)],
+                ],
+            },
+            (ShipClass::Interceptor, Direction::Right) => ShipSprite {
+                cells: [
+                    [ShipCell::new('.', accent),     ShipCell::new('^', wing),    e],
+                    [ShipCell::new('-', hull),       ShipCell::new('>', hull),    ShipCell::new('>', cockpit)],
+                    [ShipCell::new('\'', accent),    ShipCell::new('v', wing),    e],
+                ],
+            },
+            (ShipClass::Interceptor, Direction::UpRight) => ShipSprite {
+                cells: [
+                    [e,                              ShipCell::new('<', wing),    ShipCell::new('>', cockpit)],
+                    [e,                              ShipCell::new('/', hull),    ShipCell::new('v', wing)],
+                    [ShipCell::new('/', accent),     e,                           e],
+                ],
+            },
+            (ShipClass::Interceptor, Direction::UpLeft) => ShipSprite {
+                cells: [
+                    [ShipCell::new('<', cockpit),    ShipCell::new('>', wing),    e],
+                    [ShipCell::new('v', wing),       ShipCell::new('\\', hull),   e],
+                    [e,                              e,                           ShipCell::new('\\', accent)],
+                ],
+            },
+            (ShipClass::Interceptor, Direction::DownRight) => ShipSprite {
+                cells: [
+                    [ShipCell::new('\\', accent),    e,                           e],
+                    [e,                              ShipCell::new('\\', hull),   ShipCell::new('^', wing)],
+                    [e,                              ShipCell::new('<', wing),    ShipCell::new('v', cockpit)],
+                ],
+            },
+            (ShipClass::Interceptor, Direction::DownLeft) => ShipSprite {
+                cells: [
+                    [e,                              e,                           ShipCell::new('/', accent)],
+                    [ShipCell::new('^', wing),       ShipCell::new('/', hull),    e],
+                    [ShipCell::new('v', cockpit),    ShipCell::new('>', wing),    e],
+                ],
+            },
         }
     }
 }
@@ -609,12 +1744,78 @@ impl ExhaustSprite {
     }
 }
 
+/// How tiles this client doesn't know are drawn, in the view and on the minimap
+const UNKNOWN_TILE_STYLE: (char, u32) = ('?', 0x909090);
+
+/// Faint dots marking the rest of the autopilot's route
+const NAV_BREADCRUMB_STYLE: (char, u32) = ('·', 0x3A5A78);
+
+/// Entities that aren't ships
+const PROJECTILE_STYLE: (char, u32) = ('*', 0xFFE060);
+const PICKUP_STYLE: (char, u32) = ('o', 0x60FF90);
+
+/// A sprite as one cell: a ship's heading in its hull color, or the glyph of anything else
+fn sprite_style(sprite: Sprite) -> (char, u32) {
+    match sprite.kind {
+        SpriteKind::Projectile => PROJECTILE_STYLE,
+        SpriteKind::Pickup => PICKUP_STYLE,
+        kind => (sprite.direction.to_char(), ShipPalette::for_sprite(kind).map_or(0xFFFFFF, |palette| palette.hull)),
+    }
+}
+
+/// Stations, in the view and on the minimap; your home station stands out
+const STATION_STYLE: (char, u32) = poi::style(PoiKind::Station);
+const HOME_STATION_STYLE: (char, u32) = ('⌂', 0x60E060);
+
+/// Every station the server told us about, as points of interest
+fn station_pois(remote: &RemotePlayers) -> Vec<Poi> {
+    remote
+        .stations()
+        .map(|station| Poi { kind: PoiKind::Station, name: station.name.clone(), x: station.x, y: station.y })
+        .collect()
+}
+
+/// Glyph for every known station by position
+fn station_marks(remote: &RemotePlayers) -> HashMap<(i32, i32), (char, u32)> {
+    remote
+        .stations()
+        .map(|station| {
+            let style = if remote.home() == Some(station.name.as_str()) { HOME_STATION_STYLE } else { STATION_STYLE };
+            ((station.x, station.y), style)
+        })
+        .collect()
+}
+
+/// Mix two colors channel by channel, `step` of `steps` of the way from `from` to `to`
+fn blend(from: u32, to: u32, step: u32, steps: u32) -> u32 {
+    [16, 8, 0].iter().fold(0, |color, &shift| {
+        let (a, b) = ((from >> shift & 0xFF) as i32, (to >> shift & 0xFF) as i32);
+        color | (((a + (b - a) * step as i32 / steps as i32) as u32) << shift)
+    })
+}
+
+/// Tiles per screen cell along each axis, from closest to furthest out ('+'/'-' step through them)
+const ZOOM_LEVELS: [u32; 3] = [1, 2, 4];
+
 /// Visual renderer with animation state
 struct Renderer {
     frame: u64,
     star_chars: [char; 4],
     asteroid_chars: [char; 4],
     effects_enabled: bool,
+    minimap: Minimap,
+    /// Class of the local player's ship, which picks its sprites
+    ship_class: ShipClass,
+    /// The local ship's own paint, over the theme's ship colors
+    livery: ShipLivery,
+    /// One of `ZOOM_LEVELS`; above 1 each cell shows a block of tiles and ships are single glyphs
+    zoom: u32,
+    /// Colors for tiles, the local ship and the panes around the view
+    theme: Theme,
+    /// Glyphs every cell and border passes through on the way to the screen
+    charset: Charset,
+    /// Ambient light in percent; tile colors are dimmed to it
+    brightness: u32,
 }
 
 impl Renderer {
@@ -624,6 +1825,13 @@ impl Renderer {
             star_chars: ['.', '+', '*', 'o'],
             asteroid_chars: ['o', 'O', '0', '@'],
             effects_enabled,
+            minimap: Minimap::default(),
+            ship_class: ShipClass::default(),
+            livery: ShipLivery::default(),
+            zoom: 1,
+            theme: Theme::default(),
+            charset: Charset::default(),
+            brightness: 100,
         }
     }
 
@@ -631,39 +1839,69 @@ impl Renderer {
         self.effects_enabled = !self.effects_enabled;
     }
 
+    /// Step to the next zoom level in or out; returns false when already at the end
+    fn step_zoom(&mut self, outward: bool) -> bool {
+        let level = ZOOM_LEVELS.iter().position(|&z| z == self.zoom).unwrap_or(0);
+        let next = if outward { level + 1 } else { level.wrapping_sub(1) };
+        match ZOOM_LEVELS.get(next) {
+            Some(&zoom) => {
+                self.zoom = zoom;
+                true
+            }
+            None => false,
+        }
+    }
+
     fn tick(&mut self) {
         self.frame = self.frame.wrapping_add(1);
     }
 
-    /// Get the visual representation of a tile at a position
-    fn render_tile(&self, tile: Option<Tile>, x: i32, y: i32) -> (char, u32) {
+    /// Get the visual representation of a tile at a position in a biome, in the ambient light
+    fn render_tile(&self, tile: Option<Tile>, biome: Biome, x: i32, y: i32) -> (char, u32) {
+        let (ch, color) = self.tile_style(tile, biome, x, y);
+        (ch, light::dim(self.tint(color, biome), self.brightness))
+    }
+
+    /// A color tinted toward its biome's; empty space stays black
+    fn tint(&self, color: u32, biome: Biome) -> u32 {
+        match regions::tint_index(biome) {
+            Some(index) if color != 0 => blend(color, self.theme.biomes[index].0, 1, regions::TINT_STEPS),
+            _ => color,
+        }
+    }
+
+    /// A tile's glyph and color in full light
+    fn tile_style(&self, tile: Option<Tile>, biome: Biome, x: i32, y: i32) -> (char, u32) {
         let pos_hash = hash_position(x, y, 42);
+        let theme = &self.theme;
 
         // Simplified rendering when effects are disabled
         if !self.effects_enabled {
             return match tile {
-                Some(Tile::Wall) => ('█', 0x4060A0),  // Simple blue wall
+                Some(Tile::Wall) => ('█', theme.wall.0),  // Simple wall
                 Some(Tile::Floor) => (' ', 0x000000), // Plain black
-                Some(Tile::Asteroid) => ('@', 0x808080), // Simple gray asteroid
+                Some(Tile::Asteroid) => ('@', theme.asteroids[3].0), // Simple asteroid
                 Some(Tile::Nebula) => (' ', 0x000000), // Plain black (passable)
+                Some(Tile::OreAsteroid) => ('@', theme.ore[0].0), // Gold-flecked asteroid
+                Some(Tile::CrystalField) => ('◆', theme.crystal[0].0),
+                Some(Tile::Unknown) => UNKNOWN_TILE_STYLE,
                 None => (' ', 0x000000),
             };
         }
 
         match tile {
             Some(Tile::Wall) => {
-                // Subtle wall colors - mostly blue with occasional variation
+                // Subtle wall colors - mostly shaded walls with occasional accents
                 let wall_variant = pos_hash % 100;
                 let base_color = if wall_variant < 70 {
-                    // Standard blue walls
-                    let intensity = 0x50 + ((pos_hash % 0x20) as u32);
-                    (0x20 << 16) | (intensity << 8) | 0xC0
+                    // Standard walls
+                    blend(theme.wall_shades[0].0, theme.wall_shades[1].0, pos_hash % 0x20, 0x20)
                 } else if wall_variant < 85 {
                     // Slightly cyan-tinted
-                    0x3090A0
+                    theme.wall_accents[0].0
                 } else {
                     // Occasional purple accent
-                    0x604080
+                    theme.wall_accents[1].0
                 };
 
                 // Mostly solid blocks
@@ -677,20 +1915,19 @@ impl Renderer {
             }
 
             Some(Tile::Floor) => {
-                // Sparse starfield
-                let star_chance = pos_hash % 50;  // Less frequent stars
+                // Sparse starfield, thicker or thinner by biome
+                let star_chance = pos_hash % regions::star_odds(biome);
 
                 if star_chance == 0 {
                     // Twinkling star (slower animation)
                     let twinkle = ((self.frame / 16) + (pos_hash as u64)) % 4;
-                    let colors = [0xC0C0C0, 0xD0D0A0, 0xA0C0C0, 0xC0C0C0];
-                    (self.star_chars[twinkle as usize], colors[twinkle as usize])
+                    (self.star_chars[twinkle as usize], theme.stars[twinkle as usize].0)
                 } else if star_chance == 1 {
                     // Blue star
-                    ('.', 0x5070C0)
+                    ('.', theme.faint_stars[0].0)
                 } else if star_chance == 2 {
                     // Dim white star
-                    ('.', 0x505050)
+                    ('.', theme.faint_stars[1].0)
                 } else {
                     // Empty space
                     (' ', 0x000000)
@@ -703,40 +1940,23 @@ impl Renderer {
                 let ch = self.asteroid_chars[rotation as usize];
 
                 // Muted asteroid colors
-                let color_variant = pos_hash % 4;
-                let color = match color_variant {
-                    0 => 0x907050, // Brown
-                    1 => 0x707070, // Grey
-                    2 => 0x806040, // Dark brown
-                    _ => 0x808080, // Light grey
-                };
-
-                (ch, color)
+                (ch, theme.asteroids[(pos_hash % 4) as usize].0)
             }
 
             Some(Tile::Nebula) => {
                 // Subtle nebula with slow animation
                 let flow = ((self.frame / 12) as i32 + x / 5 + y / 4) % 20;
 
-                // Muted nebula colors by region
-                let region = hash_position(x / 20, y / 20, 123);
-                let base_hue = region % 6;
-
-                let (r, g, b) = match base_hue {
-                    0 => (0x80, 0x40, 0x80), // Soft purple
-                    1 => (0x40, 0x70, 0x80), // Muted cyan
-                    2 => (0x80, 0x50, 0x40), // Soft orange
-                    3 => (0x50, 0x40, 0x80), // Deep purple
-                    4 => (0x40, 0x70, 0x50), // Soft green
-                    _ => (0x50, 0x50, 0x70), // Grey-blue
-                };
+                // Muted nebula colors by region; region 2 is ion storms
+                let tint = theme.nebula[hull::nebula_region(x, y) as usize].0;
+                let (r, g, b) = ((tint >> 16) as i32, (tint >> 8 & 0xFF) as i32, (tint & 0xFF) as i32);
 
                 // Gentler pulsing
                 let pulse = ((flow as u32 % 10) * 3) as i32;
                 let dim = 20 + (pos_hash % 20) as i32;
-                let color = ((((r as i32 - dim + pulse).max(0).min(255)) as u32) << 16)
-                    | ((((g as i32 - dim + pulse).max(0).min(255)) as u32) << 8)
-                    | (((b as i32 - dim + pulse).max(0).min(255)) as u32);
+                let color = (((r - dim + pulse).clamp(0, 255) as u32) << 16)
+                    | (((g - dim + pulse).clamp(0, 255) as u32) << 8)
+                    | ((b - dim + pulse).clamp(0, 255) as u32);
 
                 // Fewer animated characters
                 let ch = match (pos_hash + self.frame as u32 / 12) % 8 {
@@ -748,9 +1968,25 @@ impl Renderer {
                 (ch, color)
             }
 
+            Some(Tile::OreAsteroid) => {
+                // Rotates like a plain asteroid, in gold
+                let rotation = ((self.frame / 24) + (pos_hash as u64 / 3)) % 4;
+                let color = theme.ore[(pos_hash % 2) as usize].0;
+                (self.asteroid_chars[rotation as usize], color)
+            }
+
+            Some(Tile::CrystalField) => {
+                // Slow glint between two shades of cyan
+                let glint = ((self.frame / 20) + pos_hash as u64).is_multiple_of(6);
+                ('◆', theme.crystal[glint as usize].0)
+            }
+
+            // A tile kind from a newer server: solid, and obviously not something we can draw
+            Some(Tile::Unknown) => UNKNOWN_TILE_STYLE,
+
             None => {
                 // Out of bounds - mostly empty
-                if pos_hash % 100 == 0 {
+                if pos_hash.is_multiple_of(100) {
                     ('.', 0x202030)
                 } else {
                     (' ', 0x000000)
@@ -759,6 +1995,21 @@ impl Renderer {
         }
     }
 
+    /// Dim static for tiles the player hasn't explored yet
+    fn render_fog(&self, x: i32, y: i32) -> (char, u32) {
+        // Static crawls when effects are on, and holds still otherwise
+        let phase = if self.effects_enabled { (self.frame / 6) as u32 } else { 0 };
+        let noise = hash_position(x, y, 77u32.wrapping_add(phase));
+        let ch = match noise % 16 {
+            0 => '░',
+            1 | 2 => '·',
+            3 => ':',
+            _ => ' ',
+        };
+        let level = 0x18 + (noise >> 8) % 0x18;
+        (ch, (level << 16) | (level << 8) | level)
+    }
+
     /// Check if a screen offset from center is part of the ship or exhaust
     /// Returns Some(ShipCell) if it should be rendered as ship/exhaust, None otherwise
     /// offset_x/y are relative to player center (0,0 = center of ship)
@@ -768,8 +2019,8 @@ impl Renderer {
         //            col 0 = x offset -1, col 1 = x offset 0, col 2 = x offset 1
 
         // Check if in ship bounds (3x3 centered on player)
-        if offset_x >= -1 && offset_x <= 1 && offset_y >= -1 && offset_y <= 1 {
-            let ship = ShipSprite::for_direction(direction);
+        if (-1..=1).contains(&offset_x) && (-1..=1).contains(&offset_y) {
+            let ship = ShipSprite::for_class_and_direction(self.ship_class, direction, &self.theme.ship_palette().painted(&self.livery));
             let row = (offset_y + 1) as usize;
             let col = (offset_x + 1) as usize;
             let cell = ship.cells[row][col];
@@ -788,7 +2039,7 @@ impl Renderer {
         let rel_x = offset_x - exhaust_offset_x;
         let rel_y = offset_y - exhaust_offset_y;
 
-        if rel_x >= 0 && rel_x < 3 && rel_y >= 0 && rel_y < 4 {
+        if (0..3).contains(&rel_x) && (0..4).contains(&rel_y) {
             let cell = exhaust.cells[rel_y as usize][rel_x as usize];
             if cell.ch != ' ' {
                 return Some(cell);
@@ -798,6 +2049,82 @@ impl Renderer {
         None
     }
 
+    /// Cells covered by other players' ships, keyed by zoomed cell (map tile at 1x)
+    /// Remote ships use the remote palette under their pilot's paint and are drawn without exhaust
+    fn remote_ship_cells<'a>(&self, players: impl Iterator<Item = &'a PlayerInfo>) -> HashMap<(i32, i32), ShipCell> {
+        let mut cells = HashMap::new();
+        for player in players {
+            stamp_ship(&mut cells, player.x, player.y, player.direction, &ShipPalette::pilot(player), self.zoom as i32);
+        }
+        cells
+    }
+
+    /// Cells covered by entities: ships in their sprite's palette (NPCs by behavior), anything else a single glyph
+    fn entity_cells(&self, entities: impl Iterator<Item = (Entity, Position, Sprite)>) -> HashMap<(i32, i32), ShipCell> {
+        let zoom = self.zoom as i32;
+        let mut cells = HashMap::new();
+        for (_, position, sprite) in entities {
+            match ShipPalette::for_sprite(sprite.kind) {
+                Some(palette) => stamp_ship(&mut cells, position.x, position.y, sprite.direction, &palette, zoom),
+                None => {
+                    let (ch, fg) = sprite_style(sprite);
+                    cells.insert((position.x.div_euclid(zoom), position.y.div_euclid(zoom)), ShipCell::new(ch, fg));
+                }
+            }
+        }
+        cells
+    }
+}
+
+/// Border, text and background colors of a panel
+type PanelColors = (u32, u32, u32);
+
+/// Rows and columns a panel of text takes up, border included
+fn panel_size(title: &str, lines: &[String]) -> (u32, u32) {
+    let inner_w = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0).max(title.chars().count());
+    (lines.len() as u32 + 2, inner_w as u32 + 2)
+}
+
+/// Draw a bordered panel of text centered in the game area; skipped when it doesn't fit
+fn draw_panel(plane: &mut impl Surface, term_width: u32, game_height: u32, title: &str, lines: &[String], colors: PanelColors, charset: Charset) -> NcResult<()> {
+    let (border, text, bg) = colors;
+    let (box_h, box_w) = panel_size(title, lines);
+    let inner_w = box_w as usize - 2;
+    if term_width < box_w || game_height < box_h {
+        return Ok(());
+    }
+    let (origin_x, origin_y) = ((term_width - box_w) / 2, (game_height - box_h) / 2);
+    plane.set_bg_rgb(bg);
+    plane.set_fg_rgb(border);
+    let top = format!("┌{:─^width$}┐", title, width = inner_w);
+    let bottom = format!("└{}┘", "─".repeat(inner_w));
+    plane.putstr_yx(Some(origin_y), Some(origin_x), &charset.text(&top))?;
+    plane.putstr_yx(Some(origin_y + box_h - 1), Some(origin_x), &charset.text(&bottom))?;
+    for (i, line) in lines.iter().enumerate() {
+        let screen_y = origin_y + 1 + i as u32;
+        plane.set_fg_rgb(border);
+        plane.putstr_yx(Some(screen_y), Some(origin_x), &charset.text("│"))?;
+        plane.putstr_yx(Some(screen_y), Some(origin_x + box_w - 1), &charset.text("│"))?;
+        plane.set_fg_rgb(text);
+        plane.putstr_yx(Some(screen_y), Some(origin_x + 1), &format!("{:<width$}", line, width = inner_w))?;
+    }
+    Ok(())
+}
+
+/// Paint a 3x3 scout sprite centered on (x, y) into a map of cells; zoomed out, just its direction glyph
+fn stamp_ship(cells: &mut HashMap<(i32, i32), ShipCell>, x: i32, y: i32, direction: Direction, palette: &ShipPalette, zoom: i32) {
+    if zoom > 1 {
+        cells.insert((x.div_euclid(zoom), y.div_euclid(zoom)), ShipCell::new(direction.to_char(), palette.hull));
+        return;
+    }
+    let sprite = ShipSprite::for_class_and_direction(ShipClass::Scout, direction, palette);
+    for (row, line) in sprite.cells.iter().enumerate() {
+        for (col, cell) in line.iter().enumerate() {
+            if cell.ch != ' ' {
+                cells.insert((x + col as i32 - 1, y + row as i32 - 1), *cell);
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -832,40 +2159,35 @@ impl Default for InputState {
             left: KeyState::default(),
             right: KeyState::default(),
             has_release_support: false,
-            key_timeout: Duration::from_millis(300),
+            key_timeout: Duration::from_millis(DEFAULT_KEY_TIMEOUT_MS),
         }
     }
 }
 
 impl InputState {
-    fn update_key(&mut self, key: NcKey, evtype: NcInputType) {
-        let key_state = match key {
-            NcKey::Up => &mut self.up,
-            NcKey::Down => &mut self.down,
-            NcKey::Left => &mut self.left,
-            NcKey::Right => &mut self.right,
-            _ => return,
-        };
-
-        match evtype {
-            NcInputType::Press | NcInputType::Repeat | NcInputType::Unknown => {
-                key_state.held = true;
-                key_state.last_seen = Instant::now();
-            }
-            NcInputType::Release => {
-                key_state.held = false;
-                self.has_release_support = true;
+    fn update_move(&mut self, action: Action, evtype: NcInputType, now: Instant) {
+        // A diagonal key holds both of its directions
+        let (dx, dy) = action.direction();
+        let states = [(dy < 0, &mut self.up), (dy > 0, &mut self.down), (dx < 0, &mut self.left), (dx > 0, &mut self.right)];
+        for key_state in states.into_iter().filter_map(|(pressed, state)| pressed.then_some(state)) {
+            match evtype {
+                NcInputType::Press | NcInputType::Repeat | NcInputType::Unknown => {
+                    key_state.held = true;
+                    key_state.last_seen = now;
+                }
+                NcInputType::Release => {
+                    key_state.held = false;
+                    self.has_release_support = true;
+                }
             }
         }
     }
 
-    fn timeout_stale_keys(&mut self) {
+    fn timeout_stale_keys(&mut self, now: Instant) {
         if self.has_release_support {
             return;
         }
 
-        let now = Instant::now();
-
         if self.up.held && now.duration_since(self.up.last_seen) > self.key_timeout {
             self.up.held = false;
         }
@@ -905,10 +2227,184 @@ impl InputState {
     }
 }
 
+/// Draw the chat log filling `plane`: a page of messages and a footer with where it is
+fn draw_chat_log(plane: &mut impl Surface, rows: u32, cols: u32, chat: &mut ChatWindow, charset: Charset) -> NcResult<()> {
+    let inner_w = cols as usize - 2;
+    let lines = chatlog::lines(chat, inner_w);
+    let range = chat.log.view(lines.len(), rows as usize - 3);
+    plane.set_bg_rgb(0x000010);
+    plane.set_fg_rgb(0x808080);
+    let top = format!("┌{:─^width$}┐", " CHAT LOG ", width = inner_w);
+    let bottom = format!("└{}┘", "─".repeat(inner_w));
+    plane.putstr_yx(Some(0), Some(0), &charset.text(&top))?;
+    plane.putstr_yx(Some(rows - 1), Some(0), &charset.text(&bottom))?;
+    for y in 1..rows - 1 {
+        plane.set_fg_rgb(0x808080);
+        plane.putstr_yx(Some(y), Some(0), &charset.text("│"))?;
+        plane.putstr_yx(Some(y), Some(cols - 1), &charset.text("│"))?;
+        plane.putstr_yx(Some(y), Some(1), &" ".repeat(inner_w))?;
+    }
+    for (y, line) in (1..).zip(&lines[range.clone()]) {
+        if let Some(time) = &line.time {
+            plane.set_fg_rgb(0x606080);
+            plane.putstr_yx(Some(y), Some(1), time)?;
+        }
+        plane.set_fg_rgb(line.color);
+        plane.putstr_yx(Some(y), Some(1 + TIME_WIDTH as u32), &line.text)?;
+    }
+    let position = if lines.is_empty() {
+        "empty".to_string()
+    } else {
+        format!("{}-{} of {}", range.start + 1, range.end, lines.len())
+    };
+    let footer = format!("PgUp/PgDn page, Up/Down line, Home/End, Esc closes  ({}, times UTC)", position);
+    plane.set_fg_rgb(0x808080);
+    plane.putstr_yx(Some(rows - 2), Some(1), &footer.chars().take(inner_w).collect::<String>())?;
+    Ok(())
+}
+
+/// Draw the chat pane filling `plane`: the visible messages, then the input line (or the hint to open it)
+fn draw_chat_pane(plane: &mut impl Surface, cols: u32, chat: &ChatWindow, background: u32) -> NcResult<()> {
+    let width = cols as usize;
+    plane.set_bg_rgb(background);
+    for (i, msg) in chat.visible_messages().enumerate() {
+        plane.set_fg_rgb(chat.color_of(msg));
+        let truncated: String = msg.text.chars().take(width).collect();
+        plane.putstr_yx(Some(i as u32), Some(0), &format!("{:<width$}", truncated))?;
+
+        // Shared coordinates stand out so `/go last` has something to point at
+        plane.set_fg_rgb(0x40FFFF);
+        for (span, _) in &msg.coords {
+            let column = msg.text[..span.start].chars().count();
+            if column < width {
+                let shown: String = msg.text[span.clone()].chars().take(width - column).collect();
+                plane.putstr_yx(Some(i as u32), Some(column as u32), &shown)?;
+            }
+        }
+    }
+    // Fill remaining message lines if fewer messages
+    plane.set_fg_rgb(0x404040);
+    for i in chat.visible_messages().count()..chat.visible_lines {
+        plane.putstr_yx(Some(i as u32), Some(0), &" ".repeat(width))?;
+    }
+
+    let input_y = chat.visible_lines as u32;
+    plane.set_bg_rgb(0x000020);
+    if chat.active {
+        plane.set_fg_rgb(0x00FFFF);
+        let input_display: String = chat.input.chars().take(width - 2).collect();
+        plane.putstr_yx(Some(input_y), Some(0), &format!("> {:<width$}", input_display, width = width - 2))?;
+
+        // Matching commands or the command's usage, dimmed after what's typed
+        let hint_x = 2 + chat.input.chars().count() + 2;
+        if let Some(hint) = chat.commands.hint(&chat.input)
+            && hint_x < width
+        {
+            let hint: String = hint.chars().take(width - hint_x).collect();
+            plane.set_fg_rgb(0x407070);
+            plane.putstr_yx(Some(input_y), Some(hint_x as u32), &hint)?;
+        }
+
+        // Show cursor (by inverting colors at cursor position)
+        let cursor_x = 2 + chat.display_cursor_pos();
+        if cursor_x < width {
+            plane.set_fg_rgb(0x000020);
+            plane.set_bg_rgb(0x00FFFF);
+            let cursor_char = chat.input.chars().nth(chat.display_cursor_pos()).unwrap_or(' ');
+            plane.putstr_yx(Some(input_y), Some(cursor_x as u32), &cursor_char.to_string())?;
+        }
+    } else {
+        plane.set_fg_rgb(0x606060);
+        plane.putstr_yx(Some(input_y), Some(0), &format!("{:<width$}", "Press Enter to chat, / for commands"))?;
+
+        // Let the player know muted channels are still receiving messages
+        if chat.muted_count > 0 {
+            let muted = format!("[{} muted]", chat.muted_count);
+            if muted.len() + 40 < width {
+                plane.set_fg_rgb(0x808060);
+                plane.putstr_yx(Some(input_y), Some(cols - muted.len() as u32 - 1), &muted)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Gray border around a widget's plane, its title centered in the top edge
+fn draw_box(plane: &mut impl Surface, rows: u32, cols: u32, title: &str, charset: Charset) -> NcResult<()> {
+    let inner_w = cols as usize - 2;
+    plane.set_bg_default();
+    plane.set_fg_rgb(0x808080);
+    let top: String = format!("┌{:─^width$}┐", title, width = inner_w).chars().take(cols as usize).collect();
+    let bottom = format!("└{}┘", "─".repeat(inner_w));
+    plane.putstr_yx(Some(0), Some(0), &charset.text(&top))?;
+    plane.putstr_yx(Some(rows - 1), Some(0), &charset.text(&bottom))?;
+    for y in 1..rows - 1 {
+        plane.putstr_yx(Some(y), Some(0), &charset.text("│"))?;
+        plane.putstr_yx(Some(y), Some(cols - 1), &charset.text("│"))?;
+    }
+    Ok(())
+}
+
+/// What a script sees: the ship and the map around it
+fn script_view(player: &Player, autopilot: bool, map: &Map) -> scripting::View {
+    scripting::View::capture((player.x, player.y), player.direction.name(), autopilot, |x, y| {
+        (map.get(x, y), map.is_passable(x, y), map.explored.is_explored(x, y))
+    })
+}
+
+/// Send the lines a script asked for as if typed, and show its prints and errors
+fn apply_script_report(result: Result<scripting::Report, String>, chat: &mut ChatWindow, commands: &mut VecDeque<ChatCommand>) {
+    match result {
+        Ok(report) => {
+            for text in report.printed {
+                chat.add_message(ChatMessage::system(&text));
+            }
+            for line in report.lines {
+                if let Some(cmd) = chat.process_input(&line) {
+                    commands.push_back(cmd);
+                }
+            }
+        }
+        Err(e) => chat.add_message(ChatMessage::error(&e)),
+    }
+}
+
+/// Load a theme by name and draw the view and chat pane with it
+fn apply_theme(name: &str, renderer: &mut Renderer, chat: &mut ChatWindow) -> Result<(), String> {
+    let theme = Theme::load(name)?;
+    chat.apply_theme(&theme);
+    renderer.theme = theme;
+    Ok(())
+}
+
+/// A key as the screens see it
+fn screen_key(received: NcReceived) -> Key {
+    match received {
+        NcReceived::Char(ch) => Key::Char(ch),
+        NcReceived::Key(NcKey::Up) => Key::Up,
+        NcReceived::Key(NcKey::Down) => Key::Down,
+        NcReceived::Key(NcKey::Enter) => Key::Enter,
+        NcReceived::Key(NcKey::Esc) => Key::Esc,
+        NcReceived::Key(NcKey::Backspace) => Key::Backspace,
+        _ => Key::Other,
+    }
+}
+
+/// Below this speed a drifting ship comes to rest
+const MIN_DRIFT_SPEED: f32 = 0.02;
+
 struct Player {
     x: i32,
     y: i32,
     direction: Direction,
+    /// Velocity in tiles per tick
+    vx: f32,
+    vy: f32,
+    /// Distance travelled that doesn't add up to a whole tile yet
+    carry_x: f32,
+    carry_y: f32,
+    /// Speed of a crash into rock on the last tick
+    impact: Option<f32>,
 }
 
 impl Player {
@@ -917,136 +2413,540 @@ impl Player {
             x,
             y,
             direction: Direction::Up,
+            vx: 0.0,
+            vy: 0.0,
+            carry_x: 0.0,
+            carry_y: 0.0,
+            impact: None,
         }
     }
 
-    fn try_move(&mut self, dx: i32, dy: i32, map: &Map) -> bool {
-        if dx == 0 && dy == 0 {
-            return false;
-        }
+    /// Kill all momentum, e.g. after a teleport
+    fn stop(&mut self) {
+        self.vx = 0.0;
+        self.vy = 0.0;
+        self.carry_x = 0.0;
+        self.carry_y = 0.0;
+    }
 
-        if let Some(dir) = Direction::from_delta(dx, dy) {
+    /// One movement tick: apply thrust (-1, 0 or 1 per axis), drag, then drift; returns whether the ship moved
+    fn update(&mut self, thrust: (i32, i32), physics: &ShipPhysics, map: &Map) -> bool {
+        let (tx, ty) = thrust;
+        self.impact = None;
+        if let Some(dir) = Direction::from_delta(tx, ty) {
             self.direction = dir;
         }
 
-        let new_x = self.x + dx;
-        let new_y = self.y + dy;
-
-        if map.is_passable(new_x, new_y) {
-            self.x = new_x;
-            self.y = new_y;
-            return true;
+        // Diagonal thrust is split between the axes so it isn't faster, unless diagonals are classic
+        let split = tx != 0 && ty != 0 && physics.diagonals == Diagonals::Uniform;
+        let power = if split { physics.thrust * std::f32::consts::FRAC_1_SQRT_2 } else { physics.thrust };
+        self.vx += tx as f32 * power;
+        self.vy += ty as f32 * power;
+
+        let nebula_drag = if map.get(self.x, self.y) == Some(Tile::Nebula) { physics.nebula_drag } else { 0.0 };
+        let keep = (1.0 - physics.drag - nebula_drag).clamp(0.0, 1.0);
+        self.vx *= keep;
+        self.vy *= keep;
+
+        // Uniform diagonals cap the speed along the heading, classic ones each axis on its own
+        let speed = self.vx.hypot(self.vy);
+        let over = match physics.diagonals {
+            Diagonals::Uniform => speed / physics.max_speed,
+            Diagonals::Classic => self.vx.abs().max(self.vy.abs()) / physics.max_speed,
+        };
+        if over > 1.0 {
+            self.vx /= over;
+            self.vy /= over;
+        } else if speed < MIN_DRIFT_SPEED && thrust == (0, 0) {
+            self.stop();
+            return false;
         }
 
-        if dx != 0 && dy != 0 {
-            if map.is_passable(self.x + dx, self.y) {
-                self.x += dx;
-                return true;
+        // Move a tile at a time so nothing is skipped; hitting something kills speed on that axis
+        self.carry_x += self.vx;
+        self.carry_y += self.vy;
+        let mut moved = false;
+        while self.carry_x.abs() >= 1.0 {
+            let step = self.carry_x.signum() as i32;
+            if !map.is_passable(self.x + step, self.y) {
+                if hull::is_rock(map.get(self.x + step, self.y)) {
+                    self.impact = Some(self.vx.abs());
+                }
+                self.vx = 0.0;
+                self.carry_x = 0.0;
+                break;
             }
-            if map.is_passable(self.x, self.y + dy) {
-                self.y += dy;
-                return true;
+            self.x += step;
+            self.carry_x -= step as f32;
+            moved = true;
+        }
+        while self.carry_y.abs() >= 1.0 {
+            let step = self.carry_y.signum() as i32;
+            if !map.is_passable(self.x, self.y + step) {
+                if hull::is_rock(map.get(self.x, self.y + step)) {
+                    self.impact = Some(self.impact.unwrap_or(0.0).max(self.vy.abs()));
+                }
+                self.vy = 0.0;
+                self.carry_y = 0.0;
+                break;
             }
+            self.y += step;
+            self.carry_y -= step as f32;
+            moved = true;
         }
-
-        false
+        moved
     }
 }
 
-/// A message in the chat history
-#[derive(Clone)]
-struct ChatMessage {
-    text: String,
-    color: u32,
+/// Picture-in-picture camera showing the area around a target position
+struct PipCamera {
+    /// Map position the camera is centered on
+    target: Option<(i32, i32)>,
+    /// Whether the PiP window is shown
+    visible: bool,
+    /// Outer width including border
+    width: u32,
+    /// Outer height including border
+    height: u32,
 }
 
-impl ChatMessage {
-    fn new(text: String, color: u32) -> Self {
-        ChatMessage {
-            text,
-            color,
+impl Default for PipCamera {
+    fn default() -> Self {
+        PipCamera {
+            target: None,
+            visible: false,
+            width: 25,
+            height: 11,
         }
     }
+}
 
-    fn system(text: &str) -> Self {
-        ChatMessage::new(text.to_string(), 0xFFFF00) // Yellow for system messages
+impl PipCamera {
+    /// Toggle the PiP window
+    fn toggle(&mut self) {
+        self.visible = !self.visible;
     }
 
-    fn user(text: &str) -> Self {
-        ChatMessage::new(text.to_string(), 0x00FF00) // Green for user input
+    /// Point the camera at a map position and show it
+    fn set_target(&mut self, x: i32, y: i32) {
+        self.target = Some((x, y));
+        self.visible = true;
     }
 
-    fn error(text: &str) -> Self {
-        ChatMessage::new(text.to_string(), 0xFF4444) // Red for errors
+    /// Forget the target and hide the window
+    fn clear_target(&mut self) {
+        self.target = None;
+        self.visible = false;
     }
-}
 
-/// Chat/command window state
-struct ChatWindow {
-    /// Whether chat input is active
-    active: bool,
-    /// Current input buffer
-    input: String,
-    /// Cursor position in input
-    cursor: usize,
-    /// Message history (newest last)
-    messages: Vec<ChatMessage>,
-    /// Maximum messages to keep
-    max_messages: usize,
-    /// Number of visible message lines
-    visible_lines: usize,
-}
+    /// Whether there is anything to draw
+    fn is_active(&self) -> bool {
+        self.visible && self.target.is_some()
+    }
 
-impl Default for ChatWindow {
-    fn default() -> Self {
-        ChatWindow {
-            active: false,
-            input: String::new(),
-            cursor: 0,
-            messages: Vec::new(),
-            max_messages: 100,
-            visible_lines: 3,
+    /// Top-left screen cell of the window (top-right corner of the game area),
+    /// or None if the game area is too small to fit it
+    fn screen_origin(&self, term_width: u32, game_height: u32) -> Option<(u32, u32)> {
+        if term_width < self.width + 2 || game_height < self.height + 2 {
+            return None;
         }
+        Some((term_width - self.width - 1, 1))
     }
-}
 
-impl ChatWindow {
-    fn new() -> Self {
-        let mut chat = ChatWindow::default();
-        chat.add_message(ChatMessage::system("Welcome to Exospace! Press Enter to chat, / for commands."));
-        chat
+    /// Map position shown at an inner cell of the window (0,0 = top-left inside the border)
+    fn map_coords(&self, col: u32, row: u32) -> Option<(i32, i32)> {
+        let (tx, ty) = self.target?;
+        let half_w = (self.width as i32 - 2) / 2;
+        let half_h = (self.height as i32 - 2) / 2;
+        Some((tx + col as i32 - half_w, ty + row as i32 - half_h))
     }
+}
 
-    /// Toggle chat input mode
+/// Scaled-down overview of the map in the top-left corner ('m' toggles)
+#[derive(Default)]
+struct Minimap {
+    visible: bool,
+}
+
+impl Minimap {
     fn toggle(&mut self) {
-        self.active = !self.active;
-        if !self.active {
-            self.input.clear();
-            self.cursor = 0;
-        }
+        self.visible = !self.visible;
     }
 
-    /// Open chat (if not already open)
-    fn open(&mut self) {
-        self.active = true;
+    /// Outer size including the border, scaled to the terminal; None if it won't fit
+    fn size(term_width: u32, game_height: u32) -> Option<(u32, u32)> {
+        let inner_w = (term_width / 4).clamp(16, 48);
+        let inner_h = (game_height / 3).clamp(6, 20);
+        (term_width >= inner_w + 4 && game_height >= inner_h + 4).then_some((inner_w + 2, inner_h + 2))
     }
 
-    /// Close chat without submitting
-    fn close(&mut self) {
-        self.active = false;
-        self.input.clear();
-        self.cursor = 0;
+    /// Glyph and color for a minimap cell
+    fn cell_style(tile: Option<Tile>) -> (char, u32) {
+        match tile {
+            Some(Tile::Wall) => ('█', 0x304880),
+            Some(Tile::Floor) => ('·', 0x303040),
+            Some(Tile::Asteroid) => ('*', 0xB08050), // Brown
+            Some(Tile::Nebula) => ('░', 0x9050B0),   // Purple
+            Some(Tile::OreAsteroid) => ('$', 0xD0A040), // Gold
+            Some(Tile::CrystalField) => ('◆', 0x40C0C0), // Cyan
+            Some(Tile::Unknown) => UNKNOWN_TILE_STYLE,
+            None => (' ', 0x000000),                 // Unexplored
+        }
     }
+}
 
-    /// Add a character at cursor position
-    fn insert_char(&mut self, ch: char) {
-        self.input.insert(self.cursor, ch);
-        self.cursor += ch.len_utf8();
-    }
+/// How one frame of the minimap maps its cells onto the world
+struct MinimapView {
+    min_x: i32,
+    min_y: i32,
+    /// Tiles covered by one cell along each axis
+    scale_x: i32,
+    scale_y: i32,
+    inner_w: u32,
+    inner_h: u32,
+}
+
+impl MinimapView {
+    /// Fit an area (max exclusive) that always includes the player into the inner cells
+    fn new(area: Option<(i32, i32, i32, i32)>, player: (i32, i32), inner_w: u32, inner_h: u32) -> Self {
+        let (px, py) = player;
+        let (min_x, min_y, max_x, max_y) = area.unwrap_or((px, py, px + 1, py + 1));
+        let (min_x, min_y) = (min_x.min(px), min_y.min(py));
+        let (max_x, max_y) = (max_x.max(px + 1), max_y.max(py + 1));
+        MinimapView {
+            min_x,
+            min_y,
+            scale_x: ((max_x - min_x) as u32).div_ceil(inner_w).max(1) as i32,
+            scale_y: ((max_y - min_y) as u32).div_ceil(inner_h).max(1) as i32,
+            inner_w,
+            inner_h,
+        }
+    }
+
+    /// Inner cell containing a world position, if it's on the minimap
+    fn cell_for(&self, x: i32, y: i32) -> Option<(u32, u32)> {
+        let col = (x - self.min_x).div_euclid(self.scale_x);
+        let row = (y - self.min_y).div_euclid(self.scale_y);
+        ((0..self.inner_w as i32).contains(&col) && (0..self.inner_h as i32).contains(&row))
+            .then_some((col as u32, row as u32))
+    }
+
+    /// Most notable explored tile under a cell: resources, asteroids, nebulae, unknown kinds, mostly-wall, then open space
+    fn summarize(&self, map: &Map, col: u32, row: u32) -> Option<Tile> {
+        let x0 = self.min_x + col as i32 * self.scale_x;
+        let y0 = self.min_y + row as i32 * self.scale_y;
+        // Sample at most 3x3 tiles per cell so big maps stay cheap to redraw every frame
+        let step_x = (self.scale_x / 3).max(1) as usize;
+        let step_y = (self.scale_y / 3).max(1) as usize;
+
+        let (mut seen, mut walls, mut asteroid, mut nebula, mut unknown) = (0, 0, false, false, false);
+        let mut resource = None;
+        for y in (y0..y0 + self.scale_y).step_by(step_y) {
+            for x in (x0..x0 + self.scale_x).step_by(step_x) {
+                if !map.explored.is_explored(x, y) {
+                    continue;
+                }
+                match map.get(x, y) {
+                    Some(Tile::Wall) => walls += 1,
+                    Some(Tile::Asteroid) => asteroid = true,
+                    Some(Tile::Nebula) => nebula = true,
+                    Some(tile @ (Tile::OreAsteroid | Tile::CrystalField)) => resource = resource.or(Some(tile)),
+                    Some(Tile::Unknown) => unknown = true,
+                    Some(Tile::Floor) => {}
+                    None => continue,
+                }
+                seen += 1;
+            }
+        }
+
+        match () {
+            _ if seen == 0 => None,
+            _ if resource.is_some() => resource,
+            _ if asteroid => Some(Tile::Asteroid),
+            _ if nebula => Some(Tile::Nebula),
+            _ if unknown => Some(Tile::Unknown),
+            _ if walls * 2 > seen => Some(Tile::Wall),
+            _ => Some(Tile::Floor),
+        }
+    }
+}
+
+/// Where a chat line came from; each channel can be recolored or muted in config
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+enum ChatChannel {
+    System,
+    /// Your own typed lines
+    Input,
+    Error,
+    /// Other players' chat
+    Player,
+    /// Roll, flip, time and distance results
+    Utility,
+    /// Station and relay chatter
+    Ambient,
+}
+
+impl ChatChannel {
+    /// Spelling in config, and in the headless transcript
+    fn name(self) -> &'static str {
+        match self {
+            ChatChannel::System => "system",
+            ChatChannel::Input => "input",
+            ChatChannel::Error => "error",
+            ChatChannel::Player => "player",
+            ChatChannel::Utility => "utility",
+            ChatChannel::Ambient => "ambient",
+        }
+    }
+}
+
+/// Parse a "#RRGGBB" (or bare "RRGGBB") color
+fn parse_hex_color(text: &str) -> Option<u32> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+/// A message in the chat history
+#[derive(Clone)]
+struct ChatMessage {
+    channel: ChatChannel,
+    text: String,
+    color: u32,
+    /// Coordinates mentioned in the text, as (byte span, position), highlighted when drawn
+    coords: Vec<(Range<usize>, (i32, i32))>,
+    /// When it arrived, shown in the chat log
+    time: SystemTime,
+}
+
+impl ChatMessage {
+    fn new(channel: ChatChannel, text: String, color: u32) -> Self {
+        ChatMessage {
+            channel,
+            text,
+            color,
+            coords: Vec::new(),
+            time: SystemTime::now(),
+        }
+    }
+
+    fn system(text: &str) -> Self {
+        ChatMessage::new(ChatChannel::System, text.to_string(), 0xFFFF00) // Yellow for system messages
+    }
+
+    fn user(text: &str) -> Self {
+        ChatMessage::new(ChatChannel::Input, text.to_string(), 0x00FF00) // Green for user input
+    }
+
+    fn error(text: &str) -> Self {
+        ChatMessage::new(ChatChannel::Error, text.to_string(), 0xFF4444) // Red for errors
+    }
+
+    /// A failed server request, after `context`: a system line when the server turned it down, an error when it broke
+    fn server_error(context: &str, error: &ServerError) -> Self {
+        let text = format!("{}: {}", context, error);
+        if error.is_server_fault() { Self::error(&text) } else { Self::system(&text) }
+    }
+
+    /// A line from another player, colored per player so conversations are easy to follow
+    fn from_player(id: u64, name: &str, text: &str) -> Self {
+        let color = PLAYER_CHAT_COLORS[id as usize % PLAYER_CHAT_COLORS.len()];
+        let mut message = ChatMessage::new(ChatChannel::Player, format!("{}: {}", name, text), color);
+        message.coords = find_coordinates(&message.text);
+        message
+    }
+
+    /// Local chat or an emote from a pilot nearby, in their color
+    fn nearby(id: u64, name: &str, text: &str, emote: bool) -> Self {
+        if !emote {
+            return Self::from_player(id, &format!("{} (nearby)", name), text);
+        }
+        let color = PLAYER_CHAT_COLORS[id as usize % PLAYER_CHAT_COLORS.len()];
+        ChatMessage::new(ChatChannel::Player, format!("* {} {}", name, text), color)
+    }
+
+    /// A server-side command result, colored by kind so rolls stand out from chat
+    fn utility(kind: UtilityKind, text: &str) -> Self {
+        let color = match kind {
+            UtilityKind::Roll => 0xFF80FF,     // Magenta
+            UtilityKind::Flip => 0x80C0FF,     // Light blue
+            UtilityKind::Time => 0xC0C0FF,     // Periwinkle
+            UtilityKind::Distance => 0xA0FFA0, // Pale green
+        };
+        ChatMessage::new(ChatChannel::Utility, text.to_string(), color)
+    }
+
+    /// Station and relay chatter - muted so it stays in the background
+    fn ambient(source: &str, text: &str) -> Self {
+        ChatMessage::new(ChatChannel::Ambient, format!("[{}] {}", source, text), 0x80A090) // Muted sea green
+    }
+}
+
+/// Name colors for other players' chat - avoids the system/user/error colors
+const PLAYER_CHAT_COLORS: [u32; 6] = [
+    0x66CCFF, // Sky blue
+    0xFF99CC, // Pink
+    0xFFB366, // Orange
+    0xB399FF, // Lavender
+    0x66FFCC, // Mint
+    0xE6E6E6, // Off-white
+];
+
+/// Chat/command window state
+struct ChatWindow {
+    /// Whether chat input is active
+    active: bool,
+    /// Current input buffer
+    input: String,
+    /// Cursor position in input
+    cursor: usize,
+    /// Message history (newest last)
+    messages: Vec<ChatMessage>,
+    /// Maximum messages to keep
+    max_messages: usize,
+    /// Number of visible message lines, refitted each frame by `fit_pane()`
+    visible_lines: usize,
+    /// Current pane size
+    pane: ChatPane,
+    /// Message lines in the normal-size pane
+    pane_lines: usize,
+    /// Previous (input, cursor) states for undo (oldest first)
+    undo_stack: Vec<(String, usize)>,
+    /// Whether the last edit was typing, so a run of typed characters undoes as one step
+    typing: bool,
+    /// Colors from config that replace a channel's built-in ones
+    channel_colors: HashMap<ChatChannel, u32>,
+    /// Colors from the theme, used for channels config leaves alone
+    theme_colors: HashMap<ChatChannel, u32>,
+    /// Channels kept out of the chat pane
+    muted: Vec<ChatChannel>,
+    /// Messages hidden because their channel is muted
+    muted_count: usize,
+    /// Latest position another player mentioned, for `/go last`
+    last_coords: Option<(i32, i32)>,
+    /// Lines sent, recalled with Up/Down
+    history: InputHistory,
+    /// Full-screen scrollback over every kept message
+    log: ChatLog,
+    /// Messages not yet written to stdout; only kept headless
+    outbox: Option<Vec<ChatMessage>>,
+    /// Slash commands typed lines are run against
+    commands: CommandRegistry,
+}
+
+/// Maximum number of undo steps kept for the input line
+const MAX_UNDO: usize = 50;
+
+/// How much of the screen the chat pane takes
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ChatPane {
+    Normal,
+    /// Just the input line, for maximum view
+    Collapsed,
+    /// Half the screen, for reading history
+    Expanded,
+}
+
+impl ChatPane {
+    /// Normal → expanded → collapsed → normal
+    fn next(self) -> Self {
+        match self {
+            ChatPane::Normal => ChatPane::Expanded,
+            ChatPane::Expanded => ChatPane::Collapsed,
+            ChatPane::Collapsed => ChatPane::Normal,
+        }
+    }
+}
+
+impl Default for ChatWindow {
+    fn default() -> Self {
+        ChatWindow {
+            active: false,
+            input: String::new(),
+            cursor: 0,
+            messages: Vec::new(),
+            max_messages: MAX_CHAT_MESSAGES,
+            visible_lines: DEFAULT_CHAT_LINES,
+            pane: ChatPane::Normal,
+            pane_lines: DEFAULT_CHAT_LINES,
+            undo_stack: Vec::new(),
+            typing: false,
+            channel_colors: HashMap::new(),
+            theme_colors: HashMap::new(),
+            muted: Vec::new(),
+            muted_count: 0,
+            last_coords: None,
+            history: InputHistory::default(),
+            log: ChatLog::default(),
+            outbox: None,
+            commands: CommandRegistry::builtin(),
+        }
+    }
+}
+
+impl ChatWindow {
+    fn new() -> Self {
+        let mut chat = ChatWindow::default();
+        chat.add_message(ChatMessage::system("Welcome to Exospace! Press Enter to chat, / for commands."));
+        chat
+    }
+
+    /// Open chat (if not already open)
+    fn open(&mut self) {
+        self.active = true;
+    }
+
+    /// Close chat without submitting
+    fn close(&mut self) {
+        self.active = false;
+        self.clear_input();
+        self.history.reset();
+    }
+
+    /// Empty the input line and forget its undo history
+    fn clear_input(&mut self) {
+        self.input.clear();
+        self.cursor = 0;
+        self.undo_stack.clear();
+        self.typing = false;
+    }
+
+    /// Remember the current input so the next edit can be undone
+    fn save_undo(&mut self) {
+        if self.undo_stack.len() == MAX_UNDO {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push((self.input.clone(), self.cursor));
+    }
+
+    /// Restore the input line to before the last edit
+    fn undo(&mut self) {
+        if let Some((input, cursor)) = self.undo_stack.pop() {
+            self.input = input;
+            self.cursor = cursor;
+        }
+        self.typing = false;
+    }
+
+    /// Add a character at cursor position
+    fn insert_char(&mut self, ch: char) {
+        // Group consecutive typing into one undo step, splitting at word boundaries
+        if !self.typing || ch == ' ' {
+            self.save_undo();
+        }
+        self.typing = true;
+        self.input.insert(self.cursor, ch);
+        self.cursor += ch.len_utf8();
+    }
 
     /// Delete character before cursor (backspace)
     fn backspace(&mut self) {
         if self.cursor > 0 {
+            self.save_undo();
+            self.typing = false;
             // Find the previous character boundary
             let prev = self.input[..self.cursor]
                 .char_indices()
@@ -1061,12 +2961,64 @@ impl ChatWindow {
     /// Delete character at cursor (delete key)
     fn delete(&mut self) {
         if self.cursor < self.input.len() {
+            self.save_undo();
+            self.typing = false;
             self.input.remove(self.cursor);
         }
     }
 
+    /// Delete from cursor to end of line (Ctrl+K)
+    fn kill_to_end(&mut self) {
+        if self.cursor < self.input.len() {
+            self.save_undo();
+            self.typing = false;
+            self.input.truncate(self.cursor);
+        }
+    }
+
+    /// Delete the word before the cursor (Ctrl+W)
+    fn delete_word_before(&mut self) {
+        let start = self.word_start_before(self.cursor);
+        if start < self.cursor {
+            self.save_undo();
+            self.typing = false;
+            self.input.replace_range(start..self.cursor, "");
+            self.cursor = start;
+        }
+    }
+
+    /// Byte index of the start of the word before `pos` (skipping spaces first)
+    fn word_start_before(&self, pos: usize) -> usize {
+        let before = &self.input[..pos];
+        let trimmed = before.trim_end_matches(' ');
+        trimmed.rfind(' ').map(|i| i + 1).unwrap_or(0)
+    }
+
+    /// Byte index just past the end of the word after `pos` (skipping spaces first)
+    fn word_end_after(&self, pos: usize) -> usize {
+        let after = &self.input[pos..];
+        let skipped = after.len() - after.trim_start_matches(' ').len();
+        after[skipped..]
+            .find(' ')
+            .map(|i| pos + skipped + i)
+            .unwrap_or(self.input.len())
+    }
+
+    /// Move cursor to the start of the previous word (Ctrl+Left)
+    fn word_left(&mut self) {
+        self.cursor = self.word_start_before(self.cursor);
+        self.typing = false;
+    }
+
+    /// Move cursor to the end of the next word (Ctrl+Right)
+    fn word_right(&mut self) {
+        self.cursor = self.word_end_after(self.cursor);
+        self.typing = false;
+    }
+
     /// Move cursor left
     fn cursor_left(&mut self) {
+        self.typing = false;
         if self.cursor > 0 {
             self.cursor = self.input[..self.cursor]
                 .char_indices()
@@ -1078,6 +3030,7 @@ impl ChatWindow {
 
     /// Move cursor right
     fn cursor_right(&mut self) {
+        self.typing = false;
         if self.cursor < self.input.len() {
             self.cursor = self.input[self.cursor..]
                 .char_indices()
@@ -1087,14 +3040,49 @@ impl ChatWindow {
         }
     }
 
+    /// Replace the input line with recalled or completed text, cursor at the end
+    fn set_input(&mut self, text: &str) {
+        if text != self.input {
+            self.save_undo();
+        }
+        self.input = text.to_string();
+        self.cursor = self.input.len();
+        self.typing = false;
+    }
+
+    /// Recall the line sent before the one shown (Up)
+    fn history_up(&mut self) {
+        if let Some(line) = self.history.older(&self.input).map(str::to_string) {
+            self.set_input(&line);
+        }
+    }
+
+    /// Step back towards the line being typed (Down)
+    fn history_down(&mut self) {
+        if let Some(line) = self.history.newer().map(str::to_string) {
+            self.set_input(&line);
+        }
+    }
+
+    /// Complete the command name or argument before the cursor (Tab)
+    fn complete(&mut self) {
+        if let Some(completed) = self.commands.complete(&self.input[..self.cursor]) {
+            let line = format!("{}{}", completed, &self.input[self.cursor..]);
+            self.set_input(&line);
+            self.cursor = completed.len();
+        }
+    }
+
     /// Move cursor to start
     fn cursor_home(&mut self) {
         self.cursor = 0;
+        self.typing = false;
     }
 
     /// Move cursor to end
     fn cursor_end(&mut self) {
         self.cursor = self.input.len();
+        self.typing = false;
     }
 
     /// Submit the current input and return it
@@ -1106,15 +3094,82 @@ impl ChatWindow {
 
         let text = self.input.clone();
         self.add_message(ChatMessage::user(&text));
-        self.input.clear();
-        self.cursor = 0;
+        self.history.push(&text);
+        self.clear_input();
         self.active = false;
 
         Some(text)
     }
 
+    /// Take pane size, channel colors and mutes from config, reporting colors that don't parse
+    fn apply_config(&mut self, config: &Config) {
+        self.pane_lines = config.chat_lines();
+        self.visible_lines = self.pane_lines;
+        self.muted = config.muted_channels.clone();
+        self.channel_colors.clear();
+        let mut colors: Vec<_> = config.chat_colors.iter().collect();
+        colors.sort();
+        for (&channel, text) in colors {
+            match parse_hex_color(text) {
+                Some(color) => {
+                    self.channel_colors.insert(channel, color);
+                }
+                None => self.add_message(ChatMessage::error(&format!(
+                    "Bad chat color for {:?}: {} (use #RRGGBB)",
+                    channel, text
+                ))),
+            }
+        }
+    }
+
+    /// Switch to the next pane size
+    fn cycle_pane(&mut self) {
+        self.pane = self.pane.next();
+    }
+
+    /// Set the message lines for the current pane size, leaving the game at least `MIN_GAME_ROWS`
+    fn fit_pane(&mut self, term_height: u32) {
+        // Input line and status bar are always shown
+        let room = term_height.saturating_sub(MIN_GAME_ROWS + 2) as usize;
+        let lines = match self.pane {
+            ChatPane::Normal => self.pane_lines,
+            ChatPane::Collapsed => 0,
+            ChatPane::Expanded => (term_height as usize / 2).saturating_sub(2).max(self.pane_lines),
+        };
+        self.visible_lines = lines.min(room);
+    }
+
+    /// Rows taken by the pane: messages, input line and status bar
+    fn height(&self) -> u32 {
+        self.visible_lines as u32 + 2
+    }
+
+    fn is_muted(&self, channel: ChatChannel) -> bool {
+        self.muted.contains(&channel)
+    }
+
+    /// Color to draw a message in, honouring config overrides
+    fn color_of(&self, message: &ChatMessage) -> u32 {
+        let channel = &message.channel;
+        self.channel_colors.get(channel).or(self.theme_colors.get(channel)).copied().unwrap_or(message.color)
+    }
+
+    /// Take channel colors from a theme; config's `chat_colors` still win
+    fn apply_theme(&mut self, theme: &Theme) {
+        self.theme_colors = theme.chat.iter().map(|(&channel, color)| (channel, color.0)).collect();
+    }
+
     /// Add a message to history
     fn add_message(&mut self, message: ChatMessage) {
+        if self.is_muted(message.channel) {
+            self.muted_count += 1;
+        }
+        if let Some(&(_, position)) = message.coords.last() {
+            self.last_coords = Some(position);
+        }
+        if let Some(outbox) = &mut self.outbox {
+            outbox.push(message.clone());
+        }
         self.messages.push(message);
         if self.messages.len() > self.max_messages {
             self.messages.remove(0);
@@ -1130,51 +3185,95 @@ impl ChatWindow {
 
         // Check if it's a command (starts with /)
         if let Some(cmd) = text.strip_prefix('/') {
-            let parts: Vec<&str> = cmd.splitn(2, ' ').collect();
-            let command = parts[0].to_lowercase();
-            let args = parts.get(1).map(|s| s.to_string());
-
-            match command.as_str() {
-                "help" | "?" => {
-                    self.add_message(ChatMessage::system("Commands:"));
-                    self.add_message(ChatMessage::system("  /help - Show this help"));
-                    self.add_message(ChatMessage::system("  /pos - Show current position"));
-                    self.add_message(ChatMessage::system("  /goto X Y - Teleport to position"));
-                    self.add_message(ChatMessage::system("  /fx - Toggle effects"));
-                    self.add_message(ChatMessage::system("  /quit - Exit game"));
-                    None
-                }
-                "quit" | "exit" | "q" => Some(ChatCommand::Quit),
-                "pos" | "position" | "where" => Some(ChatCommand::ShowPosition),
-                "goto" | "tp" | "teleport" => {
-                    if let Some(args) = args {
-                        let coords: Vec<&str> = args.split_whitespace().collect();
-                        if coords.len() >= 2 {
-                            if let (Ok(x), Ok(y)) = (coords[0].parse::<i32>(), coords[1].parse::<i32>()) {
-                                return Some(ChatCommand::Teleport(x, y));
-                            }
-                        }
-                    }
-                    self.add_message(ChatMessage::error("Usage: /goto X Y"));
-                    None
+            let mut words = cmd.split_whitespace();
+            let command = words.next().unwrap_or("").to_lowercase();
+            let args: Vec<&str> = words.collect();
+
+            let Some(spec) = self.commands.find(&command).copied() else {
+                self.add_message(ChatMessage::error(&format!("Unknown command: /{}", command)));
+                if let Some(suggestion) = self.commands.suggest(&command) {
+                    self.add_message(ChatMessage::system(
+                        &format!("Did you mean /{}? Usage: {}", suggestion.name, suggestion.usage())
+                    ));
                 }
-                "fx" | "effects" => Some(ChatCommand::ToggleEffects),
-                _ => {
-                    self.add_message(ChatMessage::error(&format!("Unknown command: /{}", command)));
-                    None
+                return None;
+            };
+
+            let values = match spec.parse_args(&args) {
+                Ok(values) => values,
+                Err(err) => {
+                    self.report_arg_error(&spec, &command, &args, &err);
+                    return None;
                 }
-            }
+            };
+
+            (spec.run)(&values, self)
         } else {
-            // Regular chat message (for now just echo it)
-            self.add_message(ChatMessage::new(format!("You: {}", text), 0xAAAAAA));
-            None
+            // Regular chat message - echo locally, the main loop relays it if online
+            self.add_message(ChatMessage::new(ChatChannel::Input, format!("You: {}", text), 0xAAAAAA));
+            Some(ChatCommand::Say(text.to_string()))
+        }
+    }
+
+    /// List every registered command with its usage (`/help`)
+    fn show_help(&mut self) {
+        let lines: Vec<String> = self.commands.iter().map(|spec| format!("  {} - {}", spec.usage(), spec.description)).collect();
+        self.add_message(ChatMessage::system("Commands:"));
+        for line in lines {
+            self.add_message(ChatMessage::system(&line));
+        }
+        self.add_message(ChatMessage::system("Editing: Ctrl+Left/Right words, Ctrl+W delete word, Ctrl+K kill to end, Ctrl+Z undo, Up/Down history, Tab completes"));
+    }
+
+    /// Teleport to the latest position another player mentioned (`/goto last`)
+    fn teleport_to_last(&mut self) -> Option<ChatCommand> {
+        if self.last_coords.is_none() {
+            self.add_message(ChatMessage::error("Nobody has shared coordinates in chat yet"));
+        }
+        self.last_coords.map(|(x, y)| ChatCommand::Teleport(x, y))
+    }
+
+    fn clear(&mut self) {
+        self.messages.clear();
+        self.muted_count = 0;
+    }
+
+    /// Parse one autoexec line; only slash commands are allowed, and `#` starts a comment
+    fn process_autoexec(&mut self, line: &str) -> Option<ChatCommand> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        if !line.starts_with('/') {
+            self.add_message(ChatMessage::error(&format!("autoexec: not a command, skipped: {}", line)));
+            return None;
         }
+        self.process_input(line)
+    }
+
+    /// Show the usage line, then the input with the offending argument underlined
+    fn report_arg_error(&mut self, spec: &CommandSpec, command: &str, args: &[&str], err: &ArgError) {
+        self.add_message(ChatMessage::error(&format!("Usage: {}", spec.usage())));
+
+        let echoed = std::iter::once(format!("/{}", command))
+            .chain(args.iter().map(|a| a.to_string()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.add_message(ChatMessage::new(ChatChannel::Error, format!("  {}", echoed), 0xAAAAAA));
+
+        // Column where the bad argument starts (or just past the end if it's missing)
+        let column = 1 + command.chars().count() + args[..err.index.min(args.len())].iter().map(|a| a.chars().count() + 1).sum::<usize>() + 1;
+        let width = args.get(err.index).map(|a| a.chars().count()).unwrap_or(1);
+        self.add_message(ChatMessage::error(
+            &format!("  {}{} {}", " ".repeat(column), "^".repeat(width), err.message)
+        ));
     }
 
-    /// Get the visible messages (most recent)
+    /// Get the visible messages (most recent, skipping muted channels)
     fn visible_messages(&self) -> impl Iterator<Item = &ChatMessage> {
-        let start = self.messages.len().saturating_sub(self.visible_lines);
-        self.messages[start..].iter()
+        let shown = self.messages.iter().filter(|m| !self.is_muted(m.channel));
+        let skip = shown.clone().count().saturating_sub(self.visible_lines);
+        shown.skip(skip)
     }
 
     /// Get cursor position in display characters (for rendering)
@@ -1190,1136 +3289,5166 @@ enum ChatCommand {
     ShowPosition,
     Teleport(i32, i32),
     ToggleEffects,
+    SetTarget(i32, i32),
+    ClearTarget,
+    /// Unhook the view from the ship (F5), or follow it again
+    ToggleSpectate,
+    /// Leave spectator mode
+    FollowShip,
+    /// Server-side dice roll: (count, sides)
+    Roll(u32, u32),
+    Flip,
+    /// The time of day, and the server's clock when online
+    ServerTime,
+    Distance(DistanceTarget),
+    /// List points of interest with distances and bearings
+    ListPois,
+    /// Rebind a game key to a key spec ("none" unbinds)
+    Bind(Action, String),
+    ListBindings,
+    /// Mine the resource tile next to the ship
+    Mine,
+    /// Laser away the asteroid next to the ship
+    FireLaser,
+    /// Fire a projectile the way the ship faces
+    Fire,
+    /// Autopilot to a position along a planned route
+    Navigate(i32, i32),
+    /// Stop the autopilot and drop the waypoint
+    CancelNav,
+    /// Bookmark the ship's position under a name
+    Mark(String),
+    ListWaypoints,
+    /// Make a bookmark the waypoint and fly there
+    GotoMark(String),
+    /// Fetch the mission board and open the log
+    Missions,
+    /// Take an offer from the board, by its number in the log
+    AcceptMission(i32),
+    /// Drop a mission under way, by its number in the log
+    AbandonMission(i32),
+    /// Show our credits, and an account's latest transactions
+    Wallet,
+    /// Fetch a page of a server leaderboard and open it
+    Leaderboard(LeaderboardMetric, u32),
+    /// Ask the server for the stats dashboard
+    Stats,
+    /// Open the settings screen
+    Settings,
+    /// Save the next frame as text and ANSI files
+    Screenshot,
+    /// Take up config the settings screen changed (internal; no slash command)
+    ApplySettings,
+    /// Pick up the last game from its session file (internal; the title menu's Continue)
+    Continue,
+    /// Put the ship and hold back as a session had them, once its world is loaded (internal)
+    ResumeSession(Session),
+    /// Fly a local map, leaving any server (internal; the title menu's New Game)
+    NewGame,
+    /// Load a server's world, on a seed or the command line's, and dial in (internal; the title menu's Connect)
+    Connect(String, Option<u64>),
+    /// Make the station we're docked at our home
+    SetHome,
+    /// Fly back to our home station
+    Respawn,
+    /// Jump through the gate the ship is on to another star system
+    Jump,
+    /// Swap to a star system's map, arriving at (x, y) (internal; the warp queues it)
+    Arrive(u64, i32, i32),
+    /// List what the server's content packs define
+    ShowContent,
+    /// Change the solo world's difficulty
+    SetDifficulty(Difficulty),
+    ShowDifficulty,
+    /// Fly a different class of ship
+    SetShip(ShipClass),
+    ShowShip,
+    /// Name the ship; None clears the name
+    NameShip(Option<String>),
+    /// Set or clear the callsign shown before our name
+    SetCallsign(Option<String>),
+    /// Paint a part of the ship; None goes back to the theme's color
+    PaintShip(ShipPart, Option<u32>),
+    /// Write the solo map to the config directory under a name
+    SaveMap(String),
+    LoadMap(String),
+    /// Fetch the server's community maps and open the list
+    CommunityMaps,
+    /// Download a community map by id and fly it as the solo map
+    PlayCommunityMap(u64),
+    /// Share the solo map on the server under a name
+    UploadMap(String),
+    ShowSeed,
+    /// Show the newest lines of the log file
+    DebugLog(usize),
+    /// Switch to another seed's map, streamed or local like the current one; None is today's
+    NewMap(Option<u64>),
+    /// Switch color theme by name
+    SetTheme(String),
+    ShowThemes,
+    /// Run a script from the scripts directory by name
+    RunScript(String),
+    StopScript,
+    ListScripts,
+    Say(String),
+    /// A line for the pilots nearby, in a bubble over our ship; an emote when the flag is set
+    SayNearby(String, bool),
 }
 
-fn main() -> NcResult<()> {
-    let nc = unsafe { Nc::new()? };
+/// What an argument position accepts
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ArgKind {
+    /// A signed integer
+    Int,
+    /// A map seed, any unsigned 64-bit number
+    Seed,
+    /// One of a fixed set of words (first is the canonical spelling)
+    Keyword(&'static [&'static str]),
+    /// Dice notation like `2d6` or `d20`
+    Dice,
+    /// A player name (anything that isn't a number)
+    Name,
+    /// A game action name like `toggle_minimap`
+    Action,
+    /// A key spec like `q`, `space`, `up` or `f5`, or `none`
+    Key,
+    /// A difficulty name like `hard`
+    Difficulty,
+    /// A ship class name like `freighter`
+    ShipClass,
+    /// A leaderboard name like `ore`
+    Metric,
+    /// The rest of the line, spaces and all; only ever the last argument
+    Text,
+    /// A color like `#FF8800`, or `none`
+    Color,
+}
 
-    // Load user configuration
-    let mut config = Config::load();
+impl ArgKind {
+    /// Every word the argument takes, for Tab completion; empty for open-ended ones
+    fn choices(self) -> Vec<&'static str> {
+        match self {
+            ArgKind::Keyword(words) => words.to_vec(),
+            ArgKind::Action => Action::ALL.iter().map(|action| action.name()).collect(),
+            ArgKind::Difficulty => Difficulty::ALL.iter().map(|difficulty| difficulty.name()).collect(),
+            ArgKind::ShipClass => ShipClass::ALL.iter().map(|class| class.name()).collect(),
+            ArgKind::Metric => LeaderboardMetric::ALL.iter().map(|metric| metric.name()).collect(),
+            ArgKind::Int | ArgKind::Seed | ArgKind::Dice | ArgKind::Name | ArgKind::Key | ArgKind::Text | ArgKind::Color => Vec::new(),
+        }
+    }
+}
 
-    let map = Map::new(&config);
-    let start = map.find_start_position();
-    let mut player = Player::new(start.0, start.1);
-    let mut renderer = Renderer::new(config.effects_enabled);
-    let mut chat = ChatWindow::new();
+/// One argument in a command's usage line
+#[derive(Clone, Copy, Debug)]
+struct ArgSpec {
+    name: &'static str,
+    kind: ArgKind,
+}
 
-    let stdplane = unsafe { nc.stdplane() };
-    let (mut term_height, mut term_width) = stdplane.dim_yx();
+/// A successfully parsed argument
+#[derive(Clone, Debug, PartialEq)]
+enum ArgValue {
+    Int(i32),
+    Seed(u64),
+    Keyword(&'static str),
+    /// (count, sides)
+    Dice(u32, u32),
+    Name(String),
+    Action(Action),
+    Key(String),
+    Difficulty(Difficulty),
+    ShipClass(ShipClass),
+    Metric(LeaderboardMetric),
+    Text(String),
+    Color(Option<u32>),
+}
 
-    let mut input_state = InputState::default();
-    let mut last_move_time = Instant::now();
-    let move_delay = Duration::from_millis(33);
+/// Why a command's arguments didn't parse
+#[derive(Clone, Debug, PartialEq)]
+struct ArgError {
+    /// Index of the offending argument; equals the argument count when one is missing
+    index: usize,
+    message: String,
+}
 
-    // Chat area takes up bottom lines: messages + input line + status bar
-    let chat_height: u32 = 5; // 3 message lines + 1 input line + 1 status bar
+/// Runs a command whose arguments matched one of its forms: a `ChatCommand` for the main
+/// loop to carry out, or None once the chat window has dealt with it
+type CommandFn = fn(&[ArgValue], &mut ChatWindow) -> Option<ChatCommand>;
+
+/// A slash command, the argument lists it accepts and what it does
+#[derive(Clone, Copy)]
+struct CommandSpec {
+    name: &'static str,
+    aliases: &'static [&'static str],
+    /// Alternative argument lists; `&[]` means no arguments
+    forms: &'static [&'static [ArgSpec]],
+    description: &'static str,
+    run: CommandFn,
+}
 
-    loop {
-        let mut quit = false;
-        let mut input = NcInput::new_empty();
+const ARG_X: ArgSpec = ArgSpec { name: "X", kind: ArgKind::Int };
+const ARG_Y: ArgSpec = ArgSpec { name: "Y", kind: ArgKind::Int };
+const ARG_LAST: ArgSpec = ArgSpec { name: "last", kind: ArgKind::Keyword(&["last"]) };
+const ARG_OFF: ArgSpec = ArgSpec { name: "off", kind: ArgKind::Keyword(&["off", "clear", "none"]) };
+const ARG_DICE: ArgSpec = ArgSpec { name: "NdM", kind: ArgKind::Dice };
+const ARG_PLAYER: ArgSpec = ArgSpec { name: "PLAYER", kind: ArgKind::Name };
+const ARG_ACTION: ArgSpec = ArgSpec { name: "ACTION", kind: ArgKind::Action };
+const ARG_KEY: ArgSpec = ArgSpec { name: "KEY", kind: ArgKind::Key };
+const ARG_DIFFICULTY: ArgSpec = ArgSpec { name: "LEVEL", kind: ArgKind::Difficulty };
+const ARG_SHIP: ArgSpec = ArgSpec { name: "CLASS", kind: ArgKind::ShipClass };
+const ARG_SHIP_NAME: ArgSpec = ArgSpec { name: "name", kind: ArgKind::Keyword(&["name"]) };
+const ARG_NAME_TEXT: ArgSpec = ArgSpec { name: "NAME", kind: ArgKind::Text };
+const ARG_CALLSIGN: ArgSpec = ArgSpec { name: "callsign", kind: ArgKind::Keyword(&["callsign"]) };
+const ARG_CALLSIGN_TEXT: ArgSpec = ArgSpec { name: "TAG", kind: ArgKind::Text };
+const ARG_SAY_TEXT: ArgSpec = ArgSpec { name: "TEXT", kind: ArgKind::Text };
+const ARG_PAINT: ArgSpec = ArgSpec { name: "color", kind: ArgKind::Keyword(&["color", "colour", "paint"]) };
+const ARG_PART: ArgSpec = ArgSpec { name: "PART", kind: ArgKind::Keyword(&["hull", "cockpit", "wing"]) };
+const ARG_COLOR: ArgSpec = ArgSpec { name: "#RRGGBB", kind: ArgKind::Color };
+const ARG_SEED: ArgSpec = ArgSpec { name: "SEED", kind: ArgKind::Seed };
+const ARG_DAILY: ArgSpec = ArgSpec { name: "daily", kind: ArgKind::Keyword(&["daily", "today"]) };
+const ARG_SAVE: ArgSpec = ArgSpec { name: "NAME", kind: ArgKind::Name };
+const ARG_THEME: ArgSpec = ArgSpec { name: "THEME", kind: ArgKind::Name };
+const ARG_MARK: ArgSpec = ArgSpec { name: "NAME", kind: ArgKind::Name };
+const ARG_MISSION: ArgSpec = ArgSpec { name: "N", kind: ArgKind::Int };
+const ARG_METRIC: ArgSpec = ArgSpec { name: "BOARD", kind: ArgKind::Metric };
+const ARG_PAGE: ArgSpec = ArgSpec { name: "PAGE", kind: ArgKind::Int };
+const ARG_SCRIPT: ArgSpec = ArgSpec { name: "SCRIPT", kind: ArgKind::Name };
+const ARG_STOP: ArgSpec = ArgSpec { name: "stop", kind: ArgKind::Keyword(&["stop", "off"]) };
+const ARG_LOG: ArgSpec = ArgSpec { name: "log", kind: ArgKind::Keyword(&["log"]) };
+const ARG_LINES: ArgSpec = ArgSpec { name: "LINES", kind: ArgKind::Int };
+
+/// The built-in slash commands, in help order
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec { name: "help",   aliases: &["?"],                 forms: &[&[]],                          description: "Show this help", run: |_, chat| { chat.show_help(); None } },
+    CommandSpec { name: "pos",    aliases: &["position", "where"], forms: &[&[]],                          description: "Show current position", run: |_, _| Some(ChatCommand::ShowPosition) },
+    CommandSpec { name: "goto",   aliases: &["go", "tp", "teleport"], forms: &[&[ARG_X, ARG_Y], &[ARG_LAST]], description: "Teleport to position (last: latest one in chat)", run: |args, chat| match args {
+        &[ArgValue::Int(x), ArgValue::Int(y)] => Some(ChatCommand::Teleport(x, y)),
+        _ => chat.teleport_to_last(),
+    } },
+    CommandSpec { name: "fx",     aliases: &["effects"],           forms: &[&[]],                          description: "Toggle effects", run: |_, _| Some(ChatCommand::ToggleEffects) },
+    CommandSpec { name: "target", aliases: &["watch"],             forms: &[&[ARG_X, ARG_Y], &[ARG_OFF]],  description: "Watch a position (P toggles)", run: |args, _| match args {
+        &[ArgValue::Int(x), ArgValue::Int(y)] => Some(ChatCommand::SetTarget(x, y)),
+        _ => Some(ChatCommand::ClearTarget),
+    } },
+    CommandSpec { name: "spectate", aliases: &["freecam", "cam"],  forms: &[&[], &[ARG_OFF]],               description: "Pan the view with the movement keys, leaving the ship be (F5; Home snaps back)", run: |args, _| match args {
+        [] => Some(ChatCommand::ToggleSpectate),
+        _ => Some(ChatCommand::FollowShip),
+    } },
+    CommandSpec { name: "roll",   aliases: &["dice", "r"],         forms: &[&[ARG_DICE], &[]],             description: "Roll dice for everyone to see (default 1d6)", run: |args, _| match args {
+        &[ArgValue::Dice(count, sides)] => Some(ChatCommand::Roll(count, sides)),
+        _ => Some(ChatCommand::Roll(1, 6)),
+    } },
+    CommandSpec { name: "say",    aliases: &["local"],             forms: &[&[ARG_SAY_TEXT]],              description: "Say something to the pilots nearby, in a bubble over your ship", run: |args, _| match args {
+        [ArgValue::Text(text)] => Some(ChatCommand::SayNearby(text.clone(), false)),
+        _ => None,
+    } },
+    CommandSpec { name: "emote",  aliases: &["me"],                forms: &[&[ARG_SAY_TEXT]],              description: "Act something out for the pilots nearby (/me waves)", run: |args, _| match args {
+        [ArgValue::Text(text)] => Some(ChatCommand::SayNearby(text.clone(), true)),
+        _ => None,
+    } },
+    CommandSpec { name: "flip",   aliases: &["coin"],              forms: &[&[]],                          description: "Flip a coin for everyone to see", run: |_, _| Some(ChatCommand::Flip) },
+    CommandSpec { name: "time",   aliases: &["clock"],             forms: &[&[]],                          description: "Show the time of day (and the server's clock)", run: |_, _| Some(ChatCommand::ServerTime) },
+    CommandSpec { name: "distance", aliases: &["dist"],            forms: &[&[ARG_PLAYER], &[ARG_X, ARG_Y]], description: "Distance to a player or position", run: |args, _| match args {
+        &[ArgValue::Int(x), ArgValue::Int(y)] => Some(ChatCommand::Distance(DistanceTarget::Position { x, y })),
+        [ArgValue::Name(name)] => Some(ChatCommand::Distance(DistanceTarget::Player { name: name.clone() })),
+        _ => None,
+    } },
+    CommandSpec { name: "poi",    aliases: &["places"],            forms: &[&[]],                          description: "List known stations, derelicts and beacons, nearest first", run: |_, _| Some(ChatCommand::ListPois) },
+    CommandSpec { name: "mine",   aliases: &["dig"],               forms: &[&[]],                          description: "Mine ore or crystals next to your ship (X)", run: |_, _| Some(ChatCommand::Mine) },
+    CommandSpec { name: "laser",  aliases: &["cut"],               forms: &[&[]],                          description: "Cut away an asteroid next to your ship (L)", run: |_, _| Some(ChatCommand::FireLaser) },
+    CommandSpec { name: "fire",   aliases: &["shoot"],             forms: &[&[]],                          description: "Fire a projectile the way your ship faces (Space)", run: |_, _| Some(ChatCommand::Fire) },
+    CommandSpec { name: "nav",    aliases: &["autopilot", "route"], forms: &[&[ARG_X, ARG_Y], &[ARG_OFF]], description: "Fly a route to a position (thrust to take over)", run: |args, _| match args {
+        &[ArgValue::Int(x), ArgValue::Int(y)] => Some(ChatCommand::Navigate(x, y)),
+        _ => Some(ChatCommand::CancelNav),
+    } },
+    CommandSpec { name: "mark",   aliases: &["bookmark"],          forms: &[&[ARG_MARK]],                  description: "Bookmark your position in this world", run: |args, _| match args { [ArgValue::Name(name)] => Some(ChatCommand::Mark(name.clone())), _ => None } },
+    CommandSpec { name: "waypoints", aliases: &["marks", "bookmarks"], forms: &[&[]],                      description: "List this world's bookmarks, nearest first", run: |_, _| Some(ChatCommand::ListWaypoints) },
+    CommandSpec { name: "goto-mark", aliases: &["gotomark"],       forms: &[&[ARG_MARK]],                  description: "Fly to a bookmark, with an arrow pointing the way", run: |args, _| match args { [ArgValue::Name(name)] => Some(ChatCommand::GotoMark(name.clone())), _ => None } },
+    CommandSpec { name: "missions", aliases: &["jobs"],            forms: &[&[]],                          description: "Fetch the mission board and open the mission log (J)", run: |_, _| Some(ChatCommand::Missions) },
+    CommandSpec { name: "accept", aliases: &[],                    forms: &[&[ARG_MISSION]],               description: "Take offer N from the mission board", run: |args, _| match args { &[ArgValue::Int(n)] => Some(ChatCommand::AcceptMission(n)), _ => None } },
+    CommandSpec { name: "abandon", aliases: &[],                   forms: &[&[ARG_MISSION]],               description: "Drop mission N from your log", run: |args, _| match args { &[ArgValue::Int(n)] => Some(ChatCommand::AbandonMission(n)), _ => None } },
+    CommandSpec { name: "wallet", aliases: &["balance"],           forms: &[&[]],                          description: "Show your credits and, when logged in, your latest transactions", run: |_, _| Some(ChatCommand::Wallet) },
+    CommandSpec { name: "top",    aliases: &["leaderboard", "ranks"], forms: &[&[ARG_METRIC, ARG_PAGE], &[ARG_METRIC], &[]], description: "Show the leaderboard: distance, ore or credits", run: |args, _| match *args {
+        [ArgValue::Metric(metric), ArgValue::Int(page)] => Some(ChatCommand::Leaderboard(metric, page.max(1) as u32)),
+        [ArgValue::Metric(metric)] => Some(ChatCommand::Leaderboard(metric, 1)),
+        _ => Some(ChatCommand::Leaderboard(LeaderboardMetric::Distance, 1)),
+    } },
+    CommandSpec { name: "stats",  aliases: &["statistics"],        forms: &[&[]],                          description: "Show your lifetime and session stats", run: |_, _| Some(ChatCommand::Stats) },
+    CommandSpec { name: "settings", aliases: &["options", "config"], forms: &[&[]],                        description: "Change effects, server, timing and colors (F2)", run: |_, _| Some(ChatCommand::Settings) },
+    CommandSpec { name: "sethome", aliases: &[],                   forms: &[&[]],                          description: "Make the station you're docked at your home", run: |_, _| Some(ChatCommand::SetHome) },
+    CommandSpec { name: "respawn", aliases: &["home"],             forms: &[&[]],                          description: "Return to your home station", run: |_, _| Some(ChatCommand::Respawn) },
+    CommandSpec { name: "jump",   aliases: &["warp"],              forms: &[&[]],                          description: "Jump through the gate your ship is on to another star system (J)", run: |_, _| Some(ChatCommand::Jump) },
+    CommandSpec { name: "content", aliases: &["packs"],            forms: &[&[]],                          description: "List the server's content packs and what they add", run: |_, _| Some(ChatCommand::ShowContent) },
+    CommandSpec { name: "difficulty", aliases: &["diff"],          forms: &[&[ARG_DIFFICULTY], &[]],       description: "Set or show the solo world's difficulty", run: |args, _| match args {
+        &[ArgValue::Difficulty(difficulty)] => Some(ChatCommand::SetDifficulty(difficulty)),
+        _ => Some(ChatCommand::ShowDifficulty),
+    } },
+    CommandSpec { name: "ship",   aliases: &["class"],             forms: &[&[ARG_SHIP], &[ARG_SHIP_NAME, ARG_NAME_TEXT], &[ARG_CALLSIGN, ARG_CALLSIGN_TEXT], &[ARG_PAINT, ARG_PART, ARG_COLOR], &[]], description: "Fly a scout, freighter or interceptor, name, tag or paint it, or show yours", run: |args, _| match args {
+        &[ArgValue::ShipClass(class)] => Some(ChatCommand::SetShip(class)),
+        [ArgValue::Keyword("name"), ArgValue::Text(text)] => Some(ChatCommand::NameShip(text_or_off(text))),
+        [ArgValue::Keyword("callsign"), ArgValue::Text(text)] => Some(ChatCommand::SetCallsign(text_or_off(text))),
+        [_, ArgValue::Keyword(part), ArgValue::Color(color)] => Some(ChatCommand::PaintShip(ShipPart::from_name(part)?, *color)),
+        _ => Some(ChatCommand::ShowShip),
+    } },
+    CommandSpec { name: "savemap", aliases: &["save"],             forms: &[&[ARG_SAVE]],                  description: "Save the solo map, exploration included", run: |args, _| match args { [ArgValue::Name(name)] => Some(ChatCommand::SaveMap(name.clone())), _ => None } },
+    CommandSpec { name: "loadmap", aliases: &["load"],             forms: &[&[ARG_SAVE]],                  description: "Load a saved solo map", run: |args, _| match args { [ArgValue::Name(name)] => Some(ChatCommand::LoadMap(name.clone())), _ => None } },
+    CommandSpec { name: "maps",   aliases: &["community"],         forms: &[&[]],                          description: "Browse the server's community maps and fly one", run: |_, _| Some(ChatCommand::CommunityMaps) },
+    CommandSpec { name: "upload", aliases: &["share"],             forms: &[&[ARG_SAVE]],                  description: "Share the solo map on the server (logged in)", run: |args, _| match args { [ArgValue::Name(name)] => Some(ChatCommand::UploadMap(name.clone())), _ => None } },
+    CommandSpec { name: "seed",   aliases: &[],                    forms: &[&[]],                          description: "Show the map's seed", run: |_, _| Some(ChatCommand::ShowSeed) },
+    CommandSpec { name: "newmap", aliases: &["reseed"],            forms: &[&[ARG_SEED], &[ARG_DAILY]],    description: "Fly a map from another seed (daily: today's, the same for everyone)", run: |args, _| match args {
+        &[ArgValue::Seed(seed)] => Some(ChatCommand::NewMap(Some(seed))),
+        _ => Some(ChatCommand::NewMap(None)),
+    } },
+    CommandSpec { name: "theme",  aliases: &["colors"],            forms: &[&[ARG_THEME], &[]],            description: "Switch color theme, or list the themes", run: |args, _| match args {
+        [ArgValue::Name(name)] => Some(ChatCommand::SetTheme(name.to_lowercase())),
+        _ => Some(ChatCommand::ShowThemes),
+    } },
+    CommandSpec { name: "run",    aliases: &["script"],            forms: &[&[ARG_STOP], &[ARG_SCRIPT], &[]], description: "Run a script, stop the running one, or list the scripts", run: |args, _| match args {
+        [ArgValue::Keyword(_)] => Some(ChatCommand::StopScript),
+        [ArgValue::Name(name)] => Some(ChatCommand::RunScript(name.clone())),
+        _ => Some(ChatCommand::ListScripts),
+    } },
+    CommandSpec { name: "bind",   aliases: &["key"],               forms: &[&[ARG_ACTION, ARG_KEY], &[]],  description: "Rebind a game key (no arguments lists keys)", run: |args, _| match args {
+        [ArgValue::Action(action), ArgValue::Key(key)] => Some(ChatCommand::Bind(*action, key.clone())),
+        _ => Some(ChatCommand::ListBindings),
+    } },
+    CommandSpec { name: "screenshot", aliases: &["shot"],         forms: &[&[]],                          description: "Save the view, HUD and chat as text and ANSI files (F12)", run: |_, _| Some(ChatCommand::Screenshot) },
+    CommandSpec { name: "debug",  aliases: &[],                    forms: &[&[ARG_LOG, ARG_LINES], &[ARG_LOG]], description: "Show the newest lines of the log file", run: |args, _| match *args {
+        [_, ArgValue::Int(lines)] => Some(ChatCommand::DebugLog((lines.max(1) as usize).min(logging::MAX_TAIL_LINES))),
+        _ => Some(ChatCommand::DebugLog(logging::TAIL_LINES)),
+    } },
+    CommandSpec { name: "log",    aliases: &["scrollback"],        forms: &[&[]],                          description: "Scroll back through the whole chat (PgUp)", run: |_, chat| { chat.log.open(); None } },
+    CommandSpec { name: "clear",  aliases: &["cls"],               forms: &[&[]],                          description: "Clear the chat", run: |_, chat| { chat.clear(); None } },
+    CommandSpec { name: "quit",   aliases: &["exit", "q"],         forms: &[&[]],                          description: "Exit game", run: |_, _| Some(ChatCommand::Quit) },
+];
+
+/// The slash commands the chat window runs: the built-in ones, then any registered later.
+/// Help, suggestions, Tab completion and input hints all come from here
+struct CommandRegistry {
+    commands: Vec<CommandSpec>,
+}
 
-        loop {
-            match nc.get_nblock(Some(&mut input)) {
-                Ok(received) => {
-                    if chat.active {
-                        // Chat mode input handling
-                        match received {
-                            NcReceived::NoInput => break,
-                            NcReceived::Char(ch) => {
-                                if ch.is_ascii_graphic() || ch == ' ' {
-                                    chat.insert_char(ch);
-                                }
-                            }
-                            NcReceived::Key(key) => {
-                                match key {
-                                    NcKey::Enter => {
-                                        if let Some(text) = chat.submit() {
-                                            if let Some(cmd) = chat.process_input(&text) {
-                                                match cmd {
-                                                    ChatCommand::Quit => {
-                                                        quit = true;
-                                                        break;
-                                                    }
-                                                    ChatCommand::ShowPosition => {
-                                                        chat.add_message(ChatMessage::system(
-                                                            &format!("Position: ({}, {})", player.x, player.y)
-                                                        ));
-                                                    }
-                                                    ChatCommand::Teleport(x, y) => {
-                                                        if map.is_passable(x, y) {
-                                                            player.x = x;
-                                                            player.y = y;
-                                                            chat.add_message(ChatMessage::system(
-                                                                &format!("Teleported to ({}, {})", x, y)
-                                                            ));
-                                                        } else {
-                                                            chat.add_message(ChatMessage::error(
-                                                                &format!("Cannot teleport to ({}, {}) - not passable", x, y)
-                                                            ));
-                                                        }
-                                                    }
-                                                    ChatCommand::ToggleEffects => {
-                                                        renderer.toggle_effects();
-                                                        config.effects_enabled = renderer.effects_enabled;
-                                                        let _ = config.save();
-                                                        chat.add_message(ChatMessage::system(
-                                                            &format!("Effects: {}", if renderer.effects_enabled { "ON" } else { "OFF" })
-                                                        ));
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                    NcKey::Esc => {
-                                        chat.close();
-                                    }
-                                    NcKey::Backspace => {
-                                        chat.backspace();
-                                    }
-                                    NcKey::Del => {
-                                        chat.delete();
-                                    }
-                                    NcKey::Left => {
-                                        chat.cursor_left();
-                                    }
-                                    NcKey::Right => {
-                                        chat.cursor_right();
-                                    }
-                                    NcKey::Home => {
-                                        chat.cursor_home();
-                                    }
-                                    NcKey::End => {
-                                        chat.cursor_end();
-                                    }
-                                    NcKey::Resize => {
-                                        let dims = stdplane.dim_yx();
-                                        term_height = dims.0;
-                                        term_width = dims.1;
-                                    }
-                                    _ => {}
-                                }
-                            }
-                        }
-                    } else {
-                        // Game mode input handling
-                        match received {
-                            NcReceived::NoInput => break,
-                            NcReceived::Char('q') | NcReceived::Char('Q') => {
-                                quit = true;
-                                break;
-                            }
-                            NcReceived::Char('b') | NcReceived::Char('B') => {
-                                renderer.toggle_effects();
-                                config.effects_enabled = renderer.effects_enabled;
-                                let _ = config.save();
-                            }
-                            NcReceived::Char('/') => {
-                                // Open chat with / pre-filled for command
-                                chat.open();
-                                chat.insert_char('/');
-                            }
-                            NcReceived::Key(key) => {
-                                let evtype = NcInputType::from(input.evtype);
-                                match key {
-                                    NcKey::Enter => {
-                                        chat.open();
-                                    }
-                                    NcKey::Up | NcKey::Down | NcKey::Left | NcKey::Right => {
-                                        input_state.update_key(key, evtype);
-                                    }
-                                    NcKey::Resize => {
-                                        let dims = stdplane.dim_yx();
-                                        term_height = dims.0;
-                                        term_width = dims.1;
-                                    }
-                                    _ => {}
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                },
-                Err(_) => break,
-            }
+impl CommandRegistry {
+    fn builtin() -> Self {
+        let mut registry = CommandRegistry { commands: Vec::with_capacity(COMMANDS.len()) };
+        for spec in COMMANDS {
+            registry.register(*spec).expect("built-in commands have distinct names");
         }
+        registry
+    }
 
-        if quit {
-            break;
+    /// Add a command after those already known; its name and aliases must be free
+    fn register(&mut self, spec: CommandSpec) -> Result<(), String> {
+        if spec.forms.is_empty() {
+            return Err(format!("/{} needs at least one form", spec.name));
         }
+        if let Some(taken) = std::iter::once(&spec.name).chain(spec.aliases).find(|name| self.find(name).is_some()) {
+            return Err(format!("/{} is already a command", taken));
+        }
+        self.commands.push(spec);
+        Ok(())
+    }
 
-        // Only process movement when not in chat mode
-        if !chat.active {
-            input_state.timeout_stale_keys();
+    /// Commands in help order
+    fn iter(&self) -> impl Iterator<Item = &CommandSpec> {
+        self.commands.iter()
+    }
+    /// Look up a command by name or alias
+    fn find(&self, name: &str) -> Option<&CommandSpec> {
+        self.commands.iter().find(|spec| spec.name == name || spec.aliases.contains(&name))
+    }
 
-            if input_state.any_movement() && last_move_time.elapsed() >= move_delay {
-                let (dx, dy) = input_state.movement_delta();
-                player.try_move(dx, dy, &map);
-                last_move_time = Instant::now();
-            }
+    /// Closest known command to a mistyped name, if any is close enough
+    fn suggest(&self, name: &str) -> Option<&CommandSpec> {
+        if name.is_empty() {
+            return None;
         }
+        self.commands
+            .iter()
+            .flat_map(|spec| std::iter::once(spec.name).chain(spec.aliases.iter().copied()).map(move |n| (spec, n)))
+            .filter_map(|(spec, candidate)| {
+                // Prefixes ("/tar") count as close; otherwise allow a couple of typos
+                let distance = if name.len() >= 2 && candidate.starts_with(name) {
+                    1
+                } else {
+                    edit_distance(name, candidate)
+                };
+                (distance <= 2 && distance < candidate.len()).then_some((distance, spec))
+            })
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, spec)| spec)
+    }
+
+    /// Tab completion of the last word of a partly typed command line; an
+    /// ambiguous word is only extended as far as its choices agree
+    fn complete(&self, text: &str) -> Option<String> {
+        let rest = text.strip_prefix('/')?;
+        let word_start = text.rfind(' ').map_or(1, |i| i + 1);
+        let word = text[word_start..].to_lowercase();
+        if word.is_empty() {
+            return None;
+        }
+        let mut choices: Vec<&str> = if word_start == 1 {
+            self.commands.iter().map(|spec| spec.name).collect()
+        } else {
+            let mut words = rest[..word_start - 1].split_whitespace();
+            let spec = self.find(&words.next()?.to_lowercase())?;
+            let index = words.count();
+            spec.forms.iter().filter_map(|form| form.get(index)).flat_map(|arg| arg.kind.choices()).collect()
+        };
+        choices.retain(|choice| choice.starts_with(&word));
+        choices.sort_unstable();
+        choices.dedup();
+        match choices.as_slice() {
+            [] => None,
+            [only] => Some(format!("{}{} ", &text[..word_start], only)),
+            _ => {
+                let shared = common_prefix(&choices);
+                (shared.len() > word.len()).then(|| format!("{}{}", &text[..word_start], shared))
+            }
+        }
+    }
 
-        // Update animation frame
-        renderer.tick();
+    /// Shown after the input line: commands matching a partly typed name, then the command's usage
+    fn hint(&self, text: &str) -> Option<String> {
+        let rest = text.strip_prefix('/')?;
+        let name = rest.split(' ').next().unwrap_or("").to_lowercase();
+        if name.is_empty() {
+            return None;
+        }
+        if let Some(spec) = self.find(&name) {
+            return Some(spec.usage());
+        }
+        if rest.contains(' ') {
+            return None;
+        }
+        let names: Vec<String> = self
+            .commands
+            .iter()
+            .filter(|spec| spec.name.starts_with(&name))
+            .map(|spec| format!("/{}", spec.name))
+            .collect();
+        (!names.is_empty()).then(|| names.join(" "))
+    }
 
-        // Render
+}
+
+impl CommandSpec {
+    /// Usage line covering every form, e.g. "/target X Y | /target off"
+    fn usage(&self) -> String {
+        self.forms
+            .iter()
+            .map(|form| {
+                std::iter::once(format!("/{}", self.name))
+                    .chain(form.iter().map(|arg| arg.name.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+
+    /// Parse arguments against each form; on failure report the form that got furthest
+    fn parse_args(&self, args: &[&str]) -> Result<Vec<ArgValue>, ArgError> {
+        let mut best: Option<ArgError> = None;
+        for form in self.forms {
+            match Self::parse_form(form, args) {
+                Ok(values) => return Ok(values),
+                Err(err) => {
+                    if best.as_ref().is_none_or(|b| err.index > b.index) {
+                        best = Some(err);
+                    }
+                }
+            }
+        }
+        Err(best.expect("every command has at least one form"))
+    }
+
+    fn parse_form(form: &[ArgSpec], args: &[&str]) -> Result<Vec<ArgValue>, ArgError> {
+        let mut values = Vec::with_capacity(form.len());
+        for (index, spec) in form.iter().enumerate() {
+            let Some(arg) = args.get(index) else {
+                return Err(ArgError { index, message: format!("missing {}", spec.name) });
+            };
+            let value = match spec.kind {
+                ArgKind::Int => arg.parse::<i32>().map(ArgValue::Int).map_err(|_| ArgError {
+                    index,
+                    message: format!("{} must be a number", spec.name),
+                })?,
+                ArgKind::Seed => arg.parse::<u64>().map(ArgValue::Seed).map_err(|_| ArgError {
+                    index,
+                    message: format!("{} must be a whole number from 0", spec.name),
+                })?,
+                ArgKind::Keyword(words) => words
+                    .iter()
+                    .find(|w| w.eq_ignore_ascii_case(arg))
+                    .map(|w| ArgValue::Keyword(w))
+                    .ok_or_else(|| ArgError { index, message: format!("expected '{}'", words[0]) })?,
+                ArgKind::Dice => parse_dice(arg).map(|(count, sides)| ArgValue::Dice(count, sides)).ok_or_else(|| {
+                    ArgError { index, message: format!("dice must look like 2d6 (max {}d{})", MAX_DICE, MAX_DIE_SIDES) }
+                })?,
+                ArgKind::Name => {
+                    if arg.parse::<i32>().is_ok() {
+                        return Err(ArgError { index, message: format!("{} can't be a number", spec.name) });
+                    }
+                    ArgValue::Name(arg.to_string())
+                }
+                ArgKind::Action => Action::from_name(arg).map(ArgValue::Action).ok_or_else(|| ArgError {
+                    index,
+                    message: "unknown action - /bind lists them".to_string(),
+                })?,
+                ArgKind::Difficulty => Difficulty::from_name(arg).map(ArgValue::Difficulty).ok_or_else(|| ArgError {
+                    index,
+                    message: "expected easy, normal or hard".to_string(),
+                })?,
+                ArgKind::ShipClass => ShipClass::from_name(arg).map(ArgValue::ShipClass).ok_or_else(|| ArgError {
+                    index,
+                    message: "expected scout, freighter or interceptor".to_string(),
+                })?,
+                ArgKind::Metric => LeaderboardMetric::from_name(arg).map(ArgValue::Metric).ok_or_else(|| ArgError {
+                    index,
+                    message: "expected distance, ore or credits".to_string(),
+                })?,
+                ArgKind::Key => {
+                    if !arg.eq_ignore_ascii_case("none") && KeySpec::parse(arg).is_none() {
+                        return Err(ArgError { index, message: "unknown key - try a letter, up, space or f5".to_string() });
+                    }
+                    ArgValue::Key(arg.to_string())
+                }
+                ArgKind::Text => {
+                    values.push(ArgValue::Text(args[index..].join(" ")));
+                    return Ok(values);
+                }
+                ArgKind::Color if text_or_off(arg).is_none() => ArgValue::Color(None),
+                ArgKind::Color => parse_hex_color(arg).map(|color| ArgValue::Color(Some(color))).ok_or_else(|| ArgError {
+                    index,
+                    message: "colors look like #FF8800, or none".to_string(),
+                })?,
+            };
+            values.push(value);
+        }
+        if args.len() > form.len() {
+            return Err(ArgError { index: form.len(), message: "unexpected argument".to_string() });
+        }
+        Ok(values)
+    }
+}
+
+/// Text from a command, or None when it's one of the words that clear things ("off", "clear", "none")
+fn text_or_off(text: &str) -> Option<String> {
+    let ArgKind::Keyword(words) = ARG_OFF.kind else {
+        unreachable!("ARG_OFF is a keyword");
+    };
+    (!words.iter().any(|word| word.eq_ignore_ascii_case(text))).then(|| text.to_string())
+}
+
+/// Parse dice notation ("2d6", "d20") into (count, sides) within the server's limits
+fn parse_dice(text: &str) -> Option<(u32, u32)> {
+    let (count, sides) = text.split_once(['d', 'D'])?;
+    let count = if count.is_empty() { 1 } else { count.parse::<u32>().ok()? };
+    let sides = sides.parse::<u32>().ok()?;
+    ((1..=MAX_DICE).contains(&count) && (2..=MAX_DIE_SIDES).contains(&sides)).then_some((count, sides))
+}
+
+/// Coordinates written like "(1234, -56)" in a chat line, with their byte spans
+fn find_coordinates(text: &str) -> Vec<(Range<usize>, (i32, i32))> {
+    let mut found = Vec::new();
+    for (start, _) in text.match_indices('(') {
+        let Some(len) = text[start + 1..].find(')') else {
+            continue;
+        };
+        if let Some((x, y)) = text[start + 1..start + 1 + len].split_once(',')
+            && let (Ok(x), Ok(y)) = (x.trim().parse::<i32>(), y.trim().parse::<i32>())
+        {
+            found.push((start..start + len + 2, (x, y)));
+        }
+    }
+    found
+}
+
+/// Longest start every word shares
+fn common_prefix<'a>(words: &[&'a str]) -> &'a str {
+    let first = words.first().copied().unwrap_or("");
+    let len = words
+        .iter()
+        .map(|word| first.chars().zip(word.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a.len_utf8()).sum())
+        .min()
+        .unwrap_or(0);
+    &first[..len]
+}
+
+/// Levenshtein distance between two strings, in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+/// Send a server-side utility command, or explain why it can't run offline
+fn send_utility(net: &Option<NetClient>, chat: &mut ChatWindow, command: &str, message: ClientMessage) {
+    match net {
+        Some(client) => client.send(message),
+        None => chat.add_message(ChatMessage::error(&format!("{} needs a server connection", command))),
+    }
+}
+
+/// Save a change to the ship's livery and show it: on our own ship, and to other pilots when online
+fn repaint_ship(config: &Config, renderer: &mut Renderer, connection: &mut Connection, chat: &mut ChatWindow) {
+    if let Err(e) = config.save() {
+        chat.add_message(ChatMessage::error(&e));
+    }
+    let (livery, _) = config.livery();
+    renderer.livery = livery.clone();
+    connection.repaint(livery);
+}
+
+/// Today's seed: asked of the server when given one, else worked out from our own clock
+fn daily_seed(server_url: Option<&str>) -> DailySeed {
+    server_url
+        .and_then(|url| fetch_daily(url).ok())
+        .unwrap_or_else(|| DailySeed::for_time(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())))
+}
+
+/// Today's seed being asked of the server on a worker thread, so `/newmap daily` doesn't stall the frame
+struct DailyFetch(Receiver<DailySeed>);
+
+impl DailyFetch {
+    fn start(server_url: &str) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let url = server_url.to_string();
+        thread::spawn(move || {
+            let _ = sender.send(daily_seed(Some(&url)));
+        });
+        DailyFetch(receiver)
+    }
+
+    /// Today's seed once the thread has it; worked out locally if the thread died
+    fn poll(&self) -> Option<DailySeed> {
+        match self.0.try_recv() {
+            Ok(daily) => Some(daily),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(daily_seed(None)),
+        }
+    }
+}
+
+fn fetch_daily(server_url: &str) -> Result<DailySeed, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(2))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let url = format!("{}/map/daily", server_url.trim_end_matches('/'));
+    let response = client.get(url).send().map_err(|e| format!("Failed to ask for today's seed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Server refused today's seed: {}", ServerError::from_response(response)));
+    }
+    response.json().map_err(|e| format!("Bad daily seed: {}", e))
+}
+
+/// One page of a server leaderboard; asked of the configured server even while flying solo
+fn fetch_leaderboard(server_url: &str, metric: LeaderboardMetric, page: u32) -> Result<Leaderboard, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(2))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let url = format!("{}/leaderboard?metric={}&page={}", server_url.trim_end_matches('/'), metric.name(), page);
+    let response = client.get(url).send().map_err(|e| format!("Failed to fetch the leaderboard: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Server refused the leaderboard: {}", ServerError::from_response(response)));
+    }
+    response.json().map_err(|e| format!("Bad leaderboard: {}", e))
+}
+
+/// The server's star systems; an older server without them has none
+fn fetch_systems(server_url: &str) -> Result<Vec<SystemInfo>, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(2))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let url = format!("{}/systems", server_url.trim_end_matches('/'));
+    let response = client.get(url).send().map_err(|e| format!("Failed to fetch the star systems: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Server refused the star systems: {}", ServerError::from_response(response)));
+    }
+    response.json().map_err(|e| format!("Bad star systems: {}", e))
+}
+
+/// Black out the game area and draw a screen's backdrop over it: the title's stars, or warp
+/// streaks when effects are on
+fn draw_backdrop(plane: &mut impl Surface, term_width: u32, game_height: u32, backdrop: Backdrop, effects_enabled: bool) -> NcResult<()> {
+    plane.set_bg_rgb(0x000000);
+    let blank = " ".repeat(term_width as usize);
+    for y in 0..game_height {
+        plane.putstr_yx(Some(y), Some(0), &blank)?;
+    }
+    match backdrop {
+        Backdrop::Starfield(elapsed) => {
+            for (x, y, glyph, color) in title::starfield(term_width, game_height, elapsed) {
+                plane.set_fg_rgb(color);
+                plane.putstr_yx(Some(y), Some(x), &glyph.to_string())?;
+            }
+        }
+        Backdrop::Warp(_) if !effects_enabled => {}
+        Backdrop::Warp(elapsed) => {
+            for (x, y, length, color) in gates::streaks(term_width, game_height, elapsed) {
+                plane.set_fg_rgb(color);
+                plane.putstr_yx(Some(y), Some(x), &"-".repeat(length.min(term_width - x) as usize))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The next input this frame: a key from the terminal or, headless, the script's next key or line
+fn next_input(nc: Option<&mut Nc>, script: Option<&mut Script>, input: &mut NcInput) -> Option<Input> {
+    match (nc, script) {
+        (Some(nc), _) => nc.get_nblock(Some(input)).ok().map(Input::Key),
+        (None, Some(script)) => script.next(input),
+        (None, None) => None,
+    }
+}
+
+/// Headless, write the chat's new messages to stdout, showing each to the script
+fn write_transcript(chat: &mut ChatWindow, script: &mut Script) {
+    for message in chat.outbox.iter_mut().flat_map(|outbox| outbox.drain(..)) {
+        println!("{}", headless::transcript_line(message.time, message.channel.name(), &message.text));
+        script.saw(&message.text);
+    }
+}
+
+/// Draw the loading panel until the map loader is done; None when the player cancels with Esc.
+/// Headless (no `nc`) it just waits
+fn load_map(mut nc: Option<&mut Nc>, config: &Config, seed: u64, charset: Charset) -> NcResult<Option<Result<Map, String>>> {
+    let mut loader = MapLoader::start(config, seed, Instant::now());
+    let mut input = NcInput::new_empty();
+    loop {
+        if let Some(result) = loader.poll() {
+            return Ok(Some(result));
+        }
+        let Some(nc) = nc.as_deref_mut() else {
+            std::thread::sleep(FRAME_SLEEP);
+            continue;
+        };
+        while let Ok(received) = nc.get_nblock(Some(&mut input)) {
+            match received {
+                NcReceived::NoInput => break,
+                NcReceived::Key(NcKey::Esc) => return Ok(None),
+                _ => {}
+            }
+        }
+        let stdplane = unsafe { nc.stdplane() };
+        let (rows, cols) = stdplane.dim_yx();
         stdplane.erase();
+        draw_panel(stdplane, cols, rows, " LOADING ", &loader.lines(Instant::now()), (0x4080C0, 0xE0E0FF, 0x000010), charset)?;
+        nc.render()?;
+        std::thread::sleep(FRAME_SLEEP);
+    }
+}
 
-        let game_height = term_height.saturating_sub(chat_height);
-        let center_screen_x = term_width / 2;
-        let center_screen_y = game_height / 2;
+fn main() -> NcResult<()> {
+    // Command line first, so --help works without a terminal or a config
+    let mut cli = Cli::load();
+    let log_problem = logging::init().err();
+    tracing::info!(version = env!("CARGO_PKG_VERSION"), headless = cli.headless, offline = cli.offline, "Client starting");
+    let mut config = Config::load(cli.config.clone());
+    config.server_override = cli.server.clone();
+
+    // Headless, a script stands in for the player and there's no terminal to draw on
+    let mut script = match cli.headless.then(|| Script::open(cli.script.as_deref())).transpose() {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    // Log in before notcurses owns the terminal, so the password prompt works; headless
+    // stdin may be the script, so only a saved token or the config's password is used
+    let register = cli.register;
+    // Offline nothing touches the server: no login, no world streaming, no multiplayer;
+    // connecting from the title menu ends that
+    let mut offline = cli.offline;
+    let server_url = config.server_url().to_string();
+    let login = match config.account.take() {
+        Some(account) if offline => {
+            config.account = Some(account);
+            None
+        }
+        Some(mut account) => {
+            let old_token = account.token.clone();
+            let result = if script.is_some() {
+                account::authenticate_quietly(&server_url, &mut account, register)
+            } else {
+                account::authenticate(&server_url, &mut account, register)
+            };
+            let token_changed = account.token != old_token;
+            config.account = Some(account);
+            if token_changed && let Err(e) = config.save() {
+                eprintln!("Warning: {}", e);
+            }
+            Some(result)
+        }
+        None if register => Some(Err("--register needs an \"account\" in the config".to_string())),
+        None => None,
+    };
+    // Tokens are the issuing server's; connecting anywhere else logs in again
+    let mut logged_in_at = matches!(login, Some(Ok(_))).then(|| server_url.clone());
 
-        // Render game area
-        for screen_y in 0..game_height {
-            for screen_x in 0..term_width {
-                let map_x = player.x + (screen_x as i32 - center_screen_x as i32);
-                let map_y = player.y + (screen_y as i32 - center_screen_y as i32);
+    // --daily plays today's seed; the server's clock decides the day when there is one
+    let daily = cli.daily.then(|| daily_seed((!offline).then_some(server_url.as_str())));
+    if let Some(daily) = &daily {
+        cli.seed = Some(daily.seed);
+    }
 
-                // Calculate offset from player center for ship rendering
-                let offset_x = screen_x as i32 - center_screen_x as i32;
-                let offset_y = screen_y as i32 - center_screen_y as i32;
+    let mut nc = if script.is_none() { Some(unsafe { Nc::new()? }) } else { None };
+    let charset = config.charset.unwrap_or_else(|| nc.as_ref().map_or(Charset::default(), |nc| Charset::detect(nc.canutf8())));
 
-                // Check if this position is part of the ship or exhaust
-                if let Some(ship_cell) = renderer.get_ship_cell(player.direction, offset_x, offset_y) {
-                    if let Some(bg_color) = ship_cell.bg {
-                        stdplane.set_bg_rgb(bg_color);
-                    } else {
-                        stdplane.set_bg_default();
+    // --server's world loads in the background behind a loading panel; otherwise the game starts
+    // solo behind the title menu, which can connect later
+    let loaded = if cli.connects_at_start() { load_map(nc.as_deref_mut(), &config, cli.seed(), charset)? } else { None };
+    let (mut map, map_notice) = Map::new(loaded, &cli);
+    let start = map.find_start_position();
+    let mut player = Player::new(start.0, start.1);
+    // Online the server sets the rules; solo worlds keep their own difficulty
+    let ship_class = config.ship_class.unwrap_or_default();
+    let mut miner = Miner::default();
+    miner.capacity = ship_class.cargo_capacity();
+    // A logged-in pilot's credits are banked on the server
+    if config.auth_token().is_some() && let Ok(balance) = map.fetch_balance() {
+        miner.inventory.credits = balance.credits;
+    }
+    if map.chunks.is_none() {
+        miner.difficulty = config.difficulty_for(&map.world_key());
+    }
+    let mut autopilot: Option<Autopilot> = None;
+    let mut scripts = Scripts::default();
+    let mut waypoint: Option<Waypoint> = None;
+    let mut mission_log = MissionLog::default();
+    let mut daily_fetch: Option<DailyFetch> = None;
+    let mut renderer = Renderer::new(cli.effects().unwrap_or(config.effects_enabled));
+    renderer.ship_class = ship_class;
+    renderer.charset = charset;
+    let mut chat = ChatWindow::new();
+    chat.apply_config(&config);
+    chat.add_message(map_notice);
+    if let Some(e) = log_problem {
+        chat.add_message(ChatMessage::error(&format!("No log file: {}", e)));
+    }
+    if let Some(daily) = daily {
+        chat.add_message(ChatMessage::system(&format!("Today's map: {} (seed {})", daily.date, daily.seed)));
+    }
+    // Headless runs keep out of the player's history and print the chat instead
+    if script.is_some() {
+        chat.outbox = Some(Vec::new());
+    } else {
+        chat.history = InputHistory::load();
+    }
+    if let Err(e) = apply_theme(config.theme_name(), &mut renderer, &mut chat) {
+        chat.add_message(ChatMessage::error(&format!("{}; using the default theme", e)));
+    }
+    let (mut keymap, key_errors) = KeyMap::from_config(&config.keybindings, config.input_profile);
+    for error in key_errors {
+        chat.add_message(ChatMessage::error(&format!("Bad keybinding {}", error)));
+    }
+    let (livery, livery_errors) = config.livery();
+    renderer.livery = livery;
+    for error in livery_errors {
+        chat.add_message(ChatMessage::error(&error));
+    }
+    let mut pip = PipCamera::default();
+    match login {
+        Some(Ok(name)) => chat.add_message(ChatMessage::system(&format!("Logged in as {}", name))),
+        Some(Err(e)) => chat.add_message(ChatMessage::error(&format!("Login failed, playing as a guest: {}", e))),
+        None => {}
+    }
+
+    // Multiplayer is optional - the game stays playable without it, and reconnects when it can
+    let mut remote = RemotePlayers::default();
+    let mut connection = if !cli.connects_at_start() {
+        Connection::offline(Instant::now())
+    } else {
+        let (connection, result) = Connection::start(&config, Instant::now());
+        if let Err(e) = result {
+            chat.add_message(ChatMessage::error(&format!("Multiplayer unavailable, retrying: {}", e)));
+        }
+        connection
+    };
+    let mut last_sent_position = None;
+    // The jump gates' systems, while streaming the server's world
+    let mut systems = if map.chunks.is_some() { fetch_systems(config.server_url()).unwrap_or_default() } else { Vec::new() };
+    let mut net_diagnostics = NetDiagnostics::new(Instant::now());
+    let mut ship = ShipStatus::new(ship_class.max_hull());
+    // The title menu first, then the shipyard until the config has a ship; headless starts in flight
+    let mut screens = ScreenManager::default();
+    if script.is_none() {
+        let shipyard = config.ship_class.is_none().then(|| Box::new(ShipMenuScreen) as Box<dyn Screen>);
+        screens.push(Box::new(TitleScreen::new(Instant::now(), config.server_url(), session::latest_summary(), shipyard)));
+    }
+    let mut hud = Hud::default();
+    let mut screenshot_due = false;
+
+    // Startup commands go through the same queue (and validation) as typed ones
+    let mut pending_commands: VecDeque<ChatCommand> = config
+        .autoexec
+        .iter()
+        .filter_map(|line| chat.process_autoexec(line))
+        .collect();
+
+    let mut stdplane = nc.as_deref_mut().map(|nc| unsafe { nc.stdplane() });
+    let (mut term_height, mut term_width) = stdplane.as_ref().map_or(HEADLESS_SIZE, |plane| plane.dim_yx());
+
+    let mut input_state = InputState { key_timeout: config.key_timeout(), ..Default::default() };
+    let mut clock = RealClock;
+    let mut last_explored_save = clock.now();
+    let mut move_steps = FixedStep::new(config.move_delay(ship_class.move_delay()), clock.now());
+    let mut ship_ticks = FixedStep::new(SHIP_TICK, clock.now());
+    let mut animation = FixedStep::new(ANIMATION_STEP, clock.now());
+    let mut pacer = FramePacer::new(config.target_fps(), clock.now());
+    let mut perf = PerfStats::new(clock.now());
+    let mut region_banner = RegionBanner::default();
+    let mut guns = Guns::new(clock.now());
+    let mut radar = Radar::new(clock.now());
+    let mut bubbles = Bubbles::default();
+    let mut camera = Camera::default();
+    // Why a headless run stopped short, for the exit status
+    let mut script_error = None;
+
+    loop {
+        // One reading of the clock per frame; everything below runs at this instant
+        let now = clock.now();
+        let mut quit = false;
+        let mut input = NcInput::new_empty();
+        if let Some(script) = script.as_mut()
+            && let Err(e) = script.advance(now)
+        {
+            script_error = Some(e);
+            break;
+        }
+
+        while let Some(next) = next_input(nc.as_deref_mut(), script.as_mut(), &mut input) {
+            let received = match next {
+                Input::Key(received) => received,
+                // Scripted lines are sent as if typed, whatever has the keys
+                Input::Line(text) => {
+                    chat.add_message(ChatMessage::user(&text));
+                    if let Some(cmd) = chat.process_input(&text) {
+                        pending_commands.push_back(cmd);
                     }
-                    stdplane.set_fg_rgb(ship_cell.fg);
-                    let s: String = ship_cell.ch.into();
-                    stdplane.putstr_yx(Some(screen_y), Some(screen_x), &s)?;
-                    stdplane.set_bg_default();
-                } else {
-                    // Render map tile
-                    let tile = map.get(map_x, map_y);
-                    let (ch, fg) = renderer.render_tile(tile, map_x, map_y);
-
-                    stdplane.set_fg_rgb(fg);
-                    stdplane.set_bg_default();
-                    let s: String = ch.into();
-                    stdplane.putstr_yx(Some(screen_y), Some(screen_x), &s)?;
-                }
-            }
-        }
-
-        // Render chat messages
-        stdplane.set_bg_rgb(0x000010);
-        let msg_start_y = game_height;
-        for (i, msg) in chat.visible_messages().enumerate() {
-            stdplane.set_fg_rgb(msg.color);
-            let truncated: String = msg.text.chars().take(term_width as usize).collect();
-            let padded = format!("{:<width$}", truncated, width = term_width as usize);
-            stdplane.putstr_yx(Some(msg_start_y + i as u32), Some(0), &padded)?;
-        }
-        // Fill remaining message lines if fewer messages
-        let msg_count = chat.visible_messages().count();
-        for i in msg_count..chat.visible_lines {
-            let blank = " ".repeat(term_width as usize);
-            stdplane.set_fg_rgb(0x404040);
-            stdplane.putstr_yx(Some(msg_start_y + i as u32), Some(0), &blank)?;
-        }
-
-        // Render chat input line
-        let input_y = term_height - 2;
-        stdplane.set_bg_rgb(0x000020);
-        if chat.active {
-            stdplane.set_fg_rgb(0x00FFFF);
-            let prompt = "> ";
-            let input_display: String = chat.input.chars().take(term_width as usize - 2).collect();
-            let input_line = format!("{}{:<width$}", prompt, input_display, width = term_width as usize - 2);
-            stdplane.putstr_yx(Some(input_y), Some(0), &input_line)?;
-
-            // Show cursor (by inverting colors at cursor position)
-            let cursor_x = 2 + chat.display_cursor_pos();
-            if cursor_x < term_width as usize {
-                stdplane.set_fg_rgb(0x000020);
-                stdplane.set_bg_rgb(0x00FFFF);
-                let cursor_char = chat.input.chars().nth(chat.display_cursor_pos()).unwrap_or(' ');
-                let cursor_str: String = cursor_char.into();
-                stdplane.putstr_yx(Some(input_y), Some(cursor_x as u32), &cursor_str)?;
+                    continue;
+                }
+            };
+            if received == NcReceived::Key(NcKey::Resize) {
+                if let Some(plane) = &stdplane {
+                    (term_height, term_width) = plane.dim_yx();
+                }
+                continue;
+            }
+            if chat.active {
+                // Chat mode input handling
+                match received {
+                    NcReceived::NoInput => break,
+                    NcReceived::Char(ch) if input.ctrl_p() => {
+                        // Line editing shortcuts
+                        match ch.to_ascii_lowercase() {
+                            'k' => chat.kill_to_end(),
+                            'w' => chat.delete_word_before(),
+                            'z' | '_' => chat.undo(),
+                            _ => {}
+                        }
+                    }
+                    NcReceived::Char('\t') => chat.complete(),
+                    NcReceived::Char(ch) => {
+                        if ch.is_ascii_graphic() || ch == ' ' {
+                            chat.insert_char(ch);
+                        }
+                    }
+                    NcReceived::Key(key) => {
+                        match key {
+                            NcKey::Enter => {
+                                if let Some(text) = chat.submit()
+                                    && let Some(cmd) = chat.process_input(&text)
+                                {
+                                    pending_commands.push_back(cmd);
+                                }
+                            }
+                            NcKey::Esc => {
+                                chat.close();
+                            }
+                            NcKey::Backspace => {
+                                chat.backspace();
+                            }
+                            NcKey::Del => {
+                                chat.delete();
+                            }
+                            NcKey::Left if input.ctrl_p() => {
+                                chat.word_left();
+                            }
+                            NcKey::Right if input.ctrl_p() => {
+                                chat.word_right();
+                            }
+                            NcKey::Left => {
+                                chat.cursor_left();
+                            }
+                            NcKey::Right => {
+                                chat.cursor_right();
+                            }
+                            NcKey::Up => {
+                                chat.history_up();
+                            }
+                            NcKey::Down => {
+                                chat.history_down();
+                            }
+                            NcKey::Tab => {
+                                chat.complete();
+                            }
+                            NcKey::PgUp | NcKey::PgDown => {
+                                chat.log.scroll_key(key);
+                            }
+                            NcKey::Home => {
+                                chat.cursor_home();
+                            }
+                            NcKey::End => {
+                                chat.cursor_end();
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            } else {
+                // Game mode input handling, through the configurable keymap
+                if received == NcReceived::NoInput {
+                    break;
+                }
+
+                let evtype = NcInputType::from(input.evtype);
+                // The chat log covers everything, so it takes keys first; Enter and / still open chat under it
+                if chat.log.is_open() && evtype != NcInputType::Release {
+                    match received {
+                        NcReceived::Key(NcKey::Esc) => chat.log.close(),
+                        NcReceived::Key(key) => {
+                            chat.log.scroll_key(key);
+                        }
+                        _ => {}
+                    }
+                    if !matches!(keymap.action_for(received), Some(Action::Chat | Action::Command)) {
+                        continue;
+                    }
+                }
+                // Pushed screens (title, shipyard, docked, stats, game over) take keys before the game
+                if !screens.is_empty() {
+                    if evtype != NcInputType::Release {
+                        let press = KeyPress { key: screen_key(received), action: keymap.action_for(received) };
+                        let mut ctx = ScreenContext {
+                            ship: &mut ship,
+                            miner: &mut miner,
+                            map: &map,
+                            chat: &mut chat,
+                            commands: &mut pending_commands,
+                            config: &mut config,
+                        };
+                        screens.handle_input(press, &mut ctx);
+                    }
+                    continue;
+                }
+                let Some(action) = keymap.action_for(received) else {
+                    continue;
+                };
+                if action.is_movement() {
+                    // Manual thrust takes over from the autopilot; a free camera takes the keys instead
+                    if evtype != NcInputType::Release && !camera.is_free() && autopilot.take().is_some() {
+                        chat.add_message(ChatMessage::system("Autopilot off - manual control"));
+                    }
+                    input_state.update_move(action, evtype, now);
+                    continue;
+                }
+                // Everything else fires once per press
+                if evtype == NcInputType::Release {
+                    continue;
+                }
+                match action {
+                    Action::Quit => {
+                        quit = true;
+                        break;
+                    }
+                    Action::ToggleEffects => {
+                        renderer.toggle_effects();
+                        config.effects_enabled = renderer.effects_enabled;
+                        let _ = config.save();
+                    }
+                    Action::TogglePip => {
+                        if pip.target.is_some() {
+                            pip.toggle();
+                        } else {
+                            chat.add_message(ChatMessage::error("No target set - use /target X Y"));
+                        }
+                    }
+                    Action::ToggleMinimap => {
+                        renderer.minimap.toggle();
+                    }
+                    Action::ToggleRadar => {
+                        radar.toggle();
+                    }
+                    Action::ToggleMissions => {
+                        if mission_log.visible {
+                            mission_log.toggle();
+                        } else {
+                            pending_commands.push_back(ChatCommand::Missions);
+                        }
+                    }
+                    Action::TogglePerf => {
+                        perf.toggle();
+                    }
+                    Action::ToggleNetStats => {
+                        net_diagnostics.toggle();
+                    }
+                    Action::Settings => {
+                        pending_commands.push_back(ChatCommand::Settings);
+                    }
+                    Action::Screenshot => {
+                        pending_commands.push_back(ChatCommand::Screenshot);
+                    }
+                    Action::Spectate => {
+                        pending_commands.push_back(ChatCommand::ToggleSpectate);
+                    }
+                    Action::SnapCamera => {
+                        camera.snap_to((player.x, player.y));
+                    }
+                    Action::ZoomIn | Action::ZoomOut => {
+                        if renderer.step_zoom(action == Action::ZoomOut) {
+                            chat.add_message(ChatMessage::system(&format!("Zoom {}x", renderer.zoom)));
+                        }
+                    }
+                    Action::Mine => {
+                        pending_commands.push_back(ChatCommand::Mine);
+                    }
+                    Action::FireLaser => {
+                        pending_commands.push_back(ChatCommand::FireLaser);
+                    }
+                    Action::Fire => {
+                        pending_commands.push_back(ChatCommand::Fire);
+                    }
+                    Action::Dock => {
+                        let stations = station_pois(&remote);
+                        match docking::station_in_range((player.x, player.y), stations.iter().chain(map.pois().iter())) {
+                            Some(station) => {
+                                player.stop();
+                                autopilot = None;
+                                chat.add_message(ChatMessage::system(&format!("Docked at {}", station.name)));
+                                screens.push(Box::new(DockedScreen::new(&station.name)));
+                            }
+                            None => chat.add_message(ChatMessage::error(&format!(
+                                "No station within {} tiles to dock at",
+                                DOCKING_RANGE
+                            ))),
+                        }
+                    }
+                    Action::Jump => {
+                        pending_commands.push_back(ChatCommand::Jump);
+                    }
+                    Action::ChatPane => {
+                        chat.cycle_pane();
+                    }
+                    Action::ChatLog => {
+                        chat.log.open();
+                    }
+                    Action::Command => {
+                        // Open chat with / pre-filled for command
+                        chat.open();
+                        chat.insert_char('/');
+                    }
+                    Action::Chat => {
+                        chat.open();
+                    }
+                    Action::MoveUp
+                    | Action::MoveDown
+                    | Action::MoveLeft
+                    | Action::MoveRight
+                    | Action::MoveUpLeft
+                    | Action::MoveUpRight
+                    | Action::MoveDownLeft
+                    | Action::MoveDownRight => {}
+                }
             }
-        } else {
-            stdplane.set_fg_rgb(0x606060);
-            let hint = format!("{:<width$}", "Press Enter to chat, / for commands", width = term_width as usize);
-            stdplane.putstr_yx(Some(input_y), Some(0), &hint)?;
         }
-        stdplane.set_bg_default();
 
-        // Status bar
-        let current_tile = map.get(player.x, player.y);
-        let tile_name = match current_tile {
-            Some(Tile::Floor) => "Space",
-            Some(Tile::Nebula) => "Nebula",
-            _ => "???",
-        };
+        // A running script's tick; what it sends runs with the chat's commands
+        if let Some(result) = scripts.tick(now, || script_view(&player, autopilot.is_some(), &map)) {
+            apply_script_report(result, &mut chat, &mut pending_commands);
+        }
 
-        stdplane.set_fg_rgb(0x00FF00);
-        stdplane.set_bg_rgb(0x000020);
+        // Today's seed for `/newmap daily`, once the server has answered
+        if let Some(daily) = daily_fetch.as_ref().and_then(DailyFetch::poll) {
+            daily_fetch = None;
+            pending_commands.push_back(ChatCommand::NewMap(Some(daily.seed)));
+        }
 
-        let effects_indicator = if renderer.effects_enabled { "FX:ON" } else { "FX:OFF" };
-        let mode_indicator = if chat.active { "[CHAT]" } else { "" };
-        let status = format!(
-            " ({:>4},{:>4}) {:>2} | {} | {} {} ",
-            player.x,
-            player.y,
-            player.direction.name(),
-            tile_name,
-            effects_indicator,
-            mode_indicator
-        );
-        let padded_status = format!("{:<width$}", status, width = term_width as usize);
-        stdplane.putstr_yx(Some(term_height - 1), Some(0), &padded_status)?;
-        stdplane.set_bg_default();
+        // Run commands from the chat line and autoexec
+        while let Some(cmd) = pending_commands.pop_front() {
+            match cmd {
+                ChatCommand::Quit => {
+                    quit = true;
+                }
+                ChatCommand::ShowPosition => {
+                    let region = map.regions().at(player.x, player.y).map_or(String::new(), |region| format!(" in {} (danger: {})", region.name, region.danger_name()));
+                    chat.add_message(ChatMessage::system(
+                        &format!("Position: ({}, {}){} - {} tiles explored", player.x, player.y, region, map.explored.count())
+                    ));
+                }
+                ChatCommand::Teleport(x, y) => {
+                    map.load_around(x, y, 0, 0);
+                    if map.is_passable(x, y) {
+                        player.x = x;
+                        player.y = y;
+                        player.stop();
+                        autopilot = None;
+                        camera.snap_to((x, y));
+                        screens.close(ScreenKind::Docked);
+                        chat.add_message(ChatMessage::system(
+                            &format!("Teleported to ({}, {})", x, y)
+                        ));
+                    } else {
+                        chat.add_message(ChatMessage::error(
+                            &format!("Cannot teleport to ({}, {}) - not passable", x, y)
+                        ));
+                    }
+                }
+                ChatCommand::ToggleEffects => {
+                    renderer.toggle_effects();
+                    config.effects_enabled = renderer.effects_enabled;
+                    let _ = config.save();
+                    chat.add_message(ChatMessage::system(
+                        &format!("Effects: {}", if renderer.effects_enabled { "ON" } else { "OFF" })
+                    ));
+                }
+                ChatCommand::SetTarget(x, y) => {
+                    pip.set_target(x, y);
+                    chat.add_message(ChatMessage::system(
+                        &format!("Watching ({}, {})", x, y)
+                    ));
+                }
+                ChatCommand::ToggleSpectate => {
+                    let message = if camera.toggle((player.x, player.y)) {
+                        "Spectating - the movement keys pan the view, Home snaps back to the ship, F5 follows it again"
+                    } else {
+                        "Following your ship"
+                    };
+                    chat.add_message(ChatMessage::system(message));
+                }
+                ChatCommand::FollowShip => {
+                    camera.follow();
+                    chat.add_message(ChatMessage::system("Following your ship"));
+                }
+                ChatCommand::ClearTarget => {
+                    pip.clear_target();
+                    chat.add_message(ChatMessage::system("Target cleared"));
+                }
+                ChatCommand::Roll(count, sides) => {
+                    send_utility(&connection.client, &mut chat, "/roll", ClientMessage::Roll { count, sides });
+                }
+                ChatCommand::Flip => send_utility(&connection.client, &mut chat, "/flip", ClientMessage::Flip),
+                ChatCommand::ServerTime => {
+                    let time = light::cycle_time(remote.server_time(now));
+                    chat.add_message(ChatMessage::system(&light::describe(time, config.light_level())));
+                    if let Some(client) = &connection.client {
+                        client.send(ClientMessage::Time);
+                    }
+                }
+                ChatCommand::Distance(target) => {
+                    send_utility(&connection.client, &mut chat, "/distance", ClientMessage::Distance { target });
+                }
+                ChatCommand::Stats => send_utility(&connection.client, &mut chat, "/stats", ClientMessage::Stats),
+                ChatCommand::Settings => {
+                    screens.close(ScreenKind::Settings);
+                    screens.push(Box::new(SettingsScreen::new(&config, &map)));
+                }
+                ChatCommand::Screenshot if nc.is_none() => {
+                    chat.add_message(ChatMessage::error("Headless there's no screen to save"));
+                }
+                ChatCommand::Screenshot => screenshot_due = true,
+                ChatCommand::ApplySettings => {
+                    renderer.effects_enabled = config.effects_enabled;
+                    move_steps.set_step(config.move_delay(config.ship_class.unwrap_or_default().move_delay()));
+                    input_state.key_timeout = config.key_timeout();
+                    pacer.set_fps(config.target_fps());
+                    if let Err(e) = apply_theme(config.theme_name(), &mut renderer, &mut chat) {
+                        chat.add_message(ChatMessage::error(&e));
+                    }
+                }
+                ChatCommand::SetHome => send_utility(&connection.client, &mut chat, "/sethome", ClientMessage::SetHome),
+                ChatCommand::Respawn => send_utility(&connection.client, &mut chat, "/respawn", ClientMessage::Respawn),
+                ChatCommand::Bind(action, key) => match keymap.bind_spec(action, &key) {
+                    Ok(previous) => {
+                        let key_name = keymap.key_for(action).map_or("none".to_string(), KeySpec::name);
+                        chat.add_message(ChatMessage::system(&format!("{} = {}", action.name(), key_name)));
+                        if let Some(other) = previous {
+                            let left = keymap.key_for(other).map_or("is now unbound".to_string(), |key| format!("keeps {}", key.name()));
+                            chat.add_message(ChatMessage::system(&format!("{} {}", other.name(), left)));
+                        }
+                        config.keybindings = keymap.to_config();
+                        if let Err(e) = config.save() {
+                            chat.add_message(ChatMessage::error(&e));
+                        }
+                    }
+                    Err(e) => chat.add_message(ChatMessage::error(&format!("Can't bind {}: {}", action.name(), e))),
+                },
+                ChatCommand::ShowContent => {
+                    for line in describe_content(remote.content()) {
+                        chat.add_message(ChatMessage::system(&line));
+                    }
+                }
+                ChatCommand::SetDifficulty(_) | ChatCommand::ShowDifficulty if map.chunks.is_some() => {
+                    chat.add_message(ChatMessage::error("Difficulty only applies to solo worlds; the server sets the rules online"));
+                }
+                ChatCommand::SetDifficulty(difficulty) => {
+                    miner.difficulty = difficulty;
+                    config.solo_difficulty.insert(map.world_key(), difficulty);
+                    if let Err(e) = config.save() {
+                        chat.add_message(ChatMessage::error(&e));
+                    }
+                    chat.add_message(ChatMessage::system(&format!("Difficulty: {}", difficulty.name())));
+                }
+                ChatCommand::SaveMap(_) | ChatCommand::LoadMap(_) if map.chunks.is_some() => {
+                    chat.add_message(ChatMessage::error("Streamed worlds live on the server; only solo maps are saved"));
+                }
+                ChatCommand::SaveMap(name) => match saves::save(&name, &map.to_saved((player.x, player.y))) {
+                    Ok(()) => chat.add_message(ChatMessage::system(&format!("Saved map '{}'", name))),
+                    Err(e) => chat.add_message(ChatMessage::error(&e)),
+                },
+                ChatCommand::LoadMap(name) => match saves::load(&name) {
+                    Ok(saved) => {
+                        let _ = map.save_explored();
+                        map = Map::from_saved(saved);
+                        (player.x, player.y) = map.find_start_position();
+                        player.stop();
+                        autopilot = None;
+                        waypoint = None;
+                        camera.follow();
+                        miner.difficulty = config.difficulty_for(&map.world_key());
+                        chat.add_message(ChatMessage::system(&format!("Loaded map '{}' (seed {})", name, map.seed)));
+                    }
+                    Err(e) => chat.add_message(ChatMessage::error(&e)),
+                },
+                ChatCommand::CommunityMaps | ChatCommand::PlayCommunityMap(_) | ChatCommand::UploadMap(_) if offline => {
+                    chat.add_message(ChatMessage::error("Offline: community maps are kept by the server"));
+                }
+                ChatCommand::CommunityMaps => match community::fetch_listings(config.server_url()) {
+                    Ok(listings) => {
+                        screens.close(ScreenKind::CommunityMaps);
+                        screens.push(Box::new(CommunityScreen::new(listings)));
+                    }
+                    Err(e) => chat.add_message(ChatMessage::error(&e)),
+                },
+                ChatCommand::PlayCommunityMap(id) => match community::fetch_map(config.server_url(), id) {
+                    Ok(data) => {
+                        let _ = map.save_explored();
+                        if map.chunks.is_some() {
+                            connection.hang_up();
+                            remote = RemotePlayers::default();
+                            last_sent_position = None;
+                            systems.clear();
+                        }
+                        let seed = community::map_seed(&data);
+                        map = Map::from_saved(saves::SavedMap { seed, map: data, explored: ExploredTiles::default() });
+                        map.explored = ExploredTiles::load(&map.world_key());
+                        (player.x, player.y) = map.find_start_position();
+                        player.stop();
+                        autopilot = None;
+                        waypoint = None;
+                        camera.follow();
+                        mission_log.clear();
+                        miner.difficulty = config.difficulty_for(&map.world_key());
+                        tracing::info!(id, seed, "Flying community map");
+                        chat.add_message(ChatMessage::system(&format!("Flying community map {} ({}x{}, seed {})", id, map.width, map.height, seed)));
+                    }
+                    Err(e) => chat.add_message(ChatMessage::error(&e)),
+                },
+                ChatCommand::UploadMap(_) if map.chunks.is_some() => {
+                    chat.add_message(ChatMessage::error("Streamed worlds live on the server; only solo maps are uploaded"));
+                }
+                ChatCommand::UploadMap(name) => match config.auth_token() {
+                    Some(token) => match community::upload(config.server_url(), token, &name, &map.to_saved((player.x, player.y)).map) {
+                        Ok(listing) => chat.add_message(ChatMessage::system(&format!("Shared '{}' as community map {}", listing.name, listing.id))),
+                        Err(e) => chat.add_message(ChatMessage::error(&e)),
+                    },
+                    None => chat.add_message(ChatMessage::error("Uploading needs an account: add one to the config and log in")),
+                },
+                ChatCommand::ShowSeed => {
+                    let world = if map.chunks.is_some() { "streamed world" } else { "local map" };
+                    chat.add_message(ChatMessage::system(&format!("Seed {} ({})", map.seed, world)));
+                }
+                ChatCommand::DebugLog(lines) => match logging::tail(lines) {
+                    Ok(tail) => {
+                        chat.add_message(ChatMessage::system(&format!("Log ({} lines, in {}):", tail.len(), logging::dir().map_or(String::new(), |dir| dir.display().to_string()))));
+                        for line in tail {
+                            chat.add_message(ChatMessage::system(&line));
+                        }
+                    }
+                    Err(e) => chat.add_message(ChatMessage::error(&e)),
+                },
+                // The server's world has one seed; other seeds are flown solo
+                ChatCommand::NewMap(_) if map.chunks.is_some() => {
+                    chat.add_message(ChatMessage::error("You're flying the server's world; /newgame to go solo first"));
+                }
+                ChatCommand::NewMap(None) if !offline => {
+                    if daily_fetch.is_none() {
+                        daily_fetch = Some(DailyFetch::start(config.server_url()));
+                        chat.add_message(ChatMessage::system("Asking the server for today's seed..."));
+                    }
+                }
+                ChatCommand::NewMap(seed) => {
+                    let seed = seed.unwrap_or_else(|| daily_seed(None).seed);
+                    let _ = map.save_explored();
+                    map = Map::generate_local(map.width, map.height, seed);
+                    map.explored = ExploredTiles::load(&map.world_key());
+                    (player.x, player.y) = map.find_start_position();
+                    player.stop();
+                    autopilot = None;
+                    waypoint = None;
+                    camera.follow();
+                    mission_log.clear();
+                    miner.difficulty = config.difficulty_for(&map.world_key());
+                    chat.add_message(ChatMessage::system(&format!("New map, seed {}", seed)));
+                }
+                ChatCommand::SetShip(class) => {
+                    config.ship_class = Some(class);
+                    if let Err(e) = config.save() {
+                        chat.add_message(ChatMessage::error(&e));
+                    }
+                    renderer.ship_class = class;
+                    move_steps.set_step(config.move_delay(class.move_delay()));
+                    miner.capacity = class.cargo_capacity();
+                    ship.refit(class.max_hull());
+                    chat.add_message(ChatMessage::system(&format!("Now flying a {}", class.describe())));
+                }
+                ChatCommand::SetTheme(name) => match apply_theme(&name, &mut renderer, &mut chat) {
+                    Ok(()) => {
+                        config.theme = Some(name.clone());
+                        if let Err(e) = config.save() {
+                            chat.add_message(ChatMessage::error(&e));
+                        }
+                        chat.add_message(ChatMessage::system(&format!("Theme: {}", name)));
+                    }
+                    Err(e) => chat.add_message(ChatMessage::error(&e)),
+                },
+                ChatCommand::ShowThemes => {
+                    chat.add_message(ChatMessage::system(&format!("Theme: {}", config.theme_name())));
+                    for name in theme::available().into_iter().filter(|name| name != config.theme_name()) {
+                        chat.add_message(ChatMessage::system(&format!("  /theme {}", name)));
+                    }
+                }
+                ChatCommand::RunScript(name) => {
+                    let view = script_view(&player, autopilot.is_some(), &map);
+                    let result = scripting::load(&name).and_then(|source| scripts.run(&name, &source, view, now));
+                    apply_script_report(result, &mut chat, &mut pending_commands);
+                    if let Some(running) = scripts.running() {
+                        chat.add_message(ChatMessage::system(&format!("Script {} running (/run stop ends it)", running)));
+                    }
+                }
+                ChatCommand::StopScript => match scripts.stop() {
+                    Some(name) => chat.add_message(ChatMessage::system(&format!("Stopped script {}", name))),
+                    None => chat.add_message(ChatMessage::system("No script is running")),
+                },
+                ChatCommand::ListScripts => {
+                    if let Some(running) = scripts.running() {
+                        chat.add_message(ChatMessage::system(&format!("Running: {}", running)));
+                    }
+                    let names = scripting::available();
+                    if names.is_empty() {
+                        let dir = scripting::dir().map_or("the scripts directory".to_string(), |dir| dir.display().to_string());
+                        chat.add_message(ChatMessage::system(&format!("No scripts yet; put NAME.rhai files in {}", dir)));
+                    }
+                    for name in names {
+                        chat.add_message(ChatMessage::system(&format!("  /run {}", name)));
+                    }
+                }
+                ChatCommand::ShowShip => {
+                    let current = config.ship_class.unwrap_or_default();
+                    let livery = &renderer.livery;
+                    let named = livery.name.as_ref().map_or(String::new(), |name| format!(", the {}", name));
+                    chat.add_message(ChatMessage::system(&format!("Flying a {}{}", current.describe(), named)));
+                    if let Some(callsign) = &livery.callsign {
+                        chat.add_message(ChatMessage::system(&format!("Callsign {}", callsign)));
+                    }
+                    if !livery.colors.is_empty() {
+                        let paint: Vec<String> = livery.colors.iter().map(|(part, color)| format!("{} #{:06X}", part.name(), color)).collect();
+                        chat.add_message(ChatMessage::system(&format!("Paint: {}", paint.join(", "))));
+                    }
+                    for class in ShipClass::ALL.into_iter().filter(|&class| class != current) {
+                        chat.add_message(ChatMessage::system(&format!("  /ship {}", class.describe())));
+                    }
+                }
+                ChatCommand::NameShip(name) => match name.as_deref().map(ShipLivery::check_name).transpose() {
+                    Ok(name) => {
+                        let message = name.as_ref().map_or("Ship name cleared".to_string(), |name| format!("Your ship is now the {}", name));
+                        config.ship_name = name;
+                        repaint_ship(&config, &mut renderer, &mut connection, &mut chat);
+                        chat.add_message(ChatMessage::system(&message));
+                    }
+                    Err(e) => chat.add_message(ChatMessage::error(&format!("Can't name the ship: {}", e))),
+                },
+                ChatCommand::SetCallsign(callsign) => match callsign.as_deref().map(ShipLivery::check_callsign).transpose() {
+                    Ok(callsign) => {
+                        let message = callsign.as_ref().map_or("Callsign cleared".to_string(), |callsign| format!("Callsign {}", callsign));
+                        config.callsign = callsign;
+                        repaint_ship(&config, &mut renderer, &mut connection, &mut chat);
+                        chat.add_message(ChatMessage::system(&message));
+                    }
+                    Err(e) => chat.add_message(ChatMessage::error(&format!("Can't set the callsign: {}", e))),
+                },
+                ChatCommand::PaintShip(part, color) => {
+                    let message = match color {
+                        Some(color) => {
+                            config.ship_colors.insert(part, format!("#{:06X}", color));
+                            format!("Painted the {} #{:06X}", part.name(), color)
+                        }
+                        None => {
+                            config.ship_colors.remove(&part);
+                            format!("The {} is back to the theme's color", part.name())
+                        }
+                    };
+                    repaint_ship(&config, &mut renderer, &mut connection, &mut chat);
+                    chat.add_message(ChatMessage::system(&message));
+                }
+                ChatCommand::ListPois => {
+                    let stations = station_pois(&remote);
+                    let lines = poi::listing((player.x, player.y), map.pois().iter().chain(&stations));
+                    if lines.is_empty() {
+                        chat.add_message(ChatMessage::system("No points of interest found yet; go exploring"));
+                    } else {
+                        chat.add_message(ChatMessage::system(&format!("Points of interest ({}):", lines.len())));
+                        for line in lines.iter().take(poi::MAX_LISTED) {
+                            chat.add_message(ChatMessage::system(&format!("  {}", line)));
+                        }
+                    }
+                }
+                ChatCommand::ShowDifficulty => {
+                    chat.add_message(ChatMessage::system(&format!("Difficulty: {} (easy, normal or hard)", miner.difficulty.name())));
+                }
+                ChatCommand::ListBindings => {
+                    chat.add_message(ChatMessage::system("Keys:"));
+                    for line in keymap.describe() {
+                        chat.add_message(ChatMessage::system(&format!("  {}", line)));
+                    }
+                }
+                ChatCommand::Mine => match miner.start((player.x, player.y), |x, y| map.get(x, y)) {
+                    Ok(resource) => chat.add_message(ChatMessage::system(&format!("Mining {}...", resource.name()))),
+                    Err(e) => chat.add_message(ChatMessage::error(&e)),
+                },
+                ChatCommand::FireLaser => match miner.fire_laser((player.x, player.y), |x, y| map.get(x, y)) {
+                    Ok(()) => chat.add_message(ChatMessage::system("Firing mining laser...")),
+                    Err(e) => chat.add_message(ChatMessage::error(&e)),
+                },
+                // Held-down fire is paced by the cooldown rather than refused out loud
+                ChatCommand::Fire => {
+                    if !screens.holds_ship() && guns.trigger(now) {
+                        match &connection.client {
+                            Some(client) => client.send(ClientMessage::Fire { direction: player.direction }),
+                            None => guns.fire((player.x, player.y), player.direction),
+                        }
+                    }
+                }
+                ChatCommand::Navigate(x, y) => {
+                    // The route can only use chunks we have, so fetch the stretch between here and there
+                    // Far-off coordinates are refused by `plan()`; they'd overflow the sums here
+                    let (dx, dy) = (x.abs_diff(player.x), y.abs_diff(player.y));
+                    if dx.max(dy) <= MAX_NAV_DISTANCE as u32 {
+                        let radius_x = (dx / 2) as i32 + SEARCH_MARGIN;
+                        let radius_y = (dy / 2) as i32 + SEARCH_MARGIN;
+                        map.load_around(player.x + (x - player.x) / 2, player.y + (y - player.y) / 2, radius_x, radius_y);
+                    }
+                    match Autopilot::plan((player.x, player.y), (x, y), config.physics.diagonals, |x, y| map.is_passable(x, y)) {
+                        Ok(route) => {
+                            chat.add_message(ChatMessage::system(
+                                &format!("Autopilot: {} tiles to ({}, {})", route.remaining().count(), x, y)
+                            ));
+                            player.stop();
+                            autopilot = Some(route);
+                        }
+                        Err(e) => chat.add_message(ChatMessage::error(&e)),
+                    }
+                }
+                ChatCommand::CancelNav => {
+                    let had_waypoint = waypoint.take().is_some();
+                    if autopilot.take().is_some() || had_waypoint {
+                        chat.add_message(ChatMessage::system("Autopilot off"));
+                    } else {
+                        chat.add_message(ChatMessage::error("Autopilot isn't on"));
+                    }
+                }
+                ChatCommand::Mark(name) => {
+                    match config.bookmarks.entry(map.world_key()).or_default().mark(&name, (player.x, player.y)) {
+                        Ok(replaced) => {
+                            let _ = config.save();
+                            let verb = if replaced { "moved to" } else { "at" };
+                            chat.add_message(ChatMessage::system(&format!("Bookmark {} {} ({}, {})", name, verb, player.x, player.y)));
+                        }
+                        Err(e) => chat.add_message(ChatMessage::error(&e)),
+                    }
+                }
+                ChatCommand::ListWaypoints => {
+                    let lines = config.bookmarks.get(&map.world_key()).map(|marks| marks.listing((player.x, player.y))).unwrap_or_default();
+                    if lines.is_empty() {
+                        chat.add_message(ChatMessage::system("No bookmarks in this world yet - /mark NAME saves one"));
+                    } else {
+                        chat.add_message(ChatMessage::system(&format!("Bookmarks ({}):", lines.len())));
+                        for line in lines {
+                            chat.add_message(ChatMessage::system(&format!("  {}", line)));
+                        }
+                    }
+                }
+                ChatCommand::GotoMark(name) => match config.bookmarks.get(&map.world_key()).and_then(|marks| marks.get(&name)) {
+                    Some(mark) => {
+                        chat.add_message(ChatMessage::system(&format!("Waypoint {} at ({}, {})", mark.name, mark.x, mark.y)));
+                        pending_commands.push_back(ChatCommand::Navigate(mark.x, mark.y));
+                        waypoint = Some(mark);
+                    }
+                    None => chat.add_message(ChatMessage::error(&format!("No bookmark called {} - /waypoints lists them", name))),
+                },
+                ChatCommand::Missions => match map.fetch_missions() {
+                    Ok(board) => {
+                        let count = board.missions.len();
+                        mission_log.post(board, now);
+                        mission_log.visible = true;
+                        chat.add_message(ChatMessage::system(&format!("Mission board: {} on offer - /accept N takes one", count)));
+                    }
+                    Err(e) => chat.add_message(ChatMessage::error(&e.to_string())),
+                },
+                ChatCommand::AcceptMission(_) if config.auth_token().is_none() => {
+                    chat.add_message(ChatMessage::error("Missions need an account: add one to the config and log in"));
+                }
+                ChatCommand::AcceptMission(n) => match mission_log.accept(n, |id| map.accept_mission(id)) {
+                    Ok(line) => chat.add_message(ChatMessage::system(&line)),
+                    Err(e) => chat.add_message(ChatMessage::error(&e)),
+                },
+                ChatCommand::AbandonMission(n) => match mission_log.abandon(n) {
+                    Ok(line) => chat.add_message(ChatMessage::system(&line)),
+                    Err(e) => chat.add_message(ChatMessage::error(&e)),
+                },
+                ChatCommand::Wallet if config.auth_token().is_none() || map.chunks.is_none() => {
+                    let line = format!("Wallet: {} cr, carried by this ship; log in on the streamed world to bank them", miner.inventory.credits);
+                    chat.add_message(ChatMessage::system(&line));
+                }
+                ChatCommand::Wallet => match map.fetch_balance().and_then(|balance| Ok((balance, map.fetch_transactions()?))) {
+                    Ok((balance, history)) => {
+                        miner.inventory.credits = balance.credits;
+                        chat.add_message(ChatMessage::system(&format!("Wallet: {} cr banked to {}", balance.credits, balance.name)));
+                        if history.transactions.is_empty() {
+                            chat.add_message(ChatMessage::system("  No transactions yet"));
+                        }
+                        for transaction in history.transactions.iter().take(WALLET_HISTORY) {
+                            let line = format!("  {:+6} cr  {:<7} {}", transaction.amount, transaction.kind.name(), transaction.memo);
+                            chat.add_message(ChatMessage::system(&line));
+                        }
+                    }
+                    Err(e) => chat.add_message(ChatMessage::error(&e.to_string())),
+                },
+                ChatCommand::Leaderboard(..) if offline => {
+                    chat.add_message(ChatMessage::error("Offline: leaderboards are kept by the server"));
+                }
+                ChatCommand::Leaderboard(metric, page) => match fetch_leaderboard(config.server_url(), metric, page) {
+                    Ok(board) => {
+                        let pilot = config.account.as_ref().filter(|account| account.token.is_some()).map(|account| account.name.clone());
+                        screens.close(ScreenKind::Leaderboard);
+                        screens.push(Box::new(LeaderboardScreen::new(board, pilot)));
+                    }
+                    Err(e) => chat.add_message(ChatMessage::error(&e)),
+                },
+                ChatCommand::Jump => {
+                    let streamed = if map.chunks.is_some() { systems.as_slice() } else { &[] };
+                    match gates::jump_target(streamed, map.seed, (player.x, player.y)) {
+                        Ok((system, arrival)) => {
+                            player.stop();
+                            autopilot = None;
+                            screens.push(Box::new(WarpScreen::new(now, system, arrival)));
+                            chat.add_message(ChatMessage::system(&format!("Jumping to the {} system", system.name)));
+                        }
+                        Err(e) => chat.add_message(ChatMessage::error(&e)),
+                    }
+                }
+                ChatCommand::Arrive(seed, x, y) => {
+                    let _ = map.save_explored();
+                    map = Map::streaming(ChunkCache::for_config(&config, seed), seed);
+                    map.explored = ExploredTiles::load(&map.world_key());
+                    (player.x, player.y) = (x, y);
+                    player.stop();
+                    waypoint = None;
+                    camera.follow();
+                }
+                ChatCommand::NewGame => {
+                    // Already solo unless --server connected at startup
+                    if map.chunks.is_some() {
+                        let _ = map.save_explored();
+                        connection.hang_up();
+                        remote = RemotePlayers::default();
+                        last_sent_position = None;
+                        let (width, height) = cli.local_size();
+                        map = Map::generate_local(width, height, cli.seed());
+                        map.explored = ExploredTiles::load(&map.world_key());
+                        (player.x, player.y) = map.find_start_position();
+                        player.stop();
+                        autopilot = None;
+                        waypoint = None;
+                        camera.follow();
+                        mission_log.clear();
+                        systems.clear();
+                        miner.difficulty = config.difficulty_for(&map.world_key());
+                        chat.add_message(ChatMessage::system("Left the server, generating local map"));
+                    }
+                }
+                ChatCommand::Continue => match session::load() {
+                    Ok((session, _)) if offline && session.server.is_some() => {
+                        chat.add_message(ChatMessage::error("The last game was online; it can't be continued with --offline"));
+                    }
+                    Ok((session, saved)) => match session.server.clone() {
+                        // Online, the server's world is loaded again on the session's seed before the ship is put back
+                        Some(url) => {
+                            let seed = session.seed;
+                            pending_commands.push_front(ChatCommand::ResumeSession(session));
+                            pending_commands.push_front(ChatCommand::Connect(url, Some(seed)));
+                        }
+                        None => {
+                            let _ = map.save_explored();
+                            if map.chunks.is_some() {
+                                connection.hang_up();
+                                remote = RemotePlayers::default();
+                                last_sent_position = None;
+                                systems.clear();
+                            }
+                            map = match saved {
+                                Some(saved) => Map::from_saved(saved),
+                                None => {
+                                    let (width, height) = cli.local_size();
+                                    let mut map = Map::generate_local(width, height, session.seed);
+                                    map.explored = ExploredTiles::load(&map.world_key());
+                                    map
+                                }
+                            };
+                            mission_log.clear();
+                            miner.difficulty = config.difficulty_for(&map.world_key());
+                            pending_commands.push_front(ChatCommand::ResumeSession(session));
+                        }
+                    },
+                    Err(e) => chat.add_message(ChatMessage::error(&e)),
+                },
+                ChatCommand::ResumeSession(session) => {
+                    // A failed connect leaves us flying solo instead
+                    if map.seed != session.seed || map.chunks.is_some() != session.server.is_some() {
+                        chat.add_message(ChatMessage::error("Couldn't continue the last game"));
+                        continue;
+                    }
+                    map.load_around(session.x, session.y, 0, 0);
+                    if map.is_passable(session.x, session.y) {
+                        (player.x, player.y) = (session.x, session.y);
+                    }
+                    player.direction = session.direction;
+                    player.stop();
+                    autopilot = None;
+                    waypoint = None;
+                    camera.follow();
+                    let space = miner.capacity;
+                    miner.inventory.ore = session.ore.min(space);
+                    miner.inventory.crystal = session.crystal.min(space - miner.inventory.ore);
+                    // Online, an account's credits are the server's
+                    if map.chunks.is_none() || config.auth_token().is_none() {
+                        miner.inventory.credits = session.credits;
+                    }
+                    chat.add_message(ChatMessage::system(&format!("Continuing where you left off at ({}, {})", player.x, player.y)));
+                }
+                ChatCommand::Connect(url, seed) => {
+                    if logged_in_at.as_deref() != Some(url.as_str()) && let Some(account) = config.account.as_mut() {
+                        match account::authenticate_quietly(&url, account, false) {
+                            Ok(name) => {
+                                logged_in_at = Some(url.clone());
+                                chat.add_message(ChatMessage::system(&format!("Logged in as {}", name)));
+                            }
+                            Err(e) => chat.add_message(ChatMessage::error(&format!("Login failed, playing as a guest: {}", e))),
+                        }
+                    }
+                    let previous = config.server_override.replace(url.clone());
+                    match load_map(nc.as_deref_mut(), &config, seed.unwrap_or(cli.seed()), renderer.charset)? {
+                        Some(Ok(loaded)) => {
+                            let _ = map.save_explored();
+                            map = loaded;
+                            map.explored = ExploredTiles::load(&map.world_key());
+                            (player.x, player.y) = map.find_start_position();
+                            player.stop();
+                            autopilot = None;
+                            waypoint = None;
+                            camera.follow();
+                            mission_log.clear();
+                            // The server sets the rules and banks the credits
+                            miner.difficulty = Difficulty::default();
+                            if config.auth_token().is_some() && let Ok(balance) = map.fetch_balance() {
+                                miner.inventory.credits = balance.credits;
+                            }
+                            // Remembered as the server to play on from now on
+                            config.server_url = Some(url.clone());
+                            config.server_override = None;
+                            if let Err(e) = config.save() {
+                                chat.add_message(ChatMessage::error(&e));
+                            }
+                            offline = false;
+                            tracing::info!(%url, seed = map.seed, "Connected");
+                            chat.add_message(ChatMessage::system(&format!("Connected to {}, streaming world", url)));
+                            remote = RemotePlayers::default();
+                            last_sent_position = None;
+                            systems = fetch_systems(&url).unwrap_or_default();
+                            let (dialed, result) = Connection::start(&config, Instant::now());
+                            connection = dialed;
+                            if let Err(e) = result {
+                                chat.add_message(ChatMessage::error(&format!("Multiplayer unavailable, retrying: {}", e)));
+                            }
+                        }
+                        Some(Err(e)) => {
+                            config.server_override = previous;
+                            tracing::warn!(%url, "Server unavailable: {}", e);
+                            chat.add_message(ChatMessage::error(&format!("Server unavailable ({}), flying solo", e)));
+                        }
+                        None => {
+                            config.server_override = previous;
+                            chat.add_message(ChatMessage::system("Connecting cancelled, flying solo"));
+                        }
+                    }
+                }
+                ChatCommand::Say(text) => {
+                    if let Some(client) = &connection.client {
+                        client.send(ClientMessage::Chat { text });
+                    }
+                }
+                ChatCommand::SayNearby(text, emote) => {
+                    let line = if emote { format!("* {} {}", config.player_name(), text) } else { format!("You (nearby): {}", text) };
+                    chat.add_message(ChatMessage::new(ChatChannel::Input, line, 0xAAAAAA));
+                    bubbles.say(Speaker::Me, &text, emote, now);
+                    if let Some(client) = &connection.client {
+                        client.send(ClientMessage::Say { text, emote });
+                    }
+                }
+            }
+        }
+
+        if quit {
+            break;
+        }
+
+        // Everything near the ship counts as explored
+        let sensors = Sensors::at(map.get(player.x, player.y), player.x, player.y);
+        map.explored.reveal_around(player.x, player.y, sensors.radius());
+        if now.duration_since(last_explored_save) >= EXPLORED_SAVE_INTERVAL {
+            let _ = map.save_explored();
+            last_explored_save = now;
+        }
+
+        // Guests' edits stay local; the server only takes them from pilots
+        if config.auth_token().is_some() && let Some(notice) = map.sync_edits(now) {
+            chat.add_message(notice);
+        }
+
+        // Thrust only when not in chat mode; the ship keeps drifting either way
+        if !chat.active {
+            input_state.timeout_stale_keys(now);
+        }
+        // Movement runs on a fixed step; a wrecked ship stays put until respawned, and a docked one until it undocks
+        for _ in 0..move_steps.advance(now) {
+            if screens.holds_ship() {
+                break;
+            }
+            let mut thrust = if !chat.active && input_state.any_movement() { input_state.movement_delta() } else { (0, 0) };
+            // A free camera pans with the movement keys while the ship drifts on
+            if camera.is_free() {
+                camera.pan(thrust, renderer.zoom as i32);
+                thrust = (0, 0);
+            }
+            if let Some(route) = autopilot.as_mut() {
+                match route.tick((player.x, player.y), |x, y| map.is_passable(x, y)) {
+                    NavStep::Cruise => {}
+                    NavStep::Move { x, y, direction } => {
+                        player.x = x;
+                        player.y = y;
+                        player.direction = direction;
+                    }
+                    NavStep::Arrived => {
+                        let (x, y) = route.destination;
+                        chat.add_message(ChatMessage::system(&format!("Autopilot: arrived at ({}, {})", x, y)));
+                        autopilot = None;
+                    }
+                    NavStep::Blocked => {
+                        chat.add_message(ChatMessage::error("Autopilot: route blocked - /nav again to replan"));
+                        autopilot = None;
+                    }
+                }
+            } else {
+                player.update(thrust, &config.physics, &map);
+            }
+        }
+
+        // The rest of the ship runs on its own fixed tick, so the class only changes how fast it flies
+        for _ in 0..ship_ticks.advance(now) {
+            if screens.holds_ship() {
+                break;
+            }
+            map.events.expire(now);
+            let in_storm = (map.get(player.x, player.y) == Some(Tile::Nebula) && hull::is_storm(player.x, player.y))
+                || map.events.damages(player.x, player.y);
+            ship.tick(player.impact.take(), in_storm);
+            if ship.destroyed() {
+                player.stop();
+                autopilot = None;
+                chat.add_message(ChatMessage::error("Hull breached - your ship is destroyed"));
+                screens.push(Box::new(GameOverScreen));
+            }
+
+            match miner.tick((player.x, player.y)) {
+                Some(MineEvent::Finished { x, y, resource, amount }) => {
+                    map.edit(x, y, resource.spent_tile());
+                    mission_log.mined(resource, amount);
+                    chat.add_message(ChatMessage::system(&format!("Mined {} {} ({})", amount, resource.name(), miner.inventory.summary())));
+                    if let Some(client) = &connection.client {
+                        let (ore, crystal) = match resource {
+                            Resource::Ore => (amount, 0),
+                            Resource::Crystal => (0, amount),
+                        };
+                        client.send(ClientMessage::Mined { ore, crystal });
+                    }
+                }
+                Some(MineEvent::Cleared { x, y }) => {
+                    map.edit(x, y, Tile::Floor);
+                    chat.add_message(ChatMessage::system("Asteroid cut away"));
+                }
+                Some(MineEvent::Interrupted) => chat.add_message(ChatMessage::error("Mining interrupted - hold still while mining")),
+                None => {}
+            }
+
+            // Missions follow the ship; finished ones are handed in as soon as they're done
+            mission_log.flew_through(player.x, player.y, map.get(player.x, player.y));
+            if let Some(id) = mission_log.due(now, (player.x, player.y), &miner.inventory) {
+                match map.complete_mission(&id) {
+                    Ok(reward) => {
+                        let line = mission_log.paid(&reward, &mut miner.inventory, miner.capacity);
+                        chat.add_message(ChatMessage::system(&line));
+                    }
+                    Err(e) => chat.add_message(ChatMessage::error(&mission_log.refused(&id, now, &e))),
+                }
+            }
+        }
+
+        // Shots fly on their own step; in multiplayer the server says what they broke, so ours are just for show
+        let ships = remote.ships();
+        let ship_at = |p: Position| ships.positions.iter().find(|&(_, &at)| at == p).map(|(entity, _)| entity.0);
+        for impact in guns.tick(now, |p| !map.is_passable(p.x, p.y), ship_at) {
+            if let Impact::Tile(Position { x, y }) = impact
+                && connection.client.is_none()
+                && map.get(x, y) == Some(Tile::Asteroid)
+            {
+                map.edit(x, y, Tile::Floor);
+            }
+        }
+
+        // Sync with other players
+        let reconnected = connection.reconnects() > 0;
+        let mut kicked = None;
+        if let Some(client) = connection.client.as_mut() {
+            for message in client.poll() {
+                net_diagnostics.observe(&message, &remote);
+                match remote.apply(message) {
+                    Some(NetEvent::Notice(notice)) => chat.add_message(ChatMessage::system(&notice)),
+                    Some(NetEvent::Chat { id, name, text }) => {
+                        chat.add_message(ChatMessage::from_player(id, &name, &text));
+                    }
+                    Some(NetEvent::Say { id, name, text, emote }) => {
+                        chat.add_message(ChatMessage::nearby(id, &name, &text, emote));
+                        bubbles.say(Speaker::Pilot(id), &text, emote, now);
+                    }
+                    Some(NetEvent::Rejected(reason)) => chat.add_message(ChatMessage::error(&reason)),
+                    Some(NetEvent::Utility { kind, text }) => chat.add_message(ChatMessage::utility(kind, &text)),
+                    Some(NetEvent::Ambient { source, text }) => chat.add_message(ChatMessage::ambient(&source, &text)),
+                    // Saved positions are in the streamed world; a local fallback map is a different place.
+                    // After a reconnect the ship is already where the server last saw it, or further on
+                    Some(NetEvent::Resume { x, y }) if map.chunks.is_some() && !reconnected => {
+                        chat.add_message(ChatMessage::system("Resuming where you left off"));
+                        pending_commands.push_back(ChatCommand::Teleport(x, y));
+                    }
+                    Some(NetEvent::Respawned { station, x, y }) if map.chunks.is_some() => {
+                        chat.add_message(ChatMessage::system(&format!("Respawning at {}", station)));
+                        pending_commands.push_back(ChatCommand::Teleport(x, y));
+                    }
+                    // Rubber-banding: the server didn't believe a move, so fly on from where it has us
+                    Some(NetEvent::Corrected { x, y }) => {
+                        player.x = x;
+                        player.y = y;
+                        player.stop();
+                        autopilot = None;
+                        last_sent_position = Some((x, y, player.direction));
+                        tracing::info!(x, y, "Move corrected by the server");
+                        chat.add_message(ChatMessage::error(&format!("The server moved your ship back to ({}, {})", x, y)));
+                    }
+                    Some(NetEvent::Stats { name, lifetime, session }) => {
+                        screens.close(ScreenKind::Stats);
+                        screens.push(Box::new(StatsScreen::new(name, lifetime, session)));
+                    }
+                    Some(NetEvent::Tiles(changes)) if map.chunks.is_some() => {
+                        for change in changes {
+                            map.set(change.x, change.y, change.tile);
+                        }
+                    }
+                    // Tile edits in the missed deltas come from the server's diff
+                    Some(NetEvent::Resync) => {
+                        client.send(ClientMessage::Resync);
+                        map.catch_up_chunks();
+                    }
+                    Some(NetEvent::Shot { id, owner, x, y, direction }) => guns.mirror(id, owner, (x, y), direction),
+                    Some(NetEvent::ShotEnded(id)) => guns.end(id),
+                    // Events happen in the home system, which is listed first
+                    Some(NetEvent::EventStarted(event)) if systems.first().is_none_or(|home| home.seed == map.seed) => {
+                        map.events.start(event, now);
+                    }
+                    Some(NetEvent::EventEnded(id)) => map.events.end(id),
+                    Some(NetEvent::Kicked(reason)) => kicked = Some(reason),
+                    Some(NetEvent::Resume { .. } | NetEvent::Respawned { .. } | NetEvent::Tiles(_) | NetEvent::EventStarted(_)) | None => {}
+                }
+            }
+
+            // Only send when something changed; the server passes moves on in its next delta
+            let position = (player.x, player.y, player.direction);
+            if last_sent_position != Some(position) {
+                client.send(ClientMessage::Position { x: player.x, y: player.y, direction: player.direction, seed: Some(map.seed) });
+                last_sent_position = Some(position);
+            }
+
+            net_diagnostics.sample(client.counters.totals(), now);
+        }
+        // Kicked pilots stay off rather than redialling straight back in
+        if let Some(reason) = kicked {
+            tracing::warn!("Kicked: {}", reason);
+            chat.add_message(ChatMessage::error(&format!("Disconnected by the server: {}", reason)));
+            connection.hang_up();
+            remote.clear();
+            guns.clear();
+        }
+
+        // Retry a dropped connection; the server resends players and NPCs on joining, and chunks catch up on what changed
+        match connection.update(now) {
+            Some(LinkEvent::Lost(reason)) => {
+                tracing::warn!("{}", reason);
+                chat.add_message(ChatMessage::error(&format!("{}, reconnecting", reason)));
+                remote.clear();
+                guns.clear();
+            }
+            Some(LinkEvent::Restored) => {
+                tracing::info!(reconnects = connection.reconnects(), "Reconnected");
+                chat.add_message(ChatMessage::system("Reconnected to multiplayer server"));
+                last_sent_position = None;
+                map.catch_up_chunks();
+            }
+            None => {}
+        }
+
+        // Animation advances at its own fixed step, however long frames take
+        for _ in 0..animation.advance(now) {
+            renderer.tick();
+        }
+        // The day/night cycle, unless the config holds the light still
+        renderer.brightness = config.light_level().unwrap_or_else(|| light::brightness(light::cycle_time(remote.server_time(now))));
+
+        // Chat area takes up bottom lines: messages + input line + status bar
+        chat.fit_pane(term_height);
+        let layout = Layout::new(term_height, term_width, chat.height());
+        let game_height = layout.game_height;
+        let center_screen_x = term_width / 2;
+        let center_screen_y = game_height / 2;
+
+        // Stream in whatever the view (and PiP) can see, and what the ship flies into while the camera is elsewhere
+        let zoom = renderer.zoom as i32;
+        camera.update((player.x, player.y), player.direction.delta(), zoom, &config.camera_feel(), now);
+        let view_center = camera.center((player.x, player.y));
+        map.load_around(view_center.0, view_center.1, (center_screen_x as i32 + 1) * zoom, (center_screen_y as i32 + 1) * zoom);
+        if camera.is_free() {
+            map.load_around(player.x, player.y, 2, 2);
+        }
+        if pip.is_active()
+            && let Some((target_x, target_y)) = pip.target
+        {
+            map.load_around(target_x, target_y, pip.width as i32 / 2, pip.height as i32 / 2);
+        }
+        map.evict_distant_chunks(view_center.0, view_center.1);
+
+        if let Some(mark) = &waypoint
+            && mark.distance((player.x, player.y)) <= ARRIVAL_DISTANCE
+        {
+            chat.add_message(ChatMessage::system(&format!("Reached waypoint {}", mark.name)));
+            waypoint = None;
+        }
+
+        // Headless there's nothing to draw: the chat goes to stdout, where the script can look for it
+        let (Some(nc), Some(stdplane)) = (nc.as_deref_mut(), stdplane.as_deref_mut()) else {
+            if let Some(script) = script.as_mut() {
+                write_transcript(&mut chat, script);
+                if script.finished() {
+                    break;
+                }
+            }
+            clock.sleep(pacer.wait(clock.now()));
+            continue;
+        };
+
+        // Render
+        let render_start = clock.now();
+        let mut tiles_drawn = 0;
+        stdplane.erase();
+        // A screenshot copies this frame into a canvas as it's drawn, so every widget is drawn again
+        let mut shot = std::mem::take(&mut screenshot_due).then(|| Canvas::new(term_width, term_height));
+        if shot.is_some() {
+            hud.redraw_all();
+        }
+
+        // Markers are keyed by the cell they fall in: the tile itself at 1x, its zoom block further out
+        let cell_of = |(x, y): (i32, i32)| (x.div_euclid(zoom), y.div_euclid(zoom));
+        // The ships our sensors pick up (jammed, only those close by), for the view, its labels, the PiP and the radar
+        let seen_ships: Vec<(Entity, Position, Sprite)> = remote.ships().drawn().filter(|(_, p, _)| sensors.sees(p.x, p.y)).collect();
+        let seen_pilots: Vec<&PlayerInfo> = remote.iter().filter(|p| sensors.sees(p.x, p.y)).collect();
+        // Pilots are drawn over NPCs when they overlap, and shots over both; trails only show where there's no ship
+        let mut remote_cells = renderer.entity_cells(seen_ships.iter().copied());
+        remote_cells.extend(renderer.remote_ship_cells(seen_pilots.iter().copied()));
+        for (position, back) in guns.trails() {
+            let (ch, fg) = TRAIL_STYLES[back];
+            remote_cells.entry(cell_of((position.x, position.y))).or_insert_with(|| ShipCell::new(ch, fg));
+        }
+        remote_cells.extend(renderer.entity_cells(guns.shots.drawn()));
+        let breadcrumbs: HashSet<(i32, i32)> =
+            autopilot.iter().flat_map(|route| route.remaining().map(|&tile| cell_of(tile))).collect();
+        let stations: HashMap<(i32, i32), (char, u32)> =
+            station_marks(&remote).into_iter().map(|(tile, style)| (cell_of(tile), style)).collect();
+        let waypoint_cell = waypoint.as_ref().map(|mark| cell_of((mark.x, mark.y)));
+        let poi_cells: HashMap<(i32, i32), (char, u32)> =
+            map.pois().iter().map(|poi| (cell_of((poi.x, poi.y)), poi::style(poi.kind))).collect();
+
+        // Render game area a row at a time; each cell is a zoom x zoom block of tiles on a world-aligned grid
+        let mut view = Tee::new(stdplane, shot.as_mut(), (0, 0));
+        let (view_cell_x, view_cell_y) = cell_of(view_center);
+        let view_left = view_cell_x - center_screen_x as i32;
+        // Where our ship is drawn, from the middle of the view; (0, 0) unless spectating
+        let (player_cell_x, player_cell_y) = cell_of((player.x, player.y));
+        let (ship_x, ship_y) = (player_cell_x - view_cell_x, player_cell_y - view_cell_y);
+        for screen_y in 0..game_height {
+            let cell_y = view_cell_y + (screen_y as i32 - center_screen_y as i32);
+            let map_y = cell_y * zoom;
+            let mut row_tiles = map.row_span(map_y, view_left..view_left + term_width as i32);
+
+            for screen_x in 0..term_width {
+                let cell_x = view_left + screen_x as i32;
+                let map_x = cell_x * zoom;
+                // At 1x read the row a run at a time; zoomed out, sample each block
+                let (explored, tile) = if zoom == 1 {
+                    (map.explored.is_explored(map_x, map_y), row_tiles.next().flatten())
+                } else {
+                    map.block_summary(map_x, map_y, zoom)
+                };
+
+                // Calculate offset from our ship's center for ship rendering
+                let offset_x = screen_x as i32 - center_screen_x as i32 - ship_x;
+                let offset_y = screen_y as i32 - center_screen_y as i32 - ship_y;
+
+                // Check if this position is part of the ship or exhaust; zoomed out the ship is one glyph
+                let own_ship = if zoom == 1 {
+                    renderer.get_ship_cell(player.direction, offset_x, offset_y)
+                } else {
+                    ((offset_x, offset_y) == (0, 0)).then(|| ShipCell::new(player.direction.to_char(), renderer.theme.ship[0].0))
+                };
+                if let Some(ship_cell) = own_ship {
+                    if let Some(bg_color) = ship_cell.bg {
+                        view.set_bg_rgb(bg_color);
+                    } else {
+                        view.set_bg_default();
+                    }
+                    view.set_fg_rgb(ship_cell.fg);
+                    let s: String = renderer.charset.glyph(ship_cell.ch).into();
+                    view.putstr_yx(Some(screen_y), Some(screen_x), &s)?;
+                    view.set_bg_default();
+                } else if let Some(ship_cell) = remote_cells.get(&(cell_x, cell_y)) {
+                    // Another player's ship, or an NPC
+                    view.set_fg_rgb(ship_cell.fg);
+                    view.set_bg_default();
+                    let s: String = renderer.charset.glyph(ship_cell.ch).into();
+                    view.putstr_yx(Some(screen_y), Some(screen_x), &s)?;
+                } else {
+                    // Render map tile, or static where we haven't been; stations, other points of interest
+                    // and the autopilot's route show on top
+                    let (ch, fg) = if waypoint_cell == Some((cell_x, cell_y)) {
+                        WAYPOINT_STYLE
+                    } else if let Some(&style) = stations.get(&(cell_x, cell_y)) {
+                        style
+                    } else if let Some(&style) = poi_cells.get(&(cell_x, cell_y)) {
+                        style
+                    } else if breadcrumbs.contains(&(cell_x, cell_y)) {
+                        NAV_BREADCRUMB_STYLE
+                    } else if explored {
+                        tiles_drawn += 1;
+                        let frame = renderer.effects_enabled.then_some(renderer.frame);
+                        map.events
+                            .at(cell_x, cell_y)
+                            .and_then(|event| events::style(event, cell_x, cell_y, tile, frame))
+                            .unwrap_or_else(|| renderer.render_tile(tile, map.biome_at(cell_x, cell_y), cell_x, cell_y))
+                    } else {
+                        renderer.render_fog(cell_x, cell_y)
+                    };
+
+                    view.set_fg_rgb(fg);
+                    view.set_bg_default();
+                    let s: String = renderer.charset.glyph(ch).into();
+                    view.putstr_yx(Some(screen_y), Some(screen_x), &s)?;
+                }
+            }
+        }
+
+        // Label other ships with their names, centered above the sprite, and nearby points of interest
+        view.set_bg_default();
+        // Zoomed out, ships are a single glyph so the label sits right above it
+        let ship_rise = if zoom == 1 { 2 } else { 1 };
+        let ships = remote.ships();
+        let npc_labels = seen_ships.iter().filter_map(|&(entity, position, sprite)| {
+            Some((ships.names.get(entity)?.clone(), position.x, position.y, ShipPalette::for_sprite(sprite.kind)?.cockpit, ship_rise))
+        });
+        let pilot_labels = seen_pilots.iter().map(|other| (other.label(), other.x, other.y, ShipPalette::pilot(other).cockpit, ship_rise));
+        let poi_labels = map
+            .pois()
+            .iter()
+            .filter(|poi| (poi.x - view_center.0).abs().max((poi.y - view_center.1).abs()) <= poi::LABEL_RANGE)
+            .map(|poi| (poi.name.clone(), poi.x, poi.y, poi::style(poi.kind).1, 1));
+        for (name, x, y, color, label_rise) in poi_labels.chain(npc_labels).chain(pilot_labels) {
+            let (cell_x, cell_y) = cell_of((x, y));
+            let label_y = cell_y - view_cell_y + center_screen_y as i32 - label_rise;
+            if label_y < 0 || label_y >= game_height as i32 {
+                continue;
+            }
+            let label_x = cell_x - view_cell_x + center_screen_x as i32 - name.chars().count() as i32 / 2;
+            let start_x = label_x.max(0);
+            view.set_fg_rgb(color);
+            let visible: String = name
+                .chars()
+                .skip((start_x - label_x) as usize)
+                .take((term_width as i32 - start_x).max(0) as usize)
+                .collect();
+            if !visible.is_empty() {
+                view.putstr_yx(Some(label_y as u32), Some(start_x as u32), &visible)?;
+            }
+        }
+
+        // Speech bubbles over the ships that said something, clear of every ship and of each other
+        bubbles.expire(now);
+        let mut taken: Vec<bubbles::Rect> = Vec::new();
+        for bubble in bubbles.iter() {
+            let speaker = match bubble.speaker {
+                Speaker::Me => Some((player.x, player.y)),
+                Speaker::Pilot(id) => seen_pilots.iter().find(|other| other.id == id).map(|other| (other.x, other.y)),
+            };
+            let Some((cell_x, cell_y)) = speaker.map(cell_of) else {
+                continue;
+            };
+            let ship = (cell_x - view_cell_x + center_screen_x as i32, cell_y - view_cell_y + center_screen_y as i32);
+            let covers_ship = |col: i32, row: i32| {
+                let (dx, dy) = (col - center_screen_x as i32, row - center_screen_y as i32);
+                (dx - ship_x).abs().max((dy - ship_y).abs()) < ship_rise || remote_cells.contains_key(&(view_left + col, view_cell_y + dy))
+            };
+            let screen = (term_width as i32, game_height as i32);
+            let Some(spot) = bubbles::place(ship, bubble.size(), ship_rise, screen, covers_ship, &taken) else {
+                continue;
+            };
+            taken.push(spot);
+            let (fg, bg) = if bubble.emote { EMOTE_STYLE } else { SAY_STYLE };
+            let brightness = bubble.brightness(now);
+            view.set_fg_rgb(light::dim(fg, brightness));
+            view.set_bg_rgb(light::dim(bg, brightness));
+            for (row, line) in bubble.lines.iter().enumerate() {
+                let text = format!(" {:<width$} ", line, width = spot.2 as usize - 2);
+                view.putstr_yx(Some((spot.1 + row as i32) as u32), Some(spot.0 as u32), &text)?;
+            }
+            view.set_bg_default();
+        }
+
+        // An arrow on the edge of the view toward an off-screen waypoint, with the tiles to go
+        if let Some(mark) = &waypoint
+            && let Some((cell_x, cell_y)) = waypoint_cell
+        {
+            let center = (center_screen_x as i32, center_screen_y as i32);
+            let target = (cell_x - view_cell_x + center.0, cell_y - view_cell_y + center.1);
+            if let Some(((x, y), direction)) = waypoints::edge_arrow(center, target, (term_width as i32, game_height as i32)) {
+                let label = format!("{} {}", mark.name, mark.distance((player.x, player.y)));
+                let width = label.chars().count() as i32;
+                let label_x = if x > center.0 { x - width - 1 } else { x + 2 };
+                view.set_fg_rgb(WAYPOINT_STYLE.1);
+                let arrow: String = renderer.charset.glyph(direction.to_char()).into();
+                view.putstr_yx(Some(y as u32), Some(x as u32), &arrow)?;
+                view.putstr_yx(Some(y as u32), Some(label_x.clamp(0, (term_width as i32 - width).max(0)) as u32), &label)?;
+            }
+        }
+
+        // HUD widgets, each on its own plane over the view; hidden under the title and the warp
+        let covered = screens.covers_view();
+        let pip_rect = (pip.is_active() && !covered)
+            .then(|| pip.screen_origin(term_width, game_height))
+            .flatten()
+            .map(|(x, y)| Rect::new(y, x, pip.height, pip.width));
+        if let Some(plane) = hud.place(stdplane, Widget::Pip, pip_rect.unwrap_or_default(), None)? {
+            let plane = &mut Tee::new(plane, shot.as_mut(), pip_rect.unwrap_or_default().origin());
+            let (target_x, target_y) = pip.target.unwrap_or_default();
+            draw_box(plane, pip.height, pip.width, &format!(" ({},{}) ", target_x, target_y), renderer.charset)?;
+            for row in 0..pip.height - 2 {
+                for col in 0..pip.width - 2 {
+                    let Some((map_x, map_y)) = pip.map_coords(col, row) else {
+                        continue;
+                    };
+                    let other = seen_pilots.iter().find(|p| (p.x, p.y) == (map_x, map_y));
+                    let entity = seen_ships.iter().find(|(_, position, _)| (position.x, position.y) == (map_x, map_y));
+                    let (ch, fg) = if (map_x, map_y) == (player.x, player.y) {
+                        (player.direction.to_char(), 0x80FFFF)
+                    } else if let Some(other) = other {
+                        (other.direction.to_char(), ShipPalette::pilot(other).hull)
+                    } else if let Some(&(_, _, sprite)) = entity {
+                        sprite_style(sprite)
+                    } else if (map_x, map_y) == (target_x, target_y) {
+                        ('+', 0xFF4444)
+                    } else if map.explored.is_explored(map_x, map_y) {
+                        renderer.render_tile(map.get(map_x, map_y), map.biome_at(map_x, map_y), map_x, map_y)
+                    } else {
+                        renderer.render_fog(map_x, map_y)
+                    };
+                    plane.set_fg_rgb(fg);
+                    let s: String = renderer.charset.glyph(ch).into();
+                    plane.putstr_yx(Some(row + 1), Some(col + 1), &s)?;
+                }
+            }
+        }
+
+        // Minimap overview in the top-left corner
+        let minimap_rect = Minimap::size(term_width, game_height)
+            .filter(|_| renderer.minimap.visible && !covered)
+            .map(|(minimap_w, minimap_h)| Rect::new(1, 1, minimap_h, minimap_w));
+        if let Some(plane) = hud.place(stdplane, Widget::Minimap, minimap_rect.unwrap_or_default(), None)? {
+            let plane = &mut Tee::new(plane, shot.as_mut(), minimap_rect.unwrap_or_default().origin());
+            let Rect { rows, cols, .. } = minimap_rect.unwrap_or_default();
+            let (inner_w, inner_h) = (cols - 2, rows - 2);
+            let view = MinimapView::new(map.extent(), (player.x, player.y), inner_w, inner_h);
+            let player_cell = view.cell_for(player.x, player.y);
+            let mut station_cells: HashMap<(u32, u32), (char, u32)> = HashMap::new();
+            for (&(x, y), &style) in &stations {
+                if let Some(cell) = view.cell_for(x, y)
+                    && (style == HOME_STATION_STYLE || !station_cells.contains_key(&cell))
+                {
+                    station_cells.insert(cell, style);
+                }
+            }
+            draw_box(plane, rows, cols, " MAP ", renderer.charset)?;
+            for row in 0..inner_h {
+                for col in 0..inner_w {
+                    let (ch, fg) = if player_cell == Some((col, row)) {
+                        (player.direction.to_char(), 0x80FFFF)
+                    } else if let Some(&style) = station_cells.get(&(col, row)) {
+                        style
+                    } else {
+                        Minimap::cell_style(view.summarize(&map, col, row))
+                    };
+                    plane.set_fg_rgb(fg);
+                    let s: String = renderer.charset.glyph(ch).into();
+                    plane.putstr_yx(Some(row + 1), Some(col + 1), &s)?;
+                }
+            }
+        }
+
+        // Radar scope in the top-right corner, under the PiP when that's open
+        let radar_rect = Radar::size(term_width, game_height).filter(|_| radar.visible && !covered).and_then(|(cols, rows)| {
+            let top = pip_rect.map_or(1, |pip| pip.y + pip.rows);
+            (game_height > top + rows).then(|| Rect::new(top, term_width - cols - 1, rows, cols))
+        });
+        if let Some(plane) = hud.place(stdplane, Widget::Radar, radar_rect.unwrap_or_default(), None)? {
+            let plane = &mut Tee::new(plane, shot.as_mut(), radar_rect.unwrap_or_default().origin());
+            let Rect { rows, cols, .. } = radar_rect.unwrap_or_default();
+            draw_box(plane, rows, cols, if sensors.jammed { " RADAR:JAM " } else { " RADAR " }, renderer.charset)?;
+            // Points of interest under stations, under shots and ships, as in the view
+            let pois = map.pois().iter().map(|poi| ((poi.x, poi.y), poi::style(poi.kind)));
+            let shots = guns.shots.drawn().map(|(_, p, sprite)| ((p.x, p.y), (BLIP, sprite_style(sprite).1)));
+            let npcs = seen_ships.iter().map(|&(_, p, sprite)| {
+                let color = ShipPalette::for_sprite(sprite.kind).map_or(sprite_style(sprite).1, |palette| palette.hull);
+                ((p.x, p.y), (BLIP, color))
+            });
+            let pilots = seen_pilots.iter().map(|p| ((p.x, p.y), (BLIP, ShipPalette::pilot(p).hull)));
+            let contacts = pois.chain(station_marks(&remote)).chain(shots).chain(npcs).chain(pilots);
+            let ship = (player.x, player.y, (player.direction.to_char(), 0x80FFFF));
+            let scope = radar.scope(now, renderer.effects_enabled, Radar::range(sensors), ship, contacts);
+            for (row, line) in (1..).zip(scope) {
+                for (col, (ch, fg)) in (1..).zip(line) {
+                    plane.set_fg_rgb(fg);
+                    let s: String = renderer.charset.glyph(ch).into();
+                    plane.putstr_yx(Some(row), Some(col), &s)?;
+                }
+            }
+        }
+
+        // Mission log down the left side, under the minimap when that's open
+        let mission_lines = (mission_log.visible && !covered).then(|| mission_log.lines(now, (player.x, player.y), &miner.inventory));
+        let mission_rect = mission_lines.as_ref().and_then(|lines| {
+            let top = minimap_rect.map_or(1, |minimap| minimap.y + minimap.rows);
+            let inner_w = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0).max(14) as u32;
+            let cols = (inner_w + 2).min(term_width.saturating_sub(2));
+            let rows = lines.len() as u32 + 2;
+            (cols >= 16 && game_height > top + rows).then(|| Rect::new(top, 1, rows, cols))
+        });
+        if let Some(plane) = hud.place(stdplane, Widget::Missions, mission_rect.unwrap_or_default(), Some(content_key(&mission_lines)))? {
+            let plane = &mut Tee::new(plane, shot.as_mut(), mission_rect.unwrap_or_default().origin());
+            let Rect { rows, cols, .. } = mission_rect.unwrap_or_default();
+            draw_box(plane, rows, cols, " MISSIONS ", renderer.charset)?;
+            plane.set_fg_rgb(0xFFD080);
+            let width = cols as usize - 2;
+            for (y, line) in (1..).zip(mission_lines.iter().flatten()) {
+                let line: String = line.chars().take(width).collect();
+                plane.putstr_yx(Some(y), Some(1), &format!("{:<width$}", line))?;
+            }
+        }
+
+        // Network diagnostics in the bottom-right corner of the game area
+        let net_lines = net_diagnostics.visible.then(|| net_diagnostics.lines(connection.client.is_some()));
+        let net_rect = net_lines.as_ref().and_then(|lines| {
+            let inner_w = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0).max(14) as u32;
+            let (cols, rows) = (inner_w + 2, lines.len() as u32 + 2);
+            (term_width >= cols + 2 && game_height >= rows + 2).then(|| Rect::new(game_height - rows - 1, term_width - cols - 1, rows, cols))
+        });
+        if let Some(plane) = hud.place(stdplane, Widget::NetStats, net_rect.unwrap_or_default(), Some(content_key(&net_lines)))? {
+            let plane = &mut Tee::new(plane, shot.as_mut(), net_rect.unwrap_or_default().origin());
+            let Rect { rows, cols, .. } = net_rect.unwrap_or_default();
+            draw_box(plane, rows, cols, " NET ", renderer.charset)?;
+            plane.set_fg_rgb(0x80FF80);
+            for (y, line) in (1..).zip(net_lines.iter().flatten()) {
+                plane.putstr_yx(Some(y), Some(1), &format!("{:<width$}", line, width = cols as usize - 2))?;
+            }
+        }
+
+        // Frame timings in the bottom-left corner, from the last full second
+        perf.sample(now);
+        let perf_lines = perf.visible.then(|| perf.lines(config.target_fps(), connection.latency()));
+        let perf_rect = perf_lines.as_ref().and_then(|lines| {
+            let inner_w = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0).max(14) as u32;
+            let (cols, rows) = (inner_w + 2, lines.len() as u32 + 2);
+            (term_width >= cols + 2 && game_height >= rows + 2).then(|| Rect::new(game_height - rows - 1, 1, rows, cols))
+        });
+        if let Some(plane) = hud.place(stdplane, Widget::Perf, perf_rect.unwrap_or_default(), Some(content_key(&perf_lines)))? {
+            let plane = &mut Tee::new(plane, shot.as_mut(), perf_rect.unwrap_or_default().origin());
+            let Rect { rows, cols, .. } = perf_rect.unwrap_or_default();
+            draw_box(plane, rows, cols, " PERF ", renderer.charset)?;
+            plane.set_fg_rgb(0xFFD080);
+            for (y, line) in (1..).zip(perf_lines.iter().flatten()) {
+                plane.putstr_yx(Some(y), Some(1), &format!("{:<width$}", line, width = cols as usize - 2))?;
+            }
+        }
+
+        // A script's text across the top of the game area
+        let script_lines = (!scripts.hud().is_empty() && !covered).then(|| scripts.hud());
+        let script_rect = script_lines.and_then(|lines| {
+            let inner_w = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0).max(14) as u32;
+            let (cols, rows) = (inner_w + 2, lines.len() as u32 + 2);
+            (term_width >= cols + 2 && game_height >= rows + 2).then(|| Rect::new(1, (term_width - cols) / 2, rows, cols))
+        });
+        if let Some(plane) = hud.place(stdplane, Widget::Script, script_rect.unwrap_or_default(), Some(content_key(&script_lines)))? {
+            let plane = &mut Tee::new(plane, shot.as_mut(), script_rect.unwrap_or_default().origin());
+            let Rect { rows, cols, .. } = script_rect.unwrap_or_default();
+            draw_box(plane, rows, cols, " SCRIPT ", renderer.charset)?;
+            plane.set_fg_rgb(0x80FFFF);
+            for (y, line) in (1..).zip(script_lines.into_iter().flatten()) {
+                plane.putstr_yx(Some(y), Some(1), &format!("{:<width$}", line, width = cols as usize - 2))?;
+            }
+        }
+
+        // The region just entered, under any script's text
+        let region_here = map.regions().at(player.x, player.y);
+        region_banner.update(region_here, now);
+        let banner = region_banner.lines(now).filter(|_| !covered);
+        let banner_rect = banner.as_ref().and_then(|(title, lines)| {
+            let inner_w = lines.iter().chain([title]).map(|line| line.chars().count()).max().unwrap_or(0).max(14) as u32;
+            let (cols, rows) = (inner_w + 2, lines.len() as u32 + 2);
+            let top = script_rect.map_or(1, |rect| rect.y + rect.rows);
+            (term_width >= cols + 2 && game_height > top + rows).then(|| Rect::new(top, (term_width - cols) / 2, rows, cols))
+        });
+        if let Some(plane) = hud.place(stdplane, Widget::Region, banner_rect.unwrap_or_default(), Some(content_key(&banner)))? {
+            let plane = &mut Tee::new(plane, shot.as_mut(), banner_rect.unwrap_or_default().origin());
+            let Rect { rows, cols, .. } = banner_rect.unwrap_or_default();
+            let (title, lines) = banner.unwrap_or_default();
+            draw_box(plane, rows, cols, &title, renderer.charset)?;
+            for (y, line) in (1..).zip(&lines) {
+                plane.set_fg_rgb(if y == 1 { regions::danger_color(region_here.map_or(0, |region| region.danger)) } else { 0xE0E0FF });
+                plane.putstr_yx(Some(y), Some(1), &format!("{:<width$}", line, width = cols as usize - 2))?;
+            }
+        }
+
+        // Screens over the view, bottom first: the top one can ask for the dialog widget's plane
+        let mut ctx = ScreenContext { ship: &mut ship, miner: &mut miner, map: &map, chat: &mut chat, commands: &mut pending_commands, config: &mut config };
+        screens.update(now, &mut ctx);
+        let mut panels = screens.render(&ctx);
+        let dialog = panels.iter().rposition(|panel| panel.own_plane).and_then(|i| {
+            let panel = panels.remove(i);
+            let (rows, cols) = panel_size(panel.title, &panel.lines);
+            Rect::centered(rows, cols, game_height, term_width).map(|rect| (panel, rect))
+        });
+        for panel in panels.iter().filter(|panel| !panel.own_plane) {
+            if let Some(backdrop) = panel.backdrop {
+                draw_backdrop(&mut Tee::new(stdplane, shot.as_mut(), (0, 0)), term_width, game_height, backdrop, renderer.effects_enabled)?;
+            }
+            let plane = &mut Tee::new(stdplane, shot.as_mut(), (0, 0));
+            draw_panel(plane, term_width, game_height, panel.title, &panel.lines, panel.colors, renderer.charset)?;
+        }
+        let dialog_key = dialog.as_ref().map(|(panel, _)| content_key(&(panel.title, &panel.lines, panel.colors)));
+        let dialog_rect = dialog.as_ref().map(|&(_, rect)| rect).unwrap_or_default();
+        if let Some(plane) = hud.place(stdplane, Widget::Dialog, dialog_rect, dialog_key)?
+            && let Some((panel, rect)) = &dialog
+        {
+            let plane = &mut Tee::new(plane, shot.as_mut(), rect.origin());
+            draw_panel(plane, rect.cols, rect.rows, panel.title, &panel.lines, panel.colors, renderer.charset)?;
+        }
+
+        // The chat log fills the view, over any screen's dialog
+        let log_rect = (chat.log.is_open() && game_height >= 5 && term_width as usize >= TIME_WIDTH + 12)
+            .then(|| Rect::new(0, 0, game_height, term_width));
+        if let Some(plane) = hud.place(stdplane, Widget::ChatLog, log_rect.unwrap_or_default(), None)? {
+            let plane = &mut Tee::new(plane, shot.as_mut(), (0, 0));
+            draw_chat_log(plane, game_height, term_width, &mut chat, renderer.charset)?;
+        }
+
+        // Chat messages and the input line
+        let chat_background = renderer.theme.chat_background.0;
+        let messages: Vec<_> = chat.visible_messages().map(|msg| (&msg.text, chat.color_of(msg))).collect();
+        let chat_key = content_key(&(messages, chat.active, &chat.input, chat.display_cursor_pos(), chat.muted_count, chat_background));
+        if let Some(plane) = hud.place(stdplane, Widget::Chat, layout.chat, Some(chat_key))? {
+            let plane = &mut Tee::new(plane, shot.as_mut(), layout.chat.origin());
+            draw_chat_pane(plane, layout.chat.cols, &chat, chat_background)?;
+        }
+
+        // Status bar
+        let current_tile = map.get(player.x, player.y);
+        let event_here = map.events.at(player.x, player.y).map(|event| event.kind).filter(|_| map.is_passable(player.x, player.y));
+        let tile_name = match current_tile {
+            _ if event_here == Some(EventKind::MeteorShower) => "Meteor shower",
+            _ if event_here == Some(EventKind::IonStorm) => "Ion storm",
+            Some(Tile::Floor) => "Space",
+            Some(Tile::Nebula) if hull::is_storm(player.x, player.y) => "Ion storm",
+            Some(Tile::Nebula) => "Nebula",
+            _ => "???",
+        };
+
+        let region_name = region_here.map_or(String::new(), |region| format!("{}: ", region.name));
+
+        let effects_indicator = if renderer.effects_enabled { "FX:ON" } else { "FX:OFF" };
+        let zoom_indicator = if renderer.zoom > 1 { format!(" Z:{}x", renderer.zoom) } else { String::new() };
+        let sensor_indicator = if sensors.jammed { " SNS:JAM" } else { "" };
+        let camera_indicator = if camera.is_free() { format!(" CAM:{},{}", view_center.0, view_center.1) } else { String::new() };
+        let mode_indicator = if chat.active {
+            "[CHAT]"
+        } else if chat.log.is_open() {
+            "[LOG]"
+        } else {
+            screens.indicator()
+        };
+        let link = connection.state();
+        let net_indicator = match (link, connection.retry_in(now)) {
+            (LinkState::Online, _) => format!("{}:{}", link.label(), remote.len() + 1),
+            (LinkState::Reconnecting, Some(wait)) => format!("{} {}s", link.label(), wait.as_secs_f32().ceil()),
+            (LinkState::Reconnecting, None) => format!("{}...", link.label()),
+            (LinkState::Offline, _) => link.label().to_string(),
+        };
+        let cargo = match (&autopilot, miner.progress()) {
+            (Some(route), _) => format!("NAV {} to go", route.remaining().count()),
+            (None, Some((work, progress))) => format!("{} {:>3.0}%", work.label(), progress * 100.0),
+            (None, None) => format!("{} CR:{}", miner.inventory.summary(), miner.inventory.credits),
+        };
+        let status = format!(
+            " ({:>4},{:>4}) {:>2} | {} | {}{}{} | {} | {}{}{} | {} {} ",
+            player.x,
+            player.y,
+            player.direction.name(),
+            ship.summary(),
+            region_name,
+            tile_name,
+            sensor_indicator,
+            cargo,
+            effects_indicator,
+            zoom_indicator,
+            camera_indicator,
+            net_indicator,
+            mode_indicator
+        );
+        let status_colors = (renderer.theme.status[0].0, renderer.theme.status[1].0);
+        if let Some(plane) = hud.place(stdplane, Widget::Status, layout.status, Some(content_key(&(&status, status_colors))))? {
+            let plane = &mut Tee::new(plane, shot.as_mut(), layout.status.origin());
+            plane.set_fg_rgb(status_colors.0);
+            plane.set_bg_rgb(status_colors.1);
+            plane.putstr_yx(Some(0), Some(0), &format!("{:<width$}", status, width = layout.status.cols as usize))?;
+        }
+
+        if let Some(canvas) = &shot {
+            match screenshot::save(canvas, SystemTime::now()) {
+                Ok(path) => chat.add_message(ChatMessage::system(&format!("Screenshot saved to {}.txt (and .ans, in color)", path.display()))),
+                Err(e) => chat.add_message(ChatMessage::error(&e)),
+            }
+        }
+
+        nc.render()?;
+        perf.record(now, clock.now().saturating_duration_since(render_start), tiles_drawn);
+
+        // Sleep until the next frame is due, less whatever this one took
+        let wait = pacer.wait(clock.now());
+        clock.sleep(wait);
+    }
+
+    let _ = map.save_explored();
+    // Quitting from the title menu, or from a headless run, leaves the last game to continue
+    if script.is_none() && !screens.is_open(ScreenKind::Title) {
+        let session = Session {
+            server: map.chunks.is_some().then(|| config.server_url().to_string()),
+            seed: map.seed,
+            x: player.x,
+            y: player.y,
+            direction: player.direction,
+            ore: miner.inventory.ore,
+            crystal: miner.inventory.crystal,
+            credits: miner.inventory.credits,
+        };
+        let _ = session::save(&session, map.chunks.is_none().then(|| map.to_saved((player.x, player.y))).as_ref());
+    }
+    if let Some(script) = script.as_mut() {
+        write_transcript(&mut chat, script);
+    }
+    if let Some(nc) = nc {
+        unsafe { nc.stop()? };
+    }
+    tracing::info!("Client exiting");
+    if let Some(e) = script_error {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use exospace_core::{NpcInfo, fnv1a};
+    use exospace_core::ecs::Entities;
+    use std::collections::BTreeMap;
+
+    // ==================== Map Tests ====================
+
+    #[test]
+    fn test_map_dimensions() {
+        let map = Map::generate_local(100, 50, WORLD_SEED);
+        assert_eq!(map.width, 100);
+        assert_eq!(map.height, 50);
+        assert_eq!(map.tiles.len(), 100 * 50); // flat, row-major
+        assert_eq!(map.rect(0..100, 0..50).count(), 50); // height rows
+        assert!(map.rect(0..100, 0..50).all(|row| row.len() == 100)); // width columns
+    }
+
+    #[test]
+    fn test_map_has_walls_and_floors() {
+        let map = Map::generate_local(100, 50, WORLD_SEED);
+
+        let has_walls = map.tiles.contains(&Tile::Wall);
+        let has_floors = map.tiles.contains(&Tile::Floor);
+
+        assert!(has_walls, "Map should contain walls");
+        assert!(has_floors, "Map should contain floors");
+    }
+
+    #[test]
+    fn test_map_border_is_walls() {
+        let map = Map::generate_local(100, 50, WORLD_SEED);
+
+        // Check top and bottom borders
+        for x in 0..100 {
+            assert_eq!(map.get(x, 0), Some(Tile::Wall), "Top border should be wall at x={}", x);
+            assert_eq!(map.get(x, 49), Some(Tile::Wall), "Bottom border should be wall at x={}", x);
+        }
+
+        // Check left and right borders
+        for y in 0..50 {
+            assert_eq!(map.get(0, y), Some(Tile::Wall), "Left border should be wall at y={}", y);
+            assert_eq!(map.get(99, y), Some(Tile::Wall), "Right border should be wall at y={}", y);
+        }
+    }
+
+    #[test]
+    fn test_map_get_out_of_bounds() {
+        let map = Map::generate_local(100, 50, WORLD_SEED);
+
+        assert_eq!(map.get(-1, 0), None);
+        assert_eq!(map.get(0, -1), None);
+        assert_eq!(map.get(100, 0), None);
+        assert_eq!(map.get(0, 50), None);
+    }
+
+    #[test]
+    fn test_map_index_is_row_major() {
+        let map = Map::generate_local(100, 50, WORLD_SEED);
+        assert_eq!(map.index(0, 0), Some(0));
+        assert_eq!(map.index(99, 0), Some(99));
+        assert_eq!(map.index(0, 1), Some(100));
+        assert_eq!(map.index(100, 0), None, "x past the width must not wrap to the next row");
+        assert_eq!(map.index(-1, 1), None);
+        assert_eq!(map.get(7, 3), Some(map.tiles[3 * 100 + 7]));
+    }
+
+    #[test]
+    fn test_map_rect_clipped() {
+        let map = Map::generate_local(100, 50, WORLD_SEED);
+        let rect: Vec<&[Tile]> = map.rect(95..110, 48..60).collect();
+        assert_eq!(rect.len(), 2, "Rows past the bottom are skipped");
+        assert!(rect.iter().all(|row| row.len() == 5), "Columns past the right edge are clipped");
+        assert_eq!(rect[1], &map.tiles[49 * 100 + 95..]);
+    }
+
+    #[test]
+    fn test_map_row_span_local() {
+        let map = Map::generate_local(100, 50, WORLD_SEED);
+        let span: Vec<Option<Tile>> = map.row_span(10, -3..103).collect();
+        assert_eq!(span.len(), 106, "One entry per requested column");
+        for (i, tile) in span.iter().enumerate() {
+            assert_eq!(*tile, map.get(i as i32 - 3, 10), "Column {} should match get()", i as i32 - 3);
+        }
+        assert!(map.row_span(-1, 0..10).all(|t| t.is_none()), "Rows off the map are empty");
+    }
+
+    #[test]
+    fn test_map_is_passable() {
+        let map = Map::generate_local(100, 50, WORLD_SEED);
+
+        // Border should not be passable
+        assert!(!map.is_passable(0, 0));
+        assert!(!map.is_passable(-1, 0));
+
+        // Find a floor tile and check it's passable
+        let start = map.find_start_position();
+        assert!(map.is_passable(start.0, start.1), "Start position should be passable");
+    }
+
+    #[test]
+    fn test_map_find_start_position_is_passable() {
+        let map = Map::generate_local(100, 50, WORLD_SEED);
+        let (x, y) = map.find_start_position();
+
+        assert!(map.is_passable(x, y), "Start position must be passable");
+        assert!(x > 0 && x < 100, "Start x should be within bounds");
+        assert!(y > 0 && y < 50, "Start y should be within bounds");
+    }
+
+    /// Hashes of the offline fallback map's tiles and start position at a few sizes.
+    /// If a change to generation is intentional, paste the new hashes from the failure messages.
+    #[test]
+    fn test_golden_local_maps() {
+        let cases: [(usize, usize, u64); 3] = [
+            (200, 100, 0x5a9ef3e34bd4f51b),
+            (80, 40, 0xe2a14184b7065cd1),
+            (500, 250, 0x2dc173b64e2a5ab7),
+        ];
+        for (width, height, expected) in cases {
+            let map = Map::generate_local(width, height, WORLD_SEED);
+            let mut bytes: Vec<u8> = map.tiles.iter().map(|t| t.to_byte()).collect();
+            let (x, y) = map.find_start_position();
+            bytes.extend_from_slice(&x.to_le_bytes());
+            bytes.extend_from_slice(&y.to_le_bytes());
+            let hash = fnv1a(&bytes);
+            assert_eq!(hash, expected, "Local map at {}x{} changed: now {:#018x}", width, height, hash);
+        }
+    }
+
+    // ==================== Chunk Cache Tests ====================
+
+    fn test_chunk(cx: i32, cy: i32, tile: Tile) -> ChunkData {
+        let size = exospace_core::CHUNK_SIZE;
+        ChunkData { cx, cy, tiles: vec![tile; (size * size) as usize], pois: Vec::new(), regions: Vec::new() }
+    }
+
+    /// A streaming map with no reachable server, so tests control which chunks exist
+    fn offline_streaming_map() -> Map {
+        Map {
+            tiles: Vec::new(),
+            width: 0,
+            height: 0,
+            start_position: Some(SPAWN_POSITION),
+            chunks: Some(ChunkCache::new("http://127.0.0.1:9", None, WORLD_SEED, true)),
+            seed: WORLD_SEED,
+            explored: ExploredTiles::default(),
+            pois: KnownPois::default(),
+            regions: KnownRegions::default(),
+            events: ActiveEvents::default(),
+        }
+    }
+
+    #[test]
+    fn test_streaming_map_reads_from_chunks() {
+        let mut map = offline_streaming_map();
+        let cache = map.chunks.as_mut().unwrap();
+        cache.insert(test_chunk(0, 0, Tile::Floor));
+        cache.insert(test_chunk(-1, 0, Tile::Wall));
+
+        assert_eq!(map.get(5, 5), Some(Tile::Floor));
+        assert_eq!(map.get(-1, 5), Some(Tile::Wall), "Negative coords map to chunk -1");
+        assert_eq!(map.get(5, -1), None, "Unloaded chunk reads as nothing");
+        assert!(!map.is_passable(5, -1), "Unloaded chunks are not passable");
+    }
+
+    #[test]
+    fn test_streaming_map_row_span_crosses_chunks() {
+        let mut map = offline_streaming_map();
+        let cache = map.chunks.as_mut().unwrap();
+        cache.insert(test_chunk(0, 0, Tile::Floor));
+        cache.insert(test_chunk(-1, 0, Tile::Wall));
+
+        let span: Vec<Option<Tile>> = map.row_span(5, -2..CHUNK_SIZE + 2).collect();
+        assert_eq!(span.len(), (CHUNK_SIZE + 4) as usize);
+        assert_eq!(&span[..2], &[Some(Tile::Wall); 2]);
+        assert!(span[2..(CHUNK_SIZE + 2) as usize].iter().all(|&t| t == Some(Tile::Floor)));
+        assert_eq!(&span[(CHUNK_SIZE + 2) as usize..], &[None; 2], "Unloaded chunk reads as nothing");
+    }
+
+    #[test]
+    fn test_streaming_map_failed_fetch_is_not_retried_immediately() {
+        let mut map = offline_streaming_map();
+        map.load_around(0, 0, 0, 0);
+
+        let cache = map.chunks.as_ref().unwrap();
+        assert!(cache.chunks.is_empty());
+        assert!(cache.failed.contains_key(&(0, 0)), "Failed chunk should be remembered");
+    }
+
+    #[test]
+    fn test_rate_limited_chunks_pause_fetching() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 1024]).unwrap();
+            let body = r#"{"code":"rate_limited","error":"Too many requests; try again in 30s"}"#;
+            let head = format!("HTTP/1.1 429 Too Many Requests\r\nRetry-After: 30\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(body.as_bytes()).unwrap();
+        });
+
+        let mut cache = ChunkCache::new(&url, None, WORLD_SEED, false);
+        cache.load_area(0, 0, CHUNK_SIZE, CHUNK_SIZE);
+        server.join().unwrap();
+        let paused_for = cache.paused_until.expect("Paused").duration_since(Instant::now());
+        assert!(paused_for > Duration::from_secs(25), "Paused as long as the server asked, not {:?}", paused_for);
+        assert!(cache.failed.is_empty(), "The rest of the area isn't asked for or marked failed");
+
+        cache.load_area(0, 0, CHUNK_SIZE, CHUNK_SIZE);
+        assert!(cache.failed.is_empty(), "Nothing is asked for while paused");
+    }
+
+    #[test]
+    fn test_chunk_fetch_compression_toggle() {
+        let (url, server) = serve_chunks(2);
+        let compressed = ChunkCache::new(&url, None, WORLD_SEED, true).fetch(0, 0);
+        let plain = ChunkCache::new(&url, None, WORLD_SEED, false).fetch(0, 0);
+        assert_eq!(compressed.unwrap().tiles[0], Tile::Floor, "An uncompressed reply is read as it is");
+        assert_eq!(plain.unwrap().tiles[0], Tile::Floor);
+
+        let requests = server.join().unwrap();
+        let accepts = |request: &str| request.lines().find(|line| line.starts_with("accept-encoding:")).map(str::to_string);
+        assert_eq!(accepts(&requests[0]).as_deref(), Some("accept-encoding: gzip, zstd"));
+        assert_eq!(accepts(&requests[1]), None, "Turned off, compression isn't asked for");
+        assert!(Config::default().compression(), "On by default");
+    }
+
+    #[test]
+    fn test_chunk_fetch_revalidates_kept_chunks() {
+        let dir = std::env::temp_dir().join(format!("exospace-kept-chunks-test-{}", std::process::id()));
+        let (url, server) = serve_chunks(2);
+        let mut first = ChunkCache::new(&url, None, WORLD_SEED, false);
+        first.store = ChunkStore::at(dir.clone());
+        first.fetch(0, 1).unwrap();
+        assert!(first.store.load(0, 1).is_some(), "Tagged chunks are kept");
+
+        let mut second = ChunkCache::new(&url, None, WORLD_SEED, false);
+        second.store = ChunkStore::at(dir.clone());
+        let chunk = second.fetch(0, 1).unwrap();
+        assert_eq!((chunk.cx, chunk.cy, chunk.tiles[0]), (0, 1, Tile::Floor), "A 304 has no body, so this came off the disk");
+        assert_eq!(second.version, Some(4), "The 304 still says how current the chunk is");
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("if-none-match"), "Nothing kept the first time");
+        assert!(requests[1].contains("if-none-match: w/\"floor\""));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_chunk_cache_catches_up_from_diffs() {
+        let (url, server) = serve_chunks(2);
+        let mut cache = ChunkCache::new(&url, None, WORLD_SEED, false);
+        assert!(!cache.catch_up(), "Nothing to catch up from before the server gives a version");
+        let chunk = cache.fetch(0, 0).unwrap();
+        cache.insert(chunk);
+        cache.fetch(1, 0).unwrap();
+        server.join().unwrap();
+        assert_eq!(cache.version, Some(4), "Chunks say how current they are");
+
+        let (url, server) = serve_json(vec![
+            r#"{"version":7,"tiles":[{"x":1,"y":1,"tile":"Wall"},{"x":2,"y":1,"tile":"Wall"}],"more":true}"#,
+            r#"{"version":9,"tiles":[{"x":900,"y":900,"tile":"Wall"}]}"#,
+            r#"{"version":2,"tiles":[]}"#,
+        ]);
+        cache.server_url = url;
+        cache.edits.record(2, 1, Tile::Nebula);
+        *cache.get_mut(2, 1).unwrap() = Tile::Nebula;
+        assert!(cache.catch_up());
+        assert_eq!(cache.get(1, 1), Some(Tile::Wall));
+        assert_eq!(cache.get(2, 1), Some(Tile::Nebula), "Our unsent edit stays on top");
+        assert_eq!(cache.version, Some(9), "Changes to chunks not loaded are passed over");
+        assert!(!cache.catch_up(), "The server's world started over, so the chunks must be fetched again");
+
+        let requests = server.join().unwrap();
+        let asked: Vec<&str> = requests.iter().map(|line| line.split(' ').nth(1).unwrap_or_default()).collect();
+        assert_eq!(asked, vec!["/map/diff?since=4", "/map/diff?since=7", "/map/diff?since=9"], "Pages follow on");
+    }
+
+    #[test]
+    fn test_map_new_falls_back_to_local() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["exospace-client-terminal", "--width", "80", "--height", "70"]).unwrap();
+        let (map, notice) = Map::new(Some(Err("refused".to_string())), &cli);
+        assert!(map.chunks.is_none());
+        assert_eq!((map.width, map.height), (80, 70));
+        assert_eq!(notice.text, "Server unavailable (refused), generating local map");
+
+        let (map, notice) = Map::new(None, &cli);
+        assert!(map.chunks.is_none());
+        assert_eq!(notice.text, "Flying solo, generating local map", "Without --server the game starts solo");
+
+        let cli = Cli::try_parse_from(["exospace-client-terminal", "--server", "http://127.0.0.1:9", "--width", "80", "--height", "70"]).unwrap();
+        let (map, notice) = Map::new(None, &cli);
+        assert!(map.chunks.is_none());
+        assert_eq!(notice.text, "Loading cancelled, generating local map");
+
+        let (map, _) = Map::new(Some(Ok(offline_streaming_map())), &cli);
+        assert!(map.chunks.is_some(), "A loaded map is played as it is");
+    }
+
+    #[test]
+    fn test_streaming_map_skips_loaded_chunks() {
+        let mut map = offline_streaming_map();
+        map.chunks.as_mut().unwrap().insert(test_chunk(0, 0, Tile::Floor));
+
+        // Entirely inside chunk (0, 0), so nothing needs fetching
+        map.load_around(10, 10, 5, 5);
+        assert!(map.chunks.as_ref().unwrap().failed.is_empty());
+    }
+
+    #[test]
+    fn test_streaming_map_evicts_farthest_chunks() {
+        let mut map = offline_streaming_map();
+        let cache = map.chunks.as_mut().unwrap();
+        for i in 0..(MAX_CACHED_CHUNKS as i32 + 10) {
+            cache.insert(test_chunk(i, 0, Tile::Floor));
+        }
+
+        map.evict_distant_chunks(0, 0);
+        let cache = map.chunks.as_ref().unwrap();
+        assert_eq!(cache.chunks.len(), MAX_CACHED_CHUNKS);
+        assert!(cache.chunks.contains_key(&(0, 0)), "Chunk under the player is kept");
+        assert!(!cache.chunks.contains_key(&(MAX_CACHED_CHUNKS as i32 + 9, 0)), "Farthest chunk is dropped");
+    }
+
+    #[test]
+    fn test_map_world_key_per_source_and_seed() {
+        let local = Map::generate_local(100, 50, WORLD_SEED);
+        let streaming = offline_streaming_map();
+        assert_eq!(local.world_key(), format!("local-{}", WORLD_SEED));
+        assert_eq!(streaming.world_key(), format!("stream-{}", WORLD_SEED));
+        assert_eq!(local.explored.count(), 0, "New maps start unexplored");
+    }
+
+    #[test]
+    fn test_map_save_round_trip_resumes_at_ship() {
+        let mut map = Map::generate_local(100, 50, WORLD_SEED);
+        let ship = map.find_start_position();
+        map.set(ship.0 + 1, ship.1, Tile::Asteroid);
+        map.explored.mark(3, 4);
+        let restored = Map::from_saved(saves::SavedMap::from_bytes(&map.to_saved(ship).to_bytes()).unwrap());
+        assert_eq!(restored.world_key(), map.world_key(), "Saves keep the seed");
+        assert_eq!(restored.find_start_position(), ship, "Loading resumes where the ship was");
+        assert_eq!(restored.get(ship.0 + 1, ship.1), Some(Tile::Asteroid), "Edits are saved");
+        assert!(restored.explored.is_explored(3, 4));
+        assert_eq!(restored.regions().iter().count(), 1, "A local map is surveyed into regions, and they're saved");
+        assert_eq!(restored.regions().at(ship.0, ship.1), map.regions().at(ship.0, ship.1));
+    }
+
+    #[test]
+    fn test_local_map_ignores_chunk_calls() {
+        let mut map = Map::generate_local(100, 50, WORLD_SEED);
+        map.load_around(10, 10, 50, 50);
+        map.evict_distant_chunks(10, 10);
+        assert!(map.chunks.is_none());
+        assert!(map.get(0, 0).is_some());
+    }
+
+    // ==================== Player Tests ====================
+
+    #[test]
+    fn test_player_new() {
+        let player = Player::new(10, 20);
+        assert_eq!(player.x, 10);
+        assert_eq!(player.y, 20);
+        assert_eq!(player.direction, Direction::Up);
+    }
+
+    /// Streamed map made of a single tile type around the origin
+    fn open_map(tile: Tile) -> Map {
+        let mut map = offline_streaming_map();
+        let cache = map.chunks.as_mut().unwrap();
+        for (cx, cy) in [(-1, -1), (0, -1), (-1, 0), (0, 0)] {
+            cache.insert(test_chunk(cx, cy, tile));
+        }
+        map
+    }
+
+    #[test]
+    fn test_map_set_changes_local_and_streamed_tiles() {
+        let mut local = Map::generate_local(20, 10, WORLD_SEED);
+        local.set(3, 4, Tile::OreAsteroid);
+        assert_eq!(local.get(3, 4), Some(Tile::OreAsteroid));
+        local.set(-1, 4, Tile::Floor); // Off the map: ignored
+
+        let mut streamed = open_map(Tile::Floor);
+        streamed.set(-5, 7, Tile::CrystalField);
+        assert_eq!(streamed.get(-5, 7), Some(Tile::CrystalField));
+        streamed.set(500, 500, Tile::Wall); // Chunk not loaded: ignored
+        assert_eq!(streamed.get(500, 500), None);
+    }
+
+    #[test]
+    fn test_player_thrust_updates_direction() {
+        let map = Map::generate_local(100, 50, WORLD_SEED);
+        let start = map.find_start_position();
+        let mut player = Player::new(start.0, start.1);
+        let physics = ShipPhysics::default();
+
+        // Direction follows thrust even if blocked
+        player.update((1, 0), &physics, &map);
+        assert_eq!(player.direction, Direction::Right);
+
+        player.update((0, 1), &physics, &map);
+        assert_eq!(player.direction, Direction::Down);
+    }
+
+    #[test]
+    fn test_player_no_move_without_thrust_or_momentum() {
+        let map = Map::generate_local(100, 50, WORLD_SEED);
+        let start = map.find_start_position();
+        let mut player = Player::new(start.0, start.1);
+        let original_dir = player.direction;
+
+        let moved = player.update((0, 0), &ShipPhysics::default(), &map);
+        assert!(!moved, "Should not move at rest");
+        assert_eq!(player.direction, original_dir, "Direction should not change");
+        assert_eq!((player.x, player.y), start);
+    }
+
+    #[test]
+    fn test_player_collision_with_wall() {
+        let map = Map::generate_local(100, 50, WORLD_SEED);
+        let mut player = Player::new(1, 1); // Near the wall border
+        let physics = ShipPhysics::default();
+
+        // Thrust into the wall (border is at x=0)
+        for _ in 0..20 {
+            player.update((-1, 0), &physics, &map);
+        }
+        assert_eq!(player.x, 1, "X position should not change");
+        assert!(player.vx.abs() < 0.5, "Hitting the wall kills horizontal speed");
+    }
+
+    #[test]
+    fn test_player_reports_rock_impacts() {
+        let mut map = open_map(Tile::Floor);
+        map.set(5, 0, Tile::Asteroid);
+        map.set(0, 5, Tile::Wall);
+        let physics = ShipPhysics::default();
+
+        let mut player = Player::new(0, 0);
+        while player.x < 4 {
+            player.update((1, 0), &physics, &map);
+            assert_eq!(player.impact, None);
+        }
+        player.update((1, 0), &physics, &map);
+        assert!(player.impact.is_some_and(|speed| speed > hull::SAFE_IMPACT_SPEED), "Flying into an asteroid at speed is a crash");
+        player.update((0, 0), &physics, &map);
+        assert_eq!(player.impact, None, "Impacts last one tick");
+
+        let mut player = Player::new(0, 0);
+        for _ in 0..20 {
+            player.update((0, 1), &physics, &map);
+            assert_eq!(player.impact, None, "Walls don't count");
+        }
+    }
+
+    #[test]
+    fn test_player_accelerates_and_drifts() {
+        let map = open_map(Tile::Floor);
+        let physics = ShipPhysics::default();
+        let mut player = Player::new(0, 0);
+
+        assert!(!player.update((1, 0), &physics, &map), "First tick only builds up speed");
+        for _ in 0..20 {
+            player.update((1, 0), &physics, &map);
+        }
+        assert!((player.vx - physics.max_speed).abs() < 1e-4, "Speed tops out at max_speed");
+
+        let coasting_from = player.x;
+        player.update((0, 0), &physics, &map);
+        player.update((0, 0), &physics, &map);
+        assert!(player.x > coasting_from, "Momentum carries the ship without thrust");
+
+        for _ in 0..200 {
+            player.update((0, 0), &physics, &map);
+        }
+        assert_eq!(player.vx, 0.0, "Drag eventually brings the ship to rest");
+    }
+
+    #[test]
+    fn test_player_nebula_drag_slows() {
+        let physics = ShipPhysics::default();
+        let top_speed = |tile| {
+            let map = open_map(tile);
+            let mut player = Player::new(-30, 0);
+            for _ in 0..30 {
+                player.update((1, 0), &physics, &map);
+            }
+            player.vx
+        };
+        assert!(top_speed(Tile::Nebula) < top_speed(Tile::Floor) * 0.6, "Nebulae are thick going");
+    }
+
+    #[test]
+    fn test_player_diagonal_thrust_not_faster() {
+        let map = open_map(Tile::Floor);
+        let physics = ShipPhysics { max_speed: 10.0, ..Default::default() };
+        let mut straight = Player::new(0, 0);
+        let mut diagonal = Player::new(0, 0);
+        straight.update((1, 0), &physics, &map);
+        diagonal.update((1, 1), &physics, &map);
+        assert!((diagonal.vx.hypot(diagonal.vy) - straight.vx).abs() < 1e-4);
+        assert_eq!(diagonal.direction, Direction::DownRight);
+    }
+
+    #[test]
+    fn test_player_long_diagonal_runs() {
+        let mut map = offline_streaming_map();
+        for (cx, cy) in (0..4).flat_map(|cy| (0..4).map(move |cx| (cx, cy))) {
+            map.chunks.as_mut().unwrap().insert(test_chunk(cx, cy, Tile::Floor));
+        }
+        // Distance from the start after 150 ticks of thrust
+        let run = |thrust, diagonals| {
+            let physics = ShipPhysics { diagonals, ..Default::default() };
+            let mut player = Player::new(0, 0);
+            for _ in 0..150 {
+                player.update(thrust, &physics, &map);
+            }
+            (player.x as f32).hypot(player.y as f32)
+        };
+        let straight = run((1, 0), Diagonals::Uniform);
+        let diagonal = run((1, 1), Diagonals::Uniform);
+        assert!((diagonal - straight).abs() <= 2.0, "Diagonals cover the same ground: {} vs {}", diagonal, straight);
+        let classic = run((1, 1), Diagonals::Classic);
+        assert!((classic / straight - std::f32::consts::SQRT_2).abs() < 0.05, "Classic diagonals are faster: {} vs {}", classic, straight);
+    }
+
+    #[test]
+    fn test_player_stop() {
+        let map = open_map(Tile::Floor);
+        let mut player = Player::new(0, 0);
+        player.update((0, -1), &ShipPhysics::default(), &map);
+        player.stop();
+        assert_eq!((player.vx, player.vy, player.carry_x, player.carry_y), (0.0, 0.0, 0.0, 0.0));
+    }
+
+    // ==================== Renderer Tests ====================
+
+    #[test]
+    fn test_renderer_new_with_effects_enabled() {
+        let renderer = Renderer::new(true);
+        assert_eq!(renderer.frame, 0);
+        assert!(renderer.effects_enabled);
+    }
+
+    #[test]
+    fn test_renderer_new_with_effects_disabled() {
+        let renderer = Renderer::new(false);
+        assert_eq!(renderer.frame, 0);
+        assert!(!renderer.effects_enabled);
+    }
+
+    #[test]
+    fn test_renderer_toggle_effects() {
+        let mut renderer = Renderer::new(true);
+        assert!(renderer.effects_enabled);
+
+        renderer.toggle_effects();
+        assert!(!renderer.effects_enabled);
+
+        renderer.toggle_effects();
+        assert!(renderer.effects_enabled);
+    }
+
+    #[test]
+    fn test_renderer_unknown_tiles_are_question_marks() {
+        for effects in [true, false] {
+            let renderer = Renderer::new(effects);
+            assert_eq!(renderer.render_tile(Some(Tile::Unknown), Biome::DeepSpace, 3, 4), UNKNOWN_TILE_STYLE);
+        }
+        assert_eq!(Minimap::cell_style(Some(Tile::Unknown)), UNKNOWN_TILE_STYLE);
+
+        let map = open_map(Tile::Unknown);
+        let mut player = Player::new(0, 0);
+        for _ in 0..20 {
+            player.update((1, 0), &ShipPhysics::default(), &map);
+        }
+        assert_eq!((player.x, player.y), (0, 0), "Unknown tiles block movement");
+    }
+
+    #[test]
+    fn test_renderer_fog_is_dim() {
+        let renderer = Renderer::new(true);
+        for x in 0..200 {
+            let (_, color) = renderer.render_fog(x, 7);
+            let (r, g, b) = (color >> 16, (color >> 8) & 0xFF, color & 0xFF);
+            assert!(r == g && g == b, "Fog should be gray");
+            assert!(r < 0x30, "Fog should be dim, got {:06X}", color);
+        }
+    }
+
+    #[test]
+    fn test_renderer_fog_animation_follows_effects() {
+        let mut animated = Renderer::new(true);
+        let mut frozen = Renderer::new(false);
+        let before: Vec<_> = (0..100).map(|x| (animated.render_fog(x, 0), frozen.render_fog(x, 0))).collect();
+        for _ in 0..6 {
+            animated.tick();
+            frozen.tick();
+        }
+        let after: Vec<_> = (0..100).map(|x| (animated.render_fog(x, 0), frozen.render_fog(x, 0))).collect();
+        assert!(before.iter().zip(&after).any(|(b, a)| b.0 != a.0), "Static should crawl with effects on");
+        assert!(before.iter().zip(&after).all(|(b, a)| b.1 == a.1), "Static should hold still with effects off");
+    }
+
+    #[test]
+    fn test_renderer_tick() {
+        let mut renderer = Renderer::new(true);
+        assert_eq!(renderer.frame, 0);
+
+        renderer.tick();
+        assert_eq!(renderer.frame, 1);
+
+        renderer.tick();
+        assert_eq!(renderer.frame, 2);
+    }
+
+    #[test]
+    fn test_renderer_effects_disabled_returns_simple_tiles() {
+        let renderer = Renderer::new(false);
+
+        // With effects disabled, floor should return space with black
+        let (ch, color) = renderer.render_tile(Some(Tile::Floor), Biome::DeepSpace, 0, 0);
+        assert_eq!(ch, ' ');
+        assert_eq!(color, 0x000000);
+
+        // Wall should return solid block
+        let (ch, _) = renderer.render_tile(Some(Tile::Wall), Biome::DeepSpace, 0, 0);
+        assert_eq!(ch, '█');
+    }
+
+    #[test]
+    fn test_renderer_render_tile_deterministic() {
+        let renderer = Renderer::new(true);
+
+        // Same position should give same result
+        let result1 = renderer.render_tile(Some(Tile::Wall), Biome::DeepSpace, 10, 20);
+        let result2 = renderer.render_tile(Some(Tile::Wall), Biome::DeepSpace, 10, 20);
+        assert_eq!(result1, result2, "Render should be deterministic for same position");
+    }
+
+    #[test]
+    fn test_renderer_draws_in_its_theme() {
+        let mut renderer = Renderer::new(false);
+        assert_eq!(renderer.render_tile(Some(Tile::Wall), Biome::DeepSpace, 0, 0), ('█', 0x4060A0));
+        assert_eq!(blend(0x2050C0, 0x2070C0, 0x1F, 0x20), 0x206FC0, "Default walls shade like before themes");
+
+        renderer.theme = Theme::preset("monochrome").unwrap();
+        assert_eq!(renderer.render_tile(Some(Tile::Wall), Biome::DeepSpace, 0, 0).1, 0x909090);
+        assert_eq!(renderer.render_tile(Some(Tile::CrystalField), Biome::DeepSpace, 0, 0).1, 0xE0E0E0);
+        let hull = renderer.get_ship_cell(Direction::Up, 0, 0).unwrap();
+        assert_eq!(hull.fg, 0xE0E0E0, "The local ship is painted from the theme");
+    }
+
+    #[test]
+    fn test_renderer_dims_tiles_in_the_dark() {
+        let mut renderer = Renderer::new(false);
+        let lit = renderer.get_ship_cell(Direction::Up, 0, 0);
+        renderer.brightness = 50;
+        assert_eq!(renderer.render_tile(Some(Tile::Wall), Biome::DeepSpace, 0, 0), ('█', 0x203050), "Half light, half as bright");
+        assert_eq!(renderer.get_ship_cell(Direction::Up, 0, 0), lit, "Ships stay lit");
+
+        let mut config = Config { light_level: Some(5), ..Config::default() };
+        assert_eq!(config.light_level(), Some(light::LEVEL_LIMITS.0), "Never locked in the dark");
+        config.light_level = None;
+        assert_eq!(config.light_level(), None);
+    }
+
+    #[test]
+    fn test_renderer_draws_biomes() {
+        let renderer = Renderer::new(true);
+        let (_, deep) = renderer.render_tile(Some(Tile::Wall), Biome::DeepSpace, 0, 0);
+        let (_, station) = renderer.render_tile(Some(Tile::Wall), Biome::StationSector, 0, 0);
+        assert_eq!(station, blend(deep, renderer.theme.biomes[3].0, 1, regions::TINT_STEPS), "Tinted toward the biome's color");
+        assert_eq!(renderer.tint(0, Biome::DebrisField), 0, "Empty space stays black");
+
+        let stars = |biome| {
+            (0..100 * 100).filter(|i| renderer.render_tile(Some(Tile::Floor), biome, i % 100, i / 100).0 != ' ').count()
+        };
+        let (station, deep, cloud) = (stars(Biome::StationSector), stars(Biome::DeepSpace), stars(Biome::NebulaCloud));
+        assert!(station > deep && deep > cloud, "Stars crowd station sectors and hide in clouds: {} {} {}", station, deep, cloud);
+    }
+
+    // ==================== InputState Tests ====================
+
+    #[test]
+    fn test_input_state_default() {
+        let state = InputState::default();
+        assert!(!state.up.held);
+        assert!(!state.down.held);
+        assert!(!state.left.held);
+        assert!(!state.right.held);
+        assert!(!state.has_release_support);
+    }
+
+    #[test]
+    fn test_input_state_movement_delta_cardinal() {
+        let mut state = InputState::default();
+        state.up.held = true;
+        assert_eq!(state.movement_delta(), (0, -1));
+
+        state.up.held = false;
+        state.down.held = true;
+        assert_eq!(state.movement_delta(), (0, 1));
+
+        state.down.held = false;
+        state.left.held = true;
+        assert_eq!(state.movement_delta(), (-1, 0));
+
+        state.left.held = false;
+        state.right.held = true;
+        assert_eq!(state.movement_delta(), (1, 0));
+    }
+
+    #[test]
+    fn test_input_state_movement_delta_diagonal() {
+        let mut state = InputState::default();
+
+        state.up.held = true;
+        state.right.held = true;
+        assert_eq!(state.movement_delta(), (1, -1));
+
+        state.right.held = false;
+        state.left.held = true;
+        assert_eq!(state.movement_delta(), (-1, -1));
+    }
+
+    #[test]
+    fn test_input_state_held_keys_time_out() {
+        let mut clock = MockClock::new();
+        let mut state = InputState::default();
+        state.update_move(Action::MoveUp, NcInputType::Press, clock.now());
+        clock.advance(Duration::from_millis(250));
+        state.timeout_stale_keys(clock.now());
+        assert!(state.up.held, "Still inside the key timeout");
+
+        state.update_move(Action::MoveUp, NcInputType::Repeat, clock.now());
+        clock.advance(Duration::from_millis(250));
+        state.timeout_stale_keys(clock.now());
+        assert!(state.up.held, "Repeats keep the key held");
+
+        clock.advance(Duration::from_millis(100));
+        state.timeout_stale_keys(clock.now());
+        assert!(!state.up.held, "No repeat for longer than the timeout lets go");
+
+        state.update_move(Action::MoveLeft, NcInputType::Press, clock.now());
+        state.update_move(Action::MoveLeft, NcInputType::Release, clock.now());
+        state.update_move(Action::MoveUp, NcInputType::Press, clock.now());
+        clock.advance(Duration::from_secs(5));
+        state.timeout_stale_keys(clock.now());
+        assert!(state.up.held, "Once releases are seen, keys are held until released");
+    }
+
+    #[test]
+    fn test_input_state_diagonal_keys() {
+        let mut clock = MockClock::new();
+        let mut state = InputState::default();
+        state.update_move(Action::MoveUpLeft, NcInputType::Press, clock.now());
+        assert_eq!(state.movement_delta(), (-1, -1), "One key holds both directions");
+
+        clock.advance(Duration::from_millis(500));
+        state.timeout_stale_keys(clock.now());
+        assert!(!state.any_movement(), "Both directions time out together");
+
+        state.update_move(Action::MoveDownRight, NcInputType::Press, clock.now());
+        state.update_move(Action::MoveRight, NcInputType::Press, clock.now());
+        state.update_move(Action::MoveDownRight, NcInputType::Release, clock.now());
+        assert_eq!(state.movement_delta(), (0, 0), "Releasing the diagonal lets go of its directions");
+
+        state.update_move(Action::Quit, NcInputType::Press, clock.now());
+        assert!(!state.any_movement(), "Other actions don't move");
+    }
+
+    #[test]
+    fn test_input_state_any_movement() {
+        let mut state = InputState::default();
+        assert!(!state.any_movement());
+
+        state.up.held = true;
+        assert!(state.any_movement());
+    }
+
+    // ==================== ShipCell Tests ====================
+
+    #[test]
+    fn test_ship_cell_new() {
+        let cell = ShipCell::new('^', 0xFF0000);
+        assert_eq!(cell.ch, '^');
+        assert_eq!(cell.fg, 0xFF0000);
+        assert!(cell.bg.is_none());
+    }
+
+    #[test]
+    fn test_ship_cell_empty() {
+        let cell = ShipCell::empty();
+        assert_eq!(cell.ch, ' ');
+        assert_eq!(cell.fg, 0x000000);
+        assert!(cell.bg.is_none());
+    }
+
+    // ==================== ShipSprite Tests ====================
+
+    #[test]
+    fn test_ship_sprite_all_directions() {
+        // Verify ship sprites exist for every class in all 8 directions
+        let directions = [
+            Direction::Up, Direction::Down, Direction::Left, Direction::Right,
+            Direction::UpRight, Direction::UpLeft, Direction::DownRight, Direction::DownLeft,
+        ];
+
+        for (class, dir) in ShipClass::ALL.into_iter().flat_map(|class| directions.map(|dir| (class, dir))) {
+            let sprite = ShipSprite::for_class_and_direction(class, dir, &ShipPalette::player());
+            // Ship should be 3x3
+            assert_eq!(sprite.cells.len(), 3);
+            for row in &sprite.cells {
+                assert_eq!(row.len(), 3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ship_sprite_center_not_empty() {
+        // Center of ship should never be empty for any class or direction
+        let directions = [
+            Direction::Up, Direction::Down, Direction::Left, Direction::Right,
+            Direction::UpRight, Direction::UpLeft, Direction::DownRight, Direction::DownLeft,
+        ];
+
+        for (class, dir) in ShipClass::ALL.into_iter().flat_map(|class| directions.map(|dir| (class, dir))) {
+            let sprite = ShipSprite::for_class_and_direction(class, dir, &ShipPalette::player());
+            let center = sprite.cells[1][1];
+            assert_ne!(center.ch, ' ', "Center of ship should not be empty for {:?} {:?}", class, dir);
+        }
+    }
+
+    #[test]
+    fn test_ship_sprite_has_cockpit() {
+        // Each ship direction should have at least one cockpit-colored cell
+        let cockpit_color = 0x80FFFF;
+        let directions = [
+            Direction::Up, Direction::Down, Direction::Left, Direction::Right,
+            Direction::UpRight, Direction::UpLeft, Direction::DownRight, Direction::DownLeft,
+        ];
+
+        for (class, dir) in ShipClass::ALL.into_iter().flat_map(|class| directions.map(|dir| (class, dir))) {
+            let sprite = ShipSprite::for_class_and_direction(class, dir, &ShipPalette::player());
+            let has_cockpit = sprite.cells.iter()
+                .flatten()
+                .any(|cell| cell.fg == cockpit_color);
+            assert!(has_cockpit, "Ship should have cockpit for {:?} {:?}", class, dir);
+        }
+    }
+
+    // ==================== ExhaustSprite Tests ====================
+
+    #[test]
+    fn test_exhaust_sprite_all_directions() {
+        // Verify exhaust sprites exist for all 8 directions
+        let directions = [
+            Direction::Up, Direction::Down, Direction::Left, Direction::Right,
+            Direction::UpRight, Direction::UpLeft, Direction::DownRight, Direction::DownLeft,
+        ];
+
+        for dir in directions {
+            let sprite = ExhaustSprite::for_direction(dir, 0);
+            // Exhaust should be 4x3 (4 rows, 3 columns)
+            assert_eq!(sprite.cells.len(), 4);
+            for row in &sprite.cells {
+                assert_eq!(row.len(), 3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_exhaust_sprite_animation() {
+        // Exhaust should change over frames
+        let sprite1 = ExhaustSprite::for_direction(Direction::Up, 0);
+        let sprite2 = ExhaustSprite::for_direction(Direction::Up, 4);
+        let sprite3 = ExhaustSprite::for_direction(Direction::Up, 8);
+
+        // At least one cell should be different between frames
+        let cells_match = |s1: &ExhaustSprite, s2: &ExhaustSprite| {
+            s1.cells.iter().flatten().zip(s2.cells.iter().flatten())
+                .all(|(c1, c2)| c1.ch == c2.ch && c1.fg == c2.fg)
+        };
+
+        // Animation cycles, so frames 0 and 16 should match (4 phases * 4 frames each)
+        assert!(!cells_match(&sprite1, &sprite2) || !cells_match(&sprite2, &sprite3),
+            "Exhaust should animate");
+    }
+
+    #[test]
+    fn test_exhaust_offset_opposite_to_direction() {
+        // Exhaust should appear behind the ship (opposite to movement direction)
+        let (_x, y) = ExhaustSprite::offset_for_direction(Direction::Up);
+        assert!(y > 0, "Up-facing ship exhaust should be below (positive y)");
+
+        let (_x, y) = ExhaustSprite::offset_for_direction(Direction::Down);
+        assert!(y < 0, "Down-facing ship exhaust should be above (negative y)");
+
+        let (x, _y) = ExhaustSprite::offset_for_direction(Direction::Left);
+        assert!(x > 0, "Left-facing ship exhaust should be to right (positive x)");
+
+        let (x, _y) = ExhaustSprite::offset_for_direction(Direction::Right);
+        assert!(x < 0, "Right-facing ship exhaust should be to left (negative x)");
+    }
+
+    // ==================== Renderer Ship Cell Tests ====================
+
+    #[test]
+    fn test_renderer_get_ship_cell_center() {
+        let renderer = Renderer::new(true);
+
+        // Center of ship (offset 0,0) should return a cell
+        let cell = renderer.get_ship_cell(Direction::Up, 0, 0);
+        assert!(cell.is_some(), "Ship center should exist");
+    }
+
+    #[test]
+    fn test_renderer_get_ship_cell_bounds() {
+        let renderer = Renderer::new(true);
+
+        // Ship cells at corners of 3x3 grid
+        let corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+        for (ox, oy) in corners {
+            // At least some corners should have content (not all are empty)
+            let _ = renderer.get_ship_cell(Direction::Up, ox, oy);
+        }
+
+        // Far outside ship should return None (unless it's exhaust)
+        let cell = renderer.get_ship_cell(Direction::Up, 10, 10);
+        assert!(cell.is_none(), "Far from ship should be None");
+    }
+
+    #[test]
+    fn test_renderer_get_ship_cell_exhaust_area() {
+        let renderer = Renderer::new(true);
+
+        // For Up-facing ship, exhaust should be below (positive y offset)
+        let (ex, ey) = ExhaustSprite::offset_for_direction(Direction::Up);
+        // Check a cell in the exhaust area
+        let cell = renderer.get_ship_cell(Direction::Up, ex + 1, ey);
+        assert!(cell.is_some(), "Exhaust area should have content");
+    }
+
+    #[test]
+    fn test_renderer_get_ship_cell_all_directions() {
+        let renderer = Renderer::new(true);
+        let directions = [
+            Direction::Up, Direction::Down, Direction::Left, Direction::Right,
+            Direction::UpRight, Direction::UpLeft, Direction::DownRight, Direction::DownLeft,
+        ];
+
+        for dir in directions {
+            // Every direction should have a ship center
+            let cell = renderer.get_ship_cell(dir, 0, 0);
+            assert!(cell.is_some(), "Ship center should exist for {:?}", dir);
+        }
+    }
+
+    #[test]
+    fn test_remote_ship_cells_use_remote_palette() {
+        let renderer = Renderer::new(true);
+        let others = [PlayerInfo { id: 2, name: "Ace".to_string(), x: 10, y: 10, direction: Direction::Up, ship: ShipLivery::default() }];
+        let cells = renderer.remote_ship_cells(others.iter());
+
+        let center = cells.get(&(10, 10)).expect("Remote ship center should be drawn");
+        assert_eq!(center.fg, ShipPalette::remote().hull);
+        assert_ne!(ShipPalette::remote().hull, ShipPalette::player().hull, "Remote hull must differ");
+        // Up-facing sprite has empty top corners and no exhaust below
+        assert!(!cells.contains_key(&(9, 9)), "Empty sprite cells are skipped");
+        assert!(!cells.contains_key(&(10, 12)), "Remote ships have no exhaust");
+    }
+
+    #[test]
+    fn test_liveries_paint_ships() {
+        let ship = ShipLivery { colors: BTreeMap::from([(ShipPart::Hull, 0x112233)]), ..ShipLivery::default() };
+        let painted = ShipPalette::remote().painted(&ship);
+        assert_eq!(painted.hull, 0x112233);
+        assert_eq!(painted.cockpit, ShipPalette::remote().cockpit, "Unpainted parts keep their color");
+
+        let mut renderer = Renderer::new(true);
+        let others = [PlayerInfo { id: 2, name: "Ace".to_string(), x: 10, y: 10, direction: Direction::Up, ship: ship.clone() }];
+        assert_eq!(renderer.remote_ship_cells(others.iter())[&(10, 10)].fg, 0x112233, "Other pilots show their paint");
+
+        renderer.livery = ship;
+        assert_eq!(renderer.get_ship_cell(Direction::Up, 0, 0).unwrap().fg, 0x112233, "So does our own ship");
+    }
+
+    #[test]
+    fn test_config_livery() {
+        let json = r##"{"ship_name":"Rusty Bucket","callsign":"ace","ship_colors":{"hull":"#FF8800","wing":"green"}}"##;
+        let config: Config = serde_json::from_str(json).unwrap();
+        let (livery, errors) = config.livery();
+        assert_eq!(livery.name.as_deref(), Some("Rusty Bucket"));
+        assert_eq!(livery.callsign.as_deref(), Some("ACE"));
+        assert_eq!(livery.colors, BTreeMap::from([(ShipPart::Hull, 0xFF8800)]));
+        assert_eq!(errors, vec!["Bad ship color for wing: green (use #RRGGBB)".to_string()]);
+
+        let bad = Config { callsign: Some("A".to_string()), ..Default::default() };
+        let (livery, errors) = bad.livery();
+        assert!(livery.is_empty() && errors.len() == 1, "Bad callsigns are left out: {:?}", errors);
+        assert!(Config::default().livery().0.is_empty());
+    }
+
+    #[test]
+    fn test_entity_cells_tinted_by_behavior() {
+        let mut renderer = Renderer::new(true);
+        let npc = |id, x, behavior| NpcInfo { id, name: "Drone".to_string(), x, y: 0, direction: Direction::Left, behavior };
+        let mut entities = Entities::default();
+        entities.put_npc(&npc(1, 0, NpcBehavior::Patrol));
+        entities.put_npc(&npc(2, 10, NpcBehavior::Flee));
+        let shot = entities.spawn();
+        entities.positions.insert(shot, Position { x: 20, y: 0 });
+        entities.sprites.insert(shot, Sprite { kind: SpriteKind::Projectile, direction: Direction::Up });
+        let cells = renderer.entity_cells(entities.drawn());
+
+        assert_eq!(cells[&(0, 0)].fg, ShipPalette::npc(NpcBehavior::Patrol).hull);
+        assert_eq!(cells[&(10, 0)].fg, ShipPalette::npc(NpcBehavior::Flee).hull);
+        assert_eq!((cells[&(20, 0)].ch, cells[&(20, 0)].fg), PROJECTILE_STYLE, "Projectiles are a single glyph");
+        assert!(!cells.contains_key(&(21, 0)));
+        renderer.zoom = 4;
+        assert_eq!(renderer.entity_cells(entities.drawn())[&(5, 0)].ch, PROJECTILE_STYLE.0, "Zoomed out, in the block it's in");
+        let hulls = [ShipPalette::player().hull, ShipPalette::remote().hull];
+        for behavior in [NpcBehavior::Patrol, NpcBehavior::Wander, NpcBehavior::Flee, NpcBehavior::Unknown] {
+            assert!(!hulls.contains(&ShipPalette::npc(behavior).hull), "{:?} ships must not look like pilots", behavior);
+        }
+    }
+
+    #[test]
+    fn test_ship_classes_look_different() {
+        let glyphs = |class| ShipSprite::for_class_and_direction(class, Direction::Up, &ShipPalette::player()).cells.map(|row| row.map(|cell| cell.ch));
+        assert_ne!(glyphs(ShipClass::Scout), glyphs(ShipClass::Freighter));
+        assert_ne!(glyphs(ShipClass::Scout), glyphs(ShipClass::Interceptor));
+        assert_ne!(glyphs(ShipClass::Freighter), glyphs(ShipClass::Interceptor));
+    }
+
+    #[test]
+    fn test_ship_sprite_palette() {
+        let sprite = ShipSprite::for_class_and_direction(ShipClass::Scout, Direction::Up, &ShipPalette::remote());
+        assert_eq!(sprite.cells[0][1].fg, ShipPalette::remote().cockpit);
+        let sprite = ShipSprite::for_class_and_direction(ShipClass::Scout, Direction::Up, &ShipPalette::player());
+        assert_eq!(sprite.cells[0][1].fg, ShipPalette::player().cockpit);
+    }
+
+    // ==================== Config Tests ====================
+
+    #[test]
+    fn test_config_default() {
+        let config = Config::default();
+        assert!(!config.effects_enabled, "Effects should be disabled by default");
+        assert!(config.server_url.is_none(), "Server URL should be None by default");
+        assert!(config.ship_class.is_none(), "New pilots pick a ship first");
+    }
+
+    #[test]
+    fn test_config_server_url_default() {
+        let config = Config::default();
+        assert_eq!(config.server_url(), SERVER_URL);
+    }
+
+    #[test]
+    fn test_config_server_url_override() {
+        let config = Config {
+            effects_enabled: false,
+            server_url: Some("http://custom:8080".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.server_url(), "http://custom:8080");
+    }
+
+    #[test]
+    fn test_config_serialization() {
+        let config = Config {
+            effects_enabled: true,
+            server_url: Some("http://test:3000".to_string()),
+            player_name: Some("Ace".to_string()),
+            autoexec: vec!["/fx".to_string()],
+            chat_colors: HashMap::from([(ChatChannel::Ambient, "#406040".to_string())]),
+            muted_channels: vec![ChatChannel::Utility],
+            keybindings: HashMap::from([(Action::MoveUp, "w".to_string())]),
+            input_profile: InputProfile::Vi,
+            chat_lines: Some(5),
+            physics: ShipPhysics { drag: 0.3, ..Default::default() },
+            account: Some(AccountConfig { name: "Ace".to_string(), password: None, token: Some("t0k3n".to_string()) }),
+            solo_difficulty: HashMap::from([("local-7".to_string(), Difficulty::Hard)]),
+            bookmarks: HashMap::from([("stream-7".to_string(), Bookmarks::default())]),
+            ship_class: Some(ShipClass::Freighter),
+            ship_name: Some("Rusty Bucket".to_string()),
+            callsign: Some("ACE".to_string()),
+            ship_colors: HashMap::from([(ShipPart::Hull, "#FF8800".to_string())]),
+            move_delay_ms: Some(50),
+            key_timeout_ms: Some(500),
+            target_fps: Some(30),
+            theme: Some("solarized".to_string()),
+            charset: Some(Charset::Ascii),
+            compression: Some(false),
+            chunk_cache: Some(false),
+            light_level: Some(70),
+            camera_smoothing_ms: Some(0),
+            camera_deadzone: Some(2),
+            camera_lookahead: Some(50),
+            path: Some(PathBuf::from("/tmp/config.json")),
+            server_override: Some("http://override:3000".to_string()),
+        };
+
+        let json = serde_json::to_string(&config).unwrap();
+        let parsed: Config = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.effects_enabled, config.effects_enabled);
+        assert_eq!(parsed.server_url, config.server_url);
+        assert_eq!(parsed.player_name, config.player_name);
+        assert_eq!(parsed.autoexec, config.autoexec);
+        assert_eq!(parsed.chat_colors, config.chat_colors);
+        assert_eq!(parsed.muted_channels, config.muted_channels);
+        assert_eq!(parsed.keybindings, config.keybindings);
+        assert_eq!(parsed.input_profile, InputProfile::Vi);
+        assert_eq!(parsed.chat_lines, config.chat_lines);
+        assert_eq!(parsed.physics, config.physics);
+        assert_eq!(parsed.account, config.account);
+        assert_eq!(parsed.auth_token(), Some("t0k3n"));
+        assert_eq!(parsed.difficulty_for("local-7"), Difficulty::Hard);
+        assert_eq!(parsed.difficulty_for("local-8"), Difficulty::Normal, "Each solo world has its own");
+        assert_eq!(parsed.bookmarks, config.bookmarks);
+        assert_eq!(parsed.ship_class, config.ship_class);
+        assert_eq!(parsed.livery(), config.livery());
+        assert_eq!(parsed.move_delay_ms, config.move_delay_ms);
+        assert_eq!(parsed.key_timeout(), Duration::from_millis(500));
+        assert_eq!(parsed.target_fps(), 30);
+        assert_eq!(parsed.theme_name(), "solarized");
+        assert_eq!(Config::default().theme_name(), "default");
+        assert!(json.contains(r#""charset":"ascii""#));
+        assert_eq!(parsed.charset, Some(Charset::Ascii));
+        assert!(!parsed.compression());
+        assert!(!parsed.chunk_cache());
+        assert!(Config::default().chunk_cache(), "Chunks are kept by default");
+        assert_eq!(parsed.light_level(), Some(70));
+        assert_eq!(parsed.camera_feel(), CameraFeel { smoothing: Duration::ZERO, deadzone: 2, lookahead: 10 }, "Look-ahead is capped");
+        assert_eq!(Config::default().camera_feel(), CameraFeel::default());
+        assert!(!json.contains("override") && !json.contains("/tmp/config.json"), "Command line settings aren't saved");
+        assert_eq!(config.server_url(), "http://override:3000", "--server wins over the config");
+        assert_eq!(parsed.server_url(), "http://test:3000");
+    }
+
+    #[test]
+    fn test_config_load_and_save_at_path() {
+        let path = std::env::temp_dir().join(format!("exospace-config-{}.json", std::process::id()));
+        let mut config = Config::load(Some(path.clone()));
+        assert_eq!(config.server_url(), SERVER_URL, "A missing file gives the defaults");
+        config.player_name = Some("Vex".to_string());
+        config.save().unwrap();
+        assert_eq!(Config::load(Some(path.clone())).player_name(), "Vex", "Saves go back to the same file");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_config_move_delay_clamped_on_load() {
+        let path = std::env::temp_dir().join(format!("exospace-config-delay-{}.json", std::process::id()));
+        let ship = Duration::from_millis(33);
+        fs::write(&path, r#"{"move_delay_ms": 0}"#).unwrap();
+        assert_eq!(Config::load(Some(path.clone())).move_delay(ship), Duration::from_millis(settings::MOVE_DELAY_LIMITS.0));
+        fs::write(&path, r#"{"move_delay_ms": 100000}"#).unwrap();
+        assert_eq!(Config::load(Some(path.clone())).move_delay(ship), Duration::from_millis(settings::MOVE_DELAY_LIMITS.1));
+        fs::write(&path, "{}").unwrap();
+        assert_eq!(Config::load(Some(path.clone())).move_delay(ship), ship, "No override flies at the ship's own pace");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_config_chat_lines() {
+        assert_eq!(Config::default().chat_lines(), DEFAULT_CHAT_LINES);
+        assert_eq!(Config { chat_lines: Some(6), ..Default::default() }.chat_lines(), 6);
+        assert_eq!(Config { chat_lines: Some(0), ..Default::default() }.chat_lines(), 1, "At least one line");
+        assert_eq!(Config { chat_lines: Some(500), ..Default::default() }.chat_lines(), MAX_CHAT_LINES);
+    }
+
+    #[test]
+    fn test_config_keybindings_from_json() {
+        let parsed: Config = serde_json::from_str(r#"{"keybindings":{"toggle_minimap":"tab","quit":"none"}}"#).unwrap();
+        assert_eq!(parsed.keybindings.get(&Action::ToggleMinimap).map(String::as_str), Some("tab"));
+        assert_eq!(parsed.keybindings.len(), 2);
+        assert!(Config::default().keybindings.is_empty(), "Defaults come from the keymap, not config");
+
+        let wasd: Config = serde_json::from_str(r#"{"input_profile":"wasd"}"#).unwrap();
+        assert_eq!(wasd.input_profile, InputProfile::Wasd);
+        assert_eq!(Config::default().input_profile, InputProfile::Arrows, "Arrows alone unless a profile is picked");
+    }
+
+    #[test]
+    fn test_config_chat_channels_from_json() {
+        let json = r##"{"chat_colors":{"player":"#FFFFFF","error":"FF0000"},"muted_channels":["ambient"]}"##;
+        let parsed: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed.chat_colors.get(&ChatChannel::Player).map(String::as_str), Some("#FFFFFF"));
+        assert_eq!(parsed.muted_channels, vec![ChatChannel::Ambient]);
+        assert!(serde_json::from_str::<Config>(r#"{"muted_channels":["bogus"]}"#).is_err(), "Unknown channel");
+    }
+
+    #[test]
+    fn test_config_autoexec() {
+        let parsed: Config = serde_json::from_str(r#"{"autoexec":["/fx","/target 1 2"]}"#).unwrap();
+        assert_eq!(parsed.autoexec, vec!["/fx".to_string(), "/target 1 2".to_string()]);
+        assert!(Config::default().autoexec.is_empty());
+    }
+
+    #[test]
+    fn test_config_player_name_default() {
+        let config = Config::default();
+        assert_eq!(config.player_name(), DEFAULT_PLAYER_NAME);
+    }
+
+    #[test]
+    fn test_config_missing_fields_use_defaults() {
+        // Config files written before a field existed should still load
+        let parsed: Config = serde_json::from_str(r#"{"effects_enabled":true}"#).unwrap();
+        assert!(parsed.effects_enabled);
+        assert!(parsed.player_name.is_none());
+    }
+
+    #[test]
+    fn test_config_physics() {
+        let parsed: Config = serde_json::from_str(r#"{"physics":{"thrust":0.5}}"#).unwrap();
+        assert_eq!(parsed.physics.thrust, 0.5);
+        assert_eq!(parsed.physics.drag, ShipPhysics::default().drag, "Unset constants keep their defaults");
+        assert_eq!(parsed.physics.diagonals, Diagonals::Uniform);
+        let classic: Config = serde_json::from_str(r#"{"physics":{"diagonals":"classic"}}"#).unwrap();
+        assert_eq!(classic.physics.diagonals, Diagonals::Classic);
+    }
+
+    #[test]
+    fn test_config_physics_clamped_on_load() {
+        let path = std::env::temp_dir().join(format!("exospace-config-physics-{}.json", std::process::id()));
+        fs::write(&path, r#"{"physics":{"thrust":0,"drag":-1,"nebula_drag":5,"max_speed":1000}}"#).unwrap();
+        let physics = Config::load(Some(path.clone())).physics;
+        assert_eq!(physics.thrust, THRUST_LIMITS.0, "A ship with no thrust would never move");
+        assert_eq!(physics.drag, 0.0, "Negative drag would speed the ship up forever");
+        assert_eq!(physics.nebula_drag, 1.0);
+        assert_eq!(physics.max_speed, MAX_SPEED_LIMITS.1);
+        assert_eq!(ShipPhysics::default().clamped(), ShipPhysics::default(), "The defaults are within limits");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_config_path_returns_some() {
+        // Config path should work on most systems
+        let path = Config::config_path();
+        // We can't guarantee it's Some on all systems, but if it is, check structure
+        if let Some(p) = path {
+            assert!(p.ends_with("config.json"));
+            assert!(p.to_string_lossy().contains("exospace"));
+        }
+    }
+
+    // ==================== PipCamera Tests ====================
+
+    #[test]
+    fn test_pip_camera_default_inactive() {
+        let pip = PipCamera::default();
+        assert!(pip.target.is_none());
+        assert!(!pip.visible);
+        assert!(!pip.is_active());
+    }
 
-        nc.render()?;
+    #[test]
+    fn test_pip_camera_set_and_clear_target() {
+        let mut pip = PipCamera::default();
+        pip.set_target(10, 20);
+        assert_eq!(pip.target, Some((10, 20)));
+        assert!(pip.is_active(), "Setting a target should show the window");
 
-        std::thread::sleep(Duration::from_millis(16));
+        pip.toggle();
+        assert!(!pip.is_active());
+
+        pip.clear_target();
+        assert!(pip.target.is_none());
+        assert!(!pip.visible);
     }
 
-    unsafe { nc.stop()? };
-    Ok(())
-}
+    #[test]
+    fn test_pip_camera_toggle_without_target() {
+        let mut pip = PipCamera::default();
+        pip.toggle();
+        assert!(pip.visible);
+        assert!(!pip.is_active(), "No target means nothing to draw");
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_pip_camera_screen_origin() {
+        let pip = PipCamera::default();
+        assert_eq!(pip.screen_origin(80, 20), Some((80 - pip.width - 1, 1)));
+        assert_eq!(pip.screen_origin(10, 20), None, "Too narrow");
+        assert_eq!(pip.screen_origin(80, 5), None, "Too short");
+    }
+
+    #[test]
+    fn test_pip_camera_map_coords_centered() {
+        let mut pip = PipCamera::default();
+        assert_eq!(pip.map_coords(0, 0), None);
+
+        pip.set_target(100, 50);
+        let center_col = (pip.width - 2) / 2;
+        let center_row = (pip.height - 2) / 2;
+        assert_eq!(pip.map_coords(center_col, center_row), Some((100, 50)));
+        assert_eq!(pip.map_coords(center_col - 1, center_row + 1), Some((99, 51)));
+    }
 
-    // ==================== Tile Tests ====================
+    // ==================== Minimap Tests ====================
 
     #[test]
-    fn test_tile_passability() {
-        assert!(Tile::Floor.is_passable(), "Floor should be passable");
-        assert!(Tile::Nebula.is_passable(), "Nebula should be passable");
-        assert!(!Tile::Wall.is_passable(), "Wall should not be passable");
-        assert!(!Tile::Asteroid.is_passable(), "Asteroid should not be passable");
+    fn test_minimap_default_hidden_and_toggle() {
+        let mut minimap = Minimap::default();
+        assert!(!minimap.visible);
+        minimap.toggle();
+        assert!(minimap.visible);
+        minimap.toggle();
+        assert!(!minimap.visible);
     }
 
-    // ==================== Direction Tests ====================
+    #[test]
+    fn test_minimap_size_scales_and_clamps() {
+        assert_eq!(Minimap::size(120, 45), Some((30 + 2, 15 + 2)));
+        assert_eq!(Minimap::size(400, 200), Some((48 + 2, 20 + 2)), "Capped on huge terminals");
+        assert_eq!(Minimap::size(40, 12), Some((16 + 2, 6 + 2)), "Never smaller than the minimum");
+        assert_eq!(Minimap::size(18, 40), None, "Too narrow");
+        assert_eq!(Minimap::size(80, 8), None, "Too short");
+    }
 
     #[test]
-    fn test_direction_from_delta_cardinal() {
-        assert_eq!(Direction::from_delta(0, -1), Some(Direction::Up));
-        assert_eq!(Direction::from_delta(0, 1), Some(Direction::Down));
-        assert_eq!(Direction::from_delta(-1, 0), Some(Direction::Left));
-        assert_eq!(Direction::from_delta(1, 0), Some(Direction::Right));
+    fn test_minimap_cell_style_colors() {
+        assert_eq!(Minimap::cell_style(None).0, ' ', "Unexplored is blank");
+        let asteroid = Minimap::cell_style(Some(Tile::Asteroid));
+        let nebula = Minimap::cell_style(Some(Tile::Nebula));
+        let floor = Minimap::cell_style(Some(Tile::Floor));
+        assert_ne!(asteroid.1, nebula.1, "Asteroids and nebulae are color-coded");
+        assert_ne!(asteroid.1, floor.1);
+        assert_ne!(nebula.1, floor.1);
     }
 
     #[test]
-    fn test_direction_from_delta_diagonal() {
-        assert_eq!(Direction::from_delta(1, -1), Some(Direction::UpRight));
-        assert_eq!(Direction::from_delta(-1, -1), Some(Direction::UpLeft));
-        assert_eq!(Direction::from_delta(1, 1), Some(Direction::DownRight));
-        assert_eq!(Direction::from_delta(-1, 1), Some(Direction::DownLeft));
+    fn test_minimap_view_scales_area_to_cells() {
+        let view = MinimapView::new(Some((0, 0, 100, 50)), (10, 10), 20, 10);
+        assert_eq!((view.scale_x, view.scale_y), (5, 5));
+        assert_eq!(view.cell_for(0, 0), Some((0, 0)));
+        assert_eq!(view.cell_for(99, 49), Some((19, 9)));
+        assert_eq!(view.cell_for(100, 0), None, "Outside the area");
+        assert_eq!(view.cell_for(-1, 0), None);
     }
 
     #[test]
-    fn test_direction_from_delta_zero() {
-        assert_eq!(Direction::from_delta(0, 0), None);
+    fn test_minimap_view_includes_player() {
+        let view = MinimapView::new(Some((0, 0, 64, 64)), (-10, 100), 16, 8);
+        assert!(view.cell_for(-10, 100).is_some(), "Player is always on the minimap");
+        assert!(view.cell_for(0, 0).is_some());
+
+        let empty = MinimapView::new(None, (5, 5), 16, 8);
+        assert_eq!((empty.scale_x, empty.scale_y), (1, 1), "Small areas are never zoomed in past 1:1");
+        assert_eq!(empty.cell_for(5, 5), Some((0, 0)));
     }
 
     #[test]
-    fn test_direction_to_char() {
-        assert_eq!(Direction::Up.to_char(), '↑');
-        assert_eq!(Direction::Down.to_char(), '↓');
-        assert_eq!(Direction::Left.to_char(), '←');
-        assert_eq!(Direction::Right.to_char(), '→');
-        assert_eq!(Direction::UpRight.to_char(), '↗');
-        assert_eq!(Direction::UpLeft.to_char(), '↖');
-        assert_eq!(Direction::DownRight.to_char(), '↘');
-        assert_eq!(Direction::DownLeft.to_char(), '↙');
+    fn test_block_summary_majority_and_fog() {
+        let mut map = offline_streaming_map();
+        map.chunks.as_mut().unwrap().insert(test_chunk(0, 0, Tile::Floor));
+        map.set(1, 0, Tile::Asteroid);
+        map.set(0, 1, Tile::Asteroid);
+        map.set(3, 3, Tile::Wall);
+        assert_eq!(map.block_summary(0, 0, 2), (false, None), "Unexplored blocks are fog");
+
+        map.explored.reveal_around(2, 2, 10);
+        assert_eq!(map.block_summary(0, 0, 2), (true, Some(Tile::Asteroid)), "Two of four tiles tie; rock wins");
+        assert_eq!(map.block_summary(0, 0, 4), (true, Some(Tile::Floor)), "Floor is the majority of the 4x4 block");
+        assert_eq!(map.block_summary(2, 2, 2), (true, Some(Tile::Floor)));
     }
 
     #[test]
-    fn test_direction_name() {
-        assert_eq!(Direction::Up.name(), "N");
-        assert_eq!(Direction::UpRight.name(), "NE");
-        assert_eq!(Direction::Right.name(), "E");
-        assert_eq!(Direction::DownRight.name(), "SE");
-        assert_eq!(Direction::Down.name(), "S");
-        assert_eq!(Direction::DownLeft.name(), "SW");
-        assert_eq!(Direction::Left.name(), "W");
-        assert_eq!(Direction::UpLeft.name(), "NW");
+    fn test_zoom_steps_through_levels() {
+        let mut renderer = Renderer::new(false);
+        assert!(!renderer.step_zoom(false), "Already fully zoomed in");
+        assert!(renderer.step_zoom(true));
+        assert!(renderer.step_zoom(true));
+        assert_eq!(renderer.zoom, 4);
+        assert!(!renderer.step_zoom(true), "Already fully zoomed out");
+        assert!(renderer.step_zoom(false));
+        assert_eq!(renderer.zoom, 2);
     }
 
-    // ==================== Hash Tests ====================
+    #[test]
+    fn test_zoomed_out_ships_are_single_glyphs() {
+        let mut renderer = Renderer::new(false);
+        renderer.zoom = 4;
+        let others = [PlayerInfo { id: 1, name: "Ace".to_string(), x: -1, y: 9, direction: Direction::DownLeft, ship: ShipLivery::default() }];
+        let cells = renderer.remote_ship_cells(others.iter());
+        assert_eq!(cells.len(), 1, "No sprite, just a glyph");
+        let cell = cells[&(-1, 2)];
+        assert_eq!((cell.ch, cell.fg), (Direction::DownLeft.to_char(), ShipPalette::remote().hull));
+    }
 
     #[test]
-    fn test_hash_position_deterministic() {
-        // Same inputs should always produce same output
-        let hash1 = hash_position(10, 20, 42);
-        let hash2 = hash_position(10, 20, 42);
-        assert_eq!(hash1, hash2, "hash_position should be deterministic");
+    fn test_minimap_summarize_priority_and_fog() {
+        let mut map = offline_streaming_map();
+        let cache = map.chunks.as_mut().unwrap();
+        cache.insert(test_chunk(0, 0, Tile::Wall));
+        cache.insert(test_chunk(1, 0, Tile::Floor));
+        let view = MinimapView::new(Some((0, 0, 2 * CHUNK_SIZE, CHUNK_SIZE)), (0, 0), 2, 1);
+
+        assert_eq!(view.summarize(&map, 0, 0), None, "Unexplored cells stay blank");
+
+        map.explored.reveal_around(CHUNK_SIZE / 2, CHUNK_SIZE / 2, 30);
+        map.explored.reveal_around(CHUNK_SIZE + CHUNK_SIZE / 2, CHUNK_SIZE / 2, 30);
+        assert_eq!(view.summarize(&map, 0, 0), Some(Tile::Wall));
+        assert_eq!(view.summarize(&map, 1, 0), Some(Tile::Floor));
+
+        map.chunks.as_mut().unwrap().insert(test_chunk(1, 0, Tile::Nebula));
+        assert_eq!(view.summarize(&map, 1, 0), Some(Tile::Nebula), "Nebulae stand out over open space");
+        map.chunks.as_mut().unwrap().insert(test_chunk(0, 0, Tile::Asteroid));
+        assert_eq!(view.summarize(&map, 0, 0), Some(Tile::Asteroid));
     }
 
     #[test]
-    fn test_hash_position_different_inputs() {
-        // Different inputs should produce different outputs
-        let hash1 = hash_position(10, 20, 42);
-        let hash2 = hash_position(11, 20, 42);
-        let hash3 = hash_position(10, 21, 42);
-        let hash4 = hash_position(10, 20, 43);
+    fn test_map_extent() {
+        let local = Map::generate_local(100, 50, WORLD_SEED);
+        assert_eq!(local.extent(), Some((0, 0, 100, 50)), "Local map shows whole");
 
-        assert_ne!(hash1, hash2, "Different x should produce different hash");
-        assert_ne!(hash1, hash3, "Different y should produce different hash");
-        assert_ne!(hash1, hash4, "Different seed should produce different hash");
+        let mut streamed = offline_streaming_map();
+        assert_eq!(streamed.extent(), None, "Nothing explored yet");
+        streamed.explored.mark(1, 1);
+        assert_eq!(streamed.extent(), Some((0, 0, CHUNK_SIZE, CHUNK_SIZE)), "Streamed map shows explored part");
     }
 
-    // ==================== Map Tests ====================
+    // ==================== ChatMessage Tests ====================
 
     #[test]
-    fn test_map_dimensions() {
-        let map = Map::generate_local(100, 50);
-        assert_eq!(map.width, 100);
-        assert_eq!(map.height, 50);
-        assert_eq!(map.tiles.len(), 50); // height rows
-        assert_eq!(map.tiles[0].len(), 100); // width columns
+    fn test_chat_message_new() {
+        let msg = ChatMessage::new(ChatChannel::System, "Hello".to_string(), 0xFF0000);
+        assert_eq!(msg.channel, ChatChannel::System);
+        assert_eq!(msg.text, "Hello");
+        assert_eq!(msg.color, 0xFF0000);
     }
 
     #[test]
-    fn test_map_has_walls_and_floors() {
-        let map = Map::generate_local(100, 50);
-
-        let has_walls = map.tiles.iter().flatten().any(|t| *t == Tile::Wall);
-        let has_floors = map.tiles.iter().flatten().any(|t| *t == Tile::Floor);
+    fn test_chat_message_system() {
+        let msg = ChatMessage::system("System message");
+        assert_eq!(msg.text, "System message");
+        assert_eq!(msg.color, 0xFFFF00); // Yellow
+    }
 
-        assert!(has_walls, "Map should contain walls");
-        assert!(has_floors, "Map should contain floors");
+    #[test]
+    fn test_chat_message_user() {
+        let msg = ChatMessage::user("User input");
+        assert_eq!(msg.text, "User input");
+        assert_eq!(msg.color, 0x00FF00); // Green
     }
 
     #[test]
-    fn test_map_border_is_walls() {
-        let map = Map::generate_local(100, 50);
+    fn test_chat_message_error() {
+        let msg = ChatMessage::error("Error!");
+        assert_eq!(msg.text, "Error!");
+        assert_eq!(msg.color, 0xFF4444); // Red
+    }
 
-        // Check top and bottom borders
-        for x in 0..100 {
-            assert_eq!(map.get(x, 0), Some(Tile::Wall), "Top border should be wall at x={}", x);
-            assert_eq!(map.get(x, 49), Some(Tile::Wall), "Bottom border should be wall at x={}", x);
+    #[test]
+    fn test_chat_message_from_player() {
+        let msg = ChatMessage::from_player(7, "Ace", "hi");
+        assert_eq!(msg.text, "Ace: hi");
+        assert_eq!(msg.color, ChatMessage::from_player(7, "Other", "x").color, "Color depends only on id");
+        assert_ne!(msg.color, ChatMessage::from_player(8, "Ace", "hi").color, "Neighbouring ids get different colors");
+        for color in PLAYER_CHAT_COLORS {
+            assert!(![0xFFFF00, 0x00FF00, 0xFF4444].contains(&color), "Player colors must not clash with system colors");
         }
+    }
 
-        // Check left and right borders
-        for y in 0..50 {
-            assert_eq!(map.get(0, y), Some(Tile::Wall), "Left border should be wall at y={}", y);
-            assert_eq!(map.get(99, y), Some(Tile::Wall), "Right border should be wall at y={}", y);
+    #[test]
+    fn test_chat_message_utility() {
+        let kinds = [UtilityKind::Roll, UtilityKind::Flip, UtilityKind::Time, UtilityKind::Distance];
+        let colors: Vec<u32> = kinds.iter().map(|&k| ChatMessage::utility(k, "x").color).collect();
+        assert_eq!(ChatMessage::utility(UtilityKind::Roll, "[roll] Ace rolled 1d6: 4").text, "[roll] Ace rolled 1d6: 4");
+        for (i, color) in colors.iter().enumerate() {
+            assert!(!colors[i + 1..].contains(color), "Each utility kind gets its own color");
+            assert!(![0xFFFF00, 0x00FF00, 0xFF4444].contains(color), "Utility colors must not clash with system colors");
+            assert!(!PLAYER_CHAT_COLORS.contains(color), "Utility colors must not look like player chat");
         }
     }
 
     #[test]
-    fn test_map_get_out_of_bounds() {
-        let map = Map::generate_local(100, 50);
-
-        assert_eq!(map.get(-1, 0), None);
-        assert_eq!(map.get(0, -1), None);
-        assert_eq!(map.get(100, 0), None);
-        assert_eq!(map.get(0, 50), None);
+    fn test_chat_message_ambient() {
+        let msg = ChatMessage::ambient("Haven Traffic Control", "Lane two is clear");
+        assert_eq!(msg.text, "[Haven Traffic Control] Lane two is clear");
+        assert!(!PLAYER_CHAT_COLORS.contains(&msg.color), "Ambient chatter must not look like a player");
+        assert_ne!(msg.color, ChatMessage::system("x").color);
     }
 
+    // ==================== ChatWindow Tests ====================
+
     #[test]
-    fn test_map_is_passable() {
-        let map = Map::generate_local(100, 50);
+    fn test_chat_window_default() {
+        let chat = ChatWindow::default();
+        assert!(!chat.active);
+        assert!(chat.input.is_empty());
+        assert_eq!(chat.cursor, 0);
+        assert!(chat.messages.is_empty());
+    }
 
-        // Border should not be passable
-        assert!(!map.is_passable(0, 0));
-        assert!(!map.is_passable(-1, 0));
+    #[test]
+    fn test_chat_window_new_has_welcome_message() {
+        let chat = ChatWindow::new();
+        assert_eq!(chat.messages.len(), 1);
+        assert!(chat.messages[0].text.contains("Welcome"));
+    }
 
-        // Find a floor tile and check it's passable
-        let start = map.find_start_position();
-        assert!(map.is_passable(start.0, start.1), "Start position should be passable");
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(parse_hex_color("#80A090"), Some(0x80A090));
+        assert_eq!(parse_hex_color("ffffff"), Some(0xFFFFFF));
+        assert_eq!(parse_hex_color("#FFF"), None, "Short form isn't supported");
+        assert_eq!(parse_hex_color("#GG0000"), None);
     }
 
     #[test]
-    fn test_map_find_start_position_is_passable() {
-        let map = Map::generate_local(100, 50);
-        let (x, y) = map.find_start_position();
+    fn test_chat_window_channel_colors_from_config() {
+        let config = Config {
+            chat_colors: HashMap::from([
+                (ChatChannel::Ambient, "#112233".to_string()),
+                (ChatChannel::Error, "red".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let mut chat = ChatWindow::default();
+        chat.apply_config(&config);
 
-        assert!(map.is_passable(x, y), "Start position must be passable");
-        assert!(x > 0 && x < 100, "Start x should be within bounds");
-        assert!(y > 0 && y < 50, "Start y should be within bounds");
+        assert_eq!(chat.color_of(&ChatMessage::ambient("Relay", "hi")), 0x112233);
+        assert_eq!(chat.color_of(&ChatMessage::system("hi")), 0xFFFF00, "Unconfigured channels keep their colors");
+        assert_eq!(chat.color_of(&ChatMessage::error("x")), 0xFF4444, "Bad colors are ignored");
+        assert!(chat.messages.iter().any(|m| m.text.contains("Bad chat color")), "Bad colors are reported");
     }
 
-    // ==================== Player Tests ====================
-
     #[test]
-    fn test_player_new() {
-        let player = Player::new(10, 20);
-        assert_eq!(player.x, 10);
-        assert_eq!(player.y, 20);
-        assert_eq!(player.direction, Direction::Up);
+    fn test_chat_window_muted_channels_hidden_and_counted() {
+        let config = Config { muted_channels: vec![ChatChannel::Ambient], ..Default::default() };
+        let mut chat = ChatWindow::default();
+        chat.apply_config(&config);
+
+        chat.add_message(ChatMessage::system("one"));
+        chat.add_message(ChatMessage::from_player(1, "Ace", "two"));
+        for _ in 0..5 {
+            chat.add_message(ChatMessage::ambient("Relay", "noise"));
+        }
+        chat.add_message(ChatMessage::system("three"));
+
+        let shown: Vec<&str> = chat.visible_messages().map(|m| m.text.as_str()).collect();
+        assert_eq!(shown, vec!["one", "Ace: two", "three"], "Muted lines don't push others out of the pane");
+        assert_eq!(chat.muted_count, 5);
+        assert_eq!(chat.messages.len(), 8, "Muted lines are still kept");
     }
 
     #[test]
-    fn test_player_move_updates_direction() {
-        let map = Map::generate_local(100, 50);
-        let start = map.find_start_position();
-        let mut player = Player::new(start.0, start.1);
+    fn test_chat_window_theme_colors() {
+        let config = Config { chat_colors: HashMap::from([(ChatChannel::Error, "#112233".to_string())]), ..Default::default() };
+        let mut chat = ChatWindow::default();
+        chat.apply_config(&config);
+        chat.apply_theme(&Theme::preset("solarized").unwrap());
 
-        // Try to move right (even if blocked, direction should update)
-        player.try_move(1, 0, &map);
-        assert_eq!(player.direction, Direction::Right);
+        assert_eq!(chat.color_of(&ChatMessage::system("hi")), 0xB58900);
+        assert_eq!(chat.color_of(&ChatMessage::error("x")), 0x112233, "Config colors win over the theme");
+        assert_eq!(chat.color_of(&ChatMessage::from_player(1, "Ace", "hi")), ChatMessage::from_player(1, "Ace", "hi").color);
 
-        // Try to move down
-        player.try_move(0, 1, &map);
-        assert_eq!(player.direction, Direction::Down);
+        chat.apply_theme(&Theme::default());
+        assert_eq!(chat.color_of(&ChatMessage::system("hi")), 0xFFFF00);
     }
 
     #[test]
-    fn test_player_no_move_on_zero_delta() {
-        let map = Map::generate_local(100, 50);
-        let start = map.find_start_position();
-        let mut player = Player::new(start.0, start.1);
-        let original_dir = player.direction;
+    fn test_chat_window_open_close() {
+        let mut chat = ChatWindow::default();
 
-        let moved = player.try_move(0, 0, &map);
-        assert!(!moved, "Should not move with zero delta");
-        assert_eq!(player.direction, original_dir, "Direction should not change");
+        chat.open();
+        assert!(chat.active);
+
+        chat.insert_char('h');
+        chat.insert_char('i');
+        assert_eq!(chat.input, "hi");
+
+        chat.close();
+        assert!(!chat.active);
+        assert!(chat.input.is_empty());
+        assert_eq!(chat.cursor, 0);
     }
 
     #[test]
-    fn test_player_collision_with_wall() {
-        let map = Map::generate_local(100, 50);
-        let mut player = Player::new(1, 1); // Near the wall border
+    fn test_chat_window_insert_char() {
+        let mut chat = ChatWindow::default();
+        chat.insert_char('a');
+        chat.insert_char('b');
+        chat.insert_char('c');
 
-        // Try to move into the wall (border is at x=0)
-        let moved = player.try_move(-1, 0, &map);
-        assert!(!moved, "Should not move into wall");
-        assert_eq!(player.x, 1, "X position should not change");
+        assert_eq!(chat.input, "abc");
+        assert_eq!(chat.cursor, 3);
     }
 
-    // ==================== Renderer Tests ====================
+    #[test]
+    fn test_chat_window_backspace() {
+        let mut chat = ChatWindow::default();
+        chat.insert_char('a');
+        chat.insert_char('b');
+        chat.insert_char('c');
+
+        chat.backspace();
+        assert_eq!(chat.input, "ab");
+        assert_eq!(chat.cursor, 2);
+
+        chat.backspace();
+        assert_eq!(chat.input, "a");
+
+        chat.backspace();
+        assert!(chat.input.is_empty());
+
+        // Backspace on empty should do nothing
+        chat.backspace();
+        assert!(chat.input.is_empty());
+    }
 
     #[test]
-    fn test_renderer_new_with_effects_enabled() {
-        let renderer = Renderer::new(true);
-        assert_eq!(renderer.frame, 0);
-        assert!(renderer.effects_enabled);
+    fn test_chat_window_cursor_movement() {
+        let mut chat = ChatWindow::default();
+        chat.insert_char('a');
+        chat.insert_char('b');
+        chat.insert_char('c');
+        assert_eq!(chat.cursor, 3);
+
+        chat.cursor_left();
+        assert_eq!(chat.cursor, 2);
+
+        chat.cursor_left();
+        assert_eq!(chat.cursor, 1);
+
+        chat.cursor_right();
+        assert_eq!(chat.cursor, 2);
+
+        chat.cursor_home();
+        assert_eq!(chat.cursor, 0);
+
+        chat.cursor_end();
+        assert_eq!(chat.cursor, 3);
     }
 
     #[test]
-    fn test_renderer_new_with_effects_disabled() {
-        let renderer = Renderer::new(false);
-        assert_eq!(renderer.frame, 0);
-        assert!(!renderer.effects_enabled);
+    fn test_chat_window_delete() {
+        let mut chat = ChatWindow::default();
+        chat.insert_char('a');
+        chat.insert_char('b');
+        chat.insert_char('c');
+        chat.cursor_home();
+
+        chat.delete();
+        assert_eq!(chat.input, "bc");
+
+        chat.delete();
+        assert_eq!(chat.input, "c");
     }
 
     #[test]
-    fn test_renderer_toggle_effects() {
-        let mut renderer = Renderer::new(true);
-        assert!(renderer.effects_enabled);
+    fn test_chat_window_insert_at_cursor() {
+        let mut chat = ChatWindow::default();
+        chat.insert_char('a');
+        chat.insert_char('c');
+        chat.cursor_left();
+        chat.insert_char('b');
 
-        renderer.toggle_effects();
-        assert!(!renderer.effects_enabled);
+        assert_eq!(chat.input, "abc");
+    }
 
-        renderer.toggle_effects();
-        assert!(renderer.effects_enabled);
+    #[test]
+    fn test_chat_window_submit() {
+        let mut chat = ChatWindow::default();
+        chat.open();
+        chat.insert_char('h');
+        chat.insert_char('i');
+
+        let result = chat.submit();
+        assert_eq!(result, Some("hi".to_string()));
+        assert!(chat.input.is_empty());
+        assert!(!chat.active);
     }
 
     #[test]
-    fn test_renderer_tick() {
-        let mut renderer = Renderer::new(true);
-        assert_eq!(renderer.frame, 0);
+    fn test_chat_window_submit_empty() {
+        let mut chat = ChatWindow::default();
+        chat.open();
 
-        renderer.tick();
-        assert_eq!(renderer.frame, 1);
+        let result = chat.submit();
+        assert!(result.is_none());
+        assert!(!chat.active);
+    }
 
-        renderer.tick();
-        assert_eq!(renderer.frame, 2);
+    #[test]
+    fn test_chat_window_add_message() {
+        let mut chat = ChatWindow::default();
+        chat.add_message(ChatMessage::system("Test 1"));
+        chat.add_message(ChatMessage::system("Test 2"));
+
+        assert_eq!(chat.messages.len(), 2);
+        assert_eq!(chat.messages[0].text, "Test 1");
+        assert_eq!(chat.messages[1].text, "Test 2");
     }
 
     #[test]
-    fn test_renderer_effects_disabled_returns_simple_tiles() {
-        let renderer = Renderer::new(false);
+    fn test_chat_window_max_messages() {
+        let mut chat = ChatWindow { max_messages: 3, ..Default::default() };
 
-        // With effects disabled, floor should return space with black
-        let (ch, color) = renderer.render_tile(Some(Tile::Floor), 0, 0);
-        assert_eq!(ch, ' ');
-        assert_eq!(color, 0x000000);
+        chat.add_message(ChatMessage::system("1"));
+        chat.add_message(ChatMessage::system("2"));
+        chat.add_message(ChatMessage::system("3"));
+        chat.add_message(ChatMessage::system("4"));
 
-        // Wall should return solid block
-        let (ch, _) = renderer.render_tile(Some(Tile::Wall), 0, 0);
-        assert_eq!(ch, '█');
+        assert_eq!(chat.messages.len(), 3);
+        assert_eq!(chat.messages[0].text, "2"); // First message removed
+        assert_eq!(chat.messages[2].text, "4");
     }
 
     #[test]
-    fn test_renderer_render_tile_deterministic() {
-        let renderer = Renderer::new(true);
+    fn test_chat_window_visible_messages() {
+        let mut chat = ChatWindow { visible_lines: 2, ..Default::default() };
 
-        // Same position should give same result
-        let result1 = renderer.render_tile(Some(Tile::Wall), 10, 20);
-        let result2 = renderer.render_tile(Some(Tile::Wall), 10, 20);
-        assert_eq!(result1, result2, "Render should be deterministic for same position");
-    }
+        chat.add_message(ChatMessage::system("1"));
+        chat.add_message(ChatMessage::system("2"));
+        chat.add_message(ChatMessage::system("3"));
 
-    // ==================== InputState Tests ====================
+        let visible: Vec<_> = chat.visible_messages().collect();
+        assert_eq!(visible.len(), 2);
+        assert_eq!(visible[0].text, "2");
+        assert_eq!(visible[1].text, "3");
+    }
 
     #[test]
-    fn test_input_state_default() {
-        let state = InputState::default();
-        assert!(!state.up.held);
-        assert!(!state.down.held);
-        assert!(!state.left.held);
-        assert!(!state.right.held);
-        assert!(!state.has_release_support);
+    fn test_chat_pane_cycles() {
+        assert_eq!(ChatPane::Normal.next(), ChatPane::Expanded);
+        assert_eq!(ChatPane::Expanded.next(), ChatPane::Collapsed);
+        assert_eq!(ChatPane::Collapsed.next(), ChatPane::Normal);
     }
 
     #[test]
-    fn test_input_state_movement_delta_cardinal() {
-        let mut state = InputState::default();
-        state.up.held = true;
-        assert_eq!(state.movement_delta(), (0, -1));
+    fn test_chat_fit_pane_sizes() {
+        let mut chat = ChatWindow::default();
+        chat.apply_config(&Config { chat_lines: Some(4), ..Default::default() });
 
-        state.up.held = false;
-        state.down.held = true;
-        assert_eq!(state.movement_delta(), (0, 1));
+        chat.fit_pane(50);
+        assert_eq!(chat.visible_lines, 4);
+        assert_eq!(chat.height(), 6, "Messages plus input line and status bar");
 
-        state.down.held = false;
-        state.left.held = true;
-        assert_eq!(state.movement_delta(), (-1, 0));
+        chat.cycle_pane();
+        chat.fit_pane(50);
+        assert_eq!(chat.visible_lines, 23, "Expanded takes half the screen");
 
-        state.left.held = false;
-        state.right.held = true;
-        assert_eq!(state.movement_delta(), (1, 0));
+        chat.cycle_pane();
+        chat.fit_pane(50);
+        assert_eq!(chat.visible_lines, 0, "Collapsed shows only the input line");
+        assert_eq!(chat.height(), 2);
     }
 
     #[test]
-    fn test_input_state_movement_delta_diagonal() {
-        let mut state = InputState::default();
+    fn test_chat_fit_pane_keeps_game_visible() {
+        let mut chat = ChatWindow::default();
+        chat.apply_config(&Config { chat_lines: Some(MAX_CHAT_LINES), ..Default::default() });
+        chat.fit_pane(20);
+        assert_eq!(chat.height(), 20 - MIN_GAME_ROWS);
+        chat.fit_pane(5);
+        assert_eq!(chat.visible_lines, 0, "Tiny terminals still get the input line");
+    }
 
-        state.up.held = true;
-        state.right.held = true;
-        assert_eq!(state.movement_delta(), (1, -1));
+    fn chat_with_input(text: &str) -> ChatWindow {
+        let mut chat = ChatWindow::default();
+        for ch in text.chars() {
+            chat.insert_char(ch);
+        }
+        chat
+    }
 
-        state.right.held = false;
-        state.left.held = true;
-        assert_eq!(state.movement_delta(), (-1, -1));
+    #[test]
+    fn test_chat_window_word_movement() {
+        let mut chat = chat_with_input("/goto  10 20");
+
+        chat.word_left();
+        assert_eq!(chat.cursor, 10, "Should jump to start of '20'");
+        chat.word_left();
+        assert_eq!(chat.cursor, 7, "Should skip spaces to start of '10'");
+        chat.word_left();
+        chat.word_left();
+        assert_eq!(chat.cursor, 0, "Should stop at start of line");
+
+        chat.word_right();
+        assert_eq!(chat.cursor, 5, "Should jump to end of '/goto'");
+        chat.word_right();
+        assert_eq!(chat.cursor, 9, "Should skip spaces to end of '10'");
+        chat.cursor_end();
+        chat.word_right();
+        assert_eq!(chat.cursor, chat.input.len(), "Should stop at end of line");
     }
 
     #[test]
-    fn test_input_state_any_movement() {
-        let mut state = InputState::default();
-        assert!(!state.any_movement());
+    fn test_chat_window_kill_to_end() {
+        let mut chat = chat_with_input("/goto 10 20");
+        chat.word_left();
+        chat.kill_to_end();
+        assert_eq!(chat.input, "/goto 10 ");
+        assert_eq!(chat.cursor, 9);
 
-        state.up.held = true;
-        assert!(state.any_movement());
+        // Nothing after cursor - no-op
+        chat.kill_to_end();
+        assert_eq!(chat.input, "/goto 10 ");
     }
 
-    // ==================== ShipCell Tests ====================
-
     #[test]
-    fn test_ship_cell_new() {
-        let cell = ShipCell::new('^', 0xFF0000);
-        assert_eq!(cell.ch, '^');
-        assert_eq!(cell.fg, 0xFF0000);
-        assert!(cell.bg.is_none());
+    fn test_chat_window_delete_word_before() {
+        let mut chat = chat_with_input("hello big world");
+        chat.delete_word_before();
+        assert_eq!(chat.input, "hello big ");
+
+        // Trailing spaces are removed along with the word
+        chat.delete_word_before();
+        assert_eq!(chat.input, "hello ");
+
+        chat.word_left();
+        chat.delete_word_before();
+        assert_eq!(chat.input, "hello ", "Nothing before cursor to delete");
     }
 
     #[test]
-    fn test_ship_cell_with_bg() {
-        let cell = ShipCell::with_bg('X', 0xFF0000, 0x00FF00);
-        assert_eq!(cell.ch, 'X');
-        assert_eq!(cell.fg, 0xFF0000);
-        assert_eq!(cell.bg, Some(0x00FF00));
+    fn test_chat_window_undo() {
+        let mut chat = chat_with_input("hello world");
+
+        chat.delete_word_before();
+        assert_eq!(chat.input, "hello ");
+        chat.undo();
+        assert_eq!(chat.input, "hello world");
+        assert_eq!(chat.cursor, 11, "Undo restores the cursor too");
+
+        chat.cursor_home();
+        chat.kill_to_end();
+        assert!(chat.input.is_empty());
+        chat.undo();
+        assert_eq!(chat.input, "hello world");
     }
 
     #[test]
-    fn test_ship_cell_empty() {
-        let cell = ShipCell::empty();
-        assert_eq!(cell.ch, ' ');
-        assert_eq!(cell.fg, 0x000000);
-        assert!(cell.bg.is_none());
-    }
+    fn test_chat_window_undo_groups_typing_by_word() {
+        let mut chat = chat_with_input("hello world");
 
-    // ==================== ShipSprite Tests ====================
+        chat.undo();
+        assert_eq!(chat.input, "hello", "First undo removes the last typed word");
+        chat.undo();
+        assert_eq!(chat.input, "", "Second undo removes the first word");
+        chat.undo();
+        assert_eq!(chat.input, "", "Undo with empty history is a no-op");
+    }
 
     #[test]
-    fn test_ship_sprite_all_directions() {
-        // Verify ship sprites exist for all 8 directions
-        let directions = [
-            Direction::Up, Direction::Down, Direction::Left, Direction::Right,
-            Direction::UpRight, Direction::UpLeft, Direction::DownRight, Direction::DownLeft,
-        ];
+    fn test_chat_window_undo_history_cleared_on_close() {
+        let mut chat = chat_with_input("abc");
+        chat.close();
+        assert!(chat.undo_stack.is_empty());
 
-        for dir in directions {
-            let sprite = ShipSprite::for_direction(dir);
-            // Ship should be 3x3
-            assert_eq!(sprite.cells.len(), 3);
-            for row in &sprite.cells {
-                assert_eq!(row.len(), 3);
-            }
-        }
+        let mut chat = chat_with_input("abc");
+        chat.submit();
+        chat.undo();
+        assert!(chat.input.is_empty(), "Submitted text can't be undone back into the input");
     }
 
     #[test]
-    fn test_ship_sprite_center_not_empty() {
-        // Center of ship should never be empty for any direction
-        let directions = [
-            Direction::Up, Direction::Down, Direction::Left, Direction::Right,
-            Direction::UpRight, Direction::UpLeft, Direction::DownRight, Direction::DownLeft,
-        ];
-
-        for dir in directions {
-            let sprite = ShipSprite::for_direction(dir);
-            let center = sprite.cells[1][1];
-            assert_ne!(center.ch, ' ', "Center of ship should not be empty for {:?}", dir);
+    fn test_chat_window_history_recall() {
+        let mut chat = ChatWindow::default();
+        for line in ["/pos", "hello"] {
+            chat.open();
+            for ch in line.chars() {
+                chat.insert_char(ch);
+            }
+            chat.submit();
         }
+
+        let chat_typing = |chat: &mut ChatWindow| {
+            chat.open();
+            chat.insert_char('h');
+            chat.insert_char('i');
+        };
+        chat_typing(&mut chat);
+        chat.history_up();
+        assert_eq!((chat.input.as_str(), chat.cursor), ("hello", 5), "Up recalls the last line, cursor at the end");
+        chat.history_up();
+        chat.history_up();
+        assert_eq!(chat.input, "/pos", "Up stops at the oldest line");
+        chat.history_down();
+        chat.history_down();
+        assert_eq!(chat.input, "hi", "Down past the newest brings back what was typed");
+        chat.history_up();
+        chat.undo();
+        assert_eq!(chat.input, "hi", "A recall can be undone");
+
+        chat.history_up();
+        chat.close();
+        chat_typing(&mut chat);
+        chat.history_down();
+        assert_eq!(chat.input, "hi", "Closing the chat ends browsing");
     }
 
     #[test]
-    fn test_ship_sprite_has_cockpit() {
-        // Each ship direction should have at least one cockpit-colored cell
-        let cockpit_color = 0x80FFFF;
-        let directions = [
-            Direction::Up, Direction::Down, Direction::Left, Direction::Right,
-            Direction::UpRight, Direction::UpLeft, Direction::DownRight, Direction::DownLeft,
-        ];
+    fn test_chat_window_tab_completion() {
+        let mut chat = chat_with_input("/tar 5");
+        chat.word_left();
+        chat.cursor_left();
+        chat.complete();
+        assert_eq!(chat.input, "/target  5", "Completes the word before the cursor, keeping the rest");
+        assert_eq!(chat.cursor, 8);
 
-        for dir in directions {
-            let sprite = ShipSprite::for_direction(dir);
-            let has_cockpit = sprite.cells.iter()
-                .flatten()
-                .any(|cell| cell.fg == cockpit_color);
-            assert!(has_cockpit, "Ship should have cockpit for {:?}", dir);
-        }
+        let mut chat = chat_with_input("hello");
+        chat.complete();
+        assert_eq!(chat.input, "hello", "Plain chat isn't completed");
     }
 
-    // ==================== ExhaustSprite Tests ====================
+    // ==================== Command Completion Tests ====================
 
     #[test]
-    fn test_exhaust_sprite_all_directions() {
-        // Verify exhaust sprites exist for all 8 directions
-        let directions = [
-            Direction::Up, Direction::Down, Direction::Left, Direction::Right,
-            Direction::UpRight, Direction::UpLeft, Direction::DownRight, Direction::DownLeft,
-        ];
-
-        for dir in directions {
-            let sprite = ExhaustSprite::for_direction(dir, 0);
-            // Exhaust should be 4x3 (4 rows, 3 columns)
-            assert_eq!(sprite.cells.len(), 4);
-            for row in &sprite.cells {
-                assert_eq!(row.len(), 3);
-            }
-        }
+    fn test_command_completion() {
+        let commands = CommandRegistry::builtin();
+        assert_eq!(commands.complete("/he").as_deref(), Some("/help "));
+        assert_eq!(commands.complete("/GOT").as_deref(), Some("/goto"), "goto and goto-mark agree that far");
+        assert_eq!(commands.complete("/goto-").as_deref(), Some("/goto-mark "));
+        assert_eq!(commands.complete("/s").as_deref(), None, "stats, settings, ship... share only 's'");
+        assert_eq!(commands.complete("/w").as_deref(), Some("/wa"), "Ambiguous names extend as far as they agree");
+        assert_eq!(commands.complete("/de").as_deref(), Some("/debug "));
+        assert_eq!(commands.complete("/see").as_deref(), Some("/seed "));
+        assert_eq!(commands.complete("/").as_deref(), None);
+        assert_eq!(commands.complete("/xyz").as_deref(), None);
+
+        assert_eq!(commands.complete("/ship fr").as_deref(), Some("/ship freighter "));
+        assert_eq!(commands.complete("/diff h").as_deref(), Some("/diff hard "), "Aliases complete arguments too");
+        assert_eq!(commands.complete("/goto l").as_deref(), Some("/goto last "));
+        assert_eq!(commands.complete("/bind toggle_m").as_deref(), Some("/bind toggle_mi"), "toggle_minimap and toggle_missions");
+        assert_eq!(commands.complete("/bind toggle_min").as_deref(), Some("/bind toggle_minimap "));
+        assert_eq!(commands.complete("/bind toggle_minimap ta").as_deref(), None, "Keys aren't a fixed list");
+        assert_eq!(commands.complete("/goto 1").as_deref(), None);
     }
 
     #[test]
-    fn test_exhaust_sprite_animation() {
-        // Exhaust should change over frames
-        let sprite1 = ExhaustSprite::for_direction(Direction::Up, 0);
-        let sprite2 = ExhaustSprite::for_direction(Direction::Up, 4);
-        let sprite3 = ExhaustSprite::for_direction(Direction::Up, 8);
+    fn test_command_hint() {
+        let commands = CommandRegistry::builtin();
+        assert_eq!(commands.hint("/sh").as_deref(), Some("/ship"));
+        assert_eq!(commands.hint("/fl").as_deref(), Some("/flip"));
+        assert_eq!(commands.hint("/ta").as_deref(), Some("/target"));
+        assert_eq!(commands.hint("/s").as_deref(), Some("/spectate /say /stats /settings /sethome /ship /savemap /seed /screenshot"));
+        assert_eq!(commands.hint("/target 5").as_deref(), Some("/target X Y | /target off"), "Usage once the command is known");
+        assert_eq!(commands.hint("/tp").as_deref(), Some("/goto X Y | /goto last"));
+        assert_eq!(commands.hint("/nope 1"), None);
+        assert_eq!(commands.hint("hello"), None);
+        assert_eq!(commands.hint("/"), None);
+    }
 
-        // At least one cell should be different between frames
-        let cells_match = |s1: &ExhaustSprite, s2: &ExhaustSprite| {
-            s1.cells.iter().flatten().zip(s2.cells.iter().flatten())
-                .all(|(c1, c2)| c1.ch == c2.ch && c1.fg == c2.fg)
-        };
+    // ==================== ChatCommand Tests ====================
 
-        // Animation cycles, so frames 0 and 16 should match (4 phases * 4 frames each)
-        assert!(!cells_match(&sprite1, &sprite2) || !cells_match(&sprite2, &sprite3),
-            "Exhaust should animate");
+    #[test]
+    fn test_chat_process_help_command() {
+        let mut chat = ChatWindow::default();
+        let cmd = chat.process_input("/help");
+        assert!(cmd.is_none()); // Help just adds messages
+        assert!(!chat.messages.is_empty());
     }
 
     #[test]
-    fn test_exhaust_offset_opposite_to_direction() {
-        // Exhaust should appear behind the ship (opposite to movement direction)
-        let (_x, y) = ExhaustSprite::offset_for_direction(Direction::Up);
-        assert!(y > 0, "Up-facing ship exhaust should be below (positive y)");
-
-        let (_x, y) = ExhaustSprite::offset_for_direction(Direction::Down);
-        assert!(y < 0, "Down-facing ship exhaust should be above (negative y)");
-
-        let (x, _y) = ExhaustSprite::offset_for_direction(Direction::Left);
-        assert!(x > 0, "Left-facing ship exhaust should be to right (positive x)");
+    fn test_chat_process_log_and_clear() {
+        let mut chat = ChatWindow { muted: vec![ChatChannel::Ambient], ..Default::default() };
+        chat.add_message(ChatMessage::ambient("Relay", "static"));
+        assert_eq!(chat.process_input("/log"), None);
+        assert!(chat.log.is_open(), "/log opens the chat log");
 
-        let (x, _y) = ExhaustSprite::offset_for_direction(Direction::Right);
-        assert!(x < 0, "Right-facing ship exhaust should be to left (negative x)");
+        assert_eq!(chat.process_input("/cls"), None);
+        assert!(chat.messages.is_empty());
+        assert_eq!(chat.muted_count, 0, "Nothing muted is left waiting");
     }
 
-    // ==================== Renderer Ship Cell Tests ====================
-
     #[test]
-    fn test_renderer_get_ship_cell_center() {
-        let renderer = Renderer::new(true);
-
-        // Center of ship (offset 0,0) should return a cell
-        let cell = renderer.get_ship_cell(Direction::Up, 0, 0);
-        assert!(cell.is_some(), "Ship center should exist");
+    fn test_chat_process_quit_command() {
+        let mut chat = ChatWindow::default();
+        let cmd = chat.process_input("/quit");
+        assert_eq!(cmd, Some(ChatCommand::Quit));
     }
 
     #[test]
-    fn test_renderer_get_ship_cell_bounds() {
-        let renderer = Renderer::new(true);
+    fn test_chat_process_pos_command() {
+        let mut chat = ChatWindow::default();
+        let cmd = chat.process_input("/pos");
+        assert_eq!(cmd, Some(ChatCommand::ShowPosition));
+    }
 
-        // Ship cells at corners of 3x3 grid
-        let corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
-        for (ox, oy) in corners {
-            // At least some corners should have content (not all are empty)
-            let _ = renderer.get_ship_cell(Direction::Up, ox, oy);
-        }
+    #[test]
+    fn test_chat_process_goto_command() {
+        let mut chat = ChatWindow::default();
+        let cmd = chat.process_input("/goto 100 200");
+        assert_eq!(cmd, Some(ChatCommand::Teleport(100, 200)));
+    }
 
-        // Far outside ship should return None (unless it's exhaust)
-        let cell = renderer.get_ship_cell(Direction::Up, 10, 10);
-        assert!(cell.is_none(), "Far from ship should be None");
+    #[test]
+    fn test_chat_process_goto_invalid() {
+        let mut chat = ChatWindow::default();
+        let cmd = chat.process_input("/goto");
+        assert!(cmd.is_none());
+        // Should have added an error message
+        assert!(chat.messages.iter().any(|m| m.text.contains("Usage")));
     }
 
     #[test]
-    fn test_renderer_get_ship_cell_exhaust_area() {
-        let renderer = Renderer::new(true);
+    fn test_chat_process_fx_command() {
+        let mut chat = ChatWindow::default();
+        let cmd = chat.process_input("/fx");
+        assert_eq!(cmd, Some(ChatCommand::ToggleEffects));
+    }
 
-        // For Up-facing ship, exhaust should be below (positive y offset)
-        let (ex, ey) = ExhaustSprite::offset_for_direction(Direction::Up);
-        // Check a cell in the exhaust area
-        let cell = renderer.get_ship_cell(Direction::Up, ex + 1, ey);
-        assert!(cell.is_some(), "Exhaust area should have content");
+    #[test]
+    fn test_chat_process_target_command() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/target 5 -7"), Some(ChatCommand::SetTarget(5, -7)));
+        assert_eq!(chat.process_input("/target off"), Some(ChatCommand::ClearTarget));
     }
 
     #[test]
-    fn test_renderer_get_ship_cell_all_directions() {
-        let renderer = Renderer::new(true);
-        let directions = [
-            Direction::Up, Direction::Down, Direction::Left, Direction::Right,
-            Direction::UpRight, Direction::UpLeft, Direction::DownRight, Direction::DownLeft,
-        ];
+    fn test_chat_process_target_invalid() {
+        let mut chat = ChatWindow::default();
+        assert!(chat.process_input("/target 5").is_none());
+        assert!(chat.messages.iter().any(|m| m.text.contains("Usage: /target")));
+    }
 
-        for dir in directions {
-            // Every direction should have a ship center
-            let cell = renderer.get_ship_cell(dir, 0, 0);
-            assert!(cell.is_some(), "Ship center should exist for {:?}", dir);
-        }
+    #[test]
+    fn test_chat_process_unknown_command() {
+        let mut chat = ChatWindow::default();
+        let cmd = chat.process_input("/unknowncmd");
+        assert!(cmd.is_none());
+        assert!(chat.messages.iter().any(|m| m.text.contains("Unknown command")));
     }
 
-    // ==================== Config Tests ====================
-
     #[test]
-    fn test_config_default() {
-        let config = Config::default();
-        assert!(!config.effects_enabled, "Effects should be disabled by default");
-        assert!(config.server_url.is_none(), "Server URL should be None by default");
+    fn test_chat_process_aliases() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/TP 1 2"), Some(ChatCommand::Teleport(1, 2)), "Commands are case-insensitive");
+        assert_eq!(chat.process_input("/watch clear"), Some(ChatCommand::ClearTarget));
+        assert_eq!(chat.process_input("/exit"), Some(ChatCommand::Quit));
     }
 
     #[test]
-    fn test_config_server_url_default() {
-        let config = Config::default();
-        assert_eq!(config.server_url(), SERVER_URL);
+    fn test_chat_process_unknown_command_suggests() {
+        let mut chat = ChatWindow::default();
+        assert!(chat.process_input("/gto 1 2").is_none());
+        let last = &chat.messages.last().unwrap().text;
+        assert!(last.contains("Did you mean /goto?"), "Got: {}", last);
+        assert!(last.contains("/goto X Y"), "Suggestion should include usage");
     }
 
     #[test]
-    fn test_config_server_url_override() {
-        let config = Config {
-            effects_enabled: false,
-            server_url: Some("http://custom:8080".to_string()),
-        };
-        assert_eq!(config.server_url(), "http://custom:8080");
+    fn test_chat_process_unknown_command_no_suggestion() {
+        let mut chat = ChatWindow::default();
+        chat.process_input("/xyzzy");
+        assert!(!chat.messages.iter().any(|m| m.text.contains("Did you mean")));
     }
 
     #[test]
-    fn test_config_serialization() {
-        let config = Config {
-            effects_enabled: true,
-            server_url: Some("http://test:3000".to_string()),
-        };
+    fn test_chat_process_bad_argument_highlighted() {
+        let mut chat = ChatWindow::default();
+        assert!(chat.process_input("/goto 10 abc").is_none());
 
-        let json = serde_json::to_string(&config).unwrap();
-        let parsed: Config = serde_json::from_str(&json).unwrap();
+        let lines: Vec<&str> = chat.messages.iter().rev().take(2).map(|m| m.text.as_str()).collect();
+        let (marker, echoed) = (lines[0], lines[1]);
+        assert_eq!(echoed, "  /goto 10 abc");
+        assert!(marker.contains("Y must be a number"));
+        // The carets sit exactly under the bad argument
+        assert_eq!(marker.find('^'), echoed.find("abc"));
+        assert!(marker.contains("^^^ "));
 
-        assert_eq!(parsed.effects_enabled, config.effects_enabled);
-        assert_eq!(parsed.server_url, config.server_url);
+        // Counted in characters, so a name with accents doesn't push them along
+        chat.process_input("/mark Zoë extra");
+        let lines: Vec<&str> = chat.messages.iter().rev().take(2).map(|m| m.text.as_str()).collect();
+        let (marker, echoed) = (lines[0], lines[1]);
+        let extra = echoed[..echoed.find("extra").unwrap()].chars().count();
+        assert_eq!(marker.chars().position(|c| c == '^'), Some(extra));
+        assert!(marker.contains("^^^^^ unexpected argument"));
     }
 
     #[test]
-    fn test_config_path_returns_some() {
-        // Config path should work on most systems
-        let path = Config::config_path();
-        // We can't guarantee it's Some on all systems, but if it is, check structure
-        if let Some(p) = path {
-            assert!(p.ends_with("config.json"));
-            assert!(p.to_string_lossy().contains("exospace"));
-        }
+    fn test_chat_process_missing_argument_highlighted() {
+        let mut chat = ChatWindow::default();
+        chat.process_input("/target 5");
+        let marker = &chat.messages.last().unwrap().text;
+        assert!(marker.contains("missing Y"), "Should report the form that got furthest: {}", marker);
+        assert_eq!(marker.find('^'), Some("  /target 5 ".len()));
     }
 
-    // ==================== ChatMessage Tests ====================
-
     #[test]
-    fn test_chat_message_new() {
-        let msg = ChatMessage::new("Hello".to_string(), 0xFF0000);
-        assert_eq!(msg.text, "Hello");
-        assert_eq!(msg.color, 0xFF0000);
+    fn test_chat_process_roll_command() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/roll 2d6"), Some(ChatCommand::Roll(2, 6)));
+        assert_eq!(chat.process_input("/roll D20"), Some(ChatCommand::Roll(1, 20)));
+        assert_eq!(chat.process_input("/roll"), Some(ChatCommand::Roll(1, 6)), "Bare /roll is 1d6");
+        assert!(chat.process_input("/roll 99d6").is_none());
+        assert!(chat.messages.last().unwrap().text.contains("dice must look like 2d6"));
     }
 
     #[test]
-    fn test_chat_message_system() {
-        let msg = ChatMessage::system("System message");
-        assert_eq!(msg.text, "System message");
-        assert_eq!(msg.color, 0xFFFF00); // Yellow
+    fn test_chat_process_flip_and_time_commands() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/flip"), Some(ChatCommand::Flip));
+        assert_eq!(chat.process_input("/coin"), Some(ChatCommand::Flip));
+        assert_eq!(chat.process_input("/time"), Some(ChatCommand::ServerTime));
     }
 
     #[test]
-    fn test_chat_message_user() {
-        let msg = ChatMessage::user("User input");
-        assert_eq!(msg.text, "User input");
-        assert_eq!(msg.color, 0x00FF00); // Green
+    fn test_chat_process_mine_command() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/mine"), Some(ChatCommand::Mine));
+        assert_eq!(chat.process_input("/dig"), Some(ChatCommand::Mine));
+        assert_eq!(chat.process_input("/laser"), Some(ChatCommand::FireLaser));
+        assert_eq!(chat.process_input("/cut"), Some(ChatCommand::FireLaser));
+        assert_eq!(chat.process_input("/fire"), Some(ChatCommand::Fire));
+        assert_eq!(chat.process_input("/shoot"), Some(ChatCommand::Fire));
     }
 
     #[test]
-    fn test_chat_message_error() {
-        let msg = ChatMessage::error("Error!");
-        assert_eq!(msg.text, "Error!");
-        assert_eq!(msg.color, 0xFF4444); // Red
+    fn test_chat_process_home_commands() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/sethome"), Some(ChatCommand::SetHome));
+        assert_eq!(chat.process_input("/respawn"), Some(ChatCommand::Respawn));
+        assert_eq!(chat.process_input("/home"), Some(ChatCommand::Respawn));
     }
 
-    // ==================== ChatWindow Tests ====================
-
     #[test]
-    fn test_chat_window_default() {
-        let chat = ChatWindow::default();
-        assert!(!chat.active);
-        assert!(chat.input.is_empty());
-        assert_eq!(chat.cursor, 0);
-        assert!(chat.messages.is_empty());
+    fn test_station_marks_single_out_home() {
+        use exospace_core::{ServerMessage, StationInfo};
+
+        let mut remote = RemotePlayers::default();
+        let station = |name: &str, x| StationInfo { name: name.into(), x, y: 0 };
+        let stations = vec![station("Haven Station", 0), station("Frontier Relay", 160)];
+        remote.apply(ServerMessage::Stations { stations, home: Some("Frontier Relay".into()) });
+        let marks = station_marks(&remote);
+        assert_eq!(marks.get(&(0, 0)), Some(&STATION_STYLE));
+        assert_eq!(marks.get(&(160, 0)), Some(&HOME_STATION_STYLE));
     }
 
     #[test]
-    fn test_chat_window_new_has_welcome_message() {
-        let chat = ChatWindow::new();
-        assert_eq!(chat.messages.len(), 1);
-        assert!(chat.messages[0].text.contains("Welcome"));
+    fn test_chat_process_content_command() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/content"), Some(ChatCommand::ShowContent));
+        assert_eq!(chat.process_input("/packs"), Some(ChatCommand::ShowContent));
     }
 
     #[test]
-    fn test_chat_window_toggle() {
+    fn test_chat_process_map_save_commands() {
         let mut chat = ChatWindow::default();
-        assert!(!chat.active);
-
-        chat.toggle();
-        assert!(chat.active);
-
-        chat.toggle();
-        assert!(!chat.active);
+        assert_eq!(chat.process_input("/savemap belt"), Some(ChatCommand::SaveMap("belt".to_string())));
+        assert_eq!(chat.process_input("/load belt"), Some(ChatCommand::LoadMap("belt".to_string())));
+        assert_eq!(chat.process_input("/loadmap"), None, "A name is required");
     }
 
     #[test]
-    fn test_chat_window_open_close() {
+    fn test_chat_process_community_map_commands() {
         let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/maps"), Some(ChatCommand::CommunityMaps));
+        assert_eq!(chat.process_input("/share belt"), Some(ChatCommand::UploadMap("belt".to_string())));
+        assert_eq!(chat.process_input("/upload"), None, "A name is required");
+    }
 
-        chat.open();
-        assert!(chat.active);
-
-        chat.insert_char('h');
-        chat.insert_char('i');
-        assert_eq!(chat.input, "hi");
-
-        chat.close();
-        assert!(!chat.active);
-        assert!(chat.input.is_empty());
-        assert_eq!(chat.cursor, 0);
+    #[test]
+    fn test_chat_process_seed_commands() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/seed"), Some(ChatCommand::ShowSeed));
+        assert_eq!(chat.process_input("/newmap 18446744073709551615"), Some(ChatCommand::NewMap(Some(u64::MAX))), "Any u64 seed");
+        assert_eq!(chat.process_input("/reseed Daily"), Some(ChatCommand::NewMap(None)));
+        assert_eq!(chat.process_input("/newmap -4"), None);
+        assert!(chat.messages.last().unwrap().text.contains("SEED must be a whole number"));
     }
 
     #[test]
-    fn test_chat_window_insert_char() {
+    fn test_chat_process_debug_log() {
         let mut chat = ChatWindow::default();
-        chat.insert_char('a');
-        chat.insert_char('b');
-        chat.insert_char('c');
+        assert_eq!(chat.process_input("/debug log"), Some(ChatCommand::DebugLog(logging::TAIL_LINES)));
+        assert_eq!(chat.process_input("/debug log 3"), Some(ChatCommand::DebugLog(3)));
+        assert_eq!(chat.process_input("/debug log 5000"), Some(ChatCommand::DebugLog(logging::MAX_TAIL_LINES)), "Capped to what chat can show");
+        assert_eq!(chat.process_input("/debug"), None, "Only the log so far");
+    }
 
-        assert_eq!(chat.input, "abc");
-        assert_eq!(chat.cursor, 3);
+    #[test]
+    fn test_daily_seed_falls_back_to_our_clock() {
+        let today = daily_seed(Some("http://127.0.0.1:9"));
+        assert_eq!(today, daily_seed(None), "No server, same seed worked out locally");
     }
 
     #[test]
-    fn test_chat_window_backspace() {
+    fn test_chat_process_difficulty_command() {
         let mut chat = ChatWindow::default();
-        chat.insert_char('a');
-        chat.insert_char('b');
-        chat.insert_char('c');
-
-        chat.backspace();
-        assert_eq!(chat.input, "ab");
-        assert_eq!(chat.cursor, 2);
+        assert_eq!(chat.process_input("/difficulty Hard"), Some(ChatCommand::SetDifficulty(Difficulty::Hard)));
+        assert_eq!(chat.process_input("/diff"), Some(ChatCommand::ShowDifficulty));
+        assert_eq!(chat.process_input("/difficulty brutal"), None);
+        assert!(chat.messages.last().unwrap().text.contains("easy, normal or hard"));
+    }
 
-        chat.backspace();
-        assert_eq!(chat.input, "a");
+    #[test]
+    fn test_chat_process_ship_command() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/ship Interceptor"), Some(ChatCommand::SetShip(ShipClass::Interceptor)));
+        assert_eq!(chat.process_input("/class"), Some(ChatCommand::ShowShip));
+    }
 
-        chat.backspace();
-        assert!(chat.input.is_empty());
+    #[test]
+    fn test_chat_process_ship_livery_commands() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/ship name Rusty  Bucket"), Some(ChatCommand::NameShip(Some("Rusty Bucket".to_string()))), "Names take the rest of the line");
+        assert_eq!(chat.process_input("/ship name none"), Some(ChatCommand::NameShip(None)));
+        assert_eq!(chat.process_input("/ship callsign ace-1"), Some(ChatCommand::SetCallsign(Some("ace-1".to_string()))));
+        assert_eq!(chat.process_input("/ship color Hull #ff8800"), Some(ChatCommand::PaintShip(ShipPart::Hull, Some(0xFF8800))));
+        assert_eq!(chat.process_input("/ship paint wing off"), Some(ChatCommand::PaintShip(ShipPart::Wing, None)));
 
-        // Backspace on empty should do nothing
-        chat.backspace();
-        assert!(chat.input.is_empty());
+        assert_eq!(chat.process_input("/ship color hull orange"), None, "Colors are hex");
+        assert!(chat.messages.last().unwrap().text.contains("#FF8800"), "Got {}", chat.messages.last().unwrap().text);
+        assert_eq!(chat.process_input("/ship color fins #FFFFFF"), None);
+        assert_eq!(chat.process_input("/ship name"), None);
+        assert!(chat.messages.last().unwrap().text.contains("missing NAME"));
     }
 
     #[test]
-    fn test_chat_window_cursor_movement() {
+    fn test_chat_process_spectate_command() {
         let mut chat = ChatWindow::default();
-        chat.insert_char('a');
-        chat.insert_char('b');
-        chat.insert_char('c');
-        assert_eq!(chat.cursor, 3);
+        assert_eq!(chat.process_input("/spectate"), Some(ChatCommand::ToggleSpectate));
+        assert_eq!(chat.process_input("/freecam"), Some(ChatCommand::ToggleSpectate));
+        assert_eq!(chat.process_input("/spectate off"), Some(ChatCommand::FollowShip));
+        assert_eq!(chat.process_input("/cam now"), None, "Only off follows the ship again");
+    }
 
-        chat.cursor_left();
-        assert_eq!(chat.cursor, 2);
+    #[test]
+    fn test_chat_process_say_and_emote_commands() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/say hi there"), Some(ChatCommand::SayNearby("hi there".to_string(), false)));
+        assert_eq!(chat.process_input("/local hi"), Some(ChatCommand::SayNearby("hi".to_string(), false)));
+        assert_eq!(chat.process_input("/me waves"), Some(ChatCommand::SayNearby("waves".to_string(), true)));
+        assert_eq!(chat.process_input("/emote tips a hat"), Some(ChatCommand::SayNearby("tips a hat".to_string(), true)));
 
-        chat.cursor_left();
-        assert_eq!(chat.cursor, 1);
+        assert_eq!(chat.process_input("/say"), None, "Nothing to say");
+        assert!(chat.messages.last().unwrap().text.contains("missing TEXT"));
+        assert_eq!(ChatMessage::nearby(1, "Ace", "hi", false).text, "Ace (nearby): hi");
+        assert_eq!(ChatMessage::nearby(1, "Ace", "waves", true).text, "* Ace waves");
+    }
 
-        chat.cursor_right();
-        assert_eq!(chat.cursor, 2);
+    #[test]
+    fn test_chat_process_poi_command() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/poi"), Some(ChatCommand::ListPois));
+        assert_eq!(chat.process_input("/places"), Some(ChatCommand::ListPois));
+        assert_eq!(chat.process_input("/poi here"), None, "/poi takes no arguments");
+        assert_eq!(chat.process_input("/ship dreadnought"), None);
+        assert!(chat.messages.last().unwrap().text.contains("scout, freighter or interceptor"));
+    }
 
-        chat.cursor_home();
-        assert_eq!(chat.cursor, 0);
+    #[test]
+    fn test_chat_process_stats_command() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/stats"), Some(ChatCommand::Stats));
+        assert_eq!(chat.process_input("/statistics"), Some(ChatCommand::Stats));
+    }
 
-        chat.cursor_end();
-        assert_eq!(chat.cursor, 3);
+    #[test]
+    fn test_chat_process_theme_command() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/theme High-Contrast"), Some(ChatCommand::SetTheme("high-contrast".to_string())));
+        assert_eq!(chat.process_input("/colors"), Some(ChatCommand::ShowThemes));
+        assert_eq!(chat.process_input("/theme 3"), None, "Theme names aren't numbers");
     }
 
     #[test]
-    fn test_chat_window_delete() {
+    fn test_chat_process_settings_command() {
         let mut chat = ChatWindow::default();
-        chat.insert_char('a');
-        chat.insert_char('b');
-        chat.insert_char('c');
-        chat.cursor_home();
+        assert_eq!(chat.process_input("/settings"), Some(ChatCommand::Settings));
+        assert_eq!(chat.process_input("/options"), Some(ChatCommand::Settings));
+        assert_eq!(chat.process_input("/settings now"), None, "Settings takes no arguments");
+    }
 
-        chat.delete();
-        assert_eq!(chat.input, "bc");
+    #[test]
+    fn test_chat_process_nav_command() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/nav 40 -12"), Some(ChatCommand::Navigate(40, -12)));
+        assert_eq!(chat.process_input("/autopilot off"), Some(ChatCommand::CancelNav));
+        assert_eq!(chat.process_input("/nav 40"), None, "Needs both coordinates");
+    }
 
-        chat.delete();
-        assert_eq!(chat.input, "c");
+    #[test]
+    fn test_chat_process_waypoint_commands() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/mark Home"), Some(ChatCommand::Mark("Home".to_string())));
+        assert_eq!(chat.process_input("/waypoints"), Some(ChatCommand::ListWaypoints));
+        assert_eq!(chat.process_input("/goto-mark home"), Some(ChatCommand::GotoMark("home".to_string())));
+        assert_eq!(chat.process_input("/mark"), None, "Needs a name");
+        assert_eq!(chat.process_input("/goto-mark 12"), None, "Names aren't numbers");
     }
 
     #[test]
-    fn test_chat_window_insert_at_cursor() {
+    fn test_chat_process_mission_commands() {
         let mut chat = ChatWindow::default();
-        chat.insert_char('a');
-        chat.insert_char('c');
-        chat.cursor_left();
-        chat.insert_char('b');
+        assert_eq!(chat.process_input("/missions"), Some(ChatCommand::Missions));
+        assert_eq!(chat.process_input("/jobs"), Some(ChatCommand::Missions));
+        assert_eq!(chat.process_input("/accept 2"), Some(ChatCommand::AcceptMission(2)));
+        assert_eq!(chat.process_input("/abandon 1"), Some(ChatCommand::AbandonMission(1)));
+        assert_eq!(chat.process_input("/accept"), None, "Needs the offer's number");
+        assert_eq!(chat.process_input("/abandon survey"), None);
+        assert_eq!(chat.process_input("/wallet"), Some(ChatCommand::Wallet));
+        assert_eq!(chat.process_input("/balance"), Some(ChatCommand::Wallet));
+    }
 
-        assert_eq!(chat.input, "abc");
+    #[test]
+    fn test_chat_process_leaderboard_command() {
+        let mut chat = ChatWindow::default();
+        assert_eq!(chat.process_input("/top"), Some(ChatCommand::Leaderboard(LeaderboardMetric::Distance, 1)));
+        assert_eq!(chat.process_input("/top Ore"), Some(ChatCommand::Leaderboard(LeaderboardMetric::Ore, 1)));
+        assert_eq!(chat.process_input("/leaderboard credits 3"), Some(ChatCommand::Leaderboard(LeaderboardMetric::Credits, 3)));
+        assert_eq!(chat.process_input("/top ore 0"), Some(ChatCommand::Leaderboard(LeaderboardMetric::Ore, 1)), "Pages start at 1");
+        assert_eq!(chat.process_input("/top kills"), None);
+        assert_eq!(chat.commands.complete("/top cr").as_deref(), Some("/top credits "));
     }
 
     #[test]
-    fn test_chat_window_submit() {
+    fn test_chat_process_distance_command() {
         let mut chat = ChatWindow::default();
-        chat.open();
-        chat.insert_char('h');
-        chat.insert_char('i');
+        assert_eq!(
+            chat.process_input("/distance Ace"),
+            Some(ChatCommand::Distance(DistanceTarget::Player { name: "Ace".to_string() }))
+        );
+        assert_eq!(
+            chat.process_input("/dist 10 -4"),
+            Some(ChatCommand::Distance(DistanceTarget::Position { x: 10, y: -4 }))
+        );
+        assert!(chat.process_input("/distance 10").is_none());
+        assert!(chat.messages.last().unwrap().text.contains("missing Y"), "A lone number reads as a position");
+    }
 
-        let result = chat.submit();
-        assert_eq!(result, Some("hi".to_string()));
-        assert!(chat.input.is_empty());
-        assert!(!chat.active);
+    #[test]
+    fn test_find_coordinates() {
+        let text = "Ace: meet at (1234, -567) or (5,5)";
+        let found = find_coordinates(text);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].1, (1234, -567));
+        assert_eq!(&text[found[0].0.clone()], "(1234, -567)");
+        assert_eq!(found[1].1, (5, 5));
+
+        assert!(find_coordinates("(hi, there) (1 2) (3, ) (4, 5").is_empty(), "Only well-formed pairs count");
+        assert_eq!(find_coordinates("((7, 8))")[0].1, (7, 8), "Nested parens still find the pair");
     }
 
     #[test]
-    fn test_chat_window_submit_empty() {
+    fn test_chat_go_last_uses_latest_shared_coordinates() {
         let mut chat = ChatWindow::default();
-        chat.open();
+        assert!(chat.process_input("/go last").is_none());
+        assert!(chat.messages.last().unwrap().text.contains("Nobody has shared"));
 
-        let result = chat.submit();
-        assert!(result.is_none());
-        assert!(!chat.active);
+        chat.add_message(ChatMessage::from_player(1, "Ace", "rocks at (10, 20)"));
+        chat.add_message(ChatMessage::from_player(2, "Bo", "no, over at (-3, 4)!"));
+        chat.add_message(ChatMessage::from_player(1, "Ace", "ok"));
+        chat.add_message(ChatMessage::system("Position: (0, 0) - 5 tiles explored"));
+        assert_eq!(chat.process_input("/go last"), Some(ChatCommand::Teleport(-3, 4)), "Only other players' lines count");
+        assert_eq!(chat.process_input("/goto 1 2"), Some(ChatCommand::Teleport(1, 2)));
     }
 
     #[test]
-    fn test_chat_window_add_message() {
+    fn test_chat_message_from_player_finds_coordinates() {
+        let msg = ChatMessage::from_player(1, "Ace", "at (3, 4)");
+        assert_eq!(msg.coords.len(), 1);
+        assert_eq!(&msg.text[msg.coords[0].0.clone()], "(3, 4)");
+        assert!(ChatMessage::system("(3, 4)").coords.is_empty());
+    }
+
+    #[test]
+    fn test_chat_process_bind_command() {
         let mut chat = ChatWindow::default();
-        chat.add_message(ChatMessage::system("Test 1"));
-        chat.add_message(ChatMessage::system("Test 2"));
+        assert_eq!(chat.process_input("/bind move_up w"), Some(ChatCommand::Bind(Action::MoveUp, "w".to_string())));
+        assert_eq!(chat.process_input("/key QUIT none"), Some(ChatCommand::Bind(Action::Quit, "none".to_string())));
+        assert_eq!(chat.process_input("/bind"), Some(ChatCommand::ListBindings));
 
-        assert_eq!(chat.messages.len(), 2);
-        assert_eq!(chat.messages[0].text, "Test 1");
-        assert_eq!(chat.messages[1].text, "Test 2");
+        assert!(chat.process_input("/bind teleport space").is_none());
+        assert!(chat.messages.last().unwrap().text.contains("unknown action"));
+        assert!(chat.process_input("/bind quit hyper").is_none());
+        assert!(chat.messages.last().unwrap().text.contains("unknown key"));
     }
 
     #[test]
-    fn test_chat_window_max_messages() {
+    fn test_chat_process_extra_argument_rejected() {
         let mut chat = ChatWindow::default();
-        chat.max_messages = 3;
+        assert!(chat.process_input("/pos now").is_none());
+        assert!(chat.messages.last().unwrap().text.contains("unexpected argument"));
+    }
 
-        chat.add_message(ChatMessage::system("1"));
-        chat.add_message(ChatMessage::system("2"));
-        chat.add_message(ChatMessage::system("3"));
-        chat.add_message(ChatMessage::system("4"));
+    // ==================== Command Spec Tests ====================
 
-        assert_eq!(chat.messages.len(), 3);
-        assert_eq!(chat.messages[0].text, "2"); // First message removed
-        assert_eq!(chat.messages[2].text, "4");
+    #[test]
+    fn test_command_spec_names_unique() {
+        let mut seen = std::collections::HashSet::new();
+        for spec in COMMANDS {
+            for name in std::iter::once(&spec.name).chain(spec.aliases) {
+                assert!(seen.insert(*name), "Duplicate command name or alias: {}", name);
+            }
+        }
     }
 
     #[test]
-    fn test_chat_window_visible_messages() {
+    fn test_command_registry_register() {
         let mut chat = ChatWindow::default();
-        chat.visible_lines = 2;
+        let hail = CommandSpec {
+            name: "hail",
+            aliases: &["greet"],
+            forms: &[&[ARG_PLAYER]],
+            description: "Greet a pilot",
+            run: |args, _| match args {
+                [ArgValue::Name(name)] => Some(ChatCommand::Say(format!("Hello, {}!", name))),
+                _ => None,
+            },
+        };
+        chat.commands.register(hail).unwrap();
+        assert_eq!(chat.process_input("/greet Ace"), Some(ChatCommand::Say("Hello, Ace!".to_string())), "Runs like a built-in");
+        assert_eq!(chat.process_input("/hail 7"), None, "Arguments are checked against its forms");
+        assert_eq!(chat.commands.complete("/ha").as_deref(), Some("/hail "));
+        assert_eq!(chat.commands.suggest("hial").map(|spec| spec.name), Some("hail"));
 
-        chat.add_message(ChatMessage::system("1"));
-        chat.add_message(ChatMessage::system("2"));
-        chat.add_message(ChatMessage::system("3"));
+        chat.clear();
+        chat.process_input("/help");
+        let help: Vec<&str> = chat.messages.iter().map(|message| message.text.as_str()).collect();
+        assert!(help.contains(&"  /hail PLAYER - Greet a pilot"), "Help lists registered commands: {:?}", help);
 
-        let visible: Vec<_> = chat.visible_messages().collect();
-        assert_eq!(visible.len(), 2);
-        assert_eq!(visible[0].text, "2");
-        assert_eq!(visible[1].text, "3");
+        let clash = CommandSpec { name: "salute", aliases: &["q"], ..hail };
+        assert_eq!(chat.commands.register(clash), Err("/q is already a command".to_string()));
+        assert_eq!(chat.commands.register(hail), Err("/hail is already a command".to_string()));
+        let formless = CommandSpec { name: "wave", aliases: &[], forms: &[], ..hail };
+        assert!(chat.commands.register(formless).is_err(), "A command needs a form to parse against");
+        assert_eq!(chat.process_input("/quit"), Some(ChatCommand::Quit), "Built-ins are untouched");
     }
 
-    // ==================== ChatCommand Tests ====================
+    #[test]
+    fn test_command_spec_usage() {
+        let commands = CommandRegistry::builtin();
+        assert_eq!(commands.find("goto").unwrap().usage(), "/goto X Y | /goto last");
+        assert_eq!(commands.find("watch").unwrap().usage(), "/target X Y | /target off");
+        assert_eq!(commands.find("q").unwrap().usage(), "/quit");
+        assert_eq!(commands.find("roll").unwrap().usage(), "/roll NdM | /roll");
+        assert_eq!(commands.find("dist").unwrap().usage(), "/distance PLAYER | /distance X Y");
+    }
 
     #[test]
-    fn test_chat_process_help_command() {
-        let mut chat = ChatWindow::default();
-        let cmd = chat.process_input("/help");
-        assert!(cmd.is_none()); // Help just adds messages
-        assert!(!chat.messages.is_empty());
+    fn test_parse_dice() {
+        assert_eq!(parse_dice("2d6"), Some((2, 6)));
+        assert_eq!(parse_dice("d20"), Some((1, 20)));
+        assert_eq!(parse_dice("3D8"), Some((3, 8)));
+        assert_eq!(parse_dice(&format!("{}d{}", MAX_DICE, MAX_DIE_SIDES)), Some((MAX_DICE, MAX_DIE_SIDES)));
+        for bad in ["", "d", "6", "2d", "0d6", "2d1", "2x6", "-1d6", "21d6", "1d1001"] {
+            assert_eq!(parse_dice(bad), None, "{:?} should not parse", bad);
+        }
     }
 
     #[test]
-    fn test_chat_process_quit_command() {
-        let mut chat = ChatWindow::default();
-        let cmd = chat.process_input("/quit");
-        assert_eq!(cmd, Some(ChatCommand::Quit));
+    fn test_command_spec_name_argument() {
+        let commands = CommandRegistry::builtin();
+        let spec = commands.find("distance").unwrap();
+        assert_eq!(spec.parse_args(&["Ace"]), Ok(vec![ArgValue::Name("Ace".to_string())]));
+        let err = CommandSpec::parse_form(&[ARG_PLAYER], &["42"]).unwrap_err();
+        assert_eq!(err, ArgError { index: 0, message: "PLAYER can't be a number".to_string() });
     }
 
     #[test]
-    fn test_chat_process_pos_command() {
-        let mut chat = ChatWindow::default();
-        let cmd = chat.process_input("/pos");
-        assert_eq!(cmd, Some(ChatCommand::ShowPosition));
+    fn test_command_spec_suggest() {
+        let commands = CommandRegistry::builtin();
+        assert_eq!(commands.suggest("gotp").map(|s| s.name), Some("goto"));
+        assert_eq!(commands.suggest("tar").map(|s| s.name), Some("target"), "Prefixes suggest");
+        assert_eq!(commands.suggest("hlep").map(|s| s.name), Some("help"));
+        assert!(commands.suggest("z").is_none(), "Single letters shouldn't match everything");
+        assert!(commands.suggest("").is_none());
     }
 
     #[test]
-    fn test_chat_process_goto_command() {
-        let mut chat = ChatWindow::default();
-        let cmd = chat.process_input("/goto 100 200");
-        assert_eq!(cmd, Some(ChatCommand::Teleport(100, 200)));
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("goto", "goto"), 0);
+        assert_eq!(edit_distance("gto", "goto"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
     }
 
     #[test]
-    fn test_chat_process_goto_invalid() {
+    fn test_chat_process_autoexec() {
         let mut chat = ChatWindow::default();
-        let cmd = chat.process_input("/goto");
-        assert!(cmd.is_none());
-        // Should have added an error message
-        assert!(chat.messages.iter().any(|m| m.text.contains("Usage")));
+        assert_eq!(chat.process_autoexec("  /target 3 4 "), Some(ChatCommand::SetTarget(3, 4)));
+        assert!(chat.process_autoexec("").is_none());
+        assert!(chat.process_autoexec("# comment").is_none());
+        assert!(chat.messages.is_empty(), "Blank lines and comments are silent");
     }
 
     #[test]
-    fn test_chat_process_fx_command() {
+    fn test_chat_process_autoexec_rejects_chat_lines() {
         let mut chat = ChatWindow::default();
-        let cmd = chat.process_input("/fx");
-        assert_eq!(cmd, Some(ChatCommand::ToggleEffects));
+        assert!(chat.process_autoexec("hello everyone").is_none(), "Autoexec never sends chat");
+        assert!(chat.messages.last().unwrap().text.contains("not a command"));
     }
 
     #[test]
-    fn test_chat_process_unknown_command() {
+    fn test_chat_process_autoexec_validates_like_typed() {
         let mut chat = ChatWindow::default();
-        let cmd = chat.process_input("/unknowncmd");
-        assert!(cmd.is_none());
-        assert!(chat.messages.iter().any(|m| m.text.contains("Unknown command")));
+        assert!(chat.process_autoexec("/goto 1 x").is_none());
+        assert!(chat.messages.last().unwrap().text.contains("Y must be a number"));
+        assert!(chat.process_autoexec("/gto 1 2").is_none());
+        assert!(chat.messages.last().unwrap().text.contains("Did you mean /goto?"));
     }
 
     #[test]
     fn test_chat_process_regular_message() {
         let mut chat = ChatWindow::default();
         let cmd = chat.process_input("Hello world");
-        assert!(cmd.is_none());
+        assert_eq!(cmd, Some(ChatCommand::Say("Hello world".to_string())));
         assert!(chat.messages.iter().any(|m| m.text.contains("You: Hello world")));
     }
 
@@ -0,0 +1,157 @@
+//! A list menu widget: a cursor over label/value rows, and a text field for
+//! editing a value in place.
+//!
+//! Screens own a `Menu`, feed it keys and draw its `lines()`. Up/Down move
+//! the cursor, wrapping at the ends; Enter reports the row as
+//! `MenuEvent::Chosen` and Esc as `MenuEvent::Closed`. A screen that wants a
+//! typed value calls `edit()` with the text to start from, and keys then go
+//! to the field until Enter
+//! (`MenuEvent::Edited`) or Esc, which drops the edit.
+
+use crate::screens::Key;
+
+/// Longest value the field takes
+pub const MAX_FIELD_LEN: usize = 120;
+
+/// One row: what it is and its current value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MenuItem {
+    pub label: String,
+    pub value: String,
+}
+
+impl MenuItem {
+    pub fn new(label: &str, value: String) -> Self {
+        MenuItem { label: label.to_string(), value }
+    }
+}
+
+/// What a key did to the menu
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MenuEvent {
+    Nothing,
+    /// Enter on a row
+    Chosen(usize),
+    /// Enter in the field: the row and the text typed
+    Edited(usize, String),
+    /// Esc outside the field
+    Closed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Menu {
+    items: Vec<MenuItem>,
+    selected: usize,
+    /// Text being typed for the selected row
+    field: Option<String>,
+}
+
+impl Menu {
+    pub fn new(items: Vec<MenuItem>) -> Self {
+        Menu { items, selected: 0, field: None }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn set_value(&mut self, index: usize, value: String) {
+        if let Some(item) = self.items.get_mut(index) {
+            item.value = value;
+        }
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.field.is_some()
+    }
+
+    /// Open the field on the selected row, starting with `text`
+    pub fn edit(&mut self, text: String) {
+        self.field = Some(text);
+    }
+
+    pub fn input(&mut self, key: Key) -> MenuEvent {
+        if let Some(field) = &mut self.field {
+            match key {
+                Key::Char(ch) if !ch.is_control() && field.chars().count() < MAX_FIELD_LEN => field.push(ch),
+                Key::Backspace => {
+                    field.pop();
+                }
+                Key::Enter => return MenuEvent::Edited(self.selected, self.field.take().unwrap_or_default()),
+                Key::Esc => self.field = None,
+                _ => {}
+            }
+            return MenuEvent::Nothing;
+        }
+        let count = self.items.len().max(1);
+        match key {
+            Key::Up => self.selected = (self.selected + count - 1) % count,
+            Key::Down => self.selected = (self.selected + 1) % count,
+            Key::Enter if !self.items.is_empty() => return MenuEvent::Chosen(self.selected),
+            Key::Esc => return MenuEvent::Closed,
+            _ => {}
+        }
+        MenuEvent::Nothing
+    }
+
+    /// One line per row, labels padded to line up, e.g. "> Effects      on"; the field shows as "[text_]"
+    pub fn lines(&self) -> Vec<String> {
+        let width = self.items.iter().map(|item| item.label.chars().count()).max().unwrap_or(0);
+        self.items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let marker = if i == self.selected { '>' } else { ' ' };
+                let value = match &self.field {
+                    Some(field) if i == self.selected => format!("[{}_]", field),
+                    _ => item.value.clone(),
+                };
+                format!("{} {:<width$}  {}", marker, item.label, value, width = width)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn menu() -> Menu {
+        Menu::new(vec![MenuItem::new("Effects", "off".to_string()), MenuItem::new("Key timeout", "300 ms".to_string())])
+    }
+
+    // ==================== Navigation Tests ====================
+
+    #[test]
+    fn test_cursor_wraps_and_chooses() {
+        let mut menu = menu();
+        assert_eq!(menu.input(Key::Up), MenuEvent::Nothing);
+        assert_eq!(menu.selected(), 1, "Up from the top wraps to the bottom");
+        assert_eq!(menu.lines(), ["  Effects      off", "> Key timeout  300 ms"]);
+        assert_eq!(menu.input(Key::Down), MenuEvent::Nothing);
+        assert_eq!(menu.input(Key::Enter), MenuEvent::Chosen(0));
+        assert_eq!(menu.input(Key::Esc), MenuEvent::Closed);
+    }
+
+    // ==================== Field Tests ====================
+
+    #[test]
+    fn test_field_edits_in_place() {
+        let mut menu = menu();
+        menu.input(Key::Down);
+        menu.edit("300".to_string());
+        assert!(menu.is_editing());
+        menu.input(Key::Backspace);
+        menu.input(Key::Backspace);
+        menu.input(Key::Char('5'));
+        assert_eq!(menu.lines()[1], "> Key timeout  [35_]", "The field starts with the given text");
+        assert_eq!(menu.input(Key::Up), MenuEvent::Nothing, "Arrows don't leave the field");
+        assert_eq!(menu.input(Key::Enter), MenuEvent::Edited(1, "35".to_string()));
+        assert!(!menu.is_editing());
+
+        menu.edit(String::new());
+        menu.input(Key::Char('x'));
+        assert_eq!(menu.input(Key::Esc), MenuEvent::Nothing, "Esc drops the edit without closing");
+        assert_eq!(menu.lines()[1], "> Key timeout  300 ms");
+    }
+}
@@ -0,0 +1,359 @@
+//! Mining resource tiles into the ship's hold.
+//!
+//! Ore asteroids and crystal fields are solid, so they're mined from a
+//! neighbouring tile: `/mine` (or the mine key) picks the nearest resource
+//! next to the ship and extracts it over `MINE_TICKS` ship ticks (scaled
+//! by the solo world's `Difficulty`). The mining laser (`/laser`, space) works
+//! the same way on a plain asteroid, cutting it away to open space over
+//! `LASER_TICKS`. Moving off the spot interrupts either job. A finished tile
+//! is spent or cut away, which is recorded as a map edit (see `edits.rs`).
+//! The hold takes as much as the ship class allows; yields that don't fit
+//! are lost, and mining is refused once it's full.
+
+use exospace_core::Tile;
+
+use crate::difficulty::Difficulty;
+use crate::ships::ShipClass;
+
+/// Ship ticks (33ms each) to extract one tile at normal difficulty, about a second
+pub const MINE_TICKS: u32 = 30;
+
+/// Movement ticks for the laser to cut through an asteroid, half a second
+pub const LASER_TICKS: u32 = 15;
+
+/// What a resource tile yields
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resource {
+    Ore,
+    Crystal,
+}
+
+impl Resource {
+    pub fn from_tile(tile: Tile) -> Option<Resource> {
+        match tile {
+            Tile::OreAsteroid => Some(Resource::Ore),
+            Tile::CrystalField => Some(Resource::Crystal),
+            _ => None,
+        }
+    }
+
+    /// What's left of the tile once it's been mined
+    pub fn spent_tile(self) -> Tile {
+        match self {
+            Resource::Ore => Tile::Asteroid,
+            Resource::Crystal => Tile::Nebula,
+        }
+    }
+
+    /// Units extracted from one tile at normal difficulty, as the server counts them
+    pub fn yield_amount(self) -> u32 {
+        let tile = match self {
+            Resource::Ore => Tile::OreAsteroid,
+            Resource::Crystal => Tile::CrystalField,
+        };
+        tile.mining_yield().map_or(0, |(_, amount)| amount)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Resource::Ore => "ore",
+            Resource::Crystal => "crystal",
+        }
+    }
+
+    /// The resource a content item id stands for, e.g. "ore"
+    pub fn from_item(item: &str) -> Option<Resource> {
+        [Resource::Ore, Resource::Crystal].into_iter().find(|resource| resource.name() == item)
+    }
+}
+
+/// Resources carried by the ship, and the credits to trade them with
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Inventory {
+    pub ore: u32,
+    pub crystal: u32,
+    /// Earned by selling at station markets (see `docking.rs`)
+    pub credits: u32,
+}
+
+impl Inventory {
+    fn slot(&mut self, resource: Resource) -> &mut u32 {
+        match resource {
+            Resource::Ore => &mut self.ore,
+            Resource::Crystal => &mut self.crystal,
+        }
+    }
+
+    pub fn add(&mut self, resource: Resource, amount: u32) {
+        let slot = self.slot(resource);
+        *slot = slot.saturating_add(amount);
+    }
+
+    /// Take `amount` out of the hold; false, taking nothing, if there isn't that much
+    pub fn remove(&mut self, resource: Resource, amount: u32) -> bool {
+        let slot = self.slot(resource);
+        let Some(left) = slot.checked_sub(amount) else {
+            return false;
+        };
+        *slot = left;
+        true
+    }
+
+    pub fn count(&self, resource: Resource) -> u32 {
+        match resource {
+            Resource::Ore => self.ore,
+            Resource::Crystal => self.crystal,
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.ore.saturating_add(self.crystal)
+    }
+
+    /// Status bar text, e.g. "Ore:3 Cry:1"
+    pub fn summary(&self) -> String {
+        format!("Ore:{} Cry:{}", self.ore, self.crystal)
+    }
+}
+
+/// What a job does to its tile
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Work {
+    Extract(Resource),
+    /// Laser a plain asteroid away
+    Clear,
+}
+
+impl Work {
+    /// Status bar text, e.g. "Mining ore"
+    pub fn label(self) -> &'static str {
+        match self {
+            Work::Extract(Resource::Ore) => "Mining ore",
+            Work::Extract(Resource::Crystal) => "Mining crystal",
+            Work::Clear => "Lasering",
+        }
+    }
+}
+
+/// A mining job in progress
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Job {
+    target: (i32, i32),
+    work: Work,
+    /// Where the ship was when it started; leaving interrupts the job
+    anchor: (i32, i32),
+    ticks: u32,
+}
+
+/// How a tick of mining went
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MineEvent {
+    /// The tile is spent and its yield is in the hold
+    Finished { x: i32, y: i32, resource: Resource, amount: u32 },
+    /// The laser cut the asteroid away
+    Cleared { x: i32, y: i32 },
+    Interrupted,
+}
+
+/// The ship's mining gear: the hold plus the current job, if any
+pub struct Miner {
+    pub inventory: Inventory,
+    /// Most the hold takes, from the ship class
+    pub capacity: u32,
+    /// Scales yields and mining time; always normal online
+    pub difficulty: Difficulty,
+    job: Option<Job>,
+}
+
+impl Default for Miner {
+    fn default() -> Self {
+        Miner {
+            inventory: Inventory::default(),
+            capacity: ShipClass::default().cargo_capacity(),
+            difficulty: Difficulty::default(),
+            job: None,
+        }
+    }
+}
+
+/// Neighbours checked for a target, orthogonal ones first
+const NEIGHBOURS: [(i32, i32); 8] = [(0, -1), (1, 0), (0, 1), (-1, 0), (1, -1), (1, 1), (-1, 1), (-1, -1)];
+
+impl Miner {
+    /// Start mining the first resource tile next to the ship (orthogonal neighbours first)
+    pub fn start(&mut self, ship: (i32, i32), tile_at: impl Fn(i32, i32) -> Option<Tile>) -> Result<Resource, String> {
+        if self.inventory.total() >= self.capacity {
+            return Err(format!("Hold full ({} units)", self.capacity));
+        }
+        let (target, resource) = self
+            .find_target(ship, "Nothing to mine here - fly next to ore or crystals", |x, y| tile_at(x, y).and_then(Resource::from_tile))?;
+        self.job = Some(Job { target, work: Work::Extract(resource), anchor: ship, ticks: 0 });
+        Ok(resource)
+    }
+
+    /// Start lasering the first plain asteroid next to the ship
+    pub fn fire_laser(&mut self, ship: (i32, i32), tile_at: impl Fn(i32, i32) -> Option<Tile>) -> Result<(), String> {
+        let (target, ()) = self
+            .find_target(ship, "Nothing to cut here - fly next to an asteroid", |x, y| (tile_at(x, y) == Some(Tile::Asteroid)).then_some(()))?;
+        self.job = Some(Job { target, work: Work::Clear, anchor: ship, ticks: 0 });
+        Ok(())
+    }
+
+    /// First neighbour `pick` accepts, unless a job is already running; `missing` says there's none
+    fn find_target<T>(&self, ship: (i32, i32), missing: &str, pick: impl Fn(i32, i32) -> Option<T>) -> Result<((i32, i32), T), String> {
+        if self.job.is_some() {
+            return Err("Already mining".to_string());
+        }
+        let (x, y) = ship;
+        NEIGHBOURS
+            .iter()
+            .map(|&(dx, dy)| (x + dx, y + dy))
+            .find_map(|(tx, ty)| pick(tx, ty).map(|found| ((tx, ty), found)))
+            .ok_or_else(|| missing.to_string())
+    }
+
+    /// Movement ticks a kind of work takes
+    fn ticks_for(&self, work: Work) -> u32 {
+        match work {
+            Work::Extract(_) => self.difficulty.mine_ticks(),
+            Work::Clear => LASER_TICKS,
+        }
+    }
+
+    /// Advance the job by one ship tick
+    pub fn tick(&mut self, ship: (i32, i32)) -> Option<MineEvent> {
+        let job = self.job.as_mut()?;
+        if ship != job.anchor {
+            self.job = None;
+            return Some(MineEvent::Interrupted);
+        }
+        job.ticks += 1;
+        let Job { target: (x, y), work, ticks, .. } = *job;
+        if ticks < self.ticks_for(work) {
+            return None;
+        }
+        self.job = None;
+        match work {
+            Work::Extract(resource) => {
+                let room = self.capacity.saturating_sub(self.inventory.total());
+                let amount = self.difficulty.mining_yield(resource).min(room);
+                self.inventory.add(resource, amount);
+                Some(MineEvent::Finished { x, y, resource, amount })
+            }
+            Work::Clear => Some(MineEvent::Cleared { x, y }),
+        }
+    }
+
+    /// What the job is doing and how far along it is, 0.0..1.0
+    pub fn progress(&self) -> Option<(Work, f32)> {
+        self.job.map(|job| (job.work, job.ticks as f32 / self.ticks_for(job.work) as f32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Open space with ore just east of the origin and crystals to the north-west
+    fn field(x: i32, y: i32) -> Option<Tile> {
+        Some(match (x, y) {
+            (1, 0) => Tile::OreAsteroid,
+            (-1, -1) => Tile::CrystalField,
+            _ => Tile::Floor,
+        })
+    }
+
+    #[test]
+    fn test_mining_fills_the_hold() {
+        let mut miner = Miner::default();
+        assert_eq!(miner.start((0, 0), field), Ok(Resource::Ore), "Orthogonal neighbours come first");
+        assert!(miner.start((0, 0), field).is_err(), "One job at a time");
+
+        for _ in 1..MINE_TICKS {
+            assert_eq!(miner.tick((0, 0)), None);
+        }
+        let (work, progress) = miner.progress().unwrap();
+        assert_eq!(work, Work::Extract(Resource::Ore));
+        assert!(progress > 0.9);
+
+        assert_eq!(miner.tick((0, 0)), Some(MineEvent::Finished { x: 1, y: 0, resource: Resource::Ore, amount: 3 }));
+        assert_eq!(miner.inventory, Inventory { ore: 3, crystal: 0, credits: 0 });
+        assert!(miner.progress().is_none(), "The job is done");
+        assert_eq!(miner.tick((0, 0)), None, "Nothing happens without a job");
+    }
+
+    #[test]
+    fn test_mining_diagonal_and_nothing_nearby() {
+        let mut miner = Miner::default();
+        assert_eq!(miner.start((-2, -1), field), Ok(Resource::Crystal));
+
+        let mut empty = Miner::default();
+        assert!(empty.start((10, 10), field).unwrap_err().contains("Nothing to mine"));
+    }
+
+    #[test]
+    fn test_moving_interrupts_mining() {
+        let mut miner = Miner::default();
+        miner.start((0, 0), field).unwrap();
+        miner.tick((0, 0));
+        assert_eq!(miner.tick((0, 1)), Some(MineEvent::Interrupted));
+        assert_eq!(miner.inventory, Inventory::default(), "Nothing is kept from an interrupted job");
+        assert!(miner.start((0, 0), field).is_ok(), "A new job can start right away");
+    }
+
+    #[test]
+    fn test_hard_mining_is_slower_and_leaner() {
+        let mut miner = Miner { difficulty: Difficulty::Hard, ..Miner::default() };
+        miner.start((0, 0), field).unwrap();
+        for _ in 0..MINE_TICKS {
+            assert_eq!(miner.tick((0, 0)), None, "Still working after the normal time");
+        }
+        let event = std::iter::from_fn(|| Some(miner.tick((0, 0)))).find_map(|event| event);
+        assert_eq!(event, Some(MineEvent::Finished { x: 1, y: 0, resource: Resource::Ore, amount: 2 }));
+    }
+
+    #[test]
+    fn test_small_holds_fill_up() {
+        let mut miner = Miner { capacity: 4, ..Miner::default() };
+        miner.start((0, 0), field).unwrap();
+        let event = std::iter::from_fn(|| Some(miner.tick((0, 0)))).find_map(|event| event);
+        assert_eq!(event, Some(MineEvent::Finished { x: 1, y: 0, resource: Resource::Ore, amount: 3 }));
+        miner.start((0, 0), field).unwrap();
+        let event = std::iter::from_fn(|| Some(miner.tick((0, 0)))).find_map(|event| event);
+        assert_eq!(event, Some(MineEvent::Finished { x: 1, y: 0, resource: Resource::Ore, amount: 1 }), "Only what fits");
+        assert!(miner.start((0, 0), field).unwrap_err().contains("Hold full"));
+    }
+
+    #[test]
+    fn test_laser_clears_asteroids() {
+        let rocks = |x, y| Some(if (x, y) == (0, 1) { Tile::Asteroid } else { Tile::Floor });
+        let mut miner = Miner::default();
+        assert!(miner.fire_laser((0, 0), field).unwrap_err().contains("Nothing to cut"), "Ore isn't lasered away");
+        miner.fire_laser((0, 0), rocks).unwrap();
+        assert!(miner.start((0, 0), field).is_err(), "The laser is busy");
+        assert_eq!(miner.progress().map(|(work, _)| work.label()), Some("Lasering"));
+        for _ in 1..LASER_TICKS {
+            assert_eq!(miner.tick((0, 0)), None);
+        }
+        assert_eq!(miner.tick((0, 0)), Some(MineEvent::Cleared { x: 0, y: 1 }), "Quicker than mining");
+        assert_eq!(miner.inventory, Inventory::default(), "Plain rock yields nothing");
+    }
+
+    #[test]
+    fn test_resource_tiles() {
+        assert_eq!(Resource::from_tile(Tile::OreAsteroid), Some(Resource::Ore));
+        assert_eq!(Resource::from_tile(Tile::CrystalField), Some(Resource::Crystal));
+        assert_eq!(Resource::from_tile(Tile::Asteroid), None);
+        assert!(Resource::Crystal.spent_tile().is_passable(), "A spent crystal field is plain nebula");
+        assert!(!Resource::Ore.spent_tile().is_passable());
+
+        let mut inventory = Inventory::default();
+        inventory.add(Resource::Crystal, 2);
+        assert_eq!(inventory.summary(), "Ore:0 Cry:2");
+        assert!(!inventory.remove(Resource::Crystal, 3), "Can't take more than the hold has");
+        assert!(inventory.remove(Resource::Crystal, 2));
+        assert_eq!(inventory.count(Resource::Crystal), 0);
+        assert_eq!(Resource::from_item("crystal"), Some(Resource::Crystal));
+        assert_eq!(Resource::from_item("gas"), None);
+    }
+}
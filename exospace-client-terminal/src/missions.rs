@@ -0,0 +1,410 @@
+//! The mission log: jobs from the server's board, and tracking them.
+//!
+//! `/missions` (or the log key, 'j') fetches the board (`GET /missions`) and
+//! opens the log panel, which lists the offers and the missions under way.
+//! `/accept N` takes offer N, up to `MAX_ACTIVE` at once, and `/abandon N`
+//! drops mission N. The game loop tells the log about every ore mined and
+//! nebula tile flown through; a delivery is done once the ship is within
+//! docking range of the destination with the cargo aboard. Finished missions
+//! are reported to the server (`POST /missions/{id}/complete`), which
+//! answers with the reward: the cargo leaves the hold and the credits and
+//! items go in. Only the streamed world has a board.
+
+use exospace_core::{ErrorCode, ItemCount, MissionBoard, MissionInfo, MissionReward, Objective, Tile};
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::apierror::ServerError;
+use crate::docking::DOCKING_RANGE;
+use crate::mining::{Inventory, Resource};
+use crate::poi;
+
+/// Most missions under way at once
+pub const MAX_ACTIVE: usize = 3;
+
+/// How long to wait before handing in a mission again after the server turned it down
+pub const REPORT_RETRY: Duration = Duration::from_secs(10);
+
+/// Why a call to the mission board failed
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallFailure {
+    /// The server couldn't be reached, or its answer read
+    Unreachable(String),
+    /// The server turned the call down
+    Refused(ServerError),
+}
+
+impl fmt::Display for CallFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallFailure::Unreachable(reason) => f.write_str(reason),
+            CallFailure::Refused(error) => error.fmt(f),
+        }
+    }
+}
+
+/// A mission under way
+#[derive(Clone, Debug, PartialEq)]
+struct Active {
+    info: MissionInfo,
+    /// Ore mined, or nebula tiles surveyed, since it was taken
+    progress: u32,
+    surveyed: HashSet<(i32, i32)>,
+    /// Not handed in again before this, after a refusal
+    retry_at: Option<Instant>,
+}
+
+impl Active {
+    /// How it's going, e.g. "4/20 nebula tiles"
+    fn status(&self, ship: (i32, i32), inventory: &Inventory) -> String {
+        match &self.info.objective {
+            Objective::Deliver { cargo, x, y, .. } => {
+                let aboard = Resource::from_item(&cargo.item).map_or(0, |resource| inventory.count(resource));
+                let (dx, dy) = (x - ship.0, y - ship.1);
+                let heading = poi::bearing(dx, dy).map_or("here", |direction| direction.name());
+                format!("{}/{} {} aboard, {} tiles {}", aboard.min(cargo.count), cargo.count, cargo.item, poi::distance(dx, dy), heading)
+            }
+            Objective::Scan { tiles } => format!("{}/{} nebula tiles", self.progress, tiles),
+            Objective::Mine { ore } => format!("{}/{} ore", self.progress, ore),
+            Objective::Unknown => "can't be tracked".to_string(),
+        }
+    }
+
+    /// Whether the objective is met
+    fn done(&self, ship: (i32, i32), inventory: &Inventory) -> bool {
+        match &self.info.objective {
+            Objective::Deliver { cargo, x, y, .. } => {
+                let aboard = Resource::from_item(&cargo.item).is_some_and(|resource| inventory.count(resource) >= cargo.count);
+                aboard && (x - ship.0).abs().max((y - ship.1).abs()) <= DOCKING_RANGE
+            }
+            Objective::Scan { tiles } => self.progress >= *tiles,
+            Objective::Mine { ore } => self.progress >= *ore,
+            Objective::Unknown => false,
+        }
+    }
+}
+
+/// What a mission asks, e.g. "Deliver 5 ore to Haven Station"
+pub fn goal(objective: &Objective) -> String {
+    match objective {
+        Objective::Deliver { cargo, to, .. } => format!("Deliver {} {} to {}", cargo.count, cargo.item, to),
+        Objective::Scan { tiles } => format!("Survey {} nebula tiles", tiles),
+        Objective::Mine { ore } => format!("Mine {} ore", ore),
+        Objective::Unknown => "Unknown objective".to_string(),
+    }
+}
+
+/// What a reward comes to, e.g. "72 cr + 2 crystal"
+pub fn pay(credits: u32, items: &[ItemCount]) -> String {
+    let items = items.iter().map(|item| format!(" + {} {}", item.count, item.item));
+    format!("{} cr", credits) + items.collect::<String>().as_str()
+}
+
+/// The board's offers and the missions we've taken
+#[derive(Default)]
+pub struct MissionLog {
+    pub visible: bool,
+    offers: Vec<MissionInfo>,
+    active: Vec<Active>,
+    /// When the board we have is replaced
+    refresh_at: Option<Instant>,
+}
+
+impl MissionLog {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Take a freshly fetched board, leaving off missions already under way
+    pub fn post(&mut self, board: MissionBoard, now: Instant) {
+        self.offers = board.missions.into_iter().filter(|offer| !self.active.iter().any(|active| active.info.id == offer.id)).collect();
+        self.refresh_at = Some(now + Duration::from_secs(board.expires_in));
+    }
+
+    /// Take offer `number` (from 1) once `take` has signed up for it with the server; the chat line saying so
+    pub fn accept(&mut self, number: i32, take: impl FnOnce(&str) -> Result<(), CallFailure>) -> Result<String, String> {
+        let index = usize::try_from(number).ok().and_then(|n| n.checked_sub(1)).filter(|&i| i < self.offers.len());
+        let Some(index) = index else {
+            return Err(match self.offers.len() {
+                0 => "No missions on offer - /missions fetches the board".to_string(),
+                count => format!("Pick an offer from 1 to {}", count),
+            });
+        };
+        if self.active.len() >= MAX_ACTIVE {
+            return Err(format!("Your log is full ({} missions) - /abandon one first", MAX_ACTIVE));
+        }
+        if self.offers[index].objective == Objective::Unknown {
+            return Err("This client can't track that mission".to_string());
+        }
+        take(&self.offers[index].id).map_err(|e| e.to_string())?;
+        let info = self.offers.remove(index);
+        let line = format!("Mission accepted: {} - {}", info.name, goal(&info.objective));
+        self.active.push(Active { info, progress: 0, surveyed: HashSet::new(), retry_at: None });
+        Ok(line)
+    }
+
+    /// Drop mission `number` (from 1) from the log; the chat line saying so
+    pub fn abandon(&mut self, number: i32) -> Result<String, String> {
+        let index = usize::try_from(number).ok().and_then(|n| n.checked_sub(1)).filter(|&i| i < self.active.len());
+        match index {
+            Some(index) => Ok(format!("Mission abandoned: {}", self.active.remove(index).info.name)),
+            None if self.active.is_empty() => Err("No missions under way".to_string()),
+            None => Err(format!("Pick a mission from 1 to {}", self.active.len())),
+        }
+    }
+
+    /// Count ore toward mining missions
+    pub fn mined(&mut self, resource: Resource, amount: u32) {
+        if resource != Resource::Ore {
+            return;
+        }
+        for active in &mut self.active {
+            if matches!(active.info.objective, Objective::Mine { .. }) {
+                active.progress = active.progress.saturating_add(amount);
+            }
+        }
+    }
+
+    /// Count a tile the ship is on toward surveys, if it's nebula none of them has seen yet
+    pub fn flew_through(&mut self, x: i32, y: i32, tile: Option<Tile>) {
+        if tile != Some(Tile::Nebula) {
+            return;
+        }
+        for active in &mut self.active {
+            if let Objective::Scan { tiles } = active.info.objective
+                && active.progress < tiles
+                && active.surveyed.insert((x, y))
+            {
+                active.progress = active.surveyed.len() as u32;
+            }
+        }
+    }
+
+    /// The first mission that looks finished to hand in, skipping any refused too recently; the server keeps its
+    /// own count and has the last word
+    pub fn due(&self, now: Instant, ship: (i32, i32), inventory: &Inventory) -> Option<String> {
+        self.active
+            .iter()
+            .filter(|active| active.retry_at.is_none_or(|at| now >= at))
+            .find(|active| active.done(ship, inventory))
+            .map(|active| active.info.id.clone())
+    }
+
+    /// The server turned mission `id` down: missions that can't be completed any more are dropped, the rest
+    /// wait `REPORT_RETRY`; the chat line saying so
+    pub fn refused(&mut self, id: &str, now: Instant, failure: &CallFailure) -> String {
+        let Some(index) = self.active.iter().position(|active| active.info.id == id) else {
+            return failure.to_string();
+        };
+        let gone = matches!(failure, CallFailure::Refused(error)
+            if matches!(error.code, ErrorCode::MissionExpired | ErrorCode::UnknownMission | ErrorCode::MissionClaimed | ErrorCode::MissionNotAccepted));
+        if gone {
+            let active = self.active.remove(index);
+            return format!("{}: {} - dropped from the log", active.info.name, failure);
+        }
+        self.active[index].retry_at = Some(now + REPORT_RETRY);
+        format!("Couldn't hand in {}: {}", self.active[index].info.name, failure)
+    }
+
+    /// Settle a completed mission: the cargo leaves the hold, the reward goes in as far as it fits;
+    /// the chat line saying so
+    pub fn paid(&mut self, reward: &MissionReward, inventory: &mut Inventory, capacity: u32) -> String {
+        let name = match self.active.iter().position(|active| active.info.id == reward.mission) {
+            Some(index) => {
+                let active = self.active.remove(index);
+                if let Objective::Deliver { cargo, .. } = &active.info.objective
+                    && let Some(resource) = Resource::from_item(&cargo.item)
+                {
+                    inventory.remove(resource, cargo.count.min(inventory.count(resource)));
+                }
+                active.info.name
+            }
+            None => reward.mission.clone(),
+        };
+        inventory.credits = reward.balance.unwrap_or(inventory.credits.saturating_add(reward.credits));
+        let mut left = 0;
+        for item in &reward.items {
+            let Some(resource) = Resource::from_item(&item.item) else {
+                continue;
+            };
+            let fits = item.count.min(capacity.saturating_sub(inventory.total()));
+            inventory.add(resource, fits);
+            left += item.count - fits;
+        }
+        let line = format!("Mission complete: {} - paid {}", name, pay(reward.credits, &reward.items));
+        if left > 0 { format!("{} ({} left behind, hold full)", line, left) } else { line }
+    }
+
+    /// Forget the board and every mission, e.g. on leaving the world they came from
+    pub fn clear(&mut self) {
+        *self = MissionLog { visible: self.visible, ..MissionLog::default() };
+    }
+
+    /// The log panel's text
+    pub fn lines(&self, now: Instant, ship: (i32, i32), inventory: &Inventory) -> Vec<String> {
+        let mut lines = Vec::new();
+        match self.refresh_at {
+            Some(at) => {
+                let minutes = at.saturating_duration_since(now).as_secs().div_ceil(60);
+                lines.push(format!("On offer (new board in {} min):", minutes));
+            }
+            None => lines.push("On offer:".to_string()),
+        }
+        if self.offers.is_empty() {
+            lines.push("  nothing - /missions fetches the board".to_string());
+        }
+        for (n, offer) in (1..).zip(&self.offers) {
+            lines.push(format!("  {}. {}: {} ({})", n, offer.name, goal(&offer.objective), pay(offer.credits, &offer.items)));
+        }
+        lines.push(format!("Under way ({}/{}):", self.active.len(), MAX_ACTIVE));
+        if self.active.is_empty() {
+            lines.push("  nothing - /accept N takes an offer".to_string());
+        }
+        for (n, active) in (1..).zip(&self.active) {
+            lines.push(format!("  {}. {}: {}", n, active.info.name, active.status(ship, inventory)));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mission(id: &str, objective: Objective) -> MissionInfo {
+        MissionInfo { id: id.to_string(), name: format!("Job {}", id), objective, credits: 50, items: Vec::new() }
+    }
+
+    fn board() -> MissionBoard {
+        let cargo = ItemCount { item: "ore".to_string(), count: 5 };
+        MissionBoard {
+            missions: vec![
+                mission("7-0", Objective::Scan { tiles: 2 }),
+                mission("7-1", Objective::Mine { ore: 6 }),
+                mission("7-2", Objective::Deliver { cargo, to: "Haven Station".to_string(), x: 10, y: 0 }),
+                mission("7-3", Objective::Unknown),
+            ],
+            expires_in: 600,
+        }
+    }
+
+    fn taken(_: &str) -> Result<(), CallFailure> {
+        Ok(())
+    }
+
+    fn refusal(code: ErrorCode) -> CallFailure {
+        CallFailure::Refused(ServerError { status: 409, code, message: Some("Nope".to_string()), retry_after: None })
+    }
+
+    // ==================== Board Tests ====================
+
+    #[test]
+    fn test_accepting_and_abandoning() {
+        let now = Instant::now();
+        let mut log = MissionLog::default();
+        assert!(log.accept(1, taken).unwrap_err().contains("/missions"), "Nothing to take before the board is fetched");
+        log.post(board(), now);
+        assert_eq!(log.accept(1, |_| Err(refusal(ErrorCode::BadToken))), Err("Nope".to_string()));
+        assert_eq!(log.offers.len(), 4, "Offers the server turns down stay on the board");
+        assert_eq!(log.accept(1, taken), Ok("Mission accepted: Job 7-0 - Survey 2 nebula tiles".to_string()));
+        assert_eq!(log.offers.len(), 3, "Taken offers leave the board");
+        assert_eq!(log.accept(3, taken), Err("This client can't track that mission".to_string()));
+        assert_eq!(log.accept(0, taken), Err("Pick an offer from 1 to 3".to_string()));
+        log.accept(1, taken).unwrap();
+        log.accept(1, taken).unwrap();
+        assert!(log.accept(1, taken).unwrap_err().contains("full"), "At most {} at once", MAX_ACTIVE);
+
+        log.post(board(), now);
+        assert_eq!(log.offers.len(), 1, "A refetched board leaves off what's under way");
+        assert_eq!(log.abandon(2), Ok("Mission abandoned: Job 7-1".to_string()));
+        assert!(log.abandon(5).is_err());
+    }
+
+    #[test]
+    fn test_panel_lists_offers_and_progress() {
+        let now = Instant::now();
+        let mut log = MissionLog::default();
+        log.post(board(), now);
+        log.accept(3, taken).unwrap();
+        let inventory = Inventory { ore: 2, ..Inventory::default() };
+        let lines = log.lines(now, (0, 0), &inventory);
+        assert_eq!(lines[0], "On offer (new board in 10 min):");
+        assert_eq!(lines[1], "  1. Job 7-0: Survey 2 nebula tiles (50 cr)");
+        assert_eq!(lines.last().unwrap(), "  1. Job 7-2: 2/5 ore aboard, 10 tiles E");
+        assert_eq!(pay(72, &[ItemCount { item: "crystal".to_string(), count: 2 }]), "72 cr + 2 crystal");
+    }
+
+    // ==================== Tracking Tests ====================
+
+    #[test]
+    fn test_surveys_count_each_nebula_tile_once() {
+        let now = Instant::now();
+        let mut log = MissionLog::default();
+        log.post(board(), now);
+        log.accept(1, taken).unwrap();
+        log.flew_through(1, 1, Some(Tile::Nebula));
+        log.flew_through(1, 1, Some(Tile::Nebula));
+        log.flew_through(2, 1, Some(Tile::Floor));
+        assert_eq!(log.due(now, (0, 0), &Inventory::default()), None, "One tile of two");
+        log.flew_through(3, 1, Some(Tile::Nebula));
+        assert_eq!(log.due(now, (9, 9), &Inventory::default()), Some("7-0".to_string()));
+    }
+
+    #[test]
+    fn test_mining_and_deliveries() {
+        let now = Instant::now();
+        let mut log = MissionLog::default();
+        log.post(board(), now);
+        log.accept(2, taken).unwrap();
+        log.accept(2, taken).unwrap();
+        log.mined(Resource::Crystal, 10);
+        log.mined(Resource::Ore, 3);
+        let mut hold = Inventory { ore: 5, ..Inventory::default() };
+        assert_eq!(log.due(now, (0, 0), &hold), None, "3 of 6 ore, and the station is too far");
+        assert_eq!(log.due(now, (10 - DOCKING_RANGE, 0), &hold), Some("7-2".to_string()), "Docked with the cargo");
+        hold.ore = 4;
+        assert_eq!(log.due(now, (10, 0), &hold), None, "Short of cargo");
+        log.mined(Resource::Ore, 3);
+        assert_eq!(log.due(now, (0, 0), &hold), Some("7-1".to_string()));
+    }
+
+    // ==================== Payout Tests ====================
+
+    #[test]
+    fn test_payouts_take_cargo_and_fill_the_hold() {
+        let now = Instant::now();
+        let mut log = MissionLog::default();
+        log.post(board(), now);
+        log.accept(3, taken).unwrap();
+        let mut hold = Inventory { ore: 7, credits: 10, ..Inventory::default() };
+        let reward = MissionReward { mission: "7-2".to_string(), credits: 90, items: vec![ItemCount { item: "crystal".to_string(), count: 4 }], balance: None };
+        assert_eq!(log.paid(&reward, &mut hold, 5), "Mission complete: Job 7-2 - paid 90 cr + 4 crystal (1 left behind, hold full)");
+        assert_eq!(hold, Inventory { ore: 2, crystal: 3, credits: 100 }, "The cargo is handed over");
+        assert!(log.active.is_empty());
+        let banked = MissionReward { items: Vec::new(), balance: Some(400), ..reward };
+        log.paid(&banked, &mut hold, 5);
+        assert_eq!(hold.credits, 400, "An account's balance comes from the server");
+    }
+
+    #[test]
+    fn test_refusals_retry_or_drop() {
+        let now = Instant::now();
+        let mut log = MissionLog::default();
+        log.post(board(), now);
+        log.accept(1, taken).unwrap();
+        log.accept(1, taken).unwrap();
+        log.flew_through(0, 0, Some(Tile::Nebula));
+        log.flew_through(0, 1, Some(Tile::Nebula));
+        log.mined(Resource::Ore, 6);
+        let hold = Inventory::default();
+
+        assert_eq!(log.refused("7-0", now, &CallFailure::Unreachable("Timed out".to_string())), "Couldn't hand in Job 7-0: Timed out");
+        assert_eq!(log.due(now, (0, 0), &hold), Some("7-1".to_string()), "The other one goes first");
+        assert_eq!(log.due(now + REPORT_RETRY, (0, 0), &hold), Some("7-0".to_string()), "Then it's tried again");
+        log.refused("7-0", now, &refusal(ErrorCode::MissionUnfinished));
+        assert_eq!(log.active.len(), 2, "Unfinished missions stay in the log");
+        assert_eq!(log.refused("7-1", now, &refusal(ErrorCode::MissionExpired)), "Job 7-1: Nope - dropped from the log");
+        assert_eq!(log.active.len(), 1);
+    }
+}
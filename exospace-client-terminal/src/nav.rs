@@ -0,0 +1,254 @@
+//! Routes for `/nav X Y` and the autopilot that flies them.
+//!
+//! `find_path` is A* over tile passability with 8-way moves; a diagonal step
+//! is only taken when both tiles beside it are open, since the ship moves
+//! along one axis and then the other. The autopilot flies the route a tile
+//! every `TICKS_PER_TILE` movement ticks, bypassing momentum so it never
+//! drifts into a wall on a corner. With uniform diagonals it banks progress
+//! each tick and a diagonal step costs as much more than a straight one as it
+//! does in the search, so the ship covers ground at the same rate every way;
+//! classic diagonals take a tile either way per step. The search only looks a
+//! little way outside the box spanned by the two ends, which keeps it bounded
+//! on open space.
+
+use crate::Diagonals;
+use exospace_core::Direction;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+/// Furthest `/nav` will plan, in tiles along either axis
+pub const MAX_NAV_DISTANCE: i32 = 200;
+
+/// How far outside the start-goal box a route may swing to get around things
+pub const SEARCH_MARGIN: i32 = 32;
+
+/// Movement ticks (33ms each) per tile flown, about 10 tiles a second
+const TICKS_PER_TILE: u32 = 3;
+
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+/// Octile distance, in the same units as the step costs
+fn heuristic((x, y): (i32, i32), (gx, gy): (i32, i32)) -> u32 {
+    let dx = x.abs_diff(gx);
+    let dy = y.abs_diff(gy);
+    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+}
+
+/// Shortest route from `start` to `goal`, excluding `start`; None if there isn't one within the search box
+pub fn find_path(start: (i32, i32), goal: (i32, i32), passable: impl Fn(i32, i32) -> bool) -> Option<Vec<(i32, i32)>> {
+    if !passable(goal.0, goal.1) {
+        return None;
+    }
+    let (min_x, max_x) = (start.0.min(goal.0) - SEARCH_MARGIN, start.0.max(goal.0) + SEARCH_MARGIN);
+    let (min_y, max_y) = (start.1.min(goal.1) - SEARCH_MARGIN, start.1.max(goal.1) + SEARCH_MARGIN);
+    let open_at = |x: i32, y: i32| (min_x..=max_x).contains(&x) && (min_y..=max_y).contains(&y) && passable(x, y);
+
+    let mut frontier = BinaryHeap::new();
+    let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    let mut cost: HashMap<(i32, i32), u32> = HashMap::from([(start, 0)]);
+    frontier.push(Reverse((heuristic(start, goal), start)));
+
+    while let Some(Reverse((_, current))) = frontier.pop() {
+        if current == goal {
+            let mut path = vec![goal];
+            while let Some(&previous) = came_from.get(path.last().unwrap()) {
+                path.push(previous);
+            }
+            path.pop();
+            path.reverse();
+            return Some(path);
+        }
+        let (x, y) = current;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx, dy) == (0, 0) || !open_at(x + dx, y + dy) {
+                    continue;
+                }
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal && !(open_at(x + dx, y) && open_at(x, y + dy)) {
+                    continue;
+                }
+                let next = (x + dx, y + dy);
+                let next_cost = cost[&current] + if diagonal { DIAGONAL_COST } else { STRAIGHT_COST };
+                if cost.get(&next).is_none_or(|&known| next_cost < known) {
+                    cost.insert(next, next_cost);
+                    came_from.insert(next, current);
+                    frontier.push(Reverse((next_cost + heuristic(next, goal), next)));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// What the autopilot did this tick
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NavStep {
+    /// Between tiles
+    Cruise,
+    /// Fly the ship to this tile, facing this way
+    Move { x: i32, y: i32, direction: Direction },
+    Arrived,
+    /// The next tile closed up (an edit, or its chunk was dropped) or the ship is off the route
+    Blocked,
+}
+
+/// A route being flown
+#[derive(Debug)]
+pub struct Autopilot {
+    pub destination: (i32, i32),
+    route: VecDeque<(i32, i32)>,
+    diagonals: Diagonals,
+    /// Banked progress toward the next tile, in step cost units
+    progress: u32,
+}
+
+impl Autopilot {
+    /// Plan a route from the ship to a destination
+    pub fn plan(ship: (i32, i32), destination: (i32, i32), diagonals: Diagonals, passable: impl Fn(i32, i32) -> bool) -> Result<Self, String> {
+        let (x, y) = destination;
+        if ship.0.abs_diff(x).max(ship.1.abs_diff(y)) > MAX_NAV_DISTANCE as u32 {
+            return Err(format!("({}, {}) is too far - /nav plans up to {} tiles", x, y, MAX_NAV_DISTANCE));
+        }
+        if !passable(x, y) {
+            return Err(format!("Can't fly to ({}, {}) - not passable", x, y));
+        }
+        let route = find_path(ship, destination, passable).ok_or_else(|| format!("No route to ({}, {})", x, y))?;
+        Ok(Autopilot { destination, route: route.into(), diagonals, progress: 0 })
+    }
+
+    /// Tiles still to fly, nearest first; drawn as breadcrumbs
+    pub fn remaining(&self) -> impl Iterator<Item = &(i32, i32)> {
+        self.route.iter()
+    }
+
+    /// Advance by one movement tick
+    pub fn tick(&mut self, ship: (i32, i32), passable: impl Fn(i32, i32) -> bool) -> NavStep {
+        let Some(&(x, y)) = self.route.front() else {
+            return NavStep::Arrived;
+        };
+        let diagonal = x != ship.0 && y != ship.1 && self.diagonals == Diagonals::Uniform;
+        let cost = TICKS_PER_TILE * if diagonal { DIAGONAL_COST } else { STRAIGHT_COST };
+        self.progress += STRAIGHT_COST;
+        if self.progress < cost {
+            return NavStep::Cruise;
+        }
+        self.progress -= cost;
+        let Some(direction) = Direction::from_delta(x - ship.0, y - ship.1) else {
+            return NavStep::Blocked;
+        };
+        if (x - ship.0).abs() > 1 || (y - ship.1).abs() > 1 || !passable(x, y) {
+            return NavStep::Blocked;
+        }
+        self.route.pop_front();
+        NavStep::Move { x, y, direction }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Open space with a wall along x = 5 from y = -10 to 10
+    fn walled(x: i32, y: i32) -> bool {
+        !(x == 5 && (-10..=10).contains(&y))
+    }
+
+    fn fly(autopilot: &mut Autopilot, mut ship: (i32, i32), passable: impl Fn(i32, i32) -> bool) -> ((i32, i32), NavStep) {
+        for _ in 0..10_000 {
+            match autopilot.tick(ship, &passable) {
+                NavStep::Cruise => {}
+                NavStep::Move { x, y, .. } => ship = (x, y),
+                done => return (ship, done),
+            }
+        }
+        panic!("Autopilot never finished");
+    }
+
+    // ==================== Pathfinding Tests ====================
+
+    #[test]
+    fn test_path_in_open_space_is_straight() {
+        let path = find_path((0, 0), (6, 3), |_, _| true).unwrap();
+        assert_eq!(path.len(), 6, "Diagonals make the route as long as the longer axis");
+        assert_eq!(path.last(), Some(&(6, 3)));
+        assert!(!path.contains(&(0, 0)), "The start isn't part of the route");
+    }
+
+    #[test]
+    fn test_path_goes_around_walls() {
+        let path = find_path((0, 0), (10, 0), walled).unwrap();
+        assert!(path.iter().all(|&(x, y)| walled(x, y)), "Route crosses the wall");
+        assert!(path.iter().any(|&(_, y)| y.abs() > 10), "Route should swing round the end of the wall");
+        for pair in std::iter::once(&(0, 0)).chain(path.iter()).collect::<Vec<_>>().windows(2) {
+            let ((ax, ay), (bx, by)) = (*pair[0], *pair[1]);
+            assert!((ax - bx).abs() <= 1 && (ay - by).abs() <= 1, "Steps are one tile apart");
+        }
+    }
+
+    #[test]
+    fn test_path_never_cuts_corners() {
+        // Two rocks touching at a corner: the diagonal gap between them isn't flyable
+        let rocks = |x: i32, y: i32| !matches!((x, y), (1, 0) | (0, 1));
+        let path = find_path((0, 0), (1, 1), rocks);
+        assert!(path.is_none_or(|p| p.len() > 1), "Squeezed diagonally between two rocks");
+    }
+
+    #[test]
+    fn test_no_path_to_sealed_or_blocked_goal() {
+        let sealed = |x: i32, y: i32| (x.abs() > 2 || y.abs() > 2) || (x.abs() < 2 && y.abs() < 2);
+        assert_eq!(find_path((0, 0), (10, 10), sealed), None, "Boxed in by a ring of rock");
+        assert_eq!(find_path((0, 0), (5, 0), walled), None, "The goal is inside the wall");
+    }
+
+    // ==================== Autopilot Tests ====================
+
+    #[test]
+    fn test_autopilot_flies_route_to_destination() {
+        let mut autopilot = Autopilot::plan((0, 0), (10, 0), Diagonals::Uniform, walled).unwrap();
+        let planned = autopilot.remaining().count();
+        assert_eq!(autopilot.tick((0, 0), walled), NavStep::Cruise, "Moves a tile every few ticks");
+        let (ship, step) = fly(&mut autopilot, (0, 0), walled);
+        assert_eq!((ship, step), ((10, 0), NavStep::Arrived));
+        assert!(planned > 10);
+        assert_eq!(autopilot.remaining().count(), 0);
+    }
+
+    #[test]
+    fn test_autopilot_diagonal_speed() {
+        // Movement ticks to fly 120 tiles straight and 120 diagonally (170 tiles as the crow flies)
+        let ticks = |to: (i32, i32), diagonals| {
+            let mut autopilot = Autopilot::plan((0, 0), to, diagonals, |_, _| true).unwrap();
+            let mut ship = (0, 0);
+            (1..).find(|_| match autopilot.tick(ship, |_, _| true) {
+                NavStep::Move { x, y, .. } => {
+                    ship = (x, y);
+                    false
+                }
+                step => step == NavStep::Arrived,
+            })
+        };
+        let straight = ticks((120, 0), Diagonals::Uniform).unwrap() as f32;
+        let diagonal = ticks((120, 120), Diagonals::Uniform).unwrap() as f32;
+        let ratio = diagonal / straight;
+        assert!((ratio - std::f32::consts::SQRT_2).abs() < 0.02, "Same speed over the ground every way, took {} ticks", ratio);
+        let classic = ticks((120, 120), Diagonals::Classic).unwrap() as f32;
+        assert_eq!(classic, straight, "Classic diagonals take a tile a step");
+    }
+
+    #[test]
+    fn test_autopilot_stops_when_route_closes() {
+        let mut autopilot = Autopilot::plan((0, 0), (0, 4), Diagonals::Uniform, |_, _| true).unwrap();
+        let (ship, step) = fly(&mut autopilot, (0, 0), |_, y| y < 2);
+        assert_eq!((ship, step), ((0, 1), NavStep::Blocked));
+    }
+
+    #[test]
+    fn test_autopilot_rejects_bad_destinations() {
+        assert!(Autopilot::plan((0, 0), (5, 0), Diagonals::Uniform, walled).unwrap_err().contains("not passable"));
+        assert!(Autopilot::plan((0, 0), (MAX_NAV_DISTANCE + 1, 0), Diagonals::Uniform, walled).unwrap_err().contains("too far"));
+        let moat = |x: i32, _y: i32| x != 3;
+        assert!(Autopilot::plan((0, 0), (6, 0), Diagonals::Uniform, moat).unwrap_err().contains("No route"));
+    }
+}
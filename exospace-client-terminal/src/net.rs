@@ -0,0 +1,807 @@
+//! Multiplayer connection to the server's `/ws` endpoint.
+//!
+//! The socket lives on a background thread so the render loop never blocks
+//! on the network; the game talks to it through a pair of channels.
+//!
+//! Moves and tile edits arrive as numbered `WorldDelta`s. `RemotePlayers`
+//! applies them in order, drops ones it has already seen, and on a gap asks
+//! the game to send `Resync` so the server replies with a full `Snapshot`.
+//! The server only moves what's in range of our ship; `AreaChanged` brings
+//! ships into range and takes them out, and pilots out of range are kept
+//! (unseen) so they still count as online and their leaving is noticed.
+
+use exospace_core::ecs::{Entities, Entity, Position};
+use exospace_core::{
+    ClientMessage, ContentCatalog, Direction, EntityKind, EntityMove, NpcInfo, PilotStats, PlayerInfo, ServerMessage, ShipLivery, StationInfo,
+    TileChange,
+    WorldEvent,
+};
+use std::collections::HashMap;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use crate::netstats::NetCounters;
+
+/// How long the socket thread blocks on a read before checking for outgoing messages
+const READ_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Handle to the background websocket thread
+pub struct NetClient {
+    outgoing: Sender<ClientMessage>,
+    incoming: Receiver<ServerMessage>,
+    /// False once the socket thread has exited
+    pub connected: bool,
+    /// Traffic totals, kept by the socket thread
+    pub counters: Arc<NetCounters>,
+}
+
+impl NetClient {
+    /// Connect to the server's websocket and say hello, then show our livery; with a token the server uses the account name
+    pub fn connect(server_url: &str, name: &str, token: Option<&str>, ship: &ShipLivery) -> Result<Self, String> {
+        let mut request = ws_url(server_url)
+            .into_client_request()
+            .map_err(|e| format!("Bad server URL: {}", e))?;
+        if let Some(token) = token {
+            let value = crate::account::bearer(token).parse().map_err(|_| "Bad account token".to_string())?;
+            request.headers_mut().insert("Authorization", value);
+        }
+        let (mut socket, _) = tungstenite::connect(request)
+            .map_err(|e| format!("Failed to open websocket: {}", e))?;
+
+        if let MaybeTlsStream::Plain(stream) = socket.get_mut() {
+            stream
+                .set_read_timeout(Some(READ_TIMEOUT))
+                .map_err(|e| format!("Failed to configure socket: {}", e))?;
+        }
+
+        let counters = Arc::new(NetCounters::default());
+        let hello = ClientMessage::Hello { name: name.to_string() };
+        send_json(&mut socket, &hello, &counters).map_err(|e| format!("Failed to send hello: {}", e))?;
+        if !ship.is_empty() {
+            let livery = ClientMessage::Livery { ship: ship.clone() };
+            send_json(&mut socket, &livery, &counters).map_err(|e| format!("Failed to send livery: {}", e))?;
+        }
+
+        let (outgoing_tx, outgoing_rx) = mpsc::channel();
+        let (incoming_tx, incoming_rx) = mpsc::channel();
+        let thread_counters = counters.clone();
+        thread::spawn(move || run_socket(socket, outgoing_rx, incoming_tx, thread_counters));
+
+        Ok(NetClient {
+            outgoing: outgoing_tx,
+            incoming: incoming_rx,
+            connected: true,
+            counters,
+        })
+    }
+
+    /// Queue a message for the server
+    pub fn send(&self, message: ClientMessage) {
+        // A send error means the thread is gone; poll() will notice
+        let _ = self.outgoing.send(message);
+    }
+
+    /// Drain everything the server has sent since the last poll
+    pub fn poll(&mut self) -> Vec<ServerMessage> {
+        let mut messages = Vec::new();
+        loop {
+            match self.incoming.try_recv() {
+                Ok(message) => messages.push(message),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.connected = false;
+                    break;
+                }
+            }
+        }
+        messages
+    }
+}
+
+/// Turn the HTTP server URL into the websocket endpoint URL
+fn ws_url(server_url: &str) -> String {
+    let base = server_url.trim_end_matches('/');
+    let base = if let Some(rest) = base.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = base.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        base.to_string()
+    };
+    format!("{}/ws", base)
+}
+
+fn send_json(
+    socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
+    message: &ClientMessage,
+    counters: &NetCounters,
+) -> tungstenite::Result<()> {
+    let json = serde_json::to_string(message).expect("ClientMessage always serializes");
+    counters.sent(json.len());
+    socket.send(Message::text(json))
+}
+
+/// Socket thread: forward outgoing messages and relay incoming ones until either side hangs up
+fn run_socket(
+    mut socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    outgoing: Receiver<ClientMessage>,
+    incoming: Sender<ServerMessage>,
+    counters: Arc<NetCounters>,
+) {
+    loop {
+        loop {
+            match outgoing.try_recv() {
+                Ok(message) => {
+                    if send_json(&mut socket, &message, &counters).is_err() {
+                        return;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    let _ = socket.close(None);
+                    return;
+                }
+            }
+        }
+
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let parsed = serde_json::from_str::<ServerMessage>(&text);
+                counters.received(text.len(), parsed.is_ok());
+                if let Ok(message) = parsed
+                    && incoming.send(message).is_err()
+                {
+                    return;
+                }
+            }
+            Ok(Message::Close(_)) => return,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+/// Something from the server the player should see in chat
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetEvent {
+    /// Joins, leaves and other status lines
+    Notice(String),
+    /// A chat line from another player
+    Chat { id: u64, name: String, text: String },
+    /// Local chat or an emote from a pilot close by, for a bubble over their ship
+    Say { id: u64, name: String, text: String, emote: bool },
+    /// The server refused our last chat line or command
+    Rejected(String),
+    /// Result of a server-side utility command, already formatted
+    Utility { kind: UtilityKind, text: String },
+    /// Background chatter from a nearby station or relay
+    Ambient { source: String, text: String },
+    /// The server remembers where our account last was
+    Resume { x: i32, y: i32 },
+    /// Reply to `/respawn`: the server has moved us to a station
+    Respawned { station: String, x: i32, y: i32 },
+    /// The server refused a move; our ship is still at (x, y)
+    Corrected { x: i32, y: i32 },
+    /// Reply to `/stats`, for the dashboard
+    Stats { name: String, lifetime: Option<PilotStats>, session: PilotStats },
+    /// World tiles someone changed
+    Tiles(Vec<TileChange>),
+    /// Someone fired a projectile from (x, y)
+    Shot { id: u64, owner: u64, x: i32, y: i32, direction: Direction },
+    /// A projectile stopped
+    ShotEnded(u64),
+    /// A meteor shower or ion storm began, or was under way when we connected
+    EventStarted(WorldEvent),
+    EventEnded(u64),
+    /// A delta went missing; the server should be asked for a snapshot
+    Resync,
+    /// An admin closed our session, and why
+    Kicked(String),
+}
+
+/// Which utility command produced a result, so chat can color it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UtilityKind {
+    Roll,
+    Flip,
+    Time,
+    Distance,
+}
+
+/// Other players currently on the server, as last reported
+#[derive(Default)]
+pub struct RemotePlayers {
+    /// Our own session id once the server has welcomed us
+    pub my_id: Option<u64>,
+    players: HashMap<u64, PlayerInfo>,
+    /// Pilots online but out of range, where they were last seen
+    distant: HashMap<u64, PlayerInfo>,
+    /// Server-driven ships, from the latest snapshot, under the server's ids
+    npcs: Entities,
+    stations: Vec<StationInfo>,
+    /// The station our account calls home
+    home: Option<String>,
+    /// What the server's content packs define
+    content: ContentCatalog,
+    /// Number of the last world delta applied
+    seq: Option<u64>,
+    /// Waiting on a snapshot after a gap; deltas are dropped until it comes
+    resyncing: bool,
+    /// Whether we've already said the server speaks a newer protocol
+    warned_unknown: bool,
+    /// The server's clock when it welcomed us, and when that was
+    clock: Option<(u64, Instant)>,
+}
+
+impl RemotePlayers {
+    /// Apply a server message; returns an event worth showing in chat, if any
+    pub fn apply(&mut self, message: ServerMessage) -> Option<NetEvent> {
+        match message {
+            ServerMessage::Welcome { id, players, seq, unix_secs } => {
+                self.my_id = Some(id);
+                self.clock = (unix_secs > 0).then(|| (unix_secs, Instant::now()));
+                self.seq = Some(seq);
+                self.resyncing = false;
+                self.players = players.into_iter().map(|p| (p.id, p)).collect();
+                self.distant.clear();
+                Some(NetEvent::Notice(format!("Joined multiplayer ({} other pilots online)", self.players.len())))
+            }
+            ServerMessage::PlayerJoined { player } => {
+                if Some(player.id) == self.my_id {
+                    return None;
+                }
+                let notice = format!("{} joined", player.name);
+                self.distant.remove(&player.id);
+                self.players.insert(player.id, player);
+                Some(NetEvent::Notice(notice))
+            }
+            ServerMessage::PlayerMoved { id, x, y, direction } => {
+                if let Some(player) = self.players.get_mut(&id) {
+                    player.x = x;
+                    player.y = y;
+                    player.direction = direction;
+                }
+                None
+            }
+            ServerMessage::Livery { id, ship } => {
+                let player = self.players.get_mut(&id).or_else(|| self.distant.get_mut(&id))?;
+                let renamed = ship.name.as_ref().filter(|&name| player.ship.name.as_ref() != Some(name));
+                let notice = renamed.map(|name| NetEvent::Notice(format!("{} is flying the {}", player.name, name)));
+                player.ship = ship;
+                notice
+            }
+            ServerMessage::PlayerLeft { id } => {
+                self.players.remove(&id).or_else(|| self.distant.remove(&id)).map(|p| NetEvent::Notice(format!("{} left", p.name)))
+            }
+            ServerMessage::Chat { id, name, text } => Some(NetEvent::Chat { id, name, text }),
+            ServerMessage::Say { id, name, text, emote } => Some(NetEvent::Say { id, name, text, emote }),
+            ServerMessage::ChatRejected { reason } | ServerMessage::CommandFailed { reason } => {
+                Some(NetEvent::Rejected(reason))
+            }
+            ServerMessage::Roll { name, count, sides, rolls, total, .. } => {
+                let dice = rolls.iter().map(|r| r.to_string()).collect::<Vec<_>>().join(" + ");
+                let text = if rolls.len() > 1 {
+                    format!("[roll] {} rolled {}d{}: {} = {}", name, count, sides, dice, total)
+                } else {
+                    format!("[roll] {} rolled {}d{}: {}", name, count, sides, total)
+                };
+                Some(NetEvent::Utility { kind: UtilityKind::Roll, text })
+            }
+            ServerMessage::Flip { name, heads, .. } => Some(NetEvent::Utility {
+                kind: UtilityKind::Flip,
+                text: format!("[flip] {} flipped a coin: {}", name, if heads { "heads" } else { "tails" }),
+            }),
+            ServerMessage::Time { unix_secs } => Some(NetEvent::Utility {
+                kind: UtilityKind::Time,
+                text: format!("[time] Server time: {}", format_utc_time(unix_secs)),
+            }),
+            ServerMessage::Distance { target, distance } => Some(NetEvent::Utility {
+                kind: UtilityKind::Distance,
+                text: format!("[dist] {} is {} tiles away", target, distance),
+            }),
+            ServerMessage::Ambient { source, text } => Some(NetEvent::Ambient { source, text }),
+            ServerMessage::Resume { x, y } => Some(NetEvent::Resume { x, y }),
+            ServerMessage::Stations { stations, home } => {
+                self.stations = stations;
+                self.home = home;
+                None
+            }
+            ServerMessage::HomeSet { station } => {
+                let notice = format!("{} is now your home station", station);
+                self.home = Some(station);
+                Some(NetEvent::Notice(notice))
+            }
+            ServerMessage::Respawned { station, x, y } => Some(NetEvent::Respawned { station, x, y }),
+            ServerMessage::Corrected { x, y } => Some(NetEvent::Corrected { x, y }),
+            ServerMessage::Kicked { reason } => Some(NetEvent::Kicked(reason)),
+            ServerMessage::Shot { id, owner, x, y, direction } => Some(NetEvent::Shot { id, owner, x, y, direction }),
+            ServerMessage::ShotEnded { id, .. } => Some(NetEvent::ShotEnded(id)),
+            ServerMessage::EventStarted { event } => Some(NetEvent::EventStarted(event)),
+            ServerMessage::EventEnded { id } => Some(NetEvent::EventEnded(id)),
+            ServerMessage::Hit { target, destroyed: false } => Some(NetEvent::Notice(format!("Hit {}", target))),
+            ServerMessage::Hit { target, destroyed: true } => {
+                Some(NetEvent::Notice(format!("{} destroyed - it limps home for repairs", target)))
+            }
+            ServerMessage::Stats { name, lifetime, session } => Some(NetEvent::Stats { name, lifetime, session }),
+            ServerMessage::Npcs { ships } => {
+                self.set_npcs(&ships);
+                None
+            }
+            ServerMessage::WorldDelta { seq, moved, tiles } => match self.seq {
+                // Already reflected in what we have
+                Some(last) if seq <= last => None,
+                // Ask once, then wait for the snapshot
+                Some(last) if seq > last + 1 => (!std::mem::replace(&mut self.resyncing, true)).then_some(NetEvent::Resync),
+                _ => {
+                    self.seq = Some(seq);
+                    for entity in moved {
+                        self.move_entity(entity);
+                    }
+                    (!tiles.is_empty()).then_some(NetEvent::Tiles(tiles))
+                }
+            },
+            ServerMessage::AreaChanged { players, npcs, gone } => {
+                for player in players.into_iter().filter(|p| Some(p.id) != self.my_id) {
+                    self.distant.remove(&player.id);
+                    self.players.insert(player.id, player);
+                }
+                for npc in &npcs {
+                    self.npcs.put_npc(npc);
+                }
+                for entity in gone {
+                    match entity.kind {
+                        EntityKind::Player => {
+                            if let Some(player) = self.players.remove(&entity.id) {
+                                self.distant.insert(entity.id, player);
+                            }
+                        }
+                        EntityKind::Npc => {
+                            self.npcs.despawn(Entity(entity.id));
+                        }
+                    }
+                }
+                None
+            }
+            ServerMessage::Content { catalog } => {
+                self.content = catalog;
+                None
+            }
+            ServerMessage::Snapshot { seq, players, npcs } => {
+                self.seq = Some(seq);
+                self.resyncing = false;
+                self.players = players.into_iter().filter(|p| Some(p.id) != self.my_id).map(|p| (p.id, p)).collect();
+                self.distant.clear();
+                self.set_npcs(&npcs);
+                None
+            }
+            ServerMessage::Unknown if !self.warned_unknown => {
+                self.warned_unknown = true;
+                Some(NetEvent::Notice("The server sent something this client doesn't understand; try updating".into()))
+            }
+            ServerMessage::Unknown => None,
+        }
+    }
+
+    /// Move a known player or NPC; anyone we haven't heard of (ourselves included) is ignored
+    fn move_entity(&mut self, entity: EntityMove) {
+        match entity.kind {
+            EntityKind::Player => {
+                if let Some(player) = self.players.get_mut(&entity.id) {
+                    (player.x, player.y, player.direction) = (entity.x, entity.y, entity.direction);
+                }
+            }
+            EntityKind::Npc => {
+                let npc = Entity(entity.id);
+                if let (Some(position), Some(sprite)) = (self.npcs.positions.get_mut(npc), self.npcs.sprites.get_mut(npc)) {
+                    *position = Position { x: entity.x, y: entity.y };
+                    sprite.direction = entity.direction;
+                }
+            }
+        }
+    }
+
+    /// Replace every NPC ship with a snapshot's
+    fn set_npcs(&mut self, ships: &[NpcInfo]) {
+        self.npcs.clear();
+        for ship in ships {
+            self.npcs.put_npc(ship);
+        }
+    }
+
+    /// Forget everyone (e.g. after disconnecting)
+    pub fn clear(&mut self) {
+        self.my_id = None;
+        self.seq = None;
+        self.resyncing = false;
+        self.players.clear();
+        self.distant.clear();
+        self.npcs.clear();
+        self.stations.clear();
+        self.home = None;
+        self.content = ContentCatalog::default();
+        self.clock = None;
+    }
+
+    /// The server's time now, carried on from its welcome; None offline or from servers that don't send it
+    pub fn server_time(&self, now: Instant) -> Option<u64> {
+        self.clock.map(|(unix_secs, at)| unix_secs + now.saturating_duration_since(at).as_secs())
+    }
+
+    /// Pilots in range
+    pub fn iter(&self) -> impl Iterator<Item = &PlayerInfo> {
+        self.players.values()
+    }
+
+    /// Server-driven ships, as entities with a position, sprite and name
+    pub fn ships(&self) -> &Entities {
+        &self.npcs
+    }
+
+    pub fn stations(&self) -> impl Iterator<Item = &StationInfo> {
+        self.stations.iter()
+    }
+
+    pub fn content(&self) -> &ContentCatalog {
+        &self.content
+    }
+
+    pub fn home(&self) -> Option<&str> {
+        self.home.as_deref()
+    }
+
+    /// A pilot in range
+    pub fn get(&self, id: u64) -> Option<&PlayerInfo> {
+        self.players.get(&id)
+    }
+
+    /// Whether a pilot is online, in range or not
+    pub fn is_online(&self, id: u64) -> bool {
+        self.players.contains_key(&id) || self.distant.contains_key(&id)
+    }
+
+    /// Pilots online, in range or not
+    pub fn len(&self) -> usize {
+        self.players.len() + self.distant.len()
+    }
+}
+
+/// `/content` lines: the packs, then one line per kind of definition that has any
+pub fn describe_content(catalog: &ContentCatalog) -> Vec<String> {
+    if catalog.packs.is_empty() {
+        return vec!["The server hasn't sent any content packs".to_string()];
+    }
+    let join = |names: Vec<String>| names.join(", ");
+    let packs = catalog.packs.iter().map(|p| if p.version.is_empty() { p.name.clone() } else { format!("{} {}", p.name, p.version) });
+    let mut lines = vec![format!("Content packs: {}", join(packs.collect()))];
+    let mut add = |label: &str, names: Vec<String>| {
+        if !names.is_empty() {
+            lines.push(format!("  {}: {}", label, join(names)));
+        }
+    };
+    add("Items", catalog.items.iter().map(|i| i.name.clone()).collect());
+    add("Ships", catalog.ships.iter().map(|s| format!("{} (hull {}, cargo {})", s.name, s.hull, s.cargo)).collect());
+    add("Upgrades", catalog.upgrades.iter().map(|u| u.name.clone()).collect());
+    add("Missions", catalog.missions.iter().map(|m| m.name.clone()).collect());
+    lines
+}
+
+/// Time of day for a Unix timestamp, e.g. "13:05:09 UTC"
+fn format_utc_time(unix_secs: u64) -> String {
+    let secs = unix_secs % 86_400;
+    format!("{:02}:{:02}:{:02} UTC", secs / 3600, secs % 3600 / 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(id: u64, name: &str) -> PlayerInfo {
+        PlayerInfo { id, name: name.to_string(), x: 0, y: 0, direction: Direction::Up, ship: ShipLivery::default() }
+    }
+
+    #[test]
+    fn test_ws_url() {
+        assert_eq!(ws_url("http://localhost:3000"), "ws://localhost:3000/ws");
+        assert_eq!(ws_url("http://localhost:3000/"), "ws://localhost:3000/ws");
+        assert_eq!(ws_url("https://exo.example"), "wss://exo.example/ws");
+    }
+
+    #[test]
+    fn test_remote_players_welcome() {
+        let mut remote = RemotePlayers::default();
+        let notice = remote.apply(ServerMessage::Welcome { id: 3, players: vec![info(1, "Ace")], seq: 0, unix_secs: 0 });
+        assert_eq!(remote.my_id, Some(3));
+        assert_eq!(remote.len(), 1);
+        assert!(matches!(notice, Some(NetEvent::Notice(text)) if text.contains("1 other")));
+        assert_eq!(remote.server_time(Instant::now()), None, "An older server sends no clock");
+
+        remote.apply(ServerMessage::Welcome { id: 3, players: vec![], seq: 0, unix_secs: 1_000 });
+        assert_eq!(remote.server_time(Instant::now() + Duration::from_secs(90)), Some(1_090), "The server's clock runs on");
+        remote.clear();
+        assert_eq!(remote.server_time(Instant::now()), None);
+    }
+
+    #[test]
+    fn test_remote_players_join_move_leave() {
+        let mut remote = RemotePlayers::default();
+        remote.apply(ServerMessage::Welcome { id: 3, players: vec![], seq: 0, unix_secs: 0 });
+
+        assert_eq!(
+            remote.apply(ServerMessage::PlayerJoined { player: info(1, "Ace") }),
+            Some(NetEvent::Notice("Ace joined".into()))
+        );
+        assert_eq!(remote.len(), 1);
+
+        remote.apply(ServerMessage::PlayerMoved { id: 1, x: 5, y: 6, direction: Direction::Left });
+        let ace = remote.iter().next().unwrap();
+        assert_eq!((ace.x, ace.y, ace.direction), (5, 6, Direction::Left));
+
+        assert_eq!(remote.apply(ServerMessage::PlayerLeft { id: 1 }), Some(NetEvent::Notice("Ace left".into())));
+        assert_eq!(remote.len(), 0);
+    }
+
+    #[test]
+    fn test_remote_players_livery() {
+        let mut remote = RemotePlayers::default();
+        remote.apply(ServerMessage::Welcome { id: 3, players: vec![info(1, "Ace")], seq: 0, unix_secs: 0 });
+
+        let named = ShipLivery { name: Some("Rusty Bucket".into()), ..ShipLivery::default() };
+        assert_eq!(
+            remote.apply(ServerMessage::Livery { id: 1, ship: named.clone() }),
+            Some(NetEvent::Notice("Ace is flying the Rusty Bucket".into()))
+        );
+        assert_eq!(remote.get(1).unwrap().ship, named);
+
+        let painted = ShipLivery { callsign: Some("ACE".into()), ..named };
+        assert!(remote.apply(ServerMessage::Livery { id: 1, ship: painted.clone() }).is_none(), "Only a new name is worth a notice");
+        assert_eq!(remote.get(1).unwrap().label(), "[ACE] Ace");
+        assert!(remote.apply(ServerMessage::Livery { id: 9, ship: painted }).is_none(), "Unknown pilots are ignored");
+    }
+
+    #[test]
+    fn test_remote_players_ignores_self_and_unknown() {
+        let mut remote = RemotePlayers::default();
+        remote.apply(ServerMessage::Welcome { id: 3, players: vec![], seq: 0, unix_secs: 0 });
+
+        assert!(remote.apply(ServerMessage::PlayerJoined { player: info(3, "Me") }).is_none());
+        assert!(remote.apply(ServerMessage::PlayerMoved { id: 9, x: 1, y: 1, direction: Direction::Up }).is_none());
+        assert!(remote.apply(ServerMessage::PlayerLeft { id: 9 }).is_none());
+        assert_eq!(remote.len(), 0);
+    }
+
+    #[test]
+    fn test_remote_players_chat_events() {
+        let mut remote = RemotePlayers::default();
+        let chat = ServerMessage::Chat { id: 1, name: "Ace".into(), text: "hi".into() };
+        assert_eq!(remote.apply(chat), Some(NetEvent::Chat { id: 1, name: "Ace".into(), text: "hi".into() }));
+        let say = ServerMessage::Say { id: 1, name: "Ace".into(), text: "waves".into(), emote: true };
+        assert_eq!(remote.apply(say), Some(NetEvent::Say { id: 1, name: "Ace".into(), text: "waves".into(), emote: true }));
+
+        let rejected = ServerMessage::ChatRejected { reason: "slow down".into() };
+        assert_eq!(remote.apply(rejected), Some(NetEvent::Rejected("slow down".into())));
+    }
+
+    #[test]
+    fn test_remote_players_utility_events() {
+        let mut remote = RemotePlayers::default();
+
+        let roll = ServerMessage::Roll { id: 1, name: "Ace".into(), count: 2, sides: 6, rolls: vec![3, 5], total: 8 };
+        assert_eq!(
+            remote.apply(roll),
+            Some(NetEvent::Utility { kind: UtilityKind::Roll, text: "[roll] Ace rolled 2d6: 3 + 5 = 8".into() })
+        );
+
+        let single = ServerMessage::Roll { id: 1, name: "Ace".into(), count: 1, sides: 20, rolls: vec![17], total: 17 };
+        assert_eq!(
+            remote.apply(single),
+            Some(NetEvent::Utility { kind: UtilityKind::Roll, text: "[roll] Ace rolled 1d20: 17".into() }),
+            "A single die shouldn't repeat its value as a sum"
+        );
+
+        let flip = ServerMessage::Flip { id: 1, name: "Ace".into(), heads: false };
+        assert_eq!(
+            remote.apply(flip),
+            Some(NetEvent::Utility { kind: UtilityKind::Flip, text: "[flip] Ace flipped a coin: tails".into() })
+        );
+
+        let distance = ServerMessage::Distance { target: "Bravo".into(), distance: 42 };
+        assert_eq!(
+            remote.apply(distance),
+            Some(NetEvent::Utility { kind: UtilityKind::Distance, text: "[dist] Bravo is 42 tiles away".into() })
+        );
+
+        let ambient = ServerMessage::Ambient { source: "Haven".into(), text: "Lane clear".into() };
+        assert_eq!(remote.apply(ambient), Some(NetEvent::Ambient { source: "Haven".into(), text: "Lane clear".into() }));
+
+        let failed = ServerMessage::CommandFailed { reason: "No player named Zed".into() };
+        assert_eq!(remote.apply(failed), Some(NetEvent::Rejected("No player named Zed".into())));
+    }
+
+    #[test]
+    fn test_format_utc_time() {
+        assert_eq!(format_utc_time(0), "00:00:00 UTC");
+        // 2023-11-14 22:13:20 UTC
+        assert_eq!(format_utc_time(1_700_000_000), "22:13:20 UTC");
+    }
+
+    #[test]
+    fn test_resume_becomes_event() {
+        let mut remote = RemotePlayers::default();
+        assert_eq!(remote.apply(ServerMessage::Resume { x: 5, y: -6 }), Some(NetEvent::Resume { x: 5, y: -6 }));
+        assert_eq!(remote.apply(ServerMessage::Corrected { x: 1, y: 2 }), Some(NetEvent::Corrected { x: 1, y: 2 }));
+        assert_eq!(remote.apply(ServerMessage::Kicked { reason: "AFK".into() }), Some(NetEvent::Kicked("AFK".into())));
+    }
+
+    #[test]
+    fn test_shots_and_hits_become_events() {
+        let mut remote = RemotePlayers::default();
+        let shot = ServerMessage::Shot { id: 4, owner: 1, x: 2, y: 3, direction: Direction::Down };
+        assert_eq!(remote.apply(shot), Some(NetEvent::Shot { id: 4, owner: 1, x: 2, y: 3, direction: Direction::Down }));
+        assert_eq!(remote.apply(ServerMessage::ShotEnded { id: 4, x: 2, y: 9 }), Some(NetEvent::ShotEnded(4)));
+        let hit = ServerMessage::Hit { target: "Survey Drone".into(), destroyed: false };
+        assert_eq!(remote.apply(hit), Some(NetEvent::Notice("Hit Survey Drone".into())));
+        let destroyed = ServerMessage::Hit { target: "Survey Drone".into(), destroyed: true };
+        assert!(matches!(remote.apply(destroyed), Some(NetEvent::Notice(text)) if text.starts_with("Survey Drone destroyed")));
+    }
+
+    #[test]
+    fn test_world_events_become_events() {
+        let mut remote = RemotePlayers::default();
+        let storm = WorldEvent { id: 5, kind: exospace_core::EventKind::IonStorm, x: 1, y: 2, radius: 8, secs_left: 60 };
+        assert_eq!(remote.apply(ServerMessage::EventStarted { event: storm.clone() }), Some(NetEvent::EventStarted(storm)));
+        assert_eq!(remote.apply(ServerMessage::EventEnded { id: 5 }), Some(NetEvent::EventEnded(5)));
+    }
+
+    #[test]
+    fn test_unknown_messages_warn_once() {
+        let mut remote = RemotePlayers::default();
+        let message: ServerMessage = serde_json::from_str(r#"{"type":"npc_spawned","id":4,"x":1}"#).unwrap();
+        assert_eq!(message, ServerMessage::Unknown, "Newer message types still parse");
+        assert!(matches!(remote.apply(message.clone()), Some(NetEvent::Notice(text)) if text.contains("updating")));
+        assert!(remote.apply(message).is_none(), "Only the first unknown message is worth a notice");
+    }
+
+    #[test]
+    fn test_npc_snapshots_replace_the_last() {
+        use exospace_core::NpcBehavior;
+
+        let npc = |id, x| NpcInfo { id, name: "Drone".into(), x, y: 0, direction: Direction::Left, behavior: NpcBehavior::Wander };
+        let mut remote = RemotePlayers::default();
+        assert!(remote.apply(ServerMessage::Npcs { ships: vec![npc(1, 0), npc(2, 5)] }).is_none(), "NPC movement is silent");
+        assert!(remote.apply(ServerMessage::Npcs { ships: vec![npc(2, 6)] }).is_none());
+        let xs: Vec<(u64, i32)> = remote.ships().npcs().iter().map(|n| (n.id, n.x)).collect();
+        assert_eq!(xs, vec![(2, 6)]);
+        assert_eq!(remote.len(), 0, "NPCs aren't counted as pilots");
+        let moved = vec![EntityMove { kind: EntityKind::Npc, id: 2, x: 7, y: 1, direction: Direction::Right }];
+        remote.apply(ServerMessage::WorldDelta { seq: 1, moved, tiles: Vec::new() });
+        assert_eq!(remote.ships().npc_info(Entity(2)).map(|n| (n.x, n.y, n.direction)), Some((7, 1, Direction::Right)));
+
+        remote.clear();
+        assert_eq!(remote.ships().len(), 0);
+    }
+
+    #[test]
+    fn test_world_deltas_apply_in_order() {
+        use exospace_core::{Tile, TileChange};
+
+        let mut remote = RemotePlayers::default();
+        remote.apply(ServerMessage::Welcome { id: 3, players: vec![info(1, "Ace")], seq: 4, unix_secs: 0 });
+        let moved = |x| vec![EntityMove { kind: EntityKind::Player, id: 1, x, y: 2, direction: Direction::Left }];
+        let tiles = vec![TileChange { x: 9, y: 9, tile: Tile::Wall }];
+
+        let event = remote.apply(ServerMessage::WorldDelta { seq: 5, moved: moved(7), tiles: tiles.clone() });
+        assert_eq!(event, Some(NetEvent::Tiles(tiles)));
+        assert_eq!((remote.get(1).unwrap().x, remote.get(1).unwrap().direction), (7, Direction::Left));
+
+        assert!(remote.apply(ServerMessage::WorldDelta { seq: 5, moved: moved(1), tiles: Vec::new() }).is_none());
+        assert_eq!(remote.get(1).unwrap().x, 7, "A repeated delta is dropped");
+        let own = vec![EntityMove { kind: EntityKind::Player, id: 3, x: 0, y: 0, direction: Direction::Up }];
+        assert!(remote.apply(ServerMessage::WorldDelta { seq: 6, moved: own, tiles: Vec::new() }).is_none());
+        assert_eq!(remote.len(), 1, "Our own echo isn't a remote pilot");
+    }
+
+    #[test]
+    fn test_area_changes_hide_and_show_ships() {
+        use exospace_core::{EntityId, NpcBehavior};
+
+        let npc = NpcInfo { id: 4, name: "Drone".into(), x: 90, y: 0, direction: Direction::Left, behavior: NpcBehavior::Wander };
+        let mut remote = RemotePlayers::default();
+        remote.apply(ServerMessage::Welcome { id: 3, players: vec![info(1, "Ace"), info(2, "Bo")], seq: 0, unix_secs: 0 });
+        let gone = vec![EntityId { kind: EntityKind::Player, id: 1 }];
+        assert!(remote.apply(ServerMessage::AreaChanged { players: Vec::new(), npcs: vec![npc.clone()], gone }).is_none(), "Silent");
+        assert!(remote.get(1).is_none(), "Out of range pilots aren't drawn");
+        assert_eq!(remote.len(), 2, "But they're still online");
+        assert_eq!(remote.ships().len(), 1);
+
+        let ace = PlayerInfo { x: 70, ..info(1, "Ace") };
+        let gone = vec![EntityId { kind: EntityKind::Npc, id: 4 }];
+        remote.apply(ServerMessage::AreaChanged { players: vec![ace], npcs: Vec::new(), gone });
+        assert_eq!(remote.get(1).map(|p| p.x), Some(70), "Back in range where they are now");
+        assert_eq!(remote.ships().len(), 0);
+
+        remote.apply(ServerMessage::AreaChanged { players: Vec::new(), npcs: Vec::new(), gone: vec![EntityId { kind: EntityKind::Player, id: 2 }] });
+        assert_eq!(remote.apply(ServerMessage::PlayerLeft { id: 2 }), Some(NetEvent::Notice("Bo left".into())), "Leaving is noticed out of range too");
+        assert_eq!(remote.len(), 1);
+    }
+
+    #[test]
+    fn test_delta_gap_resyncs_from_snapshot() {
+        let mut remote = RemotePlayers::default();
+        remote.apply(ServerMessage::Welcome { id: 3, players: vec![info(1, "Ace")], seq: 1, unix_secs: 0 });
+        let moved = vec![EntityMove { kind: EntityKind::Player, id: 1, x: 50, y: 0, direction: Direction::Up }];
+        let skipped = ServerMessage::WorldDelta { seq: 3, moved, tiles: Vec::new() };
+        assert_eq!(remote.apply(skipped.clone()), Some(NetEvent::Resync), "Delta 2 went missing");
+        assert!(remote.apply(skipped).is_none(), "Only one resync request per gap");
+        assert_eq!(remote.get(1).unwrap().x, 0, "Deltas after a gap wait for the snapshot");
+
+        let ace = PlayerInfo { x: 50, ..info(1, "Ace") };
+        let me = info(3, "Me");
+        assert!(remote.apply(ServerMessage::Snapshot { seq: 3, players: vec![ace, me], npcs: Vec::new() }).is_none());
+        assert_eq!((remote.len(), remote.get(1).unwrap().x), (1, 50));
+        let moved = vec![EntityMove { kind: EntityKind::Player, id: 1, x: 51, y: 0, direction: Direction::Up }];
+        remote.apply(ServerMessage::WorldDelta { seq: 4, moved, tiles: Vec::new() });
+        assert_eq!(remote.get(1).unwrap().x, 51, "Back in step after the snapshot");
+    }
+
+    #[test]
+    fn test_content_catalog_is_kept_and_described() {
+        use exospace_core::{ItemDef, PackInfo, ShipClassDef};
+
+        let mut remote = RemotePlayers::default();
+        assert_eq!(describe_content(remote.content()), vec!["The server hasn't sent any content packs".to_string()]);
+        let catalog = ContentCatalog {
+            packs: vec![PackInfo { name: "core".into(), version: "0.1.0".into() }, PackInfo { name: "frontier".into(), version: String::new() }],
+            items: vec![ItemDef { id: "ore".into(), name: "Ore".into(), description: String::new() }],
+            ships: vec![ShipClassDef { id: "scout".into(), name: "Scout".into(), description: String::new(), hull: 100, cargo: 40 }],
+            ..ContentCatalog::default()
+        };
+        assert!(remote.apply(ServerMessage::Content { catalog }).is_none());
+        assert_eq!(
+            describe_content(remote.content()),
+            vec!["Content packs: core 0.1.0, frontier", "  Items: Ore", "  Ships: Scout (hull 100, cargo 40)"]
+        );
+        remote.clear();
+        assert!(remote.content().packs.is_empty());
+    }
+
+    #[test]
+    fn test_home_station_tracking() {
+        let station = |name: &str| StationInfo { name: name.into(), x: 0, y: 0 };
+        let mut remote = RemotePlayers::default();
+        let stations = vec![station("Haven Station"), station("Frontier Relay")];
+        assert!(remote.apply(ServerMessage::Stations { stations, home: None }).is_none());
+        assert_eq!(remote.stations().count(), 2);
+        assert_eq!(remote.home(), None);
+
+        let event = remote.apply(ServerMessage::HomeSet { station: "Frontier Relay".into() });
+        assert_eq!(event, Some(NetEvent::Notice("Frontier Relay is now your home station".into())));
+        assert_eq!(remote.home(), Some("Frontier Relay"));
+
+        remote.clear();
+        assert_eq!((remote.stations().count(), remote.home()), (0, None), "Stations are resent on reconnect");
+    }
+
+    #[test]
+    fn test_remote_players_clear() {
+        let mut remote = RemotePlayers::default();
+        remote.apply(ServerMessage::Welcome { id: 3, players: vec![info(1, "Ace")], seq: 0, unix_secs: 0 });
+        remote.clear();
+        assert!(remote.my_id.is_none());
+        assert_eq!(remote.len(), 0);
+    }
+
+    #[test]
+    fn test_connect_fails_without_server() {
+        // Port 9 (discard) on localhost is essentially never a websocket server
+        assert!(NetClient::connect("http://127.0.0.1:9", "Ace", None, &ShipLivery::default()).is_err());
+        assert!(NetClient::connect("http://127.0.0.1:9", "Ace", Some("abc"), &ShipLivery::default()).is_err());
+    }
+}
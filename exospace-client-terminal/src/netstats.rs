@@ -0,0 +1,250 @@
+//! Network diagnostics for the F4 overlay.
+//!
+//! The socket thread counts raw traffic in `NetCounters`; the game loop shows
+//! each server message to `NetDiagnostics` before applying it, and once a
+//! second both are turned into per-second rates. Corrections are position
+//! snaps: remote ships that jumped further than one update can move them,
+//! and the server putting our own ship somewhere else.
+
+use exospace_core::{EntityKind, ServerMessage};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::net::RemotePlayers;
+
+/// How often the overlay's rates are recomputed
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A remote ship moving further than this in one update has been snapped into place
+const SNAP_DISTANCE: i32 = 3;
+
+/// Running totals kept by the socket thread
+#[derive(Default)]
+pub struct NetCounters {
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    messages_in: AtomicU64,
+    /// Frames that weren't valid server messages
+    dropped: AtomicU64,
+}
+
+impl NetCounters {
+    pub fn received(&self, bytes: usize, parsed: bool) {
+        self.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+        let counter = if parsed { &self.messages_in } else { &self.dropped };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn sent(&self, bytes: usize) {
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn totals(&self) -> NetTotals {
+        NetTotals {
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+            messages_in: self.messages_in.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A snapshot of `NetCounters`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetTotals {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub messages_in: u64,
+    pub dropped: u64,
+}
+
+/// Per-second figures from the last complete sample
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetRates {
+    pub bytes_in: f32,
+    pub bytes_out: f32,
+    pub messages: f32,
+    /// Remote pilot position updates, from `PlayerMoved` or a `WorldDelta`
+    pub snapshots: f32,
+    pub corrections: f32,
+}
+
+/// State behind the network overlay
+pub struct NetDiagnostics {
+    pub visible: bool,
+    pub rates: NetRates,
+    /// Unparseable frames since connecting
+    pub dropped: u64,
+    /// Messages we parsed but had no use for (unknown types, ships we don't know about)
+    pub ignored: u64,
+    sample_start: Instant,
+    sample_totals: NetTotals,
+    snapshots: u32,
+    corrections: u32,
+}
+
+impl NetDiagnostics {
+    pub fn new(now: Instant) -> Self {
+        NetDiagnostics {
+            visible: false,
+            rates: NetRates::default(),
+            dropped: 0,
+            ignored: 0,
+            sample_start: now,
+            sample_totals: NetTotals::default(),
+            snapshots: 0,
+            corrections: 0,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Count a server message; call before `RemotePlayers::apply` so jumps can be measured
+    pub fn observe(&mut self, message: &ServerMessage, remote: &RemotePlayers) {
+        match message {
+            ServerMessage::PlayerMoved { id, x, y, .. } => self.observe_move(*id, *x, *y, remote),
+            ServerMessage::WorldDelta { moved, .. } => {
+                for entity in moved.iter().filter(|e| e.kind == EntityKind::Player && Some(e.id) != remote.my_id) {
+                    self.observe_move(entity.id, entity.x, entity.y, remote);
+                }
+            }
+            ServerMessage::PlayerLeft { id } if !remote.is_online(*id) => self.ignored += 1,
+            ServerMessage::Resume { .. } | ServerMessage::Snapshot { .. } => self.corrections += 1,
+            ServerMessage::Unknown => self.ignored += 1,
+            _ => {}
+        }
+    }
+
+    fn observe_move(&mut self, id: u64, x: i32, y: i32, remote: &RemotePlayers) {
+        self.snapshots += 1;
+        match remote.get(id) {
+            Some(player) if (player.x - x).abs().max((player.y - y).abs()) > SNAP_DISTANCE => self.corrections += 1,
+            Some(_) => {}
+            None => self.ignored += 1,
+        }
+    }
+
+    /// Turn the last second of counts into rates; does nothing until a full interval has passed
+    pub fn sample(&mut self, totals: NetTotals, now: Instant) {
+        let elapsed = now.duration_since(self.sample_start);
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+        let secs = elapsed.as_secs_f32();
+        let last = self.sample_totals;
+        self.rates = NetRates {
+            bytes_in: totals.bytes_in.saturating_sub(last.bytes_in) as f32 / secs,
+            bytes_out: totals.bytes_out.saturating_sub(last.bytes_out) as f32 / secs,
+            messages: totals.messages_in.saturating_sub(last.messages_in) as f32 / secs,
+            snapshots: self.snapshots as f32 / secs,
+            corrections: self.corrections as f32 / secs,
+        };
+        self.dropped = totals.dropped;
+        self.sample_totals = totals;
+        self.sample_start = now;
+        self.snapshots = 0;
+        self.corrections = 0;
+    }
+
+    /// Overlay text, one line per figure
+    pub fn lines(&self, connected: bool) -> Vec<String> {
+        if !connected {
+            return vec!["offline".to_string()];
+        }
+        let rates = &self.rates;
+        vec![
+            format!("in    {}", format_rate(rates.bytes_in)),
+            format!("out   {}", format_rate(rates.bytes_out)),
+            format!("msgs  {:.1}/s", rates.messages),
+            format!("snaps {:.1}/s", rates.snapshots),
+            format!("fixes {:.1}/s", rates.corrections),
+            format!("drop  {}  ign {}", self.dropped, self.ignored),
+        ]
+    }
+}
+
+/// Bytes per second, scaled to B/s or KB/s
+fn format_rate(bytes_per_sec: f32) -> String {
+    if bytes_per_sec < 1024.0 {
+        format!("{:.0} B/s", bytes_per_sec)
+    } else {
+        format!("{:.1} KB/s", bytes_per_sec / 1024.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::{Direction, EntityMove, PlayerInfo};
+
+    fn remote_with_ace() -> RemotePlayers {
+        let mut remote = RemotePlayers::default();
+        let ace = PlayerInfo { id: 1, name: "Ace".into(), x: 0, y: 0, direction: Direction::Up, ship: Default::default() };
+        remote.apply(ServerMessage::Welcome { id: 3, players: vec![ace], seq: 0, unix_secs: 0 });
+        remote
+    }
+
+    fn moved(id: u64, x: i32, y: i32) -> ServerMessage {
+        let moved = vec![EntityMove { kind: EntityKind::Player, id, x, y, direction: Direction::Right }];
+        ServerMessage::WorldDelta { seq: 1, moved, tiles: Vec::new() }
+    }
+
+    #[test]
+    fn test_counters_totals() {
+        let counters = NetCounters::default();
+        counters.received(100, true);
+        counters.received(7, false);
+        counters.sent(40);
+        assert_eq!(
+            counters.totals(),
+            NetTotals { bytes_in: 107, bytes_out: 40, messages_in: 1, dropped: 1 },
+            "Bad frames count as bytes but not as messages"
+        );
+    }
+
+    #[test]
+    fn test_sample_computes_rates() {
+        let start = Instant::now();
+        let mut diagnostics = NetDiagnostics::new(start);
+        let remote = remote_with_ace();
+        diagnostics.observe(&moved(1, 1, 0), &remote);
+        diagnostics.observe(&moved(1, 9, 0), &remote);
+
+        let totals = NetTotals { bytes_in: 4096, bytes_out: 1024, messages_in: 20, dropped: 2 };
+        diagnostics.sample(totals, start + Duration::from_millis(500));
+        assert_eq!(diagnostics.rates, NetRates::default(), "Rates wait for a full interval");
+
+        diagnostics.sample(totals, start + Duration::from_secs(2));
+        let rates = diagnostics.rates;
+        assert_eq!((rates.bytes_in, rates.bytes_out, rates.messages), (2048.0, 512.0, 10.0));
+        assert_eq!((rates.snapshots, rates.corrections), (1.0, 0.5), "The 9-tile jump is a correction");
+        assert_eq!(diagnostics.dropped, 2);
+
+        diagnostics.sample(totals, start + Duration::from_secs(3));
+        assert_eq!(diagnostics.rates, NetRates::default(), "Rates only cover the latest interval");
+    }
+
+    #[test]
+    fn test_observe_counts_ignored_messages() {
+        let mut diagnostics = NetDiagnostics::new(Instant::now());
+        let remote = remote_with_ace();
+        diagnostics.observe(&moved(9, 1, 1), &remote);
+        diagnostics.observe(&ServerMessage::PlayerLeft { id: 9 }, &remote);
+        diagnostics.observe(&ServerMessage::Unknown, &remote);
+        diagnostics.observe(&ServerMessage::PlayerLeft { id: 1 }, &remote);
+        assert_eq!(diagnostics.ignored, 3, "Unknown players and message types are ignored");
+    }
+
+    #[test]
+    fn test_overlay_lines() {
+        let mut diagnostics = NetDiagnostics::new(Instant::now());
+        assert_eq!(diagnostics.lines(false), vec!["offline"]);
+        diagnostics.rates.bytes_in = 3072.0;
+        diagnostics.rates.bytes_out = 200.0;
+        let lines = diagnostics.lines(true);
+        assert_eq!(lines[0], "in    3.0 KB/s");
+        assert_eq!(lines[1], "out   200 B/s");
+    }
+}
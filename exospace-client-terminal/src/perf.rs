@@ -0,0 +1,220 @@
+//! Frame pacing and the F3 performance overlay.
+//!
+//! `FramePacer` keeps frames on a schedule of `1 / target_fps`: the loop
+//! sleeps until the next deadline rather than for a fixed time, so the work a
+//! frame takes comes out of its sleep. A frame that runs past its deadline
+//! starts the schedule over instead of rushing the frames after it.
+//! `PerfStats` collects what each frame cost and turns it into per-second
+//! figures, the way `NetDiagnostics` does for traffic.
+
+use std::time::{Duration, Instant};
+
+/// Frame rate when none is configured
+pub const DEFAULT_TARGET_FPS: u32 = 60;
+
+/// Frame rates the settings accept
+pub const FPS_LIMITS: (u32, u32) = (10, 240);
+
+/// How often the overlay's figures are recomputed
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sleeps the main loop until each frame's deadline
+pub struct FramePacer {
+    frame: Duration,
+    /// When the next frame should start
+    deadline: Instant,
+}
+
+impl FramePacer {
+    pub fn new(fps: u32, now: Instant) -> Self {
+        let frame = frame_length(fps);
+        FramePacer { frame, deadline: now + frame }
+    }
+
+    /// Change the target; the frame under way keeps its deadline
+    pub fn set_fps(&mut self, fps: u32) {
+        self.frame = frame_length(fps);
+    }
+
+    /// How long to sleep before the next frame; zero when this one ran late
+    pub fn wait(&mut self, now: Instant) -> Duration {
+        let wait = self.deadline.saturating_duration_since(now);
+        self.deadline = self.deadline.max(now) + self.frame;
+        wait
+    }
+}
+
+fn frame_length(fps: u32) -> Duration {
+    Duration::from_secs(1) / fps.clamp(FPS_LIMITS.0, FPS_LIMITS.1)
+}
+
+/// Figures from the last complete sample
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PerfSample {
+    pub fps: f32,
+    /// Mean time from one frame's start to the next
+    pub frame_ms: f32,
+    pub worst_frame_ms: f32,
+    /// Mean time spent drawing and handing the frame to the terminal
+    pub render_ms: f32,
+    /// Map tiles drawn in the game view, per frame
+    pub tiles: u32,
+}
+
+/// State behind the performance overlay
+pub struct PerfStats {
+    pub visible: bool,
+    pub sample: PerfSample,
+    sample_start: Instant,
+    last_frame: Option<Instant>,
+    frames: u32,
+    /// Gaps between frame starts summed in `frame_time`
+    intervals: u32,
+    frame_time: Duration,
+    worst_frame: Duration,
+    render_time: Duration,
+    tiles: u64,
+}
+
+impl PerfStats {
+    pub fn new(now: Instant) -> Self {
+        PerfStats {
+            visible: false,
+            sample: PerfSample::default(),
+            sample_start: now,
+            last_frame: None,
+            frames: 0,
+            intervals: 0,
+            frame_time: Duration::ZERO,
+            worst_frame: Duration::ZERO,
+            render_time: Duration::ZERO,
+            tiles: 0,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Count a frame that started at `start`, spent `render` drawing and drew `tiles` map tiles
+    pub fn record(&mut self, start: Instant, render: Duration, tiles: u32) {
+        if let Some(last) = self.last_frame.replace(start) {
+            let frame = start.saturating_duration_since(last);
+            self.frame_time += frame;
+            self.intervals += 1;
+            self.worst_frame = self.worst_frame.max(frame);
+        }
+        self.frames += 1;
+        self.render_time += render;
+        self.tiles += tiles as u64;
+    }
+
+    /// Turn the last second of frames into figures; does nothing until a full interval has passed
+    pub fn sample(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.sample_start);
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+        let frames = self.frames.max(1);
+        self.sample = PerfSample {
+            fps: self.frames as f32 / elapsed.as_secs_f32(),
+            frame_ms: millis(self.frame_time) / self.intervals.max(1) as f32,
+            worst_frame_ms: millis(self.worst_frame),
+            render_ms: millis(self.render_time) / frames as f32,
+            tiles: (self.tiles / frames as u64) as u32,
+        };
+        self.sample_start = now;
+        self.frames = 0;
+        self.intervals = 0;
+        self.frame_time = Duration::ZERO;
+        self.worst_frame = Duration::ZERO;
+        self.render_time = Duration::ZERO;
+        self.tiles = 0;
+    }
+
+    /// Overlay text, one line per figure; `latency` is the last health ping's round trip
+    pub fn lines(&self, target_fps: u32, latency: Option<Duration>) -> Vec<String> {
+        let sample = &self.sample;
+        vec![
+            format!("fps   {:.1} / {}", sample.fps, target_fps),
+            format!("frame {:.1} ms", sample.frame_ms),
+            format!("worst {:.1} ms", sample.worst_frame_ms),
+            format!("draw  {:.1} ms", sample.render_ms),
+            format!("tiles {}", sample.tiles),
+            match latency {
+                Some(latency) => format!("ping  {} ms", latency.as_millis()),
+                None => "ping  --".to_string(),
+            },
+        ]
+    }
+}
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{GameClock, MockClock};
+
+    // ==================== Pacing Tests ====================
+
+    #[test]
+    fn test_pacer_sleeps_to_the_deadline() {
+        let mut clock = MockClock::new();
+        let mut pacer = FramePacer::new(50, clock.now());
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(pacer.wait(clock.now()), Duration::from_millis(15), "Work done comes out of the sleep");
+        clock.sleep(Duration::from_millis(15));
+        clock.advance(Duration::from_millis(12));
+        assert_eq!(pacer.wait(clock.now()), Duration::from_millis(8));
+    }
+
+    #[test]
+    fn test_pacer_late_frames_restart_the_schedule() {
+        let mut clock = MockClock::new();
+        let mut pacer = FramePacer::new(50, clock.now());
+        clock.advance(Duration::from_millis(70));
+        assert_eq!(pacer.wait(clock.now()), Duration::ZERO, "A late frame doesn't sleep");
+        clock.advance(Duration::from_millis(4));
+        assert_eq!(pacer.wait(clock.now()), Duration::from_millis(16), "The next deadline is a frame after the late one");
+
+        pacer.set_fps(1000);
+        clock.advance(Duration::from_millis(40));
+        assert_eq!(pacer.wait(clock.now()), Duration::ZERO);
+        assert_eq!(pacer.wait(clock.now()), Duration::from_secs(1) / FPS_LIMITS.1, "Targets are capped");
+    }
+
+    // ==================== Overlay Tests ====================
+
+    #[test]
+    fn test_stats_sample() {
+        let start = Instant::now();
+        let mut stats = PerfStats::new(start);
+        for (ms, render, tiles) in [(0, 2, 100), (10, 4, 300), (40, 6, 200)] {
+            stats.record(start + Duration::from_millis(ms), Duration::from_millis(render), tiles);
+        }
+        stats.sample(start + Duration::from_millis(500));
+        assert_eq!(stats.sample, PerfSample::default(), "Figures wait for a full interval");
+
+        stats.sample(start + Duration::from_secs(2));
+        let sample = stats.sample;
+        assert_eq!(sample.fps, 1.5);
+        assert_eq!((sample.frame_ms, sample.worst_frame_ms), (20.0, 30.0), "Frame times are between frame starts");
+        assert_eq!((sample.render_ms, sample.tiles), (4.0, 200));
+
+        stats.sample(start + Duration::from_secs(3));
+        assert_eq!(stats.sample.fps, 0.0, "Figures only cover the latest interval");
+    }
+
+    #[test]
+    fn test_overlay_lines() {
+        let mut stats = PerfStats::new(Instant::now());
+        stats.sample.fps = 59.94;
+        let lines = stats.lines(60, Some(Duration::from_millis(42)));
+        assert_eq!(lines[0], "fps   59.9 / 60");
+        assert_eq!(lines[5], "ping  42 ms");
+        assert_eq!(stats.lines(60, None)[5], "ping  --", "No latency offline");
+    }
+}
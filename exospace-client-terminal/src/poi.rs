@@ -0,0 +1,127 @@
+//! Named points of interest: stations, derelicts, beacons and jump gates.
+//!
+//! Streamed chunks carry the points of interest inside them, and they're
+//! remembered here as chunks arrive, even after the chunk itself is evicted.
+//! Saved solo maps keep theirs. Each kind has its own glyph in the view, and
+//! names are shown above the ones within `LABEL_RANGE` of the ship. `/poi`
+//! lists everything known, stations included, nearest first.
+
+use exospace_core::{Direction, Poi, PoiKind};
+use std::collections::BTreeMap;
+
+/// Points of interest this close to the ship, in tiles along either axis, get a name label
+pub const LABEL_RANGE: i32 = 24;
+
+/// Most points of interest `/poi` shows, nearest first
+pub const MAX_LISTED: usize = 10;
+
+/// Glyph and color for each kind of point of interest
+pub const fn style(kind: PoiKind) -> (char, u32) {
+    match kind {
+        PoiKind::Station => ('Ħ', 0xC0C0D0),
+        PoiKind::Derelict => ('¤', 0xB08860),
+        PoiKind::Beacon => ('✦', 0x60D0F0),
+        PoiKind::JumpGate => ('◎', 0xC080FF),
+        PoiKind::Unknown => ('◊', 0x909090),
+    }
+}
+
+/// Every point of interest found so far, by position
+#[derive(Default)]
+pub struct KnownPois {
+    pois: BTreeMap<(i32, i32), Poi>,
+}
+
+impl KnownPois {
+    pub fn add(&mut self, pois: &[Poi]) {
+        for poi in pois {
+            self.pois.insert((poi.x, poi.y), poi.clone());
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Poi> {
+        self.pois.values()
+    }
+}
+
+/// Straight-line distance in tiles, rounded
+pub fn distance(dx: i32, dy: i32) -> u32 {
+    (dx as f64).hypot(dy as f64).round() as u32
+}
+
+/// Compass direction of an offset, to the nearest of eight; None when there's no offset
+pub fn bearing(dx: i32, dy: i32) -> Option<Direction> {
+    if (dx, dy) == (0, 0) {
+        return None;
+    }
+    // Screen y grows downwards, so north is -y; sectors are 45 degrees centered on each direction
+    let angle = (dx as f64).atan2(-dy as f64).to_degrees().rem_euclid(360.0);
+    let sector = ((angle + 22.5) / 45.0) as usize % 8;
+    Some(
+        [
+            Direction::Up,
+            Direction::UpRight,
+            Direction::Right,
+            Direction::DownRight,
+            Direction::Down,
+            Direction::DownLeft,
+            Direction::Left,
+            Direction::UpLeft,
+        ][sector],
+    )
+}
+
+/// `/poi` output from `from`, nearest first, e.g. "Beacon Kappa-7 (beacon): 120 tiles NE at (80, -90)"
+pub fn listing<'a>(from: (i32, i32), pois: impl Iterator<Item = &'a Poi>) -> Vec<String> {
+    let mut pois: Vec<(u32, &Poi)> = pois.map(|poi| (distance(poi.x - from.0, poi.y - from.1), poi)).collect();
+    pois.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.name.cmp(&b.1.name)));
+    pois.into_iter()
+        .map(|(tiles, poi)| {
+            let heading = bearing(poi.x - from.0, poi.y - from.1).map_or("here", |direction| direction.name());
+            format!("{} ({}): {} tiles {} at ({}, {})", poi.name, poi.kind.name(), tiles, heading, poi.x, poi.y)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poi(kind: PoiKind, name: &str, x: i32, y: i32) -> Poi {
+        Poi { kind, name: name.to_string(), x, y }
+    }
+
+    // ==================== Bearing Tests ====================
+
+    #[test]
+    fn test_bearings_use_screen_north() {
+        assert_eq!(bearing(0, -10), Some(Direction::Up));
+        assert_eq!(bearing(10, 0), Some(Direction::Right));
+        assert_eq!(bearing(7, 7), Some(Direction::DownRight));
+        assert_eq!(bearing(-10, -1), Some(Direction::Left), "Close to due west rounds to west");
+        assert_eq!(bearing(-10, -6), Some(Direction::UpLeft));
+        assert_eq!(bearing(0, 0), None);
+        assert_eq!(distance(3, -4), 5);
+    }
+
+    // ==================== Listing Tests ====================
+
+    #[test]
+    fn test_listing_is_nearest_first() {
+        let mut known = KnownPois::default();
+        known.add(&[poi(PoiKind::Derelict, "Wreck of the Lost Lark", 100, 0), poi(PoiKind::Beacon, "Beacon Tau-2", 0, -20)]);
+        known.add(&[poi(PoiKind::Beacon, "Beacon Tau-3", 0, -20)]);
+        let station = poi(PoiKind::Station, "Haven Station", 0, 0);
+        let lines = listing((0, 0), known.iter().chain([&station]));
+        assert_eq!(
+            lines,
+            vec![
+                "Haven Station (station): 0 tiles here at (0, 0)",
+                "Beacon Tau-3 (beacon): 20 tiles N at (0, -20)",
+                "Wreck of the Lost Lark (derelict): 100 tiles E at (100, 0)",
+            ],
+            "A newer point of interest at the same spot replaces the old one"
+        );
+        assert_ne!(style(PoiKind::Derelict), style(PoiKind::Beacon));
+    }
+}
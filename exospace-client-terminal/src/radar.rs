@@ -0,0 +1,211 @@
+//! The radar: blips for everything the ship's sensors pick up around it.
+//!
+//! The scope is a circle `RADIUS` rows high and twice as wide, since cells
+//! are tall, with the ship in the middle. It reaches `RADAR_RANGE` tiles, or
+//! only `NEBULA_SENSOR_RANGE` while a nebula jams the sensors, and rings
+//! mark half and full range. Contacts are the ships, shots and points of
+//! interest the game view draws. With effects on, a beam sweeps round every
+//! `SWEEP_PERIOD`; each blip glows as the beam passes and fades until the
+//! next pass.
+
+use exospace_core::NEBULA_SENSOR_RANGE;
+use std::f32::consts::TAU;
+use std::time::{Duration, Instant};
+
+use crate::fog::Sensors;
+
+/// How far the radar reaches with clear sensors, in tiles
+pub const RADAR_RANGE: i32 = 40;
+
+/// Rows from the middle of the scope to its edge
+const RADIUS: i32 = 6;
+
+/// How long the beam takes to go round
+const SWEEP_PERIOD: Duration = Duration::from_secs(3);
+
+/// How far behind the beam its glow reaches, in radians
+const BEAM_WIDTH: f32 = 0.5;
+
+/// How much of its color a blip has lost by the time the beam comes round again
+const BLIP_FADE: f32 = 0.7;
+
+/// Glyph for a ship or shot on the scope
+pub const BLIP: char = '•';
+
+const RING_STYLE: (char, u32) = ('·', 0x206030);
+const BEAM_STYLE: (char, u32) = ('░', 0x1C6030);
+const EMPTY: (char, u32) = (' ', 0x000000);
+
+/// The radar widget's state
+pub struct Radar {
+    pub visible: bool,
+    started: Instant,
+}
+
+impl Radar {
+    pub fn new(now: Instant) -> Self {
+        Radar { visible: false, started: now }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Outer size as (cols, rows), border included; None if the game view is too small
+    pub fn size(term_width: u32, game_height: u32) -> Option<(u32, u32)> {
+        let (cols, rows) = (4 * RADIUS as u32 + 3, 2 * RADIUS as u32 + 3);
+        (term_width >= cols + 2 && game_height >= rows + 2).then_some((cols, rows))
+    }
+
+    /// How far the scope reaches with these sensors, in tiles
+    pub fn range(sensors: Sensors) -> i32 {
+        if sensors.jammed { NEBULA_SENSOR_RANGE } else { RADAR_RANGE }
+    }
+
+    /// The beam's heading, clockwise from straight up
+    fn sweep(&self, now: Instant) -> f32 {
+        let period = SWEEP_PERIOD.as_secs_f32();
+        now.saturating_duration_since(self.started).as_secs_f32() % period / period * TAU
+    }
+
+    /// The scope's cells row by row: range rings, the beam when `sweeping`, the ship in the middle
+    /// and a blip for each contact within `range` tiles of it
+    pub fn scope(
+        &self,
+        now: Instant,
+        sweeping: bool,
+        range: i32,
+        (ship_x, ship_y, ship): (i32, i32, (char, u32)),
+        contacts: impl IntoIterator<Item = ((i32, i32), (char, u32))>,
+    ) -> Vec<Vec<(char, u32)>> {
+        let sweep = self.sweep(now);
+        let size = (4 * RADIUS + 1, 2 * RADIUS + 1);
+        let mut cells: Vec<Vec<(char, u32)>> = (0..size.1)
+            .map(|row| (0..size.0).map(|col| background(col - 2 * RADIUS, row - RADIUS, sweeping.then_some(sweep))).collect())
+            .collect();
+
+        let range = range.max(1) as i64;
+        for ((x, y), (ch, color)) in contacts {
+            let (dx, dy) = (x as i64 - ship_x as i64, y as i64 - ship_y as i64);
+            if dx * dx + dy * dy > range * range {
+                continue;
+            }
+            let col = (dx as f32 * (2 * RADIUS) as f32 / range as f32).round() as i32;
+            let row = (dy as f32 * RADIUS as f32 / range as f32).round() as i32;
+            let color = if sweeping { fade(color, behind(sweep, col, row)) } else { color };
+            cells[(row + RADIUS) as usize][(col + 2 * RADIUS) as usize] = (ch, color);
+        }
+        cells[RADIUS as usize][2 * RADIUS as usize] = ship;
+        cells
+    }
+}
+
+/// How far from the middle of the scope a cell is, as a fraction of its radius
+fn reach(col: i32, row: i32) -> f32 {
+    (col as f32 / 2.0).hypot(row as f32) / RADIUS as f32
+}
+
+/// How far the beam at `sweep` has turned past a cell, in radians
+fn behind(sweep: f32, col: i32, row: i32) -> f32 {
+    let heading = (col as f32 / 2.0).atan2(-row as f32);
+    (sweep - heading).rem_euclid(TAU)
+}
+
+/// An empty cell of the scope: ring, beam or nothing
+fn background(col: i32, row: i32, sweep: Option<f32>) -> (char, u32) {
+    let reach = reach(col, row);
+    let tolerance = 0.5 / RADIUS as f32;
+    if reach > 1.0 + tolerance {
+        EMPTY
+    } else if [0.5, 1.0].iter().any(|ring| (reach - ring).abs() < tolerance) {
+        RING_STYLE
+    } else if sweep.is_some_and(|sweep| behind(sweep, col, row) < BEAM_WIDTH) {
+        BEAM_STYLE
+    } else {
+        EMPTY
+    }
+}
+
+/// A blip's color `behind` radians after the beam passed it
+fn fade(color: u32, behind: f32) -> u32 {
+    let keep = 1.0 - BLIP_FADE * behind / TAU;
+    [16, 8, 0].iter().fold(0, |faded, &shift| faded | (((color >> shift & 0xFF) as f32 * keep) as u32) << shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::Tile;
+
+    const SHIP: (char, u32) = ('↑', 0x80FFFF);
+    const CENTER: (usize, usize) = (RADIUS as usize, 2 * RADIUS as usize);
+
+    // ==================== Scope Tests ====================
+
+    #[test]
+    fn test_contacts_land_relative_to_the_ship() {
+        let now = Instant::now();
+        let radar = Radar::new(now);
+        let contacts = [((120, 100), (BLIP, 0xFF0000)), ((100, 60), ('H', 0x00FF00)), ((150, 100), (BLIP, 0x0000FF))];
+        let scope = radar.scope(now, false, 40, (100, 100, SHIP), contacts);
+
+        assert_eq!(scope.len(), 2 * RADIUS as usize + 1);
+        assert_eq!(scope[0].len(), 4 * RADIUS as usize + 1, "Twice as wide as it is tall");
+        assert_eq!(scope[CENTER.0][CENTER.1], SHIP, "The ship is in the middle");
+        assert_eq!(scope[CENTER.0][CENTER.1 + RADIUS as usize], (BLIP, 0xFF0000), "Half range east is halfway to the edge");
+        assert_eq!(scope[0][CENTER.1], ('H', 0x00FF00), "Full range north is the top edge");
+        assert!(scope.iter().flatten().all(|&(_, color)| color != 0x0000FF), "Out of range contacts are left off");
+    }
+
+    #[test]
+    fn test_rings_mark_half_and_full_range() {
+        let now = Instant::now();
+        let scope = Radar::new(now).scope(now, false, 40, (0, 0, SHIP), []);
+        assert_eq!(scope[0][CENTER.1], RING_STYLE);
+        assert_eq!(scope[CENTER.0][0], RING_STYLE, "The edge is as far across as it is up");
+        assert_eq!(scope[CENTER.0 - RADIUS as usize / 2][CENTER.1], RING_STYLE, "Half range");
+        assert_eq!(scope[CENTER.0 - RADIUS as usize / 2 - 1][CENTER.1], EMPTY);
+        assert_eq!(scope[0][0], EMPTY, "Corners are outside the scope");
+    }
+
+    #[test]
+    fn test_jammed_sensors_shrink_the_range() {
+        assert_eq!(Radar::range(Sensors::at(Some(Tile::Floor), 0, 0)), RADAR_RANGE);
+        assert_eq!(Radar::range(Sensors::at(Some(Tile::Nebula), 0, 0)), NEBULA_SENSOR_RANGE);
+        let now = Instant::now();
+        let scope = Radar::new(now).scope(now, false, NEBULA_SENSOR_RANGE, (0, 0, SHIP), [((NEBULA_SENSOR_RANGE, 0), (BLIP, 0xFF0000))]);
+        assert_eq!(scope[CENTER.0][4 * RADIUS as usize], (BLIP, 0xFF0000), "Close contacts fill the smaller scope");
+    }
+
+    // ==================== Sweep Tests ====================
+
+    #[test]
+    fn test_beam_sweeps_round() {
+        let now = Instant::now();
+        let radar = Radar::new(now);
+        let above = (CENTER.0 - 2, CENTER.1);
+        let below = (CENTER.0 + 2, CENTER.1);
+        let still = radar.scope(now, false, 40, (0, 0, SHIP), []);
+        assert!(still.iter().flatten().all(|&cell| cell != BEAM_STYLE), "No beam with effects off");
+
+        let start = radar.scope(now + Duration::from_millis(50), true, 40, (0, 0, SHIP), []);
+        assert_eq!(start[above.0][above.1], BEAM_STYLE, "The beam starts pointing up");
+        assert_eq!(start[below.0][below.1], EMPTY);
+        let half = radar.scope(now + SWEEP_PERIOD / 2 + Duration::from_millis(50), true, 40, (0, 0, SHIP), []);
+        assert_eq!(half[above.0][above.1], EMPTY);
+        assert_eq!(half[below.0][below.1], BEAM_STYLE, "Half a turn later it points down");
+    }
+
+    #[test]
+    fn test_blips_fade_after_the_beam_passes() {
+        let now = Instant::now();
+        let radar = Radar::new(now);
+        let contact = [((0, -20), (BLIP, 0xFFFFFF))];
+        let blip = (CENTER.0 - RADIUS as usize / 2, CENTER.1);
+        let fresh = radar.scope(now + Duration::from_millis(50), true, 40, (0, 0, SHIP), contact)[blip.0][blip.1].1;
+        let later = radar.scope(now + SWEEP_PERIOD * 3 / 4, true, 40, (0, 0, SHIP), contact)[blip.0][blip.1].1;
+        assert!(fresh & 0xFF > 0xF0, "Bright as the beam passes");
+        assert!(later & 0xFF < fresh & 0xFF, "Dimmer once it has gone by");
+        assert_eq!(fade(0xFFFFFF, 0.0), 0xFFFFFF);
+    }
+}
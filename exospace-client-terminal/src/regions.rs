@@ -0,0 +1,147 @@
+//! Named regions: the area the ship is in, and a banner on the way in.
+//!
+//! Streamed chunks each name the region they lie in (see
+//! `exospace_core::regions`), remembered here as they arrive, like points of
+//! interest. Local maps are surveyed as they're generated and saved maps keep
+//! theirs. The status bar shows the region under the ship and `/pos` says
+//! it; crossing into another one shows its name, danger and description over
+//! the view for `BANNER_TIME`. The region's biome changes how the view is
+//! drawn: tiles are tinted toward the theme's color for it (`Theme::biomes`)
+//! and the stars thin out or crowd in (`star_odds()`).
+
+use exospace_core::regions::{Biome, MAX_DANGER, REGION_SIZE, Region, region_coords};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// How long the banner stays up after entering a region
+pub const BANNER_TIME: Duration = Duration::from_secs(4);
+
+/// Color of a danger rating, green through red
+pub fn danger_color(danger: u8) -> u32 {
+    [0x60E060, 0xA0E060, 0xE0E060, 0xF0B040, 0xF07040, 0xFF4040][danger.min(MAX_DANGER) as usize]
+}
+
+/// Biome tints are one step of this many from a tile's color toward the biome's
+pub const TINT_STEPS: u32 = 4;
+
+/// One open tile in this many has a star: gas hides them, and station lights crowd the sky
+pub fn star_odds(biome: Biome) -> u32 {
+    match biome {
+        Biome::AsteroidBelt => 70,
+        Biome::NebulaCloud => 110,
+        Biome::DebrisField => 60,
+        Biome::StationSector => 35,
+        Biome::DeepSpace | Biome::Unknown => 50,
+    }
+}
+
+/// Which of `Theme::biomes` tints a biome; deep space is drawn as it is
+pub fn tint_index(biome: Biome) -> Option<usize> {
+    match biome {
+        Biome::AsteroidBelt => Some(0),
+        Biome::NebulaCloud => Some(1),
+        Biome::DebrisField => Some(2),
+        Biome::StationSector => Some(3),
+        Biome::DeepSpace | Biome::Unknown => None,
+    }
+}
+
+/// Every region heard of so far, by its top-left corner
+#[derive(Default)]
+pub struct KnownRegions {
+    regions: BTreeMap<(i32, i32), Region>,
+}
+
+impl KnownRegions {
+    pub fn add(&mut self, regions: &[Region]) {
+        for region in regions {
+            self.regions.insert((region.x, region.y), region.clone());
+        }
+    }
+
+    /// The region covering (x, y); they're normally whole grid squares, so that one is tried first
+    pub fn at(&self, x: i32, y: i32) -> Option<&Region> {
+        let (rx, ry) = region_coords(x, y);
+        self.regions
+            .get(&(rx * REGION_SIZE, ry * REGION_SIZE))
+            .filter(|region| region.contains(x, y))
+            .or_else(|| self.regions.values().find(|region| region.contains(x, y)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Region> {
+        self.regions.values()
+    }
+}
+
+/// The region the ship was last in, and the banner for it while it's up
+#[derive(Default)]
+pub struct RegionBanner {
+    current: Option<Region>,
+    shown_at: Option<Instant>,
+}
+
+impl RegionBanner {
+    /// Note where the ship is; a different region than last time puts the banner up
+    pub fn update(&mut self, here: Option<&Region>, now: Instant) {
+        if self.current.as_ref() == here {
+            return;
+        }
+        self.current = here.cloned();
+        self.shown_at = here.map(|_| now);
+    }
+
+    /// Banner title and text while it's up
+    pub fn lines(&self, now: Instant) -> Option<(String, Vec<String>)> {
+        let region = self.current.as_ref()?;
+        if now.saturating_duration_since(self.shown_at?) >= BANNER_TIME {
+            return None;
+        }
+        let mut lines = vec![format!("Danger: {} ({}/{})", region.danger_name(), region.danger.min(MAX_DANGER), MAX_DANGER)];
+        if !region.description.is_empty() {
+            lines.push(region.description.clone());
+        }
+        Some((format!(" {} ", region.name.to_uppercase()), lines))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(name: &str, x: i32, y: i32, size: u32) -> Region {
+        Region { name: name.to_string(), x, y, width: size, height: size, biome: Biome::AsteroidBelt, danger: 3, description: "Rocks.".to_string() }
+    }
+
+    // ==================== Lookup Tests ====================
+
+    #[test]
+    fn test_regions_by_position() {
+        let mut known = KnownRegions::default();
+        known.add(&[region("Kessler Belt", -128, 0, 128), region("Small", 1000, 1000, 10)]);
+        known.add(&[region("Kessler Belt", -128, 0, 128)]);
+        assert_eq!(known.iter().count(), 2, "A region heard of twice is kept once");
+        assert_eq!(known.at(-1, 127).map(|region| region.name.as_str()), Some("Kessler Belt"));
+        assert_eq!(known.at(1005, 1009).map(|region| region.name.as_str()), Some("Small"), "Off the grid");
+        assert!(known.at(0, 0).is_none());
+    }
+
+    // ==================== Banner Tests ====================
+
+    #[test]
+    fn test_banner_on_entering() {
+        let now = Instant::now();
+        let belt = region("Kessler Belt", 0, 0, 128);
+        let mut banner = RegionBanner::default();
+        banner.update(Some(&belt), now);
+        let (title, lines) = banner.lines(now).unwrap();
+        assert_eq!(title, " KESSLER BELT ");
+        assert_eq!(lines, vec!["Danger: high (3/5)", "Rocks."]);
+        assert!(banner.lines(now + BANNER_TIME).is_none(), "Gone after a while");
+
+        banner.update(Some(&belt), now + BANNER_TIME * 2);
+        assert!(banner.lines(now + BANNER_TIME * 2).is_none(), "Staying put doesn't bring it back");
+        banner.update(None, now + BANNER_TIME * 2);
+        banner.update(Some(&belt), now + BANNER_TIME * 3);
+        assert!(banner.lines(now + BANNER_TIME * 3).is_some(), "Coming back in does");
+    }
+}
@@ -0,0 +1,172 @@
+//! Saved solo maps for `/savemap`, `/loadmap` and `--offline`.
+//!
+//! A save is one file, `~/.config/exospace/maps/<name>.map`, holding a
+//! locally generated map's tiles (with the ship's position as the start),
+//! its seed and its explored tiles. Streamed worlds live on the server and
+//! aren't saved here. `--offline` starts from the most recently written save.
+
+use exospace_core::MapData;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::fog::ExploredTiles;
+
+const SAVE_MAGIC: &[u8; 4] = b"EXOM";
+const SAVE_VERSION: u8 = 1;
+const SAVE_EXTENSION: &str = "map";
+
+/// Longest save name accepted
+pub const MAX_SAVE_NAME_LEN: usize = 32;
+
+/// A solo map as written to disk
+pub struct SavedMap {
+    pub seed: u64,
+    pub map: MapData,
+    pub explored: ExploredTiles,
+}
+
+impl SavedMap {
+    /// Encode as magic, version, seed, map length, the map's binary encoding, then the exploration bitsets
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let map = self.map.to_bytes();
+        let mut out = Vec::with_capacity(17 + map.len());
+        out.extend_from_slice(SAVE_MAGIC);
+        out.push(SAVE_VERSION);
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.extend_from_slice(&(map.len() as u32).to_le_bytes());
+        out.extend_from_slice(&map);
+        out.extend_from_slice(&self.explored.to_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let rest = bytes.strip_prefix(SAVE_MAGIC).ok_or("Not a saved map")?;
+        let (&version, rest) = rest.split_first().ok_or("Saved map is truncated")?;
+        if version != SAVE_VERSION {
+            return Err(format!("Unsupported saved map version {}", version));
+        }
+        let (seed, rest) = rest.split_at_checked(8).ok_or("Saved map is truncated")?;
+        let (map_len, rest) = rest.split_at_checked(4).ok_or("Saved map is truncated")?;
+        let map_len = u32::from_le_bytes(map_len.try_into().unwrap()) as usize;
+        let (map, explored) = rest.split_at_checked(map_len).ok_or("Saved map is truncated")?;
+        Ok(SavedMap {
+            seed: u64::from_le_bytes(seed.try_into().unwrap()),
+            map: MapData::from_bytes(map).map_err(|e| format!("Bad map in save: {:?}", e))?,
+            explored: ExploredTiles::from_bytes(explored)?,
+        })
+    }
+}
+
+/// Names become file names, so they stay short and plain
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_SAVE_NAME_LEN {
+        return Err(format!("Save names are 1-{} characters", MAX_SAVE_NAME_LEN));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err("Save names use letters, digits, - and _".to_string());
+    }
+    Ok(())
+}
+
+/// Directory holding saved maps
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|mut p| {
+        p.push("exospace");
+        p.push("maps");
+        p
+    })
+}
+
+pub fn save(name: &str, saved: &SavedMap) -> Result<(), String> {
+    check_name(name)?;
+    let dir = dir().ok_or_else(|| "Could not determine config directory".to_string())?;
+    save_to(&dir, name, saved)
+}
+
+pub fn save_to(dir: &Path, name: &str, saved: &SavedMap) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create maps directory: {}", e))?;
+    let path = dir.join(name).with_extension(SAVE_EXTENSION);
+    fs::write(path, saved.to_bytes()).map_err(|e| format!("Failed to write saved map: {}", e))
+}
+
+pub fn load(name: &str) -> Result<SavedMap, String> {
+    check_name(name)?;
+    let dir = dir().ok_or_else(|| "Could not determine config directory".to_string())?;
+    load_from(&dir, name)
+}
+
+pub fn load_from(dir: &Path, name: &str) -> Result<SavedMap, String> {
+    let path = dir.join(name).with_extension(SAVE_EXTENSION);
+    let bytes = fs::read(&path).map_err(|_| format!("No saved map named '{}'", name))?;
+    SavedMap::from_bytes(&bytes)
+}
+
+/// Name of the most recently written save, if any
+pub fn latest() -> Option<String> {
+    latest_in(&dir()?)
+}
+
+pub fn latest_in(dir: &Path) -> Option<String> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == SAVE_EXTENSION))
+        .filter_map(|entry| {
+            let modified = entry.metadata().and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+            let name = entry.path().file_stem()?.to_str()?.to_string();
+            Some((modified, name))
+        })
+        .max()
+        .map(|(_, name)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::Tile;
+
+    fn sample() -> SavedMap {
+        let mut explored = ExploredTiles::default();
+        explored.mark(1, 2);
+        let tiles = vec![Tile::Wall, Tile::Floor, Tile::OreAsteroid, Tile::Nebula, Tile::Floor, Tile::Wall];
+        SavedMap { seed: 777, map: MapData { tiles, width: 3, height: 2, start_x: 1, start_y: 1, pois: Vec::new(), regions: Vec::new() }, explored }
+    }
+
+    // ==================== Save File Tests ====================
+
+    #[test]
+    fn test_saved_map_round_trip() {
+        let bytes = sample().to_bytes();
+        let loaded = SavedMap::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.seed, 777);
+        assert_eq!((loaded.map.width, loaded.map.height, loaded.map.start_x), (3, 2, 1));
+        assert_eq!(loaded.map.tiles, sample().map.tiles);
+        assert!(loaded.explored.is_explored(1, 2));
+        assert_eq!(loaded.explored.count(), 1);
+
+        assert!(SavedMap::from_bytes(&bytes[..20]).is_err(), "Truncated saves are refused");
+        assert!(SavedMap::from_bytes(b"EXOF").is_err());
+    }
+
+    #[test]
+    fn test_save_names() {
+        assert!(check_name("belt-run_2").is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name("../config").is_err(), "Names can't leave the maps directory");
+        assert!(check_name(&"x".repeat(MAX_SAVE_NAME_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_save_load_and_latest() {
+        let dir = std::env::temp_dir().join(format!("exospace-saves-{}", std::process::id()));
+        assert_eq!(latest_in(&dir), None, "No directory, no saves");
+        save_to(&dir, "first", &sample()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        save_to(&dir, "second", &sample()).unwrap();
+        assert_eq!(load_from(&dir, "first").unwrap().seed, 777);
+        assert!(load_from(&dir, "third").is_err_and(|e| e.contains("No saved map")));
+        assert_eq!(latest_in(&dir), Some("second".to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
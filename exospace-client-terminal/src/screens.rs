@@ -0,0 +1,654 @@
+//! The screen stack: which screen has the keyboard and what's drawn over the view.
+//!
+//! The game itself (flying, chat and the world view) is the bottom of the
+//! stack and never leaves it. Everything else is a `Screen` pushed on top:
+//! the title menu, the shipyard menu, the docked screen, the stats panel, the
+//! leaderboard, the community map list, the warp through a jump gate and game over. Only the top screen gets keys and ticks; each answers with a
+//! `Transition` for the `ScreenManager` to carry out, and every screen on the
+//! stack is drawn as a panel over the view, bottom first. Screens that cover
+//! the view (the title, the warp) also hide the HUD widgets over it. A new
+//! screen is a `Screen` impl and a push; the game loop doesn't change.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::community::{CommunityScreen, MapPick};
+use crate::docking::{DockInput, DockedScreen, MarketCall};
+use crate::gates::WarpScreen;
+use crate::hull::{self, ShipStatus};
+use crate::keys::Action;
+use crate::leaderboard::LeaderboardScreen;
+use crate::mining::Miner;
+use crate::ships::{self, ShipClass};
+use crate::stats::StatsScreen;
+use crate::title::{TitleChoice, TitleScreen};
+use crate::{ChatCommand, ChatMessage, ChatWindow, Config, Map, PanelColors};
+use exospace_core::LeaderboardMetric;
+
+/// A key as the screens see it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Enter,
+    Esc,
+    Backspace,
+    Other,
+}
+
+/// A key press, with the game action it's bound to, if any
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyPress {
+    pub key: Key,
+    pub action: Option<Action>,
+}
+
+/// The game state screens may read and change
+pub struct ScreenContext<'a> {
+    pub ship: &'a mut ShipStatus,
+    pub miner: &'a mut Miner,
+    pub map: &'a Map,
+    pub chat: &'a mut ChatWindow,
+    /// Commands for the game loop to run, as if typed
+    pub commands: &'a mut VecDeque<ChatCommand>,
+    /// Saved with `Config::save()` by screens that change it
+    pub config: &'a mut Config,
+}
+
+/// Which screen a `Screen` is, for closing one by kind
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScreenKind {
+    Title,
+    ShipMenu,
+    Docked,
+    Stats,
+    Leaderboard,
+    CommunityMaps,
+    Settings,
+    Warp,
+    GameOver,
+}
+
+/// What a screen wants done after a key or a tick
+pub enum Transition {
+    Stay,
+    /// Close this screen, uncovering the one below
+    Pop,
+    /// Close this screen and open another in its place
+    Replace(Box<dyn Screen>),
+}
+
+/// A boxed panel drawn over the game view
+#[derive(Clone, Debug, PartialEq)]
+pub struct Panel {
+    pub title: &'static str,
+    pub lines: Vec<String>,
+    pub colors: PanelColors,
+    /// Drawn on a plane of its own rather than straight onto the view
+    pub own_plane: bool,
+    /// Drawn over the whole view first, if anything
+    pub backdrop: Option<Backdrop>,
+}
+
+impl Panel {
+    pub fn new(title: &'static str, lines: Vec<String>, colors: PanelColors) -> Self {
+        Panel { title, lines, colors, own_plane: false, backdrop: None }
+    }
+}
+
+/// What a panel blacks out the view with, this far into its animation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backdrop {
+    /// The title's drifting starfield (`title::starfield()`)
+    Starfield(Duration),
+    /// Warp streaks (`gates::streaks()`); just black with effects off
+    Warp(Duration),
+}
+
+pub trait Screen {
+    fn kind(&self) -> ScreenKind;
+
+    fn handle_input(&mut self, press: KeyPress, ctx: &mut ScreenContext) -> Transition;
+
+    /// Called once a frame while this screen is on top
+    fn update(&mut self, _now: Instant, _ctx: &mut ScreenContext) -> Transition {
+        Transition::Stay
+    }
+
+    fn render(&self, ctx: &ScreenContext) -> Panel;
+
+    /// Whether the ship holds still while this screen is open
+    fn holds_ship(&self) -> bool {
+        false
+    }
+
+    /// Status bar tag, e.g. "[DOCKED]"
+    fn indicator(&self) -> &'static str {
+        ""
+    }
+
+    /// Whether this screen hides the view, HUD widgets and all
+    fn covers_view(&self) -> bool {
+        false
+    }
+}
+
+/// The screens over the game, bottom first
+#[derive(Default)]
+pub struct ScreenManager {
+    stack: Vec<Box<dyn Screen>>,
+}
+
+impl ScreenManager {
+    pub fn push(&mut self, screen: Box<dyn Screen>) {
+        self.stack.push(screen);
+    }
+
+    /// True when the game has the keyboard
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    pub fn is_open(&self, kind: ScreenKind) -> bool {
+        self.stack.iter().any(|screen| screen.kind() == kind)
+    }
+
+    /// Close every screen of one kind, wherever it is on the stack
+    pub fn close(&mut self, kind: ScreenKind) {
+        self.stack.retain(|screen| screen.kind() != kind);
+    }
+
+    pub fn handle_input(&mut self, press: KeyPress, ctx: &mut ScreenContext) {
+        if let Some(top) = self.stack.last_mut() {
+            let transition = top.handle_input(press, ctx);
+            self.apply(transition);
+        }
+    }
+
+    pub fn update(&mut self, now: Instant, ctx: &mut ScreenContext) {
+        if let Some(top) = self.stack.last_mut() {
+            let transition = top.update(now, ctx);
+            self.apply(transition);
+        }
+    }
+
+    fn apply(&mut self, transition: Transition) {
+        match transition {
+            Transition::Stay => {}
+            Transition::Pop => {
+                self.stack.pop();
+            }
+            Transition::Replace(screen) => {
+                self.stack.pop();
+                self.stack.push(screen);
+            }
+        }
+    }
+
+    /// Every open screen's panel, bottom first
+    pub fn render(&self, ctx: &ScreenContext) -> Vec<Panel> {
+        self.stack.iter().map(|screen| screen.render(ctx)).collect()
+    }
+
+    pub fn holds_ship(&self) -> bool {
+        self.stack.iter().any(|screen| screen.holds_ship())
+    }
+
+    /// The topmost screen's status bar tag
+    pub fn indicator(&self) -> &'static str {
+        self.stack.iter().rev().map(|screen| screen.indicator()).find(|tag| !tag.is_empty()).unwrap_or("")
+    }
+
+    pub fn covers_view(&self) -> bool {
+        self.stack.iter().any(|screen| screen.covers_view())
+    }
+}
+
+impl Screen for TitleScreen {
+    fn kind(&self) -> ScreenKind {
+        ScreenKind::Title
+    }
+
+    /// Starting a game closes the menu, handing over to the shipyard on a first run; the leaderboards
+    /// and settings open over it
+    fn handle_input(&mut self, press: KeyPress, ctx: &mut ScreenContext) -> Transition {
+        let command = match self.input(press.key, ctx.config.server_url()) {
+            None => return Transition::Stay,
+            Some(TitleChoice::Continue) => ChatCommand::Continue,
+            Some(TitleChoice::NewGame) => ChatCommand::NewGame,
+            Some(TitleChoice::Connect(url)) => ChatCommand::Connect(url, None),
+            Some(TitleChoice::Leaderboards) => ChatCommand::Leaderboard(LeaderboardMetric::Distance, 1),
+            Some(TitleChoice::Settings) => ChatCommand::Settings,
+            Some(TitleChoice::Quit) => ChatCommand::Quit,
+        };
+        let starts = matches!(command, ChatCommand::Continue | ChatCommand::NewGame | ChatCommand::Connect(..));
+        ctx.commands.push_back(command);
+        if !starts {
+            return Transition::Stay;
+        }
+        self.take_then().map_or(Transition::Pop, Transition::Replace)
+    }
+
+    fn update(&mut self, now: Instant, ctx: &mut ScreenContext) -> Transition {
+        self.tick(now, ctx.config.server_url());
+        Transition::Stay
+    }
+
+    fn render(&self, _ctx: &ScreenContext) -> Panel {
+        let panel = Panel::new(" EXOSPACE ", self.lines(), (0x6080C0, 0xC0E0FF, 0x000018));
+        Panel { backdrop: Some(Backdrop::Starfield(self.elapsed())), ..panel }
+    }
+
+    fn holds_ship(&self) -> bool {
+        true
+    }
+
+    fn covers_view(&self) -> bool {
+        true
+    }
+}
+
+/// Picking a ship class; shown at startup until the config has one
+pub struct ShipMenuScreen;
+
+impl Screen for ShipMenuScreen {
+    fn kind(&self) -> ScreenKind {
+        ScreenKind::ShipMenu
+    }
+
+    /// A class's digit picks it; Esc keeps the scout
+    fn handle_input(&mut self, press: KeyPress, ctx: &mut ScreenContext) -> Transition {
+        let choice = match press.key {
+            Key::Char(key) => ships::menu_choice(key),
+            Key::Esc => Some(ShipClass::Scout),
+            _ => None,
+        };
+        match choice {
+            Some(class) => {
+                ctx.commands.push_back(ChatCommand::SetShip(class));
+                Transition::Pop
+            }
+            None => Transition::Stay,
+        }
+    }
+
+    fn render(&self, _ctx: &ScreenContext) -> Panel {
+        Panel::new(" SHIPYARD ", ships::menu_lines(), (0x808080, 0x80FFFF, 0x000010))
+    }
+}
+
+/// The wreck, until a key respawns a fresh ship at the start
+pub struct GameOverScreen;
+
+impl Screen for GameOverScreen {
+    fn kind(&self) -> ScreenKind {
+        ScreenKind::GameOver
+    }
+
+    fn handle_input(&mut self, _press: KeyPress, ctx: &mut ScreenContext) -> Transition {
+        *ctx.ship = ShipStatus::new(ctx.ship.max_hull);
+        let (x, y) = ctx.map.find_start_position();
+        ctx.commands.push_back(ChatCommand::Teleport(x, y));
+        Transition::Pop
+    }
+
+    fn render(&self, _ctx: &ScreenContext) -> Panel {
+        Panel::new(" GAME OVER ", hull::game_over_lines(), (0xFF4040, 0xFFC0C0, 0x100000))
+    }
+
+    fn holds_ship(&self) -> bool {
+        true
+    }
+}
+
+impl Screen for WarpScreen {
+    fn kind(&self) -> ScreenKind {
+        ScreenKind::Warp
+    }
+
+    /// Nothing to do but wait for the far side
+    fn handle_input(&mut self, _press: KeyPress, _ctx: &mut ScreenContext) -> Transition {
+        Transition::Stay
+    }
+
+    /// Queues the map swap under the streaks, then ends once the far side is in
+    fn update(&mut self, now: Instant, ctx: &mut ScreenContext) -> Transition {
+        if let Some((seed, (x, y))) = self.tick(now) {
+            ctx.commands.push_back(ChatCommand::Arrive(seed, x, y));
+        }
+        let (seed, (x, y)) = self.destination();
+        let arrived = ctx.map.seed == seed && ctx.map.get(x, y).is_some();
+        if self.is_over(arrived) { Transition::Pop } else { Transition::Stay }
+    }
+
+    fn render(&self, _ctx: &ScreenContext) -> Panel {
+        let panel = Panel::new(" WARP ", self.lines(), (0xA060FF, 0xE0D0FF, 0x08001A));
+        Panel { backdrop: Some(Backdrop::Warp(self.elapsed())), ..panel }
+    }
+
+    fn holds_ship(&self) -> bool {
+        true
+    }
+
+    fn indicator(&self) -> &'static str {
+        "[WARP]"
+    }
+
+    fn covers_view(&self) -> bool {
+        true
+    }
+}
+
+impl Screen for StatsScreen {
+    fn kind(&self) -> ScreenKind {
+        ScreenKind::Stats
+    }
+
+    /// Any key closes the dashboard
+    fn handle_input(&mut self, _press: KeyPress, _ctx: &mut ScreenContext) -> Transition {
+        Transition::Pop
+    }
+
+    fn render(&self, _ctx: &ScreenContext) -> Panel {
+        Panel::new(" STATS ", self.lines(), (0x808080, 0xE0E0FF, 0x000010))
+    }
+}
+
+impl Screen for LeaderboardScreen {
+    fn kind(&self) -> ScreenKind {
+        ScreenKind::Leaderboard
+    }
+
+    /// Up/Down turn the page and digits pick a board, fetched by the game loop; Esc closes
+    fn handle_input(&mut self, press: KeyPress, ctx: &mut ScreenContext) -> Transition {
+        let wanted = match press.key {
+            Key::Up => self.turn(-1, None),
+            Key::Down => self.turn(1, None),
+            Key::Char(ch) => match ch.to_digit(10).and_then(|n| LeaderboardMetric::ALL.get((n as usize).wrapping_sub(1))) {
+                Some(&metric) => self.turn(0, Some(metric)),
+                None => None,
+            },
+            Key::Esc => return Transition::Pop,
+            _ => None,
+        };
+        if let Some((metric, page)) = wanted {
+            ctx.commands.push_back(ChatCommand::Leaderboard(metric, page));
+        }
+        Transition::Stay
+    }
+
+    fn render(&self, _ctx: &ScreenContext) -> Panel {
+        Panel::new(" LEADERBOARD ", self.lines(), (0xC0A040, 0xFFF0C0, 0x100C00))
+    }
+}
+
+impl Screen for CommunityScreen {
+    fn kind(&self) -> ScreenKind {
+        ScreenKind::CommunityMaps
+    }
+
+    /// Enter closes the list and has the game loop fetch and fly the map; Esc just closes it
+    fn handle_input(&mut self, press: KeyPress, ctx: &mut ScreenContext) -> Transition {
+        match self.input(press.key) {
+            Some(MapPick::Play(id)) => {
+                ctx.commands.push_back(ChatCommand::PlayCommunityMap(id));
+                Transition::Pop
+            }
+            Some(MapPick::Close) => Transition::Pop,
+            None => Transition::Stay,
+        }
+    }
+
+    fn render(&self, _ctx: &ScreenContext) -> Panel {
+        Panel::new(" COMMUNITY MAPS ", self.lines(), (0x60C0A0, 0xD0FFF0, 0x001410))
+    }
+}
+
+impl Screen for DockedScreen {
+    fn kind(&self) -> ScreenKind {
+        ScreenKind::Docked
+    }
+
+    /// Keys drive the station's menu; market requests are sent straight away
+    fn handle_input(&mut self, press: KeyPress, ctx: &mut ScreenContext) -> Transition {
+        let input = match press.key {
+            Key::Up => DockInput::Up,
+            Key::Down => DockInput::Down,
+            Key::Enter => DockInput::Select,
+            Key::Esc => DockInput::Leave,
+            Key::Char(ch) if ch.is_ascii_digit() => DockInput::Number(ch.to_digit(10).unwrap_or(0)),
+            Key::Char('b' | 'B') => DockInput::Buy,
+            Key::Char('s' | 'S') => DockInput::Sell,
+            Key::Char('a' | 'A') => DockInput::SellAll,
+            _ if press.action == Some(Action::Dock) => DockInput::Leave,
+            _ => return Transition::Stay,
+        };
+        if !self.input(input, ctx.ship, ctx.miner) {
+            ctx.chat.add_message(ChatMessage::system(&format!("Undocked from {}", self.station)));
+            return Transition::Pop;
+        }
+        if let Some(call) = self.take_call() {
+            // The server only trades with accounts, so a guest doesn't need to ask
+            let reply = match call {
+                MarketCall::Trade(_) if ctx.config.auth_token().is_none() => {
+                    Err("Trading needs an account: add one to the config and log in".to_string())
+                }
+                _ => ctx.map.market_call(&self.station, &call),
+            };
+            self.answer(reply, ctx.ship, ctx.miner);
+        }
+        Transition::Stay
+    }
+
+    fn render(&self, ctx: &ScreenContext) -> Panel {
+        let lines = self.lines(ctx.ship, ctx.miner);
+        Panel { own_plane: true, ..Panel::new(" DOCKED ", lines, (0x80A0C0, 0xE0F0FF, 0x001020)) }
+    }
+
+    fn holds_ship(&self) -> bool {
+        true
+    }
+
+    fn indicator(&self) -> &'static str {
+        "[DOCKED]"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::{WARP_DURATION, WARP_TIMEOUT};
+    use exospace_core::SystemInfo;
+    use crate::SERVER_URL;
+    use exospace_core::PilotStats;
+
+    struct Game {
+        ship: ShipStatus,
+        miner: Miner,
+        map: Map,
+        chat: ChatWindow,
+        commands: VecDeque<ChatCommand>,
+        config: Config,
+    }
+
+    impl Game {
+        fn new() -> Self {
+            Game {
+                ship: ShipStatus::default(),
+                miner: Miner::default(),
+                map: Map::generate_local(60, 40, 7),
+                chat: ChatWindow::default(),
+                commands: VecDeque::new(),
+                config: Config::default(),
+            }
+        }
+
+        fn ctx(&mut self) -> ScreenContext<'_> {
+            ScreenContext { ship: &mut self.ship, miner: &mut self.miner, map: &self.map, chat: &mut self.chat, commands: &mut self.commands, config: &mut self.config }
+        }
+    }
+
+    fn is_open(screens: &ScreenManager, kind: ScreenKind) -> bool {
+        screens.is_open(kind)
+    }
+
+    fn press(key: Key) -> KeyPress {
+        KeyPress { key, action: None }
+    }
+
+    // ==================== Stack Tests ====================
+
+    #[test]
+    fn test_only_the_top_screen_gets_keys() {
+        let mut game = Game::new();
+        let mut screens = ScreenManager::default();
+        assert!(screens.is_empty(), "The game has the keyboard to begin with");
+        screens.push(Box::new(ShipMenuScreen));
+        screens.push(Box::new(StatsScreen::new("Ace".to_string(), None, PilotStats::default())));
+        assert_eq!(screens.render(&game.ctx()).iter().map(|panel| panel.title).collect::<Vec<_>>(), [" SHIPYARD ", " STATS "]);
+
+        screens.handle_input(press(Key::Char('3')), &mut game.ctx());
+        assert!(game.commands.is_empty(), "The stats panel swallows the key that closes it");
+        assert!(!is_open(&screens, ScreenKind::Stats));
+        screens.handle_input(press(Key::Char('3')), &mut game.ctx());
+        assert_eq!(game.commands.pop_front(), Some(ChatCommand::SetShip(ShipClass::ALL[2])));
+        assert!(screens.is_empty());
+    }
+
+    #[test]
+    fn test_title_waits_for_a_choice() {
+        let mut game = Game::new();
+        let mut screens = ScreenManager::default();
+        let now = Instant::now();
+        screens.push(Box::new(TitleScreen::new(now, SERVER_URL, None, Some(Box::new(ShipMenuScreen)))));
+        screens.update(now + Duration::from_secs(60), &mut game.ctx());
+        screens.handle_input(press(Key::Other), &mut game.ctx());
+        assert!(is_open(&screens, ScreenKind::Title), "No timing out, and stray keys do nothing");
+        assert!(screens.holds_ship());
+        assert_eq!(screens.render(&game.ctx())[0].backdrop, Some(Backdrop::Starfield(Duration::from_secs(60))), "Over the starfield");
+        assert!(screens.covers_view());
+
+        screens.handle_input(press(Key::Enter), &mut game.ctx());
+        assert_eq!(game.commands.pop_front(), Some(ChatCommand::NewGame));
+        assert!(!is_open(&screens, ScreenKind::Title));
+        assert!(is_open(&screens, ScreenKind::ShipMenu), "The title hands over to the shipyard");
+    }
+
+    #[test]
+    fn test_title_opens_screens_over_itself() {
+        let mut game = Game::new();
+        let mut screens = ScreenManager::default();
+        screens.push(Box::new(TitleScreen::new(Instant::now(), SERVER_URL, None, None)));
+        screens.handle_input(press(Key::Char('L')), &mut game.ctx());
+        for key in [Key::Up, Key::Up, Key::Enter] {
+            screens.handle_input(press(key), &mut game.ctx());
+        }
+        assert_eq!(game.commands.drain(..).collect::<Vec<_>>(), [
+            ChatCommand::Leaderboard(LeaderboardMetric::Distance, 1),
+            ChatCommand::Settings,
+        ]);
+        assert!(is_open(&screens, ScreenKind::Title), "The menu is still there underneath");
+
+        for key in [Key::Up, Key::Up, Key::Enter, Key::Enter] {
+            screens.handle_input(press(key), &mut game.ctx());
+        }
+        assert_eq!(game.commands.pop_front(), Some(ChatCommand::Connect(SERVER_URL.to_string(), None)), "Connecting to the config's server");
+        assert!(screens.is_empty());
+    }
+
+    // ==================== Screen Tests ====================
+
+    #[test]
+    fn test_warp_holds_the_ship_until_it_ends() {
+        let mut game = Game::new();
+        let mut screens = ScreenManager::default();
+        let now = Instant::now();
+        let vega = SystemInfo { id: "vega".to_string(), name: "Vega".to_string(), seed: 7, gates: Vec::new() };
+        screens.push(Box::new(WarpScreen::new(now, &vega, (10, 10))));
+        screens.handle_input(press(Key::Esc), &mut game.ctx());
+        assert!(is_open(&screens, ScreenKind::Warp), "Keys don't cut a jump short");
+        assert!(screens.holds_ship());
+        assert!(screens.covers_view());
+        assert_eq!(screens.render(&game.ctx())[0].backdrop, Some(Backdrop::Warp(Duration::ZERO)), "Over the streaks");
+        screens.update(now + WARP_DURATION / 2, &mut game.ctx());
+        assert_eq!(game.commands.pop_front(), Some(ChatCommand::Arrive(7, 10, 10)), "The map swaps under the streaks");
+        screens.update(now + WARP_DURATION, &mut game.ctx());
+        assert!(screens.is_empty(), "Ends with the far side loaded");
+        assert!(game.commands.is_empty());
+
+        screens.push(Box::new(WarpScreen::new(now, &vega, (500, 500))));
+        screens.update(now + WARP_DURATION, &mut game.ctx());
+        assert!(is_open(&screens, ScreenKind::Warp), "Waiting for the chunk under the ship");
+        screens.update(now + WARP_TIMEOUT, &mut game.ctx());
+        assert!(screens.is_empty());
+    }
+
+    #[test]
+    fn test_game_over_respawns() {
+        let mut game = Game::new();
+        game.ship.damage(1000.0);
+        let mut screens = ScreenManager::default();
+        screens.push(Box::new(GameOverScreen));
+        assert!(screens.holds_ship());
+        screens.handle_input(press(Key::Char('x')), &mut game.ctx());
+        assert!(!game.ship.destroyed(), "A fresh ship");
+        let (x, y) = game.map.find_start_position();
+        assert_eq!(game.commands.pop_front(), Some(ChatCommand::Teleport(x, y)));
+        assert!(!screens.holds_ship());
+    }
+
+    #[test]
+    fn test_leaderboard_asks_for_pages() {
+        use exospace_core::Leaderboard;
+
+        let mut game = Game::new();
+        let mut screens = ScreenManager::default();
+        let board = Leaderboard { metric: LeaderboardMetric::Ore, page: 1, pages: 2, entries: Vec::new() };
+        screens.push(Box::new(LeaderboardScreen::new(board, None)));
+        screens.handle_input(press(Key::Up), &mut game.ctx());
+        assert!(game.commands.is_empty(), "Already on the first page");
+        screens.handle_input(press(Key::Down), &mut game.ctx());
+        screens.handle_input(press(Key::Char('3')), &mut game.ctx());
+        screens.handle_input(press(Key::Char('9')), &mut game.ctx());
+        assert_eq!(game.commands.drain(..).collect::<Vec<_>>(), [
+            ChatCommand::Leaderboard(LeaderboardMetric::Ore, 2),
+            ChatCommand::Leaderboard(LeaderboardMetric::Credits, 1),
+        ]);
+        assert!(is_open(&screens, ScreenKind::Leaderboard), "The game loop swaps in the new page");
+        screens.handle_input(press(Key::Esc), &mut game.ctx());
+        assert!(screens.is_empty());
+    }
+
+    #[test]
+    fn test_community_maps_hand_over_a_pick() {
+        use exospace_core::MapListing;
+
+        let mut game = Game::new();
+        let mut screens = ScreenManager::default();
+        let belt = MapListing { id: 4, name: "Belt".to_string(), author: "Ace".to_string(), width: 40, height: 20, uploaded: 0 };
+        screens.push(Box::new(CommunityScreen::new(vec![belt])));
+        screens.handle_input(press(Key::Enter), &mut game.ctx());
+        assert_eq!(game.commands.pop_front(), Some(ChatCommand::PlayCommunityMap(4)));
+        assert!(screens.is_empty(), "The list closes for the game loop to load the map");
+    }
+
+    #[test]
+    fn test_docked_screen_on_the_stack() {
+        let mut game = Game::new();
+        let mut screens = ScreenManager::default();
+        screens.push(Box::new(DockedScreen::new("Haven Station")));
+        assert_eq!(screens.indicator(), "[DOCKED]");
+        let panels = screens.render(&game.ctx());
+        assert!(panels[0].own_plane && panels[0].lines[0] == "Welcome to Haven Station");
+
+        screens.handle_input(press(Key::Char('3')), &mut game.ctx());
+        let panels = screens.render(&game.ctx());
+        assert!(panels[0].lines.iter().any(|line| line.starts_with("The market is closed")), "Local maps have no markets");
+
+        screens.handle_input(KeyPress { key: Key::Char('d'), action: Some(Action::Dock) }, &mut game.ctx());
+        assert!(screens.is_empty(), "The dock key undocks");
+        assert_eq!(game.chat.messages.last().unwrap().text, "Undocked from Haven Station");
+        assert_eq!(screens.indicator(), "");
+    }
+}
@@ -0,0 +1,255 @@
+//! Screenshots: the frame as text, for `/screenshot` and F12.
+//!
+//! Drawing goes through `Surface`, which notcurses planes and `Canvas`, a
+//! plain grid of glyphs and colors, both implement. On a screenshot frame
+//! the main loop wraps the standard plane and every HUD widget's plane in a
+//! `Tee`, which draws on the plane as usual and copies each cell into one
+//! terminal-sized canvas at the widget's spot, so the canvas ends up with the
+//! view, the HUD and the chat stacked as they are on screen. `save()` writes
+//! it to `~/.config/exospace/screenshots/` twice: plain text, and with
+//! 24-bit ANSI colors for `cat` in a terminal.
+
+use exospace_core::DailySeed;
+use libnotcurses_sys::{NcPlane, NcResult};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chatlog;
+
+/// The drawing calls the game makes, on a notcurses plane or a canvas
+pub trait Surface {
+    fn set_fg_rgb(&mut self, rgb: u32);
+    fn set_bg_rgb(&mut self, rgb: u32);
+    fn set_bg_default(&mut self);
+    /// Write `text` a cell per character from (x, y); returns the cells written
+    fn putstr_yx(&mut self, y: Option<u32>, x: Option<u32>, text: &str) -> NcResult<u32>;
+}
+
+impl Surface for NcPlane {
+    fn set_fg_rgb(&mut self, rgb: u32) {
+        NcPlane::set_fg_rgb(self, rgb);
+    }
+
+    fn set_bg_rgb(&mut self, rgb: u32) {
+        NcPlane::set_bg_rgb(self, rgb);
+    }
+
+    fn set_bg_default(&mut self) {
+        NcPlane::set_bg_default(self);
+    }
+
+    fn putstr_yx(&mut self, y: Option<u32>, x: Option<u32>, text: &str) -> NcResult<u32> {
+        NcPlane::putstr_yx(self, y, x, text)
+    }
+}
+
+/// One character cell; the default is blank on the terminal's background
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: u32,
+    pub bg: Option<u32>,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell { ch: ' ', fg: 0xFFFFFF, bg: None }
+    }
+}
+
+/// A grid of cells drawn like a plane, kept in memory
+pub struct Canvas {
+    cols: u32,
+    rows: u32,
+    cells: Vec<Cell>,
+    fg: u32,
+    bg: Option<u32>,
+}
+
+impl Canvas {
+    pub fn new(cols: u32, rows: u32) -> Self {
+        Canvas { cols, rows, cells: vec![Cell::default(); (cols * rows) as usize], fg: 0xFFFFFF, bg: None }
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        self.cells.chunks(self.cols.max(1) as usize)
+    }
+
+    /// The glyphs alone, trailing spaces trimmed from each line
+    pub fn to_text(&self) -> String {
+        self.rows().map(|row| row.iter().map(|cell| cell.ch).collect::<String>().trim_end().to_string() + "\n").collect()
+    }
+
+    /// The glyphs with 24-bit color escapes, switching only where the colors change; each line ends reset
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        for row in self.rows() {
+            let (mut fg, mut bg) = (None, None);
+            for cell in row {
+                if fg != Some(cell.fg) {
+                    let (r, g, b) = (cell.fg >> 16, cell.fg >> 8 & 0xFF, cell.fg & 0xFF);
+                    out.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b));
+                    fg = Some(cell.fg);
+                }
+                if bg != Some(cell.bg) {
+                    match cell.bg {
+                        Some(color) => out.push_str(&format!("\x1b[48;2;{};{};{}m", color >> 16, color >> 8 & 0xFF, color & 0xFF)),
+                        None => out.push_str("\x1b[49m"),
+                    }
+                    bg = Some(cell.bg);
+                }
+                out.push(cell.ch);
+            }
+            out.push_str("\x1b[0m\n");
+        }
+        out
+    }
+}
+
+impl Surface for Canvas {
+    fn set_fg_rgb(&mut self, rgb: u32) {
+        self.fg = rgb;
+    }
+
+    fn set_bg_rgb(&mut self, rgb: u32) {
+        self.bg = Some(rgb);
+    }
+
+    fn set_bg_default(&mut self) {
+        self.bg = None;
+    }
+
+    fn putstr_yx(&mut self, y: Option<u32>, x: Option<u32>, text: &str) -> NcResult<u32> {
+        let (y, x) = (y.unwrap_or(0), x.unwrap_or(0));
+        let mut written = 0;
+        for (col, ch) in (x..).zip(text.chars()) {
+            if y < self.rows && col < self.cols {
+                self.cells[(y * self.cols + col) as usize] = Cell { ch, fg: self.fg, bg: self.bg };
+                written += 1;
+            }
+        }
+        Ok(written)
+    }
+}
+
+/// A plane that's also copied into a canvas, at the plane's spot on the terminal
+pub struct Tee<'a> {
+    plane: &'a mut NcPlane,
+    canvas: Option<&'a mut Canvas>,
+    /// Where the plane's (0, 0) is on the terminal
+    origin: (u32, u32),
+}
+
+impl<'a> Tee<'a> {
+    /// Draw on `plane`, copying into `canvas` when there is one
+    pub fn new(plane: &'a mut NcPlane, canvas: Option<&'a mut Canvas>, (x, y): (u32, u32)) -> Self {
+        Tee { plane, canvas, origin: (x, y) }
+    }
+}
+
+impl Surface for Tee<'_> {
+    fn set_fg_rgb(&mut self, rgb: u32) {
+        Surface::set_fg_rgb(self.plane, rgb);
+        if let Some(canvas) = &mut self.canvas {
+            canvas.set_fg_rgb(rgb);
+        }
+    }
+
+    fn set_bg_rgb(&mut self, rgb: u32) {
+        Surface::set_bg_rgb(self.plane, rgb);
+        if let Some(canvas) = &mut self.canvas {
+            canvas.set_bg_rgb(rgb);
+        }
+    }
+
+    fn set_bg_default(&mut self) {
+        Surface::set_bg_default(self.plane);
+        if let Some(canvas) = &mut self.canvas {
+            canvas.set_bg_default();
+        }
+    }
+
+    fn putstr_yx(&mut self, y: Option<u32>, x: Option<u32>, text: &str) -> NcResult<u32> {
+        if let Some(canvas) = &mut self.canvas {
+            canvas.putstr_yx(Some(self.origin.1 + y.unwrap_or(0)), Some(self.origin.0 + x.unwrap_or(0)), text)?;
+        }
+        Surface::putstr_yx(self.plane, y, x, text)
+    }
+}
+
+/// Directory screenshots are written to
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|mut p| {
+        p.push("exospace");
+        p.push("screenshots");
+        p
+    })
+}
+
+/// Write the canvas as `<name>.txt` and `<name>.ans`, named for the time (UTC); returns the path without
+/// an extension. A second shot in the same second gets a number
+pub fn save(canvas: &Canvas, time: SystemTime) -> Result<PathBuf, String> {
+    let dir = dir().ok_or_else(|| "Could not determine config directory".to_string())?;
+    save_to(&dir, canvas, time)
+}
+
+pub fn save_to(dir: &Path, canvas: &Canvas, time: SystemTime) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create screenshots directory: {}", e))?;
+    let date = DailySeed::for_time(time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())).date;
+    let stamp = format!("screenshot-{}-{}", date, chatlog::clock_time(time).replace(':', ""));
+    let base = (1..)
+        .map(|n| if n == 1 { dir.join(&stamp) } else { dir.join(format!("{}-{}", stamp, n)) })
+        .find(|base| !base.with_extension("txt").exists())
+        .expect("a free name");
+    fs::write(base.with_extension("txt"), canvas.to_text()).map_err(|e| format!("Failed to write screenshot: {}", e))?;
+    fs::write(base.with_extension("ans"), canvas.to_ansi()).map_err(|e| format!("Failed to write screenshot: {}", e))?;
+    Ok(base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // ==================== Canvas Tests ====================
+
+    #[test]
+    fn test_canvas_draws_like_a_plane() {
+        let mut canvas = Canvas::new(6, 2);
+        canvas.set_fg_rgb(0x102030);
+        assert_eq!(canvas.putstr_yx(Some(0), Some(4), "abc").unwrap(), 2, "Clipped at the edge");
+        canvas.set_bg_rgb(0x000010);
+        canvas.putstr_yx(Some(1), Some(0), "█").unwrap();
+        assert_eq!(canvas.cells[4], Cell { ch: 'a', fg: 0x102030, bg: None });
+        assert_eq!(canvas.cells[6], Cell { ch: '█', fg: 0x102030, bg: Some(0x000010) });
+        assert_eq!(canvas.to_text(), "    ab\n█\n");
+    }
+
+    #[test]
+    fn test_ansi_switches_colors_only_on_change() {
+        let mut canvas = Canvas::new(3, 1);
+        canvas.set_fg_rgb(0xFF8000);
+        canvas.putstr_yx(Some(0), Some(0), "ab").unwrap();
+        let ansi = canvas.to_ansi();
+        assert!(ansi.starts_with("\x1b[38;2;255;128;0m\x1b[49mab\x1b[38;2;255;255;255m "), "{:?}", ansi);
+        assert!(ansi.ends_with("\x1b[0m\n"), "Each line ends reset");
+    }
+
+    // ==================== Save Tests ====================
+
+    #[test]
+    fn test_saves_text_and_ansi_without_overwriting() {
+        let dir = std::env::temp_dir().join(format!("exospace-shots-{}", std::process::id()));
+        let mut canvas = Canvas::new(4, 1);
+        canvas.putstr_yx(Some(0), Some(0), "ship").unwrap();
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let first = save_to(&dir, &canvas, time).unwrap();
+        assert_eq!(first.file_name().unwrap(), "screenshot-2023-11-14-221320");
+        assert_eq!(fs::read_to_string(first.with_extension("txt")).unwrap(), "ship\n");
+        assert!(fs::read_to_string(first.with_extension("ans")).unwrap().contains("ship"));
+        let second = save_to(&dir, &canvas, time).unwrap();
+        assert_eq!(second.file_name().unwrap(), "screenshot-2023-11-14-221320-2", "Same second, new name");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,451 @@
+//! User scripts: small Rhai programs for automation, run with `/run NAME`.
+//!
+//! Scripts live in `~/.config/exospace/scripts/<name>.rhai`. The top level
+//! runs once; a script that defines `fn tick()` then stays loaded and has it
+//! called every `SCRIPT_TICK`, with `this` a map that keeps its values
+//! between calls, until it calls `stop()`, fails or `/run stop` ends it.
+//! Only one script runs at a time. What scripts can do:
+//!
+//! - `ship()`: a map of `x`, `y`, `direction` and `autopilot` (true while
+//!   a route is flown)
+//! - `tile(x, y)`, `passable(x, y)` and `explored(x, y)`: the map within
+//!   `VIEW_RANGE` tiles of the ship; further out is "unknown" and blocked
+//! - `command("/nav 10 4")` and `say("hi")`: sent as if typed in chat, so
+//!   commands are checked like the player's own
+//! - `hud("text")`: lines for a panel over the view (empty hides it);
+//!   `print()` writes to the chat
+//!
+//! Every call is capped at `MAX_OPERATIONS`, so a runaway loop fails rather
+//! than freezing the game. The interpreter comes with the `scripting`
+//! feature; without it `/run` says so.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use exospace_core::Tile;
+
+#[cfg(feature = "scripting")]
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+#[cfg(feature = "scripting")]
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+/// How often a running script's `tick()` is called
+#[cfg(feature = "scripting")]
+pub const SCRIPT_TICK: Duration = Duration::from_millis(250);
+
+/// Furthest from the ship, on each axis, that scripts can see the map
+pub const VIEW_RANGE: i32 = 40;
+
+/// Most steps the interpreter takes in one call
+#[cfg(feature = "scripting")]
+pub const MAX_OPERATIONS: u64 = 100_000;
+
+/// Most HUD lines a script can show, and their longest width
+#[cfg(feature = "scripting")]
+pub const MAX_HUD_LINES: usize = 6;
+#[cfg(feature = "scripting")]
+pub const MAX_HUD_WIDTH: usize = 40;
+
+/// Longest script name accepted
+pub const MAX_SCRIPT_NAME_LEN: usize = 32;
+
+const SCRIPT_EXTENSION: &str = "rhai";
+
+/// What a script sees of the game, taken just before it runs
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub struct View {
+    pub x: i32,
+    pub y: i32,
+    pub direction: &'static str,
+    pub autopilot: bool,
+    /// (tile, passable, explored) for every tile within `VIEW_RANGE`, a row at a time
+    tiles: Vec<(Option<Tile>, bool, bool)>,
+}
+
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+impl View {
+    /// Look at the map around the ship through `probe`, which gives (tile, passable, explored)
+    pub fn capture(ship: (i32, i32), direction: &'static str, autopilot: bool, probe: impl Fn(i32, i32) -> (Option<Tile>, bool, bool)) -> Self {
+        let span = -VIEW_RANGE..=VIEW_RANGE;
+        let tiles = span.clone().flat_map(|dy| span.clone().map(move |dx| (dx, dy))).map(|(dx, dy)| probe(ship.0 + dx, ship.1 + dy)).collect();
+        View { x: ship.0, y: ship.1, direction, autopilot, tiles }
+    }
+
+    /// (tile, passable, explored) at (x, y); out of sight it's unknown and blocked
+    fn at(&self, x: i64, y: i64) -> (Option<Tile>, bool, bool) {
+        let (dx, dy) = (x - self.x as i64, y - self.y as i64);
+        let range = VIEW_RANGE as i64;
+        if dx.abs() > range || dy.abs() > range {
+            return (None, false, false);
+        }
+        self.tiles[((dy + range) * (range * 2 + 1) + dx + range) as usize]
+    }
+}
+
+/// How scripts name tiles
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub fn tile_name(tile: Option<Tile>) -> &'static str {
+    match tile {
+        Some(Tile::Wall) => "wall",
+        Some(Tile::Floor) => "space",
+        Some(Tile::Asteroid) => "asteroid",
+        Some(Tile::Nebula) => "nebula",
+        Some(Tile::OreAsteroid) => "ore",
+        Some(Tile::CrystalField) => "crystal",
+        Some(Tile::Unknown) | None => "unknown",
+    }
+}
+
+/// What a script asked for in one call
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    /// Commands and chat lines, to be sent as if typed
+    pub lines: Vec<String>,
+    /// `print()` output
+    pub printed: Vec<String>,
+}
+
+/// A script with a `tick()`, between calls
+#[cfg(feature = "scripting")]
+struct Running {
+    name: String,
+    ast: AST,
+    /// The script's `this`
+    state: Dynamic,
+    next_tick: Instant,
+}
+
+/// What the script's functions read and write during a call
+#[cfg(feature = "scripting")]
+#[derive(Default)]
+struct Host {
+    view: Option<View>,
+    report: Report,
+    hud: Option<Vec<String>>,
+    stop: bool,
+}
+
+/// The interpreter and the script running in it, if any
+#[derive(Default)]
+pub struct Scripts {
+    #[cfg(feature = "scripting")]
+    engine: Option<Engine>,
+    #[cfg(feature = "scripting")]
+    host: Rc<RefCell<Host>>,
+    #[cfg(feature = "scripting")]
+    running: Option<Running>,
+    hud: Vec<String>,
+}
+
+impl Scripts {
+    /// Lines the script wants over the view
+    pub fn hud(&self) -> &[String] {
+        &self.hud
+    }
+
+    /// Name of the script with a `tick()` still running
+    #[cfg(feature = "scripting")]
+    pub fn running(&self) -> Option<&str> {
+        self.running.as_ref().map(|running| running.name.as_str())
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn running(&self) -> Option<&str> {
+        None
+    }
+
+    /// End the running script, returning its name
+    #[cfg(feature = "scripting")]
+    pub fn stop(&mut self) -> Option<String> {
+        self.hud.clear();
+        self.running.take().map(|running| running.name)
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn stop(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Run a script's top level; one with a `tick()` keeps running in place of any other
+    #[cfg(feature = "scripting")]
+    pub fn run(&mut self, name: &str, source: &str, view: View, now: Instant) -> Result<Report, String> {
+        self.stop();
+        let ast = self.engine().compile(source).map_err(|e| format!("Script {}: {}", name, e))?;
+        let result = self.call(view, |engine| engine.run_ast(&ast));
+        let report = result.map_err(|e| format!("Script {}: {}", name, e))?;
+        let stopped = std::mem::take(&mut self.host.borrow_mut().stop);
+        if !stopped && ast.iter_functions().any(|function| function.name == "tick" && function.params.is_empty()) {
+            let state = Dynamic::from_map(rhai::Map::new());
+            self.running = Some(Running { name: name.to_string(), ast, state, next_tick: now + SCRIPT_TICK });
+        }
+        Ok(report)
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn run(&mut self, _name: &str, _source: &str, _view: View, _now: Instant) -> Result<Report, String> {
+        Err("Scripts need the scripting feature, which this build was made without".to_string())
+    }
+
+    /// Call the running script's `tick()` when it's due; None when nothing ran. A script that
+    /// fails or calls `stop()` stops
+    #[cfg(feature = "scripting")]
+    pub fn tick(&mut self, now: Instant, view: impl FnOnce() -> View) -> Option<Result<Report, String>> {
+        if self.running.as_ref().is_none_or(|running| now < running.next_tick) {
+            return None;
+        }
+        let mut running = self.running.take()?;
+        running.next_tick = now + SCRIPT_TICK;
+        let result = self.call(view(), |engine| {
+            let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut running.state);
+            engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &running.ast, "tick", ()).map(|_| ())
+        });
+        let stopped = std::mem::take(&mut self.host.borrow_mut().stop);
+        match result {
+            Ok(report) if !stopped => {
+                self.running = Some(running);
+                Some(Ok(report))
+            }
+            Ok(report) => {
+                self.hud.clear();
+                Some(Ok(report))
+            }
+            Err(e) => {
+                self.hud.clear();
+                Some(Err(format!("Script {} stopped: {}", running.name, e)))
+            }
+        }
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn tick(&mut self, _now: Instant, _view: impl FnOnce() -> View) -> Option<Result<Report, String>> {
+        None
+    }
+
+    /// Run `call` with the script seeing `view`, collecting what it asked for
+    #[cfg(feature = "scripting")]
+    fn call(&mut self, view: View, call: impl FnOnce(&Engine) -> Result<(), Box<EvalAltResult>>) -> Result<Report, Box<EvalAltResult>> {
+        self.host.borrow_mut().view = Some(view);
+        let result = call(self.engine());
+        let mut host = self.host.borrow_mut();
+        host.view = None;
+        if let Some(lines) = host.hud.take() {
+            self.hud = lines;
+        }
+        let report = std::mem::take(&mut host.report);
+        result.map(|()| report)
+    }
+
+    /// The interpreter, made on first use with the game's functions registered
+    #[cfg(feature = "scripting")]
+    fn engine(&mut self) -> &Engine {
+        let host = &self.host;
+        self.engine.get_or_insert_with(|| new_engine(host))
+    }
+}
+
+#[cfg(feature = "scripting")]
+fn new_engine(host: &Rc<RefCell<Host>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let shared = host.clone();
+    engine.on_print(move |text| shared.borrow_mut().report.printed.push(text.to_string()));
+    let shared = host.clone();
+    engine.register_fn("ship", move || {
+        let host = shared.borrow();
+        let mut ship = rhai::Map::new();
+        if let Some(view) = &host.view {
+            ship.insert("x".into(), (view.x as i64).into());
+            ship.insert("y".into(), (view.y as i64).into());
+            ship.insert("direction".into(), view.direction.into());
+            ship.insert("autopilot".into(), view.autopilot.into());
+        }
+        ship
+    });
+    let shared = host.clone();
+    engine.register_fn("tile", move |x: i64, y: i64| {
+        shared.borrow().view.as_ref().map_or("unknown", |view| tile_name(view.at(x, y).0)).to_string()
+    });
+    let shared = host.clone();
+    engine.register_fn("passable", move |x: i64, y: i64| shared.borrow().view.as_ref().is_some_and(|view| view.at(x, y).1));
+    let shared = host.clone();
+    engine.register_fn("explored", move |x: i64, y: i64| shared.borrow().view.as_ref().is_some_and(|view| view.at(x, y).2));
+    let shared = host.clone();
+    engine.register_fn("command", move |line: &str| {
+        let line = line.trim();
+        let line = if line.starts_with('/') { line.to_string() } else { format!("/{}", line) };
+        shared.borrow_mut().report.lines.push(line);
+    });
+    let shared = host.clone();
+    engine.register_fn("say", move |text: &str| {
+        // A leading slash would make it a command
+        let text = text.trim().trim_start_matches('/');
+        if !text.is_empty() {
+            shared.borrow_mut().report.lines.push(text.to_string());
+        }
+    });
+    let shared = host.clone();
+    engine.register_fn("hud", move |text: &str| {
+        let lines = text.lines().take(MAX_HUD_LINES).map(|line| line.chars().take(MAX_HUD_WIDTH).collect()).collect();
+        shared.borrow_mut().hud = Some(lines);
+    });
+    let shared = host.clone();
+    engine.register_fn("stop", move || shared.borrow_mut().stop = true);
+    engine
+}
+
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_SCRIPT_NAME_LEN {
+        return Err(format!("Script names are 1-{} characters", MAX_SCRIPT_NAME_LEN));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err("Script names use letters, digits, - and _".to_string());
+    }
+    Ok(())
+}
+
+/// Directory holding scripts
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|mut p| {
+        p.push("exospace");
+        p.push("scripts");
+        p
+    })
+}
+
+/// The source of the script called `name`
+pub fn load(name: &str) -> Result<String, String> {
+    check_name(name)?;
+    let dir = dir().ok_or_else(|| "Could not determine config directory".to_string())?;
+    load_from(&dir, name)
+}
+
+pub fn load_from(dir: &Path, name: &str) -> Result<String, String> {
+    let path = dir.join(name).with_extension(SCRIPT_EXTENSION);
+    fs::read_to_string(&path).map_err(|_| format!("No script named '{}' in {}", name, dir.display()))
+}
+
+/// Names of the scripts in the scripts directory, sorted
+pub fn available() -> Vec<String> {
+    available_in(dir().as_deref())
+}
+
+pub fn available_in(dir: Option<&Path>) -> Vec<String> {
+    let mut names: Vec<String> = dir
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == SCRIPT_EXTENSION))
+        .filter_map(|entry| entry.path().file_stem()?.to_str().map(str::to_string))
+        .filter(|name| check_name(name).is_ok())
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Open space everywhere except a wall east of (5, 0), with the west half explored
+    fn view_at(x: i32, y: i32) -> View {
+        View::capture((x, y), "E", false, |x, y| {
+            let tile = if (x, y) == (5, 0) { Tile::Wall } else { Tile::Floor };
+            (Some(tile), tile.is_passable(), x <= 0)
+        })
+    }
+
+    // ==================== View Tests ====================
+
+    #[test]
+    fn test_view_sees_only_near_the_ship() {
+        let view = view_at(0, 0);
+        assert_eq!(view.at(5, 0), (Some(Tile::Wall), false, false));
+        assert_eq!(view.at(-3, 2), (Some(Tile::Floor), true, true));
+        assert_eq!(view.at(VIEW_RANGE as i64, -VIEW_RANGE as i64).0, Some(Tile::Floor), "The corners are in sight");
+        assert_eq!(view.at(VIEW_RANGE as i64 + 1, 0), (None, false, false), "Too far");
+        assert_eq!(view_at(100, 100).at(100 - VIEW_RANGE as i64, 100).0, Some(Tile::Floor), "Measured from the ship");
+        assert_eq!(tile_name(None), "unknown");
+    }
+
+    // ==================== Script File Tests ====================
+
+    #[test]
+    fn test_script_files() {
+        let dir = std::env::temp_dir().join(format!("exospace-scripts-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("patrol.rhai"), "stop();").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        assert_eq!(load_from(&dir, "patrol").unwrap(), "stop();");
+        assert!(load_from(&dir, "missing").unwrap_err().starts_with("No script named 'missing'"));
+        assert!(check_name("../config").is_err(), "Names can't leave the scripts directory");
+        assert_eq!(available_in(Some(&dir)), ["patrol"]);
+        assert!(available_in(None).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // ==================== Interpreter Tests ====================
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn test_scripts_see_the_ship_and_send_commands() {
+        let mut scripts = Scripts::default();
+        let source = r#"
+            let ship = ship();
+            print(`at ${ship.x},${ship.y} facing ${ship.direction}`);
+            if !passable(ship.x + 5, ship.y) && tile(ship.x + 5, ship.y) == "wall" {
+                command("nav 0 4");
+            }
+            say("/hello");
+            hud("Patrol\nleg 1");
+        "#;
+        let report = scripts.run("look", source, view_at(0, 0), Instant::now()).unwrap();
+        assert_eq!(report.printed, ["at 0,0 facing E"]);
+        assert_eq!(report.lines, ["/nav 0 4", "hello"], "Commands get their slash; chat can't sneak one in");
+        assert_eq!(scripts.hud(), ["Patrol", "leg 1"]);
+        assert_eq!(scripts.running(), None, "No tick(), so done");
+
+        let error = scripts.run("broken", "let x = ;", view_at(0, 0), Instant::now()).unwrap_err();
+        assert!(error.starts_with("Script broken:"), "{}", error);
+        assert!(scripts.hud().is_empty(), "A new run clears the old HUD");
+        let error = scripts.run("spin", "loop {}", view_at(0, 0), Instant::now()).unwrap_err();
+        assert!(error.to_lowercase().contains("operations"), "Runaway loops are cut off: {}", error);
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn test_tick_keeps_state_until_stopped() {
+        let mut scripts = Scripts::default();
+        let source = r#"
+            fn tick() {
+                this.count = if this.count == () { 1 } else { this.count + 1 };
+                if this.count == 3 { stop(); }
+                print(`tick ${this.count}`);
+            }
+        "#;
+        let start = Instant::now();
+        assert_eq!(scripts.run("count", source, view_at(0, 0), start).unwrap(), Report::default());
+        assert_eq!(scripts.running(), Some("count"));
+        assert!(scripts.tick(start, || view_at(0, 0)).is_none(), "Not due yet");
+        assert!(scripts.running().is_some(), "Still running while it waits");
+
+        let mut now = start;
+        let mut printed = Vec::new();
+        while scripts.running().is_some() {
+            now += SCRIPT_TICK;
+            printed.extend(scripts.tick(now, || view_at(0, 0)).unwrap().unwrap().printed);
+        }
+        assert_eq!(printed, ["tick 1", "tick 2", "tick 3"], "this keeps its values");
+
+        scripts.run("fail", "fn tick() { throw \"lost\"; }", view_at(0, 0), now).unwrap();
+        let error = scripts.tick(now + SCRIPT_TICK, || view_at(0, 0)).unwrap().unwrap_err();
+        assert!(error.starts_with("Script fail stopped:") && error.contains("lost"), "{}", error);
+        assert_eq!(scripts.running(), None);
+
+        scripts.run("again", source, view_at(0, 0), now).unwrap();
+        assert_eq!(scripts.stop(), Some("again".to_string()));
+        assert_eq!(scripts.stop(), None);
+    }
+}
@@ -0,0 +1,138 @@
+//! Where the last game left off, for the title screen's Continue.
+//!
+//! On the way out the client writes `~/.config/exospace/session.json`: which
+//! world it was (a local map, or a server's streamed world and its seed),
+//! the ship's position and heading, and what was in the hold. A local map's
+//! tiles go alongside in `session.map`, in the `saves` format, so mined rock
+//! stays mined; exploration is already kept per world by `fog`. Continuing an
+//! online session loads that server's world again, and the server has the
+//! last word: its `Resume` and move checks put the ship back if it disagrees.
+
+use exospace_core::Direction;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::saves::SavedMap;
+
+const SESSION_FILE: &str = "session.json";
+const SESSION_MAP_FILE: &str = "session.map";
+
+/// The game as it was when the client last exited
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Session {
+    /// The server whose world it was; None for a local map
+    pub server: Option<String>,
+    pub seed: u64,
+    pub x: i32,
+    pub y: i32,
+    pub direction: Direction,
+    pub ore: u32,
+    pub crystal: u32,
+    /// Online with an account, the server's balance counts instead
+    pub credits: u32,
+}
+
+impl Session {
+    /// The Continue row's value
+    pub fn summary(&self) -> String {
+        let world = if self.server.is_some() { "online" } else { "solo" };
+        format!("{}, seed {} at ({}, {})", world, self.seed, self.x, self.y)
+    }
+}
+
+/// Directory holding the session files
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|mut p| {
+        p.push("exospace");
+        p
+    })
+}
+
+/// Write the session, and the local map it was on; an older map is removed when there's none
+pub fn save(session: &Session, map: Option<&SavedMap>) -> Result<(), String> {
+    let dir = dir().ok_or_else(|| "Could not determine config directory".to_string())?;
+    save_to(&dir, session, map)
+}
+
+pub fn save_to(dir: &Path, session: &Session, map: Option<&SavedMap>) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    let map_path = dir.join(SESSION_MAP_FILE);
+    match map {
+        Some(map) => fs::write(&map_path, map.to_bytes()).map_err(|e| format!("Failed to write session map: {}", e))?,
+        None => {
+            let _ = fs::remove_file(&map_path);
+        }
+    }
+    let json = serde_json::to_string_pretty(session).map_err(|e| format!("Failed to encode session: {}", e))?;
+    fs::write(dir.join(SESSION_FILE), json).map_err(|e| format!("Failed to write session: {}", e))
+}
+
+/// The last session, and its local map if it had one
+pub fn load() -> Result<(Session, Option<SavedMap>), String> {
+    let dir = dir().ok_or_else(|| "Could not determine config directory".to_string())?;
+    load_from(&dir)
+}
+
+pub fn load_from(dir: &Path) -> Result<(Session, Option<SavedMap>), String> {
+    let text = fs::read_to_string(dir.join(SESSION_FILE)).map_err(|_| "No game to continue".to_string())?;
+    let session: Session = serde_json::from_str(&text).map_err(|e| format!("Bad session file: {}", e))?;
+    let map = match (&session.server, fs::read(dir.join(SESSION_MAP_FILE))) {
+        (None, Ok(bytes)) => Some(SavedMap::from_bytes(&bytes)?),
+        _ => None,
+    };
+    Ok((session, map))
+}
+
+/// The Continue row's value, if there's a session to continue
+pub fn latest_summary() -> Option<String> {
+    latest_summary_in(&dir()?)
+}
+
+pub fn latest_summary_in(dir: &Path) -> Option<String> {
+    let text = fs::read_to_string(dir.join(SESSION_FILE)).ok()?;
+    serde_json::from_str::<Session>(&text).ok().map(|session| session.summary())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fog::ExploredTiles;
+    use exospace_core::{MapData, Tile};
+
+    fn solo() -> Session {
+        Session { server: None, seed: 42, x: 3, y: -4, direction: Direction::Left, ore: 5, crystal: 1, credits: 120 }
+    }
+
+    fn map() -> SavedMap {
+        let tiles = vec![Tile::Floor; 9];
+        SavedMap { seed: 42, map: MapData { tiles, width: 3, height: 3, start_x: 1, start_y: 1, pois: Vec::new(), regions: Vec::new() }, explored: ExploredTiles::default() }
+    }
+
+    // ==================== Session File Tests ====================
+
+    #[test]
+    fn test_session_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("exospace-session-{}", std::process::id()));
+        assert!(load_from(&dir).is_err_and(|e| e == "No game to continue"));
+        assert_eq!(latest_summary_in(&dir), None);
+
+        save_to(&dir, &solo(), Some(&map())).unwrap();
+        let (session, saved) = load_from(&dir).unwrap();
+        assert_eq!(session, solo());
+        assert_eq!(saved.map(|saved| saved.map.width), Some(3), "A local map comes back with its tiles");
+        assert_eq!(latest_summary_in(&dir).as_deref(), Some("solo, seed 42 at (3, -4)"));
+
+        let online = Session { server: Some("http://localhost:3000".to_string()), ..solo() };
+        save_to(&dir, &online, None).unwrap();
+        let (session, saved) = load_from(&dir).unwrap();
+        assert_eq!(session.server.as_deref(), Some("http://localhost:3000"));
+        assert!(saved.is_none(), "The old local map is gone");
+        assert_eq!(session.summary(), "online, seed 42 at (3, -4)");
+
+        fs::write(dir.join(SESSION_FILE), "{").unwrap();
+        assert!(load_from(&dir).is_err_and(|e| e.starts_with("Bad session file")));
+        assert_eq!(latest_summary_in(&dir), None, "Nothing to offer from a broken file");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,403 @@
+//! The settings screen: config values edited in game (F2 or `/settings`).
+//!
+//! Each row is one config value. Enter flips effects on and off and steps
+//! through the color themes (`theme::available()`) and, on a solo world, its
+//! difficulty; the other rows open a text field, where an
+//! empty value goes back to the default. Every change is checked, saved
+//! with `Config::save()` and applied straight away through
+//! `ChatCommand::ApplySettings`, except the server URL, which is used by
+//! the next connect.
+
+use crate::camera::{DEADZONE_LIMITS, LOOKAHEAD_LIMITS, SMOOTHING_LIMITS};
+use crate::difficulty::Difficulty;
+use crate::keys::Action;
+use crate::menu::{Menu, MenuEvent, MenuItem};
+use crate::screens::{KeyPress, Panel, Screen, ScreenContext, ScreenKind, Transition};
+use crate::light::LEVEL_LIMITS;
+use crate::perf::FPS_LIMITS;
+use crate::theme;
+use crate::{ChatCommand, Config, Map, SERVER_URL};
+
+/// Movement tick lengths the settings accept, in milliseconds
+pub const MOVE_DELAY_LIMITS: (u64, u64) = (10, 500);
+
+/// Held-key timeouts the settings accept, in milliseconds
+pub const KEY_TIMEOUT_LIMITS: (u64, u64) = (50, 2000);
+
+/// The rows of the settings screen, in order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    Effects,
+    ServerUrl,
+    MoveDelay,
+    KeyTimeout,
+    FrameRate,
+    Theme,
+    Brightness,
+    CameraLag,
+    DeadZone,
+    LookAhead,
+    Difficulty,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 11] = [
+        Setting::Effects,
+        Setting::ServerUrl,
+        Setting::MoveDelay,
+        Setting::KeyTimeout,
+        Setting::FrameRate,
+        Setting::Theme,
+        Setting::Brightness,
+        Setting::CameraLag,
+        Setting::DeadZone,
+        Setting::LookAhead,
+        Setting::Difficulty,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Setting::Effects => "Effects",
+            Setting::ServerUrl => "Server URL",
+            Setting::MoveDelay => "Move delay",
+            Setting::KeyTimeout => "Key timeout",
+            Setting::FrameRate => "Frame rate",
+            Setting::Theme => "Color theme",
+            Setting::Brightness => "Brightness",
+            Setting::CameraLag => "Camera lag",
+            Setting::DeadZone => "Dead zone",
+            Setting::LookAhead => "Look-ahead",
+            Setting::Difficulty => "Difficulty",
+        }
+    }
+
+    /// The value as shown, or as it starts in the field
+    pub fn value(self, config: &Config) -> String {
+        match self {
+            Setting::Effects => if config.effects_enabled { "on" } else { "off" }.to_string(),
+            Setting::ServerUrl => config.server_url.clone().unwrap_or_default(),
+            Setting::MoveDelay => config.move_delay_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+            Setting::KeyTimeout => config.key_timeout().as_millis().to_string(),
+            Setting::FrameRate => config.target_fps().to_string(),
+            Setting::Theme => config.theme_name().to_string(),
+            Setting::Brightness => config.light_level().map(|level| level.to_string()).unwrap_or_default(),
+            Setting::CameraLag => config.camera_feel().smoothing.as_millis().to_string(),
+            Setting::DeadZone => config.camera_feel().deadzone.to_string(),
+            Setting::LookAhead => config.camera_feel().lookahead.to_string(),
+            Setting::Difficulty => String::new(),
+        }
+    }
+
+    /// What an empty value means
+    fn default_hint(self) -> String {
+        match self {
+            Setting::ServerUrl => format!("default ({})", SERVER_URL),
+            Setting::MoveDelay => "ship's own".to_string(),
+            Setting::Brightness => "day/night cycle".to_string(),
+            Setting::Difficulty => "set by the server".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Row text: the value with its unit, or what it falls back to when empty; difficulty is the solo world's being flown
+    fn display(self, config: &Config, map: &Map) -> String {
+        let value = self.value(config);
+        match self {
+            Setting::Difficulty if map.chunks.is_none() => config.difficulty_for(&map.world_key()).name().to_string(),
+            _ if value.is_empty() => self.default_hint(),
+            Setting::CameraLag if value == "0" => "off".to_string(),
+            Setting::MoveDelay | Setting::KeyTimeout | Setting::CameraLag => format!("{} ms", value),
+            Setting::DeadZone | Setting::LookAhead => format!("{} cells", value),
+            Setting::FrameRate => format!("{} fps", value),
+            Setting::Brightness => format!("{}%", value),
+            _ => value,
+        }
+    }
+
+    /// Check typed text and store it in `config`
+    fn set(self, config: &mut Config, text: &str) -> Result<(), String> {
+        let text = text.trim();
+        let millis = |(low, high): (u64, u64)| -> Result<Option<u64>, String> {
+            if text.is_empty() {
+                return Ok(None);
+            }
+            match text.trim_end_matches("ms").trim().parse::<u64>() {
+                Ok(ms) if (low..=high).contains(&ms) => Ok(Some(ms)),
+                _ => Err(format!("{} must be {} to {} ms", self.label(), low, high)),
+            }
+        };
+        let cells = |(low, high): (u32, u32)| -> Result<Option<u32>, String> {
+            if text.is_empty() {
+                return Ok(None);
+            }
+            match text.trim_end_matches("cells").trim().parse::<u32>() {
+                Ok(cells) if (low..=high).contains(&cells) => Ok(Some(cells)),
+                _ => Err(format!("{} must be {} to {} cells", self.label(), low, high)),
+            }
+        };
+        match self {
+            Setting::ServerUrl => {
+                if !text.is_empty() && !text.starts_with("http://") && !text.starts_with("https://") {
+                    return Err("Server URL must start with http:// or https://".to_string());
+                }
+                config.server_url = (!text.is_empty()).then(|| text.trim_end_matches('/').to_string());
+            }
+            Setting::MoveDelay => config.move_delay_ms = millis(MOVE_DELAY_LIMITS)?,
+            Setting::KeyTimeout => config.key_timeout_ms = millis(KEY_TIMEOUT_LIMITS)?,
+            Setting::CameraLag => config.camera_smoothing_ms = millis(SMOOTHING_LIMITS)?,
+            Setting::DeadZone => config.camera_deadzone = cells(DEADZONE_LIMITS)?,
+            Setting::LookAhead => config.camera_lookahead = cells(LOOKAHEAD_LIMITS)?,
+            Setting::FrameRate => {
+                config.target_fps = match text.trim_end_matches("fps").trim() {
+                    "" => None,
+                    fps => match fps.parse::<u32>() {
+                        Ok(fps) if (FPS_LIMITS.0..=FPS_LIMITS.1).contains(&fps) => Some(fps),
+                        _ => return Err(format!("Frame rate must be {} to {} fps", FPS_LIMITS.0, FPS_LIMITS.1)),
+                    },
+                }
+            }
+            Setting::Brightness => {
+                config.light_level = match text.trim_end_matches('%').trim() {
+                    "" => None,
+                    level => match level.parse::<u32>() {
+                        Ok(level) if (LEVEL_LIMITS.0..=LEVEL_LIMITS.1).contains(&level) => Some(level),
+                        _ => return Err(format!("Brightness must be {} to {}%", LEVEL_LIMITS.0, LEVEL_LIMITS.1)),
+                    },
+                }
+            }
+            Setting::Effects | Setting::Theme | Setting::Difficulty => {}
+        }
+        Ok(())
+    }
+}
+
+/// The settings menu and what the last change did
+pub struct SettingsScreen {
+    menu: Menu,
+    notice: Option<String>,
+}
+
+impl SettingsScreen {
+    pub fn new(config: &Config, map: &Map) -> Self {
+        let items = Setting::ALL.iter().map(|setting| MenuItem::new(setting.label(), setting.display(config, map))).collect();
+        SettingsScreen { menu: Menu::new(items), notice: None }
+    }
+
+    /// Save a changed config and have the game loop take it up
+    fn changed(&mut self, setting: Setting, ctx: &mut ScreenContext) {
+        self.menu.set_value(self.menu.selected(), setting.display(ctx.config, ctx.map));
+        self.notice = Some(match ctx.config.save() {
+            Ok(()) if setting == Setting::ServerUrl => "Saved; the server URL is used by the next connect".to_string(),
+            Ok(()) => format!("{} saved", setting.label()),
+            Err(e) => e,
+        });
+        ctx.commands.push_back(ChatCommand::ApplySettings);
+    }
+}
+
+impl Screen for SettingsScreen {
+    fn kind(&self) -> ScreenKind {
+        ScreenKind::Settings
+    }
+
+    fn handle_input(&mut self, press: KeyPress, ctx: &mut ScreenContext) -> Transition {
+        if press.action == Some(Action::Settings) && !self.menu.is_editing() {
+            return Transition::Pop;
+        }
+        match self.menu.input(press.key) {
+            MenuEvent::Nothing => {}
+            MenuEvent::Chosen(index) => {
+                let setting = Setting::ALL[index];
+                match setting {
+                    Setting::Effects => ctx.config.effects_enabled = !ctx.config.effects_enabled,
+                    Setting::Theme => ctx.config.theme = Some(next_theme(ctx.config.theme_name(), &theme::available())),
+                    Setting::Difficulty if ctx.map.chunks.is_some() => {
+                        self.notice = Some("Difficulty only applies to solo worlds; the server sets the rules online".to_string());
+                        return Transition::Stay;
+                    }
+                    Setting::Difficulty => {
+                        let world = ctx.map.world_key();
+                        let difficulty = next_difficulty(ctx.config.difficulty_for(&world));
+                        ctx.config.solo_difficulty.insert(world, difficulty);
+                        ctx.miner.difficulty = difficulty;
+                    }
+                    _ => {
+                        self.notice = None;
+                        self.menu.edit(setting.value(ctx.config));
+                        return Transition::Stay;
+                    }
+                }
+                self.changed(setting, ctx);
+            }
+            MenuEvent::Edited(index, text) => {
+                let setting = Setting::ALL[index];
+                match setting.set(ctx.config, &text) {
+                    Ok(()) => self.changed(setting, ctx),
+                    Err(e) => self.notice = Some(e),
+                }
+            }
+            MenuEvent::Closed => return Transition::Pop,
+        }
+        Transition::Stay
+    }
+
+    fn render(&self, _ctx: &ScreenContext) -> Panel {
+        let mut lines = self.menu.lines();
+        lines.push(String::new());
+        lines.push(self.notice.clone().unwrap_or_default());
+        lines.push(if self.menu.is_editing() {
+            "Enter saves, Esc cancels; empty for the default".to_string()
+        } else {
+            "Up/Down and Enter to change; Esc closes".to_string()
+        });
+        Panel::new(" SETTINGS ", lines, (0x808080, 0xE0E0FF, 0x000010))
+    }
+}
+
+/// The theme after `current` in `names`, wrapping; the first if `current` isn't there
+fn next_theme(current: &str, names: &[String]) -> String {
+    let index = names.iter().position(|name| name == current).map_or(0, |i| i + 1);
+    names.get(index % names.len().max(1)).cloned().unwrap_or_else(|| theme::DEFAULT_THEME.to_string())
+}
+
+/// The difficulty after `current`, wrapping from hard back to easy
+fn next_difficulty(current: Difficulty) -> Difficulty {
+    let index = Difficulty::ALL.iter().position(|&d| d == current).map_or(0, |i| i + 1);
+    Difficulty::ALL[index % Difficulty::ALL.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hull::ShipStatus;
+    use crate::mining::Miner;
+    use crate::screens::Key;
+    use crate::{ChatWindow, ChunkCache};
+    use std::collections::VecDeque;
+    use std::time::Duration;
+
+    fn press(key: Key) -> KeyPress {
+        KeyPress { key, action: None }
+    }
+
+    // ==================== Value Tests ====================
+
+    #[test]
+    fn test_values_are_checked() {
+        let mut config = Config::default();
+        let map = Map::generate_local(60, 40, 7);
+        assert_eq!(Setting::MoveDelay.display(&config, &map), "ship's own");
+        assert_eq!(Setting::KeyTimeout.display(&config, &map), "300 ms");
+        assert!(Setting::ServerUrl.display(&config, &map).starts_with("default ("));
+
+        assert_eq!(Setting::MoveDelay.set(&mut config, "5"), Err("Move delay must be 10 to 500 ms".to_string()));
+        assert_eq!(Setting::MoveDelay.set(&mut config, "40 ms"), Ok(()));
+        assert_eq!(config.move_delay_ms, Some(40));
+        assert_eq!(Setting::KeyTimeout.set(&mut config, "abc"), Err("Key timeout must be 50 to 2000 ms".to_string()));
+        assert_eq!(Setting::KeyTimeout.set(&mut config, ""), Ok(()), "Empty goes back to the default");
+        assert_eq!(config.key_timeout_ms, None);
+        assert_eq!(Setting::FrameRate.display(&config, &map), "60 fps");
+        assert_eq!(Setting::FrameRate.set(&mut config, "500"), Err("Frame rate must be 10 to 240 fps".to_string()));
+        assert_eq!(Setting::FrameRate.set(&mut config, "30 fps"), Ok(()));
+
+        assert_eq!(Setting::Brightness.display(&config, &map), "day/night cycle");
+        assert_eq!(Setting::Brightness.set(&mut config, "10"), Err("Brightness must be 20 to 100%".to_string()));
+        assert_eq!(Setting::Brightness.set(&mut config, "75%"), Ok(()));
+        assert_eq!(Setting::Brightness.display(&config, &map), "75%");
+        assert_eq!(config.target_fps(), 30);
+
+        assert_eq!(Setting::CameraLag.display(&config, &map), "80 ms");
+        assert_eq!(Setting::CameraLag.set(&mut config, "0"), Ok(()));
+        assert_eq!(Setting::CameraLag.display(&config, &map), "off", "No lag locks the view on the ship");
+        assert_eq!(Setting::CameraLag.set(&mut config, "5000"), Err("Camera lag must be 0 to 1000 ms".to_string()));
+        assert_eq!(Setting::DeadZone.set(&mut config, "3 cells"), Ok(()));
+        assert_eq!(Setting::DeadZone.display(&config, &map), "3 cells");
+        assert_eq!(Setting::LookAhead.set(&mut config, "11"), Err("Look-ahead must be 0 to 10 cells".to_string()));
+        assert_eq!(config.camera_feel().lookahead, 0);
+
+        assert!(Setting::ServerUrl.set(&mut config, "ftp://example.com").is_err());
+        assert_eq!(Setting::ServerUrl.set(&mut config, "http://example.com:3000/"), Ok(()));
+        assert_eq!(config.server_url.as_deref(), Some("http://example.com:3000"));
+        let names = theme::available_in(None);
+        assert_eq!(next_theme("default", &names), "high-contrast");
+        assert_eq!(next_theme("solarized", &names), "default", "Themes cycle");
+        assert_eq!(next_theme("deleted", &names), "default");
+    }
+
+    // ==================== Screen Tests ====================
+
+    #[test]
+    fn test_changes_are_saved_and_applied() {
+        let path = std::env::temp_dir().join(format!("exospace-settings-{}.json", std::process::id()));
+        let mut config = Config::load(Some(path.clone()));
+        let (mut ship, mut miner, mut chat, mut commands) = (ShipStatus::default(), Miner::default(), ChatWindow::default(), VecDeque::new());
+        let map = Map::generate_local(60, 40, 7);
+        let mut screen = SettingsScreen::new(&config, &map);
+        let mut input = |screen: &mut SettingsScreen, config: &mut Config, key| {
+            let mut ctx = ScreenContext { ship: &mut ship, miner: &mut miner, map: &map, chat: &mut chat, commands: &mut commands, config };
+            let transition = screen.handle_input(press(key), &mut ctx);
+            (matches!(transition, Transition::Pop), screen.render(&ctx).lines)
+        };
+
+        let (_, lines) = input(&mut screen, &mut config, Key::Enter);
+        assert!(config.effects_enabled, "Enter flips effects");
+        assert_eq!(lines[0], "> Effects      on");
+        assert_eq!(lines[12], "Effects saved");
+        assert!(Config::load(Some(path.clone())).effects_enabled, "Written to the config file");
+
+        input(&mut screen, &mut config, Key::Down);
+        input(&mut screen, &mut config, Key::Down);
+        input(&mut screen, &mut config, Key::Enter);
+        for ch in "25".chars() {
+            input(&mut screen, &mut config, Key::Char(ch));
+        }
+        let (_, lines) = input(&mut screen, &mut config, Key::Enter);
+        assert_eq!(lines[2], "> Move delay   25 ms");
+        assert_eq!(config.move_delay(Duration::from_millis(33)), Duration::from_millis(25));
+
+        input(&mut screen, &mut config, Key::Enter);
+        input(&mut screen, &mut config, Key::Char('0'));
+        input(&mut screen, &mut config, Key::Char('0'));
+        let (_, lines) = input(&mut screen, &mut config, Key::Enter);
+        assert_eq!(lines[12], "Move delay must be 10 to 500 ms");
+        assert_eq!(config.move_delay_ms, Some(25), "A bad value changes nothing");
+
+        let (closed, _) = input(&mut screen, &mut config, Key::Esc);
+        assert!(closed);
+        assert_eq!(commands.iter().filter(|&command| *command == ChatCommand::ApplySettings).count(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_difficulty_cycles_for_solo_worlds() {
+        let path = std::env::temp_dir().join(format!("exospace-settings-difficulty-{}.json", std::process::id()));
+        let mut config = Config::load(Some(path.clone()));
+        let (mut ship, mut miner, mut chat, mut commands) = (ShipStatus::default(), Miner::default(), ChatWindow::default(), VecDeque::new());
+        let mut map = Map::generate_local(60, 40, 7);
+        let mut screen = SettingsScreen::new(&config, &map);
+        let mut input = |screen: &mut SettingsScreen, config: &mut Config, map: &Map, key| {
+            let mut ctx = ScreenContext { ship: &mut ship, miner: &mut miner, map, chat: &mut chat, commands: &mut commands, config };
+            screen.handle_input(press(key), &mut ctx);
+            screen.render(&ctx).lines
+        };
+
+        input(&mut screen, &mut config, &map, Key::Up);
+        let lines = input(&mut screen, &mut config, &map, Key::Enter);
+        assert_eq!(lines[10], "> Difficulty   hard", "Normal steps to hard");
+        assert_eq!(lines[12], "Difficulty saved");
+        assert_eq!(Config::load(Some(path.clone())).difficulty_for(&map.world_key()), Difficulty::Hard, "Written for this world");
+        let lines = input(&mut screen, &mut config, &map, Key::Enter);
+        assert_eq!(lines[10], "> Difficulty   easy", "And wraps");
+        assert_eq!(config.difficulty_for("local-8"), Difficulty::Normal, "Other worlds keep theirs");
+
+        map.chunks = Some(ChunkCache::new("http://127.0.0.1:9", None, 7, true));
+        let mut screen = SettingsScreen::new(&config, &map);
+        input(&mut screen, &mut config, &map, Key::Up);
+        let lines = input(&mut screen, &mut config, &map, Key::Enter);
+        assert_eq!(lines[10], "> Difficulty   set by the server");
+        assert!(lines[12].starts_with("Difficulty only applies to solo worlds"));
+        assert_eq!(commands.iter().filter(|&command| *command == ChatCommand::ApplySettings).count(), 2, "Nothing changed online");
+        assert_eq!(miner.difficulty, Difficulty::Easy, "Mining follows straight away");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
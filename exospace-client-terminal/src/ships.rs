@@ -0,0 +1,127 @@
+//! Ship classes.
+//!
+//! The class decides how fast the ship flies (the length of a movement tick),
+//! how much its hold carries and how much hull it has, and it has its own
+//! sprites (drawn in `main.rs`). It's saved in the config; until one is picked
+//! the game opens on the ship menu, and `/ship CLASS` changes it later.
+//! Other pilots' ships and NPCs are always drawn as scouts since the server
+//! doesn't know about classes.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::hull::MAX_HULL;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShipClass {
+    /// The all-rounder every pilot started with
+    #[default]
+    Scout,
+    /// Slow and tough with a big hold
+    Freighter,
+    /// Fast and fragile with room for little
+    Interceptor,
+}
+
+impl ShipClass {
+    pub const ALL: [ShipClass; 3] = [ShipClass::Scout, ShipClass::Freighter, ShipClass::Interceptor];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ShipClass::Scout => "scout",
+            ShipClass::Freighter => "freighter",
+            ShipClass::Interceptor => "interceptor",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ShipClass> {
+        Self::ALL.into_iter().find(|class| class.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Time between movement ticks; shorter is faster
+    pub fn move_delay(self) -> Duration {
+        Duration::from_millis(match self {
+            ShipClass::Scout => 33,
+            ShipClass::Freighter => 45,
+            ShipClass::Interceptor => 25,
+        })
+    }
+
+    /// Units of ore and crystal the hold takes
+    pub fn cargo_capacity(self) -> u32 {
+        match self {
+            ShipClass::Scout => 40,
+            ShipClass::Freighter => 120,
+            ShipClass::Interceptor => 20,
+        }
+    }
+
+    pub fn max_hull(self) -> f32 {
+        match self {
+            ShipClass::Scout => MAX_HULL,
+            ShipClass::Freighter => 160.0,
+            ShipClass::Interceptor => 70.0,
+        }
+    }
+
+    /// One line of stats, e.g. "scout: 30 tiles/s, hold 40, hull 100"
+    pub fn describe(self) -> String {
+        format!(
+            "{}: {} tiles/s, hold {}, hull {}",
+            self.name(),
+            1000 / self.move_delay().as_millis(),
+            self.cargo_capacity(),
+            self.max_hull()
+        )
+    }
+}
+
+/// Startup menu text; `menu_choice` maps the digits to classes
+pub fn menu_lines() -> Vec<String> {
+    let mut lines = vec!["Choose your ship:".to_string(), String::new()];
+    for (i, class) in ShipClass::ALL.into_iter().enumerate() {
+        lines.push(format!("{}  {}", i + 1, class.describe()));
+    }
+    lines.push(String::new());
+    lines.push("Change it later with /ship".to_string());
+    lines
+}
+
+/// The class picked by a key on the startup menu
+pub fn menu_choice(key: char) -> Option<ShipClass> {
+    let index = key.to_digit(10)?.checked_sub(1)?;
+    ShipClass::ALL.get(index as usize).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Class Tests ====================
+
+    #[test]
+    fn test_classes_trade_speed_for_size() {
+        let (scout, freighter, interceptor) = (ShipClass::Scout, ShipClass::Freighter, ShipClass::Interceptor);
+        assert!(interceptor.move_delay() < scout.move_delay() && scout.move_delay() < freighter.move_delay());
+        assert!(interceptor.cargo_capacity() < scout.cargo_capacity() && scout.cargo_capacity() < freighter.cargo_capacity());
+        assert!(interceptor.max_hull() < scout.max_hull() && scout.max_hull() < freighter.max_hull());
+        assert_eq!(ShipClass::default().move_delay(), Duration::from_millis(33), "Scouts fly like ships always did");
+        assert_eq!(scout.describe(), "scout: 30 tiles/s, hold 40, hull 100");
+    }
+
+    #[test]
+    fn test_class_names_and_menu() {
+        for class in ShipClass::ALL {
+            assert_eq!(ShipClass::from_name(&class.name().to_uppercase()), Some(class));
+            let json = serde_json::to_string(&class).unwrap();
+            assert_eq!(json, format!("\"{}\"", class.name()));
+        }
+        assert_eq!(ShipClass::from_name("dreadnought"), None);
+        assert_eq!(menu_choice('2'), Some(ShipClass::Freighter));
+        assert_eq!(menu_choice('0'), None);
+        assert_eq!(menu_choice('4'), None);
+        assert_eq!(menu_choice('x'), None);
+        assert!(menu_lines().iter().any(|line| line.starts_with("3  interceptor")));
+    }
+}
@@ -0,0 +1,73 @@
+//! The `/stats` dashboard.
+//!
+//! The server keeps the numbers; `/stats` asks for them and the reply opens
+//! a panel with lifetime totals beside what this session added. Guests have
+//! no lifetime column since nothing is kept between their sessions. The
+//! panel is a screen of its own (see `screens.rs`) and closes on the next
+//! key press.
+
+use exospace_core::PilotStats;
+
+/// Reads one figure out of a set of stats
+type Figure = fn(&PilotStats) -> u64;
+
+/// Dashboard rows, top to bottom
+const ROWS: &[(&str, Figure)] = &[
+    ("distance", |s| s.distance),
+    ("ore mined", |s| s.ore_mined),
+    ("crystals", |s| s.crystal_mined),
+    ("sectors", |s| s.sectors_explored),
+];
+
+/// A stats reply, as shown
+pub struct StatsScreen {
+    name: String,
+    lifetime: Option<PilotStats>,
+    session: PilotStats,
+}
+
+impl StatsScreen {
+    pub fn new(name: String, lifetime: Option<PilotStats>, session: PilotStats) -> Self {
+        StatsScreen { name, lifetime, session }
+    }
+
+    /// Panel text, one line per figure
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("Pilot {}", self.name), format!("{:<10}{:>10}{:>9}", "", "lifetime", "session")];
+        for &(label, figure) in ROWS {
+            let lifetime = self.lifetime.as_ref().map_or("-".to_string(), |stats| figure(stats).to_string());
+            lines.push(format!("{:<10}{:>10}{:>9}", label, lifetime, format!("+{}", figure(&self.session))));
+        }
+        if self.lifetime.is_none() {
+            lines.push("Log in to keep lifetime stats".to_string());
+        }
+        lines.push("Any key closes".to_string());
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Panel Tests ====================
+
+    #[test]
+    fn test_lines_show_lifetime_and_session() {
+        let lifetime = PilotStats { distance: 1234, ore_mined: 12, crystal_mined: 2, sectors_explored: 7 };
+        let screen = StatsScreen::new("Ace".to_string(), Some(lifetime), PilotStats { distance: 40, ..PilotStats::default() });
+        let lines = screen.lines();
+        assert_eq!(lines[0], "Pilot Ace");
+        assert_eq!(lines[2], "distance        1234      +40");
+        assert_eq!(lines[5], "sectors            7       +0");
+        assert_eq!(lines.len(), 7, "No login hint for accounts");
+    }
+
+    #[test]
+    fn test_guest_lines_have_no_lifetime() {
+        let screen = StatsScreen::new("Guest-ace".to_string(), None, PilotStats { ore_mined: 3, ..PilotStats::default() });
+        let lines = screen.lines();
+        assert_eq!(lines[3], "ore mined          -       +3");
+        assert!(lines.contains(&"Log in to keep lifetime stats".to_string()));
+    }
+}
@@ -0,0 +1,280 @@
+//! Color themes: the colors the view, the ship and the chat pane are drawn in.
+//!
+//! Four presets are built in (`PRESETS`). Anything else is a JSON file,
+//! `~/.config/exospace/themes/<name>.json`, with colors written as
+//! `"#RRGGBB"`; colors it leaves out come from the default theme. The
+//! theme in use is named by `Config::theme` and picked with `/theme` or the
+//! settings screen.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{parse_hex_color, ChatChannel, ShipPalette};
+
+/// Built-in themes, in the order the settings screen steps through them
+pub const PRESETS: [&str; 4] = ["default", "high-contrast", "monochrome", "solarized"];
+
+/// Theme used when none is configured
+pub const DEFAULT_THEME: &str = PRESETS[0];
+
+/// Longest custom theme name accepted
+pub const MAX_THEME_NAME_LEN: usize = 32;
+
+const THEME_EXTENSION: &str = "json";
+
+/// A color, written "#RRGGBB" in theme files
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rgb(pub u32);
+
+impl Serialize for Rgb {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("#{:06X}", self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Rgb {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        parse_hex_color(&text)
+            .map(Rgb)
+            .ok_or_else(|| serde::de::Error::custom(format!("bad color {} (use #RRGGBB)", text)))
+    }
+}
+
+/// Every color a theme sets
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Theme {
+    /// Walls with effects off
+    pub wall: Rgb,
+    /// Walls with effects on shade between these two
+    pub wall_shades: [Rgb; 2],
+    /// The occasional tinted wall with effects on
+    pub wall_accents: [Rgb; 2],
+    /// A twinkling star's colors as it cycles
+    pub stars: [Rgb; 4],
+    /// Still stars: blue and dim
+    pub faint_stars: [Rgb; 2],
+    /// Asteroid shades; effects off uses the last
+    pub asteroids: [Rgb; 4],
+    /// Ore asteroids, two shades
+    pub ore: [Rgb; 2],
+    /// Crystal fields, resting and glinting
+    pub crystal: [Rgb; 2],
+    /// Nebula tint for each of `hull::nebula_region()`'s regions
+    pub nebula: [Rgb; 6],
+    /// Tiles in each biome but deep space are tinted toward its color: asteroid belts, nebula
+    /// clouds, debris fields and station sectors
+    pub biomes: [Rgb; 4],
+    /// The local ship: hull, cockpit, wing and accent
+    pub ship: [Rgb; 4],
+    /// Channel colors, under any set by `chat_colors` in the config
+    pub chat: HashMap<ChatChannel, Rgb>,
+    /// Chat pane background
+    pub chat_background: Rgb,
+    /// Status bar text and background
+    pub status: [Rgb; 2],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let ship = ShipPalette::player();
+        Theme {
+            wall: Rgb(0x4060A0),
+            wall_shades: [Rgb(0x2050C0), Rgb(0x2070C0)],
+            wall_accents: [Rgb(0x3090A0), Rgb(0x604080)],
+            stars: [Rgb(0xC0C0C0), Rgb(0xD0D0A0), Rgb(0xA0C0C0), Rgb(0xC0C0C0)],
+            faint_stars: [Rgb(0x5070C0), Rgb(0x505050)],
+            asteroids: [Rgb(0x907050), Rgb(0x707070), Rgb(0x806040), Rgb(0x808080)],
+            ore: [Rgb(0xD0A040), Rgb(0xB08830)],
+            crystal: [Rgb(0x40C0C0), Rgb(0xA0FFFF)],
+            nebula: [Rgb(0x804080), Rgb(0x407080), Rgb(0x805040), Rgb(0x504080), Rgb(0x407050), Rgb(0x505070)],
+            biomes: [Rgb(0xA08060), Rgb(0xA060C0), Rgb(0xA05040), Rgb(0x60A0C0)],
+            ship: [Rgb(ship.hull), Rgb(ship.cockpit), Rgb(ship.wing), Rgb(ship.accent)],
+            chat: HashMap::new(),
+            chat_background: Rgb(0x000010),
+            status: [Rgb(0x00FF00), Rgb(0x000020)],
+        }
+    }
+}
+
+impl Theme {
+    /// A built-in theme by name
+    pub fn preset(name: &str) -> Option<Theme> {
+        let chat = |system, input, error, ambient| {
+            HashMap::from([
+                (ChatChannel::System, Rgb(system)),
+                (ChatChannel::Input, Rgb(input)),
+                (ChatChannel::Error, Rgb(error)),
+                (ChatChannel::Ambient, Rgb(ambient)),
+            ])
+        };
+        let theme = match name {
+            "default" => Theme::default(),
+            "high-contrast" => Theme {
+                wall: Rgb(0x6090FF),
+                wall_shades: [Rgb(0x4080FF), Rgb(0x60A0FF)],
+                wall_accents: [Rgb(0x40E0FF), Rgb(0xA060FF)],
+                stars: [Rgb(0xFFFFFF); 4],
+                faint_stars: [Rgb(0x80A0FF), Rgb(0xA0A0A0)],
+                asteroids: [Rgb(0xE0A070), Rgb(0xC0C0C0), Rgb(0xD09060), Rgb(0xE0E0E0)],
+                ore: [Rgb(0xFFD040), Rgb(0xFFC000)],
+                crystal: [Rgb(0x40FFFF), Rgb(0xFFFFFF)],
+                nebula: [Rgb(0xC060C0), Rgb(0x60B0C0), Rgb(0xD08050), Rgb(0x8060D0), Rgb(0x60C080), Rgb(0x9090C0)],
+                biomes: [Rgb(0xFFB060), Rgb(0xE080FF), Rgb(0xFF7050), Rgb(0x80E0FF)],
+                ship: [Rgb(0x00FF80), Rgb(0xFFFFFF), Rgb(0x00C0FF), Rgb(0x80FFFF)],
+                chat: chat(0xFFFF00, 0x00FF00, 0xFF5050, 0xC0FFC0),
+                chat_background: Rgb(0x000000),
+                status: [Rgb(0xFFFFFF), Rgb(0x000000)],
+            },
+            "monochrome" => Theme {
+                wall: Rgb(0x909090),
+                wall_shades: [Rgb(0x808080), Rgb(0xA0A0A0)],
+                wall_accents: [Rgb(0x707070), Rgb(0xB0B0B0)],
+                stars: [Rgb(0xC0C0C0), Rgb(0xE0E0E0), Rgb(0xB0B0B0), Rgb(0xC0C0C0)],
+                faint_stars: [Rgb(0x707070), Rgb(0x505050)],
+                asteroids: [Rgb(0x787878), Rgb(0x686868), Rgb(0x707070), Rgb(0x808080)],
+                ore: [Rgb(0xD0D0D0), Rgb(0xB0B0B0)],
+                crystal: [Rgb(0xE0E0E0), Rgb(0xFFFFFF)],
+                nebula: [Rgb(0x707070), Rgb(0x686868), Rgb(0x787878), Rgb(0x606060), Rgb(0x6C6C6C), Rgb(0x646464)],
+                biomes: [Rgb(0xA0A0A0), Rgb(0x909090), Rgb(0x808080), Rgb(0xB0B0B0)],
+                ship: [Rgb(0xE0E0E0), Rgb(0xFFFFFF), Rgb(0xA0A0A0), Rgb(0xC0C0C0)],
+                chat: chat(0xFFFFFF, 0xD0D0D0, 0xFFFFFF, 0x909090),
+                chat_background: Rgb(0x101010),
+                status: [Rgb(0xE0E0E0), Rgb(0x202020)],
+            },
+            "solarized" => Theme {
+                wall: Rgb(0x268BD2),
+                wall_shades: [Rgb(0x1F78B8), Rgb(0x268BD2)],
+                wall_accents: [Rgb(0x2AA198), Rgb(0x6C71C4)],
+                stars: [Rgb(0x93A1A1), Rgb(0xEEE8D5), Rgb(0x839496), Rgb(0x93A1A1)],
+                faint_stars: [Rgb(0x268BD2), Rgb(0x586E75)],
+                asteroids: [Rgb(0x8B6F47), Rgb(0x657B83), Rgb(0x7A5F3A), Rgb(0x839496)],
+                ore: [Rgb(0xB58900), Rgb(0xCB4B16)],
+                crystal: [Rgb(0x2AA198), Rgb(0x93E0D8)],
+                nebula: [Rgb(0xD33682), Rgb(0x2AA198), Rgb(0xCB4B16), Rgb(0x6C71C4), Rgb(0x859900), Rgb(0x586E75)],
+                biomes: [Rgb(0xB58900), Rgb(0x6C71C4), Rgb(0xCB4B16), Rgb(0x268BD2)],
+                ship: [Rgb(0x859900), Rgb(0xEEE8D5), Rgb(0x2AA198), Rgb(0x93A1A1)],
+                chat: chat(0xB58900, 0x859900, 0xDC322F, 0x839496),
+                chat_background: Rgb(0x002B36),
+                status: [Rgb(0x93A1A1), Rgb(0x073642)],
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// A preset, else the custom theme file of that name
+    pub fn load(name: &str) -> Result<Theme, String> {
+        if let Some(theme) = Theme::preset(name) {
+            return Ok(theme);
+        }
+        check_name(name)?;
+        let dir = dir().ok_or_else(|| "Could not determine config directory".to_string())?;
+        load_from(&dir, name)
+    }
+
+    /// How the local ship's sprite is painted
+    pub fn ship_palette(&self) -> ShipPalette {
+        let [hull, cockpit, wing, accent] = self.ship.map(|color| color.0);
+        ShipPalette { hull, cockpit, wing, accent }
+    }
+}
+
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_THEME_NAME_LEN {
+        return Err(format!("Theme names are 1-{} characters", MAX_THEME_NAME_LEN));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err("Theme names use letters, digits, - and _".to_string());
+    }
+    Ok(())
+}
+
+/// Directory holding custom themes
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|mut p| {
+        p.push("exospace");
+        p.push("themes");
+        p
+    })
+}
+
+pub fn load_from(dir: &Path, name: &str) -> Result<Theme, String> {
+    let path = dir.join(name).with_extension(THEME_EXTENSION);
+    let text = fs::read_to_string(&path).map_err(|_| format!("No theme named '{}' (try {})", name, PRESETS.join(", ")))?;
+    serde_json::from_str(&text).map_err(|e| format!("Bad theme '{}': {}", name, e))
+}
+
+/// Every theme to choose from: the presets, then custom files by name
+pub fn available() -> Vec<String> {
+    available_in(dir().as_deref())
+}
+
+pub fn available_in(dir: Option<&Path>) -> Vec<String> {
+    let mut custom: Vec<String> = dir
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == THEME_EXTENSION))
+        .filter_map(|entry| entry.path().file_stem()?.to_str().map(str::to_string))
+        .filter(|name| check_name(name).is_ok() && !PRESETS.contains(&name.as_str()))
+        .collect();
+    custom.sort();
+    PRESETS.iter().map(|name| name.to_string()).chain(custom).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Preset Tests ====================
+
+    #[test]
+    fn test_presets() {
+        for name in PRESETS {
+            assert!(Theme::preset(name).is_some(), "{} is built in", name);
+        }
+        assert_eq!(Theme::preset("default"), Some(Theme::default()));
+        assert_eq!(Theme::default().ship_palette(), ShipPalette::player(), "The default ship keeps its colors");
+        assert!(Theme::default().chat.is_empty(), "The default theme leaves chat colors alone");
+        assert_eq!(Theme::preset("solarized").unwrap().chat_background, Rgb(0x002B36));
+        assert_eq!(Theme::preset("neon"), None);
+    }
+
+    // ==================== Theme File Tests ====================
+
+    #[test]
+    fn test_custom_theme_files() {
+        let dir = std::env::temp_dir().join(format!("exospace-themes-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("dusk.json"), r##"{ "wall": "#112233", "chat": { "system": "#FFAA00" } }"##).unwrap();
+        fs::write(dir.join("broken.json"), r##"{ "wall": "blue" }"##).unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let dusk = load_from(&dir, "dusk").unwrap();
+        assert_eq!(dusk.wall, Rgb(0x112233));
+        assert_eq!(dusk.chat[&ChatChannel::System], Rgb(0xFFAA00));
+        assert_eq!(dusk.stars, Theme::default().stars, "Left-out colors come from the default theme");
+        assert!(load_from(&dir, "broken").unwrap_err().contains("#RRGGBB"));
+        assert!(load_from(&dir, "missing").unwrap_err().starts_with("No theme named"));
+        assert!(check_name("../config").is_err(), "Names can't leave the themes directory");
+
+        let names = available_in(Some(&dir));
+        assert_eq!(&names[..4], &PRESETS);
+        assert_eq!(&names[4..], ["broken", "dusk"]);
+        assert_eq!(available_in(None), PRESETS);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_theme_json_round_trip() {
+        let theme = Theme::preset("high-contrast").unwrap();
+        let json = serde_json::to_string(&theme).unwrap();
+        assert!(json.contains(r##""wall":"#6090FF""##));
+        assert_eq!(serde_json::from_str::<Theme>(&json).unwrap(), theme);
+    }
+}
@@ -0,0 +1,259 @@
+//! The title screen: the startup menu over a drifting starfield.
+//!
+//! The game starts behind it on a solo world unless `--server` picked one.
+//! Continue, there when the last game left a `session` behind, picks up
+//! where it stopped; New Game flies the world behind the menu offline; Connect opens a field with the server
+//! URL and has the game loop load the server's world and dial in. The
+//! leaderboards and settings open over the menu and close back to it, and
+//! Quit leaves. The menu is a screen of its own (see `screens.rs`).
+
+use std::time::{Duration, Instant};
+
+use crate::menu::{Menu, MenuEvent, MenuItem};
+use crate::screens::{Key, Screen};
+use exospace_core::hash_position;
+
+/// Starfield layers, far to near: one star per this many cells, drift in columns a second, glyph, color
+const STAR_LAYERS: [(u32, f32, char, u32); 3] = [(40, 1.0, '.', 0x405070), (90, 2.5, '+', 0x8090B0), (220, 6.0, '*', 0xE0E8FF)];
+
+/// The menu's rows, in order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TitleItem {
+    Continue,
+    NewGame,
+    Connect,
+    Leaderboards,
+    Settings,
+    Quit,
+}
+
+impl TitleItem {
+    pub const ALL: [TitleItem; 6] =
+        [TitleItem::Continue, TitleItem::NewGame, TitleItem::Connect, TitleItem::Leaderboards, TitleItem::Settings, TitleItem::Quit];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TitleItem::Continue => "Continue",
+            TitleItem::NewGame => "New Game",
+            TitleItem::Connect => "Connect",
+            TitleItem::Leaderboards => "Leaderboards",
+            TitleItem::Settings => "Settings",
+            TitleItem::Quit => "Quit",
+        }
+    }
+}
+
+/// What the pilot picked from the menu
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TitleChoice {
+    Continue,
+    NewGame,
+    /// The server URL typed
+    Connect(String),
+    Leaderboards,
+    Settings,
+    Quit,
+}
+
+/// The startup menu
+pub struct TitleScreen {
+    opened: Instant,
+    /// The latest frame's time, for the starfield
+    now: Instant,
+    menu: Menu,
+    /// The rows shown: every item, less Continue without a session
+    items: Vec<TitleItem>,
+    /// Why the last URL was refused
+    notice: Option<String>,
+    /// Opened in its place once the game starts, e.g. the shipyard for a first run
+    then: Option<Box<dyn Screen>>,
+}
+
+impl TitleScreen {
+    /// `session` describes the game Continue would resume, if there is one
+    pub fn new(now: Instant, server_url: &str, session: Option<String>, then: Option<Box<dyn Screen>>) -> Self {
+        let items: Vec<TitleItem> = TitleItem::ALL.into_iter().filter(|&item| item != TitleItem::Continue || session.is_some()).collect();
+        let rows = items
+            .iter()
+            .map(|&item| {
+                let value = match item {
+                    TitleItem::Continue => session.clone().unwrap_or_default(),
+                    TitleItem::Connect => server_url.to_string(),
+                    _ => String::new(),
+                };
+                MenuItem::new(item.label(), value)
+            })
+            .collect();
+        TitleScreen { opened: now, now, menu: Menu::new(rows), items, notice: None, then }
+    }
+
+    /// The screen to open once the game starts
+    pub fn take_then(&mut self) -> Option<Box<dyn Screen>> {
+        self.then.take()
+    }
+
+    /// Keep the Connect row showing the server a connection would go to
+    pub fn tick(&mut self, now: Instant, server_url: &str) {
+        self.now = now;
+        if !self.menu.is_editing() {
+            self.menu.set_value(self.row(TitleItem::Connect), server_url.to_string());
+        }
+    }
+
+    fn row(&self, item: TitleItem) -> usize {
+        self.items.iter().position(|&row| row == item).unwrap_or(0)
+    }
+
+    /// Feed the menu a key; L opens the leaderboards from anywhere but the URL field
+    pub fn input(&mut self, key: Key, server_url: &str) -> Option<TitleChoice> {
+        if !self.menu.is_editing() && matches!(key, Key::Char('l' | 'L')) {
+            return Some(TitleChoice::Leaderboards);
+        }
+        match self.menu.input(key) {
+            MenuEvent::Chosen(row) => match self.items[row] {
+                TitleItem::Continue => Some(TitleChoice::Continue),
+                TitleItem::NewGame => Some(TitleChoice::NewGame),
+                TitleItem::Connect => {
+                    self.notice = None;
+                    self.menu.edit(server_url.to_string());
+                    None
+                }
+                TitleItem::Leaderboards => Some(TitleChoice::Leaderboards),
+                TitleItem::Settings => Some(TitleChoice::Settings),
+                TitleItem::Quit => Some(TitleChoice::Quit),
+            },
+            MenuEvent::Edited(_, text) => {
+                let url = text.trim().trim_end_matches('/');
+                if url.starts_with("http://") || url.starts_with("https://") {
+                    Some(TitleChoice::Connect(url.to_string()))
+                } else {
+                    self.notice = Some("Server URL must start with http:// or https://".to_string());
+                    None
+                }
+            }
+            MenuEvent::Closed | MenuEvent::Nothing => None,
+        }
+    }
+
+    /// Panel text: the name, the menu, then the keys
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec!["E X O S P A C E".to_string(), "Mine, trade and explore".to_string(), String::new()];
+        lines.extend(self.menu.lines());
+        lines.push(String::new());
+        lines.push(self.notice.clone().unwrap_or_else(|| {
+            if self.menu.is_editing() { "Enter connects, Esc cancels" } else { "Up/Down and Enter; L for leaderboards" }.to_string()
+        }));
+        lines
+    }
+
+    /// How long the starfield has been drifting
+    pub fn elapsed(&self) -> Duration {
+        self.now.duration_since(self.opened)
+    }
+}
+
+/// The stars to draw over a `cols` x `rows` area, `elapsed` into their drift, as (x, y, glyph, color);
+/// nearer layers drift faster and come later, so they're drawn on top
+pub fn starfield(cols: u32, rows: u32, elapsed: Duration) -> Vec<(u32, u32, char, u32)> {
+    if cols == 0 || rows == 0 {
+        return Vec::new();
+    }
+    let mut stars = Vec::new();
+    for (layer, &(density, speed, glyph, color)) in STAR_LAYERS.iter().enumerate() {
+        let drift = (elapsed.as_secs_f32() * speed) as u32;
+        for i in 0..(cols * rows / density).max(1) {
+            let x = hash_position(i as i32, layer as i32, 0x5747) % cols;
+            let y = hash_position(i as i32, layer as i32, 0x7261) % rows;
+            stars.push(((x + cols - drift % cols) % cols, y, glyph, color));
+        }
+    }
+    stars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "http://localhost:3000";
+
+    fn title() -> TitleScreen {
+        TitleScreen::new(Instant::now(), URL, None, None)
+    }
+
+    // ==================== Menu Tests ====================
+
+    #[test]
+    fn test_menu_choices() {
+        let mut title = title();
+        assert_eq!(title.input(Key::Enter, URL), Some(TitleChoice::NewGame), "New Game is first");
+        title.input(Key::Up, URL);
+        assert_eq!(title.input(Key::Enter, URL), Some(TitleChoice::Quit), "The cursor wraps");
+        title.input(Key::Up, URL);
+        assert_eq!(title.input(Key::Enter, URL), Some(TitleChoice::Settings));
+        assert_eq!(title.input(Key::Char('l'), URL), Some(TitleChoice::Leaderboards));
+        assert_eq!(title.input(Key::Esc, URL), None, "Esc doesn't leave the menu");
+    }
+
+    #[test]
+    fn test_continue_only_with_a_session() {
+        assert!(!title().lines().iter().any(|line| line.contains("Continue")), "Nothing to continue on a first run");
+        let mut title = TitleScreen::new(Instant::now(), URL, Some("solo, seed 7 at (1, 2)".to_string()), None);
+        assert!(title.lines().contains(&"> Continue      solo, seed 7 at (1, 2)".to_string()), "Continue is first, with where it left off");
+        assert_eq!(title.input(Key::Enter, URL), Some(TitleChoice::Continue));
+        title.input(Key::Down, URL);
+        assert_eq!(title.input(Key::Enter, URL), Some(TitleChoice::NewGame));
+        title.input(Key::Down, URL);
+        title.tick(Instant::now(), "http://other:3000");
+        assert!(title.lines().contains(&"> Connect       http://other:3000".to_string()), "Rows after it keep their values");
+    }
+
+    #[test]
+    fn test_connect_takes_a_url() {
+        let mut title = title();
+        title.input(Key::Down, URL);
+        assert_eq!(title.input(Key::Enter, URL), None, "Connect opens the field");
+        assert!(title.lines().contains(&format!("> Connect       [{}_]", URL)), "Starting from the current server");
+        for _ in 0..URL.len() {
+            title.input(Key::Backspace, URL);
+        }
+        for ch in "game.example:4000".chars() {
+            title.input(Key::Char(ch), URL);
+        }
+        assert_eq!(title.input(Key::Enter, URL), None);
+        assert_eq!(title.lines().last().map(String::as_str), Some("Server URL must start with http:// or https://"));
+
+        title.input(Key::Enter, URL);
+        title.input(Key::Char('l'), URL);
+        assert_eq!(title.input(Key::Enter, URL), Some(TitleChoice::Connect(format!("{}l", URL))), "L types in the field");
+        title.input(Key::Enter, URL);
+        for _ in 0..URL.len() + 1 {
+            title.input(Key::Backspace, URL);
+        }
+        for ch in "https://game.example/".chars() {
+            title.input(Key::Char(ch), URL);
+        }
+        assert_eq!(title.input(Key::Enter, URL), Some(TitleChoice::Connect("https://game.example".to_string())));
+    }
+
+    #[test]
+    fn test_connect_row_follows_the_config() {
+        let mut title = title();
+        title.tick(Instant::now(), "http://other:3000");
+        assert!(title.lines().contains(&"  Connect       http://other:3000".to_string()));
+    }
+
+    // ==================== Starfield Tests ====================
+
+    #[test]
+    fn test_starfield_drifts_left_and_wraps() {
+        let still = starfield(80, 20, Duration::ZERO);
+        assert!(still.iter().all(|&(x, y, _, _)| x < 80 && y < 20));
+        assert_eq!(still.len(), (1600 / 40 + 1600 / 90 + 1600 / 220) as usize);
+        let later = starfield(80, 20, Duration::from_secs(1));
+        let (near_then, near_now) = (still.last().unwrap(), later.last().unwrap());
+        assert_eq!(near_now.0, (near_then.0 + 80 - 6) % 80, "Near stars drift six columns a second");
+        assert_eq!(near_now.1, near_then.1, "Stars keep their rows");
+        assert_eq!(starfield(80, 20, Duration::from_secs(3)), starfield(80, 20, Duration::from_secs(3)), "The same sky every time");
+        assert!(starfield(0, 20, Duration::ZERO).is_empty());
+    }
+}
@@ -0,0 +1,183 @@
+//! Bookmarked positions and the waypoint the ship is heading for.
+//!
+//! `/mark NAME` bookmarks the ship's position, `/waypoints` lists the
+//! bookmarks nearest first and `/goto-mark NAME` makes one the active
+//! waypoint and sets the autopilot on it. Bookmarks are kept in the config
+//! per world (`Map::world_key()`, so per seed). While the active waypoint is
+//! off screen, an arrow on the edge of the view points the way with the
+//! distance to go; it clears once the ship gets there.
+
+use exospace_core::Direction;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::poi;
+
+/// Most bookmarks one world keeps
+pub const MAX_BOOKMARKS: usize = 50;
+
+/// Longest bookmark name, in characters
+pub const MAX_NAME_LEN: usize = 24;
+
+/// A waypoint this close, in tiles, counts as reached
+pub const ARRIVAL_DISTANCE: u32 = 1;
+
+/// How the waypoint is marked when it's in view
+pub const WAYPOINT_STYLE: (char, u32) = ('◎', 0xFF80C0);
+
+/// One world's bookmarks, by name
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Bookmarks {
+    marks: BTreeMap<String, (i32, i32)>,
+}
+
+impl Bookmarks {
+    /// Bookmark a position, replacing one of the same name in any case; true if it replaced one
+    pub fn mark(&mut self, name: &str, at: (i32, i32)) -> Result<bool, String> {
+        if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+            return Err(format!("Bookmark names are 1-{} characters", MAX_NAME_LEN));
+        }
+        if !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            return Err("Bookmark names use letters, digits, - and _".to_string());
+        }
+        let replaced = self.get(name).map(|waypoint| waypoint.name);
+        if replaced.is_none() && self.marks.len() >= MAX_BOOKMARKS {
+            return Err(format!("This world already has {} bookmarks", MAX_BOOKMARKS));
+        }
+        if let Some(old) = &replaced {
+            self.marks.remove(old);
+        }
+        self.marks.insert(name.to_string(), at);
+        Ok(replaced.is_some())
+    }
+
+    /// A bookmark by name, ignoring case
+    pub fn get(&self, name: &str) -> Option<Waypoint> {
+        self.marks
+            .iter()
+            .find(|(mark, _)| mark.eq_ignore_ascii_case(name))
+            .map(|(mark, &(x, y))| Waypoint { name: mark.clone(), x, y })
+    }
+
+    /// `/waypoints` output from `from`, nearest first, e.g. "Home: 12 tiles NE at (8, -9)"
+    pub fn listing(&self, from: (i32, i32)) -> Vec<String> {
+        let mut marks: Vec<(u32, &String, (i32, i32))> =
+            self.marks.iter().map(|(name, &(x, y))| (poi::distance(x - from.0, y - from.1), name, (x, y))).collect();
+        marks.sort();
+        marks
+            .into_iter()
+            .map(|(tiles, name, (x, y))| {
+                let heading = poi::bearing(x - from.0, y - from.1).map_or("here", |direction| direction.name());
+                format!("{}: {} tiles {} at ({}, {})", name, tiles, heading, x, y)
+            })
+            .collect()
+    }
+}
+
+/// A bookmark the ship is heading for
+#[derive(Clone, Debug, PartialEq)]
+pub struct Waypoint {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Waypoint {
+    /// Tiles left to fly from `from`
+    pub fn distance(&self, (x, y): (i32, i32)) -> u32 {
+        poi::distance(self.x - x, self.y - y)
+    }
+}
+
+/// Where the off-screen arrow goes: the cell just inside the view's edge on the line from
+/// `center` to `target` (both view cells), and the way it points; None while the target is in view
+pub fn edge_arrow(center: (i32, i32), target: (i32, i32), (width, height): (i32, i32)) -> Option<((i32, i32), Direction)> {
+    if (0..width).contains(&target.0) && (0..height).contains(&target.1) {
+        return None;
+    }
+    let (dx, dy) = ((target.0 - center.0) as f64, (target.1 - center.1) as f64);
+    let direction = poi::bearing(target.0 - center.0, target.1 - center.1)?;
+    // Shrink the offset until it just fits inside a one-cell margin
+    let room_x = if dx > 0.0 { width - 2 - center.0 } else { center.0 - 1 } as f64;
+    let room_y = if dy > 0.0 { height - 2 - center.1 } else { center.1 - 1 } as f64;
+    let scale = [(dx, room_x), (dy, room_y)]
+        .iter()
+        .filter(|(offset, _)| *offset != 0.0)
+        .map(|(offset, room)| room.max(0.0) / offset.abs())
+        .fold(1.0, f64::min);
+    let x = (center.0 as f64 + dx * scale).round() as i32;
+    let y = (center.1 as f64 + dy * scale).round() as i32;
+    Some(((x.clamp(0, width - 1), y.clamp(0, height - 1)), direction))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Bookmark Tests ====================
+
+    #[test]
+    fn test_marks_replace_by_name_in_any_case() {
+        let mut bookmarks = Bookmarks::default();
+        assert_eq!(bookmarks.mark("Home", (3, 4)), Ok(false));
+        assert_eq!(bookmarks.mark("home", (5, 6)), Ok(true), "Same name, new place");
+        assert_eq!(bookmarks.get("HOME"), Some(Waypoint { name: "home".to_string(), x: 5, y: 6 }));
+        assert_eq!(bookmarks.marks.len(), 1);
+        assert!(bookmarks.get("Away").is_none());
+    }
+
+    #[test]
+    fn test_mark_rejects_bad_names_and_too_many() {
+        let mut bookmarks = Bookmarks::default();
+        assert!(bookmarks.mark("", (0, 0)).is_err());
+        assert!(bookmarks.mark(&"x".repeat(MAX_NAME_LEN + 1), (0, 0)).is_err());
+        assert!(bookmarks.mark("a/b", (0, 0)).unwrap_err().contains("letters"));
+        for i in 0..MAX_BOOKMARKS {
+            bookmarks.mark(&format!("m{}", i), (0, 0)).unwrap();
+        }
+        assert!(bookmarks.mark("one-more", (0, 0)).is_err(), "Full");
+        assert_eq!(bookmarks.mark("m3", (1, 1)), Ok(true), "Moving one is still fine");
+    }
+
+    #[test]
+    fn test_listing_is_nearest_first() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.mark("Far", (100, 0)).unwrap();
+        bookmarks.mark("Near", (-3, -4)).unwrap();
+        bookmarks.mark("Here", (0, 0)).unwrap();
+        assert_eq!(
+            bookmarks.listing((0, 0)),
+            ["Here: 0 tiles here at (0, 0)", "Near: 5 tiles NW at (-3, -4)", "Far: 100 tiles E at (100, 0)"]
+        );
+    }
+
+    #[test]
+    fn test_bookmarks_serialize_as_a_map() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.mark("Home", (3, -4)).unwrap();
+        let json = serde_json::to_string(&bookmarks).unwrap();
+        assert_eq!(json, r#"{"Home":[3,-4]}"#);
+        assert_eq!(serde_json::from_str::<Bookmarks>(&json).unwrap(), bookmarks);
+    }
+
+    // ==================== Edge Arrow Tests ====================
+
+    #[test]
+    fn test_edge_arrow_points_at_off_screen_waypoints() {
+        let view = (80, 20);
+        let center = (40, 10);
+        assert_eq!(edge_arrow(center, (50, 5), view), None, "Nothing while it's in view");
+        assert_eq!(edge_arrow(center, (500, 10), view), Some(((78, 10), Direction::Right)));
+        assert_eq!(edge_arrow(center, (40, -100), view), Some(((40, 1), Direction::Up)));
+        let ((x, y), direction) = edge_arrow(center, (-200, 250), view).unwrap();
+        assert_eq!(direction, Direction::DownLeft);
+        assert_eq!(y, 18, "Stops at the bottom margin first");
+        assert!((1..center.0).contains(&x), "Somewhere left of the ship");
+    }
+
+    #[test]
+    fn test_edge_arrow_in_tiny_views() {
+        assert_eq!(edge_arrow((0, 0), (10, 0), (1, 1)), Some(((0, 0), Direction::Right)), "Still on screen");
+    }
+}
@@ -0,0 +1,471 @@
+//! A small entity store for ships and other things in the sky.
+//!
+//! An `Entity` is an id; what it is comes from the components attached to
+//! it, each kind kept in its own `Components` map. The server flies its NPC
+//! ships out of an `Entities` store and clients mirror the ships they hear
+//! about into one (under the server's ids) to draw them, so a new kind of
+//! object is a new combination of components rather than another struct
+//! threaded through both sides. Maps are ordered by id, so iteration, and
+//! anything simulated from it, is deterministic.
+//!
+//! Projectiles are flown by `step_projectiles()` on both sides too: the
+//! server to decide what they hit, clients to draw them between the server's
+//! `Shot` and `ShotEnded` (or for real, flying solo).
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use crate::{Direction, NpcBehavior, NpcInfo};
+
+/// How often projectiles move a tile
+pub const PROJECTILE_STEP: Duration = Duration::from_millis(50);
+
+/// Tiles a projectile flies before it fizzles out
+pub const PROJECTILE_RANGE: u32 = 20;
+
+/// Hull a projectile knocks off whatever it hits
+pub const PROJECTILE_DAMAGE: u32 = 1;
+
+/// Shortest time between two shots from the same ship
+pub const FIRE_COOLDOWN: Duration = Duration::from_millis(250);
+
+/// An id; ids are never handed out twice by the same store
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity(pub u64);
+
+/// World tile an entity is on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Tiles moved per `Entities::advance()`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Velocity {
+    pub dx: i32,
+    pub dy: i32,
+}
+
+/// What an entity is drawn as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpriteKind {
+    Pilot,
+    Npc(NpcBehavior),
+    Projectile,
+    Pickup,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sprite {
+    pub kind: SpriteKind,
+    pub direction: Direction,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub fn full(max: u32) -> Self {
+        Health { current: max, max }
+    }
+
+    /// Take damage; true once nothing is left
+    pub fn damage(&mut self, amount: u32) -> bool {
+        self.current = self.current.saturating_sub(amount);
+        self.current == 0
+    }
+}
+
+/// A shot in flight: who fired it and how many more tiles it flies
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Projectile {
+    pub owner: u64,
+    pub range: u32,
+}
+
+/// Why a projectile stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Impact {
+    /// Flew into this solid tile (it stays on the tile before)
+    Tile(Position),
+    /// Hit the entity with this id, on its tile
+    Target(u64, Position),
+    /// Ran out of range here
+    Spent(Position),
+}
+
+impl Impact {
+    pub fn position(self) -> Position {
+        match self {
+            Impact::Tile(position) | Impact::Target(_, position) | Impact::Spent(position) => position,
+        }
+    }
+}
+
+/// Where a server-driven entity is headed and why
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ai {
+    pub behavior: NpcBehavior,
+    pub home: (i32, i32),
+    /// Patrol waypoints, flown in order
+    pub route: Vec<(i32, i32)>,
+    pub leg: usize,
+    pub target: Option<(i32, i32)>,
+}
+
+impl Ai {
+    pub fn new(behavior: NpcBehavior, home: (i32, i32), route: Vec<(i32, i32)>) -> Self {
+        Ai { behavior, home, route, leg: 0, target: None }
+    }
+}
+
+/// One kind of component, by entity
+#[derive(Clone, Debug)]
+pub struct Components<T> {
+    map: BTreeMap<Entity, T>,
+}
+
+impl<T> Default for Components<T> {
+    fn default() -> Self {
+        Components { map: BTreeMap::new() }
+    }
+}
+
+impl<T> Components<T> {
+    pub fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        self.map.insert(entity, component)
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.map.get(&entity)
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.map.get_mut(&entity)
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.map.remove(&entity)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.map.iter().map(|(&entity, component)| (entity, component))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.map.iter_mut().map(|(&entity, component)| (entity, component))
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+    }
+}
+
+/// Every entity and its components
+#[derive(Clone, Debug, Default)]
+pub struct Entities {
+    alive: BTreeSet<Entity>,
+    /// Highest id handed out or mirrored
+    last_id: u64,
+    pub positions: Components<Position>,
+    pub velocities: Components<Velocity>,
+    pub sprites: Components<Sprite>,
+    pub health: Components<Health>,
+    pub ai: Components<Ai>,
+    pub names: Components<String>,
+    pub projectiles: Components<Projectile>,
+}
+
+impl Entities {
+    /// A new entity with no components; ids start at 1
+    pub fn spawn(&mut self) -> Entity {
+        self.last_id += 1;
+        let entity = Entity(self.last_id);
+        self.alive.insert(entity);
+        entity
+    }
+
+    /// Take an entity under an id chosen elsewhere (a server's); later spawns go past it
+    pub fn adopt(&mut self, entity: Entity) {
+        self.last_id = self.last_id.max(entity.0);
+        self.alive.insert(entity);
+    }
+
+    /// Remove an entity and all its components; false if it wasn't there
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        self.positions.remove(entity);
+        self.velocities.remove(entity);
+        self.sprites.remove(entity);
+        self.health.remove(entity);
+        self.ai.remove(entity);
+        self.names.remove(entity);
+        self.projectiles.remove(entity);
+        self.alive.remove(&entity)
+    }
+
+    /// Remove everything; ids already handed out stay used
+    pub fn clear(&mut self) {
+        self.alive.clear();
+        self.positions.clear();
+        self.velocities.clear();
+        self.sprites.clear();
+        self.health.clear();
+        self.ai.clear();
+        self.names.clear();
+        self.projectiles.clear();
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.alive.contains(&entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.alive.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.alive.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive.iter().copied()
+    }
+
+    /// Entities with something to draw: a position and a sprite
+    pub fn drawn(&self) -> impl Iterator<Item = (Entity, Position, Sprite)> + '_ {
+        self.sprites.iter().filter_map(|(entity, &sprite)| Some((entity, *self.positions.get(entity)?, sprite)))
+    }
+
+    /// Move an entity by its velocity, turning its sprite to face the way it went; its new position
+    pub fn advance(&mut self, entity: Entity) -> Option<Position> {
+        let velocity = *self.velocities.get(entity)?;
+        let position = self.positions.get_mut(entity)?;
+        position.x += velocity.dx;
+        position.y += velocity.dy;
+        if let (Some(sprite), Some(direction)) = (self.sprites.get_mut(entity), Direction::from_delta(velocity.dx, velocity.dy)) {
+            sprite.direction = direction;
+        }
+        Some(*position)
+    }
+
+    /// Add or replace an NPC ship under its own id
+    pub fn put_npc(&mut self, info: &NpcInfo) -> Entity {
+        let entity = Entity(info.id);
+        self.adopt(entity);
+        self.positions.insert(entity, Position { x: info.x, y: info.y });
+        self.sprites.insert(entity, Sprite { kind: SpriteKind::Npc(info.behavior), direction: info.direction });
+        self.names.insert(entity, info.name.clone());
+        entity
+    }
+
+    /// An NPC ship's public view; None unless it has a name, position and NPC sprite
+    pub fn npc_info(&self, entity: Entity) -> Option<NpcInfo> {
+        let position = self.positions.get(entity)?;
+        let sprite = self.sprites.get(entity)?;
+        let SpriteKind::Npc(behavior) = sprite.kind else {
+            return None;
+        };
+        Some(NpcInfo {
+            id: entity.0,
+            name: self.names.get(entity)?.clone(),
+            x: position.x,
+            y: position.y,
+            direction: sprite.direction,
+            behavior,
+        })
+    }
+
+    /// Every NPC ship's public view, by id
+    pub fn npcs(&self) -> Vec<NpcInfo> {
+        self.iter().filter_map(|entity| self.npc_info(entity)).collect()
+    }
+
+    /// A new projectile on the shooter's tile, heading `direction`
+    pub fn fire(&mut self, owner: u64, from: Position, direction: Direction) -> Entity {
+        let entity = self.spawn();
+        self.put_projectile(entity, owner, from, direction);
+        entity
+    }
+
+    /// `fire()` under an id chosen elsewhere (a server's)
+    pub fn put_projectile(&mut self, entity: Entity, owner: u64, from: Position, direction: Direction) {
+        let (dx, dy) = direction.delta();
+        self.adopt(entity);
+        self.positions.insert(entity, from);
+        self.velocities.insert(entity, Velocity { dx, dy });
+        self.sprites.insert(entity, Sprite { kind: SpriteKind::Projectile, direction });
+        self.projectiles.insert(entity, Projectile { owner, range: PROJECTILE_RANGE });
+    }
+
+    /// The tile each projectile flies into on its next step, so callers can look them up before stepping
+    pub fn projectile_steps(&self) -> Vec<Position> {
+        self.projectiles
+            .iter()
+            .filter_map(|(entity, _)| Some((self.positions.get(entity)?, self.velocities.get(entity)?)))
+            .map(|(from, velocity)| Position { x: from.x + velocity.dx, y: from.y + velocity.dy })
+            .collect()
+    }
+
+    /// Fly every projectile one tile. One that would enter a `solid` tile, or reaches a tile with a `target`
+    /// (an id) on it, stops there, as does one at the end of its range; stopped projectiles are despawned
+    /// and returned with why they stopped
+    pub fn step_projectiles(
+        &mut self,
+        mut solid: impl FnMut(Position) -> bool,
+        mut target: impl FnMut(Position) -> Option<u64>,
+    ) -> Vec<(Entity, Projectile, Impact)> {
+        let shots: Vec<(Entity, Projectile)> = self.projectiles.iter().map(|(entity, &shot)| (entity, shot)).collect();
+        let mut stopped = Vec::new();
+        for (entity, shot) in shots {
+            let (Some(&from), Some(&velocity)) = (self.positions.get(entity), self.velocities.get(entity)) else {
+                continue;
+            };
+            let next = Position { x: from.x + velocity.dx, y: from.y + velocity.dy };
+            let impact = if solid(next) {
+                Some(Impact::Tile(next))
+            } else {
+                self.advance(entity);
+                match target(next) {
+                    Some(id) => Some(Impact::Target(id, next)),
+                    None if shot.range <= 1 => Some(Impact::Spent(next)),
+                    None => None,
+                }
+            };
+            match impact {
+                Some(impact) => {
+                    self.despawn(entity);
+                    stopped.push((entity, shot, impact));
+                }
+                None => {
+                    if let Some(shot) = self.projectiles.get_mut(entity) {
+                        shot.range -= 1;
+                    }
+                }
+            }
+        }
+        stopped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npc(id: u64, x: i32) -> NpcInfo {
+        NpcInfo { id, name: format!("Ship {}", id), x, y: 0, direction: Direction::Up, behavior: NpcBehavior::Patrol }
+    }
+
+    // ==================== Store Tests ====================
+
+    #[test]
+    fn test_ids_are_not_reused() {
+        let mut entities = Entities::default();
+        let first = entities.spawn();
+        let second = entities.spawn();
+        assert_eq!((first, second), (Entity(1), Entity(2)));
+        entities.positions.insert(second, Position { x: 1, y: 2 });
+        assert!(entities.despawn(second));
+        assert!(!entities.despawn(second), "Already gone");
+        assert!(entities.positions.is_empty(), "Components go with the entity");
+        assert_eq!(entities.spawn(), Entity(3));
+
+        entities.adopt(Entity(10));
+        assert_eq!(entities.spawn(), Entity(11), "Spawns go past adopted ids");
+        entities.clear();
+        assert!(entities.is_empty());
+        assert_eq!(entities.spawn(), Entity(12));
+    }
+
+    #[test]
+    fn test_drawn_needs_position_and_sprite() {
+        let mut entities = Entities::default();
+        let ship = entities.spawn();
+        let ghost = entities.spawn();
+        let shot = entities.spawn();
+        entities.positions.insert(ship, Position { x: 4, y: 5 });
+        entities.sprites.insert(ship, Sprite { kind: SpriteKind::Pilot, direction: Direction::Left });
+        entities.sprites.insert(ghost, Sprite { kind: SpriteKind::Pickup, direction: Direction::Up });
+        entities.positions.insert(shot, Position { x: 0, y: 0 });
+        entities.sprites.insert(shot, Sprite { kind: SpriteKind::Projectile, direction: Direction::Up });
+        let drawn: Vec<(Entity, i32)> = entities.drawn().map(|(entity, position, _)| (entity, position.x)).collect();
+        assert_eq!(drawn, [(ship, 4), (shot, 0)], "Nothing without a position is drawn");
+    }
+
+    // ==================== System Tests ====================
+
+    #[test]
+    fn test_advance_moves_and_turns() {
+        let mut entities = Entities::default();
+        let shot = entities.spawn();
+        entities.positions.insert(shot, Position { x: 0, y: 0 });
+        entities.sprites.insert(shot, Sprite { kind: SpriteKind::Projectile, direction: Direction::Up });
+        assert_eq!(entities.advance(shot), None, "Nothing moves without a velocity");
+        entities.velocities.insert(shot, Velocity { dx: 1, dy: 1 });
+        assert_eq!(entities.advance(shot), Some(Position { x: 1, y: 1 }));
+        assert_eq!(entities.sprites.get(shot).unwrap().direction, Direction::DownRight);
+
+        let mut health = Health::full(10);
+        assert!(!health.damage(4));
+        assert!(health.damage(9), "Damage stops at zero");
+        assert_eq!(health.current, 0);
+    }
+
+    #[test]
+    fn test_npcs_round_trip() {
+        let mut entities = Entities::default();
+        entities.put_npc(&npc(7, 3));
+        entities.put_npc(&npc(2, 1));
+        entities.put_npc(&npc(7, 9));
+        let ids: Vec<(u64, i32)> = entities.npcs().iter().map(|n| (n.id, n.x)).collect();
+        assert_eq!(ids, [(2, 1), (7, 9)], "Put again replaces; listed by id");
+        assert_eq!(entities.npc_info(Entity(7)), Some(npc(7, 9)));
+
+        let pilot = entities.spawn();
+        entities.positions.insert(pilot, Position::default());
+        entities.sprites.insert(pilot, Sprite { kind: SpriteKind::Pilot, direction: Direction::Up });
+        entities.names.insert(pilot, "Ace".to_string());
+        assert_eq!(pilot, Entity(8));
+        assert_eq!(entities.npc_info(pilot), None, "Only NPC sprites are NPCs");
+    }
+
+    #[test]
+    fn test_projectiles_stop_at_walls_targets_and_range() {
+        let mut entities = Entities::default();
+        let wall = entities.fire(1, Position { x: 0, y: 0 }, Direction::Right);
+        let ship = entities.fire(1, Position { x: 0, y: 5 }, Direction::Up);
+        let far = entities.fire(2, Position { x: 100, y: 0 }, Direction::Left);
+        assert_eq!(entities.sprites.get(ship).unwrap().kind, SpriteKind::Projectile);
+
+        let solid = |p: Position| p == Position { x: 2, y: 0 };
+        let target = |p: Position| (p == Position { x: 0, y: 3 }).then_some(42);
+        let mut ahead = entities.projectile_steps();
+        ahead.sort_by_key(|p| (p.x, p.y));
+        assert_eq!(ahead, [Position { x: 0, y: 4 }, Position { x: 1, y: 0 }, Position { x: 99, y: 0 }]);
+        assert!(entities.step_projectiles(solid, target).is_empty(), "Nothing hit on the first tile");
+        let stopped = entities.step_projectiles(solid, target);
+        let impacts: Vec<(Entity, Impact)> = stopped.iter().map(|&(entity, _, impact)| (entity, impact)).collect();
+        assert_eq!(impacts, [(wall, Impact::Tile(Position { x: 2, y: 0 })), (ship, Impact::Target(42, Position { x: 0, y: 3 }))]);
+        assert_eq!(stopped[1].1.owner, 1);
+        assert!(!entities.is_alive(wall) && !entities.is_alive(ship), "Stopped shots are despawned");
+        assert_eq!(entities.positions.get(far), Some(&Position { x: 98, y: 0 }));
+
+        let mut spent = Vec::new();
+        while spent.is_empty() {
+            spent = entities.step_projectiles(|_| false, |_| None);
+        }
+        assert_eq!(spent[0].2, Impact::Spent(Position { x: 100 - PROJECTILE_RANGE as i32, y: 0 }), "Flies its full range");
+        assert!(entities.is_empty());
+    }
+}
@@ -0,0 +1,2237 @@
+//! Types shared between the ExoSpace server and clients.
+//!
+//! Anything that crosses the wire (tiles, map payloads) or has to agree
+//! between both sides (passability, procedural hashing) lives here so the
+//! binaries can't drift apart.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+pub mod ecs;
+pub mod regions;
+
+use regions::{Biome, Region};
+
+/// Tile types in the map
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Tile {
+    Wall,
+    Floor,
+    Asteroid,
+    Nebula,
+    /// An asteroid with ore in it, mined from an adjacent tile
+    OreAsteroid,
+    /// A cluster of crystals inside a nebula, mined from an adjacent tile
+    CrystalField,
+    /// A tile kind from a newer server; treated as solid so old clients stay playable
+    #[serde(other)]
+    Unknown,
+}
+
+impl Tile {
+    /// Whether a ship can occupy this tile
+    pub fn is_passable(&self) -> bool {
+        matches!(self, Tile::Floor | Tile::Nebula)
+    }
+
+    /// What a pilot can turn this tile into: mining empties a resource tile,
+    /// and the mining laser cuts a plain asteroid away
+    pub fn broken(self) -> Option<Tile> {
+        match self {
+            Tile::OreAsteroid => Some(Tile::Asteroid),
+            Tile::CrystalField => Some(Tile::Nebula),
+            Tile::Asteroid => Some(Tile::Floor),
+            _ => None,
+        }
+    }
+
+    /// The good mining this tile gives, by content id, and how much of it at normal difficulty
+    pub fn mining_yield(self) -> Option<(&'static str, u32)> {
+        match self {
+            Tile::OreAsteroid => Some(("ore", 3)),
+            Tile::CrystalField => Some(("crystal", 1)),
+            _ => None,
+        }
+    }
+
+    /// One-byte code used by the binary map format
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Tile::Wall => 0,
+            Tile::Floor => 1,
+            Tile::Asteroid => 2,
+            Tile::Nebula => 3,
+            Tile::OreAsteroid => 4,
+            Tile::CrystalField => 5,
+            Tile::Unknown => 255,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Tile> {
+        match byte {
+            0 => Some(Tile::Wall),
+            1 => Some(Tile::Floor),
+            2 => Some(Tile::Asteroid),
+            3 => Some(Tile::Nebula),
+            4 => Some(Tile::OreAsteroid),
+            5 => Some(Tile::CrystalField),
+            255 => Some(Tile::Unknown),
+            _ => None,
+        }
+    }
+}
+
+/// How far, in tiles, sensors reach into or out of a nebula
+pub const NEBULA_SENSOR_RANGE: i32 = 6;
+
+/// Whether two tiles are close enough for sensors to reach through a nebula
+pub fn within_sensor_range(a: (i32, i32), b: (i32, i32)) -> bool {
+    let (dx, dy) = (a.0.abs_diff(b.0) as u64, a.1.abs_diff(b.1) as u64);
+    let range = NEBULA_SENSOR_RANGE as u64;
+    dx <= range && dy <= range && dx * dx + dy * dy <= range * range
+}
+
+/// Content type for the binary map/chunk format (requested with `?format=bin`)
+pub const BINARY_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Response header carrying the world version a chunk of the server's world is current as of
+pub const WORLD_VERSION_HEADER: &str = "x-world-version";
+
+/// Version byte written after the magic in binary payloads
+const BINARY_VERSION: u8 = 1;
+const MAP_MAGIC: &[u8; 4] = b"EXOM";
+const CHUNK_MAGIC: &[u8; 4] = b"EXOC";
+
+/// Why a binary map or chunk payload couldn't be decoded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "not an ExoSpace binary payload"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported binary format version {}", v),
+            DecodeError::Truncated => write!(f, "payload is truncated"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Little-endian reader over a binary payload
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < n {
+            return Err(DecodeError::Truncated);
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn header(&mut self, magic: &[u8; 4]) -> Result<(), DecodeError> {
+        if self.take(4)? != magic {
+            return Err(DecodeError::BadMagic);
+        }
+        match self.take(1)?[0] {
+            BINARY_VERSION => Ok(()),
+            other => Err(DecodeError::UnsupportedVersion(other)),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, DecodeError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// A string after its length byte
+    fn text(&mut self) -> Result<String, DecodeError> {
+        let len = self.take(1)?[0] as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    /// Tile codes this build doesn't know decode as `Tile::Unknown`
+    fn tiles(&mut self, count: usize) -> Result<Vec<Tile>, DecodeError> {
+        Ok(self.take(count)?.iter().map(|&b| Tile::from_byte(b).unwrap_or(Tile::Unknown)).collect())
+    }
+
+    /// The trailing points of interest section; payloads from before it had none
+    fn pois(&mut self) -> Result<Vec<Poi>, DecodeError> {
+        if self.bytes.is_empty() {
+            return Ok(Vec::new());
+        }
+        let count = self.u32()?;
+        let mut pois = Vec::new();
+        for _ in 0..count {
+            let kind = PoiKind::from_byte(self.take(1)?[0]);
+            let x = self.i32()?;
+            let y = self.i32()?;
+            let name = self.text()?;
+            pois.push(Poi { kind, name, x, y });
+        }
+        Ok(pois)
+    }
+
+    /// The regions section after the points of interest; payloads from before it had none
+    fn regions(&mut self) -> Result<Vec<Region>, DecodeError> {
+        if self.bytes.is_empty() {
+            return Ok(Vec::new());
+        }
+        let count = self.u32()?;
+        let mut regions = Vec::new();
+        for _ in 0..count {
+            let x = self.i32()?;
+            let y = self.i32()?;
+            let width = self.u32()?;
+            let height = self.u32()?;
+            let danger = self.take(1)?[0];
+            let name = self.text()?;
+            let description = self.text()?;
+            regions.push(Region { name, x, y, width, height, biome: Biome::default(), danger, description });
+        }
+        // Then a biome byte for each, which payloads from before biomes left off
+        if !self.bytes.is_empty() {
+            for (region, &byte) in regions.iter_mut().zip(self.take(count as usize)?) {
+                region.biome = Biome::from_byte(byte);
+            }
+        }
+        Ok(regions)
+    }
+}
+
+/// Append a string after its length byte, cut short at 255 bytes
+fn write_text(out: &mut Vec<u8>, text: &str) {
+    let mut len = text.len().min(u8::MAX as usize);
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    out.push(len as u8);
+    out.extend_from_slice(&text.as_bytes()[..len]);
+}
+
+/// Append the points of interest section, then the regions section, leaving off what's empty at
+/// the end so plain maps and chunks encode as before
+fn write_extras(out: &mut Vec<u8>, pois: &[Poi], regions: &[Region]) {
+    if pois.is_empty() && regions.is_empty() {
+        return;
+    }
+    out.extend_from_slice(&(pois.len() as u32).to_le_bytes());
+    for poi in pois {
+        out.push(poi.kind.to_byte());
+        out.extend_from_slice(&poi.x.to_le_bytes());
+        out.extend_from_slice(&poi.y.to_le_bytes());
+        write_text(out, &poi.name);
+    }
+    if regions.is_empty() {
+        return;
+    }
+    out.extend_from_slice(&(regions.len() as u32).to_le_bytes());
+    for region in regions {
+        out.extend_from_slice(&region.x.to_le_bytes());
+        out.extend_from_slice(&region.y.to_le_bytes());
+        out.extend_from_slice(&region.width.to_le_bytes());
+        out.extend_from_slice(&region.height.to_le_bytes());
+        out.push(region.danger);
+        write_text(out, &region.name);
+        write_text(out, &region.description);
+    }
+    out.extend(regions.iter().map(|region| region.biome.to_byte()));
+}
+
+/// What sort of place a point of interest is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoiKind {
+    Station,
+    /// A wrecked ship
+    Derelict,
+    /// A navigation beacon
+    Beacon,
+    /// A jump gate to another star system (see `SystemInfo`)
+    JumpGate,
+    /// A kind from a newer server
+    #[serde(other)]
+    Unknown,
+}
+
+impl PoiKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            PoiKind::Station => "station",
+            PoiKind::Derelict => "derelict",
+            PoiKind::Beacon => "beacon",
+            PoiKind::JumpGate => "jump gate",
+            PoiKind::Unknown => "unknown",
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            PoiKind::Station => 0,
+            PoiKind::Derelict => 1,
+            PoiKind::Beacon => 2,
+            PoiKind::JumpGate => 3,
+            PoiKind::Unknown => 255,
+        }
+    }
+
+    fn from_byte(byte: u8) -> PoiKind {
+        match byte {
+            0 => PoiKind::Station,
+            1 => PoiKind::Derelict,
+            2 => PoiKind::Beacon,
+            3 => PoiKind::JumpGate,
+            _ => PoiKind::Unknown,
+        }
+    }
+}
+
+/// A named place on the map; names longer than 255 bytes are cut short in the binary format
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Poi {
+    pub kind: PoiKind,
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Smallest and largest maps `/map` will generate; bigger ones would eat the server's memory
+pub const MIN_MAP_SIDE: usize = 3;
+pub const MAX_MAP_WIDTH: usize = 2000;
+pub const MAX_MAP_HEIGHT: usize = 1000;
+
+/// Map data that can be serialized and sent to clients
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MapData {
+    /// Row-major, `width * height` tiles
+    pub tiles: Vec<Tile>,
+    pub width: usize,
+    pub height: usize,
+    pub start_x: i32,
+    pub start_y: i32,
+    /// Named places, in world coordinates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pois: Vec<Poi>,
+    /// Named areas, in world coordinates (see `regions.rs`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<Region>,
+}
+
+impl MapData {
+    /// A `width` x `height` map filled with one tile, starting at the origin
+    pub fn filled(width: usize, height: usize, tile: Tile) -> Self {
+        MapData { tiles: vec![tile; width * height], width, height, start_x: 0, start_y: 0, pois: Vec::new(), regions: Vec::new() }
+    }
+
+    /// Position of (x, y) in `tiles`, if it's on the map
+    pub fn index(&self, x: usize, y: usize) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| y * self.width + x)
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<Tile> {
+        self.index(x, y).map(|i| self.tiles[i])
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut Tile> {
+        self.index(x, y).map(|i| &mut self.tiles[i])
+    }
+
+    /// One full row as a contiguous slice
+    pub fn row(&self, y: usize) -> Option<&[Tile]> {
+        (y < self.height).then(|| &self.tiles[y * self.width..(y + 1) * self.width])
+    }
+
+    /// Every row, top to bottom
+    pub fn rows(&self) -> impl Iterator<Item = &[Tile]> {
+        self.rect(0..self.width, 0..self.height)
+    }
+
+    /// Row slices covering a rectangle, clipped to the map
+    pub fn rect(&self, xs: Range<usize>, ys: Range<usize>) -> impl Iterator<Item = &[Tile]> {
+        let (xs, ys) = self.clip(xs, ys);
+        ys.map(move |y| &self.tiles[y * self.width + xs.start..y * self.width + xs.end])
+    }
+
+    /// Mutable row slices covering a rectangle, clipped to the map
+    pub fn rect_mut(&mut self, xs: Range<usize>, ys: Range<usize>) -> impl Iterator<Item = &mut [Tile]> {
+        let (xs, ys) = self.clip(xs, ys);
+        self.tiles
+            .chunks_exact_mut(self.width.max(1))
+            .skip(ys.start)
+            .take(ys.len())
+            .map(move |row| &mut row[xs.clone()])
+    }
+
+    fn clip(&self, xs: Range<usize>, ys: Range<usize>) -> (Range<usize>, Range<usize>) {
+        let xs = xs.start.min(self.width)..xs.end.min(self.width);
+        let ys = ys.start.min(self.height)..ys.end.min(self.height);
+        (xs.start..xs.end.max(xs.start), ys.start..ys.end.max(ys.start))
+    }
+
+    /// Encode as magic, version, width, height, start x/y, one byte per tile (row-major), then any
+    /// points of interest: a count and each one's kind byte, x, y and length-prefixed name; then any
+    /// regions: a count and each one's x, y, width, height, danger byte, and length-prefixed name and
+    /// description, then a biome byte for each
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(21 + self.width * self.height);
+        out.extend_from_slice(MAP_MAGIC);
+        out.push(BINARY_VERSION);
+        out.extend_from_slice(&(self.width as u32).to_le_bytes());
+        out.extend_from_slice(&(self.height as u32).to_le_bytes());
+        out.extend_from_slice(&self.start_x.to_le_bytes());
+        out.extend_from_slice(&self.start_y.to_le_bytes());
+        out.extend(self.tiles.iter().map(|t| t.to_byte()));
+        write_extras(&mut out, &self.pois, &self.regions);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<MapData, DecodeError> {
+        let mut reader = ByteReader { bytes };
+        reader.header(MAP_MAGIC)?;
+        let width = reader.u32()? as usize;
+        let height = reader.u32()? as usize;
+        let start_x = reader.i32()?;
+        let start_y = reader.i32()?;
+        let tiles = reader.tiles(width.checked_mul(height).ok_or(DecodeError::Truncated)?)?;
+        let pois = reader.pois()?;
+        let regions = reader.regions()?;
+        Ok(MapData { tiles, width, height, start_x, start_y, pois, regions })
+    }
+}
+
+/// Side length of a streamed map chunk, in tiles
+pub const CHUNK_SIZE: i32 = 64;
+
+/// Furthest chunk from the origin on either axis; beyond it world coordinates overflow
+pub const MAX_CHUNK_COORD: i32 = i32::MAX / CHUNK_SIZE - 1;
+
+/// Where new players appear in the streamed world; chunk generators keep it clear
+pub const SPAWN_POSITION: (i32, i32) = (0, 0);
+
+/// One square chunk of the unbounded world, as served by `/map/chunk`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkData {
+    pub cx: i32,
+    pub cy: i32,
+    /// Row-major, `CHUNK_SIZE * CHUNK_SIZE` tiles
+    pub tiles: Vec<Tile>,
+    /// Named places inside the chunk, in world coordinates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pois: Vec<Poi>,
+    /// The region the chunk lies in, whole
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<Region>,
+}
+
+impl ChunkData {
+    /// Tile at a position local to this chunk (0..CHUNK_SIZE on each axis)
+    pub fn get(&self, local_x: i32, local_y: i32) -> Option<Tile> {
+        if !(0..CHUNK_SIZE).contains(&local_x) || !(0..CHUNK_SIZE).contains(&local_y) {
+            return None;
+        }
+        self.tiles.get((local_y * CHUNK_SIZE + local_x) as usize).copied()
+    }
+
+    pub fn get_mut(&mut self, local_x: i32, local_y: i32) -> Option<&mut Tile> {
+        if !(0..CHUNK_SIZE).contains(&local_x) || !(0..CHUNK_SIZE).contains(&local_y) {
+            return None;
+        }
+        self.tiles.get_mut((local_y * CHUNK_SIZE + local_x) as usize)
+    }
+
+    /// Encode as magic, version, cx, cy, `CHUNK_SIZE * CHUNK_SIZE` tile bytes, then any points of
+    /// interest and regions as in `MapData::to_bytes`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(13 + self.tiles.len());
+        out.extend_from_slice(CHUNK_MAGIC);
+        out.push(BINARY_VERSION);
+        out.extend_from_slice(&self.cx.to_le_bytes());
+        out.extend_from_slice(&self.cy.to_le_bytes());
+        out.extend(self.tiles.iter().map(|t| t.to_byte()));
+        write_extras(&mut out, &self.pois, &self.regions);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ChunkData, DecodeError> {
+        let mut reader = ByteReader { bytes };
+        reader.header(CHUNK_MAGIC)?;
+        let cx = reader.i32()?;
+        let cy = reader.i32()?;
+        let tiles = reader.tiles((CHUNK_SIZE * CHUNK_SIZE) as usize)?;
+        let pois = reader.pois()?;
+        let regions = reader.regions()?;
+        Ok(ChunkData { cx, cy, tiles, pois, regions })
+    }
+}
+
+/// Chunk coordinates containing a world position (works for negative positions)
+pub fn chunk_coords(x: i32, y: i32) -> (i32, i32) {
+    (x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE))
+}
+
+/// Position of a world tile within its chunk
+pub fn chunk_local(x: i32, y: i32) -> (i32, i32) {
+    (x.rem_euclid(CHUNK_SIZE), y.rem_euclid(CHUNK_SIZE))
+}
+
+/// 8-directional orientation
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Direction {
+    #[default]
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+impl Direction {
+    /// Every direction, clockwise from up
+    pub const ALL: [Direction; 8] = [
+        Direction::Up,
+        Direction::UpRight,
+        Direction::Right,
+        Direction::DownRight,
+        Direction::Down,
+        Direction::DownLeft,
+        Direction::Left,
+        Direction::UpLeft,
+    ];
+
+    pub fn from_delta(dx: i32, dy: i32) -> Option<Direction> {
+        match (dx, dy) {
+            (0, -1) => Some(Direction::Up),
+            (1, -1) => Some(Direction::UpRight),
+            (1, 0) => Some(Direction::Right),
+            (1, 1) => Some(Direction::DownRight),
+            (0, 1) => Some(Direction::Down),
+            (-1, 1) => Some(Direction::DownLeft),
+            (-1, 0) => Some(Direction::Left),
+            (-1, -1) => Some(Direction::UpLeft),
+            _ => None,
+        }
+    }
+
+    /// One tile this way, as (dx, dy)
+    pub fn delta(self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::UpRight => (1, -1),
+            Direction::Right => (1, 0),
+            Direction::DownRight => (1, 1),
+            Direction::Down => (0, 1),
+            Direction::DownLeft => (-1, 1),
+            Direction::Left => (-1, 0),
+            Direction::UpLeft => (-1, -1),
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        match self {
+            Direction::Up => '↑',
+            Direction::UpRight => '↗',
+            Direction::Right => '→',
+            Direction::DownRight => '↘',
+            Direction::Down => '↓',
+            Direction::DownLeft => '↙',
+            Direction::Left => '←',
+            Direction::UpLeft => '↖',
+        }
+    }
+
+    /// `to_char()` for terminals without Unicode arrows
+    pub fn to_ascii(self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::UpRight => '/',
+            Direction::Right => '>',
+            Direction::DownRight => '\\',
+            Direction::Down => 'v',
+            Direction::DownLeft => '/',
+            Direction::Left => '<',
+            Direction::UpLeft => '\\',
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Up => "N",
+            Direction::UpRight => "NE",
+            Direction::Right => "E",
+            Direction::DownRight => "SE",
+            Direction::Down => "S",
+            Direction::DownLeft => "SW",
+            Direction::Left => "W",
+            Direction::UpLeft => "NW",
+        }
+    }
+}
+
+/// FNV-1a over some bytes: stable across runs and platforms, unlike std's hasher
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Simple deterministic hash for consistent random-looking values
+pub fn hash_position(x: i32, y: i32, seed: u32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(374761393);
+    h = h.wrapping_add((y as u32).wrapping_mul(668265263));
+    h = h.wrapping_add(seed.wrapping_mul(1013904223));
+    h ^= h >> 13;
+    h = h.wrapping_mul(1274126177);
+    h ^= h >> 16;
+    h
+}
+
+/// The seed everyone shares for one UTC day, served at `GET /map/daily`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailySeed {
+    /// "YYYY-MM-DD"
+    pub date: String,
+    pub seed: u64,
+}
+
+impl DailySeed {
+    /// The day containing a Unix timestamp; servers and offline clients work it out alike
+    pub fn for_time(unix_secs: u64) -> Self {
+        let days = unix_secs / 86_400;
+        // splitmix64, so neighbouring days get unrelated seeds
+        let mut z = days.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        let (year, month, day) = civil_date(days);
+        DailySeed { date: format!("{:04}-{:02}-{:02}", year, month, day), seed: z ^ (z >> 31) }
+    }
+}
+
+/// (year, month, day) of a day counted from 1970-01-01
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's civil_from_days, for days on or after the epoch
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Longest chat message the server will relay, in characters
+pub const MAX_CHAT_LEN: usize = 200;
+
+/// How far, in tiles, local chat and emotes carry
+pub const SAY_RANGE: u32 = 30;
+
+/// Most dice one `/roll` may throw
+pub const MAX_DICE: u32 = 20;
+
+/// Most sides a `/roll` die may have
+pub const MAX_DIE_SIDES: u32 = 1000;
+
+/// Body of `POST /register` and `POST /login`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credentials {
+    pub name: String,
+    pub password: String,
+}
+
+/// Reply to a successful `/register` or `/login`; send the token back as `Authorization: Bearer <token>`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthToken {
+    pub name: String,
+    pub token: String,
+}
+
+/// Reply to `GET /me`: the account a token belongs to
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountInfo {
+    pub name: String,
+}
+
+/// Why an HTTP request failed, for clients to act on without reading the message
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// A query string, body or path parameter that doesn't parse
+    MalformedRequest,
+    /// A value out of bounds: names, passwords, chat text, tile kinds, tick rates, order sizes
+    Invalid,
+    MapSize,
+    UnknownAlgorithm,
+    OutOfWorld,
+    NameTaken,
+    BadCredentials,
+    /// A missing, wrong or expired bearer token
+    BadToken,
+    UnknownStation,
+    /// The pilot's ship isn't within docking range of the station
+    NotDocked,
+    UnknownItem,
+    ShortStock,
+    UnknownMission,
+    /// The mission's board has been replaced
+    MissionExpired,
+    /// The objective isn't met yet, or the ship isn't where it should be
+    MissionUnfinished,
+    /// This account already completed the mission
+    MissionClaimed,
+    /// The mission was never accepted by this account
+    MissionNotAccepted,
+    /// The account can't cover a purchase or repair
+    ShortCredits,
+    /// The account's hold hasn't got the goods to sell or deliver
+    ShortCargo,
+    /// The tile is already something it can't become from
+    TileConflict,
+    /// The pilot's ship isn't close enough to the tile it reported
+    OutOfReach,
+    /// Admin routes are off: the server has no admin token
+    AdminDisabled,
+    AdminToken,
+    NotFound,
+    /// The server couldn't read or save its data
+    Storage,
+    /// Over a request budget; the response's `Retry-After` says when to try again
+    RateLimited,
+    /// A code from a newer server
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+/// Body of every failed HTTP request
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    #[serde(default)]
+    pub code: ErrorCode,
+    /// Human-readable, fit to show the player
+    pub error: String,
+}
+
+/// Where `/distance` measures to
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DistanceTarget {
+    Player { name: String },
+    Position { x: i32, y: i32 },
+}
+
+/// Public view of a connected player
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    pub id: u64,
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub direction: Direction,
+    /// The pilot's ship name, callsign and paint; empty from servers that predate liveries
+    #[serde(default, skip_serializing_if = "ShipLivery::is_empty")]
+    pub ship: ShipLivery,
+}
+
+impl PlayerInfo {
+    /// Name to show above the ship: "[ACE-1] Ace" with a callsign
+    pub fn label(&self) -> String {
+        match &self.ship.callsign {
+            Some(callsign) => format!("[{}] {}", callsign, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Longest ship name, in characters
+pub const MAX_SHIP_NAME: usize = 24;
+
+/// Longest callsign, in characters
+pub const MAX_CALLSIGN: usize = 8;
+
+/// A part of a ship sprite the pilot can repaint
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShipPart {
+    Hull,
+    Cockpit,
+    Wing,
+}
+
+impl ShipPart {
+    pub const ALL: [ShipPart; 3] = [ShipPart::Hull, ShipPart::Cockpit, ShipPart::Wing];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ShipPart::Hull => "hull",
+            ShipPart::Cockpit => "cockpit",
+            ShipPart::Wing => "wing",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ShipPart> {
+        ShipPart::ALL.into_iter().find(|part| part.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// How a pilot named and painted their ship; parts left out keep the usual look
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShipLivery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callsign: Option<String>,
+    /// Paint as 0xRRGGBB, by part
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub colors: BTreeMap<ShipPart, u32>,
+}
+
+impl ShipLivery {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.callsign.is_none() && self.colors.is_empty()
+    }
+
+    /// A ship name fit to show: 1-24 characters once trimmed, no control characters
+    pub fn check_name(name: &str) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("ship names can't be empty".to_string());
+        }
+        if name.chars().any(char::is_control) {
+            return Err("ship names can't hold control characters".to_string());
+        }
+        if name.chars().count() > MAX_SHIP_NAME {
+            return Err(format!("ship names are at most {} characters", MAX_SHIP_NAME));
+        }
+        Ok(name.to_string())
+    }
+
+    /// A callsign: 2-8 letters, digits or dashes, kept uppercase
+    pub fn check_callsign(callsign: &str) -> Result<String, String> {
+        let callsign = callsign.trim();
+        if !(2..=MAX_CALLSIGN).contains(&callsign.chars().count()) {
+            return Err(format!("callsigns are 2-{} characters", MAX_CALLSIGN));
+        }
+        if !callsign.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err("callsigns are letters, digits and dashes".to_string());
+        }
+        Ok(callsign.to_ascii_uppercase())
+    }
+
+    /// Only the parts that pass the checks, for a livery that came over the wire
+    pub fn sanitized(self) -> ShipLivery {
+        ShipLivery {
+            name: self.name.and_then(|name| ShipLivery::check_name(&name).ok()),
+            callsign: self.callsign.and_then(|callsign| ShipLivery::check_callsign(&callsign).ok()),
+            colors: self.colors.into_iter().filter(|&(_, color)| color <= 0xFFFFFF).collect(),
+        }
+    }
+}
+
+/// How a server-driven ship gets around
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NpcBehavior {
+    /// Flies a fixed loop of waypoints
+    Patrol,
+    /// Drifts between random spots near home
+    Wander,
+    /// Keeps away from nearby pilots
+    Flee,
+    /// A behavior from a newer server
+    #[serde(other)]
+    Unknown,
+}
+
+/// Public view of a server-driven ship; ids are separate from player session ids
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NpcInfo {
+    pub id: u64,
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub direction: Direction,
+    pub behavior: NpcBehavior,
+}
+
+/// A station pilots can dock at, make their home and respawn at
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StationInfo {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// URL id for a station, e.g. "haven-station" for "Haven Station"
+pub fn station_id(name: &str) -> String {
+    let slug: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' }).collect();
+    slug.split('-').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-")
+}
+
+/// One commodity on a station's market; prices are credits per unit
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarketGood {
+    pub item: String,
+    /// What a pilot pays for one
+    pub buy_price: u32,
+    /// What a pilot gets for one
+    pub sell_price: u32,
+    pub stock: u32,
+}
+
+/// Reply to `GET /station/{id}/market`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarketInfo {
+    pub station: String,
+    pub goods: Vec<MarketGood>,
+}
+
+/// Which way a trade goes, from the pilot's side
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+/// Body of `POST /station/{id}/market`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeOrder {
+    pub item: String,
+    pub quantity: u32,
+    pub side: TradeSide,
+}
+
+/// Reply to a trade: the credits it cost or earned, and the market after it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeReceipt {
+    pub order: TradeOrder,
+    pub total: u32,
+    pub market: MarketInfo,
+    /// The account's credits after the trade; None for guests, who keep their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<u32>,
+}
+
+/// Credits a station charges per point of hull patched; shields are topped up free
+pub const REPAIR_PRICE: u32 = 2;
+
+/// Body of `POST /station/{id}/repair`: how many hull points need patching
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairOrder {
+    pub hull: u32,
+}
+
+/// Reply to a repair: what it cost
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairReceipt {
+    pub hull: u32,
+    pub cost: u32,
+    /// The account's credits after paying; None for guests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<u32>,
+}
+
+/// What moved a pilot's credits
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionKind {
+    Mission,
+    Trade,
+    Repair,
+    /// A kind from a newer server
+    #[serde(other)]
+    Other,
+}
+
+impl TransactionKind {
+    /// As written in JSON, e.g. "trade"
+    pub fn name(self) -> &'static str {
+        match self {
+            TransactionKind::Mission => "mission",
+            TransactionKind::Trade => "trade",
+            TransactionKind::Repair => "repair",
+            TransactionKind::Other => "other",
+        }
+    }
+
+    /// The kind `name` gives; anything unrecognised is `Other`
+    pub fn from_name(name: &str) -> TransactionKind {
+        [TransactionKind::Mission, TransactionKind::Trade, TransactionKind::Repair]
+            .into_iter()
+            .find(|kind| kind.name() == name)
+            .unwrap_or(TransactionKind::Other)
+    }
+}
+
+/// One line of a pilot's ledger
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transaction {
+    pub kind: TransactionKind,
+    /// Credits in, or out when negative
+    pub amount: i64,
+    /// The balance after it
+    pub balance: u32,
+    /// What it was for, e.g. "Sold 5 ore at Haven Station"
+    pub memo: String,
+    /// Unix seconds
+    pub time: u64,
+}
+
+/// Reply to `GET /player/balance`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Balance {
+    pub name: String,
+    pub credits: u32,
+}
+
+/// Reply to `GET /player/transactions`: the latest, newest first
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionHistory {
+    pub transactions: Vec<Transaction>,
+}
+
+/// What a mission asks of a pilot
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Objective {
+    /// Carry `cargo` to a point of interest and hand it over there
+    Deliver { cargo: ItemCount, to: String, x: i32, y: i32 },
+    /// Fly through this many different nebula tiles
+    Scan { tiles: u32 },
+    /// Mine this much ore
+    Mine { ore: u32 },
+    /// An objective from a newer server
+    #[serde(other)]
+    Unknown,
+}
+
+/// A job on a server's mission board
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissionInfo {
+    pub id: String,
+    pub name: String,
+    pub objective: Objective,
+    pub credits: u32,
+    /// Items paid on top of the credits
+    #[serde(default)]
+    pub items: Vec<ItemCount>,
+}
+
+/// Reply to `GET /missions`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissionBoard {
+    pub missions: Vec<MissionInfo>,
+    /// Seconds until the board is replaced
+    pub expires_in: u64,
+}
+
+/// Reply to a completed mission: what it paid
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissionReward {
+    pub mission: String,
+    pub credits: u32,
+    pub items: Vec<ItemCount>,
+    /// The account's credits after the payout; None for guests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<u32>,
+}
+
+/// Some number of one item, by content id
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemCount {
+    pub item: String,
+    pub count: u32,
+}
+
+/// A kind of item, e.g. ore
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemDef {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// A hull pilots can fly
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShipClassDef {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub hull: u32,
+    /// Units of items the hold carries
+    pub cargo: u32,
+}
+
+/// Something fitted to a ship for a price
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpgradeDef {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Ship class ids it fits; empty fits any
+    #[serde(default)]
+    pub ships: Vec<String>,
+    #[serde(default)]
+    pub cost: Vec<ItemCount>,
+}
+
+/// A job pilots can take: hand in `deliver`, receive `reward`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissionTemplate {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub deliver: Vec<ItemCount>,
+    #[serde(default)]
+    pub reward: Vec<ItemCount>,
+}
+
+/// A content pack the server loaded
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackInfo {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+}
+
+/// Every definition from the server's content packs, merged in load order
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContentCatalog {
+    pub packs: Vec<PackInfo>,
+    pub items: Vec<ItemDef>,
+    pub ships: Vec<ShipClassDef>,
+    pub upgrades: Vec<UpgradeDef>,
+    pub missions: Vec<MissionTemplate>,
+}
+
+/// What moved in a `WorldDelta`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    Player,
+    Npc,
+}
+
+/// Which player or NPC ship, in `AreaChanged`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EntityId {
+    pub kind: EntityKind,
+    pub id: u64,
+}
+
+/// Where an entity is now; deltas carry absolute positions, so applying one twice is harmless
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntityMove {
+    pub kind: EntityKind,
+    pub id: u64,
+    pub x: i32,
+    pub y: i32,
+    pub direction: Direction,
+}
+
+/// A tile that changed since the previous delta
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileChange {
+    pub x: i32,
+    pub y: i32,
+    pub tile: Tile,
+}
+
+/// Reply to `GET /map/diff?since=N`: the world's tiles changed after version N, as they are now
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MapDiff {
+    /// The version these changes bring a copy of the world up to; ask from here next time
+    pub version: u64,
+    pub tiles: Vec<TileChange>,
+    /// More changes past `version` didn't fit; ask again for the rest
+    #[serde(default)]
+    pub more: bool,
+}
+
+/// A pilot's running totals, kept by the server
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PilotStats {
+    /// Tiles flown; teleports don't count
+    pub distance: u64,
+    pub ore_mined: u64,
+    pub crystal_mined: u64,
+    /// Distinct chunks visited
+    pub sectors_explored: u64,
+}
+
+impl PilotStats {
+    pub fn plus(self, other: PilotStats) -> PilotStats {
+        PilotStats {
+            distance: self.distance + other.distance,
+            ore_mined: self.ore_mined + other.ore_mined,
+            crystal_mined: self.crystal_mined + other.crystal_mined,
+            sectors_explored: self.sectors_explored + other.sectors_explored,
+        }
+    }
+}
+
+/// What a leaderboard ranks pilots by
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardMetric {
+    /// Tiles flown
+    Distance,
+    /// Ore mined
+    Ore,
+    /// Credits earned; spending doesn't count against it
+    Credits,
+}
+
+impl LeaderboardMetric {
+    pub const ALL: [LeaderboardMetric; 3] = [LeaderboardMetric::Distance, LeaderboardMetric::Ore, LeaderboardMetric::Credits];
+
+    /// As written in JSON and `?metric=`, e.g. "ore"
+    pub fn name(self) -> &'static str {
+        match self {
+            LeaderboardMetric::Distance => "distance",
+            LeaderboardMetric::Ore => "ore",
+            LeaderboardMetric::Credits => "credits",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LeaderboardMetric> {
+        Self::ALL.into_iter().find(|metric| metric.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Heading for the board, e.g. "Ore mined"
+    pub fn title(self) -> &'static str {
+        match self {
+            LeaderboardMetric::Distance => "Distance traveled",
+            LeaderboardMetric::Ore => "Ore mined",
+            LeaderboardMetric::Credits => "Credits earned",
+        }
+    }
+}
+
+/// One pilot's place on a leaderboard
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    /// 1 for the top pilot
+    pub rank: u32,
+    pub name: String,
+    pub value: u64,
+}
+
+/// Reply to `GET /leaderboard`: one page of the ranking
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Leaderboard {
+    pub metric: LeaderboardMetric,
+    /// From 1
+    pub page: u32,
+    /// At least 1, even for an empty board
+    pub pages: u32,
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+/// A map a pilot shared on the server, as `GET /maps` lists it; `GET /maps/{id}` has its tiles
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MapListing {
+    pub id: u64,
+    pub name: String,
+    /// The account that uploaded it
+    pub author: String,
+    pub width: usize,
+    pub height: usize,
+    /// When it was uploaded, as Unix seconds
+    pub uploaded: u64,
+}
+
+/// A jump gate, in its own system's coordinates
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GateInfo {
+    pub x: i32,
+    pub y: i32,
+    /// The `SystemInfo::id` it leads to
+    pub to: String,
+}
+
+/// One of the server's star systems, as listed by `GET /systems`: its own map seed and its gates
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemInfo {
+    /// Lowercase, as in `/system/{id}/map`
+    pub id: String,
+    pub name: String,
+    pub seed: u64,
+    pub gates: Vec<GateInfo>,
+}
+
+impl SystemInfo {
+    /// The gate a ship at (x, y) is sitting on
+    pub fn gate_at(&self, x: i32, y: i32) -> Option<&GateInfo> {
+        self.gates.iter().find(|gate| (gate.x, gate.y) == (x, y))
+    }
+
+    /// The gate back to `system`, where a jump from there arrives
+    pub fn gate_to(&self, system: &str) -> Option<&GateInfo> {
+        self.gates.iter().find(|gate| gate.to == system)
+    }
+}
+
+/// One tile in this many under a meteor shower is blocked by falling rock
+pub const METEOR_DEBRIS_ODDS: u32 = 5;
+
+/// What sort of regional event the server has started
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// Falling rock blocks scattered tiles in the area while it lasts
+    MeteorShower,
+    /// A charged cloud that wears down the hull of any ship inside
+    IonStorm,
+    /// A kind from a newer server
+    #[serde(other)]
+    Unknown,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 2] = [EventKind::MeteorShower, EventKind::IonStorm];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::MeteorShower => "meteor shower",
+            EventKind::IonStorm => "ion storm",
+            EventKind::Unknown => "disturbance",
+        }
+    }
+}
+
+/// A regional event, sent in `ServerMessage::EventStarted`; it covers the tiles within `radius` of (x, y)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorldEvent {
+    pub id: u64,
+    pub kind: EventKind,
+    pub x: i32,
+    pub y: i32,
+    pub radius: u32,
+    /// Seconds left when it was sent
+    pub secs_left: u32,
+}
+
+impl WorldEvent {
+    /// Whether (x, y) is inside the event, as the crow flies
+    pub fn covers(&self, x: i32, y: i32) -> bool {
+        let (dx, dy) = (self.x.abs_diff(x) as u64, self.y.abs_diff(y) as u64);
+        let radius = self.radius as u64;
+        dx <= radius && dy <= radius && dx * dx + dy * dy <= radius * radius
+    }
+
+    /// Whether falling rock blocks (x, y); the same tiles for everyone, picked by position and id
+    pub fn blocks(&self, x: i32, y: i32) -> bool {
+        self.kind == EventKind::MeteorShower && self.covers(x, y) && hash_position(x, y, self.id as u32).is_multiple_of(METEOR_DEBRIS_ODDS)
+    }
+
+    /// Whether a ship at (x, y) takes storm damage
+    pub fn damages(&self, x: i32, y: i32) -> bool {
+        self.kind == EventKind::IonStorm && self.covers(x, y)
+    }
+}
+
+/// Messages sent from a client to the server over the `/ws` socket
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// First message after connecting
+    Hello { name: String },
+    /// Where the player's ship has flown to and its facing, in the star system with this seed
+    /// (the home system when missing). The server only takes it if the ship could have got there
+    Position {
+        x: i32,
+        y: i32,
+        direction: Direction,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>,
+    },
+    /// A chat line to relay to everyone else
+    Chat { text: String },
+    /// A line said aloud, or an emote, heard only by pilots within `SAY_RANGE`
+    Say {
+        text: String,
+        #[serde(default)]
+        emote: bool,
+    },
+    /// Roll `count` dice with `sides` sides; the server rolls so everyone trusts the result
+    Roll { count: u32, sides: u32 },
+    /// Flip a coin on the server
+    Flip,
+    /// Ask for the server's clock
+    Time,
+    /// Ask how far away a player or position is
+    Distance { target: DistanceTarget },
+    /// Resources just mined, for the stats store
+    Mined { ore: u32, crystal: u32 },
+    /// Ask for the pilot's lifetime and session stats
+    Stats,
+    /// Make the station we're docked at our home
+    SetHome,
+    /// Go back to our home station (Haven's by default)
+    Respawn,
+    /// Ask for a full `Snapshot` after missing a `WorldDelta`
+    Resync,
+    /// Fire a projectile from our ship, heading this way
+    Fire { direction: Direction },
+    /// Our ship's name, callsign and paint; sent after Hello and whenever they change
+    Livery { ship: ShipLivery },
+}
+
+/// Messages broadcast by the server over the `/ws` socket
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Sent once in reply to Hello with the session id, everyone already online
+    /// and the sequence number of the last `WorldDelta` already reflected, plus the
+    /// server's clock (seconds since the Unix epoch; 0 from servers that predate it)
+    Welcome {
+        id: u64,
+        players: Vec<PlayerInfo>,
+        #[serde(default)]
+        seq: u64,
+        #[serde(default)]
+        unix_secs: u64,
+    },
+    PlayerJoined { player: PlayerInfo },
+    /// A single move, as sent by servers that predate `WorldDelta`
+    PlayerMoved { id: u64, x: i32, y: i32, direction: Direction },
+    PlayerLeft { id: u64 },
+    /// A chat line from another player
+    Chat { id: u64, name: String, text: String },
+    /// Local chat or an emote from a pilot close by
+    Say {
+        id: u64,
+        name: String,
+        text: String,
+        #[serde(default)]
+        emote: bool,
+    },
+    /// Sent only to the sender when a chat line was not relayed
+    ChatRejected { reason: String },
+    /// A dice roll, broadcast to everyone including the roller
+    Roll { id: u64, name: String, count: u32, sides: u32, rolls: Vec<u32>, total: u32 },
+    /// A coin flip, broadcast to everyone including the flipper
+    Flip { id: u64, name: String, heads: bool },
+    /// Reply to `Time`: seconds since the Unix epoch
+    Time { unix_secs: u64 },
+    /// Reply to `Distance`: straight-line distance in tiles, rounded
+    Distance { target: String, distance: u32 },
+    /// Sent only to the sender when a utility command couldn't run
+    CommandFailed { reason: String },
+    /// Sent only to the mover when a `Position` was refused (too fast, or through something
+    /// solid): the ship is still here as far as everyone else is concerned
+    Corrected { x: i32, y: i32 },
+    /// Background chatter from a nearby point of interest (traffic control, news relays)
+    Ambient { source: String, text: String },
+    /// Sent after Welcome to a logged-in pilot: where they were when they last left
+    Resume { x: i32, y: i32 },
+    /// Every NPC ship, sent after Welcome (and on every move by servers that predate `WorldDelta`)
+    Npcs { ships: Vec<NpcInfo> },
+    /// Everything that moved or changed during one tick, numbered one after the last
+    WorldDelta { seq: u64, moved: Vec<EntityMove>, tiles: Vec<TileChange> },
+    /// Sent just before a `WorldDelta` when pilots or NPC ships come within range (in full,
+    /// so they can be drawn) or go out of it; deltas only move what's in range
+    AreaChanged {
+        players: Vec<PlayerInfo>,
+        npcs: Vec<NpcInfo>,
+        #[serde(default)]
+        gone: Vec<EntityId>,
+    },
+    /// Reply to `Resync`: the full state as of delta `seq`
+    Snapshot { seq: u64, players: Vec<PlayerInfo>, npcs: Vec<NpcInfo> },
+    /// Sent after Welcome: the items, ships, upgrades and missions this server's content packs define
+    Content { catalog: ContentCatalog },
+    /// Reply to `Stats`; `lifetime` includes this session and is None for guests
+    Stats { name: String, lifetime: Option<PilotStats>, session: PilotStats },
+    /// Sent after Welcome: every station, and which one is our home
+    Stations { stations: Vec<StationInfo>, home: Option<String> },
+    /// Reply to `SetHome`
+    HomeSet { station: String },
+    /// Reply to `Respawn`: the ship is now at this station
+    Respawned { station: String, x: i32, y: i32 },
+    /// An admin closed this session; the socket closes next and the client shouldn't redial
+    Kicked { reason: String },
+    /// A pilot fired a projectile from their tile; clients fly it themselves (`ecs::Entities::step_projectiles`)
+    /// until `ShotEnded`
+    Shot { id: u64, owner: u64, x: i32, y: i32, direction: Direction },
+    /// A projectile stopped here: it hit something or ran out of range
+    ShotEnded { id: u64, x: i32, y: i32 },
+    /// Sent only to the shooter when their projectile hits an NPC ship
+    Hit { target: String, destroyed: bool },
+    /// A regional event began (and, after Welcome, each one already under way)
+    EventStarted { event: WorldEvent },
+    /// An event ran its course; its area is back to normal
+    EventEnded { id: u64 },
+    /// A pilot renamed or repainted their ship
+    Livery { id: u64, ship: ShipLivery },
+    /// A message type from a newer server, kept so one unknown message doesn't break the stream
+    #[serde(other)]
+    Unknown,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Tile Tests ====================
+
+    #[test]
+    fn test_tile_passability() {
+        assert!(Tile::Floor.is_passable(), "Floor should be passable");
+        assert!(Tile::Nebula.is_passable(), "Nebula should be passable");
+        assert!(!Tile::Wall.is_passable(), "Wall should not be passable");
+        assert!(!Tile::Asteroid.is_passable(), "Asteroid should not be passable");
+        assert!(!Tile::OreAsteroid.is_passable(), "Ore is mined from next to it, not flown through");
+        assert!(!Tile::CrystalField.is_passable(), "Crystals are mined from next to them, not flown through");
+        assert!(!Tile::Unknown.is_passable(), "Unknown tiles should not be passable");
+    }
+
+    #[test]
+    fn test_sensor_range() {
+        assert!(within_sensor_range((0, 0), (NEBULA_SENSOR_RANGE, 0)), "The edge of the range is in range");
+        assert!(!within_sensor_range((0, 0), (NEBULA_SENSOR_RANGE + 1, 0)));
+        assert!(!within_sensor_range((0, 0), (5, 5)), "Range is measured as the crow flies");
+        assert!(!within_sensor_range((i32::MAX, 0), (i32::MIN, 0)), "Opposite ends of the world don't overflow");
+    }
+
+    #[test]
+    fn test_tile_breaking() {
+        assert_eq!(Tile::OreAsteroid.broken(), Some(Tile::Asteroid));
+        assert_eq!(Tile::Asteroid.broken(), Some(Tile::Floor), "The laser clears asteroids");
+        assert_eq!(Tile::Asteroid.broken().and_then(Tile::broken), None, "Open space stays open");
+        assert_eq!(Tile::Wall.broken(), None, "Walls can't be broken");
+        assert_eq!(Tile::OreAsteroid.mining_yield(), Some(("ore", 3)));
+        assert_eq!(Tile::Asteroid.mining_yield(), None, "The laser leaves nothing to keep");
+    }
+
+    #[test]
+    fn test_tile_serialization() {
+        // Test JSON serialization round-trip
+        let tiles = vec![Tile::Wall, Tile::Floor, Tile::Asteroid, Tile::Nebula, Tile::OreAsteroid, Tile::CrystalField];
+        for tile in tiles {
+            let json = serde_json::to_string(&tile).unwrap();
+            let parsed: Tile = serde_json::from_str(&json).unwrap();
+            assert_eq!(tile, parsed);
+        }
+    }
+
+    #[test]
+    fn test_tile_json_format() {
+        // Verify exact JSON format
+        assert_eq!(serde_json::to_string(&Tile::Wall).unwrap(), "\"Wall\"");
+        assert_eq!(serde_json::to_string(&Tile::Floor).unwrap(), "\"Floor\"");
+        assert_eq!(serde_json::to_string(&Tile::Asteroid).unwrap(), "\"Asteroid\"");
+        assert_eq!(serde_json::to_string(&Tile::Nebula).unwrap(), "\"Nebula\"");
+        assert_eq!(serde_json::to_string(&Tile::OreAsteroid).unwrap(), "\"OreAsteroid\"");
+        assert_eq!(serde_json::to_string(&Tile::CrystalField).unwrap(), "\"CrystalField\"");
+    }
+
+    #[test]
+    fn test_unknown_tile_kinds_parse() {
+        let parsed: Vec<Tile> = serde_json::from_str(r#"["Floor", "Lava", "Wall"]"#).unwrap();
+        assert_eq!(parsed, vec![Tile::Floor, Tile::Unknown, Tile::Wall], "Newer tile kinds become Unknown");
+
+        let json = r#"{"tiles":["Crystal"],"width":1,"height":1,"start_x":0,"start_y":0,"entities":[{"kind":"beacon"}]}"#;
+        let map: MapData = serde_json::from_str(json).unwrap();
+        assert_eq!(map.tiles, vec![Tile::Unknown], "Unknown tiles and extra fields don't fail the map");
+    }
+
+    // ==================== Direction Tests ====================
+
+    #[test]
+    fn test_direction_from_delta_cardinal() {
+        assert_eq!(Direction::from_delta(0, -1), Some(Direction::Up));
+        assert_eq!(Direction::from_delta(0, 1), Some(Direction::Down));
+        assert_eq!(Direction::from_delta(-1, 0), Some(Direction::Left));
+        assert_eq!(Direction::from_delta(1, 0), Some(Direction::Right));
+    }
+
+    #[test]
+    fn test_direction_from_delta_diagonal() {
+        assert_eq!(Direction::from_delta(1, -1), Some(Direction::UpRight));
+        assert_eq!(Direction::from_delta(-1, -1), Some(Direction::UpLeft));
+        assert_eq!(Direction::from_delta(1, 1), Some(Direction::DownRight));
+        assert_eq!(Direction::from_delta(-1, 1), Some(Direction::DownLeft));
+    }
+
+    #[test]
+    fn test_direction_from_delta_zero() {
+        assert_eq!(Direction::from_delta(0, 0), None);
+    }
+
+    #[test]
+    fn test_direction_delta_round_trips() {
+        for direction in Direction::ALL {
+            let (dx, dy) = direction.delta();
+            assert_eq!(Direction::from_delta(dx, dy), Some(direction), "{:?}", direction);
+        }
+    }
+
+    #[test]
+    fn test_direction_to_char() {
+        assert_eq!(Direction::Up.to_char(), '↑');
+        assert_eq!(Direction::Down.to_char(), '↓');
+        assert_eq!(Direction::Left.to_char(), '←');
+        assert_eq!(Direction::Right.to_char(), '→');
+        assert_eq!(Direction::UpRight.to_char(), '↗');
+        assert_eq!(Direction::UpLeft.to_char(), '↖');
+        assert_eq!(Direction::DownRight.to_char(), '↘');
+        assert_eq!(Direction::DownLeft.to_char(), '↙');
+    }
+
+    #[test]
+    fn test_direction_to_ascii() {
+        assert_eq!(Direction::Up.to_ascii(), '^');
+        assert_eq!(Direction::Right.to_ascii(), '>');
+        assert_eq!(Direction::DownLeft.to_ascii(), '/');
+        assert_eq!(Direction::UpLeft.to_ascii(), '\\');
+        assert!(Direction::ALL.iter().all(|d| d.to_ascii().is_ascii()));
+    }
+
+    #[test]
+    fn test_direction_name() {
+        assert_eq!(Direction::Up.name(), "N");
+        assert_eq!(Direction::UpRight.name(), "NE");
+        assert_eq!(Direction::Right.name(), "E");
+        assert_eq!(Direction::DownRight.name(), "SE");
+        assert_eq!(Direction::Down.name(), "S");
+        assert_eq!(Direction::DownLeft.name(), "SW");
+        assert_eq!(Direction::Left.name(), "W");
+        assert_eq!(Direction::UpLeft.name(), "NW");
+    }
+
+    #[test]
+    fn test_direction_serialization() {
+        let json = serde_json::to_string(&Direction::UpLeft).unwrap();
+        assert_eq!(json, "\"UpLeft\"");
+        let parsed: Direction = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, Direction::UpLeft);
+    }
+
+    // ==================== Hash Function Tests ====================
+
+    #[test]
+    fn test_fnv1a_known_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_hash_position_deterministic() {
+        let hash1 = hash_position(10, 20, 42);
+        let hash2 = hash_position(10, 20, 42);
+        assert_eq!(hash1, hash2, "Same inputs should produce same hash");
+    }
+
+    #[test]
+    fn test_hash_position_different_x() {
+        let hash1 = hash_position(10, 20, 42);
+        let hash2 = hash_position(11, 20, 42);
+        assert_ne!(hash1, hash2, "Different x should produce different hash");
+    }
+
+    #[test]
+    fn test_hash_position_different_y() {
+        let hash1 = hash_position(10, 20, 42);
+        let hash2 = hash_position(10, 21, 42);
+        assert_ne!(hash1, hash2, "Different y should produce different hash");
+    }
+
+    #[test]
+    fn test_hash_position_different_seed() {
+        let hash1 = hash_position(10, 20, 42);
+        let hash2 = hash_position(10, 20, 43);
+        assert_ne!(hash1, hash2, "Different seed should produce different hash");
+    }
+
+    #[test]
+    fn test_hash_position_negative_coords() {
+        // Should not panic with negative coordinates
+        let hash1 = hash_position(-10, -20, 42);
+        let hash2 = hash_position(-10, -20, 42);
+        assert_eq!(hash1, hash2, "Negative coords should still be deterministic");
+    }
+
+    #[test]
+    fn test_hash_position_distribution() {
+        // Test that hash produces reasonably distributed values
+        let mut values = std::collections::HashSet::new();
+        for x in 0..100 {
+            for y in 0..100 {
+                values.insert(hash_position(x, y, 42));
+            }
+        }
+        // Should have many unique values (good distribution)
+        assert!(values.len() > 9000, "Hash should have good distribution");
+    }
+
+    #[test]
+    fn test_daily_seed() {
+        let day = 86_400;
+        let today = DailySeed::for_time(20_378 * day + 3600);
+        assert_eq!(today.date, "2025-10-17");
+        assert_eq!(today, DailySeed::for_time(20_378 * day + day - 1), "The whole UTC day shares a seed");
+        assert_ne!(today.seed, DailySeed::for_time(20_379 * day).seed, "The next day is different");
+        assert_eq!(DailySeed::for_time(0).date, "1970-01-01");
+        assert_eq!(DailySeed::for_time(11_016 * day).date, "2000-02-29", "Leap days");
+    }
+
+    // ==================== MapData Tests ====================
+
+    #[test]
+    fn test_map_data_json_round_trip() {
+        let map = MapData {
+            tiles: vec![Tile::Wall, Tile::Floor, Tile::Nebula, Tile::Asteroid],
+            width: 2,
+            height: 2,
+            start_x: 1,
+            start_y: 0,
+            pois: Vec::new(),
+            regions: Vec::new(),
+        };
+
+        let json = serde_json::to_string(&map).unwrap();
+        let parsed: MapData = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.tiles, map.tiles);
+        assert_eq!(parsed.width, 2);
+        assert_eq!(parsed.height, 2);
+        assert_eq!((parsed.start_x, parsed.start_y), (1, 0));
+        assert!(json.contains(r#""tiles":["Wall","Floor","Nebula","Asteroid"]"#), "Tiles serialize flat: {}", json);
+        assert!(!json.contains("pois"), "Maps without points of interest look as they always did");
+    }
+
+    #[test]
+    fn test_map_data_index_is_row_major() {
+        let map = sample_map();
+        assert_eq!(map.index(0, 0), Some(0));
+        assert_eq!(map.index(2, 0), Some(2));
+        assert_eq!(map.index(0, 1), Some(3));
+        assert_eq!(map.index(3, 0), None, "x past the width must not wrap to the next row");
+        assert_eq!(map.index(0, 2), None);
+        assert_eq!(map.get(0, 1), Some(Tile::Asteroid));
+        assert_eq!(map.get(2, 1), Some(Tile::Wall));
+    }
+
+    #[test]
+    fn test_map_data_rows() {
+        let map = sample_map();
+        let rows: Vec<&[Tile]> = map.rows().collect();
+        assert_eq!(rows, vec![&map.tiles[0..3], &map.tiles[3..6]]);
+        assert_eq!(map.row(1), Some(&[Tile::Asteroid, Tile::Floor, Tile::Wall][..]));
+        assert_eq!(map.row(2), None);
+    }
+
+    #[test]
+    fn test_map_data_rect_clipped() {
+        let map = sample_map();
+        let rect: Vec<&[Tile]> = map.rect(1..10, 0..10).collect();
+        assert_eq!(rect, vec![&[Tile::Floor, Tile::Nebula][..], &[Tile::Floor, Tile::Wall][..]]);
+        assert_eq!(map.rect(5..8, 0..2).filter(|row| !row.is_empty()).count(), 0, "Off-map columns are empty");
+        assert_eq!(map.rect(0..3, 4..6).count(), 0, "Off-map rows are skipped");
+    }
+
+    #[test]
+    fn test_map_data_rect_mut_fills() {
+        let mut map = MapData::filled(4, 3, Tile::Wall);
+        for row in map.rect_mut(1..3, 1..9) {
+            row.fill(Tile::Floor);
+        }
+        assert_eq!(map.tiles.iter().filter(|&&t| t == Tile::Floor).count(), 4);
+        assert_eq!(map.get(1, 2), Some(Tile::Floor));
+        assert_eq!(map.get(0, 1), Some(Tile::Wall));
+        assert_eq!(map.get(3, 1), Some(Tile::Wall));
+
+        *map.get_mut(0, 0).unwrap() = Tile::Nebula;
+        assert_eq!(map.tiles[0], Tile::Nebula);
+        assert!(map.get_mut(4, 0).is_none());
+    }
+
+    // ==================== Binary Format Tests ====================
+
+    fn sample_map() -> MapData {
+        MapData {
+            tiles: vec![Tile::Wall, Tile::Floor, Tile::Nebula, Tile::Asteroid, Tile::Floor, Tile::Wall],
+            width: 3,
+            height: 2,
+            start_x: 1,
+            start_y: -4,
+            pois: Vec::new(),
+            regions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_tile_byte_round_trip() {
+        for tile in [Tile::Wall, Tile::Floor, Tile::Asteroid, Tile::Nebula, Tile::OreAsteroid, Tile::CrystalField, Tile::Unknown] {
+            assert_eq!(Tile::from_byte(tile.to_byte()), Some(tile));
+        }
+        assert_eq!(Tile::from_byte(200), None);
+    }
+
+    #[test]
+    fn test_map_binary_round_trip() {
+        let map = sample_map();
+        let bytes = map.to_bytes();
+        assert_eq!(bytes.len(), 21 + 6, "Header plus one byte per tile");
+
+        let parsed = MapData::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.tiles, map.tiles);
+        assert_eq!((parsed.width, parsed.height), (3, 2));
+        assert_eq!((parsed.start_x, parsed.start_y), (1, -4));
+    }
+
+    #[test]
+    fn test_map_binary_much_smaller_than_json() {
+        let map = MapData::filled(100, 100, Tile::Floor);
+        let json = serde_json::to_vec(&map).unwrap();
+        assert!(map.to_bytes().len() * 5 < json.len(), "Binary should be far smaller than JSON");
+    }
+
+    #[test]
+    fn test_chunk_binary_round_trip() {
+        let mut tiles = vec![Tile::Floor; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+        tiles[7] = Tile::Asteroid;
+        let chunk = ChunkData { cx: -3, cy: 9, tiles, pois: Vec::new(), regions: Vec::new() };
+        assert_eq!(ChunkData::from_bytes(&chunk.to_bytes()), Ok(chunk));
+    }
+
+    #[test]
+    fn test_points_of_interest_round_trip() {
+        let beacon = Poi { kind: PoiKind::Beacon, name: "Beacon Kappa-7".to_string(), x: -200, y: 31 };
+        let mut map = sample_map();
+        map.pois = vec![beacon.clone(), Poi { kind: PoiKind::Station, name: "Ærø Dock".to_string(), x: 2, y: 1 }];
+        let bytes = map.to_bytes();
+        assert_eq!(MapData::from_bytes(&bytes).unwrap().pois, map.pois);
+        assert_eq!(MapData::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(), DecodeError::Truncated);
+
+        let mut chunk = ChunkData { cx: 0, cy: 0, tiles: vec![Tile::Floor; (CHUNK_SIZE * CHUNK_SIZE) as usize], pois: vec![beacon], regions: Vec::new() };
+        chunk.pois[0].name = "x".repeat(300);
+        let parsed = ChunkData::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_eq!(parsed.pois[0].name.len(), 255, "Long names are cut short");
+
+        let mut newer = chunk.to_bytes();
+        newer[13 + (CHUNK_SIZE * CHUNK_SIZE) as usize + 4] = 42;
+        assert_eq!(ChunkData::from_bytes(&newer).unwrap().pois[0].kind, PoiKind::Unknown, "Kinds from a newer server decode as Unknown");
+
+        let json = r#"{"kind":"space_whale","name":"Big","x":1,"y":2}"#;
+        assert_eq!(serde_json::from_str::<Poi>(json).unwrap().kind, PoiKind::Unknown);
+    }
+
+    #[test]
+    fn test_regions_round_trip() {
+        let belt = Region {
+            name: "Kessler Belt".to_string(),
+            x: -128,
+            y: 0,
+            width: 128,
+            height: 128,
+            biome: Biome::AsteroidBelt,
+            danger: 3,
+            description: "Rocks.".to_string(),
+        };
+        let mut map = sample_map();
+        map.regions = vec![belt.clone()];
+        let bytes = map.to_bytes();
+        let parsed = MapData::from_bytes(&bytes).unwrap();
+        assert_eq!((parsed.pois.len(), parsed.regions), (0, vec![belt.clone()]), "Regions follow an empty points of interest section");
+        assert_eq!(MapData::from_bytes(&bytes[..bytes.len() - 2]).unwrap_err(), DecodeError::Truncated);
+        let before_biomes = MapData::from_bytes(&bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(before_biomes.regions[0].biome, Biome::DeepSpace, "Payloads from before biomes are deep space");
+
+        let chunk = ChunkData { cx: -1, cy: 0, tiles: vec![Tile::Floor; (CHUNK_SIZE * CHUNK_SIZE) as usize], pois: Vec::new(), regions: vec![belt] };
+        assert_eq!(ChunkData::from_bytes(&chunk.to_bytes()), Ok(chunk.clone()));
+        let json = serde_json::to_value(&chunk).unwrap();
+        assert_eq!(json["regions"][0]["danger"], 3);
+        assert_eq!(json["regions"][0]["biome"], "asteroid_belt");
+        let newer: Region = serde_json::from_value(serde_json::json!({ "name": "Q", "x": 0, "y": 0, "width": 1, "height": 1, "danger": 0, "biome": "dyson_swarm" })).unwrap();
+        assert_eq!(newer.biome, Biome::Unknown, "Biomes from a newer server");
+        assert!(serde_json::to_value(sample_map()).unwrap().get("regions").is_none(), "Left out when there are none");
+    }
+
+    #[test]
+    fn test_binary_decode_errors() {
+        let bytes = sample_map().to_bytes();
+        assert_eq!(MapData::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(), DecodeError::Truncated);
+        assert_eq!(MapData::from_bytes(b"NOPE\x01").unwrap_err(), DecodeError::BadMagic);
+        assert_eq!(ChunkData::from_bytes(&bytes).unwrap_err(), DecodeError::BadMagic, "Map payload isn't a chunk");
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 99;
+        assert_eq!(MapData::from_bytes(&wrong_version).unwrap_err(), DecodeError::UnsupportedVersion(99));
+
+        let mut new_tile = bytes;
+        new_tile[21] = 42;
+        let parsed = MapData::from_bytes(&new_tile).unwrap();
+        assert_eq!(parsed.tiles[0], Tile::Unknown, "Tile codes from a newer server decode as Unknown");
+    }
+
+    // ==================== Chunk Tests ====================
+
+    #[test]
+    fn test_chunk_coords_positive() {
+        assert_eq!(chunk_coords(0, 0), (0, 0));
+        assert_eq!(chunk_coords(CHUNK_SIZE - 1, CHUNK_SIZE - 1), (0, 0));
+        assert_eq!(chunk_coords(CHUNK_SIZE, 0), (1, 0));
+    }
+
+    #[test]
+    fn test_chunk_coords_negative() {
+        // Negative positions must not share chunk 0 with positive ones
+        assert_eq!(chunk_coords(-1, -1), (-1, -1));
+        assert_eq!(chunk_coords(-CHUNK_SIZE, 0), (-1, 0));
+        assert_eq!(chunk_coords(-CHUNK_SIZE - 1, 0), (-2, 0));
+        assert_eq!(chunk_local(-1, -CHUNK_SIZE), (CHUNK_SIZE - 1, 0));
+    }
+
+    #[test]
+    fn test_chunk_data_get() {
+        let mut tiles = vec![Tile::Floor; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+        tiles[(2 * CHUNK_SIZE + 3) as usize] = Tile::Wall;
+        let mut chunk = ChunkData { cx: 0, cy: 0, tiles, pois: Vec::new(), regions: Vec::new() };
+
+        assert_eq!(chunk.get(3, 2), Some(Tile::Wall));
+        assert_eq!(chunk.get(0, 0), Some(Tile::Floor));
+        assert_eq!(chunk.get(-1, 0), None);
+        assert_eq!(chunk.get(CHUNK_SIZE, 0), None);
+
+        *chunk.get_mut(3, 2).unwrap() = Tile::Nebula;
+        assert_eq!(chunk.get(3, 2), Some(Tile::Nebula));
+        assert!(chunk.get_mut(0, CHUNK_SIZE).is_none());
+    }
+
+    #[test]
+    fn test_map_diff_json() {
+        let diff = MapDiff { version: 7, tiles: vec![TileChange { x: -3, y: 4, tile: Tile::Floor }], more: true };
+        let json = serde_json::to_string(&diff).unwrap();
+        assert_eq!(serde_json::from_str::<MapDiff>(&json).unwrap(), diff);
+        let last_page: MapDiff = serde_json::from_str(r#"{"version":2,"tiles":[]}"#).unwrap();
+        assert!(!last_page.more, "No more unless the server says so");
+    }
+
+    // ==================== Protocol Tests ====================
+
+    #[test]
+    fn test_client_message_json_format() {
+        let msg = ClientMessage::Position { x: 3, y: 4, direction: Direction::Left, seed: None };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(json, r#"{"type":"position","x":3,"y":4,"direction":"Left"}"#);
+        let msg = ClientMessage::Position { x: 3, y: 4, direction: Direction::Left, seed: Some(9) };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(json, r#"{"type":"position","x":3,"y":4,"direction":"Left","seed":9}"#);
+        assert_eq!(serde_json::from_str::<ClientMessage>(&json).unwrap(), msg);
+
+        let msg = ClientMessage::Chat { text: "hello".to_string() };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(json, r#"{"type":"chat","text":"hello"}"#);
+
+        let msg = ClientMessage::Say { text: "hi".to_string(), emote: false };
+        let parsed: ClientMessage = serde_json::from_str(r#"{"type":"say","text":"hi"}"#).unwrap();
+        assert_eq!(parsed, msg, "Lines without the flag are said aloud");
+        let msg = ClientMessage::Say { text: "waves".to_string(), emote: true };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(json, r#"{"type":"say","text":"waves","emote":true}"#);
+        assert_eq!(serde_json::from_str::<ClientMessage>(&json).unwrap(), msg);
+
+        let msg = ClientMessage::Distance { target: DistanceTarget::Position { x: 1, y: 2 } };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(json, r#"{"type":"distance","target":{"kind":"position","x":1,"y":2}}"#);
+
+        assert_eq!(serde_json::to_string(&ClientMessage::Flip).unwrap(), r#"{"type":"flip"}"#);
+        assert_eq!(serde_json::to_string(&ClientMessage::Stats).unwrap(), r#"{"type":"stats"}"#);
+        assert_eq!(serde_json::to_string(&ClientMessage::SetHome).unwrap(), r#"{"type":"set_home"}"#);
+    }
+
+    #[test]
+    fn test_server_message_round_trip() {
+        let messages = vec![
+            ServerMessage::Welcome {
+                id: 7,
+                players: vec![PlayerInfo { id: 1, name: "Ace".to_string(), x: 1, y: 2, direction: Direction::Up, ship: ShipLivery::default() }],
+                seq: 3,
+                unix_secs: 1_700_000_000,
+            },
+            ServerMessage::Snapshot { seq: 9, players: Vec::new(), npcs: Vec::new() },
+            ServerMessage::Content {
+                catalog: ContentCatalog {
+                    packs: vec![PackInfo { name: "core".to_string(), version: "1".to_string() }],
+                    items: vec![ItemDef { id: "ore".to_string(), name: "Ore".to_string(), description: String::new() }],
+                    ..ContentCatalog::default()
+                },
+            },
+            ServerMessage::PlayerMoved { id: 1, x: 5, y: 6, direction: Direction::DownLeft },
+            ServerMessage::PlayerLeft { id: 1 },
+            ServerMessage::Chat { id: 1, name: "Ace".to_string(), text: "hi".to_string() },
+            ServerMessage::Say { id: 1, name: "Ace".to_string(), text: "waves".to_string(), emote: true },
+            ServerMessage::ChatRejected { reason: "too fast".to_string() },
+            ServerMessage::Roll { id: 1, name: "Ace".to_string(), count: 2, sides: 6, rolls: vec![3, 5], total: 8 },
+            ServerMessage::Flip { id: 1, name: "Ace".to_string(), heads: true },
+            ServerMessage::Time { unix_secs: 1_700_000_000 },
+            ServerMessage::Distance { target: "Ace".to_string(), distance: 42 },
+            ServerMessage::CommandFailed { reason: "nope".to_string() },
+            ServerMessage::Corrected { x: 7, y: -3 },
+            ServerMessage::Ambient { source: "Haven Traffic".to_string(), text: "Lane two is clear".to_string() },
+            ServerMessage::Resume { x: -40, y: 12 },
+            ServerMessage::Stats {
+                name: "Ace".to_string(),
+                lifetime: Some(PilotStats { distance: 900, ore_mined: 12, crystal_mined: 2, sectors_explored: 7 }),
+                session: PilotStats { distance: 40, ..PilotStats::default() },
+            },
+            ServerMessage::Stations {
+                stations: vec![StationInfo { name: "Haven Station".to_string(), x: 0, y: 0 }],
+                home: Some("Haven Station".to_string()),
+            },
+            ServerMessage::HomeSet { station: "Drift Depot".to_string() },
+            ServerMessage::Respawned { station: "Haven Station".to_string(), x: 1, y: -1 },
+            ServerMessage::Kicked { reason: "Griefing".to_string() },
+            ServerMessage::Livery {
+                id: 1,
+                ship: ShipLivery {
+                    name: Some("Rusty Bucket".to_string()),
+                    callsign: None,
+                    colors: BTreeMap::from([(ShipPart::Hull, 0xFF8800)]),
+                },
+            },
+            ServerMessage::Npcs {
+                ships: vec![NpcInfo {
+                    id: 1,
+                    name: "Haven Patrol".to_string(),
+                    x: 3,
+                    y: -4,
+                    direction: Direction::Left,
+                    behavior: NpcBehavior::Patrol,
+                }],
+            },
+        ];
+        for msg in messages {
+            let json = serde_json::to_string(&msg).unwrap();
+            let parsed: ServerMessage = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed, msg);
+        }
+        let behavior: NpcBehavior = serde_json::from_str(r#""escort""#).unwrap();
+        assert_eq!(behavior, NpcBehavior::Unknown, "Newer NPC behaviors don't fail the snapshot");
+    }
+
+    #[test]
+    fn test_ship_livery() {
+        assert_eq!(ShipLivery::check_name("  Rusty Bucket "), Ok("Rusty Bucket".to_string()));
+        assert!(ShipLivery::check_name(" ").is_err());
+        assert!(ShipLivery::check_name(&"x".repeat(MAX_SHIP_NAME + 1)).is_err());
+        assert!(ShipLivery::check_name("Bell\u{7}").is_err(), "No control characters");
+        assert_eq!(ShipLivery::check_callsign("ace-1"), Ok("ACE-1".to_string()));
+        assert!(ShipLivery::check_callsign("A").is_err());
+        assert!(ShipLivery::check_callsign("ACE 1").is_err());
+        assert_eq!(ShipPart::from_name("Cockpit"), Some(ShipPart::Cockpit));
+
+        let wire = ShipLivery {
+            name: Some(String::new()),
+            callsign: Some("ace".to_string()),
+            colors: BTreeMap::from([(ShipPart::Hull, 0x123456), (ShipPart::Wing, 0x1000000)]),
+        };
+        let clean = wire.sanitized();
+        assert_eq!(clean.name, None, "Bad names are dropped");
+        assert_eq!(clean.callsign.as_deref(), Some("ACE"));
+        assert_eq!(clean.colors, BTreeMap::from([(ShipPart::Hull, 0x123456)]), "Colors past 0xFFFFFF are dropped");
+
+        let json = serde_json::to_string(&ClientMessage::Livery { ship: clean.clone() }).unwrap();
+        assert_eq!(json, r#"{"type":"livery","ship":{"callsign":"ACE","colors":{"hull":1193046}}}"#);
+
+        let mut pilot: PlayerInfo = serde_json::from_str(r#"{"id":1,"name":"Ace","x":0,"y":0,"direction":"Up"}"#).unwrap();
+        assert!(pilot.ship.is_empty(), "Servers that predate liveries");
+        assert_eq!(pilot.label(), "Ace");
+        assert!(!serde_json::to_string(&pilot).unwrap().contains("ship"), "Left out when empty");
+        pilot.ship = clean;
+        assert_eq!(pilot.label(), "[ACE] Ace");
+    }
+
+    #[test]
+    fn test_world_delta_json_format() {
+        let delta = ServerMessage::WorldDelta {
+            seq: 4,
+            moved: vec![EntityMove { kind: EntityKind::Npc, id: 2, x: 1, y: -1, direction: Direction::Up }],
+            tiles: vec![TileChange { x: 5, y: 6, tile: Tile::Floor }],
+        };
+        assert_eq!(
+            serde_json::to_string(&delta).unwrap(),
+            r#"{"type":"world_delta","seq":4,"moved":[{"kind":"npc","id":2,"x":1,"y":-1,"direction":"Up"}],"tiles":[{"x":5,"y":6,"tile":"Floor"}]}"#
+        );
+        let welcome: ServerMessage = serde_json::from_str(r#"{"type":"welcome","id":1,"players":[]}"#).unwrap();
+        assert_eq!(welcome, ServerMessage::Welcome { id: 1, players: Vec::new(), seq: 0, unix_secs: 0 }, "Older servers send no seq or clock");
+        assert_eq!(serde_json::to_string(&ClientMessage::Resync).unwrap(), r#"{"type":"resync"}"#);
+
+        let area = ServerMessage::AreaChanged { players: Vec::new(), npcs: Vec::new(), gone: vec![EntityId { kind: EntityKind::Player, id: 3 }] };
+        assert_eq!(
+            serde_json::to_string(&area).unwrap(),
+            r#"{"type":"area_changed","players":[],"npcs":[],"gone":[{"kind":"player","id":3}]}"#
+        );
+    }
+
+    #[test]
+    fn test_auth_json_format() {
+        let credentials = Credentials { name: "Ace".to_string(), password: "hunter22".to_string() };
+        assert_eq!(serde_json::to_string(&credentials).unwrap(), r#"{"name":"Ace","password":"hunter22"}"#);
+        let token: AuthToken = serde_json::from_str(r#"{"name":"Ace","token":"ab12"}"#).unwrap();
+        assert_eq!(token, AuthToken { name: "Ace".to_string(), token: "ab12".to_string() });
+    }
+
+    #[test]
+    fn test_error_json_format() {
+        let body = ErrorBody { code: ErrorCode::ShortStock, error: "Only 3 in stock".to_string() };
+        assert_eq!(serde_json::to_string(&body).unwrap(), r#"{"code":"short_stock","error":"Only 3 in stock"}"#);
+        let limited: ErrorBody = serde_json::from_str(r#"{"code":"rate_limited","error":"Slow down"}"#).unwrap();
+        assert_eq!(limited.code, ErrorCode::RateLimited);
+        let newer: ErrorBody = serde_json::from_str(r#"{"code":"solar_flare","error":"Try later"}"#).unwrap();
+        assert_eq!(newer.code, ErrorCode::Unknown, "Codes from newer servers still parse");
+        let bare: ErrorBody = serde_json::from_str(r#"{"error":"Nope"}"#).unwrap();
+        assert_eq!(bare.code, ErrorCode::Unknown);
+    }
+
+    #[test]
+    fn test_market_json_format() {
+        assert_eq!(station_id("Haven Station"), "haven-station");
+        assert_eq!(station_id(" Drift Co-op  Depot"), "drift-co-op-depot");
+        let order = TradeOrder { item: "ore".to_string(), quantity: 3, side: TradeSide::Sell };
+        assert_eq!(serde_json::to_string(&order).unwrap(), r#"{"item":"ore","quantity":3,"side":"sell"}"#);
+    }
+
+    #[test]
+    fn test_mission_json_format() {
+        let scan = MissionInfo {
+            id: "981-2".to_string(),
+            name: "Nebula survey".to_string(),
+            objective: Objective::Scan { tiles: 20 },
+            credits: 60,
+            items: Vec::new(),
+        };
+        assert_eq!(
+            serde_json::to_string(&scan).unwrap(),
+            r#"{"id":"981-2","name":"Nebula survey","objective":{"kind":"scan","tiles":20},"credits":60,"items":[]}"#
+        );
+        let deliver: Objective =
+            serde_json::from_str(r#"{"kind":"deliver","cargo":{"item":"ore","count":5},"to":"Beacon Tau-3","x":4,"y":-9}"#).unwrap();
+        assert_eq!(deliver, Objective::Deliver { cargo: ItemCount { item: "ore".to_string(), count: 5 }, to: "Beacon Tau-3".to_string(), x: 4, y: -9 });
+        let newer: Objective = serde_json::from_str(r#"{"kind":"escort","ship":7}"#).unwrap();
+        assert_eq!(newer, Objective::Unknown, "Objectives from newer servers still parse");
+    }
+
+    #[test]
+    fn test_ledger_json_format() {
+        let sale = Transaction {
+            kind: TransactionKind::Trade,
+            amount: -44,
+            balance: 16,
+            memo: "Bought 1 crystal at Haven Station".to_string(),
+            time: 1_700_000_000,
+        };
+        assert_eq!(
+            serde_json::to_string(&sale).unwrap(),
+            r#"{"kind":"trade","amount":-44,"balance":16,"memo":"Bought 1 crystal at Haven Station","time":1700000000}"#
+        );
+        let newer: TransactionKind = serde_json::from_str(r#""fine""#).unwrap();
+        assert_eq!(newer, TransactionKind::Other, "Kinds from newer servers still parse");
+        let guest = RepairReceipt { hull: 10, cost: 20, balance: None };
+        assert_eq!(serde_json::to_string(&guest).unwrap(), r#"{"hull":10,"cost":20}"#, "Guests get no balance");
+        let reward: MissionReward = serde_json::from_str(r#"{"mission":"7-2","credits":90,"items":[]}"#).unwrap();
+        assert_eq!(reward.balance, None, "Rewards from older servers still parse");
+    }
+
+    #[test]
+    fn test_leaderboard_json_format() {
+        let board = Leaderboard {
+            metric: LeaderboardMetric::Ore,
+            page: 1,
+            pages: 3,
+            entries: vec![LeaderboardEntry { rank: 1, name: "Ace".to_string(), value: 120 }],
+        };
+        assert_eq!(
+            serde_json::to_string(&board).unwrap(),
+            r#"{"metric":"ore","page":1,"pages":3,"entries":[{"rank":1,"name":"Ace","value":120}]}"#
+        );
+        for metric in LeaderboardMetric::ALL {
+            assert_eq!(LeaderboardMetric::from_name(metric.name()), Some(metric), "{} round-trips", metric.name());
+        }
+        assert_eq!(LeaderboardMetric::from_name("Credits"), Some(LeaderboardMetric::Credits), "Case doesn't matter");
+        assert_eq!(LeaderboardMetric::from_name("kills"), None);
+    }
+
+    // ==================== Community Map Tests ====================
+
+    #[test]
+    fn test_map_listing_json_format() {
+        let listing = MapListing { id: 3, name: "The Maze".to_string(), author: "Ace".to_string(), width: 60, height: 30, uploaded: 1_700_000_000 };
+        let json = serde_json::to_string(&listing).unwrap();
+        assert_eq!(json, r#"{"id":3,"name":"The Maze","author":"Ace","width":60,"height":30,"uploaded":1700000000}"#);
+        assert_eq!(serde_json::from_str::<MapListing>(&json).unwrap(), listing);
+    }
+
+    // ==================== Star System Tests ====================
+
+    #[test]
+    fn test_star_systems_json_format() {
+        let vega = SystemInfo {
+            id: "vega".to_string(),
+            name: "Vega".to_string(),
+            seed: 77,
+            gates: vec![GateInfo { x: 40, y: -3, to: "haven".to_string() }],
+        };
+        assert_eq!(
+            serde_json::to_string(&vega).unwrap(),
+            r#"{"id":"vega","name":"Vega","seed":77,"gates":[{"x":40,"y":-3,"to":"haven"}]}"#
+        );
+        assert_eq!(vega.gate_at(40, -3).map(|gate| gate.to.as_str()), Some("haven"));
+        assert_eq!(vega.gate_at(41, -3), None, "Only the gate's own tile");
+        assert_eq!(vega.gate_to("haven"), vega.gates.first());
+        assert_eq!(vega.gate_to("tarsus"), None);
+
+        let gate = Poi { kind: PoiKind::JumpGate, name: "Gate to Haven".to_string(), x: 40, y: -3 };
+        assert_eq!(serde_json::to_string(&gate).unwrap(), r#"{"kind":"jump_gate","name":"Gate to Haven","x":40,"y":-3}"#);
+        let chunk = ChunkData { cx: 0, cy: -1, tiles: vec![Tile::Floor; (CHUNK_SIZE * CHUNK_SIZE) as usize], pois: vec![gate], regions: Vec::new() };
+        assert_eq!(ChunkData::from_bytes(&chunk.to_bytes()), Ok(chunk), "Gates survive the binary format");
+    }
+
+    // ==================== World Event Tests ====================
+
+    #[test]
+    fn test_world_event_areas() {
+        let storm = WorldEvent { id: 3, kind: EventKind::IonStorm, x: 10, y: -4, radius: 5, secs_left: 90 };
+        let json = serde_json::to_string(&ServerMessage::EventStarted { event: storm.clone() }).unwrap();
+        assert_eq!(json, r#"{"type":"event_started","event":{"id":3,"kind":"ion_storm","x":10,"y":-4,"radius":5,"secs_left":90}}"#);
+        assert!(storm.covers(15, -4) && storm.covers(13, 0), "Within five tiles");
+        assert!(!storm.covers(14, 0), "Not past the circle, even inside the square");
+        assert!(storm.damages(10, -4) && !storm.blocks(10, -4), "Storms hurt but don't block");
+
+        let shower = WorldEvent { kind: EventKind::MeteorShower, radius: 20, ..storm };
+        let blocked = (-10..=30).flat_map(|x| (-24..=16).map(move |y| (x, y))).filter(|&(x, y)| shower.blocks(x, y)).count();
+        assert!((150..350).contains(&blocked), "About a fifth of the area is rock, not {}", blocked);
+        assert!(!shower.blocks(31, -4) && !shower.damages(10, -4));
+
+        let parsed: ServerMessage = serde_json::from_str(r#"{"type":"event_started","event":{"id":1,"kind":"solar_flare","x":0,"y":0,"radius":3,"secs_left":9}}"#).unwrap();
+        assert!(matches!(parsed, ServerMessage::EventStarted { event } if event.kind == EventKind::Unknown), "Newer kinds still parse");
+        assert_eq!(serde_json::to_string(&ServerMessage::EventEnded { id: 3 }).unwrap(), r#"{"type":"event_ended","id":3}"#);
+    }
+}
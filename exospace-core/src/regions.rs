@@ -0,0 +1,300 @@
+//! Regions: named areas of the map, with a biome, a danger rating and a description.
+//!
+//! Maps and chunks carry a metadata layer of `Region`s beside their tiles.
+//! The world is cut into `REGION_SIZE` squares on a fixed grid, so a region
+//! spans several chunks and every chunk names the one it's in. Each square is
+//! a `Biome`: generators that shape their tiles by biome roll one per square
+//! with `Biome::at()`, and for the rest `survey()` reads it off the tiles
+//! (enough asteroids make a belt, enough gas a nebula cloud, walls all round
+//! a station sector). `survey()` then names the square by position hash and
+//! rates it from a sample of its tiles. The server surveys `/map` maps and
+//! chunks this way and clients their own local maps, so both sides name a
+//! place the same.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Tile, hash_position};
+
+/// Side length of a region's grid square, in tiles (two chunks)
+pub const REGION_SIZE: i32 = 128;
+
+/// The highest danger rating; 0 is safe
+pub const MAX_DANGER: u8 = 5;
+
+/// Keeps region names independent of the other uses of the seed
+const REGION_SALT: u32 = 0x5EC7_0A11;
+
+/// Keeps biome rolls independent of region names
+const BIOME_SALT: u32 = 0xB10E_5EED;
+
+/// Tiles between the samples `survey()` looks at, along each axis
+const SAMPLE_STEP: i32 = 4;
+
+const NAMES: [&str; 16] = [
+    "Kessler", "Marrow", "Orison", "Calloway", "Tithe", "Ashfall", "Perrin", "Halberd",
+    "Sable", "Wick", "Corran", "Ilex", "Vantage", "Morrow", "Gallant", "Thessaly",
+];
+
+const DANGER_NAMES: [&str; MAX_DANGER as usize + 1] = ["safe", "low", "moderate", "high", "severe", "extreme"];
+
+/// The sort of space a region is, which shapes its tiles and how it's drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Biome {
+    /// Open space with the odd rock formation
+    #[default]
+    DeepSpace,
+    AsteroidBelt,
+    NebulaCloud,
+    /// Broken rock and wreckage
+    DebrisField,
+    /// Built-up space around a station, or a station's insides
+    StationSector,
+    /// A biome from a newer server
+    #[serde(other)]
+    Unknown,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 5] = [Biome::DeepSpace, Biome::AsteroidBelt, Biome::NebulaCloud, Biome::DebrisField, Biome::StationSector];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Biome::DeepSpace => "deep space",
+            Biome::AsteroidBelt => "asteroid belt",
+            Biome::NebulaCloud => "nebula cloud",
+            Biome::DebrisField => "debris field",
+            Biome::StationSector => "station sector",
+            Biome::Unknown => "uncharted space",
+        }
+    }
+
+    /// The biome a generator gives grid square (rx, ry) for this seed; deep space is the most common
+    pub fn at(seed: u64, rx: i32, ry: i32) -> Biome {
+        match hash_position(rx, ry, (seed ^ (seed >> 32)) as u32 ^ BIOME_SALT) % 100 {
+            0..40 => Biome::DeepSpace,
+            40..60 => Biome::AsteroidBelt,
+            60..78 => Biome::NebulaCloud,
+            78..92 => Biome::DebrisField,
+            _ => Biome::StationSector,
+        }
+    }
+
+    /// Read off a sample of tiles, for maps that weren't generated by biome
+    fn classify(sample: &Sample) -> Biome {
+        let Sample { samples, rock, asteroids, gas, .. } = *sample;
+        if asteroids * 100 >= samples * 8 {
+            Biome::AsteroidBelt
+        } else if gas * 100 >= samples * 30 {
+            Biome::NebulaCloud
+        } else if rock * 100 >= samples * 40 {
+            Biome::StationSector
+        } else if rock * 100 >= samples * 10 || asteroids * 100 >= samples * 3 {
+            Biome::DebrisField
+        } else {
+            Biome::DeepSpace
+        }
+    }
+
+    /// Second words of region names, and the description they start with
+    fn words(self) -> (&'static [&'static str], &'static str) {
+        match self {
+            Biome::DeepSpace => (&["Expanse", "Reach", "Deep"], "Open space."),
+            Biome::AsteroidBelt => (&["Belt", "Field"], "An asteroid belt. Fly slow and watch for rocks."),
+            Biome::NebulaCloud => (&["Nebula", "Veil"], "A nebula. Sensors are short here, and ion storms gather."),
+            Biome::DebrisField => (&["Drift", "Scatter", "Graveyard"], "Broken rock and old wreckage, drifting in the dark."),
+            Biome::StationSector => (&["Sector", "Yards", "Works"], "Station space, built up and busy."),
+            Biome::Unknown => (&["Zone"], ""),
+        }
+    }
+
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            Biome::DeepSpace => 0,
+            Biome::AsteroidBelt => 1,
+            Biome::NebulaCloud => 2,
+            Biome::DebrisField => 3,
+            Biome::StationSector => 4,
+            Biome::Unknown => 255,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Biome {
+        match byte {
+            0 => Biome::DeepSpace,
+            1 => Biome::AsteroidBelt,
+            2 => Biome::NebulaCloud,
+            3 => Biome::DebrisField,
+            4 => Biome::StationSector,
+            _ => Biome::Unknown,
+        }
+    }
+}
+
+/// A named rectangle of the map, in world coordinates
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Deep space when a payload doesn't say
+    #[serde(default)]
+    pub biome: Biome,
+    /// 0 (safe) to `MAX_DANGER`
+    pub danger: u8,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+}
+
+impl Region {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && y >= self.y && ((x - self.x) as u32) < self.width && ((y - self.y) as u32) < self.height
+    }
+
+    /// The danger rating as a word; ratings from a newer server read as "extreme"
+    pub fn danger_name(&self) -> &'static str {
+        DANGER_NAMES[self.danger.min(MAX_DANGER) as usize]
+    }
+}
+
+/// The grid square holding a world position (works for negative positions)
+pub fn region_coords(x: i32, y: i32) -> (i32, i32) {
+    (x.div_euclid(REGION_SIZE), y.div_euclid(REGION_SIZE))
+}
+
+/// Tile counts from every `SAMPLE_STEP`th tile of an area
+#[derive(Default)]
+struct Sample {
+    samples: u32,
+    rock: u32,
+    asteroids: u32,
+    gas: u32,
+    riches: u32,
+}
+
+impl Sample {
+    fn take(x: i32, y: i32, width: u32, height: u32, tile: impl Fn(i32, i32) -> Option<Tile>) -> Sample {
+        let mut sample = Sample::default();
+        // Squares at the world's edge stop short of overflowing
+        for sy in (y..y.saturating_add(height as i32)).step_by(SAMPLE_STEP as usize) {
+            for sx in (x..x.saturating_add(width as i32)).step_by(SAMPLE_STEP as usize) {
+                let Some(tile) = tile(sx, sy) else { continue };
+                sample.samples += 1;
+                match tile {
+                    Tile::Wall | Tile::Unknown => sample.rock += 1,
+                    Tile::Asteroid => sample.asteroids += 1,
+                    Tile::OreAsteroid => {
+                        sample.asteroids += 1;
+                        sample.riches += 1;
+                    }
+                    Tile::Nebula => sample.gas += 1,
+                    Tile::CrystalField => {
+                        sample.gas += 1;
+                        sample.riches += 1;
+                    }
+                    Tile::Floor => {}
+                }
+            }
+        }
+        sample.samples = sample.samples.max(1);
+        sample
+    }
+}
+
+/// Name and rate the area `width` x `height` at (x, y), which lies inside one grid square, from
+/// every `SAMPLE_STEP`th tile; `tile` answers None off the map. `biome` is the one the generator
+/// gave the square, if it went by biome; otherwise it's read off the tiles
+pub fn survey(seed: u64, biome: Option<Biome>, x: i32, y: i32, width: u32, height: u32, tile: impl Fn(i32, i32) -> Option<Tile>) -> Region {
+    let sample = Sample::take(x, y, width, height, tile);
+    let biome = biome.unwrap_or_else(|| Biome::classify(&sample));
+    let Sample { samples, rock, asteroids, gas, riches } = sample;
+    let (kinds, description) = biome.words();
+    let description = if riches * 100 >= samples { format!("{} Rich pickings for miners.", description).trim_start().to_string() } else { description.to_string() };
+
+    let (rx, ry) = region_coords(x, y);
+    let roll = hash_position(rx, ry, (seed ^ (seed >> 32)) as u32 ^ REGION_SALT);
+    let name = format!("{} {}", NAMES[roll as usize % NAMES.len()], kinds[(roll >> 8) as usize % kinds.len()]);
+    let danger = ((asteroids * 4 + gas + rock) * 5 / samples).min(MAX_DANGER as u32) as u8;
+    Region { name, x, y, width, height, biome, danger, description }
+}
+
+/// The regions of a `width` x `height` map from (0, 0): its part of each grid square it covers.
+/// `biome` gives a grid square's biome when the generator chose one
+pub fn survey_map(
+    seed: u64,
+    width: usize,
+    height: usize,
+    biome: impl Fn(i32, i32) -> Option<Biome>,
+    tile: impl Fn(i32, i32) -> Option<Tile>,
+) -> Vec<Region> {
+    let (width, height) = (width as i32, height as i32);
+    let mut regions = Vec::new();
+    for y in (0..height).step_by(REGION_SIZE as usize) {
+        for x in (0..width).step_by(REGION_SIZE as usize) {
+            let (w, h) = ((width - x).min(REGION_SIZE) as u32, (height - y).min(REGION_SIZE) as u32);
+            let (rx, ry) = region_coords(x, y);
+            regions.push(survey(seed, biome(rx, ry), x, y, w, h, &tile));
+        }
+    }
+    regions
+}
+
+/// The region at (x, y), if any of `regions` covers it
+pub fn region_at(regions: &[Region], x: i32, y: i32) -> Option<&Region> {
+    regions.iter().find(|region| region.contains(x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Survey Tests ====================
+
+    #[test]
+    fn test_survey_names_what_it_sees() {
+        let belt = survey(1, None, 0, 0, 64, 64, |x, _| Some(if x % 32 == 0 { Tile::OreAsteroid } else { Tile::Floor }));
+        assert_eq!(belt.biome, Biome::AsteroidBelt);
+        assert!(belt.name.ends_with("Belt") || belt.name.ends_with("Field"), "{} is a belt", belt.name);
+        assert!(belt.description.contains("Rich pickings"), "Ore is worth a mention");
+        assert_eq!(belt.danger, 2);
+        assert_eq!(belt.danger_name(), "moderate");
+
+        let open = survey(1, None, 0, 0, 64, 64, |_, _| Some(Tile::Floor));
+        assert_eq!((open.biome, open.danger, open.description.as_str()), (Biome::DeepSpace, 0, "Open space."));
+        assert_eq!(open.name, survey(1, None, 64, 64, 64, 64, |_, _| Some(Tile::Floor)).name, "One grid square, one name");
+
+        let walls = survey(1, None, 0, 0, 16, 16, |_, _| Some(Tile::Wall));
+        assert_eq!(walls.biome, Biome::StationSector, "Walls all round are a station's insides");
+        assert_eq!(walls.danger, MAX_DANGER);
+
+        let cloud = survey(1, Some(Biome::NebulaCloud), 0, 0, 64, 64, |_, _| Some(Tile::Floor));
+        assert_eq!(cloud.biome, Biome::NebulaCloud, "The generator's biome wins over the tiles");
+        assert!(cloud.description.starts_with("A nebula"));
+    }
+
+    #[test]
+    fn test_biome_rolls() {
+        let rolls: Vec<Biome> = (0..400).map(|i| Biome::at(42, i % 20, i / 20)).collect();
+        assert_eq!(rolls, (0..400).map(|i| Biome::at(42, i % 20, i / 20)).collect::<Vec<_>>(), "Deterministic");
+        for biome in Biome::ALL {
+            assert!(rolls.contains(&biome), "{} turns up", biome.name());
+            assert_eq!(Biome::from_byte(biome.to_byte()), biome);
+        }
+        let deep = rolls.iter().filter(|&&biome| biome == Biome::DeepSpace).count();
+        assert!(deep > 100 && deep < 220, "Deep space is the most common, got {} of 400", deep);
+        assert_eq!(Biome::from_byte(9), Biome::Unknown);
+    }
+
+    #[test]
+    fn test_maps_are_split_into_regions() {
+        let regions = survey_map(7, 300, 100, |rx, _| (rx == 1).then_some(Biome::DebrisField), |_, _| Some(Tile::Floor));
+        assert_eq!(regions.iter().map(|region| region.biome).collect::<Vec<_>>(), [Biome::DeepSpace, Biome::DebrisField, Biome::DeepSpace]);
+        assert_eq!(regions.len(), 3);
+        assert_eq!((regions[2].x, regions[2].width, regions[2].height), (256, 44, 100), "Clipped to the map");
+        assert_eq!(region_at(&regions, 130, 50).map(|region| region.x), Some(128));
+        assert_eq!(region_at(&regions, 300, 50), None);
+        assert_eq!(region_coords(-1, 128), (-1, 1));
+    }
+}
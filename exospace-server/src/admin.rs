@@ -0,0 +1,169 @@
+//! Admin routes for running a live world.
+//!
+//! Every route needs the admin bearer token (`admin_rejection()`, set by
+//! `EXOSPACE_ADMIN_TOKEN`); without one they're all refused. Admins can set any world tile or clear it
+//! to open space, launch and remove NPC ships, put an announcement in every
+//! pilot's chat and kick a pilot. Changes go out the way players' do: tiles
+//! and ships in the next `sync` delta, announcements as `Ambient` chatter.
+//! A kicked session is sent `ServerMessage::Kicked` and then closed. Clearing
+//! the map cache, changing the tick rate and starting events live with what
+//! they manage (`mapcache`, `tick`, `events`).
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use exospace_core::{NpcBehavior, ServerMessage, Tile};
+use serde::Deserialize;
+
+use crate::AppState;
+use crate::auth;
+use crate::chat;
+use crate::error::{ApiError, ApiJson, ApiPath};
+use crate::world;
+
+/// Environment variable holding the bearer token for admin routes
+pub const ADMIN_TOKEN_ENV: &str = "EXOSPACE_ADMIN_TOKEN";
+
+/// Who announcements come from in pilots' chat
+pub const ANNOUNCEMENT_SOURCE: &str = "Server";
+
+/// Longest NPC ship name accepted
+const MAX_SHIP_NAME_LEN: usize = 32;
+
+/// Body of `POST /admin/tile`
+#[derive(Deserialize)]
+pub struct AdminTile {
+    x: i32,
+    y: i32,
+    /// Open space when left out
+    #[serde(default)]
+    tile: Option<Tile>,
+}
+
+/// Body of `POST /admin/npc`
+#[derive(Deserialize)]
+pub struct NpcLaunch {
+    name: String,
+    x: i32,
+    y: i32,
+    /// Wanders when left out
+    #[serde(default)]
+    behavior: Option<NpcBehavior>,
+}
+
+/// Body of `POST /admin/announce`
+#[derive(Deserialize)]
+pub struct Announcement {
+    text: String,
+}
+
+/// Body of `POST /admin/kick`
+#[derive(Deserialize)]
+pub struct Kick {
+    /// The pilot's name, ignoring case
+    name: String,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Why an admin route would turn a request away: admin routes are off, or the bearer token is missing or wrong
+pub fn admin_rejection(state: &AppState, headers: &HeaderMap) -> Option<Response> {
+    let Some(expected) = state.admin_token.as_deref() else {
+        return Some(ApiError::AdminDisabled.into_response());
+    };
+    if !auth::bearer_token(headers).is_some_and(|token| auth::constant_time_eq(token.as_bytes(), expected.as_bytes())) {
+        return Some(ApiError::AdminToken.into_response());
+    }
+    None
+}
+
+fn invalid(reason: impl Into<String>) -> Response {
+    ApiError::Invalid(reason.into()).into_response()
+}
+
+/// `POST /admin/tile`: set a world tile, or clear it to open space
+pub async fn set_tile(State(state): State<AppState>, headers: HeaderMap, ApiJson(edit): ApiJson<AdminTile>) -> Response {
+    if let Some(rejection) = admin_rejection(&state, &headers) {
+        return rejection;
+    }
+    if !world::in_world(edit.x, edit.y) {
+        return ApiError::OutOfWorld.into_response();
+    }
+    let tile = edit.tile.unwrap_or(Tile::Floor);
+    if tile == Tile::Unknown {
+        return invalid("Unknown tile kind");
+    }
+    match state.world.set_tile(edit.x, edit.y, tile) {
+        Ok(()) => {
+            state.sessions.tile_changed(edit.x, edit.y, tile);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => ApiError::Storage(e).into_response(),
+    }
+}
+
+/// `POST /admin/npc`: launch a ship from the open tile nearest (x, y); replies with it as JSON
+pub async fn spawn_npc(State(state): State<AppState>, headers: HeaderMap, ApiJson(launch): ApiJson<NpcLaunch>) -> Response {
+    if let Some(rejection) = admin_rejection(&state, &headers) {
+        return rejection;
+    }
+    let name = launch.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_SHIP_NAME_LEN || name.chars().any(char::is_control) {
+        return invalid(format!("Ship names are 1-{} printable characters", MAX_SHIP_NAME_LEN));
+    }
+    if !world::in_world(launch.x, launch.y) {
+        return ApiError::OutOfWorld.into_response();
+    }
+    let behavior = match launch.behavior {
+        None => NpcBehavior::Wander,
+        Some(NpcBehavior::Unknown) => return invalid("Unknown behavior (expected patrol, wander or flee)"),
+        Some(behavior) => behavior,
+    };
+    let ship = state.npcs.launch(&state.world, name, behavior, (launch.x, launch.y));
+    state.sessions.place_npc(&ship);
+    (StatusCode::CREATED, Json(ship)).into_response()
+}
+
+/// `DELETE /admin/npc/{id}`: take a ship out of the world
+pub async fn despawn_npc(State(state): State<AppState>, headers: HeaderMap, ApiPath(id): ApiPath<u64>) -> Response {
+    if let Some(rejection) = admin_rejection(&state, &headers) {
+        return rejection;
+    }
+    match state.npcs.remove(id) {
+        Some(_) => {
+            state.sessions.remove_npc(id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        None => ApiError::NotFound(format!("No NPC ship {}", id)).into_response(),
+    }
+}
+
+/// `POST /admin/announce`: a line in every pilot's chat
+pub async fn announce(State(state): State<AppState>, headers: HeaderMap, ApiJson(announcement): ApiJson<Announcement>) -> Response {
+    if let Some(rejection) = admin_rejection(&state, &headers) {
+        return rejection;
+    }
+    match chat::validate_message(&announcement.text) {
+        Ok(text) => {
+            state.sessions.announce(ServerMessage::Ambient { source: ANNOUNCEMENT_SOURCE.to_string(), text });
+            format!("Announced to {} pilots", state.sessions.len()).into_response()
+        }
+        Err(reason) => invalid(reason),
+    }
+}
+
+/// `POST /admin/kick`: close a pilot's session, telling them why
+pub async fn kick(State(state): State<AppState>, headers: HeaderMap, ApiJson(kick): ApiJson<Kick>) -> Response {
+    if let Some(rejection) = admin_rejection(&state, &headers) {
+        return rejection;
+    }
+    let Some(player) = state.sessions.find_by_name(kick.name.trim()) else {
+        return ApiError::NotFound(format!("No pilot named '{}' is online", kick.name.trim())).into_response();
+    };
+    let reason = kick.reason.as_deref().map(str::trim).filter(|r| !r.is_empty()).unwrap_or("Kicked by an admin");
+    state.sessions.kick(player.id, reason);
+    format!("Kicked {}", player.name).into_response()
+}
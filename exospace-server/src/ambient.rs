@@ -0,0 +1,268 @@
+//! Ambient chatter: station traffic control, news relays and the like.
+//!
+//! Each source sits at a point of interest and occasionally sends a canned
+//! line to the players within its radius, so busy parts of the world feel
+//! alive even when nobody is talking. Sources come from a JSON file named by
+//! `EXOSPACE_AMBIENT`, or the built-in set below.
+
+use exospace_core::ServerMessage;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::sessions::SessionRegistry;
+use crate::tick::System;
+use crate::utility::DiceRng;
+
+/// Environment variable naming a JSON file that replaces the built-in ambient config
+pub const AMBIENT_CONFIG_ENV: &str = "EXOSPACE_AMBIENT";
+
+/// When and what the ambient sources say
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AmbientConfig {
+    /// Seconds between rounds of chatter; 0 turns chatter off
+    pub interval_secs: u64,
+    /// Chance, in percent, that a source with listeners speaks in a given round
+    pub chance_percent: u32,
+    pub sources: Vec<AmbientSource>,
+}
+
+/// A point of interest that talks to nearby players
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct AmbientSource {
+    /// Shown as the speaker, e.g. "Haven Traffic Control"
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    /// Players within this many tiles hear it
+    pub radius: u32,
+    pub lines: Vec<String>,
+}
+
+impl Default for AmbientConfig {
+    fn default() -> Self {
+        let source = |name: &str, x, y, radius, lines: &[&str]| AmbientSource {
+            name: name.to_string(),
+            x,
+            y,
+            radius,
+            lines: lines.iter().map(|l| l.to_string()).collect(),
+        };
+        AmbientConfig {
+            interval_secs: 45,
+            chance_percent: 50,
+            sources: vec![
+                source("Haven Traffic Control", 0, 0, 40, &[
+                    "Outbound traffic, keep to the eastern lanes.",
+                    "Docking ring three is closed for maintenance.",
+                    "Reminder: no weapons hot inside the beacon perimeter.",
+                    "Mining tender Corvid, you are cleared for departure.",
+                ]),
+                source("Frontier News Relay", 160, -96, 120, &[
+                    "Ore prices steady as the Drift co-op reports record yields.",
+                    "Survey teams report unusual nebula activity to the north.",
+                    "Haven council votes to expand the outer docking ring.",
+                ]),
+                source("Drift Mining Co-op", -200, 140, 60, &[
+                    "Co-op channel open. Watch your hulls in the field.",
+                    "Rock crews, shift change in ten minutes.",
+                    "Anyone seen the survey drone? It stopped reporting.",
+                ]),
+            ],
+        }
+    }
+}
+
+impl AmbientConfig {
+    /// The config from `EXOSPACE_AMBIENT` if set and valid, otherwise the defaults
+    pub fn load() -> Self {
+        let Ok(path) = std::env::var(AMBIENT_CONFIG_ENV) else {
+            return Self::default();
+        };
+        std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to load ambient config from {}: {}", path, e);
+                Self::default()
+            })
+    }
+}
+
+/// Picks who says what each round
+pub struct Ambient {
+    config: AmbientConfig,
+    rng: DiceRng,
+    /// Last line each source used, so nobody repeats themselves back to back
+    last_line: Vec<Option<usize>>,
+}
+
+impl Ambient {
+    pub fn new(config: AmbientConfig, rng: DiceRng) -> Self {
+        let last_line = vec![None; config.sources.len()];
+        Ambient { config, rng, last_line }
+    }
+
+    /// One round of chatter; returns how many messages were sent
+    pub fn tick(&mut self, sessions: &SessionRegistry) -> usize {
+        let mut sent = 0;
+        for index in 0..self.config.sources.len() {
+            let source = &self.config.sources[index];
+            if source.lines.is_empty() {
+                continue;
+            }
+            let listeners = sessions.near(source.x, source.y, source.radius);
+            if listeners.is_empty() || self.rng.roll_die(100) > self.config.chance_percent {
+                continue;
+            }
+
+            let line = self.pick_line(index);
+            let source = &self.config.sources[index];
+            for player in listeners {
+                let message = ServerMessage::Ambient { source: source.name.clone(), text: source.lines[line].clone() };
+                sessions.send_to(player.id, message);
+                sent += 1;
+            }
+        }
+        sent
+    }
+
+    fn pick_line(&mut self, index: usize) -> usize {
+        let count = self.config.sources[index].lines.len();
+        let mut line = self.rng.roll_die(count as u32) as usize - 1;
+        if count > 1 && self.last_line[index] == Some(line) {
+            line = (line + 1) % count;
+        }
+        self.last_line[index] = Some(line);
+        line
+    }
+}
+
+/// Chatter as a deferrable simulation system: a round every `interval_secs`
+pub struct AmbientSystem {
+    ambient: Ambient,
+    sessions: Arc<SessionRegistry>,
+    interval: Duration,
+    next_round: Instant,
+}
+
+impl AmbientSystem {
+    /// None when chatter is turned off
+    pub fn new(config: AmbientConfig, sessions: Arc<SessionRegistry>, now: Instant) -> Option<Self> {
+        if config.interval_secs == 0 {
+            return None;
+        }
+        let interval = Duration::from_secs(config.interval_secs);
+        let ambient = Ambient::new(config, DiceRng::for_session(0));
+        // Nobody is connected yet, so the first round waits a full interval
+        Some(AmbientSystem { ambient, sessions, interval, next_round: now + interval })
+    }
+}
+
+impl System for AmbientSystem {
+    fn name(&self) -> &'static str {
+        "ambient"
+    }
+
+    fn tick(&mut self, now: Instant) {
+        if now < self.next_round {
+            return;
+        }
+        self.next_round = now + self.interval;
+        self.ambient.tick(&self.sessions);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::Direction;
+
+    fn one_source(chance_percent: u32, lines: &[&str]) -> AmbientConfig {
+        AmbientConfig {
+            interval_secs: 1,
+            chance_percent,
+            sources: vec![AmbientSource {
+                name: "Test Control".to_string(),
+                x: 0,
+                y: 0,
+                radius: 10,
+                lines: lines.iter().map(|l| l.to_string()).collect(),
+            }],
+        }
+    }
+
+    // ==================== Config Tests ====================
+
+    #[test]
+    fn test_default_config_is_usable() {
+        let config = AmbientConfig::default();
+        assert!(config.interval_secs > 0, "Chatter is on by default");
+        assert!(!config.sources.is_empty());
+        assert!(config.sources.iter().all(|s| !s.lines.is_empty() && s.radius > 0));
+    }
+
+    #[test]
+    fn test_config_from_json() {
+        let json = r#"{"interval_secs":0,"sources":[{"name":"Beacon","x":5,"y":-5,"radius":20,"lines":["hi"]}]}"#;
+        let config: AmbientConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.interval_secs, 0);
+        assert_eq!(config.chance_percent, AmbientConfig::default().chance_percent, "Missing fields use defaults");
+        assert_eq!(config.sources[0].name, "Beacon");
+    }
+
+    // ==================== Chatter Tests ====================
+
+    #[test]
+    fn test_tick_reaches_only_nearby_players() {
+        let sessions = SessionRegistry::default();
+        let (near, mut rx) = sessions.join("Near");
+        let (far, _) = sessions.join("Far");
+        sessions.update_position(far.id, 500, 500, Direction::Up);
+        while rx.try_recv().is_ok() {}
+
+        let mut ambient = Ambient::new(one_source(100, &["Lane clear"]), DiceRng::new(1));
+        assert_eq!(ambient.tick(&sessions), 1);
+
+        let msg = rx.try_recv().unwrap();
+        assert!(msg.is_for(near.id) && !msg.is_for(far.id));
+        assert_eq!(msg.message, ServerMessage::Ambient { source: "Test Control".to_string(), text: "Lane clear".to_string() });
+    }
+
+    #[test]
+    fn test_tick_silent_without_listeners_or_chance() {
+        let sessions = SessionRegistry::default();
+        let mut ambient = Ambient::new(one_source(100, &["Hello?"]), DiceRng::new(1));
+        assert_eq!(ambient.tick(&sessions), 0, "Nobody online, nobody to talk to");
+
+        sessions.join("Ace");
+        let mut muted = Ambient::new(one_source(0, &["Hello?"]), DiceRng::new(1));
+        assert_eq!(muted.tick(&sessions), 0, "0% chance never speaks");
+    }
+
+    #[test]
+    fn test_system_speaks_once_per_interval() {
+        let sessions = Arc::new(SessionRegistry::default());
+        let (_, mut rx) = sessions.join("Ace");
+        while rx.try_recv().is_ok() {}
+
+        let start = Instant::now();
+        assert!(AmbientSystem::new(AmbientConfig { interval_secs: 0, ..one_source(100, &["x"]) }, sessions.clone(), start).is_none());
+        let mut system = AmbientSystem::new(one_source(100, &["Lane clear"]), sessions.clone(), start).unwrap();
+
+        system.tick(start + Duration::from_millis(500));
+        assert!(rx.try_recv().is_err(), "The first round waits a full interval");
+        system.tick(start + Duration::from_secs(1));
+        assert!(rx.try_recv().is_ok());
+        system.tick(start + Duration::from_millis(1500));
+        assert!(rx.try_recv().is_err(), "Only one round per interval");
+    }
+
+    #[test]
+    fn test_lines_do_not_repeat_back_to_back() {
+        let mut ambient = Ambient::new(one_source(100, &["a", "b"]), DiceRng::new(3));
+        let picks: Vec<usize> = (0..20).map(|_| ambient.pick_line(0)).collect();
+        assert!(picks.windows(2).all(|w| w[0] != w[1]), "Got {:?}", picks);
+    }
+}
@@ -0,0 +1,411 @@
+//! Player accounts: registration, login and bearer tokens.
+//!
+//! Passwords are kept as salted PBKDF2-SHA256 hashes. Registering or logging
+//! in hands out an opaque random token that clients send back as
+//! `Authorization: Bearer <token>`. Tokens live in memory, so restarting the
+//! server logs everyone out; accounts are saved to the JSON file named by
+//! `EXOSPACE_USERS`, or kept in memory only when it isn't set.
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use exospace_core::{AccountInfo, AuthToken, Credentials};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+use crate::error::{ApiError, ApiJson};
+
+/// Environment variable naming the accounts file
+pub const USERS_FILE_ENV: &str = "EXOSPACE_USERS";
+
+/// PBKDF2 rounds for new password hashes; stored per account so it can be raised later
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+/// How long a token stays valid after login
+const TOKEN_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+const MIN_NAME_LEN: usize = 3;
+const MAX_NAME_LEN: usize = 16;
+const MIN_PASSWORD_LEN: usize = 8;
+const MAX_PASSWORD_LEN: usize = 128;
+
+/// Why a request was refused
+#[derive(Debug, PartialEq)]
+pub enum AuthError {
+    InvalidName(String),
+    InvalidPassword(String),
+    NameTaken,
+    /// Wrong name or password; deliberately doesn't say which
+    BadCredentials,
+    /// Missing, unknown or expired token
+    BadToken,
+    Storage(String),
+}
+
+impl From<AuthError> for ApiError {
+    fn from(error: AuthError) -> Self {
+        match error {
+            AuthError::InvalidName(reason) | AuthError::InvalidPassword(reason) => ApiError::Invalid(reason),
+            AuthError::NameTaken => ApiError::NameTaken,
+            AuthError::BadCredentials => ApiError::BadCredentials,
+            AuthError::BadToken => ApiError::BadToken,
+            AuthError::Storage(reason) => ApiError::Storage(reason),
+        }
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// A stored account; the password itself is never kept
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Account {
+    name: String,
+    /// Hex-encoded random salt
+    salt: String,
+    /// Hex-encoded PBKDF2-SHA256 output
+    hash: String,
+    rounds: u32,
+}
+
+struct Session {
+    name: String,
+    expires: Instant,
+}
+
+/// Registered accounts and the tokens handed out for them
+pub struct Users {
+    /// Keyed by lowercased name, so "Ace" and "ace" are the same account
+    accounts: RwLock<HashMap<String, Account>>,
+    tokens: RwLock<HashMap<String, Session>>,
+    /// Where accounts are saved; None keeps them in memory only
+    path: Option<PathBuf>,
+    rounds: u32,
+}
+
+impl Default for Users {
+    fn default() -> Self {
+        Users {
+            accounts: RwLock::new(HashMap::new()),
+            tokens: RwLock::new(HashMap::new()),
+            path: None,
+            rounds: PBKDF2_ROUNDS,
+        }
+    }
+}
+
+impl Users {
+    /// In-memory accounts with cheap hashing, so tests stay fast
+    #[cfg(test)]
+    pub fn with_rounds(rounds: u32) -> Self {
+        Users { rounds, ..Self::default() }
+    }
+
+    /// Accounts from `EXOSPACE_USERS` if set (created on first registration), otherwise in memory only
+    pub fn load() -> Self {
+        let Ok(path) = std::env::var(USERS_FILE_ENV) else {
+            return Self::default();
+        };
+        Self::load_from(PathBuf::from(&path)).unwrap_or_else(|e| {
+            tracing::warn!("Failed to load accounts from {}: {}", path, e);
+            Self::default()
+        })
+    }
+
+    fn load_from(path: PathBuf) -> Result<Self, String> {
+        let accounts: Vec<Account> = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.to_string()),
+        };
+        let accounts = accounts.into_iter().map(|a| (a.name.to_lowercase(), a)).collect();
+        Ok(Users { accounts: RwLock::new(accounts), path: Some(path), ..Self::default() })
+    }
+
+    /// Write out the accounts; called with the write lock held, so saves go in the order the changes were made.
+    /// The file is replaced through a temporary one, so a crash mid-write leaves the old accounts
+    fn save(&self, accounts: &HashMap<String, Account>) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut accounts: Vec<&Account> = accounts.values().collect();
+        accounts.sort_by(|a, b| a.name.cmp(&b.name));
+        let json = serde_json::to_string_pretty(&accounts).map_err(|e| e.to_string())?;
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, json)
+            .and_then(|()| std::fs::rename(&temporary, path))
+            .map_err(|e| format!("Failed to save accounts: {}", e))
+    }
+
+    /// Whether a name belongs to an account (case-insensitive)
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.accounts.read().unwrap().contains_key(&name.to_lowercase())
+    }
+
+    /// An account's name as registered, e.g. "Ace" for "ace"
+    pub fn display_name(&self, name: &str) -> Option<String> {
+        self.accounts.read().unwrap().get(&name.to_lowercase()).map(|account| account.name.clone())
+    }
+
+    /// Create an account and log it in
+    pub fn register(&self, credentials: &Credentials) -> Result<AuthToken, AuthError> {
+        let name = validate_name(&credentials.name)?;
+        validate_password(&credentials.password)?;
+
+        let salt = random_hex(16);
+        let account = Account {
+            name: name.to_string(),
+            hash: hash_password(&credentials.password, &salt, self.rounds),
+            salt,
+            rounds: self.rounds,
+        };
+        {
+            let mut accounts = self.accounts.write().unwrap();
+            let key = name.to_lowercase();
+            if accounts.contains_key(&key) {
+                return Err(AuthError::NameTaken);
+            }
+            accounts.insert(key.clone(), account);
+            if let Err(e) = self.save(&accounts) {
+                // Don't hand out an account that would vanish on restart
+                accounts.remove(&key);
+                return Err(AuthError::Storage(e));
+            }
+        }
+        Ok(self.issue_token(name))
+    }
+
+    /// Check a name and password and hand out a fresh token
+    pub fn login(&self, credentials: &Credentials) -> Result<AuthToken, AuthError> {
+        let account = self.accounts.read().unwrap().get(&credentials.name.trim().to_lowercase()).cloned();
+        let Some(account) = account else {
+            return Err(AuthError::BadCredentials);
+        };
+        let hash = hash_password(&credentials.password, &account.salt, account.rounds);
+        if !constant_time_eq(hash.as_bytes(), account.hash.as_bytes()) {
+            return Err(AuthError::BadCredentials);
+        }
+        Ok(self.issue_token(&account.name))
+    }
+
+    /// The account name a token was issued for, if it is still valid
+    pub fn verify(&self, token: &str) -> Option<String> {
+        let now = Instant::now();
+        let mut tokens = self.tokens.write().unwrap();
+        match tokens.get(token) {
+            Some(session) if session.expires > now => Some(session.name.clone()),
+            Some(_) => {
+                tokens.remove(token);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn issue_token(&self, name: &str) -> AuthToken {
+        let token = random_hex(32);
+        let now = Instant::now();
+        let mut tokens = self.tokens.write().unwrap();
+        tokens.retain(|_, session| session.expires > now);
+        tokens.insert(token.clone(), Session { name: name.to_string(), expires: now + TOKEN_TTL });
+        AuthToken { name: name.to_string(), token }
+    }
+}
+
+/// Account names are 3-16 letters, digits, '-' or '_'; returns the trimmed name
+fn validate_name(name: &str) -> Result<&str, AuthError> {
+    let name = name.trim();
+    if !(MIN_NAME_LEN..=MAX_NAME_LEN).contains(&name.len()) {
+        let reason = format!("Names must be {}-{} characters", MIN_NAME_LEN, MAX_NAME_LEN);
+        return Err(AuthError::InvalidName(reason));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(AuthError::InvalidName("Names may only use letters, digits, '-' and '_'".to_string()));
+    }
+    Ok(name)
+}
+
+fn validate_password(password: &str) -> Result<(), AuthError> {
+    let len = password.chars().count();
+    if !(MIN_PASSWORD_LEN..=MAX_PASSWORD_LEN).contains(&len) {
+        let reason = format!("Passwords must be {}-{} characters", MIN_PASSWORD_LEN, MAX_PASSWORD_LEN);
+        return Err(AuthError::InvalidPassword(reason));
+    }
+    Ok(())
+}
+
+fn hash_password(password: &str, salt: &str, rounds: u32) -> String {
+    let mut out = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(password.as_bytes(), salt.as_bytes(), rounds, &mut out);
+    to_hex(&out)
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    getrandom::fill(&mut buf).expect("OS random number generator unavailable");
+    to_hex(&buf)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Compare without bailing at the first difference, so timing doesn't leak how much matched
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The token from an `Authorization: Bearer <token>` header
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// The account behind an optional bearer token: None without one, refused if it's bad
+pub fn optional_account(users: &Users, headers: &HeaderMap) -> Result<Option<String>, AuthError> {
+    match bearer_token(headers) {
+        Some(token) => users.verify(token).map(Some).ok_or(AuthError::BadToken),
+        None => Ok(None),
+    }
+}
+
+/// Handler for `POST /register`
+pub async fn register(State(state): State<AppState>, ApiJson(credentials): ApiJson<Credentials>) -> Response {
+    // Hashing is deliberately slow, so keep it off the async workers
+    let result = tokio::task::spawn_blocking(move || state.users.register(&credentials)).await;
+    match result {
+        Ok(Ok(token)) => (StatusCode::CREATED, Json(token)).into_response(),
+        Ok(Err(e)) => e.into_response(),
+        Err(e) => AuthError::Storage(e.to_string()).into_response(),
+    }
+}
+
+/// Handler for `POST /login`
+pub async fn login(State(state): State<AppState>, ApiJson(credentials): ApiJson<Credentials>) -> Response {
+    let result = tokio::task::spawn_blocking(move || state.users.login(&credentials)).await;
+    match result {
+        Ok(Ok(token)) => Json(token).into_response(),
+        Ok(Err(e)) => e.into_response(),
+        Err(e) => AuthError::Storage(e.to_string()).into_response(),
+    }
+}
+
+/// Handler for `GET /me`: who the bearer token belongs to
+pub async fn me(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    match bearer_token(&headers).and_then(|token| state.users.verify(token)) {
+        Some(name) => Json(AccountInfo { name }).into_response(),
+        None => AuthError::BadToken.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_users() -> Users {
+        Users::with_rounds(10)
+    }
+
+    fn credentials(name: &str, password: &str) -> Credentials {
+        Credentials { name: name.to_string(), password: password.to_string() }
+    }
+
+    #[test]
+    fn test_register_then_login() {
+        let users = test_users();
+        let registered = users.register(&credentials(" Ace ", "hunter22")).unwrap();
+        assert_eq!(registered.name, "Ace", "Name is trimmed");
+        assert_eq!(users.verify(&registered.token), Some("Ace".to_string()));
+
+        let logged_in = users.login(&credentials("ace", "hunter22")).unwrap();
+        assert_eq!(logged_in.name, "Ace", "Login is case-insensitive and returns the registered spelling");
+        assert_ne!(logged_in.token, registered.token, "Each login gets a fresh token");
+        assert!(users.is_registered("ACE"));
+    }
+
+    #[test]
+    fn test_register_rejects_duplicates_and_bad_input() {
+        let users = test_users();
+        users.register(&credentials("Ace", "hunter22")).unwrap();
+        assert_eq!(users.register(&credentials("ACE", "other-pass")), Err(AuthError::NameTaken));
+        assert!(matches!(users.register(&credentials("Al", "hunter22")), Err(AuthError::InvalidName(_))));
+        assert!(matches!(users.register(&credentials("Ace Pilot", "hunter22")), Err(AuthError::InvalidName(_))));
+        assert!(matches!(users.register(&credentials("Bob", "short")), Err(AuthError::InvalidPassword(_))));
+        assert!(!users.is_registered("Bob"));
+        assert_eq!(users.display_name("ace"), Some("Ace".to_string()), "Names keep the case they were registered with");
+    }
+
+    #[test]
+    fn test_login_rejects_wrong_password_and_unknown_name() {
+        let users = test_users();
+        users.register(&credentials("Ace", "hunter22")).unwrap();
+        assert_eq!(users.login(&credentials("Ace", "hunter23")), Err(AuthError::BadCredentials));
+        assert_eq!(users.login(&credentials("Nobody", "hunter22")), Err(AuthError::BadCredentials));
+    }
+
+    #[test]
+    fn test_expired_tokens_are_dropped() {
+        let users = test_users();
+        let token = users.register(&credentials("Ace", "hunter22")).unwrap().token;
+        users.tokens.write().unwrap().get_mut(&token).unwrap().expires = Instant::now();
+        assert_eq!(users.verify(&token), None);
+        assert!(users.tokens.read().unwrap().is_empty(), "Expired token is forgotten");
+        assert_eq!(users.verify("not-a-token"), None);
+    }
+
+    #[test]
+    fn test_passwords_are_salted_hashes() {
+        let users = test_users();
+        users.register(&credentials("Ace", "hunter22")).unwrap();
+        users.register(&credentials("Bob", "hunter22")).unwrap();
+        let accounts = users.accounts.read().unwrap();
+        let (ace, bob) = (&accounts["ace"], &accounts["bob"]);
+        assert!(!ace.hash.contains("hunter22"));
+        assert_ne!(ace.hash, bob.hash, "Same password, different salt, different hash");
+    }
+
+    #[test]
+    fn test_accounts_persist_to_file() {
+        let path = std::env::temp_dir().join(format!("exospace-users-test-{}.json", std::process::id()));
+        let users = Users { rounds: 10, ..Users::load_from(path.clone()).unwrap() };
+        std::thread::scope(|scope| {
+            for name in ["Ace", "Bob", "Cat", "Dee"] {
+                let users = &users;
+                scope.spawn(move || users.register(&credentials(name, "hunter22")).unwrap());
+            }
+        });
+
+        let reloaded = Users::load_from(path.clone()).unwrap();
+        for name in ["Ace", "Bob", "Cat", "Dee"] {
+            assert!(reloaded.login(&credentials(name, "hunter22")).is_ok(), "{} survives a restart", name);
+        }
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        assert!(!PathBuf::from(temporary).exists(), "The temporary file is renamed into place");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_bearer_token_parsing() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(bearer_token(&headers), None);
+        headers.insert(header::AUTHORIZATION, "Bearer abc123".parse().unwrap());
+        assert_eq!(bearer_token(&headers), Some("abc123"));
+        headers.insert(header::AUTHORIZATION, "Basic abc123".parse().unwrap());
+        assert_eq!(bearer_token(&headers), None);
+    }
+}
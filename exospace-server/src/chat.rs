@@ -0,0 +1,112 @@
+//! Chat relay rules: message validation and per-session rate limiting.
+//!
+//! Relaying itself goes through the session registry's broadcast channel;
+//! this module only decides whether a line is allowed through.
+
+use exospace_core::MAX_CHAT_LEN;
+use std::time::Instant;
+
+/// Messages a session may send in a quick burst
+const CHAT_BURST: f32 = 5.0;
+
+/// Messages per second regained after a burst
+const CHAT_REFILL_PER_SEC: f32 = 1.0;
+
+/// Token bucket limiting how fast one session can chat
+pub struct ChatRateLimiter {
+    tokens: f32,
+    last_refill: Instant,
+}
+
+impl ChatRateLimiter {
+    pub fn new(now: Instant) -> Self {
+        ChatRateLimiter { tokens: CHAT_BURST, last_refill: now }
+    }
+
+    /// Spend a token if one is available at `now`
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f32();
+        self.tokens = (self.tokens + elapsed * CHAT_REFILL_PER_SEC).min(CHAT_BURST);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Clean up a chat line, or explain why it can't be relayed
+pub fn validate_message(text: &str) -> Result<String, String> {
+    let cleaned: String = text.chars().filter(|c| !c.is_control()).collect();
+    let cleaned = cleaned.trim();
+
+    if cleaned.is_empty() {
+        return Err("Message is empty".to_string());
+    }
+    if cleaned.chars().count() > MAX_CHAT_LEN {
+        return Err(format!("Message too long (max {} characters)", MAX_CHAT_LEN));
+    }
+    Ok(cleaned.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // ==================== Rate Limiter Tests ====================
+
+    #[test]
+    fn test_rate_limiter_allows_burst() {
+        let start = Instant::now();
+        let mut limiter = ChatRateLimiter::new(start);
+        for i in 0..CHAT_BURST as usize {
+            assert!(limiter.try_acquire(start), "Message {} of the burst should pass", i);
+        }
+        assert!(!limiter.try_acquire(start), "Message past the burst should be limited");
+    }
+
+    #[test]
+    fn test_rate_limiter_refills_over_time() {
+        let start = Instant::now();
+        let mut limiter = ChatRateLimiter::new(start);
+        while limiter.try_acquire(start) {}
+
+        assert!(!limiter.try_acquire(start + Duration::from_millis(500)));
+        assert!(limiter.try_acquire(start + Duration::from_millis(1600)), "A token should refill after a second");
+    }
+
+    #[test]
+    fn test_rate_limiter_caps_at_burst() {
+        let start = Instant::now();
+        let mut limiter = ChatRateLimiter::new(start);
+        let later = start + Duration::from_secs(3600);
+        let allowed = (0..100).filter(|_| limiter.try_acquire(later)).count();
+        assert_eq!(allowed, CHAT_BURST as usize, "Idle time shouldn't bank more than one burst");
+    }
+
+    // ==================== Validation Tests ====================
+
+    #[test]
+    fn test_validate_message_trims() {
+        assert_eq!(validate_message("  hi there \n"), Ok("hi there".to_string()));
+    }
+
+    #[test]
+    fn test_validate_message_rejects_empty() {
+        assert!(validate_message("").is_err());
+        assert!(validate_message("   ").is_err());
+        assert!(validate_message("\u{7}\u{1b}").is_err());
+    }
+
+    #[test]
+    fn test_validate_message_length_limit() {
+        assert!(validate_message(&"x".repeat(MAX_CHAT_LEN)).is_ok());
+        assert!(validate_message(&"x".repeat(MAX_CHAT_LEN + 1)).is_err());
+        // Counted in characters, not bytes
+        assert!(validate_message(&"é".repeat(MAX_CHAT_LEN)).is_ok());
+    }
+}
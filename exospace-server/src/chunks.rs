@@ -0,0 +1,406 @@
+//! Chunked world generation and the `/map/chunk` endpoint.
+//!
+//! Unlike `MapGenerator`, which carves a bounded map with a sequential RNG,
+//! every tile here is a pure function of its world position and the seed.
+//! Chunks can therefore be generated in any order and always line up at
+//! their edges, which is what lets clients stream an unbounded world.
+//! Stored tile edits are layered on top by `world::World::chunk`. About one
+//! chunk in four also holds a derelict or a beacon (`pois.rs`), a star
+//! system's chunks carry its jump gates (`systems.rs`), and every chunk names
+//! the region it lies in (`exospace_core::regions`). Each region's grid
+//! square rolls a `Biome` that sets how much rock, asteroid and nebula it
+//! holds (`TileMix`); deep space keeps the world's original mix, and Haven's
+//! square is always a station sector.
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use exospace_core::regions::{self, Biome, REGION_SIZE, Region};
+use exospace_core::{
+    CHUNK_SIZE, ChunkData, MAX_CHUNK_COORD, Poi, PoiKind, SPAWN_POSITION, Tile, WORLD_VERSION_HEADER, chunk_coords, hash_position,
+};
+use serde::Deserialize;
+
+use crate::error::{ApiError, ApiQuery};
+use crate::etag;
+use crate::mapgen::with_resources;
+use crate::pois::{self, POI_SALT};
+use crate::{AppState, binary_response, wants_binary};
+
+/// Tiles around the spawn point that are always open space
+const SPAWN_CLEARANCE: i32 = 6;
+
+/// One chunk in this many holds a derelict or a beacon
+const CHUNK_POI_ODDS: u32 = 4;
+
+/// Spots tried for a chunk's point of interest before giving up on a rocky chunk
+const POI_ATTEMPTS: u32 = 8;
+
+/// How a biome spreads its tiles: the noise each kind has to beat, so lower means more of it
+struct TileMix {
+    /// Rock formations, on coarse noise
+    rock: f32,
+    /// Asteroid fields, on medium noise
+    asteroids: f32,
+    /// One tile in this many inside a field is an asteroid
+    asteroid_odds: u32,
+    /// Nebulae, on broad noise
+    nebula: f32,
+}
+
+impl TileMix {
+    fn of(biome: Biome) -> TileMix {
+        match biome {
+            Biome::AsteroidBelt => TileMix { rock: 0.86, asteroids: 0.5, asteroid_odds: 2, nebula: 0.78 },
+            Biome::NebulaCloud => TileMix { rock: 0.88, asteroids: 0.8, asteroid_odds: 3, nebula: 0.42 },
+            Biome::DebrisField => TileMix { rock: 0.72, asteroids: 0.6, asteroid_odds: 5, nebula: 0.8 },
+            // Lanes kept clear for traffic
+            Biome::StationSector => TileMix { rock: 0.92, asteroids: 0.85, asteroid_odds: 4, nebula: 0.85 },
+            Biome::DeepSpace | Biome::Unknown => TileMix { rock: 0.8, asteroids: 0.72, asteroid_odds: 3, nebula: 0.68 },
+        }
+    }
+}
+
+/// Query parameters for chunk requests
+#[derive(Deserialize)]
+pub struct ChunkQuery {
+    cx: i32,
+    cy: i32,
+    /// Defaults to the world's seed, the only one with persistent edits
+    #[serde(default)]
+    seed: Option<u64>,
+    /// "bin" for the compact binary encoding; JSON otherwise
+    #[serde(default)]
+    format: Option<String>,
+}
+
+/// Position-hashed world generator
+pub struct ChunkGenerator {
+    seed: u32,
+}
+
+impl ChunkGenerator {
+    pub fn new(seed: u64) -> Self {
+        // Fold the high bits in so large seeds still differ
+        ChunkGenerator { seed: (seed ^ (seed >> 32)) as u32 }
+    }
+
+    /// Tile at a world position
+    pub fn tile_at(&self, x: i32, y: i32) -> Tile {
+        let (spawn_x, spawn_y) = SPAWN_POSITION;
+        if (x - spawn_x).abs() <= SPAWN_CLEARANCE && (y - spawn_y).abs() <= SPAWN_CLEARANCE {
+            return Tile::Floor;
+        }
+
+        let mix = TileMix::of(self.biome(x, y));
+
+        // Rock formations: peaks of coarse noise
+        if value_noise(x, y, 18, self.seed) > mix.rock {
+            return Tile::Wall;
+        }
+
+        // Asteroid fields: sparse rocks inside medium-scale regions
+        if value_noise(x, y, 12, self.seed.wrapping_add(1)) > mix.asteroids
+            && hash_position(x, y, self.seed.wrapping_add(2)).is_multiple_of(mix.asteroid_odds)
+        {
+            return with_resources(Tile::Asteroid, x, y, self.seed);
+        }
+
+        // Nebulae: broad, soft regions
+        if value_noise(x, y, 32, self.seed.wrapping_add(3)) > mix.nebula {
+            return with_resources(Tile::Nebula, x, y, self.seed);
+        }
+
+        Tile::Floor
+    }
+
+    /// Generate the chunk at chunk coordinates (cx, cy)
+    pub fn generate(&self, cx: i32, cy: i32) -> ChunkData {
+        let origin_x = cx * CHUNK_SIZE;
+        let origin_y = cy * CHUNK_SIZE;
+        let tiles = (0..CHUNK_SIZE)
+            .flat_map(|ly| (0..CHUNK_SIZE).map(move |lx| (lx, ly)))
+            .map(|(lx, ly)| self.tile_at(origin_x + lx, origin_y + ly))
+            .collect();
+        ChunkData { cx, cy, tiles, pois: self.poi(cx, cy).into_iter().collect(), regions: vec![self.region(cx, cy)] }
+    }
+
+    /// The biome of the grid square holding a world position
+    pub fn biome(&self, x: i32, y: i32) -> Biome {
+        let (rx, ry) = regions::region_coords(x, y);
+        if (rx, ry) == regions::region_coords(SPAWN_POSITION.0, SPAWN_POSITION.1) {
+            return Biome::StationSector;
+        }
+        Biome::at(self.seed as u64, rx, ry)
+    }
+
+    /// The region holding chunk (cx, cy), surveyed whole so every chunk in it agrees
+    pub fn region(&self, cx: i32, cy: i32) -> Region {
+        let (x, y) = (cx * CHUNK_SIZE, cy * CHUNK_SIZE);
+        let (rx, ry) = regions::region_coords(x, y);
+        let size = REGION_SIZE as u32;
+        let biome = Some(self.biome(x, y));
+        regions::survey(self.seed as u64, biome, rx * REGION_SIZE, ry * REGION_SIZE, size, size, |x, y| Some(self.tile_at(x, y)))
+    }
+
+    /// The derelict or beacon in a chunk, if it has one, on an open tile; the spawn chunk has Haven instead
+    pub fn poi(&self, cx: i32, cy: i32) -> Option<Poi> {
+        let seed = self.seed ^ POI_SALT;
+        let roll = hash_position(cx, cy, seed);
+        if !roll.is_multiple_of(CHUNK_POI_ODDS) || (cx, cy) == chunk_coords(SPAWN_POSITION.0, SPAWN_POSITION.1) {
+            return None;
+        }
+        let kind = if (roll >> 8).is_multiple_of(2) { PoiKind::Derelict } else { PoiKind::Beacon };
+        (1..=POI_ATTEMPTS)
+            .map(|attempt| {
+                let spot = hash_position(cx, cy, seed.wrapping_add(attempt));
+                let local_x = (spot % CHUNK_SIZE as u32) as i32;
+                let local_y = ((spot >> 8) % CHUNK_SIZE as u32) as i32;
+                (cx * CHUNK_SIZE + local_x, cy * CHUNK_SIZE + local_y)
+            })
+            .find(|&(x, y)| self.tile_at(x, y).is_passable())
+            .map(|(x, y)| Poi { kind, name: pois::name(kind, hash_position(x, y, seed)), x, y })
+    }
+}
+
+/// Smoothly interpolated value noise in 0.0..=1.0 with features roughly `scale` tiles across
+pub fn value_noise(x: i32, y: i32, scale: i32, seed: u32) -> f32 {
+    let grid_x = x.div_euclid(scale);
+    let grid_y = y.div_euclid(scale);
+    let fx = smoothstep(x.rem_euclid(scale) as f32 / scale as f32);
+    let fy = smoothstep(y.rem_euclid(scale) as f32 / scale as f32);
+
+    let corner = |gx: i32, gy: i32| (hash_position(gx, gy, seed) & 0xFFFF) as f32 / 65535.0;
+    let top = lerp(corner(grid_x, grid_y), corner(grid_x + 1, grid_y), fx);
+    let bottom = lerp(corner(grid_x, grid_y + 1), corner(grid_x + 1, grid_y + 1), fx);
+    lerp(top, bottom, fy)
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Chunk endpoint handler
+pub async fn get_chunk(State(state): State<AppState>, ApiQuery(params): ApiQuery<ChunkQuery>, headers: HeaderMap) -> Response {
+    let seed = params.seed.unwrap_or(state.world.seed);
+    chunk_response(&state, params.cx, params.cy, seed, params.format.as_deref(), &headers)
+}
+
+/// One chunk of the world with this seed, with any jump gates in it, as JSON or binary; a 304 if the
+/// client's tagged copy is still current
+pub fn chunk_response(state: &AppState, cx: i32, cy: i32, seed: u64, format: Option<&str>, headers: &HeaderMap) -> Response {
+    let in_world = -MAX_CHUNK_COORD..=MAX_CHUNK_COORD;
+    if !in_world.contains(&cx) || !in_world.contains(&cy) {
+        return ApiError::Invalid(format!("Chunk coordinates must be within ±{}", MAX_CHUNK_COORD)).into_response();
+    }
+    let _span = tracing::debug_span!("generate_chunk", cx, cy, seed).entered();
+    // Read before the chunk is built, so the chunk is at least this current; only the world's own seed has edits
+    let versions = if seed != state.world.seed {
+        None
+    } else {
+        match state.world.version().and_then(|world| Ok((world, state.world.chunk_version(cx, cy)?))) {
+            Ok(versions) => Some(versions),
+            Err(e) => return ApiError::Storage(e).into_response(),
+        }
+    };
+    let binary = wants_binary(format, headers);
+    // Edit versions start over with a new world, so the world's instance goes in the tag with them
+    let (instance, edited) = versions.map_or((0, 0), |(_, chunk)| (state.world.instance, chunk));
+    let etag = etag::tag(format_args!("chunk {} {} {} {} {} {}", instance, seed, cx, cy, edited, if binary { "bin" } else { "json" }));
+    let mut response = if etag::matches(headers, &etag) {
+        etag::not_modified(etag)
+    } else {
+        let mut chunk = state.world.chunk(cx, cy, seed);
+        chunk.pois.extend(state.systems.gate_pois(seed, cx, cy));
+        let response = if binary { binary_response(chunk.to_bytes()) } else { Json(chunk).into_response() };
+        etag::tagged(response, etag)
+    };
+    if let Some((world, _)) = versions {
+        response.headers_mut().insert(WORLD_VERSION_HEADER, HeaderValue::from(world));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::fnv1a;
+
+    // ==================== Generator Tests ====================
+
+    /// Hashes of whole chunks for fixed seeds and positions, including the spawn chunk and the world edge.
+    /// If a change to generation is intentional, paste the new hashes from the failure messages.
+    #[test]
+    fn test_golden_chunks() {
+        let cases: [(u64, i32, i32, u64); 5] = [
+            (12345, 0, 0, 0xaaa63644d0618e0b),
+            (12345, -1, -1, 0xc57b871702300393),
+            (12345, 17, -40, 0xd47d842e9ab5b81b),
+            (99, 3, 2, 0x51835f1f4f996c0e),
+            (12345, MAX_CHUNK_COORD, -MAX_CHUNK_COORD, 0xfbcc9fe3c4cd6f1c),
+        ];
+        for (seed, cx, cy, expected) in cases {
+            let hash = fnv1a(&ChunkGenerator::new(seed).generate(cx, cy).to_bytes());
+            assert_eq!(hash, expected, "Chunk ({}, {}) for seed {} changed: now {:#018x}", cx, cy, seed, hash);
+        }
+    }
+
+    #[test]
+    fn test_chunk_has_full_size() {
+        let chunk = ChunkGenerator::new(1).generate(3, -2);
+        assert_eq!(chunk.tiles.len(), (CHUNK_SIZE * CHUNK_SIZE) as usize);
+        assert_eq!((chunk.cx, chunk.cy), (3, -2));
+    }
+
+    #[test]
+    fn test_chunk_deterministic() {
+        let a = ChunkGenerator::new(42).generate(1, 1);
+        let b = ChunkGenerator::new(42).generate(1, 1);
+        assert_eq!(a, b, "Same seed and coords should give the same chunk");
+    }
+
+    #[test]
+    fn test_chunk_seeds_differ() {
+        let a = ChunkGenerator::new(1).generate(1, 1);
+        let b = ChunkGenerator::new(2).generate(1, 1);
+        assert_ne!(a.tiles, b.tiles, "Different seeds should give different chunks");
+    }
+
+    #[test]
+    fn test_chunks_match_world_tiles() {
+        // Chunks are just windows onto tile_at, so neighbours line up seamlessly
+        let generator = ChunkGenerator::new(7);
+        for (cx, cy) in [(0, 0), (-1, 0), (2, -3)] {
+            let chunk = generator.generate(cx, cy);
+            for (lx, ly) in [(0, 0), (CHUNK_SIZE - 1, 0), (5, CHUNK_SIZE - 1), (31, 17)] {
+                assert_eq!(
+                    chunk.get(lx, ly),
+                    Some(generator.tile_at(cx * CHUNK_SIZE + lx, cy * CHUNK_SIZE + ly)),
+                    "Chunk ({}, {}) local ({}, {}) should match the world tile", cx, cy, lx, ly
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunks_at_world_edge() {
+        let generator = ChunkGenerator::new(5);
+        for (cx, cy) in [(MAX_CHUNK_COORD, MAX_CHUNK_COORD), (-MAX_CHUNK_COORD, -MAX_CHUNK_COORD)] {
+            let chunk = generator.generate(cx, cy);
+            assert_eq!(chunk.tiles.len(), (CHUNK_SIZE * CHUNK_SIZE) as usize, "Edge chunks generate without overflow");
+        }
+    }
+
+    #[test]
+    fn test_spawn_is_clear() {
+        for seed in [0, 1, 12345, u64::MAX] {
+            let generator = ChunkGenerator::new(seed);
+            let (sx, sy) = SPAWN_POSITION;
+            for dy in -SPAWN_CLEARANCE..=SPAWN_CLEARANCE {
+                for dx in -SPAWN_CLEARANCE..=SPAWN_CLEARANCE {
+                    assert!(generator.tile_at(sx + dx, sy + dy).is_passable(), "Spawn area should be open");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_world_has_all_tile_types() {
+        let generator = ChunkGenerator::new(crate::world::DEFAULT_SEED);
+        let mut counts = std::collections::HashMap::new();
+        for cy in -2..2 {
+            for cx in -2..2 {
+                for tile in generator.generate(cx, cy).tiles {
+                    *counts.entry(tile).or_insert(0usize) += 1;
+                }
+            }
+        }
+        let total: usize = counts.values().sum();
+        for tile in [Tile::Wall, Tile::Floor, Tile::Asteroid, Tile::Nebula, Tile::OreAsteroid, Tile::CrystalField] {
+            assert!(counts.get(&tile).copied().unwrap_or(0) > 0, "World should contain {:?}", tile);
+        }
+        // Open space should dominate so the world stays navigable
+        assert!(counts[&Tile::Floor] * 2 > total, "Floor should be the majority");
+    }
+
+    #[test]
+    fn test_some_chunks_have_points_of_interest() {
+        let generator = ChunkGenerator::new(crate::world::DEFAULT_SEED);
+        let generator = &generator;
+        let pois: Vec<Poi> = (-4..4).flat_map(|cy| (-4..4).flat_map(move |cx| generator.generate(cx, cy).pois)).collect();
+        assert!((8..=32).contains(&pois.len()), "About one chunk in four, got {}", pois.len());
+        for poi in &pois {
+            assert!(generator.tile_at(poi.x, poi.y).is_passable(), "{} is in rock", poi.name);
+            assert!(matches!(poi.kind, PoiKind::Derelict | PoiKind::Beacon), "Stations come from stations.rs");
+        }
+        assert!(generator.poi(0, 0).is_none(), "The spawn chunk is Haven's");
+        assert_eq!(generator.generate(3, -1).pois, generator.generate(3, -1).pois);
+    }
+
+    #[test]
+    fn test_chunks_name_their_region() {
+        let generator = ChunkGenerator::new(12345);
+        let chunk = generator.generate(1, -1);
+        assert_eq!(chunk.regions.len(), 1);
+        let region = &chunk.regions[0];
+        assert_eq!((region.x, region.y, region.width), (0, -REGION_SIZE, REGION_SIZE as u32), "The whole grid square");
+        assert_eq!(generator.generate(0, -2).regions, chunk.regions, "Every chunk in a region agrees on it");
+        assert_ne!(generator.generate(2, -1).regions, chunk.regions);
+        assert_eq!(region.biome, generator.biome(0, -1), "Named for the biome it was generated as");
+    }
+
+    #[test]
+    fn test_biomes_shape_their_tiles() {
+        let generator = ChunkGenerator::new(crate::world::DEFAULT_SEED);
+        let (sx, sy) = SPAWN_POSITION;
+        assert_eq!(generator.biome(sx, sy), Biome::StationSector, "Haven's square");
+
+        // Share of asteroids and of gas over a sample of each biome's squares
+        let mut shares: std::collections::HashMap<Biome, (u32, u32, u32)> = std::collections::HashMap::new();
+        for ry in -6..6 {
+            for rx in -6..6 {
+                let share = shares.entry(generator.biome(rx * REGION_SIZE, ry * REGION_SIZE)).or_default();
+                for y in (ry * REGION_SIZE..(ry + 1) * REGION_SIZE).step_by(3) {
+                    for x in (rx * REGION_SIZE..(rx + 1) * REGION_SIZE).step_by(3) {
+                        match generator.tile_at(x, y) {
+                            Tile::Asteroid | Tile::OreAsteroid => share.0 += 1,
+                            Tile::Nebula | Tile::CrystalField => share.1 += 1,
+                            _ => {}
+                        }
+                        share.2 += 1;
+                    }
+                }
+            }
+        }
+        let percent = |biome, pick: fn(&(u32, u32, u32)) -> u32| shares.get(&biome).map(|share| pick(share) * 100 / share.2).unwrap();
+        let (asteroids, gas) = (|share: &(u32, u32, u32)| share.0, |share: &(u32, u32, u32)| share.1);
+        assert!(percent(Biome::AsteroidBelt, asteroids) > percent(Biome::DeepSpace, asteroids) * 2, "Belts are full of rock");
+        assert!(percent(Biome::NebulaCloud, gas) > percent(Biome::DeepSpace, gas) * 2, "Clouds are full of gas");
+        assert!(percent(Biome::StationSector, asteroids) <= percent(Biome::DeepSpace, asteroids), "Station lanes are kept clear");
+    }
+
+    #[test]
+    fn test_value_noise_range() {
+        for i in -200..200 {
+            let n = value_noise(i * 7, i * -3, 16, 99);
+            assert!((0.0..=1.0).contains(&n), "Noise {} out of range", n);
+        }
+    }
+
+    #[test]
+    fn test_value_noise_continuous() {
+        // Adjacent tiles differ by at most a small step, so features are blobs, not static
+        for x in -100..100 {
+            let a = value_noise(x, 5, 18, 3);
+            let b = value_noise(x + 1, 5, 18, 3);
+            assert!((a - b).abs() < 0.2, "Noise should change gradually");
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Projectiles fired by pilots.
+//!
+//! `ClientMessage::Fire` puts a projectile on the pilot's tile, as the server
+//! last heard it, heading the way they asked; each pilot fires at most once
+//! per `FIRE_COOLDOWN`. Shots live in their own `exospace_core::ecs` store
+//! and `ProjectileFlight` moves them a tile every `PROJECTILE_STEP`. A shot
+//! stops at the first solid tile, cutting a plain asteroid away as the
+//! mining laser does, or at an NPC ship, knocking `PROJECTILE_DAMAGE` off its
+//! hull (see `npc`). Sessions whose area takes in the shot (`interest`) and
+//! whose sensors pick up the shooter hear `Shot` and `ShotEnded` and fly the
+//! shot themselves in between; only the shooter hears what it hit.
+
+use exospace_core::ecs::{Entities, Impact, PROJECTILE_DAMAGE, PROJECTILE_STEP, Position, FIRE_COOLDOWN};
+use exospace_core::{Direction, ServerMessage, Tile};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::npc::{NpcRoster, TileCache};
+use crate::sessions::SessionRegistry;
+use crate::tick::System;
+use crate::world::{self, World};
+
+/// Shots in flight, and when each pilot last fired
+#[derive(Default)]
+pub struct Projectiles {
+    shots: Mutex<Entities>,
+    last_fired: Mutex<HashMap<u64, Instant>>,
+}
+
+impl Projectiles {
+    /// Fire from a pilot's tile; the `Shot` to announce, or why the guns didn't fire
+    pub fn fire(&self, owner: u64, (x, y): (i32, i32), direction: Direction, now: Instant) -> Result<ServerMessage, String> {
+        if !world::in_world(x, y) {
+            return Err("You can't fire from outside the world".to_string());
+        }
+        let mut last_fired = self.last_fired.lock().unwrap();
+        if last_fired.get(&owner).is_some_and(|&at| now.saturating_duration_since(at) < FIRE_COOLDOWN) {
+            return Err("Your guns are still cooling down".to_string());
+        }
+        last_fired.insert(owner, now);
+        let shot = self.shots.lock().unwrap().fire(owner, Position { x, y }, direction);
+        Ok(ServerMessage::Shot { id: shot.0, owner, x, y, direction })
+    }
+
+    /// Forget a pilot who left; their shots already in flight fly on
+    pub fn forget(&self, owner: u64) {
+        self.last_fired.lock().unwrap().remove(&owner);
+    }
+}
+
+/// Flies shots and settles what they hit; critical, since shots already fired shouldn't hang in the air
+pub struct ProjectileFlight {
+    projectiles: Arc<Projectiles>,
+    npcs: Arc<NpcRoster>,
+    world: Arc<World>,
+    sessions: Arc<SessionRegistry>,
+    tiles: TileCache,
+    next_step: Instant,
+}
+
+impl ProjectileFlight {
+    pub fn new(projectiles: Arc<Projectiles>, npcs: Arc<NpcRoster>, world: Arc<World>, sessions: Arc<SessionRegistry>, now: Instant) -> Self {
+        ProjectileFlight { projectiles, npcs, world, sessions, tiles: TileCache::default(), next_step: now }
+    }
+
+    /// Cut away a plain asteroid a shot hit; walls and resources just stop it
+    fn break_asteroid(&mut self, Position { x, y }: Position) {
+        if self.tiles.tile(&self.world, x, y) != Tile::Asteroid {
+            return;
+        }
+        match self.world.break_tile(x, y, Tile::Floor) {
+            Ok(Some(_)) => {
+                self.tiles.forget(x, y);
+                self.sessions.tile_changed(x, y, Tile::Floor);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to break asteroid ({}, {}): {}", x, y, e),
+        }
+    }
+}
+
+impl System for ProjectileFlight {
+    fn name(&self) -> &'static str {
+        "projectile_flight"
+    }
+
+    fn critical(&self) -> bool {
+        true
+    }
+
+    fn tick(&mut self, now: Instant) {
+        if now < self.next_step {
+            return;
+        }
+        self.next_step = now + PROJECTILE_STEP;
+
+        // Tiles and ships are looked up with the shots unlocked, so firing never waits on chunk generation;
+        // shots fired meanwhile get theirs on another pass
+        let mut solid = HashMap::new();
+        let mut ships = HashMap::new();
+        let stopped = loop {
+            let mut shots = self.projectiles.shots.lock().unwrap();
+            let mut ahead = shots.projectile_steps();
+            ahead.retain(|p| !solid.contains_key(p));
+            if ahead.is_empty() {
+                break shots.step_projectiles(|p| solid[&p], |p| ships.get(&p).copied());
+            }
+            drop(shots);
+            for p in ahead {
+                solid.insert(p, !self.tiles.passable(&self.world, p.x, p.y));
+                if let Some(ship) = self.npcs.ship_at(p.x, p.y) {
+                    ships.insert(p, ship);
+                }
+            }
+        };
+        for (shot, projectile, impact) in stopped {
+            match impact {
+                Impact::Tile(position) => self.break_asteroid(position),
+                Impact::Target(id, _) => {
+                    if let Some((ship, destroyed)) = self.npcs.hit(id, PROJECTILE_DAMAGE) {
+                        if destroyed {
+                            self.sessions.npc_moved(&ship);
+                        }
+                        self.sessions.send_to(projectile.owner, ServerMessage::Hit { target: ship.name, destroyed });
+                    }
+                }
+                Impact::Spent(_) => {}
+            }
+            let Position { x, y } = impact.position();
+            self.sessions.show(projectile.owner, (x, y), ServerMessage::ShotEnded { id: shot.0, x, y });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::MemoryStore;
+    use exospace_core::NpcBehavior;
+    use std::time::Duration;
+
+    fn world() -> Arc<World> {
+        Arc::new(World::new(Box::new(MemoryStore::default())).unwrap())
+    }
+
+    /// A corridor of open space along y = 0 from x = 0 to 20, walled in at both ends
+    fn range(world: &World) {
+        for x in -1..=21 {
+            for y in -1..=1 {
+                let tile = if y == 0 && (0..=20).contains(&x) { Tile::Floor } else { Tile::Wall };
+                world.set_tile(x, y, tile).unwrap();
+            }
+        }
+    }
+
+    // ==================== Firing Tests ====================
+
+    #[test]
+    fn test_fire_cools_down() {
+        let projectiles = Projectiles::default();
+        let now = Instant::now();
+        let shot = projectiles.fire(7, (1, 2), Direction::Left, now).unwrap();
+        assert_eq!(shot, ServerMessage::Shot { id: 1, owner: 7, x: 1, y: 2, direction: Direction::Left });
+        assert!(projectiles.fire(7, (1, 2), Direction::Left, now + FIRE_COOLDOWN / 2).unwrap_err().contains("cooling"));
+        assert!(projectiles.fire(8, (1, 2), Direction::Left, now).is_ok(), "Each pilot has their own guns");
+        assert!(projectiles.fire(7, (1, 2), Direction::Left, now + FIRE_COOLDOWN).is_ok());
+        assert!(projectiles.fire(9, (i32::MAX, 0), Direction::Up, now).is_err(), "Nothing fires from outside the world");
+        assert_eq!(projectiles.shots.lock().unwrap().len(), 3);
+    }
+
+    // ==================== Flight Tests ====================
+
+    #[test]
+    fn test_shots_break_asteroids_and_damage_ships() {
+        let world = world();
+        range(&world);
+        world.set_tile(5, 0, Tile::Asteroid).unwrap();
+        let sessions = Arc::new(SessionRegistry::default());
+        let (pilot, mut rx) = sessions.join("Ace");
+        let npcs = Arc::new(NpcRoster::default());
+        let ship = npcs.launch(&world, "Target", NpcBehavior::Wander, (10, 0));
+        assert_eq!((ship.x, ship.y), (10, 0));
+        let projectiles = Arc::new(Projectiles::default());
+        let now = Instant::now();
+        let mut flight = ProjectileFlight::new(projectiles.clone(), npcs.clone(), world.clone(), sessions.clone(), now);
+
+        projectiles.fire(pilot.id, (0, 0), Direction::Right, now).unwrap();
+        for step in 0..10 {
+            flight.tick(now + PROJECTILE_STEP * step);
+        }
+        assert_eq!(world.tile(5, 0), Tile::Floor, "The asteroid is cut away");
+        assert_eq!(projectiles.shots.lock().unwrap().len(), 0);
+
+        projectiles.fire(pilot.id, (0, 0), Direction::Right, now + Duration::from_secs(1)).unwrap();
+        for step in 10..25 {
+            flight.tick(now + PROJECTILE_STEP * step);
+        }
+        sessions.flush_delta();
+
+        let mut heard = Vec::new();
+        while let Ok(broadcast) = rx.try_recv() {
+            if broadcast.is_for(pilot.id) {
+                heard.push(broadcast.message);
+            }
+        }
+        let ended: Vec<(i32, i32)> = heard
+            .iter()
+            .filter_map(|message| match message {
+                ServerMessage::ShotEnded { x, y, .. } => Some((*x, *y)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ended, [(5, 0), (10, 0)], "Shots end at the asteroid, then at the ship");
+        assert!(heard.contains(&ServerMessage::Hit { target: "Target".to_string(), destroyed: false }));
+        assert!(
+            heard.iter().any(|m| matches!(m, ServerMessage::WorldDelta { tiles, .. } if tiles.iter().any(|t| (t.x, t.tile) == (5, Tile::Floor)))),
+            "Everyone hears about the broken asteroid"
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! Community maps: maps pilots upload for each other to fly.
+//!
+//! `POST /maps/upload?name=NAME` takes a map from a logged-in pilot, in the
+//! binary encoding (sent as `application/octet-stream`) or as `MapData`
+//! JSON; uploading a name the pilot has used before replaces that map.
+//! `GET /maps` lists them newest first and `GET /maps/{id}` hands one back
+//! the way `/map` does. Maps live in the directory named by
+//! `EXOSPACE_MAPS_DIR` (`index.json` for the listings and `<id>.map` for
+//! each map, read when it's fetched), or in memory only when it isn't set.
+//! The server keeps at most `MAX_MAPS` in all. Files are read and written
+//! on the blocking pool, outside the library's lock, and replaced through a
+//! temporary file so a reader never sees half of one.
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{State, rejection::BytesRejection},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use exospace_core::{BINARY_CONTENT_TYPE, MapData, MapListing, Tile};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::AppState;
+use crate::auth::{self, AuthError};
+use crate::error::{ApiError, ApiPath, ApiQuery};
+use crate::{binary_response, utility, wants_binary};
+
+/// Environment variable naming the directory community maps are kept in
+pub const MAPS_DIR_ENV: &str = "EXOSPACE_MAPS_DIR";
+
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Maps one account can have up at once
+pub const MAX_MAPS_PER_AUTHOR: usize = 20;
+
+/// Maps the server keeps at once, across every account
+pub const MAX_MAPS: usize = 256;
+
+/// Largest upload: a full-size map in the binary encoding, with room for its points of interest
+pub const MAX_UPLOAD_BYTES: usize = 4 * 1024 * 1024;
+
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Default)]
+struct Library {
+    /// In upload order
+    listings: Vec<MapListing>,
+    /// The maps themselves when there's no directory to keep them in
+    maps: HashMap<u64, Arc<MapData>>,
+}
+
+/// The uploaded maps
+#[derive(Default)]
+pub struct CommunityMaps {
+    /// Where they're kept; None keeps them in memory only
+    dir: Option<PathBuf>,
+    library: RwLock<Library>,
+    /// Held through an upload's file writes, so uploads go one at a time without keeping readers waiting
+    saving: Mutex<()>,
+}
+
+impl CommunityMaps {
+    /// Maps from `EXOSPACE_MAPS_DIR` if set (created on first upload), otherwise in memory only
+    pub fn load() -> Self {
+        let Ok(dir) = std::env::var(MAPS_DIR_ENV) else {
+            return Self::default();
+        };
+        Self::open(PathBuf::from(&dir)).unwrap_or_else(|e| {
+            tracing::warn!("Failed to load community maps from {}: {}", dir, e);
+            Self::default()
+        })
+    }
+
+    fn open(dir: PathBuf) -> Result<Self, String> {
+        let listings = match std::fs::read_to_string(dir.join(INDEX_FILE)) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.to_string()),
+        };
+        let library = RwLock::new(Library { listings, maps: HashMap::new() });
+        Ok(CommunityMaps { dir: Some(dir), library, saving: Mutex::default() })
+    }
+
+    /// Every map, newest first
+    pub fn list(&self) -> Vec<MapListing> {
+        let mut listings = self.library.read().unwrap().listings.clone();
+        listings.reverse();
+        listings
+    }
+
+    /// One map's tiles; None if there's no such map
+    pub fn get(&self, id: u64) -> Result<Option<Arc<MapData>>, String> {
+        let library = self.library.read().unwrap();
+        if !library.listings.iter().any(|listing| listing.id == id) {
+            return Ok(None);
+        }
+        let Some(dir) = &self.dir else {
+            return Ok(library.maps.get(&id).cloned());
+        };
+        // Read without the lock; uploads replace the file whole
+        drop(library);
+        let bytes = std::fs::read(dir.join(format!("{}.map", id))).map_err(|e| format!("Failed to read map {}: {}", id, e))?;
+        MapData::from_bytes(&bytes).map(|map| Some(Arc::new(map))).map_err(|e| format!("Map {} is damaged: {}", id, e))
+    }
+
+    /// Add `author`'s map under `name`, replacing theirs of the same name; the map should already be checked
+    pub fn add(&self, author: &str, name: &str, map: MapData, now: u64) -> Result<MapListing, ApiError> {
+        let name = check_name(name)?;
+        let _saving = self.saving.lock().unwrap();
+        // Only uploads change the listings, so a copy taken here is still current when it's put back
+        let mut listings = self.library.read().unwrap().listings.clone();
+        let ours = |listing: &MapListing| listing.author.eq_ignore_ascii_case(author);
+        let replacing = listings.iter().position(|listing| ours(listing) && listing.name.eq_ignore_ascii_case(&name));
+        if replacing.is_none() && listings.iter().filter(|listing| ours(listing)).count() >= MAX_MAPS_PER_AUTHOR {
+            return Err(ApiError::Invalid(format!("You already have {} maps up; upload one under an old name to replace it", MAX_MAPS_PER_AUTHOR)));
+        }
+        if replacing.is_none() && listings.len() >= MAX_MAPS {
+            return Err(ApiError::Invalid(format!("The server already has {} maps up; upload one under an old name to replace it", MAX_MAPS)));
+        }
+        let id = match replacing {
+            Some(index) => listings.remove(index).id,
+            None => listings.iter().map(|listing| listing.id).max().unwrap_or(0) + 1,
+        };
+        let listing = MapListing { id, name, author: author.to_string(), width: map.width, height: map.height, uploaded: now };
+        listings.push(listing.clone());
+
+        if let Some(dir) = &self.dir {
+            let saved = std::fs::create_dir_all(dir)
+                .and_then(|()| replace_file(dir, &format!("{}.map", id), &map.to_bytes()))
+                .map_err(|e| e.to_string())
+                .and_then(|()| serde_json::to_string_pretty(&listings).map_err(|e| e.to_string()))
+                .and_then(|json| replace_file(dir, INDEX_FILE, json.as_bytes()).map_err(|e| e.to_string()));
+            if let Err(e) = saved {
+                return Err(ApiError::Storage(format!("Failed to save the map: {}", e)));
+            }
+        }
+        let mut library = self.library.write().unwrap();
+        library.listings = listings;
+        if self.dir.is_none() {
+            library.maps.insert(id, Arc::new(map));
+        }
+        Ok(listing)
+    }
+}
+
+/// Write a file in `dir` by way of a temporary one, so it's never seen half written
+fn replace_file(dir: &Path, file: &str, contents: &[u8]) -> std::io::Result<()> {
+    let temporary = dir.join(format!("{}.tmp", file));
+    std::fs::write(&temporary, contents)?;
+    std::fs::rename(&temporary, dir.join(file))
+}
+
+/// A map name, trimmed: 1-32 characters, none of them control characters
+fn check_name(name: &str) -> Result<String, ApiError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN || name.chars().any(char::is_control) {
+        return Err(ApiError::Invalid(format!("Map names are 1-{} characters", MAX_NAME_LEN)));
+    }
+    Ok(name.to_string())
+}
+
+/// Whether an uploaded map holds together: every tile there, none unknown, and the start on the map
+fn check_map(map: &MapData) -> Result<(), ApiError> {
+    if map.tiles.len() != map.width * map.height {
+        return Err(ApiError::Invalid(format!("A {}x{} map has {} tiles, not {}", map.width, map.height, map.width * map.height, map.tiles.len())));
+    }
+    if map.tiles.contains(&Tile::Unknown) {
+        return Err(ApiError::Invalid("The map has tiles of an unknown kind".to_string()));
+    }
+    let on_map = |v: i32, side: usize| v >= 0 && (v as usize) < side;
+    if !on_map(map.start_x, map.width) || !on_map(map.start_y, map.height) {
+        return Err(ApiError::Invalid(format!("The start ({}, {}) is off the map", map.start_x, map.start_y)));
+    }
+    Ok(())
+}
+
+/// Query parameters for `GET /maps/{id}`
+#[derive(Deserialize)]
+pub struct CommunityMapQuery {
+    #[serde(default)]
+    format: Option<String>,
+}
+
+/// Query parameters for `POST /maps/upload`
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    name: String,
+}
+
+/// Handler for `GET /maps`
+pub async fn list_maps(State(state): State<AppState>) -> Json<Vec<MapListing>> {
+    Json(state.community.list())
+}
+
+/// Handler for `GET /maps/{id}`: the map as JSON, or binary like `/map`
+pub async fn get_map(
+    State(state): State<AppState>,
+    ApiPath(id): ApiPath<u64>,
+    ApiQuery(params): ApiQuery<CommunityMapQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let community = state.community.clone();
+    let found = tokio::task::spawn_blocking(move || community.get(id)).await.unwrap_or_else(|e| Err(e.to_string()));
+    match found {
+        Ok(Some(map)) if wants_binary(params.format.as_deref(), &headers) => binary_response(map.to_bytes()),
+        Ok(Some(map)) => Json(&*map).into_response(),
+        Ok(None) => ApiError::NotFound(format!("No community map {}", id)).into_response(),
+        Err(e) => ApiError::Storage(e).into_response(),
+    }
+}
+
+/// Handler for `POST /maps/upload?name=NAME` (needs a bearer token): 201 with the map's listing
+pub async fn upload(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<UploadQuery>,
+    body: Result<Bytes, BytesRejection>,
+) -> Response {
+    let Some(author) = auth::bearer_token(&headers).and_then(|token| state.users.verify(token)) else {
+        return AuthError::BadToken.into_response();
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(rejection) => return ApiError::Malformed(rejection.status(), rejection.body_text()).into_response(),
+    };
+    let binary = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).is_some_and(|kind| kind.starts_with(BINARY_CONTENT_TYPE));
+    let map = if binary {
+        MapData::from_bytes(&body).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_slice::<MapData>(&body).map_err(|e| e.to_string())
+    };
+    let map = match map {
+        Ok(map) => map,
+        Err(e) => return ApiError::Malformed(StatusCode::BAD_REQUEST, format!("Not a map: {}", e)).into_response(),
+    };
+    if let Err(e) = state.map_limits.check(map.width, map.height).and_then(|()| check_map(&map)) {
+        return e.into_response();
+    }
+    let community = state.community.clone();
+    let added = tokio::task::spawn_blocking(move || community.add(&author, &params.name, map, utility::unix_time())).await;
+    match added {
+        Ok(Ok(listing)) => {
+            tracing::info!(id = listing.id, author = %listing.author, name = %listing.name, "Community map uploaded");
+            (StatusCode::CREATED, Json(listing)).into_response()
+        }
+        Ok(Err(e)) => e.into_response(),
+        Err(e) => ApiError::Storage(e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(width: usize, height: usize) -> MapData {
+        MapData { start_x: 1, start_y: 1, ..MapData::filled(width, height, Tile::Floor) }
+    }
+
+    // ==================== Library Tests ====================
+
+    #[test]
+    fn test_uploads_are_listed_and_replaced() {
+        let maps = CommunityMaps::default();
+        let maze = maps.add("Ace", " The Maze ", map(10, 5), 100).unwrap();
+        assert_eq!((maze.id, maze.name.as_str()), (1, "The Maze"), "Names are trimmed");
+        let belt = maps.add("Bee", "Belt", map(20, 10), 200).unwrap();
+        assert_eq!(belt.id, 2);
+        let names: Vec<_> = maps.list().into_iter().map(|listing| listing.name).collect();
+        assert_eq!(names, vec!["Belt", "The Maze"], "Newest first");
+
+        let again = maps.add("ace", "the maze", map(30, 15), 300).unwrap();
+        assert_eq!(again.id, 1, "The same pilot and name replace the old map");
+        assert_eq!(maps.list().len(), 2);
+        assert_eq!(maps.get(1).unwrap().map(|map| map.width), Some(30));
+        assert_eq!(maps.get(9).unwrap().map(|map| map.width), None);
+    }
+
+    #[test]
+    fn test_upload_limits() {
+        let maps = CommunityMaps::default();
+        assert!(maps.add("Ace", "  ", map(10, 5), 0).is_err());
+        assert!(maps.add("Ace", &"x".repeat(MAX_NAME_LEN + 1), map(10, 5), 0).is_err());
+        for i in 0..MAX_MAPS_PER_AUTHOR {
+            maps.add("Ace", &format!("Map {}", i), map(10, 5), 0).unwrap();
+        }
+        assert!(maps.add("Ace", "One more", map(10, 5), 0).is_err(), "Each pilot has a limit");
+        assert!(maps.add("Ace", "Map 0", map(10, 5), 0).is_ok(), "Replacing one is fine at the limit");
+        assert!(maps.add("Bee", "One more", map(10, 5), 0).is_ok());
+        for i in maps.list().len()..MAX_MAPS {
+            maps.add(&format!("Pilot {}", i), "Mine", map(10, 5), 0).unwrap();
+        }
+        assert!(maps.add("Cee", "Late", map(10, 5), 0).is_err(), "The server has a limit too");
+        assert!(maps.add("Bee", "One more", map(10, 5), 0).is_ok(), "Replacing still works");
+
+        assert!(check_map(&map(10, 5)).is_ok());
+        assert!(check_map(&MapData { tiles: vec![Tile::Floor; 3], ..map(10, 5) }).is_err(), "Tiles missing");
+        assert!(check_map(&MapData { start_x: 10, ..map(10, 5) }).is_err(), "Start off the map");
+        let mut unknown = map(10, 5);
+        unknown.tiles[7] = Tile::Unknown;
+        assert!(check_map(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_maps_are_kept_in_their_directory() {
+        let dir = std::env::temp_dir().join(format!("exospace-community-{}", std::process::id()));
+        let maps = CommunityMaps::open(dir.clone()).unwrap();
+        maps.add("Ace", "Belt", map(12, 6), 100).unwrap();
+
+        let reopened = CommunityMaps::open(dir.clone()).unwrap();
+        assert_eq!(reopened.list()[0].name, "Belt");
+        let belt = reopened.get(1).unwrap().expect("The map comes back from its file");
+        assert_eq!((belt.width, belt.height, belt.start_x), (12, 6, 1));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+//! Content packs: items, ship classes, upgrades and mission templates as data.
+//!
+//! The built-in `core` pack always loads first. Every `*.json` file in the
+//! directory named by `EXOSPACE_CONTENT` is then added in file name order, so
+//! a community server can extend the game without touching either binary.
+//! Ids are shared across packs: an entry reusing one that's already defined,
+//! or referring to an item or ship class nobody defined, is skipped with a
+//! warning and the rest of its pack still loads. The merged catalog is sent to
+//! clients after `Welcome` and served as JSON by `GET /content`.
+
+use axum::{Json, extract::State};
+use exospace_core::{ContentCatalog, ItemCount, ItemDef, MissionTemplate, PackInfo, ShipClassDef, UpgradeDef};
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::AppState;
+
+/// Environment variable naming the directory of content pack files
+pub const CONTENT_DIR_ENV: &str = "EXOSPACE_CONTENT";
+
+/// One content pack file
+#[derive(Deserialize)]
+pub struct ContentPack {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub items: Vec<ItemDef>,
+    #[serde(default)]
+    pub ships: Vec<ShipClassDef>,
+    #[serde(default)]
+    pub upgrades: Vec<UpgradeDef>,
+    #[serde(default)]
+    pub missions: Vec<MissionTemplate>,
+}
+
+/// The pack every server has: what mining produces and the ship everyone starts in
+pub fn core_pack() -> ContentPack {
+    let item = |id: &str, name: &str, description: &str| ItemDef {
+        id: id.to_string(),
+        name: name.to_string(),
+        description: description.to_string(),
+    };
+    let count = |item: &str, count| ItemCount { item: item.to_string(), count };
+    ContentPack {
+        name: "core".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        items: vec![
+            item("ore", "Ore", "Mined from ore asteroids"),
+            item("crystal", "Crystal", "Mined from crystal fields"),
+        ],
+        ships: vec![ShipClassDef {
+            id: "scout".to_string(),
+            name: "Scout".to_string(),
+            description: "Light, quick and cheap to run".to_string(),
+            hull: 100,
+            cargo: 40,
+        }],
+        upgrades: vec![UpgradeDef {
+            id: "cargo-pod".to_string(),
+            name: "Cargo Pod".to_string(),
+            description: "Twenty more units of hold".to_string(),
+            ships: vec!["scout".to_string()],
+            cost: vec![count("ore", 30), count("crystal", 5)],
+        }],
+        missions: vec![MissionTemplate {
+            id: "haven-ore-run".to_string(),
+            name: "Ore for Haven".to_string(),
+            description: "Haven Station's smelters are running low".to_string(),
+            deliver: vec![count("ore", 15)],
+            reward: vec![count("crystal", 3)],
+        }],
+    }
+}
+
+/// The core pack plus every pack in `EXOSPACE_CONTENT`, logging whatever was skipped
+pub fn load() -> ContentCatalog {
+    let mut catalog = ContentCatalog::default();
+    let mut packs = vec![core_pack()];
+    if let Ok(dir) = std::env::var(CONTENT_DIR_ENV) {
+        packs.extend(read_dir(Path::new(&dir)));
+    }
+    for pack in packs {
+        let name = pack.name.clone();
+        for warning in add_pack(&mut catalog, pack) {
+            tracing::warn!("Content pack {}: {}", name, warning);
+        }
+    }
+    catalog
+}
+
+/// Parse every `*.json` file in a directory, in file name order; unreadable ones are logged and skipped
+fn read_dir(dir: &Path) -> Vec<ContentPack> {
+    let mut paths: Vec<_> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect(),
+        Err(e) => {
+            tracing::warn!("Failed to read content directory {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+    paths.sort();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+                .inspect_err(|e| tracing::warn!("Failed to load content pack {}: {}", path.display(), e))
+                .ok()
+        })
+        .collect()
+}
+
+/// Merge a pack into the catalog; returns why any entries were left out
+pub fn add_pack(catalog: &mut ContentCatalog, pack: ContentPack) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if catalog.packs.iter().any(|existing| existing.name == pack.name) {
+        warnings.push("a pack with this name is already loaded".to_string());
+        return warnings;
+    }
+    catalog.packs.push(PackInfo { name: pack.name, version: pack.version });
+
+    for item in pack.items {
+        if check_id("item", &item.id, catalog.items.iter().map(|i| &i.id), &mut warnings) {
+            catalog.items.push(item);
+        }
+    }
+    for ship in pack.ships {
+        if check_id("ship", &ship.id, catalog.ships.iter().map(|s| &s.id), &mut warnings) {
+            catalog.ships.push(ship);
+        }
+    }
+    for upgrade in pack.upgrades {
+        let fits = upgrade.ships.iter().find(|ship| !catalog.ships.iter().any(|s| &&s.id == ship));
+        if let Some(ship) = fits {
+            warnings.push(format!("upgrade '{}' fits unknown ship class '{}'", upgrade.id, ship));
+        } else if check_items(&upgrade.id, &upgrade.cost, catalog, &mut warnings)
+            && check_id("upgrade", &upgrade.id, catalog.upgrades.iter().map(|u| &u.id), &mut warnings)
+        {
+            catalog.upgrades.push(upgrade);
+        }
+    }
+    for mission in pack.missions {
+        if check_items(&mission.id, &mission.deliver, catalog, &mut warnings)
+            && check_items(&mission.id, &mission.reward, catalog, &mut warnings)
+            && check_id("mission", &mission.id, catalog.missions.iter().map(|m| &m.id), &mut warnings)
+        {
+            catalog.missions.push(mission);
+        }
+    }
+    warnings
+}
+
+/// Whether an id is non-empty and not taken yet
+fn check_id<'a>(kind: &str, id: &str, mut taken: impl Iterator<Item = &'a String>, warnings: &mut Vec<String>) -> bool {
+    if id.trim().is_empty() {
+        warnings.push(format!("{} with an empty id", kind));
+        false
+    } else if taken.any(|existing| existing == id) {
+        warnings.push(format!("{} '{}' is already defined", kind, id));
+        false
+    } else {
+        true
+    }
+}
+
+/// Whether every item an entry mentions is defined
+fn check_items(id: &str, counts: &[ItemCount], catalog: &ContentCatalog, warnings: &mut Vec<String>) -> bool {
+    match counts.iter().find(|c| !catalog.items.iter().any(|item| item.id == c.item)) {
+        Some(missing) => {
+            warnings.push(format!("'{}' uses unknown item '{}'", id, missing.item));
+            false
+        }
+        None => true,
+    }
+}
+
+/// `GET /content`: the merged catalog as JSON
+pub async fn get_content(State(state): State<AppState>) -> Json<ContentCatalog> {
+    Json((*state.content).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(json: &str) -> ContentPack {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn core_catalog() -> ContentCatalog {
+        let mut catalog = ContentCatalog::default();
+        assert!(add_pack(&mut catalog, core_pack()).is_empty(), "The core pack is consistent");
+        catalog
+    }
+
+    // ==================== Pack Tests ====================
+
+    #[test]
+    fn test_pack_extends_core() {
+        let mut catalog = core_catalog();
+        let frontier = pack(
+            r#"{
+                "name": "frontier",
+                "items": [{ "id": "ice", "name": "Ice" }],
+                "ships": [{ "id": "hauler", "name": "Hauler", "hull": 180, "cargo": 120 }],
+                "missions": [{ "id": "ice-run", "name": "Ice Run", "deliver": [{ "item": "ice", "count": 10 }] }]
+            }"#,
+        );
+        assert_eq!(add_pack(&mut catalog, frontier), Vec::<String>::new());
+        assert_eq!(catalog.packs.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["core", "frontier"]);
+        assert_eq!(catalog.items.len(), 3);
+        assert_eq!(catalog.ships[1].cargo, 120);
+        assert_eq!(catalog.missions[1].reward, Vec::new(), "Missing lists default to empty");
+    }
+
+    #[test]
+    fn test_bad_entries_are_skipped() {
+        let mut catalog = core_catalog();
+        let messy = pack(
+            r#"{
+                "name": "messy",
+                "items": [{ "id": "ore", "name": "Fake Ore" }, { "id": " ", "name": "Blank" }, { "id": "gas", "name": "Gas" }],
+                "upgrades": [
+                    { "id": "tank", "name": "Tank", "ships": ["cruiser"] },
+                    { "id": "scoop", "name": "Scoop", "cost": [{ "item": "unobtainium", "count": 1 }] },
+                    { "id": "gas-tank", "name": "Gas Tank", "cost": [{ "item": "gas", "count": 4 }] }
+                ]
+            }"#,
+        );
+        let warnings = add_pack(&mut catalog, messy);
+        assert_eq!(warnings.len(), 4, "{:?}", warnings);
+        assert!(warnings[0].contains("'ore' is already defined"));
+        assert!(warnings.iter().any(|w| w.contains("unknown ship class 'cruiser'")));
+        assert_eq!(catalog.items.iter().find(|i| i.id == "ore").unwrap().name, "Ore", "The first definition wins");
+        assert_eq!(catalog.upgrades.last().unwrap().id, "gas-tank", "Good entries still load");
+
+        assert_eq!(add_pack(&mut catalog, pack(r#"{ "name": "core" }"#)).len(), 1, "Pack names are unique");
+        assert_eq!(catalog.packs.len(), 2);
+    }
+
+    #[test]
+    fn test_read_dir_loads_json_in_name_order() {
+        let dir = std::env::temp_dir().join(format!("exospace-content-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("b.json"), r#"{ "name": "second" }"#).unwrap();
+        std::fs::write(dir.join("a.json"), r#"{ "name": "first" }"#).unwrap();
+        std::fs::write(dir.join("c.json"), "not json").unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+        let names: Vec<String> = read_dir(&dir).into_iter().map(|pack| pack.name).collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names, ["first", "second"], "Broken files are skipped");
+    }
+}
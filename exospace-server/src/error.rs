@@ -0,0 +1,258 @@
+//! Errors from the HTTP endpoints.
+//!
+//! Every handler turns a failed request into an `ApiError`, sent as
+//! `exospace_core::ErrorBody`: a machine-readable `ErrorCode` for clients to
+//! act on and a message to show the player. Domain errors (`AuthError`,
+//! `TradeError`, `MissionError`) convert into it. `ApiQuery`, `ApiJson` and `ApiPath` are
+//! `Query`, `Json` and `Path` whose rejections (a width that isn't a number,
+//! a body that isn't JSON) come back the same way instead of as axum's plain
+//! text, and unknown routes get `not_found_route`.
+
+use axum::{
+    Json,
+    extract::{FromRequest, FromRequestParts, Path, Query, Request},
+    http::{HeaderValue, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use exospace_core::{ErrorBody, ErrorCode, MIN_MAP_SIDE};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+use crate::admin::ADMIN_TOKEN_ENV;
+use crate::stations::DOCKING_RANGE;
+use crate::world::EDIT_REACH;
+
+/// A failed request
+#[derive(Debug, PartialEq)]
+pub enum ApiError {
+    /// A query string, body or path parameter that doesn't parse, with axum's status for it
+    Malformed(StatusCode, String),
+    /// A value out of bounds; the message says which and what's allowed
+    Invalid(String),
+    MapSize { max_width: usize, max_height: usize },
+    /// The message lists the algorithms there are
+    UnknownAlgorithm(String),
+    OutOfWorld,
+    NameTaken,
+    BadCredentials,
+    BadToken,
+    UnknownStation,
+    NotDocked,
+    UnknownItem,
+    /// How many the market has
+    ShortStock(u32),
+    UnknownMission,
+    MissionExpired,
+    /// Why the mission isn't done yet
+    MissionUnfinished(String),
+    MissionClaimed,
+    MissionNotAccepted,
+    /// The account's credits, short of the cost
+    ShortCredits(u32),
+    /// How many of the good the account's hold has, short of the order
+    ShortCargo(u32),
+    TileConflict,
+    OutOfReach,
+    /// Over a `throttle` budget, and how long until there's room
+    RateLimited(Duration),
+    AdminDisabled,
+    AdminToken,
+    NotFound(String),
+    Storage(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::Malformed(..) => ErrorCode::MalformedRequest,
+            ApiError::Invalid(_) => ErrorCode::Invalid,
+            ApiError::MapSize { .. } => ErrorCode::MapSize,
+            ApiError::UnknownAlgorithm(_) => ErrorCode::UnknownAlgorithm,
+            ApiError::OutOfWorld => ErrorCode::OutOfWorld,
+            ApiError::NameTaken => ErrorCode::NameTaken,
+            ApiError::BadCredentials => ErrorCode::BadCredentials,
+            ApiError::BadToken => ErrorCode::BadToken,
+            ApiError::UnknownStation => ErrorCode::UnknownStation,
+            ApiError::NotDocked => ErrorCode::NotDocked,
+            ApiError::UnknownItem => ErrorCode::UnknownItem,
+            ApiError::ShortStock(_) => ErrorCode::ShortStock,
+            ApiError::UnknownMission => ErrorCode::UnknownMission,
+            ApiError::MissionExpired => ErrorCode::MissionExpired,
+            ApiError::MissionUnfinished(_) => ErrorCode::MissionUnfinished,
+            ApiError::MissionClaimed => ErrorCode::MissionClaimed,
+            ApiError::MissionNotAccepted => ErrorCode::MissionNotAccepted,
+            ApiError::ShortCredits(_) => ErrorCode::ShortCredits,
+            ApiError::ShortCargo(_) => ErrorCode::ShortCargo,
+            ApiError::TileConflict => ErrorCode::TileConflict,
+            ApiError::OutOfReach => ErrorCode::OutOfReach,
+            ApiError::RateLimited(_) => ErrorCode::RateLimited,
+            ApiError::AdminDisabled => ErrorCode::AdminDisabled,
+            ApiError::AdminToken => ErrorCode::AdminToken,
+            ApiError::NotFound(_) => ErrorCode::NotFound,
+            ApiError::Storage(_) => ErrorCode::Storage,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Malformed(status, _) => *status,
+            ApiError::Invalid(_)
+            | ApiError::MapSize { .. }
+            | ApiError::UnknownAlgorithm(_)
+            | ApiError::OutOfWorld
+            | ApiError::UnknownItem => StatusCode::BAD_REQUEST,
+            ApiError::BadCredentials | ApiError::BadToken | ApiError::AdminToken => StatusCode::UNAUTHORIZED,
+            ApiError::AdminDisabled => StatusCode::FORBIDDEN,
+            ApiError::UnknownStation | ApiError::UnknownMission | ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::MissionExpired => StatusCode::GONE,
+            ApiError::NameTaken
+            | ApiError::ShortStock(_)
+            | ApiError::MissionUnfinished(_)
+            | ApiError::MissionClaimed
+            | ApiError::MissionNotAccepted
+            | ApiError::NotDocked
+            | ApiError::ShortCredits(_)
+            | ApiError::ShortCargo(_)
+            | ApiError::TileConflict
+            | ApiError::OutOfReach => StatusCode::CONFLICT,
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ApiError::Malformed(_, message)
+            | ApiError::Invalid(message)
+            | ApiError::UnknownAlgorithm(message)
+            | ApiError::MissionUnfinished(message)
+            | ApiError::NotFound(message)
+            | ApiError::Storage(message) => message.clone(),
+            ApiError::MapSize { max_width, max_height } => {
+                format!("Map size must be between {0}x{0} and {1}x{2}", MIN_MAP_SIDE, max_width, max_height)
+            }
+            ApiError::OutOfWorld => "Position is outside the world".to_string(),
+            ApiError::NameTaken => "That name is already registered".to_string(),
+            ApiError::BadCredentials => "Wrong name or password".to_string(),
+            ApiError::BadToken => "Invalid or expired token".to_string(),
+            ApiError::UnknownStation => "No such station".to_string(),
+            ApiError::NotDocked => format!("Dock at the station first (fly within {} tiles of it)", DOCKING_RANGE),
+            ApiError::UnknownItem => "This market doesn't trade that".to_string(),
+            ApiError::ShortStock(stock) => format!("Only {} in stock", stock),
+            ApiError::UnknownMission => "No such mission".to_string(),
+            ApiError::MissionExpired => "That mission is no longer on offer".to_string(),
+            ApiError::MissionClaimed => "You've already completed that mission".to_string(),
+            ApiError::MissionNotAccepted => "You haven't accepted that mission".to_string(),
+            ApiError::ShortCredits(held) => format!("Not enough credits ({} cr held)", held),
+            ApiError::ShortCargo(held) => format!("Not enough in your hold ({} aboard)", held),
+            ApiError::TileConflict => "That tile can't break that way".to_string(),
+            ApiError::OutOfReach => format!("Fly within {} tiles of the tile first", EDIT_REACH),
+            ApiError::RateLimited(wait) => format!("Too many requests; try again in {}s", retry_after_secs(*wait)),
+            ApiError::AdminDisabled => format!("Admin routes are disabled; set {} to enable them", ADMIN_TOKEN_ENV),
+            ApiError::AdminToken => "Missing or wrong admin token".to_string(),
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        ErrorBody { code: self.code(), error: self.message() }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status(), Json(self.body())).into_response();
+        if let ApiError::RateLimited(wait) = self {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs(wait)));
+        }
+        response
+    }
+}
+
+/// `Retry-After` is whole seconds; rounded up, so a client waiting that long finds room
+fn retry_after_secs(wait: Duration) -> u64 {
+    wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+}
+
+/// `Query`, rejecting bad query strings with an `ApiError`
+pub struct ApiQuery<T>(pub T);
+
+impl<T: DeserializeOwned, S: Send + Sync> FromRequestParts<S> for ApiQuery<T> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, ApiError> {
+        match Query::<T>::from_request_parts(parts, state).await {
+            Ok(Query(value)) => Ok(ApiQuery(value)),
+            Err(rejection) => Err(ApiError::Malformed(rejection.status(), rejection.body_text())),
+        }
+    }
+}
+
+/// `Path`, rejecting bad path parameters with an `ApiError`
+pub struct ApiPath<T>(pub T);
+
+impl<T: DeserializeOwned + Send, S: Send + Sync> FromRequestParts<S> for ApiPath<T> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, ApiError> {
+        match Path::<T>::from_request_parts(parts, state).await {
+            Ok(Path(value)) => Ok(ApiPath(value)),
+            Err(rejection) => Err(ApiError::Malformed(rejection.status(), rejection.body_text())),
+        }
+    }
+}
+
+/// `Json`, rejecting bad bodies with an `ApiError`
+pub struct ApiJson<T>(pub T);
+
+impl<T: DeserializeOwned, S: Send + Sync> FromRequest<S> for ApiJson<T> {
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, ApiError> {
+        match Json::<T>::from_request(request, state).await {
+            Ok(Json(value)) => Ok(ApiJson(value)),
+            Err(rejection) => Err(ApiError::Malformed(rejection.status(), rejection.body_text())),
+        }
+    }
+}
+
+/// The router's fallback, so a mistyped route gets an error body too
+pub async fn not_found_route() -> ApiError {
+    ApiError::NotFound("No such route".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    // ==================== Response Tests ====================
+
+    #[tokio::test]
+    async fn test_errors_carry_a_code_and_message() {
+        let response = ApiError::ShortStock(3).into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: ErrorBody = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, ErrorBody { code: ErrorCode::ShortStock, error: "Only 3 in stock".to_string() });
+    }
+
+    #[test]
+    fn test_rate_limited_says_when_to_retry() {
+        let error = ApiError::RateLimited(Duration::from_millis(2100));
+        assert_eq!(error.message(), "Too many requests; try again in 3s");
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "3", "Rounded up to whole seconds");
+    }
+
+    #[test]
+    fn test_statuses_follow_the_error() {
+        assert_eq!(ApiError::MapSize { max_width: 100, max_height: 50 }.message(), "Map size must be between 3x3 and 100x50");
+        assert_eq!(ApiError::Malformed(StatusCode::UNSUPPORTED_MEDIA_TYPE, String::new()).status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(ApiError::BadToken.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(ApiError::AdminDisabled.status(), StatusCode::FORBIDDEN);
+        assert_eq!(ApiError::UnknownStation.status(), StatusCode::NOT_FOUND);
+        assert_eq!(ApiError::Storage("disk full".to_string()).code(), ErrorCode::Storage);
+        assert_eq!(ApiError::Storage("disk full".to_string()).status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,81 @@
+//! Conditional requests for maps and chunks.
+//!
+//! `/map` and `/map/chunk` answers carry a weak ETag made from everything
+//! their content depends on: this server's build (which decides the
+//! generators), the query that picked the map or chunk, the encoding, and for
+//! the world's own chunks the world version of their latest edit. A client
+//! that sends the tag back in `If-None-Match` gets an empty 304 when nothing
+//! has changed; `/map` then skips generating the map at all. Tags are weak
+//! because compression changes the bytes but not what they mean.
+
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use exospace_core::fnv1a;
+use std::fmt::Display;
+
+/// The tag for content described by `what`, as `W/"<16 hex digits>"`
+pub fn tag(what: impl Display) -> HeaderValue {
+    let described = format!("{} {}", env!("CARGO_PKG_VERSION"), what);
+    let hash = fnv1a(described.as_bytes());
+    HeaderValue::from_str(&format!("W/\"{:016x}\"", hash)).expect("A hex tag is a valid header")
+}
+
+/// Whether the request's `If-None-Match` names this tag (or `*`), comparing weakly
+pub fn matches(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let Ok(ours) = etag.to_str().map(opaque) else {
+        return false;
+    };
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || opaque(tag) == ours)
+}
+
+/// The answer to a request whose copy is still current: 304, no body
+pub fn not_modified(etag: HeaderValue) -> Response {
+    (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response()
+}
+
+/// Attach the tag to a full answer
+pub fn tagged(mut response: Response, etag: HeaderValue) -> Response {
+    response.headers_mut().insert(header::ETAG, etag);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Tag Tests ====================
+
+    #[test]
+    fn test_tags_follow_content() {
+        assert_eq!(tag("chunk 1 2"), tag("chunk 1 2"));
+        assert_ne!(tag("chunk 1 2"), tag("chunk 2 1"));
+        let text = tag("map").to_str().unwrap().to_string();
+        assert!(text.starts_with("W/\"") && text.ends_with('"') && text.len() == 20, "{}", text);
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let etag = tag("chunk 1 2");
+        let with = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::IF_NONE_MATCH, value.parse().unwrap());
+            headers
+        };
+        let strong = etag.to_str().unwrap().trim_start_matches("W/").to_string();
+
+        assert!(!matches(&HeaderMap::new(), &etag), "Nothing to compare with");
+        assert!(matches(&with(etag.to_str().unwrap()), &etag));
+        assert!(matches(&with(&strong), &etag), "Compared weakly");
+        assert!(matches(&with(&format!("\"other\", {}", etag.to_str().unwrap())), &etag), "One of a list");
+        assert!(matches(&with("*"), &etag));
+        assert!(!matches(&with("W/\"0000000000000000\""), &etag));
+    }
+}
@@ -0,0 +1,271 @@
+//! Regional events: meteor showers and ion storms.
+//!
+//! Every `EVENT_INTERVAL` the `EventScheduler` system may start an event
+//! near a pilot, as long as fewer than `MAX_EVENTS` are under way. It's sent
+//! to everyone as `ServerMessage::EventStarted` with a chat line from
+//! `ANNOUNCER`, and when its time is up as `EventEnded` with another. What an
+//! event does to its area is worked out from it by `WorldEvent` (`blocks()`
+//! for a shower's falling rock, `damages()` inside a storm), the same on
+//! both ends; like the ion storms in nebulae, it's the client that applies
+//! them to its ship. Pilots who connect mid-event are sent each one still
+//! under way after Welcome. Events happen in the home system. Admins can
+//! start one with `POST /admin/event`.
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use exospace_core::{EventKind, ServerMessage, WorldEvent};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+use crate::admin::admin_rejection;
+use crate::error::{ApiError, ApiJson};
+use crate::sessions::SessionRegistry;
+use crate::tick::System;
+use crate::utility::DiceRng;
+use crate::world;
+
+/// How often the scheduler considers starting an event
+pub const EVENT_INTERVAL: Duration = Duration::from_secs(90);
+
+/// Chance, in percent, that a round starts one
+const EVENT_CHANCE_PERCENT: u32 = 60;
+
+/// Most events under way at once
+pub const MAX_EVENTS: usize = 3;
+
+/// How long a scheduled event lasts, in seconds
+const DURATION_SECS: (u32, u32) = (60, 180);
+
+/// Radius of a scheduled event, in tiles
+const RADIUS: (u32, u32) = (8, 20);
+
+/// Largest radius and duration an admin can ask for
+const MAX_RADIUS: u32 = 60;
+const MAX_DURATION_SECS: u32 = 3600;
+
+/// Furthest a scheduled event is centered from the pilot it's placed near, on each axis
+const MAX_OFFSET: u32 = 30;
+
+/// Who events are announced by in pilots' chat
+pub const ANNOUNCER: &str = "Sector Watch";
+
+/// Body of `POST /admin/event`
+#[derive(Deserialize)]
+pub struct EventRequest {
+    kind: EventKind,
+    x: i32,
+    y: i32,
+    radius: u32,
+    secs: u32,
+}
+
+/// The events under way, with when each ends
+#[derive(Default)]
+pub struct Events {
+    active: Mutex<Vec<(WorldEvent, Instant)>>,
+    next_id: AtomicU64,
+}
+
+impl Events {
+    /// Record a new event lasting `secs` from `now`
+    pub fn start(&self, kind: EventKind, (x, y): (i32, i32), radius: u32, secs: u32, now: Instant) -> WorldEvent {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let event = WorldEvent { id, kind, x, y, radius, secs_left: secs };
+        self.active.lock().unwrap().push((event.clone(), now + Duration::from_secs(secs as u64)));
+        event
+    }
+
+    /// Every event still under way at `now`, with the whole seconds it has left (rounded up)
+    pub fn active(&self, now: Instant) -> Vec<WorldEvent> {
+        let active = self.active.lock().unwrap();
+        active
+            .iter()
+            .filter(|&&(_, ends)| ends > now)
+            .map(|(event, ends)| WorldEvent { secs_left: ends.duration_since(now).as_secs_f32().ceil() as u32, ..event.clone() })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.active.lock().unwrap().len()
+    }
+
+    /// Take out the events over by `now`
+    pub fn expire(&self, now: Instant) -> Vec<WorldEvent> {
+        let mut active = self.active.lock().unwrap();
+        let (over, still): (Vec<_>, Vec<_>) = active.drain(..).partition(|&(_, ends)| ends <= now);
+        *active = still;
+        over.into_iter().map(|(event, _)| event).collect()
+    }
+}
+
+/// The chat line announcing an event
+pub fn announcement(event: &WorldEvent) -> String {
+    let minutes = event.secs_left.div_ceil(60);
+    let what = match event.kind {
+        EventKind::MeteorShower => "Meteor shower",
+        EventKind::IonStorm => "Ion storm",
+        EventKind::Unknown => "Disturbance",
+    };
+    format!("{} reported near ({}, {}), {} tiles across, for about {} min", what, event.x, event.y, event.radius * 2, minutes)
+}
+
+/// Tell every pilot an event started
+pub fn broadcast_start(sessions: &SessionRegistry, event: WorldEvent) {
+    let text = announcement(&event);
+    sessions.announce(ServerMessage::EventStarted { event });
+    sessions.announce(ServerMessage::Ambient { source: ANNOUNCER.to_string(), text });
+}
+
+/// Starts events near pilots and ends them when their time is up
+pub struct EventScheduler {
+    events: Arc<Events>,
+    sessions: Arc<SessionRegistry>,
+    rng: DiceRng,
+    next_round: Instant,
+}
+
+impl EventScheduler {
+    pub fn new(events: Arc<Events>, sessions: Arc<SessionRegistry>, rng: DiceRng, now: Instant) -> Self {
+        // Events start near a pilot, and a server that's just started has none to pick
+        EventScheduler { events, sessions, rng, next_round: now + EVENT_INTERVAL }
+    }
+
+    /// A number from `low` to `high`, inclusive
+    fn between(&mut self, (low, high): (u32, u32)) -> u32 {
+        low + self.rng.roll_die(high - low + 1) - 1
+    }
+
+    /// Maybe start an event near a random pilot; None when nobody's online, the dice say no or too many are under way
+    fn schedule(&mut self, now: Instant) -> Option<WorldEvent> {
+        let mut pilots = self.sessions.others(0);
+        pilots.retain(|p| self.sessions.system(p.id).is_none());
+        if pilots.is_empty() || self.events.len() >= MAX_EVENTS || self.rng.roll_die(100) > EVENT_CHANCE_PERCENT {
+            return None;
+        }
+        let pilot = &pilots[self.rng.roll_die(pilots.len() as u32) as usize - 1];
+        let kind = EventKind::ALL[self.rng.roll_die(EventKind::ALL.len() as u32) as usize - 1];
+        let dx = self.between((0, MAX_OFFSET * 2)) as i32 - MAX_OFFSET as i32;
+        let dy = self.between((0, MAX_OFFSET * 2)) as i32 - MAX_OFFSET as i32;
+        let (radius, secs) = (self.between(RADIUS), self.between(DURATION_SECS));
+        Some(self.events.start(kind, (pilot.x.saturating_add(dx), pilot.y.saturating_add(dy)), radius, secs, now))
+    }
+}
+
+impl System for EventScheduler {
+    fn name(&self) -> &'static str {
+        "events"
+    }
+
+    fn tick(&mut self, now: Instant) {
+        for event in self.events.expire(now) {
+            self.sessions.announce(ServerMessage::EventEnded { id: event.id });
+            let text = format!("The {} near ({}, {}) has passed", event.kind.name(), event.x, event.y);
+            self.sessions.announce(ServerMessage::Ambient { source: ANNOUNCER.to_string(), text });
+        }
+        if now < self.next_round {
+            return;
+        }
+        self.next_round = now + EVENT_INTERVAL;
+        if let Some(event) = self.schedule(now) {
+            broadcast_start(&self.sessions, event);
+        }
+    }
+}
+
+/// `POST /admin/event`: start an event now; replies with it as JSON
+pub async fn start_event(State(state): State<AppState>, headers: HeaderMap, ApiJson(request): ApiJson<EventRequest>) -> Response {
+    if let Some(rejection) = admin_rejection(&state, &headers) {
+        return rejection;
+    }
+    if request.kind == EventKind::Unknown {
+        return ApiError::Invalid("Unknown event kind (expected meteor_shower or ion_storm)".to_string()).into_response();
+    }
+    if !(1..=MAX_RADIUS).contains(&request.radius) || !(1..=MAX_DURATION_SECS).contains(&request.secs) {
+        let reason = format!("Events are 1-{} tiles in radius and last 1-{} seconds", MAX_RADIUS, MAX_DURATION_SECS);
+        return ApiError::Invalid(reason).into_response();
+    }
+    if !world::in_world(request.x, request.y) {
+        return ApiError::OutOfWorld.into_response();
+    }
+    let event = state.events.start(request.kind, (request.x, request.y), request.radius, request.secs, Instant::now());
+    broadcast_start(&state.sessions, event.clone());
+    (StatusCode::CREATED, Json(event)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::Direction;
+
+    // ==================== Event Tests ====================
+
+    #[test]
+    fn test_events_expire_and_count_down() {
+        let events = Events::default();
+        let start = Instant::now();
+        let storm = events.start(EventKind::IonStorm, (5, 5), 10, 60, start);
+        let shower = events.start(EventKind::MeteorShower, (-5, 0), 8, 120, start);
+        assert_ne!(storm.id, shower.id, "Every event has its own id");
+        assert_eq!(events.active(start + Duration::from_millis(30_500)).iter().map(|e| e.secs_left).collect::<Vec<_>>(), [30, 90]);
+
+        assert!(events.expire(start + Duration::from_secs(59)).is_empty());
+        assert_eq!(events.expire(start + Duration::from_secs(60)), vec![storm]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events.active(start + Duration::from_secs(60)).len(), 1, "The shower goes on");
+        assert_eq!(events.expire(start + Duration::from_secs(600)).len(), 1);
+        assert!(events.active(start).is_empty());
+    }
+
+    #[test]
+    fn test_announcement() {
+        let event = WorldEvent { id: 1, kind: EventKind::MeteorShower, x: 40, y: -12, radius: 15, secs_left: 90 };
+        assert_eq!(announcement(&event), "Meteor shower reported near (40, -12), 30 tiles across, for about 2 min");
+    }
+
+    // ==================== Scheduler Tests ====================
+
+    #[test]
+    fn test_scheduler_places_events_near_pilots() {
+        let sessions = Arc::new(SessionRegistry::default());
+        let events = Arc::new(Events::default());
+        let start = Instant::now();
+        let mut scheduler = EventScheduler::new(events.clone(), sessions.clone(), DiceRng::new(7), start);
+        assert_eq!(scheduler.schedule(start), None, "Nobody online, nobody to see it");
+
+        let (pilot, _rx) = sessions.join("Ace");
+        sessions.update_position(pilot.id, 1000, -400, Direction::Up);
+        let started: Vec<WorldEvent> = (0..50).filter_map(|_| scheduler.schedule(start)).collect();
+        assert_eq!(started.len(), MAX_EVENTS, "No more than {} at once", MAX_EVENTS);
+        for event in &started {
+            assert!(event.x.abs_diff(1000) <= MAX_OFFSET && event.y.abs_diff(-400) <= MAX_OFFSET, "{:?} is near the pilot", event);
+            assert!((RADIUS.0..=RADIUS.1).contains(&event.radius) && (DURATION_SECS.0..=DURATION_SECS.1).contains(&event.secs_left));
+        }
+    }
+
+    #[test]
+    fn test_system_announces_start_and_end() {
+        let sessions = Arc::new(SessionRegistry::default());
+        let (_, mut rx) = sessions.join("Ace");
+        while rx.try_recv().is_ok() {}
+        let events = Arc::new(Events::default());
+        let start = Instant::now();
+        let mut scheduler = EventScheduler::new(events.clone(), sessions.clone(), DiceRng::new(1), start);
+
+        scheduler.tick(start + EVENT_INTERVAL / 2);
+        assert!(rx.try_recv().is_err(), "The first round waits a full interval");
+
+        let event = events.start(EventKind::IonStorm, (0, 0), 10, 5, start);
+        scheduler.tick(start + Duration::from_secs(5));
+        assert_eq!(rx.try_recv().unwrap().message, ServerMessage::EventEnded { id: event.id });
+        let passed = ServerMessage::Ambient { source: ANNOUNCER.to_string(), text: "The ion storm near (0, 0) has passed".to_string() };
+        assert_eq!(rx.try_recv().unwrap().message, passed);
+        assert_eq!(events.len(), 0);
+    }
+}
@@ -0,0 +1,256 @@
+//! Interest management: each session only hears about what's near its pilot.
+//!
+//! The world is cut into `BUCKET_SIZE` square buckets and `InterestGrid`
+//! (kept by the `SessionRegistry`) knows which bucket every pilot and NPC
+//! ship is in. A session is subscribed to the buckets within
+//! `INTEREST_RADIUS` of its pilot's. Its `Interest` remembers what its client
+//! has been sent, so when a delta comes through the moves are cut down to the
+//! subscribed buckets, and whatever came into range or went out of it (the
+//! pilot crossed into another bucket, or something else did) goes out first
+//! as `ServerMessage::AreaChanged`. Tile changes aren't filtered: clients cache
+//! chunks they have flown away from. A pilot inside a nebula counts as out
+//! of range to anyone further than `NEBULA_SENSOR_RANGE`. Deltas keep their numbers even when
+//! nothing in them is in range, so clients still see no gap.
+
+use exospace_core::{EntityId, EntityKind, ServerMessage};
+use std::collections::{HashMap, HashSet};
+
+use crate::npc::NpcRoster;
+use crate::sessions::SessionRegistry;
+
+/// Width and height of a bucket, in tiles
+pub const BUCKET_SIZE: i32 = 64;
+
+/// Buckets each way from the pilot's that a session is subscribed to
+pub const INTEREST_RADIUS: i32 = 1;
+
+pub type Bucket = (i32, i32);
+
+pub fn bucket_of(x: i32, y: i32) -> Bucket {
+    (x.div_euclid(BUCKET_SIZE), y.div_euclid(BUCKET_SIZE))
+}
+
+/// Which bucket each pilot and NPC ship is in
+#[derive(Default)]
+pub struct InterestGrid {
+    buckets: HashMap<Bucket, HashSet<EntityId>>,
+    located: HashMap<EntityId, Bucket>,
+}
+
+impl InterestGrid {
+    /// Put an entity in the bucket for (x, y), taking it out of its old one
+    pub fn place(&mut self, entity: EntityId, x: i32, y: i32) {
+        let bucket = bucket_of(x, y);
+        match self.located.insert(entity, bucket) {
+            Some(old) if old == bucket => return,
+            Some(old) => self.take_out(entity, old),
+            None => {}
+        }
+        self.buckets.entry(bucket).or_default().insert(entity);
+    }
+
+    pub fn remove(&mut self, entity: EntityId) {
+        if let Some(old) = self.located.remove(&entity) {
+            self.take_out(entity, old);
+        }
+    }
+
+    fn take_out(&mut self, entity: EntityId, bucket: Bucket) {
+        if let Some(members) = self.buckets.get_mut(&bucket) {
+            members.remove(&entity);
+            if members.is_empty() {
+                self.buckets.remove(&bucket);
+            }
+        }
+    }
+
+    pub fn bucket(&self, entity: EntityId) -> Option<Bucket> {
+        self.located.get(&entity).copied()
+    }
+
+    /// Everything in the buckets a pilot in `center` is subscribed to
+    pub fn around(&self, center: Bucket) -> HashSet<EntityId> {
+        let mut near = HashSet::new();
+        for by in center.1 - INTEREST_RADIUS..=center.1 + INTEREST_RADIUS {
+            for bx in center.0 - INTEREST_RADIUS..=center.0 + INTEREST_RADIUS {
+                if let Some(members) = self.buckets.get(&(bx, by)) {
+                    near.extend(members);
+                }
+            }
+        }
+        near
+    }
+}
+
+/// What one session's client knows about, so it's only told about changes
+#[derive(Debug, Default)]
+pub struct Interest {
+    known: HashSet<EntityId>,
+}
+
+impl Interest {
+    /// The client was sent these (`Welcome`, `PlayerJoined`, an NPC snapshot)
+    pub fn sent(&mut self, entities: impl IntoIterator<Item = EntityId>) {
+        self.known.extend(entities);
+    }
+
+    /// The client was sent a full `Snapshot`, replacing what it had
+    pub fn reset(&mut self, entities: impl IntoIterator<Item = EntityId>) {
+        self.known = entities.into_iter().collect();
+    }
+
+    pub fn forget(&mut self, entity: EntityId) {
+        self.known.remove(&entity);
+    }
+
+    /// Cut a `WorldDelta` down to session `id`'s area, preceded by an `AreaChanged` when
+    /// something came into or went out of range; anything else passes through
+    pub fn filter(&mut self, sessions: &SessionRegistry, npcs: &NpcRoster, id: u64, message: ServerMessage) -> Vec<ServerMessage> {
+        let ServerMessage::WorldDelta { seq, moved, tiles } = message else {
+            return vec![message];
+        };
+        let me = EntityId { kind: EntityKind::Player, id };
+        let mut near = sessions.around(me);
+        near.remove(&me);
+        // Pilots in other star systems aren't here at all, and NPCs and tile edits are the home world's
+        let system = sessions.system(id);
+        near.retain(|e| match e.kind {
+            EntityKind::Player => sessions.system(e.id) == system,
+            _ => system.is_none(),
+        });
+        let tiles = if system.is_none() { tiles } else { Vec::new() };
+        // Pilots in a nebula drop off our sensors unless we're close
+        if let Some(pilot) = sessions.get(id) {
+            near.retain(|e| e.kind != EntityKind::Player || !sessions.hidden_from(e.id, (pilot.x, pilot.y)));
+        }
+
+        let mut messages = Vec::new();
+        let entered: Vec<EntityId> = near.difference(&self.known).copied().collect();
+        let mut gone: Vec<EntityId> = self.known.difference(&near).copied().collect();
+        if !entered.is_empty() || !gone.is_empty() {
+            gone.sort();
+            let mut players: Vec<_> = entered.iter().filter(|e| e.kind == EntityKind::Player).filter_map(|e| sessions.get(e.id)).collect();
+            players.sort_by_key(|p| p.id);
+            let mut ships = Vec::new();
+            if entered.iter().any(|e| e.kind == EntityKind::Npc) {
+                ships = npcs.snapshot();
+                ships.retain(|npc| entered.contains(&EntityId { kind: EntityKind::Npc, id: npc.id }));
+            }
+            messages.push(ServerMessage::AreaChanged { players, npcs: ships, gone });
+        }
+
+        let moved = moved.into_iter().filter(|e| near.contains(&EntityId { kind: e.kind, id: e.id })).collect();
+        messages.push(ServerMessage::WorldDelta { seq, moved, tiles });
+        self.known = near;
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::{Direction, EntityMove};
+
+    fn player(id: u64) -> EntityId {
+        EntityId { kind: EntityKind::Player, id }
+    }
+
+    fn delta(seq: u64, moved: Vec<EntityMove>) -> ServerMessage {
+        ServerMessage::WorldDelta { seq, moved, tiles: Vec::new() }
+    }
+
+    // ==================== Grid Tests ====================
+
+    #[test]
+    fn test_grid_buckets() {
+        assert_eq!(bucket_of(0, 0), (0, 0));
+        assert_eq!(bucket_of(BUCKET_SIZE - 1, -1), (0, -1), "Negative coordinates round down");
+        assert_eq!(bucket_of(-BUCKET_SIZE, BUCKET_SIZE), (-1, 1));
+
+        let mut grid = InterestGrid::default();
+        grid.place(player(1), 5, 5);
+        grid.place(player(2), BUCKET_SIZE + 5, 5);
+        grid.place(player(3), BUCKET_SIZE * 3, 0);
+        assert_eq!(grid.around((0, 0)), HashSet::from([player(1), player(2)]), "Neighbouring buckets are in range");
+
+        grid.place(player(1), BUCKET_SIZE * 3 + 1, 0);
+        assert_eq!(grid.bucket(player(1)), Some((3, 0)));
+        assert_eq!(grid.around((0, 0)), HashSet::from([player(2)]), "Moving takes it out of the old bucket");
+        grid.remove(player(2));
+        assert!(grid.around((0, 0)).is_empty());
+        assert!(!grid.buckets.contains_key(&(1, 0)), "Empty buckets are dropped");
+    }
+
+    // ==================== Filter Tests ====================
+
+    #[test]
+    fn test_filter_subscribes_as_pilots_cross_buckets() {
+        let sessions = SessionRegistry::default();
+        let npcs = NpcRoster::default();
+        let (a, _) = sessions.join("Alpha");
+        let (b, _) = sessions.join("Bravo");
+        let mut interest = Interest::default();
+        interest.sent([player(b.id)]);
+
+        let far = BUCKET_SIZE * 5;
+        let moved = |x| EntityMove { kind: EntityKind::Player, id: b.id, x, y: 0, direction: Direction::Right };
+        sessions.update_position(b.id, 10, 0, Direction::Right);
+        assert_eq!(interest.filter(&sessions, &npcs, a.id, delta(1, vec![moved(10)])), [delta(1, vec![moved(10)])], "Nearby moves pass");
+
+        sessions.update_position(b.id, far, 0, Direction::Right);
+        let gone = ServerMessage::AreaChanged { players: Vec::new(), npcs: Vec::new(), gone: vec![player(b.id)] };
+        assert_eq!(interest.filter(&sessions, &npcs, a.id, delta(2, vec![moved(far)])), [gone, delta(2, Vec::new())], "Out of range is unsubscribed");
+        assert_eq!(interest.filter(&sessions, &npcs, a.id, delta(3, vec![moved(far)])), [delta(3, Vec::new())], "Numbers are kept");
+
+        sessions.update_position(a.id, far - 1, 0, Direction::Right);
+        let bravo = sessions.get(b.id).unwrap();
+        let messages = interest.filter(&sessions, &npcs, a.id, delta(4, Vec::new()));
+        assert_eq!(messages[0], ServerMessage::AreaChanged { players: vec![bravo], npcs: Vec::new(), gone: Vec::new() }, "Flying over subscribes");
+        assert_eq!(interest.filter(&sessions, &npcs, a.id, ServerMessage::PlayerLeft { id: 9 }).len(), 1, "Other messages pass");
+    }
+
+    #[test]
+    fn test_filter_keeps_to_the_pilots_system() {
+        let sessions = SessionRegistry::default();
+        let npcs = NpcRoster::default();
+        let (a, _) = sessions.join("Alpha");
+        let (b, _) = sessions.join("Bravo");
+        let mut interest = Interest::default();
+        interest.sent([player(b.id)]);
+        sessions.update_position(b.id, 10, 0, Direction::Right);
+
+        let moved = EntityMove { kind: EntityKind::Player, id: b.id, x: 10, y: 0, direction: Direction::Right };
+        let change = exospace_core::TileChange { x: 1, y: 1, tile: exospace_core::Tile::Floor };
+        let edit = ServerMessage::WorldDelta { seq: 1, moved: vec![moved.clone()], tiles: vec![change] };
+        sessions.set_system(a.id, Some(7));
+        let gone = ServerMessage::AreaChanged { players: Vec::new(), npcs: Vec::new(), gone: vec![player(b.id)] };
+        assert_eq!(interest.filter(&sessions, &npcs, a.id, edit.clone()), [gone, delta(1, Vec::new())], "Nothing from the home world");
+
+        sessions.set_system(b.id, Some(7));
+        let messages = interest.filter(&sessions, &npcs, a.id, edit);
+        assert_eq!(messages.len(), 2, "Bravo followed");
+        assert_eq!(messages[1], delta(1, vec![moved]));
+    }
+
+    #[test]
+    fn test_filter_hides_pilots_in_nebulae() {
+        let sessions = SessionRegistry::default();
+        let npcs = NpcRoster::default();
+        let (a, _) = sessions.join("Alpha");
+        let (b, _) = sessions.join("Bravo");
+        let mut interest = Interest::default();
+        interest.sent([player(b.id)]);
+        sessions.update_position(a.id, 0, 0, Direction::Right);
+        sessions.update_position(b.id, 20, 0, Direction::Left);
+
+        let moved = |x| EntityMove { kind: EntityKind::Player, id: b.id, x, y: 0, direction: Direction::Left };
+        sessions.set_in_nebula(b.id, true);
+        let gone = ServerMessage::AreaChanged { players: Vec::new(), npcs: Vec::new(), gone: vec![player(b.id)] };
+        assert_eq!(interest.filter(&sessions, &npcs, a.id, delta(1, vec![moved(20)])), [gone, delta(1, Vec::new())], "Hidden in the nebula");
+
+        sessions.update_position(b.id, 4, 0, Direction::Left);
+        let messages = interest.filter(&sessions, &npcs, a.id, delta(2, vec![moved(4)]));
+        assert_eq!(messages.len(), 2, "Close enough to see through it");
+        assert_eq!(messages[1], delta(2, vec![moved(4)]));
+    }
+}
@@ -0,0 +1,81 @@
+//! Leaderboards: logged-in pilots ranked by distance flown, ore mined or
+//! credits earned.
+//!
+//! Rankings come straight from the world's store, out of the totals in
+//! `stats.rs` and the ledger behind `/player/balance`, so they cover every
+//! account that has flown here, online or not. Guests aren't ranked. `GET
+//! /leaderboard` pages through them.
+
+use axum::{
+    Json,
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use exospace_core::{Leaderboard, LeaderboardEntry, LeaderboardMetric};
+use serde::Deserialize;
+
+use crate::AppState;
+use crate::error::{ApiError, ApiQuery};
+
+/// Pilots per page unless asked otherwise
+pub const DEFAULT_PER_PAGE: u32 = 10;
+
+/// Most pilots one page lists
+pub const MAX_PER_PAGE: u32 = 50;
+
+/// Query parameters for `GET /leaderboard`
+#[derive(Deserialize)]
+pub struct LeaderboardQuery {
+    /// "distance" (the default), "ore" or "credits"
+    #[serde(default)]
+    metric: Option<String>,
+    /// From 1
+    #[serde(default = "first_page")]
+    page: u32,
+    #[serde(default = "default_per_page")]
+    per_page: u32,
+}
+
+fn first_page() -> u32 {
+    1
+}
+
+fn default_per_page() -> u32 {
+    DEFAULT_PER_PAGE
+}
+
+/// Handler for `GET /leaderboard`: a page past the end is empty
+pub async fn get_leaderboard(State(state): State<AppState>, ApiQuery(params): ApiQuery<LeaderboardQuery>) -> Response {
+    let metric = match params.metric.as_deref() {
+        None => LeaderboardMetric::Distance,
+        Some(name) => match LeaderboardMetric::from_name(name) {
+            Some(metric) => metric,
+            None => {
+                let names: Vec<&str> = LeaderboardMetric::ALL.iter().map(|metric| metric.name()).collect();
+                return ApiError::Invalid(format!("Unknown metric '{}' (try {})", name, names.join(", "))).into_response();
+            }
+        },
+    };
+    if params.page == 0 {
+        return ApiError::Invalid("Pages start at 1".to_string()).into_response();
+    }
+    if !(1..=MAX_PER_PAGE).contains(&params.per_page) {
+        return ApiError::Invalid(format!("per_page must be 1-{}", MAX_PER_PAGE)).into_response();
+    }
+    let offset = (params.page as usize - 1) * params.per_page as usize;
+    let (ranked, total) = match state.world.leaderboard(metric, offset, params.per_page as usize) {
+        Ok(ranked) => ranked,
+        Err(e) => return ApiError::Storage(e).into_response(),
+    };
+    let entries = ranked
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, value))| LeaderboardEntry {
+            rank: (offset + i + 1) as u32,
+            name: state.users.display_name(&name).unwrap_or(name),
+            value,
+        })
+        .collect();
+    let pages = total.div_ceil(params.per_page as usize).max(1) as u32;
+    Json(Leaderboard { metric, page: params.page, pages, entries }).into_response()
+}
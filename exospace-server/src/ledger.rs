@@ -0,0 +1,149 @@
+//! Pilots' credits: a balance per account and the ledger of what moved it.
+//!
+//! Logged-in pilots earn and spend credits on the server. Mission rewards
+//! are paid in, market trades move credits both ways, and station repairs
+//! cost `REPAIR_PRICE` per hull point. Every change is a `Transaction`
+//! saved by the world's store along with the new balance, and a charge the
+//! balance can't cover is refused with `ShortCredits`. Guests keep their
+//! credits on their own side, as before. `GET /player/balance` and `GET
+//! /player/transactions` show a pilot their own account.
+//!
+//! The server keeps an account's hold too, so what it sells is what it has:
+//! mined tiles (`/tile/update`) and purchases go in, sales and mission
+//! deliveries come out, and `stow()` refuses to take out more than is
+//! aboard with `ShortCargo`.
+
+use axum::{
+    Json,
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use exospace_core::{Balance, REPAIR_PRICE, RepairOrder, RepairReceipt, TransactionHistory, TransactionKind};
+
+use crate::AppState;
+use crate::auth::{self, AuthError};
+use crate::error::{ApiError, ApiJson, ApiPath};
+use crate::world::World;
+
+/// Transactions `GET /player/transactions` lists
+pub const RECENT_TRANSACTIONS: usize = 20;
+
+/// Most hull points one repair patches
+pub const MAX_REPAIR: u32 = 10_000;
+
+/// Move an account's credits by `amount` and record why; the balance after, or `ShortCredits`
+/// if the account can't cover a charge
+pub fn settle(world: &World, account: &str, kind: TransactionKind, amount: i64, memo: &str) -> Result<u32, ApiError> {
+    let storage = |e: String| {
+        tracing::warn!("Failed to save credits for {}: {}", account, e);
+        ApiError::Storage("Couldn't save your credits".to_string())
+    };
+    if amount == 0 {
+        return world.balance(account).map_err(storage);
+    }
+    match world.post_transaction(account, kind, amount, memo).map_err(storage)? {
+        Some(transaction) => Ok(transaction.balance),
+        None => Err(ApiError::ShortCredits(world.balance(account).map_err(storage)?)),
+    }
+}
+
+/// Move `amount` of a good in or out of an account's hold; the count after, or `ShortCargo` if there isn't
+/// that much aboard
+pub fn stow(world: &World, account: &str, item: &str, amount: i64) -> Result<u32, ApiError> {
+    let storage = |e: String| {
+        tracing::warn!("Failed to save the hold of {}: {}", account, e);
+        ApiError::Storage("Couldn't save your hold".to_string())
+    };
+    match world.stow(account, item, amount).map_err(storage)? {
+        Some(count) => Ok(count),
+        None => Err(ApiError::ShortCargo(world.cargo(account, item).map_err(storage)?)),
+    }
+}
+
+/// Put goods in an account's hold where a refusal isn't an option: bought, earned, or handed back
+pub fn restow(world: &World, account: &str, item: &str, quantity: i64) {
+    if let Err(e) = world.stow(account, item, quantity) {
+        tracing::warn!("Failed to stow {} {} for {}: {}", quantity, item, account, e);
+    }
+}
+
+/// Handler for `GET /player/balance`
+pub async fn get_balance(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let Some(name) = auth::bearer_token(&headers).and_then(|token| state.users.verify(token)) else {
+        return AuthError::BadToken.into_response();
+    };
+    match state.world.balance(&name) {
+        Ok(credits) => Json(Balance { name, credits }).into_response(),
+        Err(e) => ApiError::Storage(e).into_response(),
+    }
+}
+
+/// Handler for `GET /player/transactions`: the latest `RECENT_TRANSACTIONS`
+pub async fn get_transactions(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let Some(name) = auth::bearer_token(&headers).and_then(|token| state.users.verify(token)) else {
+        return AuthError::BadToken.into_response();
+    };
+    match state.world.transactions(&name, RECENT_TRANSACTIONS) {
+        Ok(transactions) => Json(TransactionHistory { transactions }).into_response(),
+        Err(e) => ApiError::Storage(e).into_response(),
+    }
+}
+
+/// Handler for `POST /station/{id}/repair`: what patching the hull costs, charged to an account; guests pay
+/// from their own credits. How much needs patching is the pilot's word, as their hold is for trades
+pub async fn repair(
+    State(state): State<AppState>,
+    ApiPath(id): ApiPath<String>,
+    headers: HeaderMap,
+    ApiJson(order): ApiJson<RepairOrder>,
+) -> Response {
+    let account = match auth::optional_account(&state.users, &headers) {
+        Ok(account) => account,
+        Err(e) => return e.into_response(),
+    };
+    let Some(station) = state.stations.by_id(&id) else {
+        return ApiError::UnknownStation.into_response();
+    };
+    if order.hull > MAX_REPAIR {
+        return ApiError::Invalid(format!("Repairs are for up to {} hull points", MAX_REPAIR)).into_response();
+    }
+    let cost = order.hull * REPAIR_PRICE;
+    let balance = match account {
+        Some(account) => {
+            let memo = format!("Repaired {} hull at {}", order.hull, station.name);
+            match settle(&state.world, &account, TransactionKind::Repair, -(cost as i64), &memo) {
+                Ok(balance) => Some(balance),
+                Err(e) => return e.into_response(),
+            }
+        }
+        None => None,
+    };
+    Json(RepairReceipt { hull: order.hull, cost, balance }).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Ledger Tests ====================
+
+    #[test]
+    fn test_settle_refuses_overdrafts() {
+        let world = World::default();
+        assert_eq!(settle(&world, "Ace", TransactionKind::Mission, 50, "Mission 7-2"), Ok(50));
+        assert_eq!(settle(&world, "Ace", TransactionKind::Repair, -60, "Repaired 30 hull"), Err(ApiError::ShortCredits(50)));
+        assert_eq!(settle(&world, "Ace", TransactionKind::Repair, 0, "Repaired 0 hull"), Ok(50), "Free work reads the balance");
+        assert_eq!(settle(&world, "ace", TransactionKind::Trade, -44, "Bought 1 crystal"), Ok(6));
+        assert_eq!(world.transactions("Ace", 10).unwrap().len(), 2, "Only what moved credits is recorded");
+    }
+
+    #[test]
+    fn test_stow_refuses_what_isnt_aboard() {
+        let world = World::default();
+        assert_eq!(stow(&world, "Ace", "ore", -1), Err(ApiError::ShortCargo(0)));
+        assert_eq!(stow(&world, "Ace", "ore", 3), Ok(3));
+        assert_eq!(stow(&world, "ace", "ore", -5), Err(ApiError::ShortCargo(3)));
+        assert_eq!(stow(&world, "Ace", "ore", -3), Ok(0));
+    }
+}
@@ -0,0 +1,85 @@
+//! Server logs, through `tracing`.
+//!
+//! Logs go to stderr, filtered by `EXOSPACE_LOG` (or `RUST_LOG` when that's
+//! unset) as `tracing_subscriber` directives: `info` by default, `debug` to
+//! see every request, or per module like `info,exospace_server::sessions=debug`.
+//! `EXOSPACE_LOG_FORMAT=json` writes one JSON object a line for log
+//! collectors. Spans cover each HTTP request (`request`, at debug), each
+//! websocket session (`session`, with its id and pilot) and map generation
+//! (`generate_map`, and `generate_chunk` at debug); each logs how long it
+//! took when it closes. The startup banner stays on stdout.
+
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+/// Environment variable holding the log filter
+pub const LOG_ENV: &str = "EXOSPACE_LOG";
+/// Environment variable choosing `text` or `json` lines
+pub const LOG_FORMAT_ENV: &str = "EXOSPACE_LOG_FORMAT";
+
+const DEFAULT_FILTER: &str = "info";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+fn parse_filter(value: &str) -> Result<EnvFilter, String> {
+    EnvFilter::try_new(value.trim()).map_err(|e| format!("'{}' is not a log filter ({})", value, e))
+}
+
+fn parse_format(value: &str) -> Result<LogFormat, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "text" | "" => Ok(LogFormat::Text),
+        "json" => Ok(LogFormat::Json),
+        _ => Err(format!("'{}' is not a log format (text or json)", value)),
+    }
+}
+
+/// Start logging; bad settings are logged once it's running and the defaults kept
+pub fn init() {
+    let mut problems = Vec::new();
+    let value = std::env::var(LOG_ENV).ok().map(|value| (LOG_ENV, value)).or_else(|| std::env::var("RUST_LOG").ok().map(|value| ("RUST_LOG", value)));
+    let filter = match value {
+        Some((name, value)) => parse_filter(&value).unwrap_or_else(|e| {
+            problems.push(format!("Ignoring {}: {}", name, e));
+            EnvFilter::new(DEFAULT_FILTER)
+        }),
+        None => EnvFilter::new(DEFAULT_FILTER),
+    };
+    let format = match std::env::var(LOG_FORMAT_ENV) {
+        Ok(value) => parse_format(&value).unwrap_or_else(|e| {
+            problems.push(format!("Ignoring {}: {}", LOG_FORMAT_ENV, e));
+            LogFormat::Text
+        }),
+        Err(_) => LogFormat::Text,
+    };
+
+    let logs = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr).with_span_events(FmtSpan::CLOSE);
+    match format {
+        LogFormat::Text => logs.init(),
+        LogFormat::Json => logs.json().init(),
+    }
+    for problem in problems {
+        tracing::warn!("{}", problem);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Log Setting Tests ====================
+
+    #[test]
+    fn test_log_settings() {
+        assert!(parse_filter("debug").is_ok());
+        assert!(parse_filter("info,exospace_server::sessions=trace").is_ok(), "Levels can be set per module");
+        assert!(parse_filter("sessions=loud").is_err());
+
+        assert_eq!(parse_format("JSON"), Ok(LogFormat::Json));
+        assert_eq!(parse_format("text"), Ok(LogFormat::Text));
+        assert!(parse_format("xml").is_err());
+    }
+}
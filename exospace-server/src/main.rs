@@ -1,34 +1,86 @@
+mod admin;
+mod ambient;
+mod auth;
+mod chat;
+mod chunks;
+mod combat;
+mod community;
+mod content;
+mod error;
+mod etag;
+mod events;
+mod interest;
+mod leaderboard;
+mod ledger;
+mod logging;
+mod mapcache;
+mod mapgen;
+mod market;
+mod metrics;
+mod missions;
+mod movement;
+mod npc;
+mod pois;
+mod preview;
+mod sessions;
+mod stations;
+mod stats;
+mod sync;
+mod systems;
+mod throttle;
+mod tick;
+mod utility;
+mod wfc;
+mod world;
+
 use axum::{
-    extract::Query,
-    routing::get,
+    extract::{DefaultBodyLimit, State},
+    http::{HeaderMap, header},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
     Json, Router,
 };
-use serde::{Deserialize, Serialize};
+use exospace_core::{BINARY_CONTENT_TYPE, DailySeed, MapData, Tile};
+use error::{ApiError, ApiQuery};
+use mapcache::MapKey;
+use mapgen::{Algorithm, MapAlgorithm};
+use serde::Deserialize;
+use sessions::SessionRegistry;
 use std::net::SocketAddr;
-
-/// Tile types in the map
-#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
-pub enum Tile {
-    Wall,
-    Floor,
-    Asteroid,
-    Nebula,
-}
-
-impl Tile {
-    fn is_passable(&self) -> bool {
-        matches!(self, Tile::Floor | Tile::Nebula)
-    }
-}
-
-/// Map data that can be serialized and sent to clients
-#[derive(Serialize, Deserialize)]
-pub struct MapData {
-    pub tiles: Vec<Vec<Tile>>,
-    pub width: usize,
-    pub height: usize,
-    pub start_x: i32,
-    pub start_y: i32,
+use std::sync::Arc;
+use tower_http::compression::CompressionLayer;
+use tower_http::trace::TraceLayer;
+
+/// Shared state handed to every handler
+#[derive(Clone, Default)]
+pub struct AppState {
+    pub sessions: Arc<SessionRegistry>,
+    pub users: Arc<auth::Users>,
+    pub world: Arc<world::World>,
+    pub npcs: Arc<npc::NpcRoster>,
+    /// Pilots' shots in flight
+    pub projectiles: Arc<combat::Projectiles>,
+    pub stations: Arc<stations::Stations>,
+    /// The star systems and their jump gates
+    pub systems: Arc<systems::Systems>,
+    /// Meteor showers and ion storms under way
+    pub events: Arc<events::Events>,
+    pub markets: Arc<market::Markets>,
+    pub missions: Arc<missions::Missions>,
+    pub content: Arc<exospace_core::ContentCatalog>,
+    pub metrics: Arc<metrics::Metrics>,
+    pub map_cache: Arc<mapcache::MapCache>,
+    /// Largest map `/map` and `/map/preview` will generate, or `/maps/upload` take
+    pub map_limits: mapcache::MapLimits,
+    /// Maps pilots have uploaded
+    pub community: Arc<community::CommunityMaps>,
+    /// Kept up to date by the simulation loop, for `/admin/tick`
+    pub tick: Arc<tick::TickStatus>,
+    /// Bearer token for admin routes; they're refused without one
+    pub admin_token: Option<Arc<str>>,
+    /// Each pilot's budget of moves, chat and map requests
+    pub throttle: Arc<throttle::Throttle>,
 }
 
 /// Query parameters for map generation
@@ -40,6 +92,12 @@ pub struct MapQuery {
     height: usize,
     #[serde(default)]
     seed: Option<u64>,
+    /// Generation algorithm by name (see `mapgen::Algorithm`); "rooms" by default
+    #[serde(default)]
+    algo: Option<String>,
+    /// "bin" for the compact binary encoding; JSON otherwise ("svg" or PNG for `/map/preview`)
+    #[serde(default)]
+    format: Option<String>,
 }
 
 fn default_width() -> usize {
@@ -50,27 +108,16 @@ fn default_height() -> usize {
     200
 }
 
-/// Simple deterministic hash for procedural generation
-fn hash_position(x: i32, y: i32, seed: u32) -> u32 {
-    let mut h = seed;
-    h ^= x as u32;
-    h = h.wrapping_mul(2654435761);
-    h ^= y as u32;
-    h = h.wrapping_mul(2654435761);
-    h ^= h >> 13;
-    h = h.wrapping_mul(1274126177);
-    h ^= h >> 16;
-    h
-}
-
-/// Map generator
-struct MapGenerator {
+/// Corridor-and-room map generator, the `rooms` algorithm
+pub struct MapGenerator {
     rng_state: u64,
+    /// Kept for placing resources, which don't draw from the rng
+    seed: u32,
 }
 
 impl MapGenerator {
     fn new(seed: u64) -> Self {
-        MapGenerator { rng_state: seed }
+        MapGenerator { rng_state: seed, seed: (seed ^ (seed >> 32)) as u32 }
     }
 
     fn rand(&mut self) -> u64 {
@@ -79,7 +126,7 @@ impl MapGenerator {
     }
 
     fn generate(&mut self, width: usize, height: usize) -> MapData {
-        let mut tiles = vec![vec![Tile::Wall; width]; height];
+        let mut map = MapData::filled(width, height, Tile::Wall);
 
         // Create main corridors with varying widths
         let mut y = 2;
@@ -87,10 +134,8 @@ impl MapGenerator {
             let corridor_height = (self.rand() % 15 + 3) as usize;
             let wall_height = (self.rand() % 4 + 1) as usize;
 
-            for cy in y..(y + corridor_height).min(height - 1) {
-                for x in 1..width - 1 {
-                    tiles[cy][x] = Tile::Floor;
-                }
+            for row in map.rect_mut(1..width - 1, y..(y + corridor_height).min(height - 1)) {
+                row.fill(Tile::Floor);
             }
             y += corridor_height + wall_height;
         }
@@ -101,10 +146,8 @@ impl MapGenerator {
             let x = (i * 30) + 15 + (self.rand() % 10) as usize;
             if x < width - 1 {
                 let passage_width = (self.rand() % 8 + 2) as usize;
-                for px in x..(x + passage_width).min(width - 1) {
-                    for y in 1..height - 1 {
-                        tiles[y][px] = Tile::Floor;
-                    }
+                for row in map.rect_mut(x..(x + passage_width).min(width - 1), 1..height - 1) {
+                    row.fill(Tile::Floor);
                 }
             }
         }
@@ -114,18 +157,20 @@ impl MapGenerator {
         for _ in 0..num_rooms {
             let room_w = (self.rand() % 20 + 5) as usize;
             let room_h = (self.rand() % 15 + 5) as usize;
+            // Only long, thin maps have room for the room count but not the room
+            if room_w + 3 > width || room_h + 3 > height {
+                continue;
+            }
             let room_x = (self.rand() as usize % (width - room_w - 2)) + 1;
             let room_y = (self.rand() as usize % (height - room_h - 2)) + 1;
 
-            for ry in room_y..(room_y + room_h).min(height - 1) {
-                for rx in room_x..(room_x + room_w).min(width - 1) {
-                    tiles[ry][rx] = Tile::Floor;
-                }
+            for row in map.rect_mut(room_x..(room_x + room_w).min(width - 1), room_y..(room_y + room_h).min(height - 1)) {
+                row.fill(Tile::Floor);
             }
         }
 
         // Add asteroid fields (clusters of impassable asteroids)
-        let num_asteroid_fields = (width * height) / 5000;
+        let num_asteroid_fields = if width > 20 && height > 10 { (width * height) / 5000 } else { 0 };
         for _ in 0..num_asteroid_fields {
             let center_x = (self.rand() as usize % (width - 20)) + 10;
             let center_y = (self.rand() as usize % (height - 10)) + 5;
@@ -137,10 +182,15 @@ impl MapGenerator {
                     if dist < (field_size * field_size) as f32 * 0.7 {
                         let ax = (center_x as i32 + dx) as usize;
                         let ay = (center_y as i32 + dy) as usize;
-                        if ax > 0 && ax < width - 1 && ay > 0 && ay < height - 1 {
-                            if tiles[ay][ax] == Tile::Floor && self.rand() % 3 != 0 {
-                                tiles[ay][ax] = Tile::Asteroid;
-                            }
+                        if ax > 0
+                            && ax < width - 1
+                            && ay > 0
+                            && ay < height - 1
+                            && map.get(ax, ay) == Some(Tile::Floor)
+                            && !self.rand().is_multiple_of(3)
+                            && let Some(tile) = map.get_mut(ax, ay)
+                        {
+                            *tile = Tile::Asteroid;
                         }
                     }
                 }
@@ -148,7 +198,7 @@ impl MapGenerator {
         }
 
         // Add nebula zones (passable but visually distinct)
-        let num_nebulae = (width * height) / 8000;
+        let num_nebulae = if width > 30 && height > 15 { (width * height) / 8000 } else { 0 };
         for _ in 0..num_nebulae {
             let center_x = (self.rand() as usize % (width - 30)) + 15;
             let center_y = (self.rand() as usize % (height - 15)) + 7;
@@ -160,56 +210,114 @@ impl MapGenerator {
                     if dist < (nebula_size * nebula_size) as f32 * 0.8 {
                         let nx = (center_x as i32 + dx) as usize;
                         let ny = (center_y as i32 + dy) as usize;
-                        if nx > 0 && nx < width - 1 && ny > 0 && ny < height - 1 {
-                            if tiles[ny][nx] == Tile::Floor {
-                                tiles[ny][nx] = Tile::Nebula;
-                            }
+                        if nx > 0
+                            && nx < width - 1
+                            && ny > 0
+                            && ny < height - 1
+                            && let Some(tile) = map.get_mut(nx, ny)
+                            && *tile == Tile::Floor
+                        {
+                            *tile = Tile::Nebula;
                         }
                     }
                 }
             }
         }
 
+        mapgen::scatter_resources(&mut map, self.seed);
+
         // Find start position
-        let (start_x, start_y) = self.find_start_position(&tiles, width, height);
-
-        MapData {
-            tiles,
-            width,
-            height,
-            start_x,
-            start_y,
-        }
-    }
-
-    fn find_start_position(&self, tiles: &[Vec<Tile>], width: usize, height: usize) -> (i32, i32) {
-        // Find a passable tile near the center
-        let center_x = width / 2;
-        let center_y = height / 2;
-
-        for radius in 0..50 {
-            for dy in -(radius as i32)..=(radius as i32) {
-                for dx in -(radius as i32)..=(radius as i32) {
-                    let x = (center_x as i32 + dx) as usize;
-                    let y = (center_y as i32 + dy) as usize;
-                    if x < width && y < height {
-                        if tiles[y][x].is_passable() {
-                            return (x as i32, y as i32);
-                        }
-                    }
-                }
+        let (start_x, start_y) = mapgen::find_start_position(&map);
+        map.start_x = start_x;
+        map.start_y = start_y;
+        map
+    }
+}
+
+impl MapAlgorithm for MapGenerator {
+    fn generate(&mut self, width: usize, height: usize) -> MapData {
+        MapGenerator::generate(self, width, height)
+    }
+}
+
+/// Handler for the map endpoint
+async fn get_map(State(state): State<AppState>, ApiQuery(params): ApiQuery<MapQuery>, headers: HeaderMap) -> Response {
+    let key = match map_key(&state, &params) {
+        Ok(key) => key,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let binary = wants_binary(params.format.as_deref(), &headers);
+    // Maps are pure functions of their key, so a client's tagged copy never goes stale
+    let etag = etag::tag(format_args!("map {} {} {}x{} {}", key.algorithm.name(), key.seed, key.width, key.height, if binary { "bin" } else { "json" }));
+    if etag::matches(&headers, &etag) {
+        return etag::not_modified(etag);
+    }
+    let map = match cached_map(&state, &params) {
+        Ok(map) => map,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let response = if binary { binary_response(map.to_bytes()) } else { Json(&*map).into_response() };
+    etag::tagged(response, etag)
+}
+
+/// What a `MapQuery` asks for; a 400 for sizes over `map_limits` or bad algorithms
+fn map_key(state: &AppState, params: &MapQuery) -> Result<MapKey, ApiError> {
+    state.map_limits.check(params.width, params.height)?;
+    let algorithm = match params.algo.as_deref() {
+        None => Algorithm::Rooms,
+        Some(name) => match Algorithm::parse(name) {
+            Some(algorithm) => algorithm,
+            None => {
+                let known: Vec<_> = Algorithm::ALL.iter().map(|a| a.name()).collect();
+                let reason = format!("Unknown map algorithm '{}' (expected one of: {})", name, known.join(", "));
+                return Err(ApiError::UnknownAlgorithm(reason));
             }
+        },
+    };
+    Ok(MapKey { algorithm, seed: params.seed.unwrap_or(12345), width: params.width, height: params.height })
+}
+
+/// The map a `MapQuery` asks for, from the cache or freshly generated; a 400 for sizes over `map_limits` or bad algorithms
+pub fn cached_map(state: &AppState, params: &MapQuery) -> Result<Arc<MapData>, ApiError> {
+    let key = map_key(state, params)?;
+    let algorithm = key.algorithm;
+    let map = match state.map_cache.get(&key) {
+        Some(map) => {
+            state.metrics.map_cache_hit();
+            map
+        }
+        None => {
+            let _span = tracing::info_span!("generate_map", algorithm = algorithm.name(), seed = key.seed, width = key.width, height = key.height).entered();
+            let started = std::time::Instant::now();
+            let mut map = algorithm.generate(key.seed, key.width, key.height);
+            pois::place_on_map(&mut map, key.seed);
+            let map = Arc::new(map);
+            state.metrics.map_generated(started.elapsed());
+            state.map_cache.insert(key, map.clone());
+            map
         }
-        (1, 1)
+    };
+    Ok(map)
+}
+
+/// Today's shared seed, so every pilot can fly the same map for the UTC day
+async fn get_daily() -> Json<DailySeed> {
+    Json(DailySeed::for_time(utility::unix_time()))
+}
+
+/// Whether the client asked for the binary encoding, via `?format=bin` or the Accept header
+pub fn wants_binary(format: Option<&str>, headers: &HeaderMap) -> bool {
+    if let Some(format) = format {
+        return format.eq_ignore_ascii_case("bin");
     }
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains(BINARY_CONTENT_TYPE))
 }
 
-/// Handler for the map endpoint
-async fn get_map(Query(params): Query<MapQuery>) -> Json<MapData> {
-    let seed = params.seed.unwrap_or(12345);
-    let mut generator = MapGenerator::new(seed);
-    let map = generator.generate(params.width, params.height);
-    Json(map)
+pub fn binary_response(bytes: Vec<u8>) -> Response {
+    ([(header::CONTENT_TYPE, BINARY_CONTENT_TYPE)], bytes).into_response()
 }
 
 /// Health check endpoint
@@ -217,22 +325,185 @@ async fn health() -> &'static str {
     "OK"
 }
 
+/// Build the application router
+fn create_router(state: AppState) -> Router {
+    // Map generation is the expensive part, so each pilot gets a budget of it; community maps are as big to send
+    let maps = Router::new()
+        .route("/map", get(get_map))
+        .route("/map/chunk", get(chunks::get_chunk))
+        .route("/map/preview", get(preview::get_preview))
+        .route("/system/{id}/map", get(systems::get_system_map))
+        .route("/maps", get(community::list_maps))
+        .route("/maps/{id}", get(community::get_map))
+        .route("/maps/upload", post(community::upload).layer(DefaultBodyLimit::max(community::MAX_UPLOAD_BYTES)))
+        .route_layer(middleware::from_fn_with_state(state.clone(), throttle::limit_maps));
+    Router::new()
+        .merge(maps)
+        .route("/", get(health))
+        .route("/health", get(health))
+        .route("/map/daily", get(get_daily))
+        .route("/map/diff", get(world::get_diff))
+        .route("/ws", get(sessions::ws_handler))
+        .route("/register", post(auth::register))
+        .route("/login", post(auth::login))
+        .route("/me", get(auth::me))
+        .route("/tile/update", post(world::update_tile))
+        .route("/entities", get(npc::get_entities))
+        .route("/station/{id}/market", get(market::get_market).post(market::trade))
+        .route("/station/{id}/repair", post(ledger::repair))
+        .route("/missions", get(missions::get_missions))
+        .route("/missions/{id}/accept", post(missions::accept))
+        .route("/missions/{id}/complete", post(missions::complete))
+        .route("/player/balance", get(ledger::get_balance))
+        .route("/player/transactions", get(ledger::get_transactions))
+        .route("/leaderboard", get(leaderboard::get_leaderboard))
+        .route("/systems", get(systems::get_systems))
+        .route("/content", get(content::get_content))
+        .route("/metrics", get(metrics::get_metrics))
+        .route("/admin/cache/clear", post(mapcache::clear_cache))
+        .route("/admin/tick", get(tick::get_status).post(tick::set_rate))
+        .route("/admin/tile", post(admin::set_tile))
+        .route("/admin/npc", post(admin::spawn_npc))
+        .route("/admin/npc/{id}", delete(admin::despawn_npc))
+        .route("/admin/announce", post(admin::announce))
+        .route("/admin/kick", post(admin::kick))
+        .route("/admin/event", post(events::start_event))
+        .fallback(error::not_found_route)
+        .route_layer(middleware::from_fn_with_state(state.clone(), metrics::track))
+        // gzip or zstd when the client's Accept-Encoding allows it, plain otherwise
+        .layer(CompressionLayer::new())
+        // A `request` span for each, logged at debug
+        .layer(TraceLayer::new_for_http())
+        .with_state(state)
+}
+
 #[tokio::main]
 async fn main() {
+    logging::init();
+
     // Build our application with routes
-    let app = Router::new()
-        .route("/", get(health))
-        .route("/health", get(health))
-        .route("/map", get(get_map));
+    let world = Arc::new(world::World::load());
+    let stations = stations::Stations::place(&world);
+    let state = AppState {
+        users: Arc::new(auth::Users::load()),
+        npcs: Arc::new(npc::NpcRoster::spawn(&world)),
+        markets: Arc::new(market::Markets::open(&stations.list(), std::time::Instant::now())),
+        missions: Arc::new(missions::Missions::new(world.seed, &stations.list())),
+        stations: Arc::new(stations),
+        systems: Arc::new(systems::Systems::chart(world.seed)),
+        content: Arc::new(content::load()),
+        map_cache: Arc::new(mapcache::MapCache::load()),
+        map_limits: mapcache::MapLimits::load(),
+        community: Arc::new(community::CommunityMaps::load()),
+        admin_token: std::env::var(admin::ADMIN_TOKEN_ENV).ok().filter(|t| !t.is_empty()).map(Arc::from),
+        throttle: Arc::new(throttle::Throttle::new(throttle::Budgets::load())),
+        world,
+        ..AppState::default()
+    };
+    let tick_rate = tick::load_tick_rate();
+    let mut simulation = tick::Simulation::new(tick_rate);
+    let state = AppState { tick: simulation.status(), ..state };
+    let now = std::time::Instant::now();
+    if let Some(chatter) = ambient::AmbientSystem::new(ambient::AmbientConfig::load(), state.sessions.clone(), now) {
+        simulation.add(Box::new(chatter));
+    }
+    simulation.add(Box::new(npc::NpcPilot::new(state.npcs.clone(), state.world.clone(), state.sessions.clone())));
+    simulation.add(Box::new(npc::NpcMovement::new(state.npcs.clone(), state.world.clone(), state.sessions.clone(), now)));
+    simulation.add(Box::new(combat::ProjectileFlight::new(state.projectiles.clone(), state.npcs.clone(), state.world.clone(), state.sessions.clone(), now)));
+    simulation.add(Box::new(world::ResourceRegrowth::new(state.world.clone(), state.sessions.clone(), state.npcs.clone())));
+    simulation.add(Box::new(market::MarketDrift::new(state.markets.clone(), now)));
+    simulation.add(Box::new(events::EventScheduler::new(state.events.clone(), state.sessions.clone(), utility::DiceRng::for_session(0), now)));
+    simulation.add(Box::new(sync::DeltaBroadcast::new(state.sessions.clone())));
+    let (stop_simulation, simulation_stopped) = tokio::sync::oneshot::channel::<()>();
+    let simulation = tokio::spawn(simulation.run(async {
+        let _ = simulation_stopped.await;
+    }));
+    let sessions = state.sessions.clone();
+    let world_seed = state.world.seed;
+    let map_cache_size = state.map_cache.capacity();
+    let map_limits = state.map_limits;
+    let budgets = state.throttle.budgets();
+    let admin_enabled = state.admin_token.is_some();
+    let app = create_router(state);
 
     // Run it
     let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
     println!("Exospace server listening on {}", addr);
-    println!("  GET /map           - Generate a map (query params: width, height, seed)");
+    println!("  GET /map           - Generate a map (query params: width, height, seed, format=bin)");
+    println!("  GET /map/chunk     - One {0}x{0} world chunk (query params: cx, cy, seed, format=bin)", exospace_core::CHUNK_SIZE);
+    println!("  GET /map/daily     - Today's seed (UTC), the same for everyone");
+    println!("  GET /map/diff      - World tiles changed since a version (query param: since; up to {} at a time)", world::MAX_DIFF_TILES);
+    println!("  GET /map/preview   - A map drawn as a PNG, start marked (same params as /map; format=svg for SVG)");
     println!("  GET /health        - Health check");
+    println!("  GET /ws            - Multiplayer websocket (position sync, chat, /roll /flip /time /distance)");
+    println!("  POST /register     - Create an account (JSON name, password); returns a bearer token");
+    println!("  POST /login        - Log in (JSON name, password); returns a bearer token");
+    println!("  GET /me            - Account name for the bearer token");
+    println!("  POST /tile/update  - Report a mined or lasered tile (JSON x, y, tile; needs a bearer token)");
+    println!("  GET /entities      - NPC ships (JSON); also streamed over /ws");
+    println!("  GET /station/{{id}}/market - A station's prices and stock (JSON); POST JSON item, quantity, side to trade (token, docked)");
+    println!("  POST /station/{{id}}/repair - Pay for hull repairs (JSON hull; {} credits a point)", exospace_core::REPAIR_PRICE);
+    println!("  GET /missions      - The mission board (JSON); POST /missions/{{id}}/accept, then /missions/{{id}}/complete to be paid (token)");
+    println!("  GET /player/balance - The bearer token's credits; GET /player/transactions lists what moved them");
+    println!("  GET /leaderboard   - Top pilots (query params: metric=distance|ore|credits, page, per_page up to {})", leaderboard::MAX_PER_PAGE);
+    println!("  GET /maps          - Maps pilots have uploaded (JSON); GET /maps/{{id}} fetches one (format=bin)");
+    println!("  POST /maps/upload  - Share a map (query param name; binary or JSON MapData body; needs a bearer token)");
+    println!("  GET /systems       - The star systems, their seeds and jump gates (JSON)");
+    println!("  GET /system/{{id}}/map - One chunk of a star system, gates included (query params: cx, cy, format=bin)");
+    println!("  GET /content       - Items, ship classes, upgrades and missions from content packs (JSON)");
+    println!("  GET /metrics       - Request counts, map generation latency and sessions (Prometheus text)");
+    println!("  POST /admin/cache/clear - Empty the /map cache (needs the admin bearer token)");
+    println!("  GET /admin/tick    - Simulation tick rate, lag and overruns (JSON; needs the admin bearer token); POST JSON tick_rate to change it");
+    println!("  POST /admin/tile   - Set a world tile (JSON x, y, tile; no tile clears it to open space)");
+    println!("  POST /admin/npc    - Launch an NPC ship (JSON name, x, y, behavior); DELETE /admin/npc/{{id}} removes one");
+    println!("  POST /admin/announce - Put a line in every pilot's chat (JSON text)");
+    println!("  POST /admin/kick   - Disconnect a pilot (JSON name, reason)");
+    println!("  POST /admin/event  - Start a meteor shower or ion storm (JSON kind, x, y, radius, secs); also scheduled every {}s", events::EVENT_INTERVAL.as_secs());
+    println!("Simulation: {} ticks/sec (${}, 1-{})", tick_rate, tick::TICK_RATE_ENV, tick::MAX_TICK_RATE);
+    println!("Ambient chatter config: ${} (JSON file), built-in defaults otherwise", ambient::AMBIENT_CONFIG_ENV);
+    println!("Content packs: ${} (directory of JSON files), built-in core pack otherwise", content::CONTENT_DIR_ENV);
+    println!("Accounts file: ${} (JSON file), in memory only otherwise", auth::USERS_FILE_ENV);
+    println!("Map cache: {} maps (${}); admin routes {} (${})", map_cache_size, mapcache::MAP_CACHE_ENV,
+        if admin_enabled { "enabled" } else { "disabled" }, admin::ADMIN_TOKEN_ENV);
+    println!("Largest map: {}x{} (${})", map_limits.max_width, map_limits.max_height, mapcache::MAX_MAP_ENV);
+    println!("Budgets per pilot: {} moves/sec (${}), {} chat lines/min (${}), {} map requests/min (${}), {} trades/min (${}); 0 is unlimited",
+        budgets.moves_per_sec, throttle::MOVES_ENV, budgets.chat_per_min, throttle::CHAT_ENV, budgets.maps_per_min, throttle::MAPS_ENV,
+        budgets.trades_per_min, throttle::TRADES_ENV);
+    println!("World database: ${} (SQLite file), in memory only otherwise; world seed {}", world::WORLD_DB_ENV, world_seed);
+    println!("Community maps: ${} (directory), in memory only otherwise", community::MAPS_DIR_ENV);
+    println!("Logs: stderr, filtered by ${} (default info; debug shows each request), ${}=json for JSON lines", logging::LOG_ENV, logging::LOG_FORMAT_ENV);
 
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // Guests' map requests are counted by address
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).with_graceful_shutdown(shutdown_signal()).await.unwrap();
+
+    // New requests have stopped; warn whoever is still flying, then let the loop send its last delta
+    tracing::info!("Shutting down");
+    sessions.announce(exospace_core::ServerMessage::Ambient { source: "Server".to_string(), text: "Shutting down".to_string() });
+    let _ = stop_simulation.send(());
+    let _ = simulation.await;
+}
+
+/// Ctrl+C, or SIGTERM on Unix
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 }
 
 #[cfg(test)]
@@ -242,90 +513,10 @@ mod tests {
         body::Body,
         http::{Request, StatusCode},
     };
+    use exospace_core::{ErrorBody, ErrorCode, MAX_MAP_HEIGHT, MAX_MAP_WIDTH, MIN_MAP_SIDE, fnv1a};
     use http_body_util::BodyExt;
     use tower::ServiceExt;
 
-    // ==================== Tile Tests ====================
-
-    #[test]
-    fn test_tile_passability() {
-        assert!(Tile::Floor.is_passable(), "Floor should be passable");
-        assert!(Tile::Nebula.is_passable(), "Nebula should be passable");
-        assert!(!Tile::Wall.is_passable(), "Wall should not be passable");
-        assert!(!Tile::Asteroid.is_passable(), "Asteroid should not be passable");
-    }
-
-    #[test]
-    fn test_tile_serialization() {
-        // Test JSON serialization round-trip
-        let tiles = vec![Tile::Wall, Tile::Floor, Tile::Asteroid, Tile::Nebula];
-        for tile in tiles {
-            let json = serde_json::to_string(&tile).unwrap();
-            let parsed: Tile = serde_json::from_str(&json).unwrap();
-            assert_eq!(tile, parsed);
-        }
-    }
-
-    #[test]
-    fn test_tile_json_format() {
-        // Verify exact JSON format
-        assert_eq!(serde_json::to_string(&Tile::Wall).unwrap(), "\"Wall\"");
-        assert_eq!(serde_json::to_string(&Tile::Floor).unwrap(), "\"Floor\"");
-        assert_eq!(serde_json::to_string(&Tile::Asteroid).unwrap(), "\"Asteroid\"");
-        assert_eq!(serde_json::to_string(&Tile::Nebula).unwrap(), "\"Nebula\"");
-    }
-
-    // ==================== Hash Function Tests ====================
-
-    #[test]
-    fn test_hash_position_deterministic() {
-        let hash1 = hash_position(10, 20, 42);
-        let hash2 = hash_position(10, 20, 42);
-        assert_eq!(hash1, hash2, "Same inputs should produce same hash");
-    }
-
-    #[test]
-    fn test_hash_position_different_x() {
-        let hash1 = hash_position(10, 20, 42);
-        let hash2 = hash_position(11, 20, 42);
-        assert_ne!(hash1, hash2, "Different x should produce different hash");
-    }
-
-    #[test]
-    fn test_hash_position_different_y() {
-        let hash1 = hash_position(10, 20, 42);
-        let hash2 = hash_position(10, 21, 42);
-        assert_ne!(hash1, hash2, "Different y should produce different hash");
-    }
-
-    #[test]
-    fn test_hash_position_different_seed() {
-        let hash1 = hash_position(10, 20, 42);
-        let hash2 = hash_position(10, 20, 43);
-        assert_ne!(hash1, hash2, "Different seed should produce different hash");
-    }
-
-    #[test]
-    fn test_hash_position_negative_coords() {
-        // Should not panic with negative coordinates
-        let hash1 = hash_position(-10, -20, 42);
-        let hash2 = hash_position(-10, -20, 42);
-        assert_eq!(hash1, hash2, "Negative coords should still be deterministic");
-    }
-
-    #[test]
-    fn test_hash_position_distribution() {
-        // Test that hash produces reasonably distributed values
-        let mut values = std::collections::HashSet::new();
-        for x in 0..100 {
-            for y in 0..100 {
-                values.insert(hash_position(x, y, 42));
-            }
-        }
-        // Should have many unique values (good distribution)
-        assert!(values.len() > 9000, "Hash should have good distribution");
-    }
-
     // ==================== MapGenerator RNG Tests ====================
 
     #[test]
@@ -392,8 +583,9 @@ mod tests {
 
         assert_eq!(map.width, 100);
         assert_eq!(map.height, 50);
-        assert_eq!(map.tiles.len(), 50, "Should have 'height' rows");
-        assert_eq!(map.tiles[0].len(), 100, "Each row should have 'width' columns");
+        assert_eq!(map.tiles.len(), 100 * 50, "Should have 'width * height' tiles");
+        assert_eq!(map.rows().count(), 50, "Should have 'height' rows");
+        assert!(map.rows().all(|row| row.len() == 100), "Each row should have 'width' columns");
     }
 
     #[test]
@@ -406,8 +598,8 @@ mod tests {
 
             assert_eq!(map.width, width, "Map width should match requested");
             assert_eq!(map.height, height, "Map height should match requested");
-            assert_eq!(map.tiles.len(), height);
-            for row in &map.tiles {
+            assert_eq!(map.tiles.len(), width * height);
+            for row in map.rows() {
                 assert_eq!(row.len(), width);
             }
         }
@@ -422,6 +614,15 @@ mod tests {
         assert_eq!(map.height, 10);
     }
 
+    #[test]
+    fn test_map_thin_dimensions_do_not_panic() {
+        // Big enough to place features, too narrow or short for them to fit
+        for (width, height) in [(MIN_MAP_SIDE, MAX_MAP_HEIGHT), (MAX_MAP_WIDTH, MIN_MAP_SIDE), (10, 600), (25, 400)] {
+            let map = MapGenerator::new(7).generate(width, height);
+            assert_eq!(map.tiles.len(), width * height);
+        }
+    }
+
     // ==================== Map Content Tests ====================
 
     #[test]
@@ -429,10 +630,10 @@ mod tests {
         let mut generator = MapGenerator::new(12345);
         let map = generator.generate(500, 200);
 
-        let has_walls = map.tiles.iter().flatten().any(|t| *t == Tile::Wall);
-        let has_floors = map.tiles.iter().flatten().any(|t| *t == Tile::Floor);
-        let has_asteroids = map.tiles.iter().flatten().any(|t| *t == Tile::Asteroid);
-        let has_nebulae = map.tiles.iter().flatten().any(|t| *t == Tile::Nebula);
+        let has_walls = map.tiles.contains(&Tile::Wall);
+        let has_floors = map.tiles.contains(&Tile::Floor);
+        let has_asteroids = map.tiles.contains(&Tile::Asteroid);
+        let has_nebulae = map.tiles.contains(&Tile::Nebula);
 
         assert!(has_walls, "Map should contain walls");
         assert!(has_floors, "Map should contain floors");
@@ -447,22 +648,22 @@ mod tests {
 
         // Check top border
         for x in 0..100 {
-            assert_eq!(map.tiles[0][x], Tile::Wall, "Top border at x={} should be wall", x);
+            assert_eq!(map.get(x, 0).unwrap(), Tile::Wall, "Top border at x={} should be wall", x);
         }
 
         // Check bottom border
         for x in 0..100 {
-            assert_eq!(map.tiles[49][x], Tile::Wall, "Bottom border at x={} should be wall", x);
+            assert_eq!(map.get(x, 49).unwrap(), Tile::Wall, "Bottom border at x={} should be wall", x);
         }
 
         // Check left border
         for y in 0..50 {
-            assert_eq!(map.tiles[y][0], Tile::Wall, "Left border at y={} should be wall", y);
+            assert_eq!(map.get(0, y).unwrap(), Tile::Wall, "Left border at y={} should be wall", y);
         }
 
         // Check right border
         for y in 0..50 {
-            assert_eq!(map.tiles[y][99], Tile::Wall, "Right border at y={} should be wall", y);
+            assert_eq!(map.get(99, y).unwrap(), Tile::Wall, "Right border at y={} should be wall", y);
         }
     }
 
@@ -472,18 +673,7 @@ mod tests {
         let map = generator.generate(100, 50);
 
         // There should be floor tiles in the interior
-        let mut has_interior_floor = false;
-        for y in 1..49 {
-            for x in 1..99 {
-                if map.tiles[y][x] == Tile::Floor {
-                    has_interior_floor = true;
-                    break;
-                }
-            }
-            if has_interior_floor {
-                break;
-            }
-        }
+        let has_interior_floor = map.rect(1..99, 1..49).any(|row| row.contains(&Tile::Floor));
         assert!(has_interior_floor, "Map should have floor tiles in interior");
     }
 
@@ -494,7 +684,7 @@ mod tests {
         let mut generator = MapGenerator::new(12345);
         let map = generator.generate(100, 50);
 
-        let start_tile = map.tiles[map.start_y as usize][map.start_x as usize];
+        let start_tile = map.get(map.start_x as usize, map.start_y as usize).unwrap();
         assert!(start_tile.is_passable(), "Start position must be passable");
     }
 
@@ -516,7 +706,7 @@ mod tests {
             let mut generator = MapGenerator::new(seed);
             let map = generator.generate(100, 50);
 
-            let start_tile = map.tiles[map.start_y as usize][map.start_x as usize];
+            let start_tile = map.get(map.start_x as usize, map.start_y as usize).unwrap();
             assert!(
                 start_tile.is_passable(),
                 "Start position must be passable for seed {}",
@@ -557,6 +747,51 @@ mod tests {
         assert!(json.contains("\"start_y\""), "JSON should contain start_y field");
     }
 
+    // ==================== Golden Map Tests ====================
+
+    /// Hashes of the full binary encoding (tiles, size and start) for fixed seeds and sizes.
+    /// If a change to generation is intentional, paste the new hashes from the failure messages.
+    #[test]
+    fn test_golden_maps() {
+        let cases: [(u64, usize, usize, u64); 5] = [
+            (12345, 50, 30, 0xc7194b1fe75b0505),
+            (12345, 200, 100, 0xd4c8aab6ddb8ee0a),
+            (1, 80, 40, 0x977b3ca38f889a5b),
+            (987654321, 300, 150, 0x2f291916a4f7ac24),
+            (7, 3, 3, 0x3ac2d70b6e6e67a1),
+        ];
+        for (seed, width, height, expected) in cases {
+            let hash = fnv1a(&MapGenerator::new(seed).generate(width, height).to_bytes());
+            assert_eq!(hash, expected, "Map for seed {} at {}x{} changed: now {:#018x}", seed, width, height, hash);
+        }
+    }
+
+    #[test]
+    fn test_golden_noise_maps() {
+        let cases: [(u64, usize, usize, u64); 3] = [
+            (12345, 200, 100, 0xece269e93a809ed3),
+            (1, 80, 40, 0xebde887e1d5b9a22),
+            (7, 3, 3, 0x14b62d8bf6802878),
+        ];
+        for (seed, width, height, expected) in cases {
+            let hash = fnv1a(&mapgen::NoiseGenerator::new(seed).generate(width, height).to_bytes());
+            assert_eq!(hash, expected, "Noise map for seed {} at {}x{} changed: now {:#018x}", seed, width, height, hash);
+        }
+    }
+
+    #[test]
+    fn test_golden_wfc_maps() {
+        let cases: [(u64, usize, usize, u64); 3] = [
+            (12345, 200, 100, 0x7b15df8dd462f32f),
+            (1, 80, 40, 0x080de178922f7407),
+            (7, 3, 3, 0x2e13f3e0f11efd3e),
+        ];
+        for (seed, width, height, expected) in cases {
+            let hash = fnv1a(&wfc::WfcGenerator::new(seed).generate(width, height).to_bytes());
+            assert_eq!(hash, expected, "WFC map for seed {} at {}x{} changed: now {:#018x}", seed, width, height, hash);
+        }
+    }
+
     // ==================== MapQuery Tests ====================
 
     #[test]
@@ -601,11 +836,13 @@ mod tests {
 
     // ==================== HTTP Endpoint Tests ====================
 
+    /// Cheap password hashing keeps the account tests fast
+    fn test_state() -> AppState {
+        AppState { users: Arc::new(auth::Users::with_rounds(10)), ..AppState::default() }
+    }
+
     fn create_app() -> Router {
-        Router::new()
-            .route("/", get(health))
-            .route("/health", get(health))
-            .route("/map", get(get_map))
+        create_router(test_state())
     }
 
     #[tokio::test]
@@ -638,6 +875,194 @@ mod tests {
         assert_eq!(&body[..], b"OK");
     }
 
+    #[tokio::test]
+    async fn test_entities_endpoint() {
+        use exospace_core::NpcInfo;
+
+        let empty = create_app()
+            .oneshot(Request::builder().uri("/entities").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = empty.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"[]", "No ships until the roster is spawned");
+
+        let state = test_state();
+        let state = AppState { npcs: Arc::new(npc::NpcRoster::spawn(&state.world)), ..state };
+        let response = create_router(state)
+            .oneshot(Request::builder().uri("/entities").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let ships: Vec<NpcInfo> = serde_json::from_slice(&body).unwrap();
+        assert!(ships.iter().any(|s| s.name == "Haven Patrol"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        let state = test_state();
+        for uri in ["/map?width=40&height=30", "/map?width=1", "/map?width=50&height=30"] {
+            create_router(state.clone()).oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+        }
+        let response = create_router(state)
+            .oneshot(Request::builder().uri("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/plain"));
+        let body = String::from_utf8(response.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap();
+        assert!(body.contains("exospace_map_generations_total 2\n"), "Rejected sizes aren't generated:\n{}", body);
+        assert!(body.contains("exospace_map_generation_seconds_count 2\n"));
+        assert!(body.contains("exospace_http_requests_total{method=\"GET\",route=\"/map\",status=\"200\"} 2\n"));
+        assert!(body.contains("exospace_http_requests_total{method=\"GET\",route=\"/map\",status=\"400\"} 1\n"));
+        assert!(body.contains("exospace_ws_sessions 0\n"));
+    }
+
+    #[tokio::test]
+    async fn test_map_cache_and_admin_clear() {
+        let state = AppState { admin_token: Some(Arc::from("s3cret")), ..test_state() };
+        let mut bodies = Vec::new();
+        for uri in ["/map?width=40&height=30&seed=5", "/map?width=40&height=30&seed=5", "/map?width=40&height=30&seed=5&format=bin"] {
+            let response = create_router(state.clone()).oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+            bodies.push(response.into_body().collect().await.unwrap().to_bytes());
+        }
+        assert_eq!(bodies[0], bodies[1], "Cached maps are the same map");
+        let rendered = state.metrics.render(0);
+        assert!(rendered.contains("exospace_map_generations_total 1\n"), "Generated once, whatever the format");
+        assert!(rendered.contains("exospace_map_cache_hits_total 2\n"));
+
+        let clear = |token: Option<&str>| {
+            let mut request = Request::builder().method("POST").uri("/admin/cache/clear");
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            request.body(Body::empty()).unwrap()
+        };
+        let response = create_router(state.clone()).oneshot(clear(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = create_router(state.clone()).oneshot(clear(Some("guess"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = create_router(state.clone()).oneshot(clear(Some("s3cret"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"Cleared 1 cached maps");
+
+        let response = create_app().oneshot(clear(Some("s3cret"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "No admin token configured, no admin routes");
+    }
+
+    #[tokio::test]
+    async fn test_admin_tick_status() {
+        let simulation = tick::Simulation::new(30);
+        let state = AppState { admin_token: Some(Arc::from("s3cret")), tick: simulation.status(), ..test_state() };
+        let status = |token: &str| {
+            Request::builder()
+                .uri("/admin/tick")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = create_router(state.clone()).oneshot(status("guess")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = create_router(state).oneshot(status("s3cret")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let report: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(report["tick_rate"], 30);
+        assert_eq!(report["running"], false, "Not started");
+        assert!(report["lag_ms"].is_number());
+    }
+
+    /// An admin request with a JSON body (or none) and the admin token used by these tests
+    fn admin_request(method: &str, uri: &str, token: &str, body: Option<serde_json::Value>) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.map_or_else(Body::empty, |json| Body::from(json.to_string())))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_admin_world_routes() {
+        use serde_json::json;
+
+        let mut simulation = tick::Simulation::new(30);
+        let state = AppState { admin_token: Some(Arc::from("s3cret")), tick: simulation.status(), ..test_state() };
+        simulation.add(Box::new(sync::DeltaBroadcast::new(state.sessions.clone())));
+        tokio::spawn(simulation.run(std::future::pending()));
+        let send = |method: &str, uri: &str, body: Option<serde_json::Value>| {
+            create_router(state.clone()).oneshot(admin_request(method, uri, "s3cret", body))
+        };
+        let guess = admin_request("POST", "/admin/tile", "guess", Some(json!({ "x": 1, "y": 1 })));
+        assert_eq!(create_router(state.clone()).oneshot(guess).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        let response = send("POST", "/admin/tile", Some(json!({ "x": 2, "y": 3, "tile": "OreAsteroid" }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(state.world.tile(2, 3), Tile::OreAsteroid);
+        send("POST", "/admin/tile", Some(json!({ "x": 2, "y": 3 }))).await.unwrap();
+        assert_eq!(state.world.tile(2, 3), Tile::Floor, "No tile clears it to open space");
+        let response = send("POST", "/admin/tile", Some(json!({ "x": i32::MAX, "y": 0 }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = send("POST", "/admin/npc", Some(json!({ "name": "Courier", "x": 0, "y": 0, "behavior": "flee" }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let ship: exospace_core::NpcInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!((ship.name.as_str(), ship.behavior), ("Courier", exospace_core::NpcBehavior::Flee));
+        assert_eq!(state.npcs.snapshot(), std::slice::from_ref(&ship));
+        let response = send("POST", "/admin/npc", Some(json!({ "name": " ", "x": 0, "y": 0 }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Ships need a name");
+        let uri = format!("/admin/npc/{}", ship.id);
+        assert_eq!(send("DELETE", &uri, None).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(send("DELETE", &uri, None).await.unwrap().status(), StatusCode::NOT_FOUND);
+        assert!(state.npcs.snapshot().is_empty());
+
+        let response = send("POST", "/admin/announce", Some(json!({ "text": "  " }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Announcements follow the chat rules");
+        let response = send("POST", "/admin/kick", Some(json!({ "name": "Nobody" }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = send("POST", "/admin/tick", Some(json!({ "tick_rate": 0 }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = send("POST", "/admin/tick", Some(json!({ "tick_rate": 60 }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        tokio::time::timeout(std::time::Duration::from_secs(2), async {
+            while state.tick.report().tick_rate != 60 {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("The running loop switches rate");
+    }
+
+    #[tokio::test]
+    async fn test_content_endpoint_and_advert() {
+        use exospace_core::{ClientMessage, ContentCatalog, ServerMessage};
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let mut catalog = ContentCatalog::default();
+        content::add_pack(&mut catalog, content::core_pack());
+        let state = AppState { content: Arc::new(catalog.clone()), ..test_state() };
+        let response = create_router(state.clone())
+            .oneshot(Request::builder().uri("/content").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(serde_json::from_slice::<ContentCatalog>(&body).unwrap(), catalog);
+
+        let addr = spawn_server_with(state).await;
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        let hello = ClientMessage::Hello { name: "Ace".into() };
+        ws.send(Message::text(serde_json::to_string(&hello).unwrap())).await.unwrap();
+        assert!(matches!(next_server_message(&mut ws).await, ServerMessage::Welcome { .. }));
+        assert_eq!(next_server_message(&mut ws).await, ServerMessage::Content { catalog }, "Clients hear what the server defines");
+    }
+
     #[tokio::test]
     async fn test_map_endpoint_default_params() {
         let app = create_app();
@@ -677,6 +1102,37 @@ mod tests {
 
         assert_eq!(map.width, 100);
         assert_eq!(map.height, 50);
+        assert_eq!(map.regions.len(), 1, "A small map is one region");
+        assert_eq!((map.regions[0].width, map.regions[0].height), (100, 50));
+    }
+
+    #[tokio::test]
+    async fn test_daily_seed_endpoint() {
+        let response = create_app()
+            .oneshot(Request::builder().uri("/map/daily").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let daily: DailySeed = serde_json::from_slice(&body).unwrap();
+        assert_eq!(daily, DailySeed::for_time(utility::unix_time()), "Today's seed");
+    }
+
+    #[tokio::test]
+    async fn test_map_preview_endpoint() {
+        let get = |uri: &str| create_app().oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap());
+        let response = get("/map/preview?width=100&height=50&seed=3").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let map = Algorithm::Rooms.generate(3, 100, 50);
+        assert_eq!(body, preview::render_png(&map), "A preview of the same map /map serves");
+
+        let response = get("/map/preview?width=100&height=50&algo=wfc&format=svg").await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/svg+xml");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.starts_with(b"<svg "));
+        assert_eq!(get("/map/preview?width=1").await.unwrap().status(), StatusCode::BAD_REQUEST, "Same limits as /map");
     }
 
     #[tokio::test]
@@ -768,6 +1224,233 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_chunk_endpoint() {
+        let app = create_app();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/map/chunk?cx=-1&cy=2&seed=42")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let chunk: exospace_core::ChunkData = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!((chunk.cx, chunk.cy), (-1, 2));
+        assert_eq!(chunk.tiles.len(), (exospace_core::CHUNK_SIZE * exospace_core::CHUNK_SIZE) as usize);
+        assert_eq!(chunk, chunks::ChunkGenerator::new(42).generate(-1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_map_endpoint_binary_format() {
+        let app = create_app();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/map?width=40&height=20&seed=9&format=bin")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("content-type").unwrap(), BINARY_CONTENT_TYPE);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let map = MapData::from_bytes(&body).unwrap();
+        let expected = MapGenerator::new(9).generate(40, 20);
+        assert_eq!(map.tiles, expected.tiles, "Binary map should match the generated map");
+        assert_eq!((map.start_x, map.start_y), (expected.start_x, expected.start_y));
+    }
+
+    #[tokio::test]
+    async fn test_map_endpoint_compression() {
+        let fetch = |encoding: Option<&'static str>| async move {
+            let mut request = Request::builder().uri("/map?width=200&height=100&seed=4");
+            if let Some(encoding) = encoding {
+                request = request.header(header::ACCEPT_ENCODING, encoding);
+            }
+            let response = create_app().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+            let content_encoding = response.headers().get(header::CONTENT_ENCODING).map(|v| v.to_str().unwrap().to_string());
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (content_encoding, body)
+        };
+
+        let (encoding, plain) = fetch(None).await;
+        assert_eq!(encoding, None, "No Accept-Encoding, no compression");
+        assert!(serde_json::from_slice::<MapData>(&plain).is_ok());
+
+        for wanted in ["gzip", "zstd"] {
+            let (encoding, compressed) = fetch(Some(wanted)).await;
+            assert_eq!(encoding.as_deref(), Some(wanted));
+            assert!(compressed.len() * 5 < plain.len(), "{} should shrink map JSON a lot: {} vs {}", wanted, compressed.len(), plain.len());
+        }
+        let (encoding, _) = fetch(Some("br")).await;
+        assert_eq!(encoding, None, "Unsupported encodings fall back to plain");
+    }
+
+    #[tokio::test]
+    async fn test_map_endpoint_algorithms() {
+        let app = create_app();
+
+        let response = app.clone().oneshot(Request::builder().uri("/map?width=60&height=30&seed=4&algo=noise").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let map: MapData = serde_json::from_slice(&body).unwrap();
+        assert_eq!(map.tiles, Algorithm::Noise.generate(4, 60, 30).tiles, "algo=noise uses the noise generator");
+
+        let response = app.clone().oneshot(Request::builder().uri("/map?width=60&height=30&seed=4&algo=rooms").body(Body::empty()).unwrap()).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let map: MapData = serde_json::from_slice(&body).unwrap();
+        assert_eq!(map.tiles, Algorithm::Rooms.generate(4, 60, 30).tiles, "algo=rooms is the default generator");
+
+        let response = app.oneshot(Request::builder().uri("/map?algo=maze").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let error: ErrorBody = serde_json::from_slice(&body).unwrap();
+        assert!(error.error.contains("rooms, noise, wfc"), "The error lists the algorithms");
+    }
+
+    #[tokio::test]
+    async fn test_chunk_endpoint_binary_via_accept_header() {
+        let app = create_app();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/map/chunk?cx=2&cy=-1")
+                    .header("accept", BINARY_CONTENT_TYPE)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers().get("content-type").unwrap(), BINARY_CONTENT_TYPE);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let chunk = exospace_core::ChunkData::from_bytes(&body).unwrap();
+        assert_eq!((chunk.cx, chunk.cy), (2, -1));
+    }
+
+    #[test]
+    fn test_wants_binary() {
+        let mut headers = HeaderMap::new();
+        assert!(!wants_binary(None, &headers), "JSON is the default");
+        assert!(wants_binary(Some("bin"), &headers));
+        assert!(!wants_binary(Some("json"), &headers));
+
+        headers.insert(header::ACCEPT, BINARY_CONTENT_TYPE.parse().unwrap());
+        assert!(wants_binary(None, &headers));
+        assert!(!wants_binary(Some("json"), &headers), "Explicit format beats Accept");
+    }
+
+    #[tokio::test]
+    async fn test_chunk_endpoint_requires_coords() {
+        let app = create_app();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/map/chunk?cx=1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_endpoint_rejects_out_of_world_coords() {
+        let uri = format!("/map/chunk?cx={}&cy=0", exospace_core::MAX_CHUNK_COORD + 1);
+        let response = create_app().oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_map_endpoint_rejects_bad_sizes() {
+        for uri in ["/map?width=0&height=50", "/map?width=100&height=2", "/map?width=100000&height=100000"] {
+            let response = create_app()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{} should be rejected", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_map_limits_are_configurable() {
+        let state = AppState { map_limits: mapcache::MapLimits { max_width: 100, max_height: 50 }, ..test_state() };
+        let app = create_router(state);
+        let get = |uri: &'static str| app.clone().oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap());
+
+        assert_eq!(get("/map?width=100&height=50").await.unwrap().status(), StatusCode::OK);
+        let response = get("/map?width=101&height=50").await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let error: ErrorBody = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.error, "Map size must be between 3x3 and 100x50", "The error names the server's limits");
+        assert_eq!(get("/map/preview?width=60&height=60").await.unwrap().status(), StatusCode::BAD_REQUEST, "Previews share the limits");
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_json() {
+        let app = create_app();
+        let error_of = |request: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let content_type = response.headers().get(header::CONTENT_TYPE).cloned();
+                assert_eq!(content_type.as_ref().and_then(|v| v.to_str().ok()), Some("application/json"), "Errors are JSON ({})", status);
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                (status, serde_json::from_slice::<ErrorBody>(&body).unwrap())
+            }
+        };
+        let get = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        let (status, body) = error_of(get("/map?width=wide")).await;
+        assert_eq!((status, body.code), (StatusCode::BAD_REQUEST, ErrorCode::MalformedRequest), "A query that isn't a number");
+        assert!(body.error.contains("width"), "The error says what was wrong: {}", body.error);
+        let (status, body) = error_of(get("/map?width=100000&height=100000")).await;
+        assert_eq!((status, body.code), (StatusCode::BAD_REQUEST, ErrorCode::MapSize));
+        let (status, body) = error_of(get("/map?algo=maze")).await;
+        assert_eq!((status, body.code), (StatusCode::BAD_REQUEST, ErrorCode::UnknownAlgorithm));
+        let (status, body) = error_of(get("/map/chunk?cx=1")).await;
+        assert_eq!((status, body.code), (StatusCode::BAD_REQUEST, ErrorCode::MalformedRequest));
+        let (status, body) = error_of(get("/station/nowhere/market")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, ErrorBody { code: ErrorCode::UnknownStation, error: "No such station".to_string() });
+        let (status, body) = error_of(get("/me")).await;
+        assert_eq!((status, body.code), (StatusCode::UNAUTHORIZED, ErrorCode::BadToken));
+        let (status, body) = error_of(admin_request("POST", "/admin/cache/clear", "secret", None)).await;
+        assert_eq!((status, body.code), (StatusCode::FORBIDDEN, ErrorCode::AdminDisabled), "Admin routes are off without a token");
+        let (status, body) = error_of(get("/nonexistent")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, ErrorBody { code: ErrorCode::NotFound, error: "No such route".to_string() });
+
+        let login = Request::builder()
+            .method("POST")
+            .uri("/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("not json"))
+            .unwrap();
+        let (status, body) = error_of(login).await;
+        assert_eq!((status, body.code), (StatusCode::BAD_REQUEST, ErrorCode::MalformedRequest), "A body that isn't JSON");
+        let (_, body) = error_of(admin_request("POST", "/register", "", Some(serde_json::json!({"name": "Ace", "password": "short"})))).await;
+        assert_eq!(body.code, ErrorCode::Invalid);
+    }
+
     #[tokio::test]
     async fn test_404_for_unknown_route() {
         let app = create_app();
@@ -784,4 +1467,1051 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
+
+    // ==================== Account Tests ====================
+
+    fn post_json(uri: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_register_login_and_me() {
+        let app = create_app();
+        let credentials = r#"{"name":"Ace","password":"hunter22"}"#;
+
+        let response = app.clone().oneshot(post_json("/register", credentials)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let again = app.clone().oneshot(post_json("/register", credentials)).await.unwrap();
+        assert_eq!(again.status(), StatusCode::CONFLICT, "Names are unique");
+
+        let wrong = app.clone().oneshot(post_json("/login", r#"{"name":"Ace","password":"nope-nope"}"#)).await.unwrap();
+        assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+        let response = app.clone().oneshot(post_json("/login", credentials)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let token: exospace_core::AuthToken = serde_json::from_slice(&body).unwrap();
+
+        let me = |auth: String| Request::builder().uri("/me").header(header::AUTHORIZATION, auth).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(me(format!("Bearer {}", token.token))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let account: exospace_core::AccountInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(account.name, "Ace");
+
+        let response = app.oneshot(me("Bearer forged".to_string())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_station_market_endpoint() {
+        use exospace_core::{Direction, MarketInfo, TradeReceipt, TransactionKind};
+
+        let state = test_state();
+        let stations = stations::Stations::place(&state.world);
+        let relay = stations.by_id("frontier-relay").cloned().unwrap();
+        let markets = market::Markets::open(&stations.list(), std::time::Instant::now());
+        let throttle = throttle::Throttle::new(throttle::Budgets { trades_per_min: 4, ..throttle::Budgets::default() });
+        let state = AppState { markets: Arc::new(markets), stations: Arc::new(stations), throttle: Arc::new(throttle), ..state };
+        let credentials = exospace_core::Credentials { name: "Ace".into(), password: "hunter22".into() };
+        let token = state.users.register(&credentials).unwrap().token;
+        state.world.post_transaction("Ace", TransactionKind::Mission, 1000, "Stake").unwrap();
+        let sessions = state.sessions.clone();
+        let app = create_router(state);
+        let get = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let as_ace = |mut request: Request<Body>| {
+            request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+            request
+        };
+
+        let response = app.clone().oneshot(get("/station/frontier-relay/market")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let market: MarketInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(market.station, "Frontier Relay");
+        let stock = market.goods[0].stock;
+
+        let buy = r#"{"item":"ore","quantity":5,"side":"buy"}"#;
+        let response = app.clone().oneshot(post_json("/station/frontier-relay/market", buy)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "Guests don't trade");
+        let response = app.clone().oneshot(as_ace(post_json("/station/frontier-relay/market", buy))).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(serde_json::from_slice::<ErrorBody>(&body).unwrap().code, ErrorCode::NotDocked, "Not flying at all");
+
+        let (pilot, _receiver) = sessions.join("Ace");
+        sessions.update_position(pilot.id, relay.x + stations::DOCKING_RANGE as i32, relay.y, Direction::Up);
+        let response = app.clone().oneshot(as_ace(post_json("/station/frontier-relay/market", buy))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let receipt: TradeReceipt = serde_json::from_slice(&body).unwrap();
+        assert_eq!(receipt.market.goods[0].stock, stock - 5);
+        assert!(receipt.total >= 5 * market.goods[0].buy_price);
+
+        let greedy = r#"{"item":"ore","quantity":500,"side":"buy"}"#;
+        let response = app.clone().oneshot(as_ace(post_json("/station/frontier-relay/market", greedy))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT, "Can't buy more than the stock");
+        let response = app.clone().oneshot(as_ace(post_json("/station/haven-station/market", buy))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT, "Docked somewhere else");
+        let response = app.clone().oneshot(as_ace(post_json("/station/frontier-relay/market", buy))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS, "Trades keep to the budget");
+        let response = app.oneshot(get("/station/atlantis/market")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_mission_board_endpoints() {
+        use exospace_core::{MissionBoard, MissionReward, Objective};
+
+        let state = test_state();
+        let state = AppState { missions: Arc::new(missions::Missions::new(state.world.seed, &[])), ..state };
+        let missions = state.missions.clone();
+        let credentials = exospace_core::Credentials { name: "Ace".into(), password: "hunter22".into() };
+        let token = state.users.register(&credentials).unwrap().token;
+        let world = state.world.clone();
+        let app = create_router(state);
+        let as_ace = |mut request: Request<Body>| {
+            request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+            request
+        };
+        let error_of = |request: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                (status, serde_json::from_slice::<ErrorBody>(&body).unwrap())
+            }
+        };
+        let response = app.clone().oneshot(Request::builder().uri("/missions").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let board: MissionBoard = serde_json::from_slice(&body).unwrap();
+        assert_eq!(board.missions.len(), missions::MISSIONS_PER_BOARD as usize);
+        assert!(board.expires_in <= missions::BOARD_PERIOD.as_secs());
+
+        let job = board.missions.iter().find(|m| matches!(m.objective, Objective::Mine { .. } | Objective::Scan { .. }));
+        let (status, body) = error_of(post_json("/missions/1-0/accept", "")).await;
+        assert_eq!((status, body.code), (StatusCode::UNAUTHORIZED, ErrorCode::BadToken), "Guests don't take missions");
+        let (status, body) = error_of(as_ace(post_json("/missions/1-0/complete", ""))).await;
+        assert_eq!((status, body.code), (StatusCode::GONE, ErrorCode::MissionExpired));
+        let (status, body) = error_of(as_ace(post_json("/missions/nope/accept", ""))).await;
+        assert_eq!((status, body.code), (StatusCode::NOT_FOUND, ErrorCode::UnknownMission));
+        let (status, body) = error_of(as_ace(post_json(&format!("/missions/{}/complete", board.missions[0].id), ""))).await;
+        assert_eq!((status, body.code), (StatusCode::CONFLICT, ErrorCode::MissionNotAccepted));
+
+        // The server keeps the score, not the pilot; a board of nothing but deliveries (rare) ends the test here
+        let Some(job) = job else { return };
+        let response = app.clone().oneshot(as_ace(post_json(&format!("/missions/{}/accept", job.id), ""))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let complete = || as_ace(post_json(&format!("/missions/{}/complete", job.id), ""));
+        let (status, body) = error_of(complete()).await;
+        assert_eq!((status, body.code), (StatusCode::CONFLICT, ErrorCode::MissionUnfinished));
+        match job.objective {
+            Objective::Mine { ore } => world.add_player_stats("Ace", exospace_core::PilotStats { ore_mined: ore.into(), ..Default::default() }),
+            Objective::Scan { tiles } => (0..tiles as i32).for_each(|x| missions.surveyed("Ace", x, 0)),
+            _ => unreachable!(),
+        }
+        let response = app.clone().oneshot(complete()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let reward: MissionReward = serde_json::from_slice(&body).unwrap();
+        assert_eq!((reward.mission.as_str(), reward.balance), (job.id.as_str(), Some(job.credits)));
+        for item in &job.items {
+            assert_eq!(world.cargo("Ace", &item.item).unwrap(), item.count, "Reward items go in the hold, ready to sell");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_community_map_endpoints() {
+        use exospace_core::{MapListing, Tile};
+
+        let state = test_state();
+        let credentials = exospace_core::Credentials { name: "Ace".into(), password: "hunter22".into() };
+        let token = state.users.register(&credentials).unwrap().token;
+        let app = create_router(state);
+        let upload = |name: &str, content_type: &str, body: Vec<u8>, token: Option<&str>| {
+            let mut request = Request::builder().method("POST").uri(format!("/maps/upload?name={}", name)).header(header::CONTENT_TYPE, content_type);
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            request.body(Body::from(body)).unwrap()
+        };
+        let maze = MapData { start_x: 2, start_y: 1, ..MapData::filled(8, 4, Tile::Floor) };
+
+        let response = app.clone().oneshot(upload("Maze", BINARY_CONTENT_TYPE, maze.to_bytes(), None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "Guests can't upload");
+        let response = app.clone().oneshot(upload("Maze", BINARY_CONTENT_TYPE, maze.to_bytes(), Some(&token))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let listing: MapListing = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        assert_eq!((listing.id, listing.author.as_str(), listing.width, listing.height), (1, "Ace", 8, 4));
+        let json = serde_json::to_vec(&maze).unwrap();
+        let response = app.clone().oneshot(upload("Maze%20too", "application/json", json, Some(&token))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED, "JSON maps are taken too");
+
+        let response = app.clone().oneshot(Request::builder().uri("/maps").body(Body::empty()).unwrap()).await.unwrap();
+        let listings: Vec<MapListing> = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        let names: Vec<_> = listings.iter().map(|listing| listing.name.as_str()).collect();
+        assert_eq!(names, vec!["Maze too", "Maze"]);
+        let response = app.clone().oneshot(Request::builder().uri("/maps/1?format=bin").body(Body::empty()).unwrap()).await.unwrap();
+        let fetched = MapData::from_bytes(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        assert_eq!((fetched.tiles, fetched.start_x), (maze.tiles.clone(), 2));
+
+        let response = app.clone().oneshot(Request::builder().uri("/maps/9").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: ErrorBody = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        assert_eq!(body.code, ErrorCode::NotFound);
+        let huge = MapData::filled(MAX_MAP_WIDTH + 1, 3, Tile::Floor);
+        let response = app.clone().oneshot(upload("Huge", BINARY_CONTENT_TYPE, huge.to_bytes(), Some(&token))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Uploads keep to the map size limit");
+        let response = app.oneshot(upload("Junk", BINARY_CONTENT_TYPE, b"not a map".to_vec(), Some(&token))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_player_ledger_endpoints() {
+        use exospace_core::{Balance, RepairReceipt, TradeReceipt, TransactionHistory, TransactionKind};
+
+        let state = test_state();
+        let stations = stations::Stations::place(&state.world);
+        let markets = market::Markets::open(&stations.list(), std::time::Instant::now());
+        let state = AppState { markets: Arc::new(markets), stations: Arc::new(stations), ..state };
+        let credentials = exospace_core::Credentials { name: "Ace".into(), password: "hunter22".into() };
+        let token = state.users.register(&credentials).unwrap().token;
+        let world = state.world.clone();
+        let haven = state.stations.by_id("haven-station").cloned().unwrap();
+        let (pilot, _receiver) = state.sessions.join("Ace");
+        state.sessions.update_position(pilot.id, haven.x, haven.y, exospace_core::Direction::Up);
+        let app = create_router(state);
+        let as_ace = |mut request: Request<Body>| {
+            request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+            request
+        };
+        let get = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let json_of = |request: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                response.into_body().collect().await.unwrap().to_bytes()
+            }
+        };
+
+        let response = app.clone().oneshot(get("/player/balance")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "Guests have no balance");
+        let buy = r#"{"item":"ore","quantity":1,"side":"buy"}"#;
+        let response = app.clone().oneshot(as_ace(post_json("/station/haven-station/market", buy))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT, "A new account can't buy anything");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(serde_json::from_slice::<ErrorBody>(&body).unwrap().code, ErrorCode::ShortCredits);
+
+        let sell = r#"{"item":"ore","quantity":5,"side":"sell"}"#;
+        let response = app.clone().oneshot(as_ace(post_json("/station/haven-station/market", sell))).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(serde_json::from_slice::<ErrorBody>(&body).unwrap().code, ErrorCode::ShortCargo, "Nothing aboard to sell");
+        world.stow("Ace", "ore", 7).unwrap();
+        let body = json_of(as_ace(post_json("/station/haven-station/market", sell))).await;
+        let sale: TradeReceipt = serde_json::from_slice(&body).unwrap();
+        assert_eq!(sale.balance, Some(sale.total), "Sales are paid into the account");
+        assert_eq!(world.cargo("Ace", "ore").unwrap(), 2, "Sold goods leave the hold");
+        let body = json_of(as_ace(post_json("/station/haven-station/repair", r#"{"hull":3}"#))).await;
+        let repair: RepairReceipt = serde_json::from_slice(&body).unwrap();
+        assert_eq!((repair.cost, repair.balance), (6, Some(sale.total - 6)));
+        let body = json_of(post_json("/station/haven-station/repair", r#"{"hull":3}"#)).await;
+        let quote: RepairReceipt = serde_json::from_slice(&body).unwrap();
+        assert_eq!((quote.cost, quote.balance), (6, None), "Guests pay their own way");
+
+        let balance: Balance = serde_json::from_slice(&json_of(as_ace(get("/player/balance"))).await).unwrap();
+        assert_eq!((balance.name.as_str(), balance.credits), ("Ace", sale.total - 6));
+        let history: TransactionHistory = serde_json::from_slice(&json_of(as_ace(get("/player/transactions"))).await).unwrap();
+        let kinds: Vec<_> = history.transactions.iter().map(|t| (t.kind, t.amount)).collect();
+        assert_eq!(kinds, vec![(TransactionKind::Repair, -6), (TransactionKind::Trade, sale.total as i64)], "Newest first");
+        assert_eq!(history.transactions[1].memo, "Sold 5 ore at Haven Station");
+
+        let mut forged = post_json("/station/haven-station/repair", r#"{"hull":3}"#);
+        forged.headers_mut().insert(header::AUTHORIZATION, "Bearer forged".parse().unwrap());
+        let response = app.clone().oneshot(forged).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "A bad token isn't treated as a guest");
+        let response = app.oneshot(post_json("/station/atlantis/repair", r#"{"hull":3}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_leaderboard_endpoint() {
+        use exospace_core::{Leaderboard, LeaderboardMetric, PilotStats};
+
+        let state = test_state();
+        for (name, distance) in [("Ace", 40), ("Bee", 90), ("Cyd", 10)] {
+            let credentials = exospace_core::Credentials { name: name.into(), password: "hunter22".into() };
+            state.users.register(&credentials).unwrap();
+            state.world.add_player_stats(name, PilotStats { distance, ..PilotStats::default() });
+        }
+        let app = create_router(state);
+        let board_at = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                serde_json::from_slice::<Leaderboard>(&body).unwrap()
+            }
+        };
+
+        let board = board_at("/leaderboard").await;
+        assert_eq!((board.metric, board.page, board.pages), (LeaderboardMetric::Distance, 1, 1), "Distance by default");
+        let ranks: Vec<_> = board.entries.iter().map(|e| (e.rank, e.name.as_str(), e.value)).collect();
+        assert_eq!(ranks, vec![(1, "Bee", 90), (2, "Ace", 40), (3, "Cyd", 10)], "Names as registered");
+
+        let board = board_at("/leaderboard?metric=distance&page=2&per_page=2").await;
+        assert_eq!((board.page, board.pages), (2, 2));
+        assert_eq!(board.entries.iter().map(|e| e.rank).collect::<Vec<_>>(), vec![3], "Ranks carry across pages");
+        let board = board_at("/leaderboard?metric=credits").await;
+        assert_eq!((board.metric, board.pages), (LeaderboardMetric::Credits, 1));
+        assert!(board.entries.is_empty(), "Nobody has earned anything");
+
+        for uri in ["/leaderboard?metric=kills", "/leaderboard?page=0", "/leaderboard?per_page=51"] {
+            let response = app.clone().oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(serde_json::from_slice::<ErrorBody>(&body).unwrap().code, ErrorCode::Invalid, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_star_system_endpoints() {
+        use exospace_core::{ChunkData, PoiKind, SystemInfo, chunk_coords};
+
+        let state = test_state();
+        let state = AppState { systems: Arc::new(systems::Systems::chart(state.world.seed)), ..state };
+        let app = create_router(state.clone());
+        let get = |uri: String| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(Request::builder().uri(&uri).body(Body::empty()).unwrap()).await.unwrap();
+                let status = response.status();
+                (status, response.into_body().collect().await.unwrap().to_bytes())
+            }
+        };
+
+        let (status, body) = get("/systems".to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+        let systems: Vec<SystemInfo> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(systems[0].seed, state.world.seed, "Home comes first");
+        let vega = systems.iter().find(|system| system.id == "vega").unwrap();
+        let gate = vega.gate_to("haven").unwrap();
+        let (cx, cy) = chunk_coords(gate.x, gate.y);
+
+        let (status, body) = get(format!("/system/Vega/map?cx={}&cy={}", cx, cy)).await;
+        assert_eq!(status, StatusCode::OK);
+        let chunk: ChunkData = serde_json::from_slice(&body).unwrap();
+        let gates: Vec<_> = chunk.pois.iter().filter(|poi| poi.kind == PoiKind::JumpGate).map(|poi| (poi.name.as_str(), poi.x, poi.y)).collect();
+        assert!(gates.contains(&("Gate to Haven", gate.x, gate.y)), "The chunk carries its gate");
+        let (_, plain) = get(format!("/map/chunk?cx={}&cy={}&seed={}", cx, cy, vega.seed)).await;
+        assert_eq!(plain, body, "The same chunk by seed");
+
+        let (status, body) = get("/system/nowhere/map?cx=0&cy=0".to_string()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(serde_json::from_slice::<ErrorBody>(&body).unwrap().code, ErrorCode::NotFound);
+        let (status, _) = get("/system/vega/map?cx=0".to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "Both chunk coordinates are needed");
+    }
+
+    #[tokio::test]
+    async fn test_register_rejects_bad_input() {
+        let app = create_app();
+        let response = app.clone().oneshot(post_json("/register", r#"{"name":"A","password":"hunter22"}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = app.clone().oneshot(post_json("/register", r#"{"name":"Ace","password":"short"}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = app.oneshot(post_json("/register", "not json")).await.unwrap();
+        assert!(response.status().is_client_error(), "Malformed body is refused");
+    }
+
+    #[tokio::test]
+    async fn test_pilots_cant_set_tiles() {
+        let state = test_state();
+        let credentials = exospace_core::Credentials { name: "Ace".into(), password: "hunter22".into() };
+        let token = state.users.register(&credentials).unwrap().token;
+        let app = create_router(state.clone());
+
+        let mut request = post_json("/world/tile", r#"{"x":3,"y":4,"tile":"Asteroid"}"#);
+        request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "Only /admin/tile sets tiles outright");
+
+        state.world.set_tile(3, 4, Tile::Asteroid).unwrap();
+        let response = app.oneshot(Request::builder().uri("/map/chunk?cx=0&cy=0").body(Body::empty()).unwrap()).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let chunk: exospace_core::ChunkData = serde_json::from_slice(&body).unwrap();
+        assert_eq!(chunk.get(3, 4), Some(Tile::Asteroid), "Chunks include stored edits");
+    }
+
+    #[tokio::test]
+    async fn test_map_diff_lists_changes_since_a_version() {
+        let state = test_state();
+        let app = create_router(state.clone());
+        let chunk_version = |response: &Response| response.headers().get(exospace_core::WORLD_VERSION_HEADER).map(|v| v.to_str().unwrap().to_string());
+
+        let response = app.clone().oneshot(Request::builder().uri("/map/chunk?cx=0&cy=0").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(chunk_version(&response).as_deref(), Some("0"), "Chunks say which version they're current as of");
+        state.world.set_tile(3, 4, Tile::Asteroid).unwrap();
+        state.world.set_tile(-5, 6, Tile::Wall).unwrap();
+        let response = app.clone().oneshot(Request::builder().uri("/map/chunk?cx=0&cy=0").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(chunk_version(&response).as_deref(), Some("2"));
+        let response = app.clone().oneshot(Request::builder().uri("/map/chunk?cx=0&cy=0&seed=99").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(chunk_version(&response), None, "Other seeds never change");
+
+        let response = app.clone().oneshot(Request::builder().uri("/map/diff?since=1").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let diff: exospace_core::MapDiff = serde_json::from_slice(&body).unwrap();
+        assert_eq!(diff, exospace_core::MapDiff {
+            version: 2,
+            tiles: vec![exospace_core::TileChange { x: -5, y: 6, tile: Tile::Wall }],
+            more: false,
+        });
+
+        let response = app.oneshot(Request::builder().uri("/map/diff?since=x").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_map_and_chunks_revalidate_with_etags() {
+        let state = test_state();
+        let app = create_router(state.clone());
+        let get = |uri: &str, etag: Option<&axum::http::HeaderValue>| {
+            let mut request = Request::builder().uri(uri);
+            if let Some(etag) = etag {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        let response = app.clone().oneshot(get("/map?width=40&height=30", None)).await.unwrap();
+        let map_tag = response.headers().get(header::ETAG).unwrap().clone();
+        let response = app.clone().oneshot(get("/map?width=40&height=30", Some(&map_tag))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(response.into_body().collect().await.unwrap().to_bytes().is_empty(), "A 304 has no body");
+        let response = app.clone().oneshot(get("/map?width=40&height=30&format=bin", Some(&map_tag))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "The binary encoding is a different copy");
+        let response = app.clone().oneshot(get("/map?width=40&height=31", Some(&map_tag))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.clone().oneshot(get("/map/chunk?cx=0&cy=0&format=bin", None)).await.unwrap();
+        let chunk_tag = response.headers().get(header::ETAG).unwrap().clone();
+        state.world.set_tile(exospace_core::CHUNK_SIZE * 3, 0, Tile::Wall).unwrap();
+        let response = app.clone().oneshot(get("/map/chunk?cx=0&cy=0&format=bin", Some(&chunk_tag))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "Edits elsewhere leave the chunk alone");
+        assert_eq!(response.headers().get(exospace_core::WORLD_VERSION_HEADER).unwrap(), "1", "Still says how current it is");
+
+        state.world.set_tile(3, 4, Tile::Wall).unwrap();
+        let response = app.oneshot(get("/map/chunk?cx=0&cy=0&format=bin", Some(&chunk_tag))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "An edit in the chunk changes its tag");
+        assert_ne!(response.headers().get(header::ETAG), Some(&chunk_tag));
+    }
+
+    #[tokio::test]
+    async fn test_tile_update_only_breaks_tiles() {
+        let state = test_state();
+        let credentials = exospace_core::Credentials { name: "Ace".into(), password: "hunter22".into() };
+        let token = state.users.register(&credentials).unwrap().token;
+        state.world.set_tile(3, 4, Tile::Asteroid).unwrap();
+        state.world.set_tile(5, 4, Tile::OreAsteroid).unwrap();
+        let app = create_router(state.clone());
+        let update = |body: &'static str| {
+            let mut request = post_json("/tile/update", body);
+            request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+            request
+        };
+
+        let response = app.clone().oneshot(post_json("/tile/update", r#"{"x":3,"y":4,"tile":"Floor"}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app.clone().oneshot(update(r#"{"x":3,"y":4,"tile":"Floor"}"#)).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(serde_json::from_slice::<ErrorBody>(&body).unwrap().code, ErrorCode::OutOfReach, "Not flying at all");
+        let (pilot, _receiver) = state.sessions.join("Ace");
+        state.sessions.update_position(pilot.id, 4 + world::EDIT_REACH as i32, 4, exospace_core::Direction::Up);
+        let response = app.clone().oneshot(update(r#"{"x":3,"y":4,"tile":"Floor"}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT, "Too far from the tile");
+        assert_eq!(state.world.tile(3, 4), Tile::Asteroid);
+
+        state.sessions.update_position(pilot.id, 4, 4, exospace_core::Direction::Up);
+        let response = app.clone().oneshot(update(r#"{"x":3,"y":4,"tile":"Wall"}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT, "Asteroids don't become walls");
+        let response = app.clone().oneshot(update(r#"{"x":3,"y":4,"tile":"Floor"}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(state.world.tile(3, 4), Tile::Floor);
+        let response = app.clone().oneshot(update(r#"{"x":3,"y":4,"tile":"Floor"}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT, "Only the first pilot breaks it");
+        assert_eq!(state.world.player_stats("Ace").ore_mined, 0, "Lasered rock isn't mining");
+
+        let response = app.oneshot(update(r#"{"x":5,"y":4,"tile":"Asteroid"}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(state.world.player_stats("Ace").ore_mined, 3, "Mined ore counts for the pilot who broke it");
+        assert_eq!(state.world.cargo("Ace", "ore").unwrap(), 3, "And goes in their hold");
+    }
+
+    // ==================== WebSocket Tests ====================
+
+    async fn spawn_server() -> SocketAddr {
+        spawn_server_with(test_state()).await
+    }
+
+    async fn spawn_server_with(state: AppState) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Moves only reach other sessions through the delta system
+        let mut simulation = tick::Simulation::new(tick::DEFAULT_TICK_RATE);
+        simulation.add(Box::new(sync::DeltaBroadcast::new(state.sessions.clone())));
+        tokio::spawn(simulation.run(std::future::pending()));
+        tokio::spawn(async move {
+            axum::serve(listener, create_router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+        });
+        addr
+    }
+
+    async fn next_server_message<S>(ws: &mut S) -> exospace_core::ServerMessage
+    where
+        S: futures_util::Stream<Item = Result<tokio_tungstenite::tungstenite::Message, tokio_tungstenite::tungstenite::Error>>
+            + Unpin,
+    {
+        use futures_util::StreamExt;
+        loop {
+            let msg = tokio::time::timeout(std::time::Duration::from_secs(2), ws.next())
+                .await
+                .expect("timed out waiting for server message")
+                .unwrap()
+                .unwrap();
+            if let tokio_tungstenite::tungstenite::Message::Text(text) = msg {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    /// The next message that isn't a `WorldDelta` (or its `AreaChanged`), which can land between a request and its reply
+    async fn next_reply<S>(ws: &mut S) -> exospace_core::ServerMessage
+    where
+        S: futures_util::Stream<Item = Result<tokio_tungstenite::tungstenite::Message, tokio_tungstenite::tungstenite::Error>>
+            + Unpin,
+    {
+        loop {
+            match next_server_message(ws).await {
+                exospace_core::ServerMessage::WorldDelta { .. } | exospace_core::ServerMessage::AreaChanged { .. } => continue,
+                message => return message,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ws_position_sync_between_players() {
+        use exospace_core::{ClientMessage, Direction, EntityKind, EntityMove, ServerMessage};
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let addr = spawn_server().await;
+        let url = format!("ws://{}/ws", addr);
+
+        let send = |msg: ClientMessage| Message::text(serde_json::to_string(&msg).unwrap());
+
+        let (mut alpha, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        alpha.send(send(ClientMessage::Hello { name: "Alpha".into() })).await.unwrap();
+        let ServerMessage::Welcome { id: alpha_id, players, .. } = next_server_message(&mut alpha).await else {
+            panic!("expected welcome");
+        };
+        assert!(players.is_empty(), "First player should see nobody else");
+
+        let (mut bravo, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        bravo.send(send(ClientMessage::Hello { name: "Bravo".into() })).await.unwrap();
+        let ServerMessage::Welcome { id: bravo_id, players, seq, unix_secs } = next_server_message(&mut bravo).await else {
+            panic!("expected welcome");
+        };
+        assert!(unix_secs > 0, "The server's clock comes with the welcome");
+        assert_eq!(players.len(), 1);
+        assert_eq!(players[0].name, "Alpha");
+
+        // Alpha hears about Bravo joining
+        match next_server_message(&mut alpha).await {
+            ServerMessage::PlayerJoined { player } => assert_eq!(player.id, bravo_id),
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        // Alpha moves, Bravo sees it in the next numbered delta
+        alpha
+            .send(send(ClientMessage::Position { x: 4, y: 5, direction: Direction::Right, seed: None }))
+            .await
+            .unwrap();
+        let alpha_moved = EntityMove { kind: EntityKind::Player, id: alpha_id, x: 4, y: 5, direction: Direction::Right };
+        assert_eq!(
+            next_server_message(&mut bravo).await,
+            ServerMessage::WorldDelta { seq: seq + 1, moved: vec![alpha_moved], tiles: Vec::new() }
+        );
+
+        // A resync gets the same state in one snapshot
+        bravo.send(send(ClientMessage::Resync)).await.unwrap();
+        let ServerMessage::Snapshot { seq: snapshot_seq, players, .. } = next_server_message(&mut bravo).await else {
+            panic!("expected snapshot");
+        };
+        assert_eq!(snapshot_seq, seq + 1);
+        assert_eq!((players[0].x, players[0].y), (4, 5));
+
+        // Alpha disconnects, Bravo is told
+        alpha.close(None).await.unwrap();
+        assert_eq!(next_server_message(&mut bravo).await, ServerMessage::PlayerLeft { id: alpha_id });
+    }
+
+    #[tokio::test]
+    async fn test_ws_moves_out_of_range_are_filtered() {
+        use exospace_core::{ClientMessage, Credentials, Direction, EntityId, EntityKind, ServerMessage};
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::{Message, client::IntoClientRequest};
+
+        // Both pilots left off near the edge between two buckets, Bravo a bucket further out
+        let state = test_state();
+        let edge = interest::BUCKET_SIZE * 2;
+        let mut tokens = Vec::new();
+        for (name, x) in [("Alpha", edge - 68), ("Bravo", edge - 3)] {
+            let credentials = Credentials { name: name.into(), password: "hunter22".into() };
+            tokens.push(state.users.register(&credentials).unwrap().token);
+            state.world.save_player_position(name, x, 0);
+        }
+        let addr = spawn_server_with(state).await;
+        let connect = |token: &str| {
+            let mut request = format!("ws://{}/ws", addr).into_client_request().unwrap();
+            request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+            tokio_tungstenite::connect_async(request)
+        };
+        let send = |msg: ClientMessage| Message::text(serde_json::to_string(&msg).unwrap());
+
+        let (mut alpha, _) = connect(&tokens[0]).await.unwrap();
+        alpha.send(send(ClientMessage::Hello { name: "Alpha".into() })).await.unwrap();
+        next_server_message(&mut alpha).await;
+        assert_eq!(next_reply(&mut alpha).await, ServerMessage::Resume { x: edge - 68, y: 0 });
+        let (mut bravo, _) = connect(&tokens[1]).await.unwrap();
+        bravo.send(send(ClientMessage::Hello { name: "Bravo".into() })).await.unwrap();
+        let ServerMessage::Welcome { id: bravo_id, .. } = next_server_message(&mut bravo).await else {
+            panic!("expected welcome");
+        };
+        assert_eq!(next_reply(&mut bravo).await, ServerMessage::Resume { x: edge - 3, y: 0 });
+        assert!(matches!(next_reply(&mut alpha).await, ServerMessage::PlayerJoined { .. }));
+
+        // Bravo flies off; Alpha is told once, then hears no more of its moves
+        bravo.send(send(ClientMessage::Position { x: edge + 2, y: 0, direction: Direction::Right, seed: None })).await.unwrap();
+        let bravo_gone = vec![EntityId { kind: EntityKind::Player, id: bravo_id }];
+        loop {
+            match next_server_message(&mut alpha).await {
+                ServerMessage::AreaChanged { players, gone, .. } => {
+                    assert!(players.is_empty());
+                    assert_eq!(gone, bravo_gone);
+                    break;
+                }
+                // Bravo's resume is still in range
+                ServerMessage::WorldDelta { moved, .. } => assert!(moved.iter().all(|m| m.x < edge), "Out of range moves aren't sent"),
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+        bravo.send(send(ClientMessage::Position { x: edge + 3, y: 0, direction: Direction::Right, seed: None })).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        // Alpha follows and is shown Bravo again
+        alpha.send(send(ClientMessage::Position { x: edge - 60, y: 0, direction: Direction::Right, seed: None })).await.unwrap();
+        let players = loop {
+            match next_server_message(&mut alpha).await {
+                ServerMessage::AreaChanged { players, .. } => break players,
+                ServerMessage::WorldDelta { moved, .. } => assert!(moved.is_empty(), "Out of range moves aren't sent"),
+                other => panic!("unexpected message {:?}", other),
+            }
+        };
+        assert_eq!(players.iter().map(|p| (p.id, p.x)).collect::<Vec<_>>(), [(bravo_id, edge + 3)]);
+    }
+
+    #[tokio::test]
+    async fn test_ws_chat_relay_and_limits() {
+        use exospace_core::{ClientMessage, MAX_CHAT_LEN, ServerMessage};
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let addr = spawn_server().await;
+        let url = format!("ws://{}/ws", addr);
+        let send = |msg: ClientMessage| Message::text(serde_json::to_string(&msg).unwrap());
+        let chat = |text: &str| send(ClientMessage::Chat { text: text.to_string() });
+
+        let (mut alpha, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        alpha.send(send(ClientMessage::Hello { name: "Alpha".into() })).await.unwrap();
+        let ServerMessage::Welcome { id: alpha_id, .. } = next_server_message(&mut alpha).await else {
+            panic!("expected welcome");
+        };
+
+        let (mut bravo, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        bravo.send(send(ClientMessage::Hello { name: "Bravo".into() })).await.unwrap();
+        let _welcome = next_server_message(&mut bravo).await;
+        let _joined = next_server_message(&mut alpha).await;
+
+        // Relayed to others with the sender's name
+        alpha.send(chat("  hello bravo ")).await.unwrap();
+        assert_eq!(
+            next_server_message(&mut bravo).await,
+            ServerMessage::Chat { id: alpha_id, name: "Alpha".into(), text: "hello bravo".into() }
+        );
+
+        // Oversized lines are rejected back to the sender only
+        alpha.send(chat(&"x".repeat(MAX_CHAT_LEN + 1))).await.unwrap();
+        assert!(matches!(next_server_message(&mut alpha).await, ServerMessage::ChatRejected { .. }));
+
+        // Flooding trips the rate limiter
+        for i in 0..10 {
+            alpha.send(chat(&format!("spam {}", i))).await.unwrap();
+        }
+        assert!(matches!(next_server_message(&mut alpha).await, ServerMessage::ChatRejected { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_ws_chat_budget_per_minute() {
+        use exospace_core::{ClientMessage, ServerMessage};
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let throttle = throttle::Throttle::new(throttle::Budgets { chat_per_min: 1, ..throttle::Budgets::default() });
+        let addr = spawn_server_with(AppState { throttle: Arc::new(throttle), ..test_state() }).await;
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        let send = |msg: ClientMessage| Message::text(serde_json::to_string(&msg).unwrap());
+        ws.send(send(ClientMessage::Hello { name: "Alpha".into() })).await.unwrap();
+        next_server_message(&mut ws).await;
+
+        ws.send(send(ClientMessage::Chat { text: "one".into() })).await.unwrap();
+        ws.send(send(ClientMessage::Chat { text: "two".into() })).await.unwrap();
+        loop {
+            match next_reply(&mut ws).await {
+                ServerMessage::Chat { text, .. } => assert_eq!(text, "one", "Only the first line goes out"),
+                ServerMessage::ChatRejected { reason } => {
+                    assert_eq!(reason, "Chat limit reached; try again in 60s");
+                    break;
+                }
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_map_requests_over_budget() {
+        let throttle = throttle::Throttle::new(throttle::Budgets { maps_per_min: 2, ..throttle::Budgets::default() });
+        let state = AppState { throttle: Arc::new(throttle), ..test_state() };
+        let get = |uri: &str| create_router(state.clone()).oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap());
+
+        assert_eq!(get("/map/chunk?cx=0&cy=0").await.unwrap().status(), StatusCode::OK);
+        assert_eq!(get("/map?width=20&height=10").await.unwrap().status(), StatusCode::OK);
+        let response = get("/map/chunk?cx=1&cy=0").await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "30", "One request a minute's budget comes back every 30 seconds");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: exospace_core::ErrorBody = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body.code, exospace_core::ErrorCode::RateLimited);
+
+        assert_eq!(get("/map/daily").await.unwrap().status(), StatusCode::OK, "Only map generation is budgeted");
+        assert_eq!(get("/health").await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_ws_admin_announce_and_kick() {
+        use exospace_core::{ClientMessage, ServerMessage};
+        use futures_util::{SinkExt, StreamExt};
+        use serde_json::json;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let state = AppState { admin_token: Some(Arc::from("s3cret")), ..test_state() };
+        let addr = spawn_server_with(state.clone()).await;
+        let (mut alpha, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        let hello = ClientMessage::Hello { name: "Alpha".into() };
+        alpha.send(Message::text(serde_json::to_string(&hello).unwrap())).await.unwrap();
+        let _welcome = next_server_message(&mut alpha).await;
+
+        let admin = |uri: &str, body| create_router(state.clone()).oneshot(admin_request("POST", uri, "s3cret", Some(body)));
+        let response = admin("/admin/announce", json!({ "text": "Restart in 5 minutes" })).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"Announced to 1 pilots");
+        let announcement = ServerMessage::Ambient { source: admin::ANNOUNCEMENT_SOURCE.into(), text: "Restart in 5 minutes".into() };
+        assert_eq!(next_reply(&mut alpha).await, announcement);
+
+        let response = admin("/admin/kick", json!({ "name": "alpha", "reason": "Testing" })).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "Names match ignoring case");
+        assert_eq!(next_reply(&mut alpha).await, ServerMessage::Kicked { reason: "Testing".into() });
+        let closed = tokio::time::timeout(std::time::Duration::from_secs(2), async {
+            while let Some(Ok(message)) = alpha.next().await {
+                if message.is_close() {
+                    break;
+                }
+            }
+        });
+        closed.await.expect("The socket is closed after the kick");
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(state.sessions.len(), 0, "The session is gone");
+    }
+
+    #[tokio::test]
+    async fn test_ws_events_start_for_everyone() {
+        use exospace_core::{ClientMessage, EventKind, ServerMessage, WorldEvent};
+        use futures_util::SinkExt;
+        use serde_json::json;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let state = AppState { admin_token: Some(Arc::from("s3cret")), ..test_state() };
+        let addr = spawn_server_with(state.clone()).await;
+        let hello = |name: &str| Message::text(serde_json::to_string(&ClientMessage::Hello { name: name.into() }).unwrap());
+        let (mut alpha, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        alpha.send(hello("Alpha")).await.unwrap();
+        let _welcome = next_server_message(&mut alpha).await;
+
+        let admin = |body| create_router(state.clone()).oneshot(admin_request("POST", "/admin/event", "s3cret", Some(body)));
+        let response = admin(json!({ "kind": "solar_flare", "x": 0, "y": 0, "radius": 5, "secs": 60 })).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Only kinds the server knows");
+        let response = admin(json!({ "kind": "ion_storm", "x": 0, "y": 0, "radius": 0, "secs": 60 })).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = admin(json!({ "kind": "ion_storm", "x": 12, "y": -8, "radius": 6, "secs": 120 })).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let storm: WorldEvent = serde_json::from_slice(&body).unwrap();
+        assert_eq!((storm.kind, storm.x, storm.y, storm.radius, storm.secs_left), (EventKind::IonStorm, 12, -8, 6, 120));
+
+        assert_eq!(next_reply(&mut alpha).await, ServerMessage::EventStarted { event: storm.clone() });
+        let ServerMessage::Ambient { source, text } = next_reply(&mut alpha).await else {
+            panic!("expected an announcement");
+        };
+        assert_eq!((source.as_str(), text.as_str()), (events::ANNOUNCER, "Ion storm reported near (12, -8), 12 tiles across, for about 2 min"));
+
+        let (mut bravo, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        bravo.send(hello("Bravo")).await.unwrap();
+        let _welcome = next_server_message(&mut bravo).await;
+        let ServerMessage::EventStarted { event } = next_reply(&mut bravo).await else {
+            panic!("expected the storm under way");
+        };
+        assert_eq!((event.id, event.kind), (storm.id, EventKind::IonStorm), "Latecomers hear of events under way");
+        assert!(event.secs_left <= 120);
+    }
+
+    #[tokio::test]
+    async fn test_ws_utility_commands() {
+        use exospace_core::{ClientMessage, DistanceTarget, ServerMessage};
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let addr = spawn_server().await;
+        let url = format!("ws://{}/ws", addr);
+        let send = |msg: ClientMessage| Message::text(serde_json::to_string(&msg).unwrap());
+
+        let (mut alpha, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        alpha.send(send(ClientMessage::Hello { name: "Alpha".into() })).await.unwrap();
+        let ServerMessage::Welcome { id: alpha_id, .. } = next_server_message(&mut alpha).await else {
+            panic!("expected welcome");
+        };
+
+        let (mut bravo, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        bravo.send(send(ClientMessage::Hello { name: "Bravo".into() })).await.unwrap();
+        let _welcome = next_server_message(&mut bravo).await;
+        let _joined = next_server_message(&mut alpha).await;
+
+        // Rolls are announced to everyone, roller included, with consistent totals
+        alpha.send(send(ClientMessage::Roll { count: 3, sides: 6 })).await.unwrap();
+        for ws in [&mut alpha, &mut bravo] {
+            match next_server_message(ws).await {
+                ServerMessage::Roll { id, name, rolls, total, .. } => {
+                    assert_eq!((id, name.as_str()), (alpha_id, "Alpha"));
+                    assert_eq!(rolls.len(), 3);
+                    assert_eq!(rolls.iter().sum::<u32>(), total);
+                }
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+
+        // Invalid rolls fail only for the sender
+        alpha.send(send(ClientMessage::Roll { count: 0, sides: 6 })).await.unwrap();
+        assert!(matches!(next_server_message(&mut alpha).await, ServerMessage::CommandFailed { .. }));
+
+        // Time and distance are private replies
+        alpha.send(send(ClientMessage::Time)).await.unwrap();
+        assert!(matches!(next_server_message(&mut alpha).await, ServerMessage::Time { unix_secs } if unix_secs > 0));
+
+        alpha.send(send(ClientMessage::Distance { target: DistanceTarget::Position { x: 3, y: 4 } })).await.unwrap();
+        assert_eq!(
+            next_server_message(&mut alpha).await,
+            ServerMessage::Distance { target: "(3, 4)".into(), distance: 5 }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ws_fire_announces_shots() {
+        use exospace_core::{ClientMessage, Direction, ServerMessage};
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let addr = spawn_server().await;
+        let url = format!("ws://{}/ws", addr);
+        let send = |msg: ClientMessage| Message::text(serde_json::to_string(&msg).unwrap());
+
+        let (mut alpha, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        alpha.send(send(ClientMessage::Hello { name: "Alpha".into() })).await.unwrap();
+        let ServerMessage::Welcome { id: alpha_id, .. } = next_server_message(&mut alpha).await else {
+            panic!("expected welcome");
+        };
+        let (mut bravo, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        bravo.send(send(ClientMessage::Hello { name: "Bravo".into() })).await.unwrap();
+        let _welcome = next_server_message(&mut bravo).await;
+        let _joined = next_server_message(&mut alpha).await;
+
+        // Shots start on the shooter's tile as the server last heard it, and everyone sees them
+        alpha.send(send(ClientMessage::Position { x: 4, y: 5, direction: Direction::Up, seed: None })).await.unwrap();
+        alpha.send(send(ClientMessage::Fire { direction: Direction::Left })).await.unwrap();
+        for ws in [&mut alpha, &mut bravo] {
+            assert_eq!(
+                next_reply(ws).await,
+                ServerMessage::Shot { id: 1, owner: alpha_id, x: 4, y: 5, direction: Direction::Left }
+            );
+        }
+
+        // Straight away again is too soon, and only the shooter is told
+        alpha.send(send(ClientMessage::Fire { direction: Direction::Left })).await.unwrap();
+        assert!(matches!(next_reply(&mut alpha).await, ServerMessage::CommandFailed { reason } if reason.contains("cooling")));
+    }
+
+    #[tokio::test]
+    async fn test_ws_token_sets_account_name() {
+        use exospace_core::{ClientMessage, Credentials, ServerMessage};
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::{Message, client::IntoClientRequest};
+
+        let state = test_state();
+        let credentials = Credentials { name: "Ace".into(), password: "hunter22".into() };
+        let token = state.users.register(&credentials).unwrap().token;
+        let addr = spawn_server_with(state).await;
+        let url = format!("ws://{}/ws", addr);
+        let hello = |name: &str| Message::text(serde_json::to_string(&ClientMessage::Hello { name: name.into() }).unwrap());
+
+        let mut request = url.as_str().into_client_request().unwrap();
+        request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        let (mut ace, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        ace.send(hello("Impostor")).await.unwrap();
+        next_server_message(&mut ace).await;
+
+        // A guest asking for the account's name gets a guest name instead
+        let (mut guest, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        guest.send(hello("ace")).await.unwrap();
+        let ServerMessage::Welcome { players, .. } = next_server_message(&mut guest).await else {
+            panic!("expected welcome");
+        };
+        assert_eq!(players[0].name, "Ace", "Logged-in pilot flies under the account name");
+        match next_server_message(&mut ace).await {
+            ServerMessage::PlayerJoined { player } => assert_eq!(player.name, "Guest-ace"),
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        let mut forged = url.as_str().into_client_request().unwrap();
+        forged.headers_mut().insert(header::AUTHORIZATION, "Bearer forged".parse().unwrap());
+        match tokio_tungstenite::connect_async(forged).await {
+            Err(tokio_tungstenite::tungstenite::Error::Http(response)) => assert_eq!(response.status(), StatusCode::UNAUTHORIZED),
+            other => panic!("bad token should be refused, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ws_resumes_saved_position() {
+        use exospace_core::{ClientMessage, Credentials, ServerMessage};
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::{Message, client::IntoClientRequest};
+
+        let state = test_state();
+        let credentials = Credentials { name: "Ace".into(), password: "hunter22".into() };
+        let token = state.users.register(&credentials).unwrap().token;
+        let world = state.world.clone();
+        let addr = spawn_server_with(state).await;
+        let connect = || {
+            let mut request = format!("ws://{}/ws", addr).into_client_request().unwrap();
+            request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+            tokio_tungstenite::connect_async(request)
+        };
+        let hello = Message::text(serde_json::to_string(&ClientMessage::Hello { name: "Ace".into() }).unwrap());
+        let position = ClientMessage::Position { x: 4, y: -3, direction: exospace_core::Direction::Left, seed: None };
+
+        let (mut ws, _) = connect().await.unwrap();
+        ws.send(hello.clone()).await.unwrap();
+        next_server_message(&mut ws).await;
+        ws.send(Message::text(serde_json::to_string(&position).unwrap())).await.unwrap();
+        ws.close(None).await.unwrap();
+
+        // The server saves on disconnect; give it a moment to notice
+        for _ in 0..50 {
+            if world.player_position("Ace").is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(world.player_position("Ace"), Some((4, -3)));
+
+        let (mut ws, _) = connect().await.unwrap();
+        ws.send(hello).await.unwrap();
+        assert!(matches!(next_server_message(&mut ws).await, ServerMessage::Welcome { .. }));
+        assert_eq!(next_server_message(&mut ws).await, ServerMessage::Resume { x: 4, y: -3 });
+    }
+
+    #[tokio::test]
+    async fn test_ws_home_station_and_respawn() {
+        use exospace_core::{ClientMessage, Credentials, Direction, ServerMessage};
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::{Message, client::IntoClientRequest};
+
+        let state = test_state();
+        let state = AppState { stations: Arc::new(stations::Stations::place(&state.world)), ..state };
+        let credentials = Credentials { name: "Ace".into(), password: "hunter22".into() };
+        let token = state.users.register(&credentials).unwrap().token;
+        let relay = state.stations.get("Frontier Relay").unwrap().clone();
+        // Sessions start at the spawn point, so leave off next to the relay
+        state.world.save_player_position("Ace", relay.x, relay.y + 2);
+        let addr = spawn_server_with(state).await;
+        let mut request = format!("ws://{}/ws", addr).into_client_request().unwrap();
+        request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        let (mut ws, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        let send = |message: ClientMessage| Message::text(serde_json::to_string(&message).unwrap());
+
+        ws.send(send(ClientMessage::Hello { name: "Ace".into() })).await.unwrap();
+        assert!(matches!(next_server_message(&mut ws).await, ServerMessage::Welcome { .. }));
+        match next_server_message(&mut ws).await {
+            ServerMessage::Stations { stations, home } => {
+                assert!(stations.contains(&relay));
+                assert_eq!(home, None, "No home picked yet");
+            }
+            other => panic!("Expected Stations, got {:?}", other),
+        }
+
+        assert_eq!(next_reply(&mut ws).await, ServerMessage::Resume { x: relay.x, y: relay.y + 2 });
+        ws.send(send(ClientMessage::SetHome)).await.unwrap();
+        assert_eq!(next_reply(&mut ws).await, ServerMessage::HomeSet { station: relay.name.clone() });
+
+        // A jump across the map is put back, and the respawn still works from there
+        ws.send(send(ClientMessage::Position { x: relay.x + 500, y: relay.y, direction: Direction::Up, seed: None })).await.unwrap();
+        assert_eq!(
+            next_reply(&mut ws).await,
+            ServerMessage::Corrected { x: relay.x, y: relay.y + 2 },
+            "Teleports are corrected"
+        );
+        ws.send(send(ClientMessage::Respawn)).await.unwrap();
+        assert_eq!(
+            next_reply(&mut ws).await,
+            ServerMessage::Respawned { station: relay.name.clone(), x: relay.x, y: relay.y },
+            "Respawns at the new home"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ws_requires_hello() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let addr = spawn_server().await;
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        ws.send(Message::text(r#"{"type":"position","x":1,"y":1,"direction":"Up"}"#)).await.unwrap();
+
+        // Server drops the socket instead of registering the session
+        let next = tokio::time::timeout(std::time::Duration::from_secs(2), ws.next()).await.unwrap();
+        assert!(matches!(next, None | Some(Ok(Message::Close(_))) | Some(Err(_))));
+    }
 }
@@ -0,0 +1,249 @@
+//! Cache of generated `/map` maps.
+//!
+//! Generating a large map is by far the slowest thing `/map` does, and the
+//! same few parameter sets tend to be asked for over and over. `MapCache`
+//! keeps the most recently used maps, keyed by algorithm, seed and size, up
+//! to `EXOSPACE_MAP_CACHE` entries (0 turns caching off). `POST
+//! /admin/cache/clear` empties it.
+//!
+//! `MapLimits` bounds the sizes `/map` will generate at all, since a map's
+//! memory grows with its area: `EXOSPACE_MAX_MAP` (e.g. "800x400") lowers
+//! the protocol's maximum for a small server.
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use exospace_core::{MAX_MAP_HEIGHT, MAX_MAP_WIDTH, MIN_MAP_SIDE, MapData};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::AppState;
+use crate::admin::admin_rejection;
+use crate::error::ApiError;
+use crate::mapgen::Algorithm;
+
+/// Environment variable holding the most maps to keep
+pub const MAP_CACHE_ENV: &str = "EXOSPACE_MAP_CACHE";
+
+/// Environment variable holding the largest map size to generate, as WIDTHxHEIGHT
+pub const MAX_MAP_ENV: &str = "EXOSPACE_MAX_MAP";
+
+/// Maps kept when `EXOSPACE_MAP_CACHE` isn't set; the largest map is about 2MB
+pub const DEFAULT_MAP_CACHE_SIZE: usize = 16;
+
+/// Everything a generated map depends on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MapKey {
+    pub algorithm: Algorithm,
+    pub seed: u64,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// The largest map `/map` will generate; never more than the protocol allows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MapLimits {
+    pub max_width: usize,
+    pub max_height: usize,
+}
+
+impl Default for MapLimits {
+    fn default() -> Self {
+        MapLimits { max_width: MAX_MAP_WIDTH, max_height: MAX_MAP_HEIGHT }
+    }
+}
+
+impl MapLimits {
+    /// "WIDTHxHEIGHT", each side at least `MIN_MAP_SIDE`; sides over the protocol's maximum are capped
+    pub fn parse(value: &str) -> Option<Self> {
+        let (width, height) = value.trim().split_once(['x', 'X'])?;
+        let (width, height): (usize, usize) = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+        if width < MIN_MAP_SIDE || height < MIN_MAP_SIDE {
+            return None;
+        }
+        Some(MapLimits { max_width: width.min(MAX_MAP_WIDTH), max_height: height.min(MAX_MAP_HEIGHT) })
+    }
+
+    /// From `EXOSPACE_MAX_MAP`; a bad value is logged and the protocol's maximum used
+    pub fn load() -> Self {
+        let Ok(value) = std::env::var(MAX_MAP_ENV) else {
+            return Self::default();
+        };
+        match Self::parse(&value) {
+            Some(limits) => limits,
+            None => {
+                tracing::warn!("Ignoring {}: '{}' is not a map size like 800x400", MAX_MAP_ENV, value);
+                Self::default()
+            }
+        }
+    }
+
+    /// A 400 unless `width` x `height` is within the limits
+    pub fn check(&self, width: usize, height: usize) -> Result<(), ApiError> {
+        if (MIN_MAP_SIDE..=self.max_width).contains(&width) && (MIN_MAP_SIDE..=self.max_height).contains(&height) {
+            return Ok(());
+        }
+        Err(ApiError::MapSize { max_width: self.max_width, max_height: self.max_height })
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Each map with the clock value of its last use
+    maps: HashMap<MapKey, (Arc<MapData>, u64)>,
+    clock: u64,
+}
+
+pub struct MapCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+impl Default for MapCache {
+    fn default() -> Self {
+        MapCache::new(DEFAULT_MAP_CACHE_SIZE)
+    }
+}
+
+impl MapCache {
+    pub fn new(capacity: usize) -> Self {
+        MapCache { capacity, entries: Mutex::new(Entries::default()) }
+    }
+
+    /// Sized by `EXOSPACE_MAP_CACHE`; a bad value is logged and the default used
+    pub fn load() -> Self {
+        let Ok(value) = std::env::var(MAP_CACHE_ENV) else {
+            return Self::default();
+        };
+        match value.trim().parse() {
+            Ok(capacity) => Self::new(capacity),
+            Err(_) => {
+                tracing::warn!("Ignoring {}: '{}' is not a number of maps", MAP_CACHE_ENV, value);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// A cached map, which now counts as the most recently used
+    pub fn get(&self, key: &MapKey) -> Option<Arc<MapData>> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        entries.maps.get_mut(key).map(|(map, used)| {
+            *used = clock;
+            map.clone()
+        })
+    }
+
+    /// Keep a map, dropping the least recently used one when full
+    pub fn insert(&self, key: MapKey, map: Arc<MapData>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        if !entries.maps.contains_key(&key)
+            && entries.maps.len() >= self.capacity
+            && let Some(oldest) = entries.maps.iter().min_by_key(|(_, (_, used))| *used).map(|(key, _)| *key)
+        {
+            entries.maps.remove(&oldest);
+        }
+        entries.maps.insert(key, (map, clock));
+    }
+
+    /// Drop every map; returns how many there were
+    pub fn clear(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let cleared = entries.maps.len();
+        entries.maps.clear();
+        cleared
+    }
+}
+
+/// `POST /admin/cache/clear`
+pub async fn clear_cache(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(rejection) = admin_rejection(&state, &headers) {
+        return rejection;
+    }
+    format!("Cleared {} cached maps", state.map_cache.clear()).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::Tile;
+
+    fn key(seed: u64) -> MapKey {
+        MapKey { algorithm: Algorithm::Rooms, seed, width: 10, height: 10 }
+    }
+
+    fn map() -> Arc<MapData> {
+        Arc::new(MapData::filled(10, 10, Tile::Floor))
+    }
+
+    // ==================== Cache Tests ====================
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let cache = MapCache::new(2);
+        cache.insert(key(1), map());
+        cache.insert(key(2), map());
+        assert!(cache.get(&key(1)).is_some(), "Using 1 makes 2 the oldest");
+        cache.insert(key(3), map());
+        assert!(cache.get(&key(2)).is_none());
+        assert!(cache.get(&key(1)).is_some() && cache.get(&key(3)).is_some());
+
+        cache.insert(key(3), map());
+        assert!(cache.get(&key(1)).is_some(), "Replacing an entry evicts nothing");
+        assert_eq!(cache.clear(), 2);
+        assert!(cache.get(&key(1)).is_none());
+    }
+
+    #[test]
+    fn test_keys_cover_every_parameter() {
+        let cache = MapCache::new(4);
+        cache.insert(key(1), map());
+        assert!(cache.get(&MapKey { algorithm: Algorithm::Noise, ..key(1) }).is_none());
+        assert!(cache.get(&MapKey { width: 11, ..key(1) }).is_none());
+        assert!(cache.get(&MapKey { height: 11, ..key(1) }).is_none());
+        assert!(cache.get(&key(2)).is_none());
+    }
+
+    #[test]
+    fn test_zero_capacity_caches_nothing() {
+        let cache = MapCache::new(0);
+        cache.insert(key(1), map());
+        assert!(cache.get(&key(1)).is_none());
+        assert_eq!(MapCache::default().capacity(), DEFAULT_MAP_CACHE_SIZE);
+    }
+
+    // ==================== Limit Tests ====================
+
+    #[test]
+    fn test_map_limits_parse() {
+        assert_eq!(MapLimits::parse("800x400"), Some(MapLimits { max_width: 800, max_height: 400 }));
+        assert_eq!(MapLimits::parse(" 64 X 32 "), Some(MapLimits { max_width: 64, max_height: 32 }));
+        assert_eq!(MapLimits::parse("100000x100000"), Some(MapLimits::default()), "Capped at the protocol's maximum");
+        for bad in ["", "800", "800x", "x400", "-5x10", "2x10", "wide x tall"] {
+            assert_eq!(MapLimits::parse(bad), None, "'{}' should be rejected", bad);
+        }
+    }
+
+    #[test]
+    fn test_map_limits_check() {
+        let limits = MapLimits { max_width: 100, max_height: 50 };
+        assert!(limits.check(100, 50).is_ok());
+        assert!(limits.check(MIN_MAP_SIDE, MIN_MAP_SIDE).is_ok());
+        assert_eq!(limits.check(101, 50), Err(ApiError::MapSize { max_width: 100, max_height: 50 }));
+        assert!(limits.check(50, 51).is_err());
+        assert!(limits.check(MIN_MAP_SIDE - 1, 10).is_err(), "Too small");
+        assert!(MapLimits::default().check(100_000, 100_000).is_err());
+    }
+}
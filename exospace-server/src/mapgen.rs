@@ -0,0 +1,463 @@
+//! Bounded map algorithms for the `/map` endpoint.
+//!
+//! `/map?algo=` picks one of the `Algorithm`s: `rooms` (the default) is the
+//! corridor-and-room `MapGenerator`, `noise` layers value noise into
+//! asteroid belts, rocky bodies and nebula clouds drifting through open
+//! space, and `wfc` lays out station interiors by wave function collapse
+//! (`wfc.rs`). Adding an algorithm means implementing `MapAlgorithm` and giving it
+//! a name here. Every algorithm, and the chunk generator, seeds resources the
+//! same way through `with_resources`. `Algorithm::generate` then runs
+//! `connect_regions` over the result, so every open tile of a served map can
+//! be reached from its start, and surveys the map's named regions
+//! (`exospace_core::regions`). The noise generator rolls a `Biome` for each
+//! region's grid square and shapes its belts and clouds by it; the regions of
+//! the other algorithms get a biome read off their tiles.
+
+use exospace_core::regions::{self, Biome};
+use exospace_core::{MapData, Tile, hash_position};
+use std::collections::VecDeque;
+
+use crate::MapGenerator;
+use crate::chunks::value_noise;
+use crate::wfc::WfcGenerator;
+
+/// Percent of asteroids that carry ore
+const ORE_PERCENT: u32 = 12;
+/// Percent of nebula tiles that hold a crystal field
+const CRYSTAL_PERCENT: u32 = 2;
+/// Keeps the resource roll independent of the other uses of the seed
+const RESOURCE_SALT: u32 = 0x5EED_0DE5;
+/// Pockets of open tiles smaller than this are filled in rather than tunnelled to
+pub const MIN_REGION: usize = 8;
+
+/// Something that can fill a bounded, wall-bordered map from its seed
+pub trait MapAlgorithm {
+    fn generate(&mut self, width: usize, height: usize) -> MapData;
+
+    /// The biome grid square (rx, ry) was generated as, for algorithms that go by biome
+    fn biome(&self, _rx: i32, _ry: i32) -> Option<Biome> {
+        None
+    }
+}
+
+/// The algorithms `/map?algo=` accepts
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    Rooms,
+    Noise,
+    Wfc,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 3] = [Algorithm::Rooms, Algorithm::Noise, Algorithm::Wfc];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Rooms => "rooms",
+            Algorithm::Noise => "noise",
+            Algorithm::Wfc => "wfc",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Algorithm> {
+        Self::ALL.into_iter().find(|algo| algo.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn generator(self, seed: u64) -> Box<dyn MapAlgorithm> {
+        match self {
+            Algorithm::Rooms => Box::new(MapGenerator::new(seed)),
+            Algorithm::Noise => Box::new(NoiseGenerator::new(seed)),
+            Algorithm::Wfc => Box::new(WfcGenerator::new(seed)),
+        }
+    }
+
+    /// A map from this algorithm with every open tile reachable from the start, and its regions
+    pub fn generate(self, seed: u64, width: usize, height: usize) -> MapData {
+        let mut generator = self.generator(seed);
+        let mut map = generator.generate(width, height);
+        connect_regions(&mut map);
+        let surveyed = regions::survey_map(seed, width, height, |rx, ry| generator.biome(rx, ry), |x, y| map.get(x as usize, y as usize));
+        map.regions = surveyed;
+        map
+    }
+}
+
+/// How a biome shapes a noise map: how wide belts run, the noise rock has to beat inside them, the
+/// share of loose asteroids around it, and the noise clouds have to beat
+struct NoiseMix {
+    belt: f32,
+    rock: f32,
+    asteroid_percent: u32,
+    cloud: f32,
+}
+
+impl NoiseMix {
+    fn of(biome: Biome) -> NoiseMix {
+        match biome {
+            Biome::AsteroidBelt => NoiseMix { belt: 0.1, rock: 0.7, asteroid_percent: 50, cloud: 0.7 },
+            Biome::NebulaCloud => NoiseMix { belt: 0.03, rock: 0.68, asteroid_percent: 45, cloud: 0.42 },
+            Biome::DebrisField => NoiseMix { belt: 0.07, rock: 0.6, asteroid_percent: 25, cloud: 0.7 },
+            Biome::StationSector => NoiseMix { belt: 0.02, rock: 0.75, asteroid_percent: 30, cloud: 0.72 },
+            Biome::DeepSpace | Biome::Unknown => NoiseMix { belt: 0.04, rock: 0.68, asteroid_percent: 45, cloud: 0.6 },
+        }
+    }
+}
+
+/// Organic maps from layered value noise: every tile depends only on its position and the seed
+pub struct NoiseGenerator {
+    seed: u32,
+}
+
+impl NoiseGenerator {
+    pub fn new(seed: u64) -> Self {
+        // Same folding as ChunkGenerator, so large seeds still differ
+        NoiseGenerator { seed: (seed ^ (seed >> 32)) as u32 }
+    }
+
+    fn tile_at(&self, x: i32, y: i32) -> Tile {
+        let (rx, ry) = regions::region_coords(x, y);
+        let mix = NoiseMix::of(Biome::at(self.seed as u64, rx, ry));
+
+        // Belts follow the contour lines of broad noise, so they wind across the map
+        let belt = (fractal_noise(x, y, 48, 3, self.seed) - 0.5).abs();
+        if belt < mix.belt {
+            // Solid bodies sit in the thick of a belt, loose rocks around them
+            if fractal_noise(x, y, 12, 2, self.seed.wrapping_add(1)) > mix.rock {
+                return Tile::Wall;
+            }
+            if hash_position(x, y, self.seed.wrapping_add(2)) % 100 < mix.asteroid_percent {
+                return Tile::Asteroid;
+            }
+        }
+
+        // Clouds: soft, billowing regions
+        if fractal_noise(x, y, 40, 4, self.seed.wrapping_add(3)) > mix.cloud {
+            return Tile::Nebula;
+        }
+
+        Tile::Floor
+    }
+}
+
+impl MapAlgorithm for NoiseGenerator {
+    fn generate(&mut self, width: usize, height: usize) -> MapData {
+        let mut map = MapData::filled(width, height, Tile::Wall);
+        for (y, row) in map.rect_mut(1..width - 1, 1..height - 1).enumerate() {
+            for (x, tile) in row.iter_mut().enumerate() {
+                *tile = self.tile_at(x as i32 + 1, y as i32 + 1);
+            }
+        }
+
+        scatter_resources(&mut map, self.seed);
+        let (start_x, start_y) = find_start_position(&map);
+        map.start_x = start_x;
+        map.start_y = start_y;
+        map
+    }
+
+    fn biome(&self, rx: i32, ry: i32) -> Option<Biome> {
+        Some(Biome::at(self.seed as u64, rx, ry))
+    }
+}
+
+/// Octaves of value noise, each half the size and weight of the last; 0.0..=1.0
+fn fractal_noise(x: i32, y: i32, scale: i32, octaves: u32, seed: u32) -> f32 {
+    let (mut total, mut weight, mut amplitude, mut scale) = (0.0, 0.0, 1.0, scale);
+    for octave in 0..octaves {
+        total += value_noise(x, y, scale, seed.wrapping_add(octave * 101)) * amplitude;
+        weight += amplitude;
+        amplitude *= 0.5;
+        scale = (scale / 2).max(1);
+    }
+    total / weight
+}
+
+/// Turn some asteroids into ore and some nebula into crystal fields, by position
+pub fn with_resources(tile: Tile, x: i32, y: i32, seed: u32) -> Tile {
+    let roll = hash_position(x, y, seed.wrapping_add(RESOURCE_SALT)) % 100;
+    match tile {
+        Tile::Asteroid if roll < ORE_PERCENT => Tile::OreAsteroid,
+        Tile::Nebula if roll < CRYSTAL_PERCENT => Tile::CrystalField,
+        other => other,
+    }
+}
+
+/// `with_resources` over a whole bounded map
+pub fn scatter_resources(map: &mut MapData, seed: u32) {
+    let (width, height) = (map.width, map.height);
+    for (y, row) in map.rect_mut(0..width, 0..height).enumerate() {
+        for (x, tile) in row.iter_mut().enumerate() {
+            *tile = with_resources(*tile, x as i32, y as i32, seed);
+        }
+    }
+}
+
+/// A passable tile near the center, searching outwards; (1, 1) if there's none close by
+pub fn find_start_position(map: &MapData) -> (i32, i32) {
+    let center_x = map.width / 2;
+    let center_y = map.height / 2;
+
+    for radius in 0..50 {
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let x = (center_x as i32 + dx) as usize;
+                let y = (center_y as i32 + dy) as usize;
+                if map.get(x, y).is_some_and(|t| t.is_passable()) {
+                    return (x as i32, y as i32);
+                }
+            }
+        }
+    }
+    (1, 1)
+}
+
+/// Make every passable tile reachable from the start: pockets under `MIN_REGION` tiles become
+/// wall, and each other region is joined to the start's by the shortest tunnel through rock
+pub fn connect_regions(map: &mut MapData) {
+    let (width, height) = (map.width, map.height);
+    let Some(start) = map.index(map.start_x as usize, map.start_y as usize).filter(|&i| map.tiles[i].is_passable()) else {
+        return;
+    };
+    // Moves are four-way here, so anything joined also works for eight-way flight
+    let neighbours = |i: usize| {
+        let (x, y) = (i % width, i / width);
+        [(y > 1).then(|| i - width), (x + 2 < width).then(|| i + 1), (y + 2 < height).then(|| i + width), (x > 1).then(|| i - 1)]
+            .into_iter()
+            .flatten()
+    };
+
+    // Label the regions, filling in the small ones as they're found
+    let mut region = vec![usize::MAX; map.tiles.len()];
+    let mut firsts = Vec::new();
+    for first in 0..map.tiles.len() {
+        if region[first] != usize::MAX || !map.tiles[first].is_passable() {
+            continue;
+        }
+        let label = firsts.len();
+        let mut members = vec![first];
+        region[first] = label;
+        let mut next = 0;
+        while let Some(&i) = members.get(next) {
+            next += 1;
+            for n in neighbours(i) {
+                if region[n] == usize::MAX && map.tiles[n].is_passable() {
+                    region[n] = label;
+                    members.push(n);
+                }
+            }
+        }
+        if members.len() < MIN_REGION && !members.contains(&start) {
+            for &i in &members {
+                map.tiles[i] = Tile::Wall;
+            }
+        } else {
+            firsts.push(first);
+        }
+    }
+
+    // 0-1 search out from the start's region: open tiles are free, rock costs one to cut
+    let main = region[start];
+    let mut parent = vec![usize::MAX; map.tiles.len()];
+    let mut cost = vec![u32::MAX; map.tiles.len()];
+    let mut queue = VecDeque::new();
+    for i in (0..map.tiles.len()).filter(|&i| region[i] == main) {
+        cost[i] = 0;
+        queue.push_back(i);
+    }
+    while let Some(i) = queue.pop_front() {
+        for n in neighbours(i) {
+            let open = map.tiles[n].is_passable();
+            let through = cost[i] + u32::from(!open);
+            if through < cost[n] {
+                cost[n] = through;
+                parent[n] = i;
+                if open { queue.push_front(n) } else { queue.push_back(n) }
+            }
+        }
+    }
+
+    // Cut each kept region's way back
+    for &first in &firsts {
+        let mut i = first;
+        while region[i] != main && parent[i] != usize::MAX {
+            if !map.tiles[i].is_passable() {
+                map.tiles[i] = Tile::Floor;
+            }
+            i = parent[i];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(map: &MapData, tile: Tile) -> usize {
+        map.tiles.iter().filter(|&&t| t == tile).count()
+    }
+
+    // ==================== Algorithm Tests ====================
+
+    #[test]
+    fn test_algorithm_names() {
+        for algo in Algorithm::ALL {
+            assert_eq!(Algorithm::parse(algo.name()), Some(algo));
+        }
+        assert_eq!(Algorithm::parse("NOISE"), Some(Algorithm::Noise), "Names are case-insensitive");
+        assert_eq!(Algorithm::parse("maze"), None);
+    }
+
+    #[test]
+    fn test_algorithms_fill_requested_size() {
+        for algo in Algorithm::ALL {
+            let map = algo.generator(5).generate(60, 25);
+            assert_eq!((map.width, map.height, map.tiles.len()), (60, 25, 1500), "{} map has the wrong size", algo.name());
+            assert!(map.get(map.start_x as usize, map.start_y as usize).unwrap().is_passable());
+        }
+    }
+
+    // ==================== NoiseGenerator Tests ====================
+
+    #[test]
+    fn test_noise_map_deterministic() {
+        let map1 = NoiseGenerator::new(12345).generate(120, 60);
+        let map2 = NoiseGenerator::new(12345).generate(120, 60);
+        let other = NoiseGenerator::new(54321).generate(120, 60);
+        assert_eq!(map1.tiles, map2.tiles, "Same seed should produce the same map");
+        assert_eq!((map1.start_x, map1.start_y), (map2.start_x, map2.start_y));
+        assert_ne!(map1.tiles, other.tiles, "Different seeds should produce different maps");
+    }
+
+    #[test]
+    fn test_noise_map_border_is_walls() {
+        let map = NoiseGenerator::new(3).generate(50, 20);
+        for x in 0..50 {
+            assert_eq!(map.get(x, 0), Some(Tile::Wall));
+            assert_eq!(map.get(x, 19), Some(Tile::Wall));
+        }
+        for y in 0..20 {
+            assert_eq!(map.get(0, y), Some(Tile::Wall));
+            assert_eq!(map.get(49, y), Some(Tile::Wall));
+        }
+    }
+
+    #[test]
+    fn test_noise_map_is_mostly_open_space() {
+        let map = NoiseGenerator::new(12345).generate(400, 200);
+        let total = map.tiles.len();
+        assert!(count(&map, Tile::Asteroid) > 0, "Should have asteroid belts");
+        assert!(count(&map, Tile::Nebula) > 0, "Should have nebula clouds");
+        assert!(count(&map, Tile::Wall) > 2 * (400 + 200), "Should have rocky bodies besides the border");
+        assert!(
+            count(&map, Tile::Floor) + count(&map, Tile::Nebula) > total * 3 / 4,
+            "Space should be mostly open"
+        );
+    }
+
+    #[test]
+    fn test_noise_map_smallest_size() {
+        let map = NoiseGenerator::new(7).generate(3, 3);
+        assert_eq!(map.get(0, 0), Some(Tile::Wall));
+        assert_eq!((map.start_x, map.start_y), (1, 1));
+    }
+
+    #[test]
+    fn test_maps_carry_their_biomes() {
+        let map = Algorithm::Noise.generate(9, 300, 130);
+        assert_eq!(map.regions.len(), 6, "Three grid squares across, two down");
+        for region in &map.regions {
+            let (rx, ry) = regions::region_coords(region.x, region.y);
+            assert_eq!(region.biome, Biome::at(9, rx, ry), "Noise maps are surveyed as the biome they were made in");
+        }
+        let rooms = Algorithm::Rooms.generate(9, 100, 50);
+        assert!(rooms.regions.iter().all(|region| region.biome != Biome::Unknown), "Other maps get a biome read off their tiles");
+        assert!(MapGenerator::new(9).generate(100, 50).regions.is_empty(), "Generators leave the survey to Algorithm::generate");
+    }
+
+    // ==================== Resource Tests ====================
+
+    #[test]
+    fn test_resources_replace_only_their_host_tiles() {
+        let (mut ore, mut crystal) = (0, 0);
+        for i in 0..2000 {
+            assert_eq!(with_resources(Tile::Floor, i, -i, 1), Tile::Floor);
+            assert_eq!(with_resources(Tile::Wall, i, -i, 1), Tile::Wall);
+            match with_resources(Tile::Asteroid, i, -i, 1) {
+                Tile::OreAsteroid => ore += 1,
+                tile => assert_eq!(tile, Tile::Asteroid),
+            }
+            match with_resources(Tile::Nebula, i, -i, 1) {
+                Tile::CrystalField => crystal += 1,
+                tile => assert_eq!(tile, Tile::Nebula),
+            }
+        }
+        assert!((100..400).contains(&ore), "About 12% of asteroids carry ore, got {}", ore);
+        assert!((10..100).contains(&crystal), "About 2% of nebula holds crystals, got {}", crystal);
+    }
+
+    #[test]
+    fn test_algorithms_place_resources() {
+        for algo in Algorithm::ALL {
+            let map = algo.generator(12345).generate(400, 200);
+            assert!(count(&map, Tile::OreAsteroid) > 0, "{} maps should have ore", algo.name());
+            assert!(count(&map, Tile::CrystalField) > 0, "{} maps should have crystals", algo.name());
+        }
+    }
+
+    // ==================== Connectivity Tests ====================
+
+    /// Passable tiles that can't be reached from the start, moving four ways
+    fn unreachable(map: &MapData) -> usize {
+        let start = map.index(map.start_x as usize, map.start_y as usize).unwrap();
+        let mut seen = vec![false; map.tiles.len()];
+        let mut stack = vec![start];
+        seen[start] = true;
+        while let Some(i) = stack.pop() {
+            for n in [i - map.width, i + 1, i + map.width, i - 1] {
+                if !seen[n] && map.tiles[n].is_passable() {
+                    seen[n] = true;
+                    stack.push(n);
+                }
+            }
+        }
+        map.tiles.iter().zip(&seen).filter(|&(tile, &seen)| tile.is_passable() && !seen).count()
+    }
+
+    #[test]
+    fn test_connect_regions_tunnels_and_fills() {
+        let mut map = MapData::filled(20, 9, Tile::Wall);
+        let rooms: [(std::ops::Range<usize>, std::ops::Range<usize>); 3] = [(1..5, 1..8), (10..15, 2..7), (17..18, 4..5)];
+        for (xs, ys) in rooms {
+            for row in map.rect_mut(xs, ys) {
+                row.fill(Tile::Floor);
+            }
+        }
+        (map.start_x, map.start_y) = (2, 2);
+        assert!(unreachable(&map) > 0);
+
+        connect_regions(&mut map);
+        assert_eq!(unreachable(&map), 0, "Every open tile can be reached from the start");
+        assert_eq!(map.get(12, 4), Some(Tile::Floor), "The big room is kept and tunnelled to");
+        assert_eq!(map.get(17, 4), Some(Tile::Wall), "A one-tile pocket is filled in");
+        assert_eq!(count(&map, Tile::Floor), 4 * 7 + 5 * 5 + 5, "The tunnel is the five tiles between the rooms");
+    }
+
+    #[test]
+    fn test_every_algorithm_is_fully_connected() {
+        for algo in Algorithm::ALL {
+            for (seed, width, height) in [(1, 120, 60), (12345, 400, 200), (77, 30, 300)] {
+                let map = algo.generate(seed, width, height);
+                assert_eq!(unreachable(&map), 0, "{} map for seed {} has unreachable tiles", algo.name(), seed);
+                assert!(count(&map, Tile::Floor) > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fractal_noise_range() {
+        for i in -500..500 {
+            let value = fractal_noise(i * 7, i * -13, 40, 4, 99);
+            assert!((0.0..=1.0).contains(&value), "Noise out of range: {}", value);
+        }
+    }
+}
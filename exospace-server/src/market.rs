@@ -0,0 +1,450 @@
+//! Station markets: per-station prices that drift over time and answer to trades.
+//!
+//! Every station trades the same goods. Each good has a base price, a drift
+//! that wanders by up to `MAX_DRIFT_STEP` every `DRIFT_INTERVAL`, and a stock.
+//! Prices scale with how scarce the stock is against `TARGET_STOCK`, so buying
+//! pushes a price up and selling pushes it down, unit by unit, while stock
+//! slowly returns to the target. Drift is worked out from the clock, so a
+//! market is always current when looked at; the `MarketDrift` system also
+//! settles every market once per interval, so prices move on the tick loop
+//! rather than in bursts on the next visit. Trading takes an account whose
+//! ship the server has docked at the station, and is kept to the pilot's
+//! trade budget (`throttle`). Trades are paid from and into the account and
+//! move goods in and out of the hold the server keeps for it (see
+//! `ledger.rs`), so only what's aboard can be sold.
+
+use axum::{
+    Json,
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use exospace_core::{
+    MarketGood, MarketInfo, StationInfo, TradeOrder, TradeReceipt, TradeSide, TransactionKind, hash_position, station_id,
+};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+use crate::auth::{self, AuthError};
+use crate::error::{ApiError, ApiJson, ApiPath};
+use crate::ledger;
+use crate::stations::DOCKING_RANGE;
+use crate::throttle::{Action, Actor};
+use crate::tick::System;
+
+/// Goods every station trades, by content id, with their base price in credits
+pub const GOODS: [(&str, u32); 2] = [("ore", 10), ("crystal", 40)];
+
+/// Stock each market drifts back toward; goods sell near their base price there
+const TARGET_STOCK: f64 = 100.0;
+
+/// Prices stop climbing once stock falls this low
+const MIN_PRICED_STOCK: f64 = 10.0;
+
+/// Share of the gap to `TARGET_STOCK` closed each drift step
+const RESTOCK_RATE: f64 = 0.1;
+
+/// How often prices drift
+pub const DRIFT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Largest change to a good's drift in one step, as a fraction
+const MAX_DRIFT_STEP: f64 = 0.05;
+
+/// Drift never takes a price further than this from its base
+const DRIFT_LIMITS: (f64, f64) = (0.75, 1.25);
+
+/// Most drift steps worked out at once; a market left longer than that has long since restocked
+const MAX_CATCH_UP: u64 = 200;
+
+/// Markup on buying and discount on selling, around the going price
+const SPREAD: f64 = 0.1;
+
+/// Most units in one order
+pub const MAX_ORDER: u32 = 500;
+
+/// Keeps drift rolls independent of the other uses of position hashing
+const MARKET_SALT: u32 = 0x3A4C_E7A1;
+
+/// One good on one market
+#[derive(Clone)]
+struct Good {
+    item: &'static str,
+    base: u32,
+    /// Fractional, so restocking can creep back a little at a time
+    stock: f64,
+    drift: f64,
+}
+
+impl Good {
+    fn price(&self) -> f64 {
+        self.base as f64 * self.drift * (TARGET_STOCK / self.stock.max(MIN_PRICED_STOCK)).sqrt()
+    }
+
+    fn buy_price(&self) -> u32 {
+        (self.price() * (1.0 + SPREAD)).round() as u32
+    }
+
+    fn sell_price(&self) -> u32 {
+        ((self.price() * (1.0 - SPREAD)).round() as u32).max(1)
+    }
+
+    fn info(&self) -> MarketGood {
+        MarketGood {
+            item: self.item.to_string(),
+            buy_price: self.buy_price(),
+            sell_price: self.sell_price(),
+            stock: self.stock as u32,
+        }
+    }
+}
+
+/// One station's market
+#[derive(Clone)]
+struct Market {
+    station: String,
+    goods: Vec<Good>,
+    opened: Instant,
+    /// Drift steps applied so far
+    steps: u64,
+    salt: u32,
+}
+
+impl Market {
+    fn new(station: &str, opened: Instant) -> Self {
+        let goods = GOODS.iter().map(|&(item, base)| Good { item, base, stock: TARGET_STOCK, drift: 1.0 }).collect();
+        let salt = station.bytes().fold(MARKET_SALT, |hash, byte| hash.rotate_left(5) ^ byte as u32);
+        Market { station: station.to_string(), goods, opened, steps: 0, salt }
+    }
+
+    /// Apply every drift step due by `now`
+    fn settle(&mut self, now: Instant) {
+        let due = (now.saturating_duration_since(self.opened).as_millis() / DRIFT_INTERVAL.as_millis()) as u64;
+        self.steps = self.steps.max(due.saturating_sub(MAX_CATCH_UP));
+        while self.steps < due {
+            self.steps += 1;
+            for (i, good) in self.goods.iter_mut().enumerate() {
+                let roll = hash_position(self.steps as i32, i as i32, self.salt) as f64 / u32::MAX as f64;
+                let change = 1.0 + (roll * 2.0 - 1.0) * MAX_DRIFT_STEP;
+                good.drift = (good.drift * change).clamp(DRIFT_LIMITS.0, DRIFT_LIMITS.1);
+                good.stock += (TARGET_STOCK - good.stock) * RESTOCK_RATE;
+            }
+        }
+    }
+
+    fn info(&self) -> MarketInfo {
+        MarketInfo { station: self.station.clone(), goods: self.goods.iter().map(Good::info).collect() }
+    }
+
+    /// Fill an order a unit at a time, so big orders move the price as they go; returns the total
+    fn trade(&mut self, order: &TradeOrder) -> Result<u32, TradeError> {
+        if order.quantity == 0 || order.quantity > MAX_ORDER {
+            return Err(TradeError::BadQuantity);
+        }
+        let good = self.goods.iter_mut().find(|good| good.item == order.item).ok_or(TradeError::UnknownItem)?;
+        let mut total = 0u32;
+        match order.side {
+            TradeSide::Buy => {
+                if (good.stock as u32) < order.quantity {
+                    return Err(TradeError::ShortStock(good.stock as u32));
+                }
+                for _ in 0..order.quantity {
+                    total += good.buy_price();
+                    good.stock -= 1.0;
+                }
+            }
+            TradeSide::Sell => {
+                for _ in 0..order.quantity {
+                    total += good.sell_price();
+                    good.stock += 1.0;
+                }
+            }
+        }
+        Ok(total)
+    }
+}
+
+/// Why a trade was refused
+#[derive(Debug, PartialEq)]
+pub enum TradeError {
+    UnknownStation,
+    UnknownItem,
+    /// None, or more than `MAX_ORDER`
+    BadQuantity,
+    /// Not enough to buy; how many the market has
+    ShortStock(u32),
+}
+
+impl From<TradeError> for ApiError {
+    fn from(error: TradeError) -> Self {
+        match error {
+            TradeError::UnknownStation => ApiError::UnknownStation,
+            TradeError::UnknownItem => ApiError::UnknownItem,
+            TradeError::BadQuantity => ApiError::Invalid(format!("Orders are for 1 to {} units", MAX_ORDER)),
+            TradeError::ShortStock(stock) => ApiError::ShortStock(stock),
+        }
+    }
+}
+
+impl IntoResponse for TradeError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// Every station's market, by `station_id`
+#[derive(Default)]
+pub struct Markets {
+    markets: Mutex<BTreeMap<String, Market>>,
+}
+
+impl Markets {
+    /// A fresh market at each station, all at base prices
+    pub fn open(stations: &[StationInfo], now: Instant) -> Self {
+        let markets = stations.iter().map(|station| (station_id(&station.name), Market::new(&station.name, now))).collect();
+        Markets { markets: Mutex::new(markets) }
+    }
+
+    /// A station's prices and stock as of `now`
+    pub fn info(&self, id: &str, now: Instant) -> Option<MarketInfo> {
+        let mut markets = self.markets.lock().unwrap();
+        let market = markets.get_mut(id)?;
+        market.settle(now);
+        Some(market.info())
+    }
+
+    /// Apply the drift due by `now` to every market
+    pub fn settle_all(&self, now: Instant) {
+        for market in self.markets.lock().unwrap().values_mut() {
+            market.settle(now);
+        }
+    }
+
+    /// Fill an order once `pay` has settled it, given the station's name and the credits the pilot gains (negative
+    /// when buying); `pay` answers with the pilot's balance after, if the server keeps it. A refusal from
+    /// `pay` leaves the market as it was
+    pub fn trade<E: From<TradeError>>(
+        &self,
+        id: &str,
+        order: TradeOrder,
+        now: Instant,
+        pay: impl FnOnce(&str, i64) -> Result<Option<u32>, E>,
+    ) -> Result<TradeReceipt, E> {
+        let mut markets = self.markets.lock().unwrap();
+        let market = markets.get_mut(id).ok_or(TradeError::UnknownStation)?;
+        market.settle(now);
+        let mut filled = market.clone();
+        let total = filled.trade(&order)?;
+        let credits = match order.side {
+            TradeSide::Buy => -(total as i64),
+            TradeSide::Sell => total as i64,
+        };
+        let balance = pay(&market.station, credits)?;
+        *market = filled;
+        Ok(TradeReceipt { order, total, market: market.info(), balance })
+    }
+}
+
+/// Drifts every market once per `DRIFT_INTERVAL`
+pub struct MarketDrift {
+    markets: Arc<Markets>,
+    next_step: Instant,
+}
+
+impl MarketDrift {
+    pub fn new(markets: Arc<Markets>, now: Instant) -> Self {
+        MarketDrift { markets, next_step: now + DRIFT_INTERVAL }
+    }
+}
+
+impl System for MarketDrift {
+    fn name(&self) -> &'static str {
+        "market_drift"
+    }
+
+    fn tick(&mut self, now: Instant) {
+        if now < self.next_step {
+            return;
+        }
+        self.next_step = now + DRIFT_INTERVAL;
+        self.markets.settle_all(now);
+    }
+}
+
+/// Handler for `GET /station/{id}/market`
+pub async fn get_market(State(state): State<AppState>, ApiPath(id): ApiPath<String>) -> Response {
+    match state.markets.info(&id, Instant::now()) {
+        Some(market) => Json(market).into_response(),
+        None => TradeError::UnknownStation.into_response(),
+    }
+}
+
+/// Handler for `POST /station/{id}/market`: buy or sell. The bearer token's pilot has to be docked there; they pay
+/// from their balance and trade from their hold
+pub async fn trade(
+    State(state): State<AppState>,
+    ApiPath(id): ApiPath<String>,
+    headers: HeaderMap,
+    ApiJson(order): ApiJson<TradeOrder>,
+) -> Response {
+    let Some(account) = auth::bearer_token(&headers).and_then(|token| state.users.verify(token)) else {
+        return AuthError::BadToken.into_response();
+    };
+    if let Err(wait) = state.throttle.check(&Actor::Account(account.clone()), Action::Trade, Instant::now()) {
+        return ApiError::RateLimited(wait).into_response();
+    }
+    let Some(station) = state.stations.by_id(&id) else {
+        return TradeError::UnknownStation.into_response();
+    };
+    // Stations are in the home world, so a ship in another system is nowhere near one
+    let ship = state.sessions.find_by_name(&account).filter(|player| state.sessions.system(player.id).is_none()).map(|player| (player.x, player.y));
+    if ship.is_none_or(|(x, y)| station.x.abs_diff(x).max(station.y.abs_diff(y)) > DOCKING_RANGE) {
+        return ApiError::NotDocked.into_response();
+    }
+    let verb = match order.side {
+        TradeSide::Buy => "Bought",
+        TradeSide::Sell => "Sold",
+    };
+    let deal = format!("{} {} {}", verb, order.quantity, order.item);
+    let (side, item, quantity) = (order.side, order.item.clone(), i64::from(order.quantity));
+    let pay = |station: &str, credits: i64| -> Result<Option<u32>, ApiError> {
+        let memo = format!("{} at {}", deal, station);
+        match side {
+            // Goods leave the hold before they're paid for, and go back if the payment can't be saved
+            TradeSide::Sell => {
+                ledger::stow(&state.world, &account, &item, -quantity)?;
+                ledger::settle(&state.world, &account, TransactionKind::Trade, credits, &memo)
+                    .inspect_err(|_| ledger::restow(&state.world, &account, &item, quantity))
+                    .map(Some)
+            }
+            TradeSide::Buy => {
+                let balance = ledger::settle(&state.world, &account, TransactionKind::Trade, credits, &memo)?;
+                ledger::restow(&state.world, &account, &item, quantity);
+                Ok(Some(balance))
+            }
+        }
+    };
+    match state.markets.trade(&id, order, Instant::now(), pay) {
+        Ok(receipt) => Json(receipt).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn haven(now: Instant) -> Markets {
+        Markets::open(&[StationInfo { name: "Haven Station".to_string(), x: 0, y: 0 }], now)
+    }
+
+    fn order(item: &str, quantity: u32, side: TradeSide) -> TradeOrder {
+        TradeOrder { item: item.to_string(), quantity, side }
+    }
+
+    /// Settles nothing
+    fn unpaid(_: &str, _: i64) -> Result<Option<u32>, TradeError> {
+        Ok(None)
+    }
+
+    fn good<'a>(market: &'a MarketInfo, item: &str) -> &'a MarketGood {
+        market.goods.iter().find(|good| good.item == item).unwrap()
+    }
+
+    // ==================== Pricing Tests ====================
+
+    #[test]
+    fn test_fresh_markets_trade_at_base_prices() {
+        let now = Instant::now();
+        let market = haven(now).info("haven-station", now).unwrap();
+        assert_eq!(market.station, "Haven Station");
+        assert_eq!(good(&market, "ore"), &MarketGood { item: "ore".to_string(), buy_price: 11, sell_price: 9, stock: 100 });
+        assert_eq!(good(&market, "crystal").buy_price, 44);
+        assert!(haven(now).info("frontier-relay", now).is_none());
+    }
+
+    #[test]
+    fn test_trades_move_prices() {
+        let now = Instant::now();
+        let markets = haven(now);
+        let receipt = markets.trade("haven-station", order("crystal", 60, TradeSide::Buy), now, unpaid).unwrap();
+        let crystal = good(&receipt.market, "crystal");
+        assert_eq!(crystal.stock, 40);
+        assert!(crystal.buy_price > 44, "Buying makes crystal dearer: {}", crystal.buy_price);
+        assert!(receipt.total > 60 * 44, "Each unit costs more than the last");
+
+        let receipt = markets.trade("haven-station", order("ore", 100, TradeSide::Sell), now, unpaid).unwrap();
+        assert!(good(&receipt.market, "ore").sell_price < 9, "Selling floods the market");
+        assert!(receipt.total < 100 * 9);
+    }
+
+    #[test]
+    fn test_bad_orders_are_refused() {
+        let now = Instant::now();
+        let markets = haven(now);
+        let trade = |id: &str, order| markets.trade(id, order, now, unpaid).map(|receipt| receipt.total);
+        assert_eq!(trade("haven-station", order("ore", 101, TradeSide::Buy)), Err(TradeError::ShortStock(100)));
+        assert_eq!(trade("haven-station", order("gas", 1, TradeSide::Buy)), Err(TradeError::UnknownItem));
+        assert_eq!(trade("haven-station", order("ore", 0, TradeSide::Sell)), Err(TradeError::BadQuantity));
+        assert_eq!(trade("haven-station", order("ore", MAX_ORDER + 1, TradeSide::Sell)), Err(TradeError::BadQuantity));
+        assert_eq!(trade("nowhere", order("ore", 1, TradeSide::Sell)), Err(TradeError::UnknownStation));
+        assert_eq!(markets.info("haven-station", now).unwrap().goods[0].stock, 100, "Refused orders change nothing");
+    }
+
+    #[test]
+    fn test_trades_wait_on_payment() {
+        let now = Instant::now();
+        let markets = haven(now);
+        let broke = |_: &str, credits: i64| if credits < 0 { Err(ApiError::ShortCredits(0)) } else { Ok(Some(9)) };
+        let refused = markets.trade("haven-station", order("ore", 5, TradeSide::Buy), now, broke);
+        assert_eq!(refused.map(|receipt| receipt.total), Err(ApiError::ShortCredits(0)));
+        assert_eq!(good(&markets.info("haven-station", now).unwrap(), "ore").stock, 100, "An unpaid order changes nothing");
+
+        let mut paid = None;
+        let receipt = markets
+            .trade("haven-station", order("ore", 1, TradeSide::Sell), now, |station: &str, credits| {
+                paid = Some((station.to_string(), credits));
+                Ok::<_, ApiError>(Some(9))
+            })
+            .unwrap();
+        assert_eq!(paid, Some(("Haven Station".to_string(), 9)), "Sales pay in");
+        assert_eq!((receipt.balance, good(&receipt.market, "ore").stock), (Some(9), 101));
+    }
+
+    // ==================== Drift Tests ====================
+
+    #[test]
+    fn test_prices_drift_and_stock_recovers() {
+        let now = Instant::now();
+        let markets = haven(now);
+        markets.trade("haven-station", order("ore", 80, TradeSide::Buy), now, unpaid).unwrap();
+        let soon = markets.info("haven-station", now + DRIFT_INTERVAL / 2).unwrap();
+        assert_eq!(good(&soon, "ore").stock, 20, "Nothing drifts inside one interval");
+
+        let later = markets.info("haven-station", now + DRIFT_INTERVAL * 10).unwrap();
+        let ore = good(&later, "ore").stock;
+        assert!(ore > 60 && ore < 100, "Stock creeps back toward the target: {}", ore);
+
+        let quiet = haven(now);
+        let prices = |at| quiet.info("haven-station", at).unwrap().goods.iter().map(|good| good.buy_price).collect::<Vec<_>>();
+        let base = prices(now);
+        let drifted: Vec<_> = (1..=50).map(|steps| prices(now + DRIFT_INTERVAL * steps)).collect();
+        assert!(drifted.iter().any(|p| *p != base), "Prices wander without any trades");
+        for p in drifted {
+            assert!(p[0] >= 8 && p[0] <= 14 && p[1] >= 33 && p[1] <= 55, "Drift stays within its limits: {:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_drift_system_settles_every_interval() {
+        let now = Instant::now();
+        let markets = Arc::new(haven(now));
+        let mut drift = MarketDrift::new(markets.clone(), now);
+        let steps = || markets.markets.lock().unwrap()["haven-station"].steps;
+        drift.tick(now + DRIFT_INTERVAL / 2);
+        assert_eq!(steps(), 0);
+        drift.tick(now + DRIFT_INTERVAL * 3);
+        assert_eq!(steps(), 3, "Every step due is applied, without anyone looking");
+    }
+}
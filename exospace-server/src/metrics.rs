@@ -0,0 +1,167 @@
+//! Request and generation metrics, served by `GET /metrics`.
+//!
+//! `Metrics` lives in `AppState` and is updated as requests come in: every
+//! routed request is counted by method, route and status by the `track`
+//! middleware, and `/map` records how long each generation took. Scrapes
+//! render everything in the Prometheus text format, with the connected
+//! session count read from the registry at that moment. Only `/map` has a
+//! cache (`mapcache.rs`); chunks are generated on every request, so there are
+//! no chunk cache hits to report.
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::AppState;
+
+/// Upper bounds of the generation latency buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+/// Prometheus text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Requests by (method, route, status)
+type RequestCounts = BTreeMap<(String, String, u16), u64>;
+
+/// Latency histogram; each observation counts in one bucket, and they're summed when rendered
+#[derive(Default)]
+struct Histogram {
+    /// One count per bucket in `LATENCY_BUCKETS`, plus the overflow
+    buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS.iter().position(|&le| seconds <= le).unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str) {
+        let mut cumulative = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let le = LATENCY_BUCKETS.get(i).map_or("+Inf".to_string(), |le| le.to_string());
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative);
+        }
+        let _ = writeln!(out, "{}_sum {}", name, self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6);
+        let _ = writeln!(out, "{}_count {}", name, self.count.load(Ordering::Relaxed));
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    map_generations: AtomicU64,
+    map_cache_hits: AtomicU64,
+    generation_latency: Histogram,
+    requests: Mutex<RequestCounts>,
+}
+
+impl Metrics {
+    /// Count a `/map` generation and how long it took
+    pub fn map_generated(&self, elapsed: Duration) {
+        self.map_generations.fetch_add(1, Ordering::Relaxed);
+        self.generation_latency.observe(elapsed);
+    }
+
+    /// Count a `/map` request answered from the cache
+    pub fn map_cache_hit(&self) {
+        self.map_cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn request(&self, method: &str, route: &str, status: u16) {
+        let mut requests = self.requests.lock().unwrap();
+        *requests.entry((method.to_string(), route.to_string(), status)).or_default() += 1;
+    }
+
+    /// Everything in the Prometheus text format
+    pub fn render(&self, sessions: usize) -> String {
+        let mut out = String::new();
+        header(&mut out, "exospace_map_generations_total", "counter", "Maps generated for GET /map");
+        let _ = writeln!(out, "exospace_map_generations_total {}", self.map_generations.load(Ordering::Relaxed));
+
+        header(&mut out, "exospace_map_cache_hits_total", "counter", "GET /map requests answered from the map cache");
+        let _ = writeln!(out, "exospace_map_cache_hits_total {}", self.map_cache_hits.load(Ordering::Relaxed));
+
+        header(&mut out, "exospace_map_generation_seconds", "histogram", "Time spent generating maps for GET /map");
+        self.generation_latency.render(&mut out, "exospace_map_generation_seconds");
+
+        header(&mut out, "exospace_ws_sessions", "gauge", "Connected WebSocket sessions");
+        let _ = writeln!(out, "exospace_ws_sessions {}", sessions);
+
+        header(&mut out, "exospace_http_requests_total", "counter", "HTTP requests by method, route and status");
+        for ((method, route, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "exospace_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                method, route, status, count
+            );
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Middleware counting each routed request under its route pattern, so `/map?seed=1` and `/map?seed=2` share a line
+pub async fn track(State(state): State<AppState>, route: MatchedPath, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let response = next.run(request).await;
+    state.metrics.request(&method, route.as_str(), response.status().as_u16());
+    response
+}
+
+/// `GET /metrics`
+pub async fn get_metrics(State(state): State<AppState>) -> Response {
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], state.metrics.render(state.sessions.len())).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Rendering Tests ====================
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let metrics = Metrics::default();
+        metrics.map_generated(Duration::from_micros(500));
+        metrics.map_generated(Duration::from_millis(20));
+        metrics.map_generated(Duration::from_secs(5));
+        let text = metrics.render(0);
+        assert!(text.contains("exospace_map_generations_total 3\n"));
+        assert!(text.contains("exospace_map_generation_seconds_bucket{le=\"0.001\"} 1\n"));
+        assert!(text.contains("exospace_map_generation_seconds_bucket{le=\"0.025\"} 2\n"));
+        assert!(text.contains("exospace_map_generation_seconds_bucket{le=\"2.5\"} 2\n"));
+        assert!(text.contains("exospace_map_generation_seconds_bucket{le=\"+Inf\"} 3\n"), "Slow ones only land in +Inf");
+        assert!(text.contains("exospace_map_generation_seconds_sum 5.0205\n"));
+        assert!(text.contains("exospace_map_generation_seconds_count 3\n"));
+    }
+
+    #[test]
+    fn test_requests_counted_per_route_and_status() {
+        let metrics = Metrics::default();
+        metrics.request("GET", "/map", 200);
+        metrics.request("GET", "/map", 200);
+        metrics.request("GET", "/map", 400);
+        let text = metrics.render(2);
+        assert!(text.contains("exospace_http_requests_total{method=\"GET\",route=\"/map\",status=\"200\"} 2\n"));
+        assert!(text.contains("exospace_http_requests_total{method=\"GET\",route=\"/map\",status=\"400\"} 1\n"));
+        assert!(text.contains("# TYPE exospace_ws_sessions gauge\nexospace_ws_sessions 2\n"));
+    }
+}
@@ -0,0 +1,474 @@
+//! The mission board: procedurally generated jobs, and paying for them.
+//!
+//! Every `BOARD_PERIOD` the board is replaced by `MISSIONS_PER_BOARD` new
+//! missions rolled from the world seed and the period's number, so every
+//! pilot sees the same board and the server stores none of it. A mission's
+//! id is its period and its place on the board (e.g. "981-2"), which is all
+//! it takes to roll it again when a pilot reports it done. Missions deliver
+//! cargo to a point of interest (a station, or a derelict or beacon in the
+//! chunks around spawn), survey nebula tiles or mine ore.
+//!
+//! Only accounts take missions on, and the server keeps score: accepting
+//! one notes the account's ore total, the session counts the nebula tiles
+//! it flies through from then on (`surveyed()`), and a delivery is checked
+//! against where the server has the pilot's ship and takes the cargo out of
+//! its hold. A mission can be completed until the board after its own is
+//! replaced, and each account is paid once per mission, into its balance
+//! (see `ledger.rs`).
+
+use axum::{
+    Json,
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use exospace_core::{
+    ItemCount, MissionBoard, MissionInfo, MissionReward, Objective, Poi, PoiKind, SPAWN_POSITION, StationInfo,
+    TransactionKind, chunk_coords, hash_position,
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::AppState;
+use crate::auth::{self, AuthError};
+use crate::chunks::ChunkGenerator;
+use crate::error::{ApiError, ApiPath};
+use crate::ledger;
+use crate::market::GOODS;
+use crate::stations::DOCKING_RANGE;
+use crate::utility;
+
+/// How long a board lasts before the next one replaces it
+pub const BOARD_PERIOD: Duration = Duration::from_secs(30 * 60);
+
+/// Missions on each board
+pub const MISSIONS_PER_BOARD: u32 = 4;
+
+/// Chunks either side of the spawn chunk searched for derelicts and beacons to deliver to
+const DESTINATION_REACH: i32 = 3;
+
+/// Credits per nebula tile surveyed
+const SURVEY_PAY: u32 = 3;
+
+/// Credits per unit of ore mined, on top of the ore itself
+const QUOTA_PAY: u32 = 4;
+
+/// Keeps mission rolls independent of the other uses of the seed
+const MISSION_SALT: u32 = 0x5EED_B0A2;
+
+/// Why a mission wasn't paid
+#[derive(Debug, PartialEq)]
+pub enum MissionError {
+    Unknown,
+    /// Its board is gone
+    Expired,
+    /// The objective isn't met; what's missing
+    Unfinished(String),
+    /// This account has been paid for it already
+    Claimed,
+    /// This account never took it on
+    NotAccepted,
+}
+
+impl From<MissionError> for ApiError {
+    fn from(error: MissionError) -> Self {
+        match error {
+            MissionError::Unknown => ApiError::UnknownMission,
+            MissionError::Expired => ApiError::MissionExpired,
+            MissionError::Unfinished(reason) => ApiError::MissionUnfinished(reason),
+            MissionError::Claimed => ApiError::MissionClaimed,
+            MissionError::NotAccepted => ApiError::MissionNotAccepted,
+        }
+    }
+}
+
+impl IntoResponse for MissionError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// What the server knows of a pilot handing a mission in
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Standing {
+    /// Where their ship is; None when they aren't flying
+    pub at: Option<(i32, i32)>,
+    /// Their stored ore total
+    pub ore_mined: u64,
+}
+
+/// A mission an account has taken on, and how far it has got
+#[derive(Debug)]
+struct Accepted {
+    period: u64,
+    place: u32,
+    mission: MissionInfo,
+    /// The account's ore total when it took the mission on
+    ore_at_start: u64,
+    /// Nebula tiles flown through since, up to the survey's target
+    surveyed: HashSet<(i32, i32)>,
+}
+
+/// The world's mission board
+#[derive(Default)]
+pub struct Missions {
+    salt: u32,
+    /// Where deliveries go
+    destinations: Vec<Poi>,
+    /// Open missions each account has taken on, by lowercase account name
+    accepted: Mutex<HashMap<String, Vec<Accepted>>>,
+    /// (period, place, account) of every mission paid out while still open
+    claimed: Mutex<HashSet<(u64, u32, String)>>,
+}
+
+impl Missions {
+    /// A board for the world with this seed, delivering to its stations and the points of interest near spawn
+    pub fn new(seed: u64, stations: &[StationInfo]) -> Self {
+        let generator = ChunkGenerator::new(seed);
+        let (spawn_cx, spawn_cy) = chunk_coords(SPAWN_POSITION.0, SPAWN_POSITION.1);
+        let reach = -DESTINATION_REACH..=DESTINATION_REACH;
+        let nearby = reach.clone().flat_map(|dy| reach.clone().map(move |dx| (spawn_cx + dx, spawn_cy + dy)));
+        let destinations = stations
+            .iter()
+            .map(|station| Poi { kind: PoiKind::Station, name: station.name.clone(), x: station.x, y: station.y })
+            .chain(nearby.filter_map(|(cx, cy)| generator.poi(cx, cy)))
+            .collect();
+        let salt = (seed ^ (seed >> 32)) as u32 ^ MISSION_SALT;
+        Missions { salt, destinations, accepted: Mutex::default(), claimed: Mutex::default() }
+    }
+
+    /// The board on offer at `now` (Unix seconds)
+    pub fn board(&self, now: u64) -> MissionBoard {
+        let period = BOARD_PERIOD.as_secs();
+        let missions = (0..MISSIONS_PER_BOARD).map(|place| self.roll(now / period, place)).collect();
+        MissionBoard { missions, expires_in: period - now % period }
+    }
+
+    /// The mission at `place` on the board for `period`
+    fn roll(&self, period: u64, place: u32) -> MissionInfo {
+        let roll = |n: u32| hash_position(period as i32, (place * 8 + n) as i32, self.salt);
+        let pick = |n: u32, low: u32, high: u32| low + roll(n) % (high - low + 1);
+        let kinds = if self.destinations.is_empty() { 2 } else { 3 };
+        let (name, objective, credits, items) = match roll(0) % kinds {
+            0 => {
+                let tiles = pick(1, 10, 40);
+                ("Nebula survey".to_string(), Objective::Scan { tiles }, tiles * SURVEY_PAY, Vec::new())
+            }
+            1 => {
+                let ore = 3 * pick(1, 3, 10);
+                let bonus = vec![ItemCount { item: "crystal".to_string(), count: pick(2, 1, 3) }];
+                ("Ore quota".to_string(), Objective::Mine { ore }, ore * QUOTA_PAY, bonus)
+            }
+            _ => {
+                let to = &self.destinations[roll(1) as usize % self.destinations.len()];
+                let (item, price) = GOODS[roll(2) as usize % GOODS.len()];
+                let count = if price > 20 { pick(3, 1, 5) } else { pick(3, 5, 20) };
+                let distance = to.x.abs_diff(SPAWN_POSITION.0).max(to.y.abs_diff(SPAWN_POSITION.1));
+                let cargo = ItemCount { item: item.to_string(), count };
+                let objective = Objective::Deliver { cargo, to: to.name.clone(), x: to.x, y: to.y };
+                (format!("Cargo for {}", to.name), objective, count * price * 2 + distance / 2, Vec::new())
+            }
+        };
+        MissionInfo { id: format!("{}-{}", period, place), name, objective, credits, items }
+    }
+
+    /// The (period, place) of an open mission's id at `now`
+    fn open(id: &str, now: u64) -> Result<(u64, u32), MissionError> {
+        let current = now / BOARD_PERIOD.as_secs();
+        let (period, place) = id
+            .split_once('-')
+            .and_then(|(period, place)| Some((period.parse::<u64>().ok()?, place.parse::<u32>().ok()?)))
+            .filter(|&(period, place)| period <= current && place < MISSIONS_PER_BOARD)
+            .ok_or(MissionError::Unknown)?;
+        if period + 1 < current {
+            return Err(MissionError::Expired);
+        }
+        Ok((period, place))
+    }
+
+    /// Take a mission on for an account whose stored ore total is `ore_mined`; taking it again changes nothing
+    pub fn accept(&self, id: &str, account: &str, ore_mined: u64, now: u64) -> Result<MissionInfo, MissionError> {
+        let (period, place) = Self::open(id, now)?;
+        let mission = self.roll(period, place);
+        if mission.objective == Objective::Unknown {
+            return Err(MissionError::Unknown);
+        }
+        let account = account.to_lowercase();
+        if self.claimed.lock().unwrap().contains(&(period, place, account.clone())) {
+            return Err(MissionError::Claimed);
+        }
+        let current = now / BOARD_PERIOD.as_secs();
+        let mut accepted = self.accepted.lock().unwrap();
+        accepted.retain(|_, missions| {
+            missions.retain(|taken| taken.period + 1 >= current);
+            !missions.is_empty()
+        });
+        let missions = accepted.entry(account).or_default();
+        if !missions.iter().any(|taken| (taken.period, taken.place) == (period, place)) {
+            let taken = Accepted { period, place, mission: mission.clone(), ore_at_start: ore_mined, surveyed: HashSet::new() };
+            missions.push(taken);
+        }
+        Ok(mission)
+    }
+
+    /// Count a nebula tile an account's ship is in toward the surveys it has taken on
+    pub fn surveyed(&self, account: &str, x: i32, y: i32) {
+        let mut accepted = self.accepted.lock().unwrap();
+        for taken in accepted.get_mut(&account.to_lowercase()).into_iter().flatten() {
+            if let Objective::Scan { tiles } = taken.mission.objective
+                && taken.surveyed.len() < tiles as usize
+            {
+                taken.surveyed.insert((x, y));
+            }
+        }
+    }
+
+    /// Pay an account for a mission it took on if, as far as the server knows, the objective is met. `pay` takes
+    /// a delivery's cargo, pays the reward and gives the balance after; its refusal leaves the mission open
+    pub fn complete<E: From<MissionError>>(
+        &self,
+        id: &str,
+        account: &str,
+        standing: Standing,
+        now: u64,
+        pay: impl FnOnce(&MissionInfo) -> Result<Option<u32>, E>,
+    ) -> Result<MissionReward, E> {
+        let (period, place) = Self::open(id, now)?;
+        let account = account.to_lowercase();
+        let mut accepted = self.accepted.lock().unwrap();
+        let missions = accepted.get_mut(&account).ok_or(MissionError::NotAccepted)?;
+        let index = missions
+            .iter()
+            .position(|taken| (taken.period, taken.place) == (period, place))
+            .ok_or(MissionError::NotAccepted)?;
+        let taken = &missions[index];
+        match &taken.mission.objective {
+            Objective::Deliver { to, x, y, .. } => {
+                if standing.at.is_none_or(|(at_x, at_y)| x.abs_diff(at_x).max(y.abs_diff(at_y)) > DOCKING_RANGE) {
+                    return Err(MissionError::Unfinished(format!("Deliver to {} at ({}, {})", to, x, y)).into());
+                }
+            }
+            Objective::Scan { tiles } if taken.surveyed.len() < *tiles as usize => {
+                let reason = format!("Surveyed {} of {} nebula tiles", taken.surveyed.len(), tiles);
+                return Err(MissionError::Unfinished(reason).into());
+            }
+            Objective::Mine { ore } if standing.ore_mined.saturating_sub(taken.ore_at_start) < u64::from(*ore) => {
+                let reason = format!("Mined {} of {} ore", standing.ore_mined.saturating_sub(taken.ore_at_start), ore);
+                return Err(MissionError::Unfinished(reason).into());
+            }
+            Objective::Scan { .. } | Objective::Mine { .. } => {}
+            Objective::Unknown => return Err(MissionError::Unknown.into()),
+        }
+        // Claimed only once it's paid, so a failed save leaves it to hand in again
+        let balance = pay(&taken.mission)?;
+        let mission = missions.remove(index).mission;
+        let current = now / BOARD_PERIOD.as_secs();
+        let mut claimed = self.claimed.lock().unwrap();
+        claimed.retain(|&(open, _, _)| open + 1 >= current);
+        claimed.insert((period, place, account));
+        Ok(MissionReward { mission: mission.id, credits: mission.credits, items: mission.items, balance })
+    }
+}
+
+/// Handler for `GET /missions`
+pub async fn get_missions(State(state): State<AppState>) -> Json<MissionBoard> {
+    Json(state.missions.board(utility::unix_time()))
+}
+
+/// Handler for `POST /missions/{id}/accept`: take a mission on; a bearer token is required
+pub async fn accept(State(state): State<AppState>, ApiPath(id): ApiPath<String>, headers: HeaderMap) -> Response {
+    let Some(account) = auth::bearer_token(&headers).and_then(|token| state.users.verify(token)) else {
+        return AuthError::BadToken.into_response();
+    };
+    let ore_mined = state.world.player_stats(&account).ore_mined;
+    match state.missions.accept(&id, &account, ore_mined, utility::unix_time()) {
+        Ok(mission) => Json(mission).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Handler for `POST /missions/{id}/complete`: the reward, if the objective is met, with a delivery's cargo
+/// taken from the account's hold, its credits paid into the account and its items stowed in the hold. A bearer
+/// token is required
+pub async fn complete(State(state): State<AppState>, ApiPath(id): ApiPath<String>, headers: HeaderMap) -> Response {
+    let Some(account) = auth::bearer_token(&headers).and_then(|token| state.users.verify(token)) else {
+        return AuthError::BadToken.into_response();
+    };
+    let standing = Standing {
+        at: state.sessions.find_by_name(&account).filter(|player| state.sessions.system(player.id).is_none()).map(|player| (player.x, player.y)),
+        ore_mined: state.world.player_stats(&account).ore_mined,
+    };
+    // The cargo leaves the hold before the reward is paid, and goes back if the payment can't be saved
+    let pay = |mission: &MissionInfo| -> Result<Option<u32>, ApiError> {
+        let cargo = match &mission.objective {
+            Objective::Deliver { cargo, .. } => Some(cargo),
+            _ => None,
+        };
+        if let Some(cargo) = cargo {
+            ledger::stow(&state.world, &account, &cargo.item, -i64::from(cargo.count))?;
+        }
+        let memo = format!("Completed mission {}", mission.id);
+        let balance = ledger::settle(&state.world, &account, TransactionKind::Mission, mission.credits as i64, &memo)
+            .inspect_err(|_| {
+                if let Some(cargo) = cargo {
+                    ledger::restow(&state.world, &account, &cargo.item, i64::from(cargo.count));
+                }
+            })?;
+        for item in &mission.items {
+            ledger::restow(&state.world, &account, &item.item, i64::from(item.count));
+        }
+        Ok(Some(balance))
+    };
+    match state.missions.complete(&id, &account, standing, utility::unix_time(), pay) {
+        Ok(reward) => Json(reward).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: u64 = BOARD_PERIOD.as_secs();
+
+    fn missions() -> Missions {
+        Missions::new(12345, &[StationInfo { name: "Haven Station".to_string(), x: 0, y: 0 }])
+    }
+
+    /// The first mission matching `wanted` on any board from `from`, with the time it's on offer
+    fn find(missions: &Missions, from: u64, wanted: impl Fn(&Objective) -> bool) -> (MissionInfo, u64) {
+        (0..200)
+            .map(|n| from + n * PERIOD)
+            .find_map(|now| missions.board(now).missions.into_iter().find(|m| wanted(&m.objective)).map(|m| (m, now)))
+            .expect("Some board has one")
+    }
+
+    fn at(x: i32, y: i32) -> Standing {
+        Standing { at: Some((x, y)), ore_mined: 0 }
+    }
+
+    /// Hand a mission in, the cargo always there to deliver
+    fn hand_in(missions: &Missions, id: &str, account: &str, standing: Standing, now: u64) -> Result<MissionReward, MissionError> {
+        missions.complete(id, account, standing, now, |_| Ok(None))
+    }
+
+    // ==================== Board Tests ====================
+
+    #[test]
+    fn test_boards_are_shared_and_replaced() {
+        let missions = missions();
+        let now = 1000 * PERIOD + 60;
+        let board = missions.board(now);
+        assert_eq!(board.missions.len(), MISSIONS_PER_BOARD as usize);
+        assert_eq!(board.expires_in, PERIOD - 60);
+        assert_eq!(board.missions[2].id, "1000-2", "Ids are the period and place");
+        assert_eq!(missions.board(now + 600).missions, board.missions, "Same board all period");
+        assert_eq!(Missions::new(12345, &[]).board(now).missions.len(), board.missions.len());
+        assert_ne!(missions.board(now + PERIOD).missions, board.missions, "Then a new one");
+
+        for mission in (0..50).flat_map(|n| missions.board(n * PERIOD).missions) {
+            assert!(mission.credits > 0, "{} pays nothing", mission.name);
+        }
+        find(&missions, 0, |o| matches!(o, Objective::Scan { .. }));
+        find(&missions, 0, |o| matches!(o, Objective::Mine { .. }));
+        find(&missions, 0, |o| matches!(o, Objective::Deliver { .. }));
+    }
+
+    #[test]
+    fn test_deliveries_go_to_stations_and_nearby_points_of_interest() {
+        let missions = missions();
+        assert_eq!(missions.destinations[0].name, "Haven Station");
+        assert!(missions.destinations.len() > 1, "Derelicts and beacons near spawn");
+        let reach = (DESTINATION_REACH + 1) * exospace_core::CHUNK_SIZE;
+        assert!(missions.destinations.iter().all(|poi| poi.x.abs() <= reach && poi.y.abs() <= reach));
+        let empty = Missions { destinations: Vec::new(), ..Missions::new(12345, &[]) };
+        assert!((0..50).flat_map(|n| empty.board(n * PERIOD).missions).all(|m| !matches!(m.objective, Objective::Deliver { .. })));
+    }
+
+    // ==================== Completion Tests ====================
+
+    #[test]
+    fn test_deliveries_need_the_ship_there_with_the_cargo() {
+        let missions = missions();
+        let (mission, now) = find(&missions, 0, |o| matches!(o, Objective::Deliver { .. }));
+        let Objective::Deliver { x, y, cargo, .. } = mission.objective.clone() else { unreachable!() };
+        let range = DOCKING_RANGE as i32;
+        missions.accept(&mission.id, "Ace", 0, now).unwrap();
+        let away = hand_in(&missions, &mission.id, "Ace", at(x + range + 1, y), now);
+        assert!(matches!(away, Err(MissionError::Unfinished(reason)) if reason.contains("Deliver to")));
+        let offline = hand_in(&missions, &mission.id, "Ace", Standing::default(), now);
+        assert!(matches!(offline, Err(MissionError::Unfinished(_))), "No ship, no delivery");
+
+        let empty = missions.complete(&mission.id, "Ace", at(x, y), now, |_| Err(MissionError::Unfinished("Empty hold".to_string())));
+        assert!(empty.is_err(), "The hold has to give up the cargo");
+        let mut delivered = None;
+        let reward = missions
+            .complete(&mission.id, "Ace", at(x - range, y + range), now, |paid: &MissionInfo| {
+                let Objective::Deliver { cargo, .. } = &paid.objective else { unreachable!() };
+                delivered = Some(cargo.clone());
+                Ok::<_, MissionError>(Some(7))
+            })
+            .unwrap();
+        assert_eq!(reward.balance, Some(7));
+        assert_eq!((reward.mission, reward.credits), (mission.id, mission.credits));
+        assert_eq!(delivered, Some(cargo));
+    }
+
+    #[test]
+    fn test_surveys_and_quotas_count_from_acceptance() {
+        let missions = missions();
+        let (scan, now) = find(&missions, 0, |o| matches!(o, Objective::Scan { .. }));
+        let Objective::Scan { tiles } = scan.objective else { unreachable!() };
+        missions.surveyed("Ace", 0, 0);
+        missions.accept(&scan.id, "Ace", 0, now).unwrap();
+        for x in 0..tiles as i32 - 1 {
+            missions.surveyed("ace", x, 0);
+            missions.surveyed("Ace", x, 0);
+        }
+        missions.surveyed("Bo", 99, 0);
+        let reason = format!("Surveyed {} of {} nebula tiles", tiles - 1, tiles);
+        assert_eq!(hand_in(&missions, &scan.id, "Ace", at(0, 0), now), Err(MissionError::Unfinished(reason)), "Each tile counts once");
+        missions.surveyed("Ace", 500, 500);
+        assert!(hand_in(&missions, &scan.id, "Ace", Standing::default(), now).is_ok(), "Anywhere will do");
+
+        let (quota, now) = find(&missions, 0, |o| matches!(o, Objective::Mine { .. }));
+        let Objective::Mine { ore } = quota.objective else { unreachable!() };
+        let before = u64::from(ore) * 5;
+        missions.accept(&quota.id, "Ace", before, now).unwrap();
+        let short = Standing { ore_mined: before + u64::from(ore) - 1, ..Standing::default() };
+        assert!(hand_in(&missions, &quota.id, "Ace", short, now).is_err(), "Ore mined before accepting doesn't count");
+        let reward = hand_in(&missions, &quota.id, "Ace", Standing { ore_mined: before + u64::from(ore), ..Standing::default() }, now).unwrap();
+        assert_eq!(reward.items, quota.items, "Quotas pay crystal on top");
+        assert_eq!(reward.items[0].item, "crystal");
+    }
+
+    #[test]
+    fn test_missions_expire_and_pay_accounts_once() {
+        let missions = missions();
+        let (quota, now) = find(&missions, 0, |o| matches!(o, Objective::Mine { .. }));
+        let done = Standing { ore_mined: 1000, ..Standing::default() };
+        assert_eq!(hand_in(&missions, &quota.id, "Ace", done, now), Err(MissionError::NotAccepted));
+        missions.accept(&quota.id, "Ace", 0, now).unwrap();
+        assert!(hand_in(&missions, &quota.id, "Ace", done, now).is_ok());
+        assert_eq!(hand_in(&missions, &quota.id, "ace", done, now), Err(MissionError::NotAccepted));
+        assert_eq!(missions.accept(&quota.id, "ACE", 0, now), Err(MissionError::Claimed), "Once paid, it can't be taken again");
+        missions.accept(&quota.id, "Bo", 0, now).unwrap();
+        assert!(hand_in(&missions, &quota.id, "Bo", done, now).is_ok(), "Everyone gets paid once");
+
+        missions.accept(&quota.id, "Cy", 0, now).unwrap();
+        missions.accept(&quota.id, "Di", 0, now).unwrap();
+        assert!(hand_in(&missions, &quota.id, "Cy", done, now + PERIOD).is_ok(), "Still open while the next board is up");
+        assert_eq!(hand_in(&missions, &quota.id, "Di", done, now + 2 * PERIOD), Err(MissionError::Expired));
+        assert_eq!(missions.accept(&quota.id, "Di", 0, now + 2 * PERIOD), Err(MissionError::Expired));
+        let (later, then) = find(&missions, now + 2 * PERIOD, |o| matches!(o, Objective::Mine { .. }));
+        missions.accept(&later.id, "Ace", 0, then).unwrap();
+        assert!(hand_in(&missions, &later.id, "Ace", done, then).is_ok());
+        assert_eq!(missions.claimed.lock().unwrap().len(), 1, "Claims on closed boards are let go");
+        assert!(!missions.accepted.lock().unwrap().contains_key("di"), "So are missions taken on them");
+        for bogus in ["", "12", "x-1", "5-99", &format!("{}-0", now / PERIOD + 1)] {
+            assert_eq!(missions.accept(bogus, "Ace", 0, now), Err(MissionError::Unknown), "{:?}", bogus);
+            assert_eq!(hand_in(&missions, bogus, "Ace", done, now), Err(MissionError::Unknown), "{:?}", bogus);
+        }
+    }
+}
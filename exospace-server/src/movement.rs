@@ -0,0 +1,230 @@
+//! Server-side checks on where pilots say they've flown.
+//!
+//! Clients fly their own ships and report each new spot with `Position`,
+//! which the server treats as an intent: `Mover::check()` only takes it if
+//! the ship could have got there from the last spot it took. Every tile on
+//! the straight line between them has to be open on the server's map of that
+//! system, and the distance has to fit a budget that refills at `MAX_SPEED`
+//! and holds up to `BURST` tiles, so bunched-up moves after a network stall
+//! still pass. A refused move leaves the pilot where they were, and the
+//! session sends `Corrected` so the client snaps back; moves the client sent
+//! before it heard are refused quietly for `CORRECTION_INTERVAL`.
+//!
+//! A session starts at the spawn point, and a saved spot or a respawn is put
+//! in place with `place()`; either way the first move is checked from there
+//! like any other, and spots outside the world are refused outright. A move
+//! into another system is only
+//! taken from a gate that leads there, and puts the ship on the gate back
+//! before it's checked like any other move.
+
+use crate::world;
+use std::time::{Duration, Instant};
+
+/// Fastest a ship flies, in tiles a second: the interceptor's one tile per 25 ms step
+pub const MAX_SPEED: f32 = 40.0;
+
+/// Most tiles of movement banked for moves that arrive bunched together
+pub const BURST: f32 = 20.0;
+
+/// Refused moves within this long of a correction don't get another one
+pub const CORRECTION_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What to do with a reported move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Accepted,
+    /// Refused: tell the client its ship is still here
+    Corrected(i32, i32),
+    /// Refused, with a correction already on its way
+    Refused,
+}
+
+/// Where the server last let one pilot's ship be, and how far it may go next
+pub struct Mover {
+    /// (system seed, x, y) of the last spot taken
+    at: (u64, i32, i32),
+    /// Tiles the ship may still cover
+    budget: f32,
+    refilled: Instant,
+    corrected: Option<Instant>,
+}
+
+impl Mover {
+    /// A ship starting at (x, y) in the system with `seed`
+    pub fn new(seed: u64, (x, y): (i32, i32), now: Instant) -> Self {
+        Mover { at: (seed, x, y), budget: BURST, refilled: now, corrected: None }
+    }
+
+    /// Put the ship somewhere the server chose: a saved spot or a respawn
+    pub fn place(&mut self, seed: u64, x: i32, y: i32) {
+        self.at = (seed, x, y);
+    }
+
+    /// Take or refuse a move to (x, y) in the system with `seed`; `passable` reads the server's map of it,
+    /// and `jump` says where a gate at a spot in a system leads, as the far system's seed and its gate back
+    pub fn check(
+        &mut self,
+        seed: u64,
+        (x, y): (i32, i32),
+        now: Instant,
+        mut passable: impl FnMut(i32, i32) -> bool,
+        jump: impl FnOnce(u64, (i32, i32)) -> Option<(u64, (i32, i32))>,
+    ) -> Verdict {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f32();
+        self.budget = (self.budget + elapsed * MAX_SPEED).min(BURST);
+        self.refilled = now;
+
+        let (from_seed, mut from_x, mut from_y) = self.at;
+        if !world::in_world(x, y) {
+            return self.correct(now, (from_x, from_y));
+        }
+        if from_seed != seed {
+            match jump(from_seed, (from_x, from_y)) {
+                Some((to, arrival)) if to == seed => {
+                    (from_x, from_y) = arrival;
+                    self.at = (seed, from_x, from_y);
+                }
+                _ => return self.correct(now, (from_x, from_y)),
+            }
+        }
+        let distance = x.abs_diff(from_x).max(y.abs_diff(from_y)) as f32;
+        if distance <= self.budget && path((from_x, from_y), (x, y)).all(|(x, y)| passable(x, y)) {
+            self.budget -= distance;
+            self.at = (seed, x, y);
+            return Verdict::Accepted;
+        }
+        self.correct(now, (from_x, from_y))
+    }
+
+    /// Refuse a move, sending the ship back to (x, y) unless a correction is already on its way
+    fn correct(&mut self, now: Instant, (x, y): (i32, i32)) -> Verdict {
+        if self.corrected.is_some_and(|at| now.saturating_duration_since(at) < CORRECTION_INTERVAL) {
+            return Verdict::Refused;
+        }
+        self.corrected = Some(now);
+        Verdict::Corrected(x, y)
+    }
+}
+
+/// The tiles a ship crosses flying straight from one tile to another, the first left out
+fn path(from: (i32, i32), to: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+    let (dx, dy) = (to.0 as i64 - from.0 as i64, to.1 as i64 - from.1 as i64);
+    let steps = dx.abs().max(dy.abs());
+    let along = move |start: i32, delta: i64, step: i64| (start as i64 + ((delta * step) as f64 / steps as f64).round() as i64) as i32;
+    (1..=steps).map(move |step| (along(from.0, dx, step), along(from.1, dy, step)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOME: u64 = 42;
+
+    /// Open space with a wall along x = 10
+    fn open(x: i32, _y: i32) -> bool {
+        x != 10
+    }
+
+    /// A gate at (5, 5) at home leading to system 7, whose gate back is at (900, 900)
+    fn gates(seed: u64, at: (i32, i32)) -> Option<(u64, (i32, i32))> {
+        match (seed, at) {
+            (HOME, (5, 5)) => Some((7, (900, 900))),
+            (7, (900, 900)) => Some((HOME, (5, 5))),
+            _ => None,
+        }
+    }
+
+    // ==================== Path Tests ====================
+
+    #[test]
+    fn test_path_crosses_each_tile() {
+        assert_eq!(path((0, 0), (3, 0)).collect::<Vec<_>>(), [(1, 0), (2, 0), (3, 0)]);
+        assert_eq!(path((0, 0), (-2, -2)).collect::<Vec<_>>(), [(-1, -1), (-2, -2)]);
+        assert_eq!(path((0, 0), (4, 2)).count(), 4, "One tile per step along the longer axis");
+        assert_eq!(path((5, 5), (5, 5)).count(), 0);
+        assert_eq!(path((i32::MAX - 1, 0), (i32::MAX, 0)).collect::<Vec<_>>(), [(i32::MAX, 0)], "No overflow at the edge");
+    }
+
+    // ==================== Mover Tests ====================
+
+    #[test]
+    fn test_moves_within_reach_are_taken() {
+        let start = Instant::now();
+        let mut mover = Mover::new(HOME, (0, 0), start);
+        let step = Duration::from_millis(25);
+        let mut now = start;
+        for x in 1..=9 {
+            now += step;
+            assert_eq!(mover.check(HOME, (x, x), now, open, gates), Verdict::Accepted, "Top speed, diagonally");
+        }
+    }
+
+    #[test]
+    fn test_teleports_and_walls_are_corrected() {
+        let start = Instant::now();
+        let mut mover = Mover::new(HOME, (0, 0), start);
+        assert_eq!(mover.check(HOME, (500, 0), start, |_, _| true, gates), Verdict::Corrected(0, 0), "Too far at once");
+        assert_eq!(mover.check(HOME, (400, 0), start, |_, _| true, gates), Verdict::Refused, "Stale moves aren't corrected again");
+        assert_eq!(mover.check(HOME, (1, 0), start, open, gates), Verdict::Accepted, "Back where the server has it");
+
+        let later = start + CORRECTION_INTERVAL;
+        mover.place(HOME, 8, 0);
+        assert_eq!(mover.check(HOME, (12, 0), later, open, gates), Verdict::Corrected(8, 0), "Not through the wall");
+        assert_eq!(mover.check(HOME, (9, 0), later + CORRECTION_INTERVAL, open, gates), Verdict::Accepted, "Right up to it");
+    }
+
+    #[test]
+    fn test_first_move_is_checked_from_the_start() {
+        let start = Instant::now();
+        let mut mover = Mover::new(HOME, (0, 0), start);
+        assert_eq!(mover.check(HOME, (300, 300), start, open, gates), Verdict::Corrected(0, 0), "Not wherever the client says");
+        let later = start + CORRECTION_INTERVAL;
+        assert_eq!(mover.check(HOME, (1, 1), later, open, gates), Verdict::Accepted);
+    }
+
+    #[test]
+    fn test_spots_outside_the_world_are_refused() {
+        let start = Instant::now();
+        let mut mover = Mover::new(HOME, (0, 0), start);
+        mover.place(HOME, i32::MAX, 0);
+        assert_eq!(mover.check(HOME, (i32::MIN, 0), start, |_, _| true, gates), Verdict::Corrected(i32::MAX, 0), "Not across the whole range");
+        mover.place(HOME, 0, 0);
+        let later = start + CORRECTION_INTERVAL;
+        assert_eq!(mover.check(HOME, (i32::MAX, 0), later, |_, _| true, gates), Verdict::Corrected(0, 0), "Off the edge of the world");
+    }
+
+    #[test]
+    fn test_speed_budget() {
+        let start = Instant::now();
+        let mut mover = Mover::new(HOME, (0, 0), start);
+        assert_eq!(mover.check(HOME, (0, BURST as i32), start, open, gates), Verdict::Accepted, "A burst after a stall");
+        assert!(matches!(mover.check(HOME, (0, BURST as i32 + 2), start, open, gates), Verdict::Corrected(..)), "Nothing left");
+        let second = start + Duration::from_secs(1);
+        assert_eq!(mover.check(HOME, (0, BURST as i32 * 2), second, open, gates), Verdict::Accepted, "Refilled, but only to the burst");
+    }
+
+    #[test]
+    fn test_other_systems_through_gates() {
+        let start = Instant::now();
+        let mut mover = Mover::new(HOME, (5, 5), start);
+        assert_eq!(mover.check(7, (900, 900), start, open, gates), Verdict::Accepted, "Arriving on the gate back");
+        assert!(matches!(mover.check(7, (0, 0), start, open, gates), Verdict::Corrected(900, 900)), "Then checked there");
+        let later = start + CORRECTION_INTERVAL;
+        assert_eq!(mover.check(HOME, (6, 5), later, open, gates), Verdict::Accepted, "And coming home, a step off the gate");
+    }
+
+    #[test]
+    fn test_seed_hops_cant_teleport() {
+        let start = Instant::now();
+        let mut mover = Mover::new(HOME, (0, 0), start);
+        assert_eq!(mover.check(7, (900, 900), start, open, gates), Verdict::Corrected(0, 0), "Not off a gate");
+        let later = start + CORRECTION_INTERVAL;
+        assert_eq!(mover.check(HOME, (400, 400), later, open, gates), Verdict::Corrected(0, 0), "Still where it was at home");
+
+        mover.place(HOME, 5, 5);
+        let later = later + CORRECTION_INTERVAL;
+        assert_eq!(mover.check(3, (900, 900), later, open, gates), Verdict::Corrected(5, 5), "The gate leads elsewhere");
+        let later = later + CORRECTION_INTERVAL;
+        assert_eq!(mover.check(7, (0, 0), later, open, gates), Verdict::Corrected(900, 900), "Out on the gate back, not anywhere");
+    }
+}
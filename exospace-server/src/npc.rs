@@ -0,0 +1,540 @@
+//! NPC ships flown by the server.
+//!
+//! A small built-in roster of ships lives near Haven: patrols fly a loop of
+//! waypoints, wanderers drift between random spots near home, and skittish
+//! ships keep away from any pilot who gets close. Ships are entities in a
+//! shared `exospace_core::ecs` store, flown by their `Ai` component. Pilots'
+//! projectiles (`combat`) wear down a ship's `Health`; one with nothing left
+//! is patched up and sent back to its home.
+//!
+//! Two simulation systems share the roster. `NpcPilot` picks each ship's
+//! destination; it is deferrable, so under load ships keep flying their
+//! current course without repathing. `NpcMovement` sets every ship's velocity
+//! one tile toward its destination, advances it a few times a second and
+//! hands the moves to the next `sync` delta. New sessions get a
+//! `ServerMessage::Npcs` snapshot; the same snapshot is served as JSON by
+//! `GET /entities`.
+
+use axum::{Json, extract::State};
+use exospace_core::ecs::{Ai, Entities, Entity, Health, Position, Velocity};
+use exospace_core::{ChunkData, Direction, NpcBehavior, NpcInfo, Tile, chunk_coords, chunk_local};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+use crate::sessions::SessionRegistry;
+use crate::tick::System;
+use crate::utility::DiceRng;
+use crate::world::World;
+
+/// How often NPC ships move a tile
+const STEP_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How far from home a wanderer strays
+const WANDER_RADIUS: i32 = 16;
+
+/// A skittish ship bolts when a pilot comes this close
+const FLEE_RADIUS: u32 = 12;
+
+/// How far a skittish ship runs before reconsidering
+const FLEE_DISTANCE: i32 = 10;
+
+/// Hits an NPC ship takes before it limps home
+const NPC_HULL: u32 = 3;
+
+/// Cached chunks are dropped after this long, so tile edits reach the NPCs
+const TILE_CACHE_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Every NPC ship, shared by the systems that fly them and `/entities`; entity ids are the ships' ids,
+/// so a removed ship's id isn't given to a new one
+#[derive(Default)]
+pub struct NpcRoster {
+    entities: Mutex<Entities>,
+}
+
+impl NpcRoster {
+    /// The built-in ships around Haven, each placed on the nearest open tile
+    pub fn spawn(world: &World) -> Self {
+        let mut tiles = TileCache::default();
+        let roster = NpcRoster::default();
+        let mut add = |name: &str, behavior, home: (i32, i32), route: &[(i32, i32)]| {
+            let (x, y) = tiles.nearest_open(world, home);
+            roster.add(name, behavior, (x, y), route.to_vec());
+        };
+        add("Haven Patrol", NpcBehavior::Patrol, (12, -8), &[(12, -8), (12, 8), (-12, 8), (-12, -8)]);
+        add("Co-op Hauler", NpcBehavior::Patrol, (-20, 10), &[(-20, 10), (-45, 30)]);
+        add("Survey Drone", NpcBehavior::Wander, (30, -20), &[]);
+        add("Tinker's Skiff", NpcBehavior::Wander, (-15, -25), &[]);
+        add("Skittish Prospector", NpcBehavior::Flee, (20, 20), &[]);
+        roster
+    }
+
+    fn add(&self, name: &str, behavior: NpcBehavior, home: (i32, i32), route: Vec<(i32, i32)>) -> NpcInfo {
+        let mut entities = self.entities.lock().unwrap();
+        let entity = entities.spawn();
+        let info = NpcInfo { id: entity.0, name: name.to_string(), x: home.0, y: home.1, direction: Direction::Up, behavior };
+        entities.put_npc(&info);
+        entities.velocities.insert(entity, Velocity::default());
+        entities.ai.insert(entity, Ai::new(behavior, home, route));
+        entities.health.insert(entity, Health::full(NPC_HULL));
+        info
+    }
+
+    /// Add a ship on the open tile nearest `home` (a patrol with no route wanders)
+    pub fn launch(&self, world: &World, name: &str, behavior: NpcBehavior, home: (i32, i32)) -> NpcInfo {
+        let home = TileCache::default().nearest_open(world, home);
+        self.add(name, behavior, home, Vec::new())
+    }
+
+    /// Take a ship out of the sky
+    pub fn remove(&self, id: u64) -> Option<NpcInfo> {
+        let mut entities = self.entities.lock().unwrap();
+        let info = entities.npc_info(Entity(id))?;
+        entities.despawn(Entity(id));
+        Some(info)
+    }
+
+    pub fn snapshot(&self) -> Vec<NpcInfo> {
+        self.entities.lock().unwrap().npcs()
+    }
+
+    /// The ship on a tile, if any
+    pub fn ship_at(&self, x: i32, y: i32) -> Option<u64> {
+        let entities = self.entities.lock().unwrap();
+        let on_tile = |&entity: &Entity| entities.positions.get(entity) == Some(&Position { x, y });
+        entities.ai.iter().map(|(entity, _)| entity).find(on_tile).map(|entity| entity.0)
+    }
+
+    /// Damage a ship; one with no hull left is repaired and put back at home. The ship as it is now,
+    /// and whether it was destroyed
+    pub fn hit(&self, id: u64, damage: u32) -> Option<(NpcInfo, bool)> {
+        let mut entities = self.entities.lock().unwrap();
+        let entity = Entity(id);
+        let destroyed = entities.health.get_mut(entity)?.damage(damage);
+        if destroyed {
+            let ai = entities.ai.get_mut(entity)?;
+            ai.target = None;
+            let (x, y) = ai.home;
+            entities.positions.insert(entity, Position { x, y });
+            entities.velocities.insert(entity, Velocity::default());
+            entities.health.insert(entity, Health::full(NPC_HULL));
+        }
+        Some((entities.npc_info(entity)?, destroyed))
+    }
+}
+
+/// Chunks looked up by NPC systems, station placement, sessions' nebula checks and move checks, so
+/// they don't regenerate one per tile
+#[derive(Default)]
+pub struct TileCache {
+    chunks: HashMap<(i32, i32), ChunkData>,
+    loaded_at: Option<Instant>,
+    /// The system the chunks are from; the home system when None
+    seed: Option<u64>,
+}
+
+impl TileCache {
+    /// A cache of another star system's chunks
+    pub fn for_seed(seed: u64) -> Self {
+        TileCache { seed: Some(seed), ..TileCache::default() }
+    }
+
+    /// Seed of the system the chunks are from
+    pub fn seed(&self, world: &World) -> u64 {
+        self.seed.unwrap_or(world.seed)
+    }
+
+    pub fn tile(&mut self, world: &World, x: i32, y: i32) -> Tile {
+        let now = Instant::now();
+        if self.loaded_at.is_none_or(|at| now.duration_since(at) > TILE_CACHE_LIFETIME) {
+            self.chunks.clear();
+            self.loaded_at = Some(now);
+        }
+        let (cx, cy) = chunk_coords(x, y);
+        let (lx, ly) = chunk_local(x, y);
+        let chunk = self.chunks.entry((cx, cy)).or_insert_with(|| world.chunk(cx, cy, self.seed.unwrap_or(world.seed)));
+        chunk.get(lx, ly).unwrap_or(Tile::Wall)
+    }
+
+    pub fn passable(&mut self, world: &World, x: i32, y: i32) -> bool {
+        self.tile(world, x, y).is_passable()
+    }
+
+    /// Drop the chunk holding a tile that was just changed, so it's read again
+    pub fn forget(&mut self, x: i32, y: i32) {
+        self.chunks.remove(&chunk_coords(x, y));
+    }
+
+    /// The closest passable tile to a spot in the home world, as `nearest_open()`
+    pub fn nearest_open(&mut self, world: &World, spot: (i32, i32)) -> (i32, i32) {
+        nearest_open(spot, |x, y| self.passable(world, x, y))
+    }
+}
+
+/// The closest tile to a spot that `passable` allows, searching outwards; the spot itself if there's none nearby
+pub fn nearest_open(spot: (i32, i32), mut passable: impl FnMut(i32, i32) -> bool) -> (i32, i32) {
+    let (x, y) = spot;
+    for radius in 0..16 {
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if passable(x + dx, y + dy) {
+                    return (x + dx, y + dy);
+                }
+            }
+        }
+    }
+    spot
+}
+
+/// Picks destinations; deferrable, since a ship can keep its course for a while
+pub struct NpcPilot {
+    roster: Arc<NpcRoster>,
+    world: Arc<World>,
+    sessions: Arc<SessionRegistry>,
+    tiles: TileCache,
+    rng: DiceRng,
+}
+
+impl NpcPilot {
+    pub fn new(roster: Arc<NpcRoster>, world: Arc<World>, sessions: Arc<SessionRegistry>) -> Self {
+        NpcPilot { roster, world, sessions, tiles: TileCache::default(), rng: DiceRng::for_session(0) }
+    }
+
+    fn plan(&mut self, npc: &mut Ai, Position { x, y }: Position) {
+        match npc.behavior {
+            NpcBehavior::Flee => {
+                // Run from the closest pilot it can sense, or potter about home once they've gone
+                let threat = self.sessions.sensed(x, y, FLEE_RADIUS).into_iter().min_by_key(|p| (p.x - x).abs().max((p.y - y).abs()));
+                if let Some(pilot) = threat {
+                    let away = ((x - pilot.x).signum(), (y - pilot.y).signum());
+                    let away = if away == (0, 0) { (1, 0) } else { away };
+                    npc.target = Some((x + away.0 * FLEE_DISTANCE, y + away.1 * FLEE_DISTANCE));
+                } else if npc.target.is_none() {
+                    npc.target = Some(self.wander_spot(npc.home));
+                }
+            }
+            _ if npc.target.is_some() => {}
+            NpcBehavior::Patrol if !npc.route.is_empty() => {
+                npc.leg = (npc.leg + 1) % npc.route.len();
+                npc.target = Some(npc.route[npc.leg]);
+            }
+            NpcBehavior::Patrol | NpcBehavior::Wander => npc.target = Some(self.wander_spot(npc.home)),
+            NpcBehavior::Unknown => {}
+        }
+    }
+
+    /// A random open spot within `WANDER_RADIUS` of home; home itself if the dice keep landing on rock
+    fn wander_spot(&mut self, (home_x, home_y): (i32, i32)) -> (i32, i32) {
+        let span = (WANDER_RADIUS * 2 + 1) as u32;
+        for _ in 0..8 {
+            let x = home_x + self.rng.roll_die(span) as i32 - 1 - WANDER_RADIUS;
+            let y = home_y + self.rng.roll_die(span) as i32 - 1 - WANDER_RADIUS;
+            if self.tiles.passable(&self.world, x, y) {
+                return (x, y);
+            }
+        }
+        (home_x, home_y)
+    }
+}
+
+impl System for NpcPilot {
+    fn name(&self) -> &'static str {
+        "npc_pilot"
+    }
+
+    /// Plans from a snapshot, so chunk lookups and pilot sensing don't hold up the roster
+    fn tick(&mut self, _now: Instant) {
+        let ships: Vec<(Entity, Ai, Position)> = {
+            let entities = self.roster.entities.lock().unwrap();
+            entities.ai.iter().filter_map(|(entity, npc)| Some((entity, npc.clone(), *entities.positions.get(entity)?))).collect()
+        };
+        let mut plans = Vec::new();
+        for (entity, seen, position) in ships {
+            let mut npc = seen.clone();
+            self.plan(&mut npc, position);
+            if npc != seen {
+                plans.push((entity, seen, npc));
+            }
+        }
+
+        // A ship that arrived or was sent home meanwhile keeps its new course and is planned next tick
+        let mut entities = self.roster.entities.lock().unwrap();
+        for (entity, seen, npc) in plans {
+            if let Some(live) = entities.ai.get_mut(entity)
+                && *live == seen
+            {
+                *live = npc;
+            }
+        }
+    }
+}
+
+/// Moves ships along their course and tells everyone where they are
+pub struct NpcMovement {
+    roster: Arc<NpcRoster>,
+    world: Arc<World>,
+    sessions: Arc<SessionRegistry>,
+    tiles: TileCache,
+    next_step: Instant,
+}
+
+impl NpcMovement {
+    /// Takes over the roster's ships, placing them for sessions' interest
+    pub fn new(roster: Arc<NpcRoster>, world: Arc<World>, sessions: Arc<SessionRegistry>, now: Instant) -> Self {
+        for npc in roster.snapshot() {
+            sessions.place_npc(&npc);
+        }
+        NpcMovement { roster, world, sessions, tiles: TileCache::default(), next_step: now }
+    }
+
+    /// One tile toward the target, taking whichever open neighbour gets closest; gives up on the target when stuck
+    fn step(&mut self, entities: &mut Entities, entity: Entity) -> bool {
+        let (Some(npc), Some(&Position { x, y })) = (entities.ai.get_mut(entity), entities.positions.get(entity)) else {
+            return false;
+        };
+        let Some((target_x, target_y)) = npc.target else {
+            return false;
+        };
+        let distance = |(px, py): (i32, i32)| (target_x - px).pow(2) + (target_y - py).pow(2);
+        if (x, y) == (target_x, target_y) {
+            npc.target = None;
+            return false;
+        }
+
+        let best = (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter(|&delta| delta != (0, 0))
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .filter(|&spot| distance(spot) < distance((x, y)))
+            .filter(|&(nx, ny)| self.tiles.passable(&self.world, nx, ny))
+            .min_by_key(|&spot| distance(spot));
+        let Some((nx, ny)) = best else {
+            npc.target = None;
+            return false;
+        };
+        entities.velocities.insert(entity, Velocity { dx: nx - x, dy: ny - y });
+        entities.advance(entity).is_some()
+    }
+}
+
+impl System for NpcMovement {
+    fn name(&self) -> &'static str {
+        "npc_movement"
+    }
+
+    /// Critical: ships already underway shouldn't freeze just because the tick ran long
+    fn critical(&self) -> bool {
+        true
+    }
+
+    fn tick(&mut self, now: Instant) {
+        if now < self.next_step {
+            return;
+        }
+        self.next_step = now + STEP_INTERVAL;
+
+        let roster = self.roster.clone();
+        let mut entities = roster.entities.lock().unwrap();
+        let ships: Vec<Entity> = entities.ai.iter().map(|(entity, _)| entity).collect();
+        for entity in ships {
+            if self.step(&mut entities, entity)
+                && let Some(info) = entities.npc_info(entity)
+            {
+                self.sessions.npc_moved(&info);
+            }
+        }
+    }
+}
+
+/// `GET /entities`: every NPC ship as JSON
+pub async fn get_entities(State(state): State<AppState>) -> Json<Vec<NpcInfo>> {
+    Json(state.npcs.snapshot())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::ServerMessage;
+    use crate::world::MemoryStore;
+
+    fn world() -> Arc<World> {
+        Arc::new(World::new(Box::new(MemoryStore::default())).unwrap())
+    }
+
+    fn roster_with(behavior: NpcBehavior, home: (i32, i32), route: &[(i32, i32)]) -> Arc<NpcRoster> {
+        let roster = NpcRoster::default();
+        roster.add("Test Ship", behavior, home, route.to_vec());
+        Arc::new(roster)
+    }
+
+    fn ship(roster: &NpcRoster) -> NpcInfo {
+        roster.snapshot()[0].clone()
+    }
+
+    fn target(roster: &NpcRoster) -> Option<(i32, i32)> {
+        roster.entities.lock().unwrap().ai.iter().next().unwrap().1.target
+    }
+
+    fn set_target(roster: &NpcRoster, target: Option<(i32, i32)>) {
+        roster.entities.lock().unwrap().ai.iter_mut().next().unwrap().1.target = target;
+    }
+
+    // ==================== Tile Tests ====================
+
+    #[test]
+    fn test_nearest_open_searches_outwards() {
+        assert_eq!(nearest_open((3, 4), |_, _| true), (3, 4), "An open spot is its own nearest");
+        assert_eq!(nearest_open((0, 0), |x, y| (x, y) == (2, -1)), (2, -1));
+        assert_eq!(nearest_open((0, 0), |x, _| x > 100), (0, 0), "Nothing open nearby");
+    }
+
+    // ==================== Roster Tests ====================
+
+    #[test]
+    fn test_spawned_ships_start_on_open_tiles() {
+        let world = world();
+        let roster = NpcRoster::spawn(&world);
+        let ships = roster.snapshot();
+        assert!(ships.len() >= 3);
+        let mut tiles = TileCache::default();
+        for ship in &ships {
+            assert!(tiles.passable(&world, ship.x, ship.y), "{} spawned inside rock", ship.name);
+        }
+        for behavior in [NpcBehavior::Patrol, NpcBehavior::Wander, NpcBehavior::Flee] {
+            assert!(ships.iter().any(|s| s.behavior == behavior), "The roster should include a {:?} ship", behavior);
+        }
+        let ids: Vec<u64> = ships.iter().map(|s| s.id).collect();
+        assert_eq!(ids, (1..=ships.len() as u64).collect::<Vec<_>>(), "Ids are unique and start at 1");
+    }
+
+    #[test]
+    fn test_launched_ships_get_fresh_ids() {
+        let world = world();
+        let roster = NpcRoster::spawn(&world);
+        let count = roster.snapshot().len() as u64;
+        let ship = roster.launch(&world, "Courier", NpcBehavior::Patrol, (3, 4));
+        assert_eq!(ship.id, count + 1);
+        assert!(TileCache::default().passable(&world, ship.x, ship.y), "Launched on open space");
+
+        assert_eq!(roster.remove(count + 1).map(|s| s.name), Some("Courier".to_string()));
+        assert!(roster.remove(count + 1).is_none(), "Already gone");
+        let again = roster.launch(&world, "Courier II", NpcBehavior::Wander, (3, 4));
+        assert_eq!(again.id, count + 2, "A removed ship's id isn't reused, so clients can't mix them up");
+    }
+
+    #[test]
+    fn test_destroyed_ships_go_home() {
+        let roster = roster_with(NpcBehavior::Wander, (3, 4), &[]);
+        roster.entities.lock().unwrap().positions.insert(Entity(1), Position { x: 9, y: 9 });
+        set_target(&roster, Some((20, 20)));
+        assert_eq!(roster.ship_at(9, 9), Some(1));
+        assert_eq!(roster.ship_at(3, 4), None);
+
+        let (ship, destroyed) = roster.hit(1, NPC_HULL - 1).unwrap();
+        assert!(!destroyed);
+        assert_eq!((ship.x, ship.y), (9, 9), "A damaged ship flies on");
+        let (ship, destroyed) = roster.hit(1, 1).unwrap();
+        assert!(destroyed);
+        assert_eq!((ship.x, ship.y), (3, 4), "Destroyed ships limp home");
+        assert_eq!(target(&roster), None);
+        assert!(!roster.hit(1, NPC_HULL - 1).unwrap().1, "Repaired to a full hull");
+        assert!(roster.hit(99, 1).is_none());
+    }
+
+    // ==================== Pilot Tests ====================
+
+    #[test]
+    fn test_patrol_flies_its_route_in_order() {
+        let world = world();
+        let sessions = Arc::new(SessionRegistry::default());
+        let roster = roster_with(NpcBehavior::Patrol, (0, 0), &[(0, 0), (4, 0), (4, 4)]);
+        let mut pilot = NpcPilot::new(roster.clone(), world, sessions);
+
+        pilot.tick(Instant::now());
+        assert_eq!(target(&roster), Some((4, 0)), "Starts toward the next waypoint");
+        pilot.tick(Instant::now());
+        assert_eq!(target(&roster), Some((4, 0)), "Keeps its course until it gets there");
+
+        set_target(&roster, None);
+        pilot.tick(Instant::now());
+        assert_eq!(target(&roster), Some((4, 4)));
+    }
+
+    #[test]
+    fn test_wanderer_stays_near_home() {
+        let world = world();
+        let roster = roster_with(NpcBehavior::Wander, (0, 0), &[]);
+        let mut pilot = NpcPilot::new(roster.clone(), world, Arc::new(SessionRegistry::default()));
+        for _ in 0..20 {
+            set_target(&roster, None);
+            pilot.tick(Instant::now());
+            let (x, y) = target(&roster).unwrap();
+            assert!(x.abs() <= WANDER_RADIUS && y.abs() <= WANDER_RADIUS, "Wandered off to ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn test_skittish_ship_flees_nearby_pilots() {
+        let world = world();
+        let sessions = Arc::new(SessionRegistry::default());
+        let roster = roster_with(NpcBehavior::Flee, (0, 0), &[]);
+        let mut pilot = NpcPilot::new(roster.clone(), world, sessions.clone());
+
+        let (ace, _rx) = sessions.join("Ace");
+        sessions.update_position(ace.id, -3, 2, Direction::Right);
+        pilot.tick(Instant::now());
+        assert_eq!(target(&roster), Some((FLEE_DISTANCE, -FLEE_DISTANCE)), "Runs directly away from the pilot");
+
+        sessions.update_position(ace.id, 500, 500, Direction::Right);
+        set_target(&roster, None);
+        pilot.tick(Instant::now());
+        let (x, y) = target(&roster).unwrap();
+        assert!(x.abs() <= WANDER_RADIUS && y.abs() <= WANDER_RADIUS, "Calms down near home once nobody's close");
+    }
+
+    // ==================== Movement Tests ====================
+
+    #[test]
+    fn test_movement_steps_toward_target_and_broadcasts() {
+        let world = world();
+        let sessions = Arc::new(SessionRegistry::default());
+        let (_, mut rx) = sessions.join("Watcher");
+        let roster = roster_with(NpcBehavior::Wander, (0, 0), &[]);
+        set_target(&roster, Some((3, 0)));
+        let start = Instant::now();
+        let mut movement = NpcMovement::new(roster.clone(), world, sessions.clone(), start);
+
+        movement.tick(start);
+        let npc = ship(&roster);
+        assert_eq!((npc.x, npc.y, npc.direction), (1, 0, Direction::Right));
+        sessions.flush_delta();
+        let moved = std::iter::from_fn(|| rx.try_recv().ok()).find_map(|b| match b.message {
+            ServerMessage::WorldDelta { moved, .. } => Some(moved),
+            _ => None,
+        });
+        assert_eq!(moved.unwrap()[0].x, 1, "Everyone hears where the ships went");
+
+        movement.tick(start + STEP_INTERVAL / 2);
+        assert_eq!(ship(&roster).x, 1, "Ships move a tile per step interval, not per tick");
+        movement.tick(start + STEP_INTERVAL);
+        movement.tick(start + STEP_INTERVAL * 2);
+        movement.tick(start + STEP_INTERVAL * 3);
+        assert_eq!((ship(&roster).x, target(&roster)), (3, None), "Arriving clears the target");
+    }
+
+    #[test]
+    fn test_movement_gives_up_when_blocked() {
+        let world = world();
+        world.set_tile(1, 0, Tile::Wall).unwrap();
+        world.set_tile(1, 1, Tile::Wall).unwrap();
+        world.set_tile(1, -1, Tile::Wall).unwrap();
+        let roster = roster_with(NpcBehavior::Wander, (0, 0), &[]);
+        set_target(&roster, Some((5, 0)));
+        let mut movement = NpcMovement::new(roster.clone(), world, Arc::new(SessionRegistry::default()), Instant::now());
+
+        movement.tick(Instant::now());
+        let npc = ship(&roster);
+        assert_eq!((npc.x, npc.y, target(&roster)), (0, 0, None), "A walled-off ship waits for a new course");
+    }
+}
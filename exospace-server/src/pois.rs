@@ -0,0 +1,103 @@
+//! Named points of interest: stations, derelicts and beacons.
+//!
+//! `/map` places a handful on every bounded map, the first one always a
+//! station, spread out over open tiles. The streamed world gets its stations
+//! from `stations.rs`, so its chunks only carry the odd derelict or beacon
+//! (see `ChunkGenerator::generate`). Names are built from word lists by
+//! position hash, so a seed always names its places the same way.
+
+use exospace_core::{MapData, Poi, PoiKind, hash_position};
+
+/// Keeps point of interest rolls independent of the other uses of the seed
+pub const POI_SALT: u32 = 0x9017_A11E;
+
+/// Open tiles per point of interest on a bounded map
+const TILES_PER_POI: usize = 10_000;
+
+/// Most points of interest on one bounded map
+const MAX_MAP_POIS: usize = 8;
+
+/// Fewest tiles between two points of interest on a bounded map, along either axis
+const MIN_SPACING: i32 = 12;
+
+const STATION_NAMES: [&str; 8] = ["Meridian", "Halcyon", "Tarsus", "Vesper", "Corvid", "Lantern", "Bastion", "Quill"];
+const STATION_SUFFIXES: [&str; 4] = ["Outpost", "Station", "Depot", "Hub"];
+const WRECK_ADJECTIVES: [&str; 8] = ["Silent", "Broken", "Drifting", "Burnt", "Lonely", "Frozen", "Hollow", "Last"];
+const WRECK_NOUNS: [&str; 8] = ["Heron", "Lark", "Promise", "Anvil", "Comet", "Widow", "Sparrow", "Fortune"];
+const GREEK_LETTERS: [&str; 8] = ["Alpha", "Beta", "Gamma", "Delta", "Kappa", "Sigma", "Tau", "Omega"];
+
+/// A name for a point of interest, picked by `roll`
+pub fn name(kind: PoiKind, roll: u32) -> String {
+    let pick = |words: &[&'static str], shift: u32| words[(roll >> shift) as usize % words.len()];
+    match kind {
+        PoiKind::Station => format!("{} {}", pick(&STATION_NAMES, 0), pick(&STATION_SUFFIXES, 8)),
+        PoiKind::Derelict => format!("Wreck of the {} {}", pick(&WRECK_ADJECTIVES, 0), pick(&WRECK_NOUNS, 8)),
+        PoiKind::Beacon | PoiKind::Unknown => format!("Beacon {}-{}", pick(&GREEK_LETTERS, 0), (roll >> 8) % 9 + 1),
+        // Named for where they lead, by `systems.rs`
+        PoiKind::JumpGate => "Jump Gate".to_string(),
+    }
+}
+
+/// Spread points of interest over a bounded map's open tiles, a station first
+pub fn place_on_map(map: &mut MapData, seed: u64) {
+    let seed = (seed ^ (seed >> 32)) as u32 ^ POI_SALT;
+    let wanted = (map.width * map.height / TILES_PER_POI).clamp(1, MAX_MAP_POIS);
+    let mut taken = vec![(map.start_x, map.start_y)];
+    // Crowded or rocky maps may run out of spots; they get fewer
+    for attempt in 0..wanted as i32 * 50 {
+        if map.pois.len() == wanted {
+            break;
+        }
+        let roll = hash_position(attempt, 0, seed);
+        let x = roll as usize % map.width;
+        let y = hash_position(attempt, 1, seed) as usize % map.height;
+        let (x, y) = (x as i32, y as i32);
+        let crowded = taken.iter().any(|&(tx, ty)| (tx - x).abs() < MIN_SPACING && (ty - y).abs() < MIN_SPACING);
+        if crowded || !map.get(x as usize, y as usize).is_some_and(|tile| tile.is_passable()) {
+            continue;
+        }
+        let kind = match map.pois.len() {
+            0 => PoiKind::Station,
+            _ if roll.is_multiple_of(2) => PoiKind::Derelict,
+            _ => PoiKind::Beacon,
+        };
+        taken.push((x, y));
+        map.pois.push(Poi { kind, name: name(kind, hash_position(x, y, seed)), x, y });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapgen::Algorithm;
+
+    // ==================== Placement Tests ====================
+
+    #[test]
+    fn test_maps_get_spread_out_points_of_interest() {
+        let mut map = Algorithm::Rooms.generator(12345).generate(200, 100);
+        place_on_map(&mut map, 12345);
+        assert_eq!(map.pois.len(), 2, "One per 10,000 tiles");
+        assert_eq!(map.pois[0].kind, PoiKind::Station, "A station comes first");
+        for poi in &map.pois {
+            assert!(map.get(poi.x as usize, poi.y as usize).unwrap().is_passable(), "{} is in rock", poi.name);
+        }
+        let (a, b) = (&map.pois[0], &map.pois[1]);
+        assert!((a.x - b.x).abs() >= MIN_SPACING || (a.y - b.y).abs() >= MIN_SPACING);
+
+        let mut again = Algorithm::Rooms.generator(12345).generate(200, 100);
+        place_on_map(&mut again, 12345);
+        assert_eq!(again.pois, map.pois, "Same seed, same places");
+
+        let mut big = Algorithm::Noise.generator(1).generate(1000, 1000);
+        place_on_map(&mut big, 1);
+        assert_eq!(big.pois.len(), MAX_MAP_POIS);
+    }
+
+    #[test]
+    fn test_names_by_kind() {
+        assert_eq!(name(PoiKind::Station, 0), "Meridian Outpost");
+        assert_eq!(name(PoiKind::Derelict, 0x0101), "Wreck of the Broken Lark");
+        assert_eq!(name(PoiKind::Beacon, 0x0604), "Beacon Kappa-7");
+    }
+}
@@ -0,0 +1,225 @@
+//! Map previews: `/map/preview` draws a generated map as a PNG (or an SVG
+//! with `?format=svg`), so seeds can be looked over in a browser.
+//!
+//! It takes the same query as `/map` and shares its cache. Tiles use the
+//! terminal client's minimap colors, scaled up to fill about
+//! `PREVIEW_SIDE` pixels, and the start position is a red tile inside a
+//! red square. The PNG is written by hand: 8-bit RGB, every row unfiltered,
+//! one zlib-compressed IDAT chunk.
+
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use exospace_core::{MapData, Tile};
+use flate2::{Compression, Crc, write::ZlibEncoder};
+use std::io::Write;
+
+use crate::error::ApiQuery;
+use crate::{AppState, MapQuery, cached_map};
+
+/// The longer side of a preview aims for this many pixels
+pub const PREVIEW_SIDE: usize = 1024;
+
+/// Most pixels per tile, so small maps don't turn into a few huge blocks
+const MAX_SCALE: usize = 8;
+
+/// The start marker: the start tile and a square this many tiles out
+const MARKER_RADIUS: usize = 2;
+
+const MARKER_COLOR: u32 = 0xFF4040;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+pub async fn get_preview(State(state): State<AppState>, ApiQuery(params): ApiQuery<MapQuery>) -> Response {
+    let map = match cached_map(&state, &params) {
+        Ok(map) => map,
+        Err(rejection) => return rejection.into_response(),
+    };
+    if params.format.as_deref().is_some_and(|format| format.eq_ignore_ascii_case("svg")) {
+        ([(header::CONTENT_TYPE, "image/svg+xml")], render_svg(&map)).into_response()
+    } else {
+        ([(header::CONTENT_TYPE, "image/png")], render_png(&map)).into_response()
+    }
+}
+
+/// Same colors as the terminal client's minimap
+pub fn tile_color(tile: Tile) -> u32 {
+    match tile {
+        Tile::Wall => 0x304880,
+        Tile::Floor => 0x303040,
+        Tile::Asteroid => 0xB08050,
+        Tile::Nebula => 0x9050B0,
+        Tile::OreAsteroid => 0xD0A040,
+        Tile::CrystalField => 0x40C0C0,
+        Tile::Unknown => 0xFF00FF,
+    }
+}
+
+/// Pixels per tile for a map this size
+pub fn scale(width: usize, height: usize) -> usize {
+    (PREVIEW_SIDE / width.max(height).max(1)).clamp(1, MAX_SCALE)
+}
+
+/// Whether (x, y) is drawn as part of the start marker
+fn is_marker(map: &MapData, x: usize, y: usize) -> bool {
+    let distance = x.abs_diff(map.start_x as usize).max(y.abs_diff(map.start_y as usize));
+    distance == 0 || distance == MARKER_RADIUS
+}
+
+fn color_at(map: &MapData, x: usize, y: usize) -> u32 {
+    if is_marker(map, x, y) {
+        return MARKER_COLOR;
+    }
+    map.get(x, y).map_or(0, tile_color)
+}
+
+pub fn render_png(map: &MapData) -> Vec<u8> {
+    let scale = scale(map.width, map.height);
+    let (width, height) = (map.width * scale, map.height * scale);
+
+    let mut row = Vec::with_capacity(1 + width * 3);
+    let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+    for y in 0..map.height {
+        row.clear();
+        row.push(0); // Filter: none
+        for x in 0..map.width {
+            let [_, r, g, b] = color_at(map, x, y).to_be_bytes();
+            for _ in 0..scale {
+                row.extend([r, g, b]);
+            }
+        }
+        for _ in 0..scale {
+            zlib.write_all(&row).expect("writing to a Vec can't fail");
+        }
+    }
+    let pixels = zlib.finish().expect("writing to a Vec can't fail");
+
+    let mut header = Vec::with_capacity(13);
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    header.extend([8, 2, 0, 0, 0]); // 8-bit RGB, deflate, no filtering choices, no interlace
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &pixels);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Length, type, data and the CRC of type and data
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    png.extend(kind);
+    png.extend(data);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend(crc.sum().to_be_bytes());
+}
+
+/// One rect per run of same-colored tiles on a row, over a wall-colored background
+pub fn render_svg(map: &MapData) -> String {
+    let scale = scale(map.width, map.height);
+    let background = tile_color(Tile::Wall);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" shape-rendering=\"crispEdges\">\n",
+        map.width * scale,
+        map.height * scale,
+        map.width,
+        map.height
+    );
+    svg.push_str(&format!("<rect width=\"{}\" height=\"{}\" fill=\"#{:06x}\"/>\n", map.width, map.height, background));
+    for y in 0..map.height {
+        let mut x = 0;
+        while x < map.width {
+            let color = color_at(map, x, y);
+            let run = (x..map.width).take_while(|&end| color_at(map, end, y) == color).count();
+            if color != background {
+                svg.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"1\" fill=\"#{:06x}\"/>\n", x, y, run, color));
+            }
+            x += run;
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    fn test_map() -> MapData {
+        let mut map = MapData::filled(10, 6, Tile::Wall);
+        for row in map.rect_mut(1..9, 1..5) {
+            row.fill(Tile::Floor);
+        }
+        map.start_x = 3;
+        map.start_y = 2;
+        map
+    }
+
+    /// The RGB pixels of a PNG written by `render_png`, checking its chunks on the way
+    fn decode(png: &[u8]) -> (usize, usize, Vec<u8>) {
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut at = 8;
+        while at < png.len() {
+            let length = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+            let (kind, data) = (&png[at + 4..at + 8], &png[at + 8..at + 8 + length]);
+            let mut crc = Crc::new();
+            crc.update(kind);
+            crc.update(data);
+            assert_eq!(png[at + 8 + length..at + 12 + length], crc.sum().to_be_bytes(), "Bad CRC on {:?}", kind);
+            chunks.push((kind.to_vec(), data.to_vec()));
+            at += 12 + length;
+        }
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| kind.as_slice()).collect();
+        assert_eq!(kinds, [b"IHDR".as_slice(), b"IDAT", b"IEND"]);
+        let width = u32::from_be_bytes(chunks[0].1[..4].try_into().unwrap()) as usize;
+        let height = u32::from_be_bytes(chunks[0].1[4..8].try_into().unwrap()) as usize;
+        let mut raw = Vec::new();
+        ZlibDecoder::new(chunks[1].1.as_slice()).read_to_end(&mut raw).unwrap();
+        assert_eq!(raw.len(), height * (1 + width * 3));
+        let pixels = raw.chunks(1 + width * 3).flat_map(|row| row[1..].to_vec()).collect();
+        (width, height, pixels)
+    }
+
+    // ==================== Rendering Tests ====================
+
+    #[test]
+    fn test_scale() {
+        assert_eq!(scale(10, 6), MAX_SCALE, "Small maps are capped");
+        assert_eq!(scale(200, 100), 5);
+        assert_eq!(scale(2000, 1000), 1, "Big maps are a pixel per tile");
+    }
+
+    #[test]
+    fn test_png_pixels() {
+        let map = test_map();
+        let (width, height, pixels) = decode(&render_png(&map));
+        assert_eq!((width, height), (10 * MAX_SCALE, 6 * MAX_SCALE));
+        let pixel = |x: usize, y: usize| {
+            let at = (y * width + x) * 3;
+            u32::from_be_bytes([0, pixels[at], pixels[at + 1], pixels[at + 2]])
+        };
+        assert_eq!(pixel(0, 0), tile_color(Tile::Wall));
+        assert_eq!(pixel(8 * MAX_SCALE - 1, 4 * MAX_SCALE), tile_color(Tile::Floor), "Tiles are scaled up");
+        assert_eq!(pixel(3 * MAX_SCALE, 2 * MAX_SCALE + 1), MARKER_COLOR, "The start is marked");
+        assert_eq!(pixel(4 * MAX_SCALE, 2 * MAX_SCALE), tile_color(Tile::Floor), "Inside the marker's square");
+        assert_eq!(pixel(5 * MAX_SCALE, 3 * MAX_SCALE), MARKER_COLOR, "The marker's square");
+    }
+
+    #[test]
+    fn test_svg_runs() {
+        let svg = render_svg(&test_map());
+        assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>\n"));
+        assert!(svg.contains("width=\"80\" height=\"48\" viewBox=\"0 0 10 6\""));
+        assert!(svg.contains("<rect x=\"3\" y=\"2\" width=\"1\" height=\"1\" fill=\"#ff4040\"/>"), "The start is marked");
+        assert!(svg.contains("<rect x=\"2\" y=\"1\" width=\"3\" height=\"1\" fill=\"#303040\"/>"), "A run of floor between marker edges");
+        assert!(!svg.contains("x=\"0\" y=\"3\""), "Walls are left to the background");
+    }
+}
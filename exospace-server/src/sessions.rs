@@ -0,0 +1,1031 @@
+//! Multiplayer session registry and the `/ws` endpoint.
+//!
+//! Every connected socket gets a session id and an entry in the registry.
+//! Position updates are checked by `movement` against the server's map,
+//! answered with `Corrected` when they're too fast or go through rock, and
+//! otherwise stored; they go out in the next `sync` delta, cut
+//! down by each session to what's in range of its pilot (`interest`). Chat lines are fanned out through a tokio broadcast channel once
+//! they pass the rules in `chat` and the pilot's `throttle` budget, which
+//! also drops moves sent faster than it allows. A pilot connecting with a bearer token
+//! flies under their account name; guests can't take a registered one.
+//! Logged-in pilots also have their position saved to the world store and
+//! are sent back there (`Resume`) when they reconnect, their `stats` added
+//! to its totals, their home station remembered for `Respawn` and the
+//! nebula tiles they fly through counted toward their surveys (`missions`).
+
+use axum::{
+    extract::{
+        State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use exospace_core::{ClientMessage, Direction, EntityId, EntityKind, EntityMove, NpcInfo, PlayerInfo, SAY_RANGE, SPAWN_POSITION, ServerMessage, ShipLivery, Tile, within_sensor_range};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+use crate::AppState;
+use crate::auth::{self, AuthError, Users};
+use crate::chat::{self, ChatRateLimiter};
+use crate::interest::{Interest, InterestGrid, bucket_of};
+use crate::movement::{Mover, Verdict};
+use crate::throttle::{Action, Actor, Throttle};
+use crate::npc::TileCache;
+use crate::stats::StatsTracker;
+use crate::sync::DeltaBuffer;
+use crate::utility::{self, DiceRng};
+use crate::world::World;
+
+/// How many undelivered broadcasts a slow session may fall behind by
+const BROADCAST_CAPACITY: usize = 256;
+
+/// How often a logged-in pilot's position and stats are saved while they fly (they are also saved when they leave)
+const POSITION_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Longest player name accepted in Hello
+const MAX_NAME_LEN: usize = 24;
+
+/// Session ids start at 1, so broadcasts tagged with 0 reach every session including the originator
+const FROM_SERVER: u64 = 0;
+
+/// A broadcast tagged with the session that caused it, so the sender can skip its own echo
+#[derive(Clone, Debug)]
+pub struct Broadcast {
+    pub from: u64,
+    /// Only this session should deliver it; everyone when `None`
+    pub to: Option<u64>,
+    pub message: ServerMessage,
+}
+
+impl Broadcast {
+    /// Whether the session `id` should pass this on to its socket
+    pub fn is_for(&self, id: u64) -> bool {
+        self.from != id && self.to.is_none_or(|to| to == id)
+    }
+}
+
+/// All connected players and the channel used to fan out their updates
+pub struct SessionRegistry {
+    players: RwLock<HashMap<u64, PlayerInfo>>,
+    next_id: AtomicU64,
+    sender: broadcast::Sender<Broadcast>,
+    delta: Mutex<DeltaBuffer>,
+    grid: Mutex<InterestGrid>,
+    /// Pilots inside a nebula, hidden from sensors beyond `NEBULA_SENSOR_RANGE`
+    in_nebula: RwLock<HashSet<u64>>,
+    /// Pilots flying in another star system, by its seed; everyone else is in the home world
+    away: RwLock<HashMap<u64, u64>>,
+}
+
+impl Default for SessionRegistry {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(BROADCAST_CAPACITY);
+        SessionRegistry {
+            players: RwLock::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            sender,
+            delta: Mutex::new(DeltaBuffer::default()),
+            grid: Mutex::new(InterestGrid::default()),
+            in_nebula: RwLock::new(HashSet::new()),
+            away: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl SessionRegistry {
+    /// Register a new player, announce them, and return their info and a broadcast receiver
+    pub fn join(&self, name: &str) -> (PlayerInfo, broadcast::Receiver<Broadcast>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let player = PlayerInfo {
+            id,
+            name: sanitize_name(name, id),
+            x: SPAWN_POSITION.0,
+            y: SPAWN_POSITION.1,
+            direction: Direction::Up,
+            ship: ShipLivery::default(),
+        };
+
+        // Subscribe before announcing so the receiver can't miss anything after the join
+        let receiver = self.sender.subscribe();
+        self.players.write().unwrap().insert(id, player.clone());
+        self.grid.lock().unwrap().place(EntityId { kind: EntityKind::Player, id }, player.x, player.y);
+        self.broadcast(id, ServerMessage::PlayerJoined { player: player.clone() });
+
+        (player, receiver)
+    }
+
+    /// Record a position update for the next delta; ignored for unknown sessions
+    pub fn update_position(&self, id: u64, x: i32, y: i32, direction: Direction) {
+        {
+            let mut players = self.players.write().unwrap();
+            let Some(player) = players.get_mut(&id) else {
+                return;
+            };
+            player.x = x;
+            player.y = y;
+            player.direction = direction;
+        }
+        self.grid.lock().unwrap().place(EntityId { kind: EntityKind::Player, id }, x, y);
+        self.delta.lock().unwrap().moved(EntityMove { kind: EntityKind::Player, id, x, y, direction });
+    }
+
+    /// Note where an NPC ship is, so sessions nearby are told about it
+    pub fn place_npc(&self, npc: &NpcInfo) {
+        self.grid.lock().unwrap().place(EntityId { kind: EntityKind::Npc, id: npc.id }, npc.x, npc.y);
+    }
+
+    /// Forget a despawned NPC ship; sessions that could see it hear it's gone with the next delta
+    pub fn remove_npc(&self, id: u64) {
+        self.grid.lock().unwrap().remove(EntityId { kind: EntityKind::Npc, id });
+        self.delta.lock().unwrap().forget(EntityKind::Npc, id);
+    }
+
+    /// Record where an NPC ship moved to for the next delta
+    pub fn npc_moved(&self, npc: &NpcInfo) {
+        self.place_npc(npc);
+        let entity = EntityMove { kind: EntityKind::Npc, id: npc.id, x: npc.x, y: npc.y, direction: npc.direction };
+        self.delta.lock().unwrap().moved(entity);
+    }
+
+    /// Record a changed world tile for the next delta
+    pub fn tile_changed(&self, x: i32, y: i32, tile: Tile) {
+        self.delta.lock().unwrap().tile_changed(x, y, tile);
+    }
+
+    /// Number of the last delta sent; state read after this is at least that current
+    pub fn delta_seq(&self) -> u64 {
+        self.delta.lock().unwrap().seq()
+    }
+
+    /// Send everyone whatever changed since the last delta
+    pub fn flush_delta(&self) {
+        // Held while sending so deltas enter the channel in sequence order
+        let mut delta = self.delta.lock().unwrap();
+        if let Some(message) = delta.take() {
+            self.broadcast(FROM_SERVER, message);
+        }
+    }
+
+    /// Take a session's new ship name, callsign and paint and show everyone else; ignored for unknown sessions
+    pub fn repaint(&self, id: u64, ship: ShipLivery) {
+        {
+            let mut players = self.players.write().unwrap();
+            let Some(player) = players.get_mut(&id) else {
+                return;
+            };
+            if player.ship == ship {
+                return;
+            }
+            player.ship = ship.clone();
+        }
+        self.broadcast(id, ServerMessage::Livery { id, ship });
+    }
+
+    /// Relay a chat line from a session to everyone else; ignored for unknown sessions
+    pub fn chat(&self, id: u64, text: &str) {
+        let Some(name) = self.players.read().unwrap().get(&id).map(|p| p.name.clone()) else {
+            return;
+        };
+        self.broadcast(id, ServerMessage::Chat { id, name, text: text.to_string() });
+    }
+
+    /// Pass local chat or an emote from a session to the pilots within `SAY_RANGE` who can see its ship;
+    /// ignored for unknown sessions
+    pub fn say(&self, id: u64, text: &str, emote: bool) {
+        let Some(speaker) = self.get(id) else {
+            return;
+        };
+        for listener in self.near_in(self.system(id), speaker.x, speaker.y, SAY_RANGE) {
+            if listener.id == id || self.hidden_from(id, (listener.x, listener.y)) {
+                continue;
+            }
+            let message = ServerMessage::Say { id, name: speaker.name.clone(), text: text.to_string(), emote };
+            let _ = self.sender.send(Broadcast { from: id, to: Some(listener.id), message });
+        }
+    }
+
+    /// Send a message to every session, including whoever caused it
+    pub fn announce(&self, message: ServerMessage) {
+        self.broadcast(FROM_SERVER, message);
+    }
+
+    /// Send a message about something a pilot did at (x, y) to the pilot and to every session in its
+    /// system whose area takes in the spot (`interest`) and whose sensors pick the pilot up
+    pub fn show(&self, pilot: u64, (x, y): (i32, i32), message: ServerMessage) {
+        let system = self.system(pilot);
+        let watchers = self.grid.lock().unwrap().around(bucket_of(x, y));
+        let mut watchers: Vec<PlayerInfo> = watchers
+            .into_iter()
+            .filter(|e| e.kind == EntityKind::Player && e.id != pilot && self.system(e.id) == system)
+            .filter_map(|e| self.get(e.id))
+            .collect();
+        watchers.sort_by_key(|p| p.id);
+        for watcher in watchers {
+            if !self.hidden_from(pilot, (watcher.x, watcher.y)) {
+                self.send_to(watcher.id, message.clone());
+            }
+        }
+        self.send_to(pilot, message);
+    }
+
+    /// Send a message to one session only
+    pub fn send_to(&self, id: u64, message: ServerMessage) {
+        let _ = self.sender.send(Broadcast { from: FROM_SERVER, to: Some(id), message });
+    }
+
+    /// Tell a session why it's being closed, then close it; false if there's no such session
+    pub fn kick(&self, id: u64, reason: &str) -> bool {
+        if self.get(id).is_none() {
+            return false;
+        }
+        self.send_to(id, ServerMessage::Kicked { reason: reason.to_string() });
+        true
+    }
+
+    /// Everyone in the home world within `radius` tiles of (x, y)
+    pub fn near(&self, x: i32, y: i32, radius: u32) -> Vec<PlayerInfo> {
+        self.near_in(None, x, y, radius)
+    }
+
+    /// Everyone in a system (None for the home world) within `radius` tiles of (x, y)
+    fn near_in(&self, system: Option<u64>, x: i32, y: i32, radius: u32) -> Vec<PlayerInfo> {
+        let away = self.away.read().unwrap();
+        let mut near: Vec<PlayerInfo> = self
+            .players
+            .read()
+            .unwrap()
+            .values()
+            .filter(|p| away.get(&p.id).copied() == system && utility::distance((x, y), (p.x, p.y)) <= radius)
+            .cloned()
+            .collect();
+        near.sort_by_key(|p| p.id);
+        near
+    }
+
+    /// Note which system a pilot is flying in: None for the home world
+    pub fn set_system(&self, id: u64, seed: Option<u64>) {
+        let mut away = self.away.write().unwrap();
+        match seed {
+            Some(seed) => away.insert(id, seed),
+            None => away.remove(&id),
+        };
+    }
+
+    /// The system a pilot is flying in; None for the home world, and for unknown sessions
+    pub fn system(&self, id: u64) -> Option<u64> {
+        self.away.read().unwrap().get(&id).copied()
+    }
+
+    /// `near`, less the pilots a nebula hides from (x, y)
+    pub fn sensed(&self, x: i32, y: i32, radius: u32) -> Vec<PlayerInfo> {
+        let in_nebula = self.in_nebula.read().unwrap();
+        let mut sensed = self.near(x, y, radius);
+        sensed.retain(|p| !in_nebula.contains(&p.id) || within_sensor_range((p.x, p.y), (x, y)));
+        sensed
+    }
+
+    /// Note whether a pilot is inside a nebula
+    pub fn set_in_nebula(&self, id: u64, inside: bool) {
+        let mut in_nebula = self.in_nebula.write().unwrap();
+        if inside {
+            in_nebula.insert(id);
+        } else {
+            in_nebula.remove(&id);
+        }
+    }
+
+    /// Whether a nebula hides pilot `id` from sensors at `from`
+    pub fn hidden_from(&self, id: u64, from: (i32, i32)) -> bool {
+        self.in_nebula.read().unwrap().contains(&id) && self.get(id).is_some_and(|p| !within_sensor_range((p.x, p.y), from))
+    }
+
+    /// Everything in the buckets around an entity's (including itself); nothing if it isn't placed
+    pub fn around(&self, entity: EntityId) -> HashSet<EntityId> {
+        let grid = self.grid.lock().unwrap();
+        grid.bucket(entity).map(|bucket| grid.around(bucket)).unwrap_or_default()
+    }
+
+    /// Current info for a session
+    pub fn get(&self, id: u64) -> Option<PlayerInfo> {
+        self.players.read().unwrap().get(&id).cloned()
+    }
+
+    /// Find a player by name, ignoring case
+    pub fn find_by_name(&self, name: &str) -> Option<PlayerInfo> {
+        self.players
+            .read()
+            .unwrap()
+            .values()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
+    /// Remove a session and tell everyone else
+    pub fn leave(&self, id: u64) {
+        if self.players.write().unwrap().remove(&id).is_some() {
+            self.grid.lock().unwrap().remove(EntityId { kind: EntityKind::Player, id });
+            self.in_nebula.write().unwrap().remove(&id);
+            self.away.write().unwrap().remove(&id);
+            self.delta.lock().unwrap().forget(EntityKind::Player, id);
+            self.broadcast(id, ServerMessage::PlayerLeft { id });
+        }
+    }
+
+    /// Everyone online except the given session
+    pub fn others(&self, id: u64) -> Vec<PlayerInfo> {
+        let mut others: Vec<PlayerInfo> = self
+            .players
+            .read()
+            .unwrap()
+            .values()
+            .filter(|p| p.id != id)
+            .cloned()
+            .collect();
+        others.sort_by_key(|p| p.id);
+        others
+    }
+
+    /// `others`, less the pilots flying in a different system from `id`
+    pub fn in_system(&self, id: u64) -> Vec<PlayerInfo> {
+        let system = self.system(id);
+        let mut others = self.others(id);
+        others.retain(|p| self.system(p.id) == system);
+        others
+    }
+
+    /// Number of connected sessions
+    pub fn len(&self) -> usize {
+        self.players.read().unwrap().len()
+    }
+
+    fn broadcast(&self, from: u64, message: ServerMessage) {
+        // Sending only fails when nobody is subscribed, which is fine
+        let _ = self.sender.send(Broadcast { from, to: None, message });
+    }
+}
+
+/// Trim a requested name to something printable, falling back to "Pilot-<id>"
+fn sanitize_name(name: &str, id: u64) -> String {
+    let cleaned: String = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_NAME_LEN)
+        .collect::<String>()
+        .trim()
+        .to_string();
+    if cleaned.is_empty() {
+        format!("Pilot-{}", id)
+    } else {
+        cleaned
+    }
+}
+
+/// Handler for the websocket endpoint
+pub async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>, headers: HeaderMap) -> Response {
+    // A bad token is refused outright rather than quietly played as a guest
+    let account = match auth::bearer_token(&headers) {
+        Some(token) => match state.users.verify(token) {
+            Some(name) => Some(name),
+            None => return AuthError::BadToken.into_response(),
+        },
+        None => None,
+    };
+    ws.on_upgrade(move |socket| handle_socket(socket, state, account))
+}
+
+/// Name a session plays under: the account's when logged in, otherwise Hello's unless an account owns it
+fn session_name(hello_name: String, account: Option<String>, users: &Users) -> String {
+    match account {
+        Some(account) => account,
+        None if users.is_registered(hello_name.trim()) => format!("Guest-{}", hello_name.trim()),
+        None => hello_name,
+    }
+}
+
+#[tracing::instrument(name = "session", skip_all, fields(id = tracing::field::Empty, pilot = tracing::field::Empty))]
+async fn handle_socket(mut socket: WebSocket, state: AppState, account: Option<String>) {
+    let AppState { sessions, users, world, npcs, projectiles, stations, systems, content, events, missions, throttle, .. } = state;
+
+    // The first message must be a Hello
+    let name = loop {
+        match socket.recv().await {
+            Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                Ok(ClientMessage::Hello { name }) => break name,
+                _ => return,
+            },
+            Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+            _ => return,
+        }
+    };
+
+    let (player, mut receiver) = sessions.join(&session_name(name, account.clone(), &users));
+    let id = player.id;
+    tracing::Span::current().record("id", id).record("pilot", player.name.as_str());
+    tracing::info!(guest = account.is_none(), "Joined");
+    let actor = account.clone().map_or(Actor::Session(id), Actor::Account);
+
+    // The number first, so the players read after it are at least that current
+    let seq = sessions.delta_seq();
+    let players = sessions.in_system(id);
+    let mut interest = Interest::default();
+    interest.sent(players.iter().map(|p| EntityId { kind: EntityKind::Player, id: p.id }));
+    let welcome = ServerMessage::Welcome { id, players, seq, unix_secs: utility::unix_time() };
+    if send_message(&mut socket, &welcome).await.is_err() {
+        sessions.leave(id);
+        return;
+    }
+
+    // Show the NPC ships right away instead of at their next move
+    let ships = npcs.snapshot();
+    interest.sent(ships.iter().map(|npc| EntityId { kind: EntityKind::Npc, id: npc.id }));
+    if !ships.is_empty() && send_message(&mut socket, &ServerMessage::Npcs { ships }).await.is_err() {
+        sessions.leave(id);
+        return;
+    }
+
+    // Stations for the map, and which one is home
+    if !stations.is_empty() {
+        let home = account.as_deref().and_then(|account| world.player_home(account));
+        let message = ServerMessage::Stations { stations: stations.list(), home };
+        if send_message(&mut socket, &message).await.is_err() {
+            sessions.leave(id);
+            return;
+        }
+    }
+
+    // What this server's content packs define
+    if !content.packs.is_empty() {
+        let message = ServerMessage::Content { catalog: (*content).clone() };
+        if send_message(&mut socket, &message).await.is_err() {
+            sessions.leave(id);
+            return;
+        }
+    }
+
+    // Meteor showers and ion storms already under way
+    for event in events.active(Instant::now()) {
+        if send_message(&mut socket, &ServerMessage::EventStarted { event }).await.is_err() {
+            sessions.leave(id);
+            return;
+        }
+    }
+
+    // Put returning pilots back where they left off
+    let mut stats = StatsTracker::default();
+    let mut tiles = TileCache::default();
+    // Everyone starts at the spawn point unless they have a saved spot
+    let mut mover = Mover::new(world.seed, SPAWN_POSITION, Instant::now());
+    // The system the pilot is flying in, for move checks
+    let mut course = TileCache::default();
+    // Where the pilot last was in the home world; positions elsewhere aren't saved
+    let mut home_spot = None;
+    if let Some(account) = &account
+        && let Some((x, y)) = world.player_position(account)
+    {
+        home_spot = Some((x, y));
+        sessions.update_position(id, x, y, player.direction);
+        mover.place(world.seed, x, y);
+        sessions.set_in_nebula(id, nebula_at(&world, &mut tiles, x, y).await);
+        stats.moved(&world, Some(account), x, y);
+        if send_message(&mut socket, &ServerMessage::Resume { x, y }).await.is_err() {
+            sessions.leave(id);
+            return;
+        }
+    }
+    let mut last_position_save = Instant::now();
+
+    let mut limiter = ChatRateLimiter::new(Instant::now());
+    let mut rng = DiceRng::for_session(id);
+
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Text(text))) => {
+                        let reply = match serde_json::from_str(&text) {
+                            // Moves over budget are dropped; the next one in budget is checked from the last taken
+                            Ok(ClientMessage::Position { .. }) if throttle.check(&actor, Action::Move, Instant::now()).is_err() => None,
+                            // Only the server's own systems, so a made-up seed never has chunks generated for it
+                            Ok(ClientMessage::Position { seed: Some(seed), .. }) if seed != world.seed && systems.by_seed(seed).is_none() => None,
+                            Ok(ClientMessage::Position { x, y, direction, seed }) => {
+                                let seed = seed.unwrap_or(world.seed);
+                                if course.seed(&world) != seed {
+                                    course = TileCache::for_seed(seed);
+                                }
+                                let passable = |x, y| course.passable(&world, x, y);
+                                match mover.check(seed, (x, y), Instant::now(), passable, |seed, at| systems.jump(seed, at)) {
+                                    Verdict::Accepted => {
+                                        let home = seed == world.seed;
+                                        let in_nebula = nebula_at(&world, &mut course, x, y).await;
+                                        sessions.set_system(id, (!home).then_some(seed));
+                                        sessions.update_position(id, x, y, direction);
+                                        sessions.set_in_nebula(id, in_nebula);
+                                        stats.moved(&world, account.as_deref(), x, y);
+                                        // Surveys are the server's count, from the moves it accepts
+                                        if let Some(account) = &account
+                                            && in_nebula
+                                            && home
+                                        {
+                                            missions.surveyed(account, x, y);
+                                        }
+                                        if home {
+                                            home_spot = Some((x, y));
+                                        }
+                                        if let Some(account) = &account
+                                            && home
+                                            && last_position_save.elapsed() >= POSITION_SAVE_INTERVAL
+                                        {
+                                            world.save_player_position(account, x, y);
+                                            stats.save(&world, Some(account));
+                                            last_position_save = Instant::now();
+                                        }
+                                        None
+                                    }
+                                    // Back to where the ship is as far as everyone else knows
+                                    Verdict::Corrected(x, y) => {
+                                        tracing::debug!(x, y, "Corrected a move");
+                                        Some(ServerMessage::Corrected { x, y })
+                                    }
+                                    Verdict::Refused => None,
+                                }
+                            }
+                            // Mining isn't chatter; it only counts toward the session's own numbers
+                            Ok(ClientMessage::Mined { ore, crystal }) => {
+                                stats.mined(ore, crystal);
+                                None
+                            }
+                            // Guns have their own cooldown, so firing doesn't eat into the chat limit
+                            Ok(ClientMessage::Fire { .. }) if sessions.system(id).is_some() => Some(ServerMessage::CommandFailed {
+                                reason: "Weapons are locked outside the home system".to_string(),
+                            }),
+                            Ok(ClientMessage::Fire { direction }) => {
+                                let ship = sessions.get(id).map_or((0, 0), |p| (p.x, p.y));
+                                match projectiles.fire(id, ship, direction, Instant::now()) {
+                                    Ok(shot) => {
+                                        sessions.show(id, ship, shot);
+                                        None
+                                    }
+                                    Err(reason) => Some(ServerMessage::CommandFailed { reason }),
+                                }
+                            }
+                            // Recovering from a missed delta isn't chatter either
+                            Ok(ClientMessage::Resync) => {
+                                let seq = sessions.delta_seq();
+                                let players = sessions.in_system(id);
+                                let ships = if sessions.system(id).is_none() { npcs.snapshot() } else { Vec::new() };
+                                let players_sent = players.iter().map(|p| EntityId { kind: EntityKind::Player, id: p.id });
+                                interest.reset(players_sent.chain(ships.iter().map(|npc| EntityId { kind: EntityKind::Npc, id: npc.id })));
+                                Some(ServerMessage::Snapshot { seq, players, npcs: ships })
+                            }
+                            Ok(ClientMessage::Hello { .. }) | Err(_) => None,
+                            // Everything else is player-initiated chatter and shares the rate limit
+                            Ok(_) if !limiter.try_acquire(Instant::now()) => Some(ServerMessage::ChatRejected {
+                                reason: "You're sending messages too fast".to_string(),
+                            }),
+                            Ok(ClientMessage::Chat { text }) => relay_chat(&throttle, &actor, &text, |clean| sessions.chat(id, clean)),
+                            Ok(ClientMessage::Say { text, emote }) => {
+                                relay_chat(&throttle, &actor, &text, |clean| sessions.say(id, clean, emote))
+                            }
+                            Ok(ClientMessage::Roll { count, sides }) => match utility::roll(&mut rng, count, sides) {
+                                Ok(rolls) => {
+                                    let name = sessions.get(id).map(|p| p.name).unwrap_or_default();
+                                    let total = rolls.iter().sum();
+                                    sessions.announce(ServerMessage::Roll { id, name, count, sides, rolls, total });
+                                    None
+                                }
+                                Err(reason) => Some(ServerMessage::CommandFailed { reason }),
+                            },
+                            Ok(ClientMessage::Flip) => {
+                                let name = sessions.get(id).map(|p| p.name).unwrap_or_default();
+                                sessions.announce(ServerMessage::Flip { id, name, heads: rng.roll_die(2) == 1 });
+                                None
+                            }
+                            Ok(ClientMessage::Time) => Some(ServerMessage::Time { unix_secs: utility::unix_time() }),
+                            Ok(ClientMessage::Distance { target }) => Some(utility::distance_reply(&sessions, id, target)),
+                            Ok(ClientMessage::SetHome) if sessions.system(id).is_some() => Some(ServerMessage::CommandFailed {
+                                reason: "There are no stations in this system".to_string(),
+                            }),
+                            Ok(ClientMessage::SetHome) => {
+                                let ship = sessions.get(id).map_or((0, 0), |p| (p.x, p.y));
+                                Some(stations.set_home(&world, account.as_deref(), ship))
+                            }
+                            Ok(ClientMessage::Respawn) => {
+                                let home = account.as_deref().and_then(|account| world.player_home(account));
+                                match stations.respawn_point(home.as_deref()).cloned() {
+                                    Some(station) => {
+                                        let direction = sessions.get(id).map_or(Direction::Up, |p| p.direction);
+                                        sessions.set_system(id, None);
+                                        sessions.update_position(id, station.x, station.y, direction);
+                                        home_spot = Some((station.x, station.y));
+                                        mover.place(world.seed, station.x, station.y);
+                                        sessions.set_in_nebula(id, nebula_at(&world, &mut tiles, station.x, station.y).await);
+                                        stats.moved(&world, account.as_deref(), station.x, station.y);
+                                        Some(ServerMessage::Respawned { station: station.name, x: station.x, y: station.y })
+                                    }
+                                    None => Some(ServerMessage::CommandFailed { reason: "There are no stations here".to_string() }),
+                                }
+                            }
+                            Ok(ClientMessage::Stats) => {
+                                let name = sessions.get(id).map(|p| p.name).unwrap_or_default();
+                                Some(stats.report(&world, account.as_deref(), name))
+                            }
+                            Ok(ClientMessage::Livery { ship }) => {
+                                sessions.repaint(id, ship.sanitized());
+                                None
+                            }
+                        };
+                        if let Some(reply) = reply
+                            && send_message(&mut socket, &reply).await.is_err()
+                        {
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+            outgoing = receiver.recv() => {
+                match outgoing {
+                    Ok(broadcast) if broadcast.is_for(id) => {
+                        let kicked = matches!(broadcast.message, ServerMessage::Kicked { .. });
+                        match &broadcast.message {
+                            // Mined and shot-away tiles open up for move checks
+                            ServerMessage::WorldDelta { tiles: changes, .. } if course.seed(&world) == world.seed => {
+                                for change in changes {
+                                    course.forget(change.x, change.y);
+                                }
+                            }
+                            ServerMessage::PlayerJoined { player } => interest.sent([EntityId { kind: EntityKind::Player, id: player.id }]),
+                            ServerMessage::PlayerLeft { id } => interest.forget(EntityId { kind: EntityKind::Player, id: *id }),
+                            _ => {}
+                        }
+                        if send_all(&mut socket, interest.filter(&sessions, &npcs, id, broadcast.message)).await.is_err() || kicked {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    // Fell behind; the client spots the gap in delta numbers and asks to resync
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+
+    if let Some(account) = &account
+        && let Some((x, y)) = home_spot
+    {
+        world.save_player_position(account, x, y);
+    }
+    stats.save(&world, account.as_deref());
+    projectiles.forget(id);
+    sessions.leave(id);
+    tracing::info!("Left");
+}
+
+/// Clean up a chat line and spend the pilot's chat budget on it, then hand it to `relay`;
+/// the rejection to send back when it can't go out
+fn relay_chat(throttle: &Throttle, actor: &Actor, text: &str, relay: impl FnOnce(&str)) -> Option<ServerMessage> {
+    let clean = match chat::validate_message(text) {
+        Ok(clean) => clean,
+        Err(reason) => return Some(ServerMessage::ChatRejected { reason }),
+    };
+    // The per-minute budget follows an account across sessions
+    match throttle.check(actor, Action::Chat, Instant::now()) {
+        Ok(()) => {
+            relay(&clean);
+            None
+        }
+        Err(wait) => Some(ServerMessage::ChatRejected {
+            reason: format!("Chat limit reached; try again in {}s", wait.as_secs_f32().ceil()),
+        }),
+    }
+}
+
+/// Whether (x, y) is nebula, read through `cache` on the blocking pool since it may generate the chunk
+async fn nebula_at(world: &Arc<World>, cache: &mut TileCache, x: i32, y: i32) -> bool {
+    let (world, mut taken) = (world.clone(), std::mem::take(cache));
+    let lookup = tokio::task::spawn_blocking(move || {
+        let inside = taken.tile(&world, x, y) == Tile::Nebula;
+        (taken, inside)
+    });
+    match lookup.await {
+        Ok((taken, inside)) => {
+            *cache = taken;
+            inside
+        }
+        Err(_) => false,
+    }
+}
+
+async fn send_message(socket: &mut WebSocket, message: &ServerMessage) -> Result<(), axum::Error> {
+    let json = serde_json::to_string(message).expect("ServerMessage always serializes");
+    socket.send(Message::text(json)).await
+}
+
+async fn send_all(socket: &mut WebSocket, messages: Vec<ServerMessage>) -> Result<(), axum::Error> {
+    for message in &messages {
+        send_message(socket, message).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interest::BUCKET_SIZE;
+    use exospace_core::NEBULA_SENSOR_RANGE;
+
+    #[test]
+    fn test_join_assigns_unique_ids() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        let (b, _) = registry.join("Bravo");
+        assert_ne!(a.id, b.id);
+        assert_eq!(registry.len(), 2);
+    }
+
+    #[test]
+    fn test_join_broadcasts_to_existing_sessions() {
+        let registry = SessionRegistry::default();
+        let (a, mut rx) = registry.join("Alpha");
+        let (b, _) = registry.join("Bravo");
+
+        // Alpha's receiver sees its own join first, then Bravo's
+        let first = rx.try_recv().unwrap();
+        assert_eq!(first.from, a.id);
+        let second = rx.try_recv().unwrap();
+        assert_eq!(second.message, ServerMessage::PlayerJoined { player: b });
+    }
+
+    #[test]
+    fn test_repaint_stores_and_broadcasts() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        let (b, mut rx) = registry.join("Bravo");
+        let _own_join = rx.try_recv();
+
+        let ship = ShipLivery { callsign: Some("ACE".to_string()), ..ShipLivery::default() };
+        registry.repaint(a.id, ship.clone());
+        assert_eq!(registry.others(b.id)[0].ship, ship, "Pilots seen later get the livery with the rest");
+        let msg = rx.try_recv().unwrap();
+        assert_eq!(msg.message, ServerMessage::Livery { id: a.id, ship: ship.clone() });
+        assert!(msg.is_for(b.id) && !msg.is_for(a.id), "Everyone but the painter hears of it");
+
+        registry.repaint(a.id, ship);
+        assert!(rx.try_recv().is_err(), "Resending the same livery is quiet");
+        registry.repaint(99, ShipLivery::default());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_update_position_stores_and_broadcasts() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        let (b, mut rx) = registry.join("Bravo");
+        let _own_join = rx.try_recv();
+
+        registry.update_position(a.id, 10, 20, Direction::Left);
+
+        let others = registry.others(b.id);
+        assert_eq!(others.len(), 1);
+        assert_eq!((others[0].x, others[0].y, others[0].direction), (10, 20, Direction::Left));
+        assert!(rx.try_recv().is_err(), "Moves wait for the next delta");
+
+        registry.flush_delta();
+        let msg = rx.try_recv().unwrap();
+        let moved = vec![EntityMove { kind: EntityKind::Player, id: a.id, x: 10, y: 20, direction: Direction::Left }];
+        assert_eq!(msg.message, ServerMessage::WorldDelta { seq: 1, moved, tiles: Vec::new() });
+        assert_eq!(registry.delta_seq(), 1);
+    }
+
+    #[test]
+    fn test_update_unknown_session_ignored() {
+        let registry = SessionRegistry::default();
+        let (_, mut rx) = registry.join("Alpha");
+        let _ = rx.try_recv();
+
+        registry.update_position(999, 1, 1, Direction::Up);
+        registry.flush_delta();
+        assert!(rx.try_recv().is_err(), "No broadcast for unknown session");
+    }
+
+    #[test]
+    fn test_leave_removes_and_broadcasts() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        let (_, mut rx) = registry.join("Bravo");
+        let _own_join = rx.try_recv();
+
+        registry.leave(a.id);
+        assert_eq!(registry.len(), 1);
+        assert_eq!(rx.try_recv().unwrap().message, ServerMessage::PlayerLeft { id: a.id });
+
+        // Leaving twice is a no-op
+        registry.leave(a.id);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_chat_broadcasts_with_name() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        let (_, mut rx) = registry.join("Bravo");
+        let _own_join = rx.try_recv();
+
+        registry.chat(a.id, "hello");
+        let msg = rx.try_recv().unwrap();
+        assert_eq!(msg.from, a.id);
+        assert_eq!(msg.message, ServerMessage::Chat { id: a.id, name: "Alpha".to_string(), text: "hello".to_string() });
+
+        registry.chat(999, "ghost");
+        assert!(rx.try_recv().is_err(), "No broadcast for unknown session");
+    }
+
+    #[test]
+    fn test_say_reaches_pilots_nearby() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        let (b, mut near_rx) = registry.join("Bravo");
+        let (c, _) = registry.join("Charlie");
+        registry.update_position(b.id, 5, 0, Direction::Up);
+        registry.update_position(c.id, SAY_RANGE as i32 + 10, 0, Direction::Up);
+        while near_rx.try_recv().is_ok() {}
+
+        registry.say(a.id, "waves", true);
+        let msg = near_rx.try_recv().unwrap();
+        assert_eq!(msg.message, ServerMessage::Say { id: a.id, name: "Alpha".to_string(), text: "waves".to_string(), emote: true });
+        assert!(msg.is_for(b.id), "Pilots in range hear it");
+        assert!(!msg.is_for(c.id), "Pilots out of range don't");
+        assert!(near_rx.try_recv().is_err(), "One copy for each pilot in range, none for the speaker");
+
+        // A pilot in a nebula is only heard up close
+        registry.set_in_nebula(a.id, true);
+        registry.update_position(b.id, NEBULA_SENSOR_RANGE + 2, 0, Direction::Up);
+        registry.say(a.id, "hides", false);
+        assert!(near_rx.try_recv().is_err(), "Nobody sees the ship to see the bubble");
+
+        registry.say(999, "ghost", false);
+        assert!(near_rx.try_recv().is_err(), "Nothing for unknown sessions");
+    }
+
+    #[test]
+    fn test_announce_reaches_everyone() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        let (_, mut rx) = registry.join("Bravo");
+        let _own_join = rx.try_recv();
+
+        registry.announce(ServerMessage::Flip { id: a.id, name: "Alpha".to_string(), heads: true });
+        let msg = rx.try_recv().unwrap();
+        assert_eq!(msg.from, FROM_SERVER, "Announcements aren't tagged with any session");
+        assert_ne!(msg.from, a.id, "So even the originator receives them");
+    }
+
+    #[test]
+    fn test_send_to_targets_one_session() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        let (b, mut rx) = registry.join("Bravo");
+        let _own_join = rx.try_recv();
+
+        registry.send_to(a.id, ServerMessage::Time { unix_secs: 1 });
+        let msg = rx.try_recv().unwrap();
+        assert!(msg.is_for(a.id), "Target session delivers it");
+        assert!(!msg.is_for(b.id), "Everyone else skips it");
+    }
+
+    #[test]
+    fn test_broadcast_is_for_everyone_but_sender() {
+        let message = ServerMessage::PlayerLeft { id: 1 };
+        let broadcast = Broadcast { from: 1, to: None, message };
+        assert!(!broadcast.is_for(1));
+        assert!(broadcast.is_for(2));
+    }
+
+    #[test]
+    fn test_near_filters_by_distance() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        let (b, _) = registry.join("Bravo");
+        registry.update_position(a.id, 3, 4, Direction::Up);
+        registry.update_position(b.id, 100, 0, Direction::Up);
+
+        let ids: Vec<u64> = registry.near(0, 0, 5).iter().map(|p| p.id).collect();
+        assert_eq!(ids, vec![a.id], "Radius is inclusive");
+        assert_eq!(registry.near(0, 0, 100).len(), 2);
+    }
+
+    #[test]
+    fn test_pilots_meet_only_in_their_own_system() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        let (b, mut rx) = registry.join("Bravo");
+        let (c, _) = registry.join("Charlie");
+        registry.update_position(b.id, 1, 0, Direction::Up);
+        registry.update_position(c.id, 2, 0, Direction::Up);
+        registry.set_system(a.id, Some(7));
+        registry.set_system(c.id, Some(7));
+        while rx.try_recv().is_ok() {}
+
+        assert_eq!(registry.system(a.id), Some(7));
+        let ids: Vec<u64> = registry.near(0, 0, 5).iter().map(|p| p.id).collect();
+        assert_eq!(ids, vec![b.id], "Only home pilots are near the home world's (0, 0)");
+        registry.say(a.id, "hello", false);
+        let msg = rx.try_recv().unwrap();
+        assert!(msg.is_for(c.id) && !msg.is_for(b.id), "Local chat stays in the system");
+
+        registry.set_system(a.id, None);
+        assert_eq!(registry.near(0, 0, 5).len(), 2, "Back home");
+        registry.leave(c.id);
+        assert_eq!(registry.system(c.id), None);
+    }
+
+    #[test]
+    fn test_nebulae_hide_pilots_from_afar() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        registry.update_position(a.id, 10, 0, Direction::Up);
+        registry.set_in_nebula(a.id, true);
+
+        assert!(registry.hidden_from(a.id, (0, 0)));
+        assert!(!registry.hidden_from(a.id, (10 - NEBULA_SENSOR_RANGE, 0)), "Close by, sensors still reach in");
+        assert!(registry.sensed(0, 0, 20).is_empty());
+        assert_eq!(registry.sensed(5, 0, 20).len(), 1);
+
+        registry.set_in_nebula(a.id, false);
+        assert!(!registry.hidden_from(a.id, (0, 0)), "Out in the open again");
+        assert_eq!(registry.sensed(0, 0, 20).len(), 1);
+    }
+
+    #[test]
+    fn test_show_reaches_the_area_and_skips_sessions_that_cant_sense_the_pilot() {
+        let registry = SessionRegistry::default();
+        let (a, mut rx) = registry.join("Alpha");
+        let (near, _) = registry.join("Near");
+        let (far, _) = registry.join("Far");
+        let (distant, _) = registry.join("Distant");
+        registry.update_position(a.id, 10, 0, Direction::Up);
+        registry.update_position(near.id, 10 - NEBULA_SENSOR_RANGE, 0, Direction::Up);
+        registry.update_position(distant.id, 10 + BUCKET_SIZE * 3, 0, Direction::Up);
+        while rx.try_recv().is_ok() {}
+        let shot = ServerMessage::Shot { id: 1, owner: a.id, x: 10, y: 0, direction: Direction::Up };
+        let mut heard = || {
+            let mut heard = Vec::new();
+            while let Ok(broadcast) = rx.try_recv() {
+                assert_eq!(broadcast.message, shot);
+                heard.extend(broadcast.to);
+            }
+            heard.sort();
+            heard
+        };
+
+        registry.show(a.id, (10, 0), shot.clone());
+        assert_eq!(heard(), vec![a.id, near.id, far.id], "{} is out of the area", distant.name);
+        registry.set_in_nebula(a.id, true);
+        registry.show(a.id, (10, 0), shot.clone());
+        assert_eq!(heard(), vec![a.id, near.id], "{} is too far to see into the nebula", far.name);
+    }
+
+    #[test]
+    fn test_find_by_name() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        assert_eq!(registry.find_by_name("ALPHA").map(|p| p.id), Some(a.id));
+        assert!(registry.find_by_name("Bravo").is_none());
+        assert_eq!(registry.get(a.id).map(|p| p.name), Some("Alpha".to_string()));
+    }
+
+    #[test]
+    fn test_session_name_reserves_accounts() {
+        let users = Users::with_rounds(10);
+        let credentials = exospace_core::Credentials { name: "Ace".to_string(), password: "hunter22".to_string() };
+        users.register(&credentials).unwrap();
+        assert_eq!(session_name("Whoever".to_string(), Some("Ace".to_string()), &users), "Ace", "Account name wins");
+        assert_eq!(session_name("ace".to_string(), None, &users), "Guest-ace", "Guests can't pose as an account");
+        assert_eq!(session_name("Bravo".to_string(), None, &users), "Bravo");
+    }
+
+    #[test]
+    fn test_others_excludes_self() {
+        let registry = SessionRegistry::default();
+        let (a, _) = registry.join("Alpha");
+        let (b, _) = registry.join("Bravo");
+        let (c, _) = registry.join("Charlie");
+
+        let ids: Vec<u64> = registry.others(b.id).iter().map(|p| p.id).collect();
+        assert_eq!(ids, vec![a.id, c.id]);
+    }
+
+    #[test]
+    fn test_sanitize_name() {
+        assert_eq!(sanitize_name("  Ace  ", 1), "Ace");
+        assert_eq!(sanitize_name("", 5), "Pilot-5");
+        assert_eq!(sanitize_name("\u{7}\n", 6), "Pilot-6");
+        assert_eq!(sanitize_name(&"x".repeat(100), 1).len(), MAX_NAME_LEN);
+    }
+}
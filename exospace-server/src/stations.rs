@@ -0,0 +1,144 @@
+//! Stations: places to dock, call home and respawn at.
+//!
+//! A small built-in set sits at the busy points of interest, each moved onto
+//! the nearest open tile when the server starts. A ship is docked while it
+//! is within `DOCKING_RANGE` tiles of a station. Logged-in pilots can make
+//! the station they're docked at their home (`SetHome`); `Respawn` puts a
+//! pilot back at their home, or at Haven for guests and anyone who hasn't
+//! picked one.
+
+use exospace_core::{SPAWN_POSITION, ServerMessage, StationInfo, station_id};
+
+use crate::npc::TileCache;
+use crate::world::World;
+
+/// How close, in tiles along either axis, a ship must be to count as docked
+pub const DOCKING_RANGE: u32 = 3;
+
+/// Where everyone respawns until they pick a home
+pub const DEFAULT_HOME: &str = "Haven Station";
+
+/// Every station, in the order clients list them
+#[derive(Default)]
+pub struct Stations {
+    stations: Vec<StationInfo>,
+}
+
+impl Stations {
+    /// The built-in stations, each placed on the nearest open tile
+    pub fn place(world: &World) -> Self {
+        let mut tiles = TileCache::default();
+        let mut station = |name: &str, spot: (i32, i32)| {
+            let (x, y) = tiles.nearest_open(world, spot);
+            StationInfo { name: name.to_string(), x, y }
+        };
+        Stations {
+            stations: vec![
+                station(DEFAULT_HOME, SPAWN_POSITION),
+                station("Frontier Relay", (160, -96)),
+                station("Drift Co-op Depot", (-200, 140)),
+            ],
+        }
+    }
+
+    pub fn list(&self) -> Vec<StationInfo> {
+        self.stations.clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stations.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StationInfo> {
+        self.stations.iter().find(|station| station.name == name)
+    }
+
+    /// A station by its `station_id`, as in `/station/{id}` routes
+    pub fn by_id(&self, id: &str) -> Option<&StationInfo> {
+        self.stations.iter().find(|station| station_id(&station.name) == id)
+    }
+
+    /// The station a ship at (x, y) is docked at, if any; the closest one wins
+    pub fn docked_at(&self, x: i32, y: i32) -> Option<&StationInfo> {
+        self.stations
+            .iter()
+            .map(|station| (station.x.abs_diff(x).max(station.y.abs_diff(y)), station))
+            .filter(|(distance, _)| *distance <= DOCKING_RANGE)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, station)| station)
+    }
+
+    /// Reply to `SetHome` from a pilot (None for guests) whose ship is at `ship`
+    pub fn set_home(&self, world: &World, account: Option<&str>, ship: (i32, i32)) -> ServerMessage {
+        let failed = |reason: String| ServerMessage::CommandFailed { reason };
+        let Some(account) = account else {
+            return failed("Log in to set a home station".to_string());
+        };
+        let Some(station) = self.docked_at(ship.0, ship.1) else {
+            return failed(format!("Dock at a station first (fly within {} tiles of one)", DOCKING_RANGE));
+        };
+        match world.set_player_home(account, &station.name) {
+            Ok(()) => ServerMessage::HomeSet { station: station.name.clone() },
+            Err(e) => {
+                tracing::warn!("Failed to save home for {}: {}", account, e);
+                failed("Couldn't save your home station".to_string())
+            }
+        }
+    }
+
+    /// Where a pilot respawns: their home if it still exists, otherwise Haven
+    pub fn respawn_point(&self, home: Option<&str>) -> Option<&StationInfo> {
+        home.and_then(|home| self.get(home)).or_else(|| self.get(DEFAULT_HOME))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Station Tests ====================
+
+    #[test]
+    fn test_stations_sit_on_open_tiles() {
+        let world = World::default();
+        let stations = Stations::place(&world);
+        assert_eq!(stations.list().len(), 3);
+        for station in stations.list() {
+            let mut tiles = TileCache::default();
+            assert_eq!(tiles.nearest_open(&world, (station.x, station.y)), (station.x, station.y), "{} is in rock", station.name);
+        }
+        assert_eq!(stations.list()[0].name, DEFAULT_HOME, "Haven comes first");
+    }
+
+    #[test]
+    fn test_docking_range() {
+        let stations = Stations::place(&World::default());
+        let haven = stations.get(DEFAULT_HOME).unwrap().clone();
+        let range = DOCKING_RANGE as i32;
+        assert_eq!(stations.docked_at(haven.x + range, haven.y - range), Some(&haven));
+        assert_eq!(stations.docked_at(haven.x + range + 1, haven.y), None, "Too far out to dock");
+    }
+
+    #[test]
+    fn test_set_home_needs_account_and_docking() {
+        let world = World::default();
+        let stations = Stations::place(&world);
+        let relay = stations.get("Frontier Relay").unwrap().clone();
+        let docked = (relay.x + 1, relay.y);
+        assert!(matches!(stations.set_home(&world, None, docked), ServerMessage::CommandFailed { reason } if reason.contains("Log in")));
+        assert!(matches!(stations.set_home(&world, Some("Ace"), (relay.x + 10, relay.y)), ServerMessage::CommandFailed { reason } if reason.contains("Dock")));
+        assert_eq!(world.player_home("Ace"), None);
+
+        assert_eq!(stations.set_home(&world, Some("Ace"), docked), ServerMessage::HomeSet { station: relay.name.clone() });
+        assert_eq!(world.player_home("ace"), Some(relay.name));
+    }
+
+    #[test]
+    fn test_respawn_point_falls_back_to_haven() {
+        let stations = Stations::place(&World::default());
+        assert_eq!(stations.respawn_point(Some("Frontier Relay")).unwrap().name, "Frontier Relay");
+        assert_eq!(stations.respawn_point(Some("Demolished Dock")).unwrap().name, DEFAULT_HOME);
+        assert_eq!(stations.respawn_point(None).unwrap().name, DEFAULT_HOME);
+        assert!(Stations::default().respawn_point(None).is_none(), "No stations, nowhere to respawn");
+    }
+}
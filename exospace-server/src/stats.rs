@@ -0,0 +1,153 @@
+//! Per-session pilot statistics.
+//!
+//! A `StatsTracker` follows one session: distance from its position updates,
+//! mining from the client's `Mined` reports, and sectors from the chunks it
+//! flies through. Logged-in pilots have their totals added to the world
+//! store as they fly and when they leave; a sector counts as explored the
+//! first time the account ever enters it. Guests only get session numbers.
+//! `Mined` reports are the client's word, so they only count toward the
+//! session's numbers; an account's stored mining totals come from the tiles
+//! the server saw it break (`/tile/update`, see `mined()`).
+
+use exospace_core::{PilotStats, ServerMessage, Tile, chunk_coords};
+use std::collections::HashSet;
+
+use crate::world::World;
+
+/// Position jumps longer than this along either axis are teleports and add no distance
+pub const MAX_STEP: u64 = 16;
+
+/// Most of one resource a single `Mined` report may claim; mining a tile yields far less
+pub const MAX_MINED_PER_REPORT: u32 = 10;
+
+/// What mining out a resource tile adds to a pilot's totals; None for tiles that yield nothing
+pub fn mined(tile: Tile) -> Option<PilotStats> {
+    match tile.mining_yield()? {
+        ("ore", amount) => Some(PilotStats { ore_mined: amount.into(), ..PilotStats::default() }),
+        (_, amount) => Some(PilotStats { crystal_mined: amount.into(), ..PilotStats::default() }),
+    }
+}
+
+#[derive(Default)]
+pub struct StatsTracker {
+    /// Everything done this session
+    pub session: PilotStats,
+    /// Distance not yet added to the store
+    unsaved: PilotStats,
+    last_position: Option<(i32, i32)>,
+    /// Chunks already entered this session, so the store is asked once per chunk
+    visited: HashSet<(i32, i32)>,
+}
+
+impl StatsTracker {
+    /// Count a position update
+    pub fn moved(&mut self, world: &World, account: Option<&str>, x: i32, y: i32) {
+        if let Some((last_x, last_y)) = self.last_position.replace((x, y)) {
+            let step = u64::from(x.abs_diff(last_x).max(y.abs_diff(last_y)));
+            if step <= MAX_STEP {
+                self.session.distance += step;
+                self.unsaved.distance += step;
+            }
+        }
+        let (cx, cy) = chunk_coords(x, y);
+        if self.visited.insert((cx, cy)) && account.is_none_or(|account| world.visit_sector(account, cx, cy)) {
+            self.session.sectors_explored += 1;
+        }
+    }
+
+    /// Count a mining report toward this session, clamped to what one tile could plausibly give
+    pub fn mined(&mut self, ore: u32, crystal: u32) {
+        self.session.ore_mined += u64::from(ore.min(MAX_MINED_PER_REPORT));
+        self.session.crystal_mined += u64::from(crystal.min(MAX_MINED_PER_REPORT));
+    }
+
+    /// Add unsaved totals to a logged-in pilot's stored ones
+    pub fn save(&mut self, world: &World, account: Option<&str>) {
+        if let Some(account) = account
+            && self.unsaved != PilotStats::default()
+        {
+            world.add_player_stats(account, std::mem::take(&mut self.unsaved));
+        }
+    }
+
+    /// Reply to `Stats`
+    pub fn report(&self, world: &World, account: Option<&str>, name: String) -> ServerMessage {
+        let lifetime = account.map(|account| world.player_stats(account).plus(self.unsaved));
+        ServerMessage::Stats { name, lifetime, session: self.session }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::CHUNK_SIZE;
+
+    fn lifetime(tracker: &StatsTracker, world: &World, account: Option<&str>) -> Option<PilotStats> {
+        match tracker.report(world, account, "Ace".to_string()) {
+            ServerMessage::Stats { lifetime, .. } => lifetime,
+            other => panic!("Expected Stats, got {:?}", other),
+        }
+    }
+
+    // ==================== Tracking Tests ====================
+
+    #[test]
+    fn test_distance_skips_teleports() {
+        let world = World::default();
+        let mut tracker = StatsTracker::default();
+        tracker.moved(&world, None, 0, 0);
+        tracker.moved(&world, None, 1, 1);
+        tracker.moved(&world, None, 4, 2);
+        assert_eq!(tracker.session.distance, 4, "Steps are measured along the longer axis");
+        tracker.moved(&world, None, 500, 2);
+        tracker.moved(&world, None, 500, 0);
+        assert_eq!(tracker.session.distance, 6, "Jumps don't count");
+    }
+
+    #[test]
+    fn test_sectors_are_new_once_per_account() {
+        let world = World::default();
+        let mut first = StatsTracker::default();
+        first.moved(&world, Some("Ace"), 0, 0);
+        first.moved(&world, Some("Ace"), CHUNK_SIZE, 0);
+        first.moved(&world, Some("Ace"), 0, 0);
+        assert_eq!(first.session.sectors_explored, 2);
+
+        let mut second = StatsTracker::default();
+        second.moved(&world, Some("Ace"), 1, 1);
+        second.moved(&world, Some("Ace"), -1, 1);
+        assert_eq!(second.session.sectors_explored, 1, "Only the chunk Ace had never entered is new");
+        let mut guest = StatsTracker::default();
+        guest.moved(&world, None, 1, 1);
+        assert_eq!(guest.session.sectors_explored, 1, "Guests count what they saw this session");
+    }
+
+    #[test]
+    fn test_lifetime_includes_unsaved_and_saved_totals() {
+        let world = World::default();
+        let mut tracker = StatsTracker::default();
+        tracker.moved(&world, Some("Ace"), 0, 0);
+        tracker.moved(&world, Some("Ace"), 3, 0);
+        let before = lifetime(&tracker, &world, Some("Ace")).unwrap();
+        assert_eq!(before, PilotStats { distance: 3, sectors_explored: 1, ..PilotStats::default() });
+
+        tracker.save(&world, Some("Ace"));
+        assert_eq!(lifetime(&tracker, &world, Some("Ace")), Some(before), "Saving doesn't double count");
+        assert_eq!(world.player_stats("Ace").distance, 3);
+        assert_eq!(lifetime(&tracker, &world, None), None, "Guests have no lifetime stats");
+    }
+
+    #[test]
+    fn test_mining_reports_only_count_for_the_session() {
+        let world = World::default();
+        let mut tracker = StatsTracker::default();
+        tracker.mined(2, 99);
+        assert_eq!((tracker.session.ore_mined, tracker.session.crystal_mined), (2, MAX_MINED_PER_REPORT as u64));
+        tracker.save(&world, Some("Ace"));
+        assert_eq!(world.player_stats("Ace"), PilotStats::default(), "The client's word isn't stored");
+
+        assert_eq!(mined(Tile::OreAsteroid), Some(PilotStats { ore_mined: 3, ..PilotStats::default() }));
+        assert_eq!(mined(Tile::CrystalField), Some(PilotStats { crystal_mined: 1, ..PilotStats::default() }));
+        assert_eq!(mined(Tile::Asteroid), None);
+    }
+}
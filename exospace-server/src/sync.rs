@@ -0,0 +1,130 @@
+//! Sequenced world deltas.
+//!
+//! Player moves, NPC moves and tile edits aren't sent the moment they happen.
+//! They collect in a `DeltaBuffer` and go out once per tick as a numbered
+//! `ServerMessage::WorldDelta`, holding the latest position of everything that
+//! moved and every tile that changed. `Welcome` tells a joining client which
+//! delta its snapshot is current as of. A client that sees a gap in the
+//! numbers (its session fell behind the broadcast channel, say) sends `Resync`
+//! and gets a fresh `Snapshot` to carry on from.
+
+use exospace_core::{EntityKind, EntityMove, ServerMessage, Tile, TileChange};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::sessions::SessionRegistry;
+use crate::tick::System;
+
+/// Changes since the last delta went out
+#[derive(Default)]
+pub struct DeltaBuffer {
+    /// Number of the last delta taken
+    seq: u64,
+    moved: HashMap<(EntityKind, u64), EntityMove>,
+    tiles: HashMap<(i32, i32), Tile>,
+}
+
+impl DeltaBuffer {
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Note a move; a later one for the same entity replaces it
+    pub fn moved(&mut self, entity: EntityMove) {
+        self.moved.insert((entity.kind, entity.id), entity);
+    }
+
+    pub fn tile_changed(&mut self, x: i32, y: i32, tile: Tile) {
+        self.tiles.insert((x, y), tile);
+    }
+
+    /// Drop a pending move for something that's gone
+    pub fn forget(&mut self, kind: EntityKind, id: u64) {
+        self.moved.remove(&(kind, id));
+    }
+
+    /// The next delta, or None when nothing changed
+    pub fn take(&mut self) -> Option<ServerMessage> {
+        if self.moved.is_empty() && self.tiles.is_empty() {
+            return None;
+        }
+        self.seq += 1;
+        let mut moved: Vec<EntityMove> = self.moved.drain().map(|(_, entity)| entity).collect();
+        moved.sort_by_key(|entity| (entity.kind, entity.id));
+        let mut tiles: Vec<TileChange> = self.tiles.drain().map(|((x, y), tile)| TileChange { x, y, tile }).collect();
+        tiles.sort_by_key(|change| (change.y, change.x));
+        Some(ServerMessage::WorldDelta { seq: self.seq, moved, tiles })
+    }
+}
+
+/// Sends everyone the tick's delta
+pub struct DeltaBroadcast {
+    sessions: Arc<SessionRegistry>,
+}
+
+impl DeltaBroadcast {
+    pub fn new(sessions: Arc<SessionRegistry>) -> Self {
+        DeltaBroadcast { sessions }
+    }
+}
+
+impl System for DeltaBroadcast {
+    fn name(&self) -> &'static str {
+        "world_delta"
+    }
+
+    /// Critical: skipping it would freeze everyone's view of the world
+    fn critical(&self) -> bool {
+        true
+    }
+
+    fn tick(&mut self, _now: Instant) {
+        self.sessions.flush_delta();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::Direction;
+
+    fn player_move(id: u64, x: i32) -> EntityMove {
+        EntityMove { kind: EntityKind::Player, id, x, y: 0, direction: Direction::Right }
+    }
+
+    // ==================== Buffer Tests ====================
+
+    #[test]
+    fn test_take_coalesces_moves_and_numbers_deltas() {
+        let mut buffer = DeltaBuffer::default();
+        assert_eq!(buffer.take(), None, "Nothing changed, nothing to send");
+        assert_eq!(buffer.seq(), 0);
+
+        buffer.moved(player_move(1, 1));
+        buffer.moved(player_move(1, 2));
+        buffer.moved(EntityMove { kind: EntityKind::Npc, id: 1, x: 7, y: 7, direction: Direction::Up });
+        buffer.tile_changed(3, 4, Tile::Wall);
+        buffer.tile_changed(3, 4, Tile::Floor);
+        let Some(ServerMessage::WorldDelta { seq, moved, tiles }) = buffer.take() else {
+            panic!("Expected a delta");
+        };
+        assert_eq!(seq, 1);
+        assert_eq!(moved.len(), 2, "Player 1 and NPC 1 are different entities");
+        assert_eq!(moved[0], player_move(1, 2), "Only the latest move is sent");
+        assert_eq!(tiles, vec![TileChange { x: 3, y: 4, tile: Tile::Floor }]);
+
+        buffer.moved(player_move(2, 5));
+        assert!(matches!(buffer.take(), Some(ServerMessage::WorldDelta { seq: 2, .. })));
+        assert_eq!(buffer.take(), None, "Taking empties the buffer");
+    }
+
+    #[test]
+    fn test_forget_drops_pending_move() {
+        let mut buffer = DeltaBuffer::default();
+        buffer.moved(player_move(1, 1));
+        buffer.forget(EntityKind::Player, 1);
+        assert_eq!(buffer.take(), None);
+        assert_eq!(buffer.seq(), 0, "No delta, no new number");
+    }
+}
@@ -0,0 +1,183 @@
+//! Star systems and the jump gates between them.
+//!
+//! The universe is a handful of named systems, each its own position-hashed
+//! world (`ChunkGenerator`) with its own seed. The home system's seed is the
+//! world's, so it's the one with stored edits, stations and NPC ships. Gates
+//! come in pairs: every gate has one back from the system it leads to, and a
+//! jump arrives on that one. `GET /systems` lists the systems and their
+//! gates; `GET /system/{id}/map` serves a system's chunks, gates included as
+//! points of interest (as does `/map/chunk` for a system's seed).
+
+use axum::{
+    Json,
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use exospace_core::{GateInfo, Poi, PoiKind, SystemInfo, chunk_coords, hash_position};
+use serde::Deserialize;
+
+use crate::AppState;
+use crate::chunks::{ChunkGenerator, chunk_response};
+use crate::error::{ApiError, ApiPath, ApiQuery};
+use crate::npc::nearest_open;
+
+/// Keeps system seeds independent of the other uses of the world seed
+const SYSTEM_SALT: u32 = 0x5157_E3A5;
+
+/// A system's gates, each as (the system it leads to, the spot it's placed near)
+type Gates = &'static [(&'static str, (i32, i32))];
+
+/// Every system, home first
+const CHART: [(&str, Gates); 4] = [
+    ("Haven", &[("Vega", (48, -24)), ("Corvid", (-56, 32))]),
+    ("Vega", &[("Haven", (40, 20)), ("Tarsus", (-60, -30))]),
+    ("Tarsus", &[("Vega", (30, 30))]),
+    ("Corvid", &[("Haven", (-40, -20))]),
+];
+
+/// Query parameters for `GET /system/{id}/map`
+#[derive(Deserialize)]
+pub struct SystemChunkQuery {
+    cx: i32,
+    cy: i32,
+    /// "bin" for the compact binary encoding; JSON otherwise
+    #[serde(default)]
+    format: Option<String>,
+}
+
+/// The systems, in the order clients list them
+#[derive(Default)]
+pub struct Systems {
+    systems: Vec<SystemInfo>,
+}
+
+impl Systems {
+    /// The built-in systems for a world seed, each gate moved onto the nearest open tile
+    pub fn chart(world_seed: u64) -> Self {
+        let systems = CHART
+            .iter()
+            .enumerate()
+            .map(|(i, &(name, gates))| {
+                let seed = if i == 0 { world_seed } else { world_seed.wrapping_add(hash_position(i as i32, 0, SYSTEM_SALT) as u64) };
+                let generator = ChunkGenerator::new(seed);
+                let gates = gates
+                    .iter()
+                    .map(|&(to, spot)| {
+                        let (x, y) = nearest_open(spot, |x, y| generator.tile_at(x, y).is_passable());
+                        GateInfo { x, y, to: to.to_lowercase() }
+                    })
+                    .collect();
+                SystemInfo { id: name.to_lowercase(), name: name.to_string(), seed, gates }
+            })
+            .collect();
+        Systems { systems }
+    }
+
+    pub fn list(&self) -> Vec<SystemInfo> {
+        self.systems.clone()
+    }
+
+    /// A system by its id, in any case
+    pub fn get(&self, id: &str) -> Option<&SystemInfo> {
+        self.systems.iter().find(|system| system.id.eq_ignore_ascii_case(id))
+    }
+
+    /// The system with this seed
+    pub fn by_seed(&self, seed: u64) -> Option<&SystemInfo> {
+        self.systems.iter().find(|system| system.seed == seed)
+    }
+
+    /// Where a jump from (x, y) in the system with `seed` comes out: the far system's seed and its gate back;
+    /// None off a gate
+    pub fn jump(&self, seed: u64, (x, y): (i32, i32)) -> Option<(u64, (i32, i32))> {
+        let here = self.by_seed(seed)?;
+        let there = self.get(&here.gate_at(x, y)?.to)?;
+        let arrival = there.gate_to(&here.id)?;
+        Some((there.seed, (arrival.x, arrival.y)))
+    }
+
+    /// The gates in chunk (cx, cy) of the system with this seed, as points of interest
+    pub fn gate_pois(&self, seed: u64, cx: i32, cy: i32) -> Vec<Poi> {
+        let Some(system) = self.by_seed(seed) else {
+            return Vec::new();
+        };
+        system
+            .gates
+            .iter()
+            .filter(|gate| chunk_coords(gate.x, gate.y) == (cx, cy))
+            .map(|gate| {
+                let to = self.get(&gate.to).map_or(gate.to.as_str(), |system| system.name.as_str());
+                Poi { kind: PoiKind::JumpGate, name: format!("Gate to {}", to), x: gate.x, y: gate.y }
+            })
+            .collect()
+    }
+}
+
+/// Handler for `GET /systems`
+pub async fn get_systems(State(state): State<AppState>) -> Json<Vec<SystemInfo>> {
+    Json(state.systems.list())
+}
+
+/// Handler for `GET /system/{id}/map`: one chunk of a system, like `/map/chunk` with its seed
+pub async fn get_system_map(
+    State(state): State<AppState>,
+    ApiPath(id): ApiPath<String>,
+    ApiQuery(params): ApiQuery<SystemChunkQuery>,
+    headers: HeaderMap,
+) -> Response {
+    match state.systems.get(&id) {
+        Some(system) => chunk_response(&state, params.cx, params.cy, system.seed, params.format.as_deref(), &headers),
+        None => ApiError::NotFound("No such star system".to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Chart Tests ====================
+
+    #[test]
+    fn test_gates_come_in_pairs() {
+        let systems = Systems::chart(12345);
+        assert_eq!(systems.list()[0].seed, 12345, "Home is the world's own seed");
+        for system in systems.list() {
+            assert!(!system.gates.is_empty(), "{} can be reached", system.name);
+            let generator = ChunkGenerator::new(system.seed);
+            for gate in &system.gates {
+                assert!(generator.tile_at(gate.x, gate.y).is_passable(), "The gate to {} is in rock", gate.to);
+                let far_side = systems.get(&gate.to).unwrap_or_else(|| panic!("{} leads nowhere", gate.to));
+                assert!(far_side.gate_to(&system.id).is_some(), "No way back from {} to {}", far_side.name, system.name);
+            }
+        }
+        let seeds: std::collections::HashSet<u64> = systems.list().iter().map(|system| system.seed).collect();
+        assert_eq!(seeds.len(), CHART.len(), "Every system has its own seed");
+        assert_eq!(Systems::chart(12345).list(), systems.list(), "Same world, same chart");
+        assert_eq!(systems.get("VEGA").map(|system| system.name.as_str()), Some("Vega"));
+    }
+
+    #[test]
+    fn test_jumps_arrive_on_the_gate_back() {
+        let systems = Systems::chart(12345);
+        let (haven, vega) = (systems.get("haven").unwrap(), systems.get("vega").unwrap());
+        let out = haven.gate_to("vega").unwrap();
+        let back = vega.gate_to("haven").unwrap();
+        assert_eq!(systems.jump(haven.seed, (out.x, out.y)), Some((vega.seed, (back.x, back.y))));
+        assert_eq!(systems.jump(vega.seed, (back.x, back.y)), Some((haven.seed, (out.x, out.y))));
+        assert_eq!(systems.jump(haven.seed, (out.x + 1, out.y)), None, "Only from the gate's own tile");
+        assert_eq!(systems.jump(99, (out.x, out.y)), None);
+    }
+
+    #[test]
+    fn test_gate_pois_are_in_their_chunk() {
+        let systems = Systems::chart(12345);
+        let haven = &systems.list()[0];
+        let gate = &haven.gates[0];
+        let (cx, cy) = chunk_coords(gate.x, gate.y);
+        let pois = systems.gate_pois(haven.seed, cx, cy);
+        assert!(pois.contains(&Poi { kind: PoiKind::JumpGate, name: "Gate to Vega".to_string(), x: gate.x, y: gate.y }));
+        assert!(systems.gate_pois(haven.seed, cx + 5, cy).is_empty());
+        assert!(systems.gate_pois(99, cx, cy).is_empty(), "Seeds that aren't a system have no gates");
+    }
+}
@@ -0,0 +1,230 @@
+//! Per-pilot budgets for moves, chat, map requests and trades.
+//!
+//! `Throttle` lives in `AppState` and keeps a token bucket for each pilot and
+//! kind of action, so a pilot who reconnects picks up where they left off.
+//! Logged-in pilots are counted by account; guests by session on `/ws` and by
+//! address over HTTP. The `limit_maps` middleware guards the map routes and
+//! answers a request over budget with a 429 and `Retry-After`, as the market
+//! does for trades; sessions drop moves over budget and turn down chat with
+//! the wait. Each budget holds a
+//! full period's worth, so bursts are fine as long as the average keeps under
+//! it, and a budget of 0 turns that limit off.
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+use crate::auth;
+use crate::error::ApiError;
+
+/// Environment variables for each budget
+pub const MOVES_ENV: &str = "EXOSPACE_MOVES_PER_SEC";
+pub const CHAT_ENV: &str = "EXOSPACE_CHAT_PER_MIN";
+pub const MAPS_ENV: &str = "EXOSPACE_MAPS_PER_MIN";
+pub const TRADES_ENV: &str = "EXOSPACE_TRADES_PER_MIN";
+
+/// Largest budget any of them takes
+const MAX_BUDGET: u32 = 100_000;
+
+/// Buckets kept before full ones are forgotten
+const MAX_TRACKED: usize = 4096;
+
+/// How many of each action a pilot may take
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Budgets {
+    /// `Position` updates a second; a ship at top speed sends 40
+    pub moves_per_sec: u32,
+    pub chat_per_min: u32,
+    /// Requests a minute to `/map`, `/map/chunk`, `/map/preview` and `/system/{id}/map`
+    pub maps_per_min: u32,
+    /// Market orders a minute
+    pub trades_per_min: u32,
+}
+
+impl Default for Budgets {
+    fn default() -> Self {
+        Budgets { moves_per_sec: 60, chat_per_min: 60, maps_per_min: 600, trades_per_min: 60 }
+    }
+}
+
+impl Budgets {
+    /// From `EXOSPACE_MOVES_PER_SEC`, `EXOSPACE_CHAT_PER_MIN`, `EXOSPACE_MAPS_PER_MIN` and `EXOSPACE_TRADES_PER_MIN`; bad
+    /// values are logged and the defaults kept
+    pub fn load() -> Self {
+        let defaults = Budgets::default();
+        Budgets {
+            moves_per_sec: load_budget(MOVES_ENV, defaults.moves_per_sec),
+            chat_per_min: load_budget(CHAT_ENV, defaults.chat_per_min),
+            maps_per_min: load_budget(MAPS_ENV, defaults.maps_per_min),
+            trades_per_min: load_budget(TRADES_ENV, defaults.trades_per_min),
+        }
+    }
+
+    /// Most banked, and how many come back a second; None when unlimited
+    fn rate(&self, action: Action) -> Option<(f32, f32)> {
+        let (amount, period) = match action {
+            Action::Move => (self.moves_per_sec, 1.0),
+            Action::Chat => (self.chat_per_min, 60.0),
+            Action::Map => (self.maps_per_min, 60.0),
+            Action::Trade => (self.trades_per_min, 60.0),
+        };
+        (amount > 0).then(|| (amount as f32, amount as f32 / period))
+    }
+}
+
+fn load_budget(name: &str, default: u32) -> u32 {
+    let Ok(value) = std::env::var(name) else {
+        return default;
+    };
+    parse_budget(&value).unwrap_or_else(|e| {
+        tracing::warn!("Ignoring {}: {}", name, e);
+        default
+    })
+}
+
+fn parse_budget(value: &str) -> Result<u32, String> {
+    match value.trim().parse::<u32>() {
+        Ok(budget) if budget <= MAX_BUDGET => Ok(budget),
+        _ => Err(format!("'{}' is not a budget (0-{}, 0 for no limit)", value, MAX_BUDGET)),
+    }
+}
+
+/// What's being counted
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Move,
+    Chat,
+    Map,
+    Trade,
+}
+
+/// Who's being counted
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Actor {
+    Account(String),
+    /// A guest's socket
+    Session(u64),
+    /// A guest's HTTP requests
+    Addr(IpAddr),
+}
+
+struct Bucket {
+    tokens: f32,
+    refilled: Instant,
+}
+
+#[derive(Default)]
+pub struct Throttle {
+    budgets: Budgets,
+    buckets: Mutex<HashMap<(Actor, Action), Bucket>>,
+}
+
+impl Throttle {
+    pub fn new(budgets: Budgets) -> Self {
+        Throttle { budgets, buckets: Mutex::default() }
+    }
+
+    pub fn budgets(&self) -> Budgets {
+        self.budgets
+    }
+
+    /// Spend one of `actor`'s `action`s at `now`, or say how long until there's one
+    pub fn check(&self, actor: &Actor, action: Action, now: Instant) -> Result<(), Duration> {
+        let Some((capacity, per_sec)) = self.budgets.rate(action) else {
+            return Ok(());
+        };
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED {
+            // Full buckets are the same as new ones
+            buckets.retain(|&(_, action), bucket| {
+                let (capacity, per_sec) = self.budgets.rate(action).unwrap_or((0.0, 0.0));
+                bucket.tokens + now.saturating_duration_since(bucket.refilled).as_secs_f32() * per_sec < capacity
+            });
+        }
+        let bucket = buckets.entry((actor.clone(), action)).or_insert(Bucket { tokens: capacity, refilled: now });
+        let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f32();
+        bucket.tokens = (bucket.tokens + elapsed * per_sec).min(capacity);
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f32((1.0 - bucket.tokens) / per_sec))
+    }
+}
+
+/// Keeps each pilot's map requests within `maps_per_min`
+pub async fn limit_maps(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let actor = match auth::bearer_token(request.headers()).and_then(|token| state.users.verify(token)) {
+        Some(account) => Actor::Account(account),
+        // Requests straight to the router (tests) have no address
+        None => Actor::Addr(
+            request.extensions().get::<ConnectInfo<SocketAddr>>().map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |info| info.0.ip()),
+        ),
+    };
+    match state.throttle.check(&actor, Action::Map, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => ApiError::RateLimited(wait).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pilot() -> Actor {
+        Actor::Account("Ace".to_string())
+    }
+
+    // ==================== Budget Tests ====================
+
+    #[test]
+    fn test_parse_budget() {
+        assert_eq!(parse_budget("30"), Ok(30));
+        assert_eq!(parse_budget(" 0 "), Ok(0), "0 turns the limit off");
+        assert!(parse_budget("-1").is_err());
+        assert!(parse_budget("lots").is_err());
+        assert!(parse_budget("100001").is_err());
+    }
+
+    // ==================== Throttle Tests ====================
+
+    #[test]
+    fn test_budget_runs_out_and_refills() {
+        let throttle = Throttle::new(Budgets { chat_per_min: 3, ..Budgets::default() });
+        let start = Instant::now();
+        for i in 0..3 {
+            assert_eq!(throttle.check(&pilot(), Action::Chat, start), Ok(()), "Message {} of the minute's budget", i);
+        }
+        let wait = throttle.check(&pilot(), Action::Chat, start).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(20), "One message comes back every 20 seconds");
+        assert!(throttle.check(&pilot(), Action::Chat, start + Duration::from_secs(10)).is_err());
+        assert_eq!(throttle.check(&pilot(), Action::Chat, start + Duration::from_secs(20)), Ok(()));
+    }
+
+    #[test]
+    fn test_budgets_are_per_actor_and_action() {
+        let throttle = Throttle::new(Budgets { moves_per_sec: 1, chat_per_min: 1, maps_per_min: 1, trades_per_min: 1 });
+        let now = Instant::now();
+        assert_eq!(throttle.check(&pilot(), Action::Move, now), Ok(()));
+        assert!(throttle.check(&pilot(), Action::Move, now).is_err());
+        assert_eq!(throttle.check(&pilot(), Action::Chat, now), Ok(()), "Moving doesn't use up chat");
+        assert_eq!(throttle.check(&pilot(), Action::Trade, now), Ok(()), "Or trades");
+        assert_eq!(throttle.check(&Actor::Session(1), Action::Move, now), Ok(()), "Other pilots have their own");
+        assert_eq!(throttle.check(&Actor::Addr(IpAddr::V4(Ipv4Addr::LOCALHOST)), Action::Map, now), Ok(()));
+    }
+
+    #[test]
+    fn test_zero_budget_is_unlimited() {
+        let throttle = Throttle::new(Budgets { maps_per_min: 0, ..Budgets::default() });
+        let now = Instant::now();
+        assert!((0..1000).all(|_| throttle.check(&pilot(), Action::Map, now).is_ok()));
+    }
+}
@@ -0,0 +1,468 @@
+//! The fixed-rate simulation loop.
+//!
+//! Everything the server does on its own, rather than in reply to a client,
+//! runs as a `System` stepped once per tick. Each tick has a budget of one
+//! tick interval. When a tick overruns it, the overrun is logged and the loop
+//! sheds load for a while: only critical systems run, and deferrable ones
+//! (ambient chatter, NPC repathing) wait until ticks fit again. Missed ticks
+//! are skipped rather than replayed, so a slow tick can't snowball into a
+//! backlog of slower ones.
+//!
+//! The loop keeps a `TickReport` (rate, lag behind schedule, overruns) in a
+//! shared `TickStatus` for `GET /admin/tick`, and stops when its shutdown
+//! future completes, after one last tick so nothing recorded is left unsent.
+//! `POST /admin/tick` asks for a new rate through the same `TickStatus`; the
+//! loop switches to it after its next tick.
+
+use axum::{
+    Json,
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::MissedTickBehavior;
+
+use crate::AppState;
+use crate::admin;
+use crate::error::{ApiError, ApiJson};
+
+/// Environment variable holding the tick rate in ticks per second
+pub const TICK_RATE_ENV: &str = "EXOSPACE_TICK_RATE";
+
+pub const DEFAULT_TICK_RATE: u32 = 20;
+pub const MAX_TICK_RATE: u32 = 120;
+
+/// Ticks that skip deferrable work after an overrun
+const SHED_TICKS: u64 = 40;
+
+/// Minimum time between overrun warnings, so a struggling server doesn't flood its log
+const WARN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Ticks per second from `EXOSPACE_TICK_RATE` if set and valid, otherwise the default
+pub fn load_tick_rate() -> u32 {
+    let Ok(value) = std::env::var(TICK_RATE_ENV) else {
+        return DEFAULT_TICK_RATE;
+    };
+    parse_tick_rate(&value).unwrap_or_else(|e| {
+        tracing::warn!("Ignoring {}: {}", TICK_RATE_ENV, e);
+        DEFAULT_TICK_RATE
+    })
+}
+
+fn parse_tick_rate(value: &str) -> Result<u32, String> {
+    match value.trim().parse::<u32>() {
+        Ok(rate) if (1..=MAX_TICK_RATE).contains(&rate) => Ok(rate),
+        _ => Err(format!("'{}' is not a tick rate (1-{} per second)", value, MAX_TICK_RATE)),
+    }
+}
+
+/// A piece of the simulation stepped every tick
+pub trait System: Send {
+    fn name(&self) -> &'static str;
+
+    /// Critical systems run every tick; the rest are skipped while the loop is shedding load
+    fn critical(&self) -> bool {
+        false
+    }
+
+    fn tick(&mut self, now: Instant);
+}
+
+/// Per-tick time accounting
+pub struct TickBudget {
+    budget: Duration,
+    tick: u64,
+    /// Deferrable work is skipped until this tick
+    shed_until: u64,
+    last_warning: Option<Instant>,
+    /// Ticks that went over budget
+    pub overruns: u64,
+    /// Deferrable system runs that were skipped
+    pub shed: u64,
+}
+
+impl TickBudget {
+    pub fn new(tick_rate: u32) -> Self {
+        TickBudget {
+            budget: Duration::from_secs(1) / tick_rate.max(1),
+            tick: 0,
+            shed_until: 0,
+            last_warning: None,
+            overruns: 0,
+            shed: 0,
+        }
+    }
+
+    /// Budget ticks for a new rate; counts and the shedding window carry over
+    pub fn set_rate(&mut self, tick_rate: u32) {
+        self.budget = Duration::from_secs(1) / tick_rate.max(1);
+    }
+
+    pub fn shedding(&self) -> bool {
+        self.tick < self.shed_until
+    }
+
+    /// Record how long a tick took; returns a warning to log when it overran and one is due
+    pub fn finish(&mut self, elapsed: Duration, now: Instant) -> Option<String> {
+        self.tick += 1;
+        if elapsed <= self.budget {
+            return None;
+        }
+        self.overruns += 1;
+        self.shed_until = self.tick + SHED_TICKS;
+        if self.last_warning.is_some_and(|at| now.duration_since(at) < WARN_INTERVAL) {
+            return None;
+        }
+        self.last_warning = Some(now);
+        Some(format!(
+            "Warning: Tick {} took {}ms (budget {}ms); shedding non-critical work for {} ticks ({} overruns, {} runs shed so far)",
+            self.tick,
+            elapsed.as_millis(),
+            self.budget.as_millis(),
+            SHED_TICKS,
+            self.overruns,
+            self.shed
+        ))
+    }
+}
+
+/// How the loop is keeping up, as `GET /admin/tick` shows it; times in milliseconds
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct TickReport {
+    pub tick_rate: u32,
+    pub running: bool,
+    pub ticks: u64,
+    pub last_tick_ms: f64,
+    /// How late the last tick started against its schedule
+    pub lag_ms: f64,
+    pub max_lag_ms: f64,
+    pub overruns: u64,
+    pub shed: u64,
+    pub shedding: bool,
+    pub systems: Vec<&'static str>,
+}
+
+/// The latest `TickReport`, shared between the loop and the admin routes
+#[derive(Default)]
+pub struct TickStatus {
+    report: Mutex<TickReport>,
+    /// A rate asked for by `POST /admin/tick`, not yet picked up by the loop
+    requested_rate: Mutex<Option<u32>>,
+}
+
+impl TickStatus {
+    pub fn report(&self) -> TickReport {
+        self.report.lock().unwrap().clone()
+    }
+
+    /// Have the loop switch to a new tick rate
+    pub fn request_rate(&self, tick_rate: u32) {
+        *self.requested_rate.lock().unwrap() = Some(tick_rate);
+    }
+
+    fn take_requested_rate(&self) -> Option<u32> {
+        self.requested_rate.lock().unwrap().take()
+    }
+
+    fn update(&self, change: impl FnOnce(&mut TickReport)) {
+        change(&mut self.report.lock().unwrap());
+    }
+}
+
+/// The systems and the budget they share
+pub struct Simulation {
+    tick_rate: u32,
+    systems: Vec<Box<dyn System>>,
+    budget: TickBudget,
+    status: Arc<TickStatus>,
+}
+
+impl Simulation {
+    pub fn new(tick_rate: u32) -> Self {
+        let status = TickStatus { report: Mutex::new(TickReport { tick_rate, ..TickReport::default() }), ..TickStatus::default() };
+        Simulation { tick_rate, systems: Vec::new(), budget: TickBudget::new(tick_rate), status: Arc::new(status) }
+    }
+
+    /// For `AppState`, so `/admin/tick` can read the loop's numbers
+    pub fn status(&self) -> Arc<TickStatus> {
+        self.status.clone()
+    }
+
+    /// Switch to a requested tick rate, if there is one; returns the new rate
+    fn apply_requested_rate(&mut self) -> Option<u32> {
+        let tick_rate = self.status.take_requested_rate().filter(|&rate| rate != self.tick_rate)?;
+        self.tick_rate = tick_rate;
+        self.budget.set_rate(tick_rate);
+        self.status.update(|report| report.tick_rate = tick_rate);
+        Some(tick_rate)
+    }
+
+    pub fn add(&mut self, system: Box<dyn System>) {
+        self.status.update(|report| report.systems.push(system.name()));
+        self.systems.push(system);
+    }
+
+    /// Run one tick; returns how long it took
+    pub fn step(&mut self, now: Instant) -> Duration {
+        let started = Instant::now();
+        let shedding = self.budget.shedding();
+        let mut slowest = ("", Duration::ZERO);
+        for system in &mut self.systems {
+            if shedding && !system.critical() {
+                self.budget.shed += 1;
+                continue;
+            }
+            let system_started = Instant::now();
+            system.tick(now);
+            let took = system_started.elapsed();
+            if took > slowest.1 {
+                slowest = (system.name(), took);
+            }
+        }
+        let elapsed = started.elapsed();
+        if let Some(warning) = self.budget.finish(elapsed, now) {
+            tracing::warn!("{}; slowest system: {} ({}ms)", warning, slowest.0, slowest.1.as_millis());
+        }
+        let budget = &self.budget;
+        self.status.update(|report| {
+            report.ticks = budget.tick;
+            report.last_tick_ms = millis(elapsed);
+            report.overruns = budget.overruns;
+            report.shed = budget.shed;
+            report.shedding = budget.shedding();
+        });
+        elapsed
+    }
+
+    /// Background task: step the simulation `tick_rate` times a second until `shutdown` completes
+    pub async fn run(mut self, shutdown: impl Future<Output = ()>) {
+        if self.systems.is_empty() {
+            return;
+        }
+        let mut interval = tokio::time::interval(Duration::from_secs(1) / self.tick_rate);
+        // A late tick runs once, then the schedule moves on instead of bursting to catch up
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        self.status.update(|report| report.running = true);
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                scheduled = interval.tick() => {
+                    let lag = millis(Instant::now().saturating_duration_since(scheduled.into_std()));
+                    self.status.update(|report| {
+                        report.lag_ms = lag;
+                        report.max_lag_ms = report.max_lag_ms.max(lag);
+                    });
+                    self.step(Instant::now());
+                    if let Some(tick_rate) = self.apply_requested_rate() {
+                        interval = tokio::time::interval(Duration::from_secs(1) / tick_rate);
+                        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                        tracing::info!("Simulation: now {} ticks/sec", tick_rate);
+                    }
+                }
+                _ = &mut shutdown => break,
+            }
+        }
+        self.step(Instant::now());
+        self.status.update(|report| report.running = false);
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// `GET /admin/tick`: the simulation's tick rate, lag and overruns (needs the admin bearer token)
+pub async fn get_status(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(rejection) = admin::admin_rejection(&state, &headers) {
+        return rejection;
+    }
+    Json(state.tick.report()).into_response()
+}
+
+/// Body of `POST /admin/tick`
+#[derive(Deserialize)]
+pub struct RateChange {
+    tick_rate: u32,
+}
+
+/// `POST /admin/tick`: change the tick rate while running (needs the admin bearer token)
+pub async fn set_rate(State(state): State<AppState>, headers: HeaderMap, ApiJson(change): ApiJson<RateChange>) -> Response {
+    if let Some(rejection) = admin::admin_rejection(&state, &headers) {
+        return rejection;
+    }
+    if !(1..=MAX_TICK_RATE).contains(&change.tick_rate) {
+        return ApiError::Invalid(format!("Tick rate must be 1-{} per second", MAX_TICK_RATE)).into_response();
+    }
+    state.tick.request_rate(change.tick_rate);
+    format!("Tick rate will be {} per second from the next tick", change.tick_rate).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Counts its runs and optionally takes a while
+    struct Probe {
+        critical: bool,
+        runs: Arc<AtomicU32>,
+        work: Duration,
+    }
+
+    impl System for Probe {
+        fn name(&self) -> &'static str {
+            "probe"
+        }
+
+        fn critical(&self) -> bool {
+            self.critical
+        }
+
+        fn tick(&mut self, _now: Instant) {
+            self.runs.fetch_add(1, Ordering::Relaxed);
+            std::thread::sleep(self.work);
+        }
+    }
+
+    fn probe(simulation: &mut Simulation, critical: bool, work: Duration) -> Arc<AtomicU32> {
+        let runs = Arc::new(AtomicU32::new(0));
+        simulation.add(Box::new(Probe { critical, runs: runs.clone(), work }));
+        runs
+    }
+
+    // ==================== Config Tests ====================
+
+    #[test]
+    fn test_parse_tick_rate() {
+        assert_eq!(parse_tick_rate("30"), Ok(30));
+        assert_eq!(parse_tick_rate(" 1 "), Ok(1));
+        assert_eq!(parse_tick_rate("120"), Ok(MAX_TICK_RATE));
+        for bad in ["0", "121", "-5", "fast", ""] {
+            assert!(parse_tick_rate(bad).is_err(), "'{}' should be rejected", bad);
+        }
+    }
+
+    // ==================== Budget Tests ====================
+
+    #[test]
+    fn test_budget_overrun_sheds_for_a_while() {
+        let now = Instant::now();
+        let mut budget = TickBudget::new(20);
+        assert_eq!(budget.finish(Duration::from_millis(50), now), None, "Exactly on budget is fine");
+        assert!(!budget.shedding());
+
+        assert!(budget.finish(Duration::from_millis(80), now).is_some(), "An overrun is logged");
+        assert!(budget.shedding());
+        assert_eq!(budget.overruns, 1);
+
+        for _ in 1..SHED_TICKS {
+            budget.finish(Duration::ZERO, now);
+        }
+        assert!(budget.shedding(), "Still shedding on the last tick of the window");
+        budget.finish(Duration::ZERO, now);
+        assert!(!budget.shedding(), "Back to normal once ticks fit again");
+    }
+
+    #[test]
+    fn test_budget_warnings_are_rate_limited() {
+        let now = Instant::now();
+        let mut budget = TickBudget::new(100);
+        let slow = Duration::from_millis(30);
+        assert!(budget.finish(slow, now).unwrap().contains("budget 10ms"));
+        assert_eq!(budget.finish(slow, now + Duration::from_secs(1)), None, "Too soon for another warning");
+        assert!(budget.finish(slow, now + WARN_INTERVAL).is_some());
+        assert_eq!(budget.overruns, 3, "Every overrun is counted, warned or not");
+    }
+
+    // ==================== Simulation Tests ====================
+
+    #[test]
+    fn test_simulation_sheds_only_deferrable_systems() {
+        let mut simulation = Simulation::new(MAX_TICK_RATE);
+        let slow = probe(&mut simulation, true, Duration::from_millis(20));
+        let chatter = probe(&mut simulation, false, Duration::ZERO);
+
+        let now = Instant::now();
+        assert!(simulation.step(now) > simulation.budget.budget, "The slow system blows the budget");
+        assert_eq!(chatter.load(Ordering::Relaxed), 1, "Nothing is shed until a tick overruns");
+
+        simulation.step(now);
+        simulation.step(now);
+        assert_eq!(slow.load(Ordering::Relaxed), 3, "Critical systems always run");
+        assert_eq!(chatter.load(Ordering::Relaxed), 1, "Deferrable systems wait out the overrun");
+        assert_eq!(simulation.budget.shed, 2);
+    }
+
+    #[test]
+    fn test_simulation_runs_everything_within_budget() {
+        let mut simulation = Simulation::new(DEFAULT_TICK_RATE);
+        let chatter = probe(&mut simulation, false, Duration::ZERO);
+        for _ in 0..5 {
+            simulation.step(Instant::now());
+        }
+        assert_eq!(chatter.load(Ordering::Relaxed), 5);
+        assert_eq!((simulation.budget.overruns, simulation.budget.shed), (0, 0));
+    }
+
+    #[test]
+    fn test_requested_rate_is_applied_once() {
+        let mut simulation = Simulation::new(DEFAULT_TICK_RATE);
+        let status = simulation.status();
+        assert_eq!(simulation.apply_requested_rate(), None, "Nothing asked for");
+        status.request_rate(DEFAULT_TICK_RATE);
+        assert_eq!(simulation.apply_requested_rate(), None, "Already at that rate");
+
+        status.request_rate(50);
+        assert_eq!(simulation.apply_requested_rate(), Some(50));
+        assert_eq!(simulation.budget.budget, Duration::from_millis(20), "The budget follows the rate");
+        assert_eq!(status.report().tick_rate, 50);
+        assert_eq!(simulation.apply_requested_rate(), None);
+    }
+
+    // ==================== Status Tests ====================
+
+    #[test]
+    fn test_status_reports_steps() {
+        let mut simulation = Simulation::new(MAX_TICK_RATE);
+        probe(&mut simulation, true, Duration::from_millis(20));
+        probe(&mut simulation, false, Duration::ZERO);
+        let status = simulation.status();
+        simulation.step(Instant::now());
+        simulation.step(Instant::now());
+
+        let report = status.report();
+        assert_eq!(report.tick_rate, MAX_TICK_RATE);
+        assert_eq!(report.ticks, 2);
+        assert_eq!(report.systems, ["probe", "probe"]);
+        assert!(report.last_tick_ms >= 20.0, "Includes the slow system");
+        assert_eq!((report.overruns, report.shed), (2, 1));
+        assert!(report.shedding);
+        assert!(!report.running, "Only run() marks the loop running");
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_on_shutdown() {
+        let mut simulation = Simulation::new(MAX_TICK_RATE);
+        let runs = probe(&mut simulation, true, Duration::ZERO);
+        let status = simulation.status();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let handle = tokio::spawn(simulation.run(async {
+            let _ = stopped.await;
+        }));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(status.report().running);
+        stop.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), handle).await.expect("Stops promptly").unwrap();
+
+        let report = status.report();
+        assert!(!report.running);
+        assert_eq!(report.ticks, u64::from(runs.load(Ordering::Relaxed)), "One last step on the way out is counted too");
+        assert!(report.ticks >= 2);
+    }
+}
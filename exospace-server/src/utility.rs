@@ -0,0 +1,182 @@
+//! Server-side utility commands: `/roll`, `/flip`, `/time` and `/distance`.
+//!
+//! These run on the server so other players can trust the results; a client
+//! can't fake a good roll when the server is the one rolling.
+
+use exospace_core::{DistanceTarget, MAX_DICE, MAX_DIE_SIDES, ServerMessage};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::sessions::SessionRegistry;
+
+/// Per-session random source for dice and coins
+pub struct DiceRng {
+    state: u64,
+}
+
+impl DiceRng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift must never be seeded with zero
+        DiceRng { state: seed | 1 }
+    }
+
+    /// Seed from the clock and session id so sessions don't share sequences
+    pub fn for_session(id: u64) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Self::new(nanos ^ id.wrapping_mul(0x9E3779B97F4A7C15))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // xorshift64*
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    /// Uniform value in 1..=sides
+    pub fn roll_die(&mut self, sides: u32) -> u32 {
+        (self.next_u64() % sides as u64) as u32 + 1
+    }
+}
+
+/// Roll `count` dice of `sides` sides, enforcing the shared limits
+pub fn roll(rng: &mut DiceRng, count: u32, sides: u32) -> Result<Vec<u32>, String> {
+    if !(1..=MAX_DICE).contains(&count) {
+        return Err(format!("Roll between 1 and {} dice", MAX_DICE));
+    }
+    if !(2..=MAX_DIE_SIDES).contains(&sides) {
+        return Err(format!("Dice need between 2 and {} sides", MAX_DIE_SIDES));
+    }
+    Ok((0..count).map(|_| rng.roll_die(sides)).collect())
+}
+
+/// Straight-line distance between two tiles, rounded to the nearest tile
+pub fn distance(from: (i32, i32), to: (i32, i32)) -> u32 {
+    // In f64 so positions at opposite ends of the i32 range can't overflow
+    let dx = to.0 as f64 - from.0 as f64;
+    let dy = to.1 as f64 - from.1 as f64;
+    dx.hypot(dy).round() as u32
+}
+
+/// Seconds since the Unix epoch on the server's clock
+pub fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Resolve a `/distance` request for a session into its reply
+pub fn distance_reply(sessions: &SessionRegistry, id: u64, target: DistanceTarget) -> ServerMessage {
+    let Some(me) = sessions.get(id) else {
+        return ServerMessage::CommandFailed { reason: "Unknown session".to_string() };
+    };
+    let (label, position) = match target {
+        DistanceTarget::Position { x, y } => (format!("({}, {})", x, y), (x, y)),
+        // Pilots in other systems, or hidden by a nebula, aren't on our sensors
+        DistanceTarget::Player { name } => match sessions
+            .find_by_name(&name)
+            .filter(|p| sessions.system(p.id) == sessions.system(id) && !sessions.hidden_from(p.id, (me.x, me.y)))
+        {
+            Some(other) => (other.name, (other.x, other.y)),
+            None => return ServerMessage::CommandFailed { reason: format!("No player named {}", name) },
+        },
+    };
+    ServerMessage::Distance { target: label, distance: distance((me.x, me.y), position) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exospace_core::Direction;
+
+    // ==================== Dice Tests ====================
+
+    #[test]
+    fn test_dice_rng_deterministic() {
+        let mut a = DiceRng::new(42);
+        let mut b = DiceRng::new(42);
+        for _ in 0..10 {
+            assert_eq!(a.roll_die(6), b.roll_die(6));
+        }
+    }
+
+    #[test]
+    fn test_dice_rng_zero_seed() {
+        let mut rng = DiceRng::new(0);
+        let rolls: Vec<u32> = (0..20).map(|_| rng.roll_die(100)).collect();
+        assert!(rolls.iter().any(|&r| r != rolls[0]), "Zero seed must still produce varied rolls");
+    }
+
+    #[test]
+    fn test_roll_in_range_and_covers_faces() {
+        let mut rng = DiceRng::new(7);
+        let rolls = roll(&mut rng, MAX_DICE, 6).unwrap();
+        assert_eq!(rolls.len(), MAX_DICE as usize);
+
+        let mut seen = [false; 6];
+        for _ in 0..50 {
+            for r in roll(&mut rng, 10, 6).unwrap() {
+                assert!((1..=6).contains(&r), "Roll {} out of range", r);
+                seen[r as usize - 1] = true;
+            }
+        }
+        assert!(seen.iter().all(|&s| s), "Every face should come up eventually");
+    }
+
+    #[test]
+    fn test_roll_limits() {
+        let mut rng = DiceRng::new(1);
+        assert!(roll(&mut rng, 0, 6).is_err());
+        assert!(roll(&mut rng, MAX_DICE + 1, 6).is_err());
+        assert!(roll(&mut rng, 1, 1).is_err());
+        assert!(roll(&mut rng, 1, MAX_DIE_SIDES + 1).is_err());
+        assert!(roll(&mut rng, 1, MAX_DIE_SIDES).is_ok());
+    }
+
+    // ==================== Distance Tests ====================
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance((0, 0), (3, 4)), 5);
+        assert_eq!(distance((10, 10), (10, 10)), 0);
+        assert_eq!(distance((-5, 0), (5, 0)), 10);
+        assert_eq!(distance((0, 0), (1, 1)), 1, "Rounded to the nearest tile");
+        assert_eq!(distance((i32::MIN, 0), (i32::MAX, 0)), u32::MAX, "Extremes don't overflow");
+    }
+
+    #[test]
+    fn test_distance_reply_to_player_and_position() {
+        let sessions = SessionRegistry::default();
+        let (me, _) = sessions.join("Alpha");
+        let (other, _) = sessions.join("Bravo");
+        sessions.update_position(me.id, 0, 0, Direction::Up);
+        sessions.update_position(other.id, 30, 40, Direction::Up);
+
+        assert_eq!(
+            distance_reply(&sessions, me.id, DistanceTarget::Player { name: "bravo".to_string() }),
+            ServerMessage::Distance { target: "Bravo".to_string(), distance: 50 },
+            "Player names match case-insensitively"
+        );
+        assert_eq!(
+            distance_reply(&sessions, me.id, DistanceTarget::Position { x: 6, y: 8 }),
+            ServerMessage::Distance { target: "(6, 8)".to_string(), distance: 10 }
+        );
+        assert!(matches!(
+            distance_reply(&sessions, me.id, DistanceTarget::Player { name: "Nobody".to_string() }),
+            ServerMessage::CommandFailed { .. }
+        ));
+
+        sessions.set_in_nebula(other.id, true);
+        assert!(
+            matches!(distance_reply(&sessions, me.id, DistanceTarget::Player { name: "Bravo".to_string() }), ServerMessage::CommandFailed { .. }),
+            "A nebula hides Bravo from afar"
+        );
+    }
+
+    #[test]
+    fn test_unix_time_is_recent() {
+        // 2023-11-14 or later
+        assert!(unix_time() > 1_700_000_000);
+    }
+}
@@ -0,0 +1,301 @@
+//! Wave function collapse: station interiors from an adjacency ruleset.
+//!
+//! The map is cut into `CELL`-tile square cells, and each cell becomes one
+//! `Module`: a corridor junction, a room, a cargo bay (asteroid rubble in
+//! the corners) or a garden (nebula). A module's four edges are either
+//! closed or a door `DOOR_WIDTH` tiles wide, and neighbouring cells must
+//! agree on their shared edge; cells against the map border must be closed.
+//! Collapse always picks the undecided cell with the fewest modules left
+//! (seeded ties), picks one of them by weight, and narrows its neighbours
+//! until nothing changes. Every combination of edges has a corridor module,
+//! so a cell can't run out of modules and no backtracking is needed.
+
+use exospace_core::{MapData, Tile, hash_position};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::mapgen::{MapAlgorithm, find_start_position, scatter_resources};
+
+/// Width and height of a module, in tiles
+pub const CELL: usize = 7;
+
+/// Width of a doorway, centred on its edge
+pub const DOOR_WIDTH: usize = 3;
+
+/// Where doorways and corridors start across a cell
+const LANE: usize = (CELL - DOOR_WIDTH) / 2;
+
+/// Edges in bit order: north, east, south, west
+const SIDES: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Style {
+    Corridor,
+    Room,
+    /// A room with rubble in its corners
+    Bay,
+    /// A room overgrown with nebula
+    Garden,
+}
+
+impl Style {
+    const ALL: [Style; 4] = [Style::Corridor, Style::Room, Style::Bay, Style::Garden];
+
+    fn weight(self) -> u32 {
+        match self {
+            Style::Corridor => 6,
+            Style::Room => 3,
+            Style::Bay | Style::Garden => 1,
+        }
+    }
+}
+
+/// One cell's contents: a style and which edges are doors (bit per side)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Module {
+    style: Style,
+    doors: u8,
+}
+
+impl Module {
+    fn door(self, side: usize) -> bool {
+        self.doors & (1 << side) != 0
+    }
+
+    /// How often collapse picks it; rooms need a way in, and sealed corridors are solid rock
+    fn weight(self) -> u32 {
+        let doors = self.doors.count_ones();
+        let shape = match doors {
+            0 if self.style == Style::Corridor => 2,
+            0 => 0,
+            1 => 1,
+            2 => 4,
+            3 => 3,
+            _ => 2,
+        };
+        self.style.weight() * shape
+    }
+
+    /// The module's tile at (x, y) within its cell; `salt` varies the rubble
+    fn tile(self, x: usize, y: usize, salt: u32) -> Tile {
+        let lane = LANE..LANE + DOOR_WIDTH;
+        let edge = x == 0 || y == 0 || x == CELL - 1 || y == CELL - 1;
+        let doorway = (y == 0 && self.door(0) || y == CELL - 1 && self.door(2)) && lane.contains(&x)
+            || (x == CELL - 1 && self.door(1) || x == 0 && self.door(3)) && lane.contains(&y);
+        match self.style {
+            Style::Corridor => {
+                let hub = self.doors != 0 && lane.contains(&x) && lane.contains(&y);
+                let north = self.door(0) && lane.contains(&x) && y < LANE;
+                let south = self.door(2) && lane.contains(&x) && y >= LANE + DOOR_WIDTH;
+                let west = self.door(3) && lane.contains(&y) && x < LANE;
+                let east = self.door(1) && lane.contains(&y) && x >= LANE + DOOR_WIDTH;
+                if hub || north || south || west || east { Tile::Floor } else { Tile::Wall }
+            }
+            _ if edge => {
+                if doorway { Tile::Floor } else { Tile::Wall }
+            }
+            Style::Room => Tile::Floor,
+            Style::Bay => {
+                // Rubble only in the corners, clear of the doorway lanes
+                let corner = !lane.contains(&x) && !lane.contains(&y);
+                if corner && !hash_position(x as i32, y as i32, salt).is_multiple_of(4) { Tile::Asteroid } else { Tile::Floor }
+            }
+            Style::Garden => Tile::Nebula,
+        }
+    }
+}
+
+/// Station interiors by wave function collapse over `Module`s
+pub struct WfcGenerator {
+    seed: u32,
+    rng_state: u64,
+    modules: Vec<Module>,
+    /// `with_door[side][door as usize]`: modules whose `side` edge is (or isn't) a door
+    with_door: [[u64; 2]; SIDES],
+}
+
+impl WfcGenerator {
+    pub fn new(seed: u64) -> Self {
+        let modules: Vec<Module> = Style::ALL
+            .iter()
+            .flat_map(|&style| (0..16).map(move |doors| Module { style, doors }))
+            .filter(|module| module.weight() > 0)
+            .collect();
+        let mut with_door = [[0; 2]; SIDES];
+        for (i, module) in modules.iter().enumerate() {
+            for (side, masks) in with_door.iter_mut().enumerate() {
+                masks[module.door(side) as usize] |= 1 << i;
+            }
+        }
+        WfcGenerator { seed: (seed ^ (seed >> 32)) as u32, rng_state: seed, modules, with_door }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        // xorshift64*, kept off zero
+        let mut x = self.rng_state | 1;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.rng_state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A module from `options`, by weight
+    fn pick(&mut self, options: u64) -> usize {
+        let candidates = (0..self.modules.len()).filter(|i| options & (1 << i) != 0);
+        let total: u64 = candidates.clone().map(|i| self.modules[i].weight() as u64).sum();
+        let mut roll = self.next_random() % total;
+        for i in candidates {
+            let weight = self.modules[i].weight() as u64;
+            if roll < weight {
+                return i;
+            }
+            roll -= weight;
+        }
+        unreachable!("the roll is below the total weight")
+    }
+
+    /// Which module each cell of a `columns` x `rows` grid holds
+    fn collapse(&mut self, columns: usize, rows: usize) -> Vec<Module> {
+        let all = (1u64 << self.modules.len()) - 1;
+        let mut options = vec![all; columns * rows];
+        for (i, cell) in options.iter_mut().enumerate() {
+            let (x, y) = (i % columns, i / columns);
+            for (side, against_border) in [y == 0, x == columns - 1, y == rows - 1, x == 0].into_iter().enumerate() {
+                if against_border {
+                    *cell &= self.with_door[side][0];
+                }
+            }
+        }
+
+        // Fewest options first; stale entries are skipped when popped
+        let mut queue = BinaryHeap::new();
+        for (i, cell) in options.iter().enumerate() {
+            queue.push(Reverse((cell.count_ones(), self.next_random() as u32, i)));
+        }
+        while let Some(Reverse((count, _, i))) = queue.pop() {
+            if count != options[i].count_ones() || count <= 1 {
+                continue;
+            }
+            options[i] = 1 << self.pick(options[i]);
+            self.propagate(&mut options, columns, rows, i, &mut queue);
+        }
+        options.into_iter().map(|cell| self.modules[cell.trailing_zeros() as usize]).collect()
+    }
+
+    /// Narrow the neighbours of a changed cell, and theirs, until everything agrees
+    fn propagate(&mut self, options: &mut [u64], columns: usize, rows: usize, start: usize, queue: &mut BinaryHeap<Reverse<(u32, u32, usize)>>) {
+        let mut stack = vec![start];
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % columns, i / columns);
+            let neighbours = [
+                (y > 0).then(|| i - columns),
+                (x + 1 < columns).then(|| i + 1),
+                (y + 1 < rows).then(|| i + columns),
+                (x > 0).then(|| i - 1),
+            ];
+            for (side, neighbour) in neighbours.into_iter().enumerate() {
+                let Some(n) = neighbour else { continue };
+                let facing = (side + 2) % SIDES;
+                // The neighbour's facing edge has to match some edge this cell can still have
+                let mut allowed = 0;
+                for door in [false, true] {
+                    if options[i] & self.with_door[side][door as usize] != 0 {
+                        allowed |= self.with_door[facing][door as usize];
+                    }
+                }
+                let narrowed = options[n] & allowed;
+                if narrowed != options[n] {
+                    options[n] = narrowed;
+                    queue.push(Reverse((narrowed.count_ones(), self.next_random() as u32, n)));
+                    stack.push(n);
+                }
+            }
+        }
+    }
+}
+
+impl MapAlgorithm for WfcGenerator {
+    fn generate(&mut self, width: usize, height: usize) -> MapData {
+        let mut map = MapData::filled(width, height, Tile::Wall);
+        let (columns, rows) = ((width - 2) / CELL, (height - 2) / CELL);
+        if columns == 0 || rows == 0 {
+            // Too small for a single module: one cramped room
+            for row in map.rect_mut(1..width - 1, 1..height - 1) {
+                row.fill(Tile::Floor);
+            }
+        } else {
+            let cells = self.collapse(columns, rows);
+            for (i, module) in cells.into_iter().enumerate() {
+                let (left, top) = (1 + i % columns * CELL, 1 + i / columns * CELL);
+                let salt = self.seed.wrapping_add(i as u32);
+                for (y, row) in map.rect_mut(left..left + CELL, top..top + CELL).enumerate() {
+                    for (x, tile) in row.iter_mut().enumerate() {
+                        *tile = module.tile(x, y, salt);
+                    }
+                }
+            }
+        }
+
+        scatter_resources(&mut map, self.seed);
+        let (start_x, start_y) = find_start_position(&map);
+        map.start_x = start_x;
+        map.start_y = start_y;
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ==================== Ruleset Tests ====================
+
+    #[test]
+    fn test_every_edge_combination_has_a_module() {
+        let generator = WfcGenerator::new(1);
+        assert!(generator.modules.len() <= 64, "Options are a u64 bitset");
+        for doors in 0..16 {
+            assert!(generator.modules.iter().any(|m| m.doors == doors), "No module for doors {:04b}", doors);
+        }
+        let bay = Module { style: Style::Bay, doors: 0b0101 };
+        assert_eq!(bay.tile(LANE, 0, 7), Tile::Floor, "North doorway");
+        assert_eq!(bay.tile(0, LANE, 7), Tile::Wall, "West is closed");
+    }
+
+    // ==================== Collapse Tests ====================
+
+    #[test]
+    fn test_neighbouring_cells_agree() {
+        let (columns, rows) = (12, 9);
+        let cells = WfcGenerator::new(42).collapse(columns, rows);
+        for (i, cell) in cells.iter().enumerate() {
+            let (x, y) = (i % columns, i / columns);
+            if x + 1 < columns {
+                assert_eq!(cell.door(1), cells[i + 1].door(3), "East edge of cell {} doesn't match", i);
+            } else {
+                assert!(!cell.door(1), "Cells against the border are closed");
+            }
+            if y + 1 < rows {
+                assert_eq!(cell.door(2), cells[i + columns].door(0), "South edge of cell {} doesn't match", i);
+            } else {
+                assert!(!cell.door(2));
+            }
+            assert!(x > 0 || !cell.door(3));
+            assert!(y > 0 || !cell.door(0));
+        }
+        assert!(cells.iter().any(|c| c.style == Style::Room) && cells.iter().any(|c| c.style == Style::Corridor));
+    }
+
+    #[test]
+    fn test_wfc_map_deterministic_and_bordered() {
+        let map = WfcGenerator::new(9).generate(90, 50);
+        assert_eq!(map.tiles, WfcGenerator::new(9).generate(90, 50).tiles, "Same seed, same station");
+        assert_ne!(map.tiles, WfcGenerator::new(10).generate(90, 50).tiles);
+        for x in 0..90 {
+            assert_eq!((map.get(x, 0), map.get(x, 49)), (Some(Tile::Wall), Some(Tile::Wall)));
+        }
+        let tiny = WfcGenerator::new(9).generate(5, 5);
+        assert_eq!(tiny.get(2, 2), Some(Tile::Floor), "Too small for a module is one room");
+    }
+}
@@ -0,0 +1,1326 @@
+//! Persistent world state: the canonical seed, edited tiles, and where
+//! logged-in pilots last were, where they call home, what they've done,
+//! what they've earned and spent and what's in their hold (the ledger
+//! behind `ledger.rs`).
+//!
+//! Chunks are still generated on demand; edits are stored as overrides on
+//! top of the generated terrain. Every edit is stamped with the next world
+//! version, so `GET /map/diff?since=N` can list the tiles changed after N
+//! (`MAX_DIFF_TILES` at a time) and a client can catch its chunks up without
+//! fetching them again; chunk responses carry the version they're current as
+//! of in `WORLD_VERSION_HEADER`, and are tagged (`etag.rs`) with the version
+//! of their own latest edit. `WorldStore` is the storage seam:
+//! `MemoryStore` forgets everything on restart, `SqliteStore` keeps it in the
+//! database file named by `EXOSPACE_DB`. `SqliteStore` needs the `sqlite`
+//! feature (on by default), which builds SQLite from source; its queries
+//! block, so on the runtime's workers they run under `block_in_place`.
+//!
+//! Mined-out ore and crystal grow back `REGROW_AFTER` later, run by the
+//! `ResourceRegrowth` system. Only tiles mined since the server started are
+//! tracked, and a tile with a ship on it waits until the ship moves off.
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use exospace_core::{
+    CHUNK_SIZE, ChunkData, LeaderboardMetric, MAX_CHUNK_COORD, MapDiff, PilotStats, Tile, TileChange, Transaction,
+    TransactionKind, chunk_coords, chunk_local,
+};
+#[cfg(feature = "sqlite")]
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Deserialize;
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+#[cfg(feature = "sqlite")]
+use tokio::runtime::RuntimeFlavor;
+
+use crate::AppState;
+use crate::auth::{self, AuthError};
+use crate::chunks::ChunkGenerator;
+use crate::error::{ApiError, ApiJson, ApiQuery};
+use crate::npc::NpcRoster;
+use crate::sessions::SessionRegistry;
+use crate::stats;
+use crate::tick::System;
+use crate::utility;
+
+/// Environment variable naming the SQLite database file
+pub const WORLD_DB_ENV: &str = "EXOSPACE_DB";
+
+/// Seed for a brand new world; matches `/map`
+pub const DEFAULT_SEED: u64 = 12345;
+
+/// How long a mined-out resource tile takes to grow back
+pub const REGROW_AFTER: Duration = Duration::from_secs(600);
+
+/// Most changed tiles in one `/map/diff` reply; the rest come with the next
+pub const MAX_DIFF_TILES: usize = 1000;
+
+/// How far from a pilot's ship, in tiles, `/tile/update` takes an edit: mining works on a neighbouring
+/// tile, with some slack for a position update still on its way
+pub const EDIT_REACH: u32 = 4;
+
+/// Storage behind the world; errors are messages for the log
+pub trait WorldStore: Send + Sync {
+    /// The world's seed, if one has been chosen
+    fn seed(&self) -> Result<Option<u64>, String>;
+    fn set_seed(&self, seed: u64) -> Result<(), String>;
+    /// The id picked for this world when it was first stored, so chunks cached from an earlier one don't match
+    fn instance(&self) -> Result<Option<u64>, String>;
+    fn set_instance(&self, instance: u64) -> Result<(), String>;
+    /// Edited tiles inside a chunk, as (local x, local y, tile)
+    fn chunk_edits(&self, cx: i32, cy: i32) -> Result<Vec<(i32, i32, Tile)>, String>;
+    /// Edit a tile, stamping it with the next world version
+    fn set_tile(&self, x: i32, y: i32, tile: Tile) -> Result<(), String>;
+    /// The latest world version; 0 before any edit
+    fn version(&self) -> Result<u64, String>;
+    /// The world version of a chunk's latest edit; 0 if it has none
+    fn chunk_version(&self, cx: i32, cy: i32) -> Result<u64, String>;
+    /// Up to `limit` tiles last edited after version `since`, oldest first, as (version, x, y, tile)
+    fn edits_since(&self, since: u64, limit: usize) -> Result<Vec<(u64, i32, i32, Tile)>, String>;
+    /// Where a pilot last was, by account name (case-insensitive)
+    fn player_position(&self, name: &str) -> Result<Option<(i32, i32)>, String>;
+    fn set_player_position(&self, name: &str, x: i32, y: i32) -> Result<(), String>;
+    /// The station a pilot calls home, by name
+    fn player_home(&self, name: &str) -> Result<Option<String>, String>;
+    fn set_player_home(&self, name: &str, station: &str) -> Result<(), String>;
+    /// A pilot's totals; `sectors_explored` counts the sectors recorded by `visit_sector`
+    fn player_stats(&self, name: &str) -> Result<PilotStats, String>;
+    /// Add to a pilot's distance and mining totals
+    fn add_player_stats(&self, name: &str, delta: PilotStats) -> Result<(), String>;
+    /// Record a pilot entering a chunk; true the first time
+    fn visit_sector(&self, name: &str, cx: i32, cy: i32) -> Result<bool, String>;
+    /// A pilot's credits; zero until their first transaction
+    fn balance(&self, name: &str) -> Result<u32, String>;
+    /// Apply `amount` to a pilot's credits and record it; None, changing nothing, if it would overdraw
+    fn post_transaction(&self, name: &str, kind: TransactionKind, amount: i64, memo: &str, time: u64)
+    -> Result<Option<Transaction>, String>;
+    /// A pilot's latest `limit` transactions, newest first
+    fn transactions(&self, name: &str, limit: usize) -> Result<Vec<Transaction>, String>;
+    /// How much of a good, by content id, a pilot's hold has; zero until they first stow any
+    fn cargo(&self, name: &str, item: &str) -> Result<u32, String>;
+    /// Add `amount` of a good to a pilot's hold (negative to take it out); the count after, or None, changing
+    /// nothing, if there isn't that much aboard
+    fn stow(&self, name: &str, item: &str, amount: i64) -> Result<Option<u32>, String>;
+    /// Pilots ranked by `metric`, highest first and ties by name, skipping `offset` and taking `limit`;
+    /// along with how many pilots are ranked at all. Pilots at zero aren't ranked
+    fn leaderboard(&self, metric: LeaderboardMetric, offset: usize, limit: usize) -> Result<(Vec<(String, u64)>, usize), String>;
+}
+
+/// The balance (or count in a hold) after `amount`, if it doesn't overdraw; both top out at `u32::MAX`
+fn balance_after(balance: u32, amount: i64) -> Option<u32> {
+    let after = balance as i64 + amount;
+    (after >= 0).then(|| after.min(u32::MAX as i64) as u32)
+}
+
+/// One chunk's edited tiles by local position, with the version each was edited at
+type ChunkEdits = HashMap<(i32, i32), (Tile, u64)>;
+
+#[derive(Default)]
+struct MemoryState {
+    seed: Option<u64>,
+    instance: Option<u64>,
+    edits: HashMap<(i32, i32), ChunkEdits>,
+    version: u64,
+    positions: HashMap<String, (i32, i32)>,
+    homes: HashMap<String, String>,
+    stats: HashMap<String, PilotStats>,
+    sectors: HashSet<(String, i32, i32)>,
+    /// Each pilot's transactions, oldest first; the last one's balance is theirs
+    ledgers: HashMap<String, Vec<Transaction>>,
+    /// Goods aboard, by (pilot, item)
+    holds: HashMap<(String, String), u32>,
+}
+
+/// Keeps everything in memory; the default when no database is configured
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<MemoryState>,
+}
+
+impl WorldStore for MemoryStore {
+    fn seed(&self) -> Result<Option<u64>, String> {
+        Ok(self.state.lock().unwrap().seed)
+    }
+
+    fn set_seed(&self, seed: u64) -> Result<(), String> {
+        self.state.lock().unwrap().seed = Some(seed);
+        Ok(())
+    }
+
+    fn instance(&self) -> Result<Option<u64>, String> {
+        Ok(self.state.lock().unwrap().instance)
+    }
+
+    fn set_instance(&self, instance: u64) -> Result<(), String> {
+        self.state.lock().unwrap().instance = Some(instance);
+        Ok(())
+    }
+
+    fn chunk_edits(&self, cx: i32, cy: i32) -> Result<Vec<(i32, i32, Tile)>, String> {
+        let state = self.state.lock().unwrap();
+        let edits = state.edits.get(&(cx, cy));
+        Ok(edits.into_iter().flatten().map(|(&(lx, ly), &(tile, _))| (lx, ly, tile)).collect())
+    }
+
+    fn set_tile(&self, x: i32, y: i32, tile: Tile) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        state.version += 1;
+        let version = state.version;
+        state.edits.entry(chunk_coords(x, y)).or_default().insert(chunk_local(x, y), (tile, version));
+        Ok(())
+    }
+
+    fn version(&self) -> Result<u64, String> {
+        Ok(self.state.lock().unwrap().version)
+    }
+
+    fn chunk_version(&self, cx: i32, cy: i32) -> Result<u64, String> {
+        let state = self.state.lock().unwrap();
+        Ok(state.edits.get(&(cx, cy)).into_iter().flat_map(|edits| edits.values()).map(|&(_, version)| version).max().unwrap_or(0))
+    }
+
+    fn edits_since(&self, since: u64, limit: usize) -> Result<Vec<(u64, i32, i32, Tile)>, String> {
+        let state = self.state.lock().unwrap();
+        let mut edits: Vec<(u64, i32, i32, Tile)> = state
+            .edits
+            .iter()
+            .flat_map(|(&(cx, cy), chunk)| {
+                chunk.iter().map(move |(&(lx, ly), &(tile, version))| (version, cx * CHUNK_SIZE + lx, cy * CHUNK_SIZE + ly, tile))
+            })
+            .filter(|&(version, ..)| version > since)
+            .collect();
+        edits.sort_unstable_by_key(|&(version, ..)| version);
+        edits.truncate(limit);
+        Ok(edits)
+    }
+
+    fn player_position(&self, name: &str) -> Result<Option<(i32, i32)>, String> {
+        Ok(self.state.lock().unwrap().positions.get(&name.to_lowercase()).copied())
+    }
+
+    fn set_player_position(&self, name: &str, x: i32, y: i32) -> Result<(), String> {
+        self.state.lock().unwrap().positions.insert(name.to_lowercase(), (x, y));
+        Ok(())
+    }
+
+    fn player_home(&self, name: &str) -> Result<Option<String>, String> {
+        Ok(self.state.lock().unwrap().homes.get(&name.to_lowercase()).cloned())
+    }
+
+    fn set_player_home(&self, name: &str, station: &str) -> Result<(), String> {
+        self.state.lock().unwrap().homes.insert(name.to_lowercase(), station.to_string());
+        Ok(())
+    }
+
+    fn player_stats(&self, name: &str) -> Result<PilotStats, String> {
+        let state = self.state.lock().unwrap();
+        let name = name.to_lowercase();
+        let sectors = state.sectors.iter().filter(|(owner, _, _)| *owner == name).count() as u64;
+        Ok(PilotStats { sectors_explored: sectors, ..state.stats.get(&name).copied().unwrap_or_default() })
+    }
+
+    fn add_player_stats(&self, name: &str, delta: PilotStats) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let stats = state.stats.entry(name.to_lowercase()).or_default();
+        *stats = stats.plus(PilotStats { sectors_explored: 0, ..delta });
+        Ok(())
+    }
+
+    fn visit_sector(&self, name: &str, cx: i32, cy: i32) -> Result<bool, String> {
+        Ok(self.state.lock().unwrap().sectors.insert((name.to_lowercase(), cx, cy)))
+    }
+
+    fn balance(&self, name: &str) -> Result<u32, String> {
+        let state = self.state.lock().unwrap();
+        Ok(state.ledgers.get(&name.to_lowercase()).and_then(|ledger| ledger.last()).map_or(0, |last| last.balance))
+    }
+
+    fn post_transaction(&self, name: &str, kind: TransactionKind, amount: i64, memo: &str, time: u64)
+    -> Result<Option<Transaction>, String> {
+        let mut state = self.state.lock().unwrap();
+        let ledger = state.ledgers.entry(name.to_lowercase()).or_default();
+        let Some(balance) = balance_after(ledger.last().map_or(0, |last| last.balance), amount) else {
+            return Ok(None);
+        };
+        let transaction = Transaction { kind, amount, balance, memo: memo.to_string(), time };
+        ledger.push(transaction.clone());
+        Ok(Some(transaction))
+    }
+
+    fn transactions(&self, name: &str, limit: usize) -> Result<Vec<Transaction>, String> {
+        let state = self.state.lock().unwrap();
+        let ledger = state.ledgers.get(&name.to_lowercase());
+        Ok(ledger.into_iter().flat_map(|ledger| ledger.iter().rev().take(limit)).cloned().collect())
+    }
+
+    fn cargo(&self, name: &str, item: &str) -> Result<u32, String> {
+        let state = self.state.lock().unwrap();
+        Ok(state.holds.get(&(name.to_lowercase(), item.to_string())).copied().unwrap_or(0))
+    }
+
+    fn stow(&self, name: &str, item: &str, amount: i64) -> Result<Option<u32>, String> {
+        let mut state = self.state.lock().unwrap();
+        let count = state.holds.entry((name.to_lowercase(), item.to_string())).or_default();
+        let Some(after) = balance_after(*count, amount) else {
+            return Ok(None);
+        };
+        *count = after;
+        Ok(Some(after))
+    }
+
+    fn leaderboard(&self, metric: LeaderboardMetric, offset: usize, limit: usize) -> Result<(Vec<(String, u64)>, usize), String> {
+        let state = self.state.lock().unwrap();
+        let mut ranked: Vec<(String, u64)> = match metric {
+            LeaderboardMetric::Distance => state.stats.iter().map(|(name, stats)| (name.clone(), stats.distance)).collect(),
+            LeaderboardMetric::Ore => state.stats.iter().map(|(name, stats)| (name.clone(), stats.ore_mined)).collect(),
+            LeaderboardMetric::Credits => state
+                .ledgers
+                .iter()
+                .map(|(name, ledger)| (name.clone(), ledger.iter().map(|t| t.amount.max(0) as u64).sum()))
+                .collect(),
+        };
+        ranked.retain(|(_, value)| *value > 0);
+        ranked.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then_with(|| a_name.cmp(b_name)));
+        let total = ranked.len();
+        Ok((ranked.into_iter().skip(offset).take(limit).collect(), total))
+    }
+}
+
+/// World state in a SQLite database
+#[cfg(feature = "sqlite")]
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    /// Open (or create) the database and its tables
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let connection = Connection::open(path).map_err(|e| e.to_string())?;
+        Self::with_connection(connection)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, String> {
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+                 CREATE TABLE IF NOT EXISTS tiles (
+                     cx INTEGER NOT NULL, cy INTEGER NOT NULL, lx INTEGER NOT NULL, ly INTEGER NOT NULL,
+                     tile INTEGER NOT NULL, version INTEGER NOT NULL DEFAULT 0, PRIMARY KEY (cx, cy, lx, ly));
+                 CREATE TABLE IF NOT EXISTS players (name TEXT PRIMARY KEY, x INTEGER NOT NULL, y INTEGER NOT NULL);
+                 CREATE TABLE IF NOT EXISTS homes (name TEXT PRIMARY KEY, station TEXT NOT NULL);
+                 CREATE TABLE IF NOT EXISTS stats (
+                     name TEXT PRIMARY KEY, distance INTEGER NOT NULL, ore INTEGER NOT NULL, crystal INTEGER NOT NULL);
+                 CREATE TABLE IF NOT EXISTS sectors (
+                     name TEXT NOT NULL, cx INTEGER NOT NULL, cy INTEGER NOT NULL, PRIMARY KEY (name, cx, cy));
+                 CREATE TABLE IF NOT EXISTS balances (name TEXT PRIMARY KEY, credits INTEGER NOT NULL);
+                 CREATE TABLE IF NOT EXISTS transactions (
+                     id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL, kind TEXT NOT NULL,
+                     amount INTEGER NOT NULL, balance INTEGER NOT NULL, memo TEXT NOT NULL, time INTEGER NOT NULL);
+                 CREATE INDEX IF NOT EXISTS transactions_by_name ON transactions (name, id);
+                 CREATE TABLE IF NOT EXISTS holds (
+                     name TEXT NOT NULL, item TEXT NOT NULL, count INTEGER NOT NULL, PRIMARY KEY (name, item));",
+            )
+            .map_err(|e| e.to_string())?;
+        // Databases from before world versions: their edits count as version 0, older than any diff
+        let versioned: bool = connection
+            .query_row("SELECT COUNT(*) > 0 FROM pragma_table_info('tiles') WHERE name = 'version'", [], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        if !versioned {
+            connection
+                .execute("ALTER TABLE tiles ADD COLUMN version INTEGER NOT NULL DEFAULT 0", [])
+                .map_err(|e| e.to_string())?;
+        }
+        connection
+            .execute("CREATE INDEX IF NOT EXISTS tiles_by_version ON tiles (version)", [])
+            .map_err(|e| e.to_string())?;
+        Ok(SqliteStore { connection: Mutex::new(connection) })
+    }
+
+    /// Run `f` on the connection. SQLite calls block, so on the server's worker threads tokio is told
+    /// first (`block_in_place`) and hands the worker's other tasks to another thread; elsewhere `f` just runs
+    fn with<T>(&self, f: impl FnOnce(&mut Connection) -> T) -> T {
+        let run = || f(&mut self.connection.lock().unwrap());
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => tokio::task::block_in_place(run),
+            _ => run(),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl WorldStore for SqliteStore {
+    fn seed(&self) -> Result<Option<u64>, String> {
+        self.with(|connection| {
+            let value: Option<String> = connection
+                .query_row("SELECT value FROM meta WHERE key = 'seed'", [], |row| row.get(0))
+                .optional()
+                .map_err(|e| e.to_string())?;
+            // Stored as text since SQLite integers are signed
+            value.map(|v| v.parse().map_err(|_| format!("Bad stored seed '{}'", v))).transpose()
+        })
+    }
+
+    fn set_seed(&self, seed: u64) -> Result<(), String> {
+        self.with(|connection| {
+            connection
+                .execute("INSERT OR REPLACE INTO meta (key, value) VALUES ('seed', ?1)", params![seed.to_string()])
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn instance(&self) -> Result<Option<u64>, String> {
+        self.with(|connection| {
+            let value: Option<String> = connection
+                .query_row("SELECT value FROM meta WHERE key = 'instance'", [], |row| row.get(0))
+                .optional()
+                .map_err(|e| e.to_string())?;
+            value.map(|v| v.parse().map_err(|_| format!("Bad stored instance '{}'", v))).transpose()
+        })
+    }
+
+    fn set_instance(&self, instance: u64) -> Result<(), String> {
+        self.with(|connection| {
+            connection
+                .execute("INSERT OR REPLACE INTO meta (key, value) VALUES ('instance', ?1)", params![instance.to_string()])
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn chunk_edits(&self, cx: i32, cy: i32) -> Result<Vec<(i32, i32, Tile)>, String> {
+        self.with(|connection| {
+            let mut statement = connection
+                .prepare_cached("SELECT lx, ly, tile FROM tiles WHERE cx = ?1 AND cy = ?2")
+                .map_err(|e| e.to_string())?;
+            let rows = statement
+                .query_map(params![cx, cy], |row| Ok((row.get(0)?, row.get(1)?, row.get::<_, u8>(2)?)))
+                .map_err(|e| e.to_string())?;
+            let mut edits = Vec::new();
+            for row in rows {
+                let (lx, ly, byte) = row.map_err(|e| e.to_string())?;
+                // Skip tiles written by a newer server rather than failing the whole chunk
+                if let Some(tile) = Tile::from_byte(byte) {
+                    edits.push((lx, ly, tile));
+                }
+            }
+            Ok(edits)
+        })
+    }
+
+    fn set_tile(&self, x: i32, y: i32, tile: Tile) -> Result<(), String> {
+        let (cx, cy) = chunk_coords(x, y);
+        let (lx, ly) = chunk_local(x, y);
+        self.with(|connection| {
+            connection
+                .execute(
+                    "INSERT OR REPLACE INTO tiles (cx, cy, lx, ly, tile, version)
+                     VALUES (?1, ?2, ?3, ?4, ?5, (SELECT COALESCE(MAX(version), 0) + 1 FROM tiles))",
+                    params![cx, cy, lx, ly, tile.to_byte()],
+                )
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn version(&self) -> Result<u64, String> {
+        self.with(|connection| {
+            connection
+                .query_row("SELECT COALESCE(MAX(version), 0) FROM tiles", [], |row| row.get::<_, i64>(0))
+                .map(|version| version as u64)
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn chunk_version(&self, cx: i32, cy: i32) -> Result<u64, String> {
+        self.with(|connection| {
+            connection
+                .query_row("SELECT COALESCE(MAX(version), 0) FROM tiles WHERE cx = ?1 AND cy = ?2", params![cx, cy], |row| {
+                    row.get::<_, i64>(0)
+                })
+                .map(|version| version as u64)
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn edits_since(&self, since: u64, limit: usize) -> Result<Vec<(u64, i32, i32, Tile)>, String> {
+        self.with(|connection| {
+            let mut statement = connection
+                .prepare_cached("SELECT version, cx, cy, lx, ly, tile FROM tiles WHERE version > ?1 ORDER BY version LIMIT ?2")
+                .map_err(|e| e.to_string())?;
+            let rows = statement
+                .query_map(params![since as i64, limit as i64], |row| {
+                    let (cx, cy): (i32, i32) = (row.get(1)?, row.get(2)?);
+                    let (lx, ly): (i32, i32) = (row.get(3)?, row.get(4)?);
+                    Ok((row.get::<_, i64>(0)? as u64, cx * CHUNK_SIZE + lx, cy * CHUNK_SIZE + ly, row.get::<_, u8>(5)?))
+                })
+                .map_err(|e| e.to_string())?;
+            let mut edits = Vec::new();
+            for row in rows {
+                let (version, x, y, byte) = row.map_err(|e| e.to_string())?;
+                // Skipped like in chunk_edits; the chunk itself still shows the server's tile
+                if let Some(tile) = Tile::from_byte(byte) {
+                    edits.push((version, x, y, tile));
+                }
+            }
+            Ok(edits)
+        })
+    }
+
+    fn player_position(&self, name: &str) -> Result<Option<(i32, i32)>, String> {
+        self.with(|connection| {
+            connection
+                .query_row("SELECT x, y FROM players WHERE name = ?1", params![name.to_lowercase()], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })
+                .optional()
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn set_player_position(&self, name: &str, x: i32, y: i32) -> Result<(), String> {
+        self.with(|connection| {
+            connection
+                .execute(
+                    "INSERT OR REPLACE INTO players (name, x, y) VALUES (?1, ?2, ?3)",
+                    params![name.to_lowercase(), x, y],
+                )
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn player_home(&self, name: &str) -> Result<Option<String>, String> {
+        self.with(|connection| {
+            connection
+                .query_row("SELECT station FROM homes WHERE name = ?1", params![name.to_lowercase()], |row| row.get(0))
+                .optional()
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn set_player_home(&self, name: &str, station: &str) -> Result<(), String> {
+        self.with(|connection| {
+            connection
+                .execute("INSERT OR REPLACE INTO homes (name, station) VALUES (?1, ?2)", params![name.to_lowercase(), station])
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn player_stats(&self, name: &str) -> Result<PilotStats, String> {
+        self.with(|connection| {
+            let name = name.to_lowercase();
+            let totals: Option<(i64, i64, i64)> = connection
+                .query_row("SELECT distance, ore, crystal FROM stats WHERE name = ?1", params![name], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })
+                .optional()
+                .map_err(|e| e.to_string())?;
+            let sectors: i64 = connection
+                .query_row("SELECT COUNT(*) FROM sectors WHERE name = ?1", params![name], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            let (distance, ore, crystal) = totals.unwrap_or_default();
+            Ok(PilotStats {
+                distance: distance as u64,
+                ore_mined: ore as u64,
+                crystal_mined: crystal as u64,
+                sectors_explored: sectors as u64,
+            })
+        })
+    }
+
+    fn add_player_stats(&self, name: &str, delta: PilotStats) -> Result<(), String> {
+        self.with(|connection| {
+            connection
+                .execute(
+                    "INSERT INTO stats (name, distance, ore, crystal) VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT (name) DO UPDATE SET
+                         distance = distance + excluded.distance, ore = ore + excluded.ore, crystal = crystal + excluded.crystal",
+                    params![name.to_lowercase(), delta.distance as i64, delta.ore_mined as i64, delta.crystal_mined as i64],
+                )
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn visit_sector(&self, name: &str, cx: i32, cy: i32) -> Result<bool, String> {
+        self.with(|connection| {
+            connection
+                .execute("INSERT OR IGNORE INTO sectors (name, cx, cy) VALUES (?1, ?2, ?3)", params![name.to_lowercase(), cx, cy])
+                .map(|inserted| inserted > 0)
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn balance(&self, name: &str) -> Result<u32, String> {
+        self.with(|connection| {
+            connection
+                .query_row("SELECT credits FROM balances WHERE name = ?1", params![name.to_lowercase()], |row| row.get(0))
+                .optional()
+                .map(Option::unwrap_or_default)
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn post_transaction(&self, name: &str, kind: TransactionKind, amount: i64, memo: &str, time: u64)
+    -> Result<Option<Transaction>, String> {
+        self.with(|connection| {
+            let name = name.to_lowercase();
+            // The balance and its ledger line are saved together or not at all
+            let transaction = connection.transaction().map_err(|e| e.to_string())?;
+            let before: Option<u32> = transaction
+                .query_row("SELECT credits FROM balances WHERE name = ?1", params![name], |row| row.get(0))
+                .optional()
+                .map_err(|e| e.to_string())?;
+            let Some(balance) = balance_after(before.unwrap_or_default(), amount) else {
+                return Ok(None);
+            };
+            transaction
+                .execute("INSERT OR REPLACE INTO balances (name, credits) VALUES (?1, ?2)", params![name, balance])
+                .map_err(|e| e.to_string())?;
+            transaction
+                .execute(
+                    "INSERT INTO transactions (name, kind, amount, balance, memo, time) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![name, kind.name(), amount, balance, memo, time as i64],
+                )
+                .map_err(|e| e.to_string())?;
+            transaction.commit().map_err(|e| e.to_string())?;
+            Ok(Some(Transaction { kind, amount, balance, memo: memo.to_string(), time }))
+        })
+    }
+
+    fn transactions(&self, name: &str, limit: usize) -> Result<Vec<Transaction>, String> {
+        self.with(|connection| {
+            let mut statement = connection
+                .prepare_cached(
+                    "SELECT kind, amount, balance, memo, time FROM transactions WHERE name = ?1 ORDER BY id DESC LIMIT ?2",
+                )
+                .map_err(|e| e.to_string())?;
+            let rows = statement
+                .query_map(params![name.to_lowercase(), limit as i64], |row| {
+                    Ok(Transaction {
+                        kind: TransactionKind::from_name(&row.get::<_, String>(0)?),
+                        amount: row.get(1)?,
+                        balance: row.get(2)?,
+                        memo: row.get(3)?,
+                        time: row.get::<_, i64>(4)? as u64,
+                    })
+                })
+                .map_err(|e| e.to_string())?;
+            rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+        })
+    }
+
+    fn cargo(&self, name: &str, item: &str) -> Result<u32, String> {
+        self.with(|connection| {
+            connection
+                .query_row("SELECT count FROM holds WHERE name = ?1 AND item = ?2", params![name.to_lowercase(), item], |row| row.get(0))
+                .optional()
+                .map(Option::unwrap_or_default)
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn stow(&self, name: &str, item: &str, amount: i64) -> Result<Option<u32>, String> {
+        self.with(|connection| {
+            let name = name.to_lowercase();
+            let transaction = connection.transaction().map_err(|e| e.to_string())?;
+            let before: Option<u32> = transaction
+                .query_row("SELECT count FROM holds WHERE name = ?1 AND item = ?2", params![name, item], |row| row.get(0))
+                .optional()
+                .map_err(|e| e.to_string())?;
+            let Some(after) = balance_after(before.unwrap_or_default(), amount) else {
+                return Ok(None);
+            };
+            transaction
+                .execute("INSERT OR REPLACE INTO holds (name, item, count) VALUES (?1, ?2, ?3)", params![name, item, after])
+                .map_err(|e| e.to_string())?;
+            transaction.commit().map_err(|e| e.to_string())?;
+            Ok(Some(after))
+        })
+    }
+
+    fn leaderboard(&self, metric: LeaderboardMetric, offset: usize, limit: usize) -> Result<(Vec<(String, u64)>, usize), String> {
+        self.with(|connection| {
+            let totals = match metric {
+                LeaderboardMetric::Distance => "SELECT name, distance AS value FROM stats",
+                LeaderboardMetric::Ore => "SELECT name, ore AS value FROM stats",
+                LeaderboardMetric::Credits => "SELECT name, SUM(amount) AS value FROM transactions WHERE amount > 0 GROUP BY name",
+            };
+            let total: i64 = connection
+                .query_row(&format!("SELECT COUNT(*) FROM ({}) WHERE value > 0", totals), [], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            let mut statement = connection
+                .prepare_cached(&format!(
+                    "SELECT name, value FROM ({}) WHERE value > 0 ORDER BY value DESC, name LIMIT ?1 OFFSET ?2",
+                    totals
+                ))
+                .map_err(|e| e.to_string())?;
+            let rows = statement
+                .query_map(params![limit as i64, offset as i64], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as u64)))
+                .map_err(|e| e.to_string())?;
+            Ok((rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?, total as usize))
+        })
+    }
+}
+
+/// The shared world: its seed plus whatever storage remembers
+pub struct World {
+    store: Box<dyn WorldStore>,
+    pub seed: u64,
+    /// Tells this world apart from an earlier one on the same seed whose edit versions started over
+    pub instance: u64,
+    /// Tiles being checked and changed right now, so two pilots can't both break one
+    breaking: Mutex<HashSet<(i32, i32)>>,
+    /// Resource tiles mined out, oldest first, as (when, x, y, what grows back)
+    depleted: Mutex<VecDeque<(Instant, i32, i32, Tile)>>,
+}
+
+/// A tile held by `World::claim()`, let go when dropped
+struct Claim<'a> {
+    world: &'a World,
+    spot: (i32, i32),
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        self.world.breaking.lock().unwrap().remove(&self.spot);
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new(Box::new(MemoryStore::default())).expect("The memory store doesn't fail")
+    }
+}
+
+impl World {
+    /// Use a store, adopting its seed and instance or giving it the default seed and a random instance
+    pub fn new(store: Box<dyn WorldStore>) -> Result<Self, String> {
+        let seed = match store.seed()? {
+            Some(seed) => seed,
+            None => {
+                store.set_seed(DEFAULT_SEED)?;
+                DEFAULT_SEED
+            }
+        };
+        let instance = match store.instance()? {
+            Some(instance) => instance,
+            None => {
+                let mut bytes = [0; 8];
+                getrandom::fill(&mut bytes).expect("OS random number generator unavailable");
+                let instance = u64::from_le_bytes(bytes);
+                store.set_instance(instance)?;
+                instance
+            }
+        };
+        Ok(World { store, seed, instance, breaking: Mutex::default(), depleted: Mutex::default() })
+    }
+
+    /// The database from `EXOSPACE_DB` if set, otherwise an in-memory world
+    #[cfg(feature = "sqlite")]
+    pub fn load() -> Self {
+        let Ok(path) = std::env::var(WORLD_DB_ENV) else {
+            return Self::default();
+        };
+        SqliteStore::open(Path::new(&path))
+            .and_then(|store| World::new(Box::new(store)))
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to open world database {}: {}", path, e);
+                Self::default()
+            })
+    }
+
+    /// Always an in-memory world; says so if a database was asked for
+    #[cfg(not(feature = "sqlite"))]
+    pub fn load() -> Self {
+        if let Ok(path) = std::env::var(WORLD_DB_ENV) {
+            tracing::warn!("Ignoring world database {}: built without the sqlite feature", path);
+        }
+        Self::default()
+    }
+
+    /// Generate a chunk; the canonical world also gets its edits applied
+    pub fn chunk(&self, cx: i32, cy: i32, seed: u64) -> ChunkData {
+        let mut chunk = ChunkGenerator::new(seed).generate(cx, cy);
+        if seed == self.seed {
+            match self.store.chunk_edits(cx, cy) {
+                Ok(edits) => {
+                    for (lx, ly, tile) in edits {
+                        chunk.tiles[(ly * CHUNK_SIZE + lx) as usize] = tile;
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to load edits for chunk ({}, {}): {}", cx, cy, e),
+            }
+        }
+        chunk
+    }
+
+    pub fn set_tile(&self, x: i32, y: i32, tile: Tile) -> Result<(), String> {
+        self.store.set_tile(x, y, tile)
+    }
+
+    /// The latest world version; a chunk generated after reading it has at least those edits
+    pub fn version(&self) -> Result<u64, String> {
+        self.store.version()
+    }
+
+    /// The world version of a chunk's latest edit, which changes whenever the chunk does
+    pub fn chunk_version(&self, cx: i32, cy: i32) -> Result<u64, String> {
+        self.store.chunk_version(cx, cy)
+    }
+
+    /// Tiles changed after version `since`, at most `limit` of them; with more to come, `version`
+    /// is the last one sent so the next diff picks up from there. A `version` below `since` means
+    /// the world has started over since then (an in-memory server restarted)
+    pub fn diff(&self, since: u64, limit: usize) -> Result<MapDiff, String> {
+        // Read first, so an edit landing meanwhile is sent twice rather than missed
+        let latest = self.store.version()?;
+        let mut edits = self.store.edits_since(since, limit + 1)?;
+        let more = edits.len() > limit;
+        edits.truncate(limit);
+        let last = edits.last().map_or(0, |&(version, ..)| version);
+        let version = if more { last } else { latest.max(last) };
+        let tiles = edits.into_iter().map(|(_, x, y, tile)| TileChange { x, y, tile }).collect();
+        Ok(MapDiff { version, tiles, more })
+    }
+
+    /// One tile of the canonical world, edits included
+    pub fn tile(&self, x: i32, y: i32) -> Tile {
+        let (cx, cy) = chunk_coords(x, y);
+        let (lx, ly) = chunk_local(x, y);
+        self.chunk(cx, cy, self.seed).get(lx, ly).unwrap_or(Tile::Unknown)
+    }
+
+    /// Hold a tile while it's checked and changed; None if someone else already is
+    fn claim(&self, x: i32, y: i32) -> Option<Claim<'_>> {
+        let claimed = self.breaking.lock().unwrap().insert((x, y));
+        claimed.then(|| Claim { world: self, spot: (x, y) })
+    }
+
+    /// Change a tile only if it breaks into `tile` (see `Tile::broken`); what it was, or None if it doesn't
+    /// or another edit to it is under way. Callers check the tile is within reach of whoever broke it
+    pub fn break_tile(&self, x: i32, y: i32, tile: Tile) -> Result<Option<Tile>, String> {
+        let Some(_claim) = self.claim(x, y) else {
+            return Ok(None);
+        };
+        let before = self.tile(x, y);
+        if before.broken() != Some(tile) {
+            return Ok(None);
+        }
+        self.store.set_tile(x, y, tile)?;
+        if matches!(before, Tile::OreAsteroid | Tile::CrystalField) {
+            self.depleted.lock().unwrap().push_back((Instant::now(), x, y, before));
+        }
+        Ok(Some(before))
+    }
+
+    /// Grow back resources mined out `REGROW_AFTER` before `now`, unless `occupied`; returns what grew
+    pub fn regrow(&self, now: Instant, occupied: impl Fn(i32, i32) -> bool) -> Vec<(i32, i32, Tile)> {
+        let due: Vec<_> = {
+            let mut depleted = self.depleted.lock().unwrap();
+            let count = depleted.iter().take_while(|&&(at, ..)| now.saturating_duration_since(at) >= REGROW_AFTER).count();
+            depleted.drain(..count).collect()
+        };
+        let mut grown = Vec::new();
+        let mut waiting = Vec::new();
+        for (at, x, y, tile) in due {
+            let Some(_claim) = self.claim(x, y) else {
+                waiting.push((at, x, y, tile));
+                continue;
+            };
+            // Mined again since, or cut away by a laser: nothing to grow back into
+            if self.tile(x, y) != tile.broken().unwrap_or(tile) {
+                continue;
+            }
+            if occupied(x, y) {
+                waiting.push((at, x, y, tile));
+                continue;
+            }
+            match self.store.set_tile(x, y, tile) {
+                Ok(()) => grown.push((x, y, tile)),
+                Err(e) => tracing::warn!("Failed to regrow tile ({}, {}): {}", x, y, e),
+            }
+        }
+        // Checked again next tick, ahead of anything mined meanwhile
+        let mut depleted = self.depleted.lock().unwrap();
+        for entry in waiting.into_iter().rev() {
+            depleted.push_front(entry);
+        }
+        grown
+    }
+
+    /// Where a pilot last was; storage errors are logged and treated as unknown
+    pub fn player_position(&self, name: &str) -> Option<(i32, i32)> {
+        self.store.player_position(name).unwrap_or_else(|e| {
+            tracing::warn!("Failed to load position for {}: {}", name, e);
+            None
+        })
+    }
+
+    pub fn save_player_position(&self, name: &str, x: i32, y: i32) {
+        if let Err(e) = self.store.set_player_position(name, x, y) {
+            tracing::warn!("Failed to save position for {}: {}", name, e);
+        }
+    }
+
+    /// The station a pilot calls home; storage errors are logged and treated as none
+    pub fn player_home(&self, name: &str) -> Option<String> {
+        self.store.player_home(name).unwrap_or_else(|e| {
+            tracing::warn!("Failed to load home for {}: {}", name, e);
+            None
+        })
+    }
+
+    pub fn set_player_home(&self, name: &str, station: &str) -> Result<(), String> {
+        self.store.set_player_home(name, station)
+    }
+
+    /// A pilot's stored totals; storage errors are logged and read as zero
+    pub fn player_stats(&self, name: &str) -> PilotStats {
+        self.store.player_stats(name).unwrap_or_else(|e| {
+            tracing::warn!("Failed to load stats for {}: {}", name, e);
+            PilotStats::default()
+        })
+    }
+
+    pub fn add_player_stats(&self, name: &str, delta: PilotStats) {
+        if let Err(e) = self.store.add_player_stats(name, delta) {
+            tracing::warn!("Failed to save stats for {}: {}", name, e);
+        }
+    }
+
+    /// Whether this is the pilot's first time in the chunk; storage errors count as not new
+    pub fn visit_sector(&self, name: &str, cx: i32, cy: i32) -> bool {
+        self.store.visit_sector(name, cx, cy).unwrap_or_else(|e| {
+            tracing::warn!("Failed to record sector for {}: {}", name, e);
+            false
+        })
+    }
+
+    pub fn balance(&self, name: &str) -> Result<u32, String> {
+        self.store.balance(name)
+    }
+
+    /// Move a pilot's credits, stamped with the time; None if they can't cover it
+    pub fn post_transaction(&self, name: &str, kind: TransactionKind, amount: i64, memo: &str) -> Result<Option<Transaction>, String> {
+        self.store.post_transaction(name, kind, amount, memo, utility::unix_time())
+    }
+
+    pub fn transactions(&self, name: &str, limit: usize) -> Result<Vec<Transaction>, String> {
+        self.store.transactions(name, limit)
+    }
+
+    pub fn cargo(&self, name: &str, item: &str) -> Result<u32, String> {
+        self.store.cargo(name, item)
+    }
+
+    /// Put goods in a pilot's hold or take them out; None if there aren't that many to take
+    pub fn stow(&self, name: &str, item: &str, amount: i64) -> Result<Option<u32>, String> {
+        self.store.stow(name, item, amount)
+    }
+
+    pub fn leaderboard(&self, metric: LeaderboardMetric, offset: usize, limit: usize) -> Result<(Vec<(String, u64)>, usize), String> {
+        self.store.leaderboard(metric, offset, limit)
+    }
+}
+
+/// Grows mined-out resources back and tells everyone
+pub struct ResourceRegrowth {
+    world: Arc<World>,
+    sessions: Arc<SessionRegistry>,
+    npcs: Arc<NpcRoster>,
+}
+
+impl ResourceRegrowth {
+    pub fn new(world: Arc<World>, sessions: Arc<SessionRegistry>, npcs: Arc<NpcRoster>) -> Self {
+        ResourceRegrowth { world, sessions, npcs }
+    }
+}
+
+impl System for ResourceRegrowth {
+    fn name(&self) -> &'static str {
+        "resource_regrowth"
+    }
+
+    fn tick(&mut self, now: Instant) {
+        // Only looked up once something is due
+        let npcs = OnceCell::new();
+        let occupied = |x: i32, y: i32| {
+            !self.sessions.near(x, y, 1).is_empty()
+                || npcs.get_or_init(|| self.npcs.snapshot()).iter().any(|npc| npc.x == x && npc.y == y)
+        };
+        for (x, y, tile) in self.world.regrow(now, occupied) {
+            self.sessions.tile_changed(x, y, tile);
+        }
+    }
+}
+
+/// Whether a tile position is inside the world's chunks
+pub fn in_world(x: i32, y: i32) -> bool {
+    let (cx, cy) = chunk_coords(x, y);
+    cx.abs() <= MAX_CHUNK_COORD && cy.abs() <= MAX_CHUNK_COORD
+}
+
+/// Query parameters for `GET /map/diff`
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    /// The world version the client's chunks are current as of; 0 for every edit
+    #[serde(default)]
+    since: u64,
+}
+
+/// Handler for `GET /map/diff`: the canonical world's tiles changed since a version
+pub async fn get_diff(State(state): State<AppState>, ApiQuery(params): ApiQuery<DiffQuery>) -> Response {
+    match state.world.diff(params.since, MAX_DIFF_TILES) {
+        Ok(diff) => Json(diff).into_response(),
+        Err(e) => ApiError::Storage(e).into_response(),
+    }
+}
+
+/// Body of `POST /tile/update`
+#[derive(Deserialize)]
+pub struct TileEdit {
+    x: i32,
+    y: i32,
+    tile: Tile,
+}
+
+/// The signed-in pilot making an edit, or why `/tile/update` would turn it away: no signed-in pilot,
+/// a position outside the world, an unknown tile or a ship that isn't in the home system within `EDIT_REACH`
+fn edit_account(state: &AppState, headers: &HeaderMap, edit: &TileEdit) -> Result<String, ApiError> {
+    let Some(account) = auth::bearer_token(headers).and_then(|token| state.users.verify(token)) else {
+        return Err(AuthError::BadToken.into());
+    };
+    if !in_world(edit.x, edit.y) {
+        return Err(ApiError::OutOfWorld);
+    }
+    if edit.tile == Tile::Unknown {
+        return Err(ApiError::Invalid("Unknown tile kind".to_string()));
+    }
+    let ship = state.sessions.find_by_name(&account).filter(|player| state.sessions.system(player.id).is_none());
+    if ship.is_none_or(|ship| ship.x.abs_diff(edit.x).max(ship.y.abs_diff(edit.y)) > EDIT_REACH) {
+        return Err(ApiError::OutOfReach);
+    }
+    Ok(account)
+}
+
+/// Handler for `POST /tile/update`: a pilot mined or lasered a tile; 409 if the world's tile can't break that way.
+/// What mining yields goes in the pilot's hold and counts toward their stored totals
+pub async fn update_tile(State(state): State<AppState>, headers: HeaderMap, ApiJson(edit): ApiJson<TileEdit>) -> Response {
+    let account = match edit_account(&state, &headers, &edit) {
+        Ok(account) => account,
+        Err(e) => return e.into_response(),
+    };
+    match state.world.break_tile(edit.x, edit.y, edit.tile) {
+        Ok(Some(before)) => {
+            state.sessions.tile_changed(edit.x, edit.y, edit.tile);
+            if let Some(mined) = stats::mined(before) {
+                state.world.add_player_stats(&account, mined);
+            }
+            if let Some((item, amount)) = before.mining_yield()
+                && let Err(e) = state.world.stow(&account, item, amount.into())
+            {
+                tracing::warn!("Failed to stow {} for {}: {}", item, account, e);
+            }
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(None) => ApiError::TileConflict.into_response(),
+        Err(e) => ApiError::Storage(e).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Both backends must behave the same
+    fn stores() -> Vec<Box<dyn WorldStore>> {
+        #[cfg_attr(not(feature = "sqlite"), allow(unused_mut))]
+        let mut stores: Vec<Box<dyn WorldStore>> = vec![Box::new(MemoryStore::default())];
+        #[cfg(feature = "sqlite")]
+        stores.push(Box::new(SqliteStore::with_connection(Connection::open_in_memory().unwrap()).unwrap()));
+        stores
+    }
+
+    #[test]
+    fn test_store_seed() {
+        for store in stores() {
+            assert_eq!(store.seed().unwrap(), None);
+            store.set_seed(u64::MAX).unwrap();
+            assert_eq!(store.seed().unwrap(), Some(u64::MAX), "Seeds above i64::MAX survive");
+        }
+    }
+
+    #[test]
+    fn test_store_tile_edits_by_chunk() {
+        for store in stores() {
+            store.set_tile(5, 6, Tile::Wall).unwrap();
+            store.set_tile(-1, -1, Tile::Nebula).unwrap();
+            store.set_tile(5, 6, Tile::Asteroid).unwrap();
+            assert_eq!(store.chunk_edits(0, 0).unwrap(), vec![(5, 6, Tile::Asteroid)], "Later edits replace earlier ones");
+            assert_eq!(store.chunk_edits(-1, -1).unwrap(), vec![(CHUNK_SIZE - 1, CHUNK_SIZE - 1, Tile::Nebula)]);
+            assert!(store.chunk_edits(3, 3).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_store_tile_versions() {
+        for store in stores() {
+            assert_eq!(store.version().unwrap(), 0);
+            store.set_tile(5, 6, Tile::Wall).unwrap();
+            store.set_tile(-1, -1, Tile::Nebula).unwrap();
+            store.set_tile(5, 6, Tile::Floor).unwrap();
+            assert_eq!(store.version().unwrap(), 3, "Each edit is a new version");
+            assert_eq!(
+                store.edits_since(0, 10).unwrap(),
+                vec![(2, -1, -1, Tile::Nebula), (3, 5, 6, Tile::Floor)],
+                "Oldest first, each tile once as it is now"
+            );
+            assert_eq!(store.edits_since(2, 10).unwrap(), vec![(3, 5, 6, Tile::Floor)]);
+            assert_eq!(store.edits_since(0, 1).unwrap().len(), 1);
+            assert!(store.edits_since(3, 10).unwrap().is_empty());
+            assert_eq!(store.chunk_version(0, 0).unwrap(), 3);
+            assert_eq!(store.chunk_version(-1, -1).unwrap(), 2, "Each chunk has its own");
+            assert_eq!(store.chunk_version(4, 4).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_sqlite_adds_versions_to_old_databases() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE tiles (
+                     cx INTEGER NOT NULL, cy INTEGER NOT NULL, lx INTEGER NOT NULL, ly INTEGER NOT NULL,
+                     tile INTEGER NOT NULL, PRIMARY KEY (cx, cy, lx, ly));
+                 INSERT INTO tiles VALUES (0, 0, 1, 1, 1);",
+            )
+            .unwrap();
+        let store = SqliteStore::with_connection(connection).unwrap();
+        assert_eq!(store.chunk_edits(0, 0).unwrap().len(), 1, "Old edits are kept");
+        assert!(store.edits_since(0, 10).unwrap().is_empty(), "They predate every diff");
+        store.set_tile(2, 2, Tile::Wall).unwrap();
+        assert_eq!(store.edits_since(0, 10).unwrap(), vec![(1, 2, 2, Tile::Wall)]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    #[cfg(feature = "sqlite")]
+    async fn test_sqlite_store_works_from_server_tasks() {
+        let store = Arc::new(SqliteStore::with_connection(Connection::open_in_memory().unwrap()).unwrap());
+        let task = tokio::spawn({
+            let store = store.clone();
+            async move { store.set_tile(3, 4, Tile::Floor) }
+        });
+        task.await.unwrap().unwrap();
+        assert_eq!(store.version().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_store_player_positions() {
+        for store in stores() {
+            assert_eq!(store.player_position("Ace").unwrap(), None);
+            store.set_player_position("Ace", 10, -20).unwrap();
+            store.set_player_position("ACE", 11, -21).unwrap();
+            assert_eq!(store.player_position("ace").unwrap(), Some((11, -21)), "Names are case-insensitive");
+        }
+    }
+
+    #[test]
+    fn test_store_player_homes() {
+        for store in stores() {
+            assert_eq!(store.player_home("Ace").unwrap(), None);
+            store.set_player_home("Ace", "Haven Station").unwrap();
+            store.set_player_home("ACE", "Drift Depot").unwrap();
+            assert_eq!(store.player_home("ace").unwrap(), Some("Drift Depot".to_string()), "Setting a home replaces the last");
+        }
+    }
+
+    #[test]
+    fn test_store_player_stats() {
+        for store in stores() {
+            assert_eq!(store.player_stats("Ace").unwrap(), PilotStats::default());
+            store.add_player_stats("Ace", PilotStats { distance: 30, ore_mined: 2, ..PilotStats::default() }).unwrap();
+            store.add_player_stats("ACE", PilotStats { distance: 5, crystal_mined: 1, sectors_explored: 9, ..PilotStats::default() }).unwrap();
+            assert!(store.visit_sector("ace", 0, 0).unwrap(), "First visit");
+            assert!(store.visit_sector("Ace", -1, 2).unwrap());
+            assert!(!store.visit_sector("Ace", 0, 0).unwrap(), "Revisits aren't new");
+            assert!(store.visit_sector("Bee", 0, 0).unwrap(), "Sectors are per pilot");
+            assert_eq!(
+                store.player_stats("Ace").unwrap(),
+                PilotStats { distance: 35, ore_mined: 2, crystal_mined: 1, sectors_explored: 2 },
+                "Totals add up; sector counts come from visits only"
+            );
+        }
+    }
+
+    #[test]
+    fn test_store_ledger() {
+        for store in stores() {
+            assert_eq!(store.balance("Ace").unwrap(), 0, "Pilots start broke");
+            let paid = store.post_transaction("Ace", TransactionKind::Mission, 90, "Mission 7-2", 10).unwrap().unwrap();
+            assert_eq!((paid.balance, paid.time), (90, 10));
+            assert_eq!(store.post_transaction("ace", TransactionKind::Trade, -91, "Bought 2 crystal", 11).unwrap(), None, "No overdrafts");
+            store.post_transaction("ACE", TransactionKind::Repair, -30, "Repaired 15 hull", 12).unwrap();
+            store.post_transaction("Bee", TransactionKind::Trade, 9, "Sold 1 ore", 13).unwrap();
+            assert_eq!(store.balance("Ace").unwrap(), 60);
+            let history = store.transactions("Ace", 10).unwrap();
+            assert_eq!(history.iter().map(|t| (t.kind, t.amount, t.balance)).collect::<Vec<_>>(), vec![
+                (TransactionKind::Repair, -30, 60),
+                (TransactionKind::Mission, 90, 90),
+            ], "Newest first, refusals unrecorded");
+            assert_eq!(store.transactions("Ace", 1).unwrap().len(), 1);
+            assert!(store.transactions("Cy", 10).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_store_holds() {
+        for store in stores() {
+            assert_eq!(store.cargo("Ace", "ore").unwrap(), 0, "Holds start empty");
+            assert_eq!(store.stow("Ace", "ore", 6).unwrap(), Some(6));
+            assert_eq!(store.stow("ACE", "ore", -7).unwrap(), None, "Can't take out more than is aboard");
+            assert_eq!(store.stow("ace", "ore", -4).unwrap(), Some(2));
+            store.stow("Ace", "crystal", 1).unwrap();
+            store.stow("Bee", "ore", 9).unwrap();
+            assert_eq!((store.cargo("Ace", "ore").unwrap(), store.cargo("Ace", "crystal").unwrap()), (2, 1), "Each good and pilot apart");
+        }
+    }
+
+    #[test]
+    fn test_store_leaderboard() {
+        for store in stores() {
+            assert_eq!(store.leaderboard(LeaderboardMetric::Distance, 0, 10).unwrap(), (vec![], 0));
+            store.add_player_stats("Ace", PilotStats { distance: 40, ore_mined: 3, ..PilotStats::default() }).unwrap();
+            store.add_player_stats("Bee", PilotStats { distance: 90, ..PilotStats::default() }).unwrap();
+            store.add_player_stats("Cy", PilotStats { distance: 40, ore_mined: 5, ..PilotStats::default() }).unwrap();
+            let (top, total) = store.leaderboard(LeaderboardMetric::Distance, 0, 10).unwrap();
+            assert_eq!(total, 3);
+            assert_eq!(top, vec![("bee".to_string(), 90), ("ace".to_string(), 40), ("cy".to_string(), 40)], "Ties go by name");
+            assert_eq!(store.leaderboard(LeaderboardMetric::Distance, 1, 1).unwrap(), (vec![("ace".to_string(), 40)], 3), "Pages");
+            assert_eq!(
+                store.leaderboard(LeaderboardMetric::Ore, 0, 10).unwrap(),
+                (vec![("cy".to_string(), 5), ("ace".to_string(), 3)], 2),
+                "Pilots who never mined aren't ranked"
+            );
+
+            store.post_transaction("Ace", TransactionKind::Mission, 90, "Mission 7-2", 10).unwrap();
+            store.post_transaction("Ace", TransactionKind::Repair, -80, "Repaired 40 hull", 11).unwrap();
+            store.post_transaction("Bee", TransactionKind::Trade, 50, "Sold 5 ore", 12).unwrap();
+            assert_eq!(
+                store.leaderboard(LeaderboardMetric::Credits, 0, 10).unwrap(),
+                (vec![("ace".to_string(), 90), ("bee".to_string(), 50)], 2),
+                "Earnings ignore spending"
+            );
+        }
+    }
+
+    #[test]
+    fn test_world_adopts_stored_seed() {
+        let store = MemoryStore::default();
+        store.set_seed(777).unwrap();
+        assert_eq!(World::new(Box::new(store)).unwrap().seed, 777);
+
+        let fresh = MemoryStore::default();
+        let world = World::new(Box::new(fresh)).unwrap();
+        assert_eq!(world.seed, DEFAULT_SEED);
+        assert_eq!(world.store.seed().unwrap(), Some(DEFAULT_SEED), "New worlds remember their seed");
+    }
+
+    #[test]
+    fn test_world_chunk_applies_edits_to_canonical_seed_only() {
+        let world = World::default();
+        world.set_tile(2, 3, Tile::Wall).unwrap();
+        assert_eq!(world.chunk(0, 0, DEFAULT_SEED).get(2, 3), Some(Tile::Wall));
+        assert_eq!(world.chunk(0, 0, 99).get(2, 3), ChunkGenerator::new(99).generate(0, 0).get(2, 3), "Other seeds are pristine");
+        assert_eq!(world.chunk(1, 0, DEFAULT_SEED), ChunkGenerator::new(DEFAULT_SEED).generate(1, 0));
+    }
+
+    #[test]
+    fn test_world_diff_pages_through_changes() {
+        let world = World::default();
+        for x in 0..5 {
+            world.set_tile(x, 0, Tile::Wall).unwrap();
+        }
+        let first = world.diff(0, 2).unwrap();
+        assert_eq!(first.tiles.iter().map(|change| change.x).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!((first.version, first.more), (2, true), "A full page picks up after its last change");
+        let rest = world.diff(first.version, 10).unwrap();
+        assert_eq!(rest.tiles.len(), 3);
+        assert_eq!((rest.version, rest.more), (5, false));
+        assert_eq!(world.diff(5, 10).unwrap(), MapDiff { version: 5, tiles: Vec::new(), more: false });
+        assert_eq!(world.diff(9, 10).unwrap().version, 5, "Behind what was asked: the world started over");
+    }
+
+    #[test]
+    fn test_world_breaks_tiles_only_as_they_break() {
+        let world = World::default();
+        world.set_tile(2, 3, Tile::OreAsteroid).unwrap();
+        assert_eq!(world.break_tile(2, 3, Tile::Floor).unwrap(), None, "Ore leaves rubble, not open space");
+        assert_eq!(world.break_tile(2, 3, Tile::Asteroid).unwrap(), Some(Tile::OreAsteroid));
+        assert_eq!(world.break_tile(2, 3, Tile::Floor).unwrap(), Some(Tile::Asteroid));
+        assert_eq!(world.break_tile(2, 3, Tile::Floor).unwrap(), None, "Already broken");
+        assert_eq!(world.tile(2, 3), Tile::Floor);
+
+        world.set_tile(5, 3, Tile::Asteroid).unwrap();
+        let claim = world.claim(5, 3).unwrap();
+        assert!(world.claim(5, 3).is_none());
+        assert_eq!(world.break_tile(5, 3, Tile::Floor).unwrap(), None, "Someone else is breaking it");
+        assert!(world.break_tile(6, 3, Tile::Floor).is_ok(), "Other tiles aren't held up");
+        drop(claim);
+        assert_eq!(world.break_tile(5, 3, Tile::Floor).unwrap(), Some(Tile::Asteroid));
+    }
+
+    #[test]
+    fn test_world_regrows_mined_resources() {
+        let world = World::default();
+        for (x, tile) in [(2, Tile::OreAsteroid), (4, Tile::CrystalField), (6, Tile::OreAsteroid)] {
+            world.set_tile(x, 3, tile).unwrap();
+            assert_eq!(world.break_tile(x, 3, tile.broken().unwrap()).unwrap(), Some(tile));
+        }
+        world.set_tile(7, 3, Tile::Asteroid).unwrap();
+        assert!(world.break_tile(7, 3, Tile::Floor).unwrap().is_some(), "Plain asteroids don't grow back");
+        assert!(world.break_tile(6, 3, Tile::Floor).unwrap().is_some(), "Lasered away after mining");
+
+        let later = Instant::now() + REGROW_AFTER;
+        assert!(world.regrow(Instant::now(), |_, _| false).is_empty(), "Nothing is due yet");
+        assert_eq!(world.regrow(later, |x, _| x == 4), vec![(2, 3, Tile::OreAsteroid)], "A ship on the tile holds it back");
+        assert_eq!(world.tile(2, 3), Tile::OreAsteroid);
+        assert_eq!(world.tile(6, 3), Tile::Floor);
+        assert_eq!(world.tile(7, 3), Tile::Floor);
+        assert_eq!(world.regrow(later, |_, _| false), vec![(4, 3, Tile::CrystalField)], "It grows once the ship has gone");
+        assert!(world.regrow(later, |_, _| false).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn test_sqlite_world_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("exospace-world-test-{}.db", std::process::id()));
+        let instance = {
+            let world = World::new(Box::new(SqliteStore::open(&path).unwrap())).unwrap();
+            world.set_tile(100, 100, Tile::Nebula).unwrap();
+            world.save_player_position("Ace", 100, 101);
+            world.post_transaction("Ace", TransactionKind::Trade, 45, "Sold 5 ore").unwrap();
+            world.instance
+        };
+        let world = World::new(Box::new(SqliteStore::open(&path).unwrap())).unwrap();
+        assert_eq!(world.instance, instance, "Still the same world");
+        assert_ne!(World::default().instance, World::default().instance, "Each new world has its own");
+        assert_eq!(world.chunk(1, 1, DEFAULT_SEED).get(36, 36), Some(Tile::Nebula));
+        assert_eq!(world.player_position("Ace"), Some((100, 101)));
+        assert_eq!(world.balance("Ace"), Ok(45));
+        assert_eq!(world.transactions("Ace", 5).unwrap()[0].memo, "Sold 5 ore");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,548 @@
+//! Stress tester for a running ExoSpace server.
+//!
+//! Hammers the server with random map and chunk requests, malformed queries
+//! and simulated websocket pilots, then prints latency percentiles per kind
+//! of request. A 5xx, a dropped connection, a reply that doesn't decode or a
+//! server that stops answering counts as a failure and makes the run exit
+//! non-zero.
+//!
+//! ```text
+//! cargo run --bin stress -- [--url URL] [--requests N] [--clients N] [--messages N] [--concurrency N] [--seed S]
+//! ```
+
+use exospace_core::{
+    ChunkData, ClientMessage, Direction, DistanceTarget, MAX_CHUNK_COORD, MAX_MAP_HEIGHT, MAX_MAP_WIDTH, MIN_MAP_SIDE,
+    MapData, ServerMessage,
+};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio_tungstenite::tungstenite::Message;
+
+const DEFAULT_URL: &str = "http://localhost:3000";
+
+/// How long any single request or reply may take before it counts as a hang
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Failure messages printed per kind of request; the rest are only counted
+const MAX_REPORTED_FAILURES: usize = 5;
+
+const USAGE: &str = "Usage: stress [--url URL] [--requests N] [--clients N] [--messages N] [--concurrency N] [--seed S]";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    url: String,
+    /// HTTP requests to send
+    requests: usize,
+    /// Simulated websocket pilots
+    clients: usize,
+    /// Messages each pilot sends
+    messages: usize,
+    /// HTTP requests in flight at once
+    concurrency: usize,
+    seed: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { url: DEFAULT_URL.to_string(), requests: 500, clients: 20, messages: 50, concurrency: 16, seed: 1 }
+    }
+}
+
+impl Options {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            if flag == "--help" || flag == "-h" {
+                return Err(USAGE.to_string());
+            }
+            let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+            let number = || value.parse::<u64>().map_err(|_| format!("{} must be a number, got '{}'", flag, value));
+            match flag.as_str() {
+                "--url" => options.url = value.trim_end_matches('/').to_string(),
+                "--requests" => options.requests = number()? as usize,
+                "--clients" => options.clients = number()? as usize,
+                "--messages" => options.messages = number()? as usize,
+                "--concurrency" => options.concurrency = (number()? as usize).max(1),
+                "--seed" => options.seed = number()?,
+                _ => return Err(format!("Unknown option '{}'\n{}", flag, USAGE)),
+            }
+        }
+        Ok(options)
+    }
+
+    fn ws_url(&self) -> String {
+        let url = self.url.replacen("https://", "wss://", 1).replacen("http://", "ws://", 1);
+        format!("{}/ws", url)
+    }
+}
+
+/// Small xorshift generator so a run can be replayed with the same `--seed`
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Xorshift never leaves zero
+        Self(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform-ish value in `lo..=hi`
+    fn range(&mut self, lo: i64, hi: i64) -> i64 {
+        lo + (self.next_u64() % (hi - lo + 1) as u64) as i64
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next_u64() % 100 < percent
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.next_u64() as usize % items.len()]
+    }
+
+    fn fork(&mut self) -> Self {
+        Self::new(self.next_u64())
+    }
+}
+
+/// One HTTP request and what a healthy server answers with
+#[derive(Debug)]
+enum Probe {
+    Map { width: usize, height: usize, seed: u64, noise: bool, binary: bool },
+    Chunk { cx: i32, cy: i32, seed: u64, binary: bool },
+    /// Must be refused with a 4xx
+    Malformed(String),
+}
+
+/// Queries the server has to reject without falling over
+const MALFORMED: &[&str] = &[
+    "/map?width=0&height=0",
+    "/map?width=-5",
+    "/map?width=abc",
+    "/map?height=99999999999999999999999",
+    "/map?width=1&height=1",
+    "/map?seed=-1",
+    "/map?width=10&width=20",
+    "/map?algo=maze",
+    "/map/chunk",
+    "/map/chunk?cx=1",
+    "/map/chunk?cx=x&cy=1",
+    "/map/chunk?cx=0&cy=-2147483648",
+    "/map/chunk?cx=2147483647&cy=2147483647",
+    "/map/chunk?cx=0&cy=0&seed=notanumber",
+    "/map/chunk?cx=1.5&cy=0",
+    "/%00",
+    "/map/../../etc/passwd",
+];
+
+impl Probe {
+    fn random(rng: &mut Rng) -> Self {
+        match rng.range(0, 9) {
+            0..=3 => {
+                // Mostly small maps with the odd one at the limits
+                let (max_w, max_h) = if rng.chance(10) { (MAX_MAP_WIDTH, MAX_MAP_HEIGHT) } else { (200, 100) };
+                Probe::Map {
+                    width: rng.range(MIN_MAP_SIDE as i64, max_w as i64) as usize,
+                    height: rng.range(MIN_MAP_SIDE as i64, max_h as i64) as usize,
+                    seed: rng.next_u64(),
+                    noise: rng.chance(50),
+                    binary: rng.chance(50),
+                }
+            }
+            4..=7 => {
+                let reach = if rng.chance(20) { MAX_CHUNK_COORD } else { 1000 };
+                let coord = |rng: &mut Rng| {
+                    if rng.chance(10) { *rng.pick(&[-reach, reach]) } else { rng.range(-reach as i64, reach as i64) as i32 }
+                };
+                Probe::Chunk { cx: coord(rng), cy: coord(rng), seed: rng.next_u64(), binary: rng.chance(50) }
+            }
+            8 => Probe::Malformed(rng.pick(MALFORMED).to_string()),
+            _ => {
+                // Random junk where a number belongs
+                let junk: String = (0..rng.range(1, 12)).map(|_| format!("%{:02X}", rng.range(0x21, 0xFF))).collect();
+                let path = if rng.chance(50) { "/map?width=" } else { "/map/chunk?cy=0&cx=" };
+                Probe::Malformed(format!("{}z{}", path, junk))
+            }
+        }
+    }
+
+    fn category(&self) -> &'static str {
+        match self {
+            Probe::Map { .. } => "map",
+            Probe::Chunk { .. } => "chunk",
+            Probe::Malformed(_) => "malformed",
+        }
+    }
+
+    fn path(&self) -> String {
+        let format = |binary: bool| if binary { "&format=bin" } else { "" };
+        match self {
+            Probe::Map { width, height, seed, noise, binary } => {
+                let algo = if *noise { "&algo=noise" } else { "" };
+                format!("/map?width={}&height={}&seed={}{}{}", width, height, seed, algo, format(*binary))
+            }
+            Probe::Chunk { cx, cy, seed, binary } => format!("/map/chunk?cx={}&cy={}&seed={}{}", cx, cy, seed, format(*binary)),
+            Probe::Malformed(path) => path.clone(),
+        }
+    }
+
+    async fn run(&self, client: &reqwest::Client, base: &str) -> Result<(), String> {
+        let path = self.path();
+        let response = client.get(format!("{}{}", base, path)).send().await.map_err(|e| format!("{}: {}", path, e))?;
+        let status = response.status();
+        let body = response.bytes().await.map_err(|e| format!("{}: reading body: {}", path, e))?;
+
+        match self {
+            Probe::Malformed(_) if status.is_client_error() => Ok(()),
+            Probe::Malformed(_) => Err(format!("{}: expected a 4xx, got {}", path, status)),
+            _ if status == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                Err(format!("{}: rate limited; lift the server's budget with EXOSPACE_MAPS_PER_MIN=0", path))
+            }
+            _ if !status.is_success() => Err(format!("{}: status {}", path, status)),
+            Probe::Map { width, height, binary, .. } => {
+                let map = if *binary {
+                    MapData::from_bytes(&body).map_err(|e| format!("{}: {:?}", path, e))?
+                } else {
+                    serde_json::from_slice::<MapData>(&body).map_err(|e| format!("{}: {}", path, e))?
+                };
+                if map.width != *width || map.height != *height || map.tiles.len() != width * height {
+                    return Err(format!("{}: got a {}x{} map with {} tiles", path, map.width, map.height, map.tiles.len()));
+                }
+                Ok(())
+            }
+            Probe::Chunk { cx, cy, binary, .. } => {
+                let chunk = if *binary {
+                    ChunkData::from_bytes(&body).map_err(|e| format!("{}: {:?}", path, e))?
+                } else {
+                    serde_json::from_slice::<ChunkData>(&body).map_err(|e| format!("{}: {}", path, e))?
+                };
+                if (chunk.cx, chunk.cy) != (*cx, *cy) {
+                    return Err(format!("{}: got chunk ({}, {})", path, chunk.cx, chunk.cy));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Latencies and failures for one kind of request
+#[derive(Default)]
+struct Stats {
+    latencies: Vec<Duration>,
+    failures: Vec<String>,
+}
+
+impl Stats {
+    fn record(&mut self, outcome: Result<Duration, String>) {
+        match outcome {
+            Ok(latency) => self.latencies.push(latency),
+            Err(failure) => self.failures.push(failure),
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted latencies
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn millis(d: Duration) -> String {
+    format!("{:.1}ms", d.as_secs_f64() * 1000.0)
+}
+
+fn print_report(stats: &mut BTreeMap<&'static str, Stats>) {
+    println!("{:<10} {:>6} {:>6} {:>9} {:>9} {:>9} {:>9}", "kind", "ok", "failed", "p50", "p90", "p99", "max");
+    for (kind, s) in stats.iter_mut() {
+        s.latencies.sort();
+        let p = |q| millis(percentile(&s.latencies, q));
+        println!(
+            "{:<10} {:>6} {:>6} {:>9} {:>9} {:>9} {:>9}",
+            kind,
+            s.latencies.len(),
+            s.failures.len(),
+            p(50.0),
+            p(90.0),
+            p(99.0),
+            p(100.0)
+        );
+    }
+    for (kind, s) in stats.iter() {
+        for failure in s.failures.iter().take(MAX_REPORTED_FAILURES) {
+            println!("  {} failed: {}", kind, failure);
+        }
+        if s.failures.len() > MAX_REPORTED_FAILURES {
+            println!("  ... and {} more {} failures", s.failures.len() - MAX_REPORTED_FAILURES, kind);
+        }
+    }
+}
+
+/// A random message a pilot might send, including junk that isn't a message at all
+fn random_ws_message(rng: &mut Rng, clients: usize) -> Message {
+    let client_message = match rng.range(0, 9) {
+        0..=3 => {
+            let extreme = rng.chance(5);
+            let coord = |rng: &mut Rng| if extreme { *rng.pick(&[i32::MIN, i32::MAX]) } else { rng.range(-500, 500) as i32 };
+            ClientMessage::Position { x: coord(rng), y: coord(rng), direction: Direction::Right, seed: None }
+        }
+        4 => {
+            let len = if rng.chance(10) { 500 } else { rng.range(0, 40) as usize };
+            ClientMessage::Chat { text: "x".repeat(len) }
+        }
+        5 => ClientMessage::Roll { count: rng.range(0, 30) as u32, sides: rng.range(0, 1200) as u32 },
+        6 => ClientMessage::Flip,
+        7 => {
+            let target = if rng.chance(50) {
+                DistanceTarget::Player { name: format!("stress-{}", rng.range(0, clients as i64)) }
+            } else {
+                DistanceTarget::Position { x: rng.range(i32::MIN as i64, i32::MAX as i64) as i32, y: 0 }
+            };
+            ClientMessage::Distance { target }
+        }
+        8 => return Message::text(*rng.pick(&["", "{", "not json", "{\"type\":\"Teleport\"}", "null"])),
+        _ => return Message::binary((0..rng.range(0, 64)).map(|_| rng.next_u64() as u8).collect::<Vec<u8>>()),
+    };
+    Message::text(serde_json::to_string(&client_message).unwrap())
+}
+
+fn ws_text(message: &ClientMessage) -> Message {
+    Message::text(serde_json::to_string(message).unwrap())
+}
+
+/// Connect as a pilot, send random traffic and time `/time` round trips
+async fn run_ws_client(url: String, index: usize, clients: usize, messages: usize, mut rng: Rng) -> Vec<(&'static str, Result<Duration, String>)> {
+    let mut outcomes = Vec::new();
+    let started = Instant::now();
+    let (mut socket, _) = match tokio::time::timeout(REPLY_TIMEOUT, tokio_tungstenite::connect_async(&url)).await {
+        Ok(Ok(connected)) => connected,
+        Ok(Err(e)) => return vec![("ws-join", Err(format!("client {}: connect: {}", index, e)))],
+        Err(_) => return vec![("ws-join", Err(format!("client {}: connect timed out", index)))],
+    };
+
+    // Some pilots skip the handshake; the server should just hang up on them
+    if rng.chance(10) {
+        let hung_up = async {
+            socket.send(Message::text("hello?")).await.ok()?;
+            while let Some(Ok(message)) = socket.next().await {
+                if message.is_close() {
+                    break;
+                }
+            }
+            Some(())
+        };
+        let outcome = match tokio::time::timeout(REPLY_TIMEOUT, hung_up).await {
+            Ok(_) => Ok(started.elapsed()),
+            Err(_) => Err(format!("client {}: server kept a connection open without a Hello", index)),
+        };
+        return vec![("ws-rude", outcome)];
+    }
+
+    let hello = ws_text(&ClientMessage::Hello { name: format!("stress-{}", index) });
+    let welcomed = async {
+        socket.send(hello).await.map_err(|e| e.to_string())?;
+        wait_for(&mut socket, |m| matches!(m, ServerMessage::Welcome { .. })).await
+    };
+    match tokio::time::timeout(REPLY_TIMEOUT, welcomed).await {
+        Ok(Ok(())) => outcomes.push(("ws-join", Ok(started.elapsed()))),
+        Ok(Err(e)) => return vec![("ws-join", Err(format!("client {}: {}", index, e)))],
+        Err(_) => return vec![("ws-join", Err(format!("client {}: no Welcome", index)))],
+    }
+
+    for _ in 0..messages {
+        if rng.chance(15) {
+            // Time replies come straight back, or as a rate limit rejection; either proves the session is alive
+            let sent = Instant::now();
+            let round_trip = async {
+                socket.send(ws_text(&ClientMessage::Time)).await.map_err(|e| e.to_string())?;
+                wait_for(&mut socket, |m| matches!(m, ServerMessage::Time { .. } | ServerMessage::ChatRejected { .. })).await
+            };
+            let outcome = match tokio::time::timeout(REPLY_TIMEOUT, round_trip).await {
+                Ok(Ok(())) => Ok(sent.elapsed()),
+                Ok(Err(e)) => Err(format!("client {}: {}", index, e)),
+                Err(_) => Err(format!("client {}: /time got no reply", index)),
+            };
+            let failed = outcome.is_err();
+            outcomes.push(("ws-time", outcome));
+            if failed {
+                return outcomes;
+            }
+        } else if let Err(e) = socket.send(random_ws_message(&mut rng, clients)).await {
+            outcomes.push(("ws-time", Err(format!("client {}: send: {}", index, e))));
+            return outcomes;
+        }
+    }
+    let _ = socket.close(None).await;
+    outcomes
+}
+
+/// Read server messages until one matches, skipping broadcasts from other pilots
+async fn wait_for<S>(socket: &mut S, wanted: impl Fn(&ServerMessage) -> bool) -> Result<(), String>
+where
+    S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    while let Some(message) = socket.next().await {
+        match message.map_err(|e| e.to_string())? {
+            Message::Text(text) => {
+                let parsed: ServerMessage =
+                    serde_json::from_str(&text).map_err(|e| format!("undecodable server message {}: {}", text, e))?;
+                if wanted(&parsed) {
+                    return Ok(());
+                }
+            }
+            Message::Close(_) => return Err("server closed the connection".to_string()),
+            _ => {}
+        }
+    }
+    Err("connection dropped".to_string())
+}
+
+#[tokio::main]
+async fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(2);
+        }
+    };
+    let client = reqwest::Client::builder().timeout(REPLY_TIMEOUT).build().expect("Failed to build HTTP client");
+    let mut rng = Rng::new(options.seed);
+    let mut stats: BTreeMap<&'static str, Stats> = BTreeMap::new();
+
+    println!(
+        "Stressing {} with {} requests and {} pilots (seed {})",
+        options.url, options.requests, options.clients, options.seed
+    );
+
+    // Pilots run alongside the HTTP load so both compete for the server
+    let mut pilots = JoinSet::new();
+    for index in 0..options.clients {
+        pilots.spawn(run_ws_client(options.ws_url(), index, options.clients, options.messages, rng.fork()));
+    }
+
+    let permits = Arc::new(Semaphore::new(options.concurrency));
+    let mut requests = JoinSet::new();
+    for _ in 0..options.requests {
+        let probe = Probe::random(&mut rng);
+        let permit = permits.clone().acquire_owned().await.expect("Semaphore closed");
+        let (client, base) = (client.clone(), options.url.clone());
+        requests.spawn(async move {
+            let started = Instant::now();
+            let outcome = probe.run(&client, &base).await.map(|()| started.elapsed());
+            drop(permit);
+            (probe.category(), outcome)
+        });
+    }
+
+    while let Some(joined) = requests.join_next().await {
+        let (kind, outcome) = joined.expect("Request task panicked");
+        stats.entry(kind).or_default().record(outcome);
+    }
+    while let Some(joined) = pilots.join_next().await {
+        for (kind, outcome) in joined.expect("Pilot task panicked") {
+            stats.entry(kind).or_default().record(outcome);
+        }
+    }
+
+    // After all that, the server should still be up
+    let started = Instant::now();
+    let health = match client.get(format!("{}/health", options.url)).send().await {
+        Ok(response) if response.status().is_success() => Ok(started.elapsed()),
+        Ok(response) => Err(format!("/health: status {}", response.status())),
+        Err(e) => Err(format!("/health: {}", e)),
+    };
+    stats.entry("health").or_default().record(health);
+
+    print_report(&mut stats);
+    let failed: usize = stats.values().map(|s| s.failures.len()).sum();
+    if failed > 0 {
+        eprintln!("{} failures", failed);
+        std::process::exit(1);
+    }
+    println!("No failures");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    // ==================== Option Tests ====================
+
+    #[test]
+    fn test_parse_defaults() {
+        assert_eq!(Options::parse(args("")).unwrap(), Options::default());
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        let options = Options::parse(args("--url http://game:4000/ --requests 10 --clients 2 --seed 9")).unwrap();
+        assert_eq!(options.url, "http://game:4000", "Trailing slash is dropped");
+        assert_eq!((options.requests, options.clients, options.seed), (10, 2, 9));
+        assert_eq!(options.ws_url(), "ws://game:4000/ws");
+        assert_eq!(Options::parse(args("--concurrency 0")).unwrap().concurrency, 1, "At least one request in flight");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Options::parse(args("--requests")).is_err(), "Missing value");
+        assert!(Options::parse(args("--requests many")).is_err(), "Not a number");
+        assert!(Options::parse(args("--bogus 1")).is_err(), "Unknown flag");
+        assert!(Options::parse(args("--help")).unwrap_err().starts_with("Usage"));
+    }
+
+    // ==================== Rng Tests ====================
+
+    #[test]
+    fn test_rng_is_deterministic_and_in_range() {
+        let (mut a, mut b) = (Rng::new(7), Rng::new(7));
+        for _ in 0..1000 {
+            let value = a.range(-3, 3);
+            assert_eq!(value, b.range(-3, 3), "Same seed, same sequence");
+            assert!((-3..=3).contains(&value));
+        }
+        assert_ne!(Rng::new(0).next_u64(), 0, "Zero seed still produces values");
+    }
+
+    #[test]
+    fn test_random_probes_stay_in_bounds() {
+        let mut rng = Rng::new(3);
+        for _ in 0..1000 {
+            match Probe::random(&mut rng) {
+                Probe::Map { width, height, .. } => {
+                    assert!((MIN_MAP_SIDE..=MAX_MAP_WIDTH).contains(&width) && (MIN_MAP_SIDE..=MAX_MAP_HEIGHT).contains(&height))
+                }
+                Probe::Chunk { cx, cy, .. } => assert!(cx.abs() <= MAX_CHUNK_COORD && cy.abs() <= MAX_CHUNK_COORD),
+                Probe::Malformed(path) => assert!(path.starts_with('/')),
+            }
+        }
+    }
+
+    // ==================== Report Tests ====================
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let sorted: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&sorted, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&sorted, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&sorted, 100.0), Duration::from_millis(100));
+        assert_eq!(percentile(&sorted, 0.0), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+}